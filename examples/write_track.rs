@@ -1,24 +1,22 @@
 use std::path::Path;
+use std::time::Duration;
 
 extern crate audio;
-use audio::Codec;
+use audio::{Codec, FadeCurve};
 
 fn main() {
   let mut aiff = audio::open(&Path::new("examples/audio/amen-break.aiff")).unwrap();
-  for sample in aiff.samples.iter_mut() {
-    // Attenuate by -3 db
-    *sample = *sample * 0.707946f32;
-  }
+  // Attenuate by -3 db
+  aiff.apply_gain(-3.0);
   // Default wave writes as i16 and don't print any error message
   if let Ok(_) = audio::save(&Path::new("examples/audio/my-break.wav"), &aiff) {
     println!("Saved");
   }
 
   let mut wave = audio::open(&Path::new("examples/audio/amen-break.wav")).unwrap();
-  for sample in wave.samples.iter_mut() {
-    // Attenuate by -3 db
-    *sample = *sample * 0.707946f32;
-  }
+  // Attenuate by -3 db, then fade out over the last half second
+  wave.apply_gain(-3.0);
+  wave.fade_out_for(Duration::from_millis(500), FadeCurve::EqualPower).unwrap();
   // Write as f64 and print and error message on failure
   match audio::save_as(&Path::new("examples/audio/my-break.aiff"), &wave, Codec::LPCM_F64_BE) {
     Ok(_) => println!("Saved"),
@@ -1,11 +1,12 @@
 //! AIFF Chunks
 use std::fmt;
 use std::io::Write;
-use aiff::{COMM, SSND};
+use aiff::{BASC, COMM, INST, MARK, SSND};
 use buffer::AudioBuffer;
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use codecs::Codec;
 use codecs::Codec::*;
+use metadata::{Instrument, Loop, LoopInfo, LoopMode};
 use self::CompressionType::*;
 use traits::Chunk;
 use error::*;
@@ -28,7 +29,11 @@ const FL64: (&'static [u8; 4], &'static [u8]) =
 pub enum AiffChunk {
   FormatVersion,
   Common,
-  SoundData
+  SoundData,
+  Marker,
+  Instrument,
+  Id3,
+  AppleLoop
 }
 
 /// Supported compression codes in the AIFC common chunk.
@@ -217,6 +222,381 @@ impl SoundDataChunk {
   }
 } 
 
+/// Writes a chunk containing the given data, adding a trailing byte if the
+/// data is of odd size. This is used for chunks whose contents are encoded
+/// separately, such as unrecognized chunks and ID3 tags.
+pub fn write_chunk<W: Write>(writer: &mut W, id: &[u8; 4], data: &[u8]) -> AudioResult<()> {
+  try!(writer.write(id));
+  try!(writer.write_u32::<BigEndian>(data.len() as u32));
+  try!(writer.write_all(data));
+  if data.len() % 2 != 0 {
+    try!(writer.write_u8(0));
+  }
+  Ok(())
+}
+
+/// A position within the sound data, referenced by other chunks.
+///
+/// Positions are frame offsets, where a position of 0 occurs before the first
+/// frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+  pub id:       i16,
+  pub position: u32,
+  pub name:     Vec<u8>
+}
+
+/// The AIFF Marker Chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkerChunk {
+  pub markers: Vec<Marker>
+}
+
+impl MarkerChunk {
+  /// Returns the position of the marker with the given id.
+  pub fn position(&self, id: i16) -> Option<u32> {
+    self.markers.iter().find(|m| m.id == id).map(|m| m.position)
+  }
+
+  #[inline]
+  pub fn calculate_size(&self) -> u32 {
+    self.markers.iter().fold(2, |size, marker|
+      size + 6 + pstring_size(&marker.name)
+    )
+  }
+
+  pub fn write<W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(MARK));
+    try!(writer.write_u32::<BigEndian>(self.calculate_size()));
+    try!(writer.write_u16::<BigEndian>(self.markers.len() as u16));
+    for marker in self.markers.iter() {
+      try!(writer.write_i16::<BigEndian>(marker.id));
+      try!(writer.write_u32::<BigEndian>(marker.position));
+      try!(writer.write_u8(marker.name.len() as u8));
+      try!(writer.write(&marker.name));
+      // Pascal-style strings are padded to an even number of bytes.
+      if (marker.name.len() + 1) % 2 == 1 {
+        try!(writer.write_u8(0));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Chunk for MarkerChunk {
+  fn read(buffer: &[u8]) -> AudioResult<MarkerChunk> {
+    if buffer.len() < 2 {
+      return Err(AudioError::Format(
+        "Marker chunk is too small".to_string()
+      ))
+    }
+    let num_markers = BigEndian::read_u16(&buffer[0..2]) as usize;
+    let mut markers = Vec::with_capacity(num_markers);
+    let mut pos = 2;
+    for _ in 0..num_markers {
+      if buffer.len() < pos + 7 {
+        return Err(AudioError::Format(
+          "Marker chunk is too small for its number of markers".to_string()
+        ))
+      }
+      let id        = BigEndian::read_i16(&buffer[pos .. pos + 2]);
+      let position  = BigEndian::read_u32(&buffer[pos + 2 .. pos + 6]);
+      let name_len  = buffer[pos + 6] as usize;
+      let name_end  = (pos + 7 + name_len).min(buffer.len());
+      markers.push(Marker {
+        id:       id,
+        position: position,
+        name:     buffer[pos + 7 .. name_end].to_vec()
+      });
+      pos += 6 + pstring_size(&buffer[pos + 7 .. name_end]) as usize;
+    }
+    Ok(MarkerChunk { markers: markers })
+  }
+}
+
+/// A loop within the AIFF Instrument Chunk.
+///
+/// Loop points refer to markers in the marker chunk. A play mode of 0 means
+/// there is no loop, 1 is a forward loop, and 2 is a forward-backward loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstrumentLoop {
+  pub play_mode:  i16,
+  pub begin_loop: i16,
+  pub end_loop:   i16
+}
+
+/// The AIFF Instrument Chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstrumentChunk {
+  pub base_note:      i8,
+  pub detune:         i8,
+  pub low_note:       i8,
+  pub high_note:      i8,
+  pub low_velocity:   i8,
+  pub high_velocity:  i8,
+  pub gain:           i16,
+  pub sustain_loop:   InstrumentLoop,
+  pub release_loop:   InstrumentLoop
+}
+
+impl InstrumentChunk {
+  /// Creates an instrument chunk and the marker chunk its loops refer to from
+  /// an `Instrument`.
+  ///
+  /// AIFF marks the end of a loop at the position following its last frame,
+  /// so loop ends are placed one frame after the `Loop` end. Backward loops
+  /// are not supported by AIFF and are written as forward loops.
+  pub fn from_instrument(instrument: &Instrument) -> (InstrumentChunk, MarkerChunk) {
+    let mut markers = Vec::new();
+    let mut make_loop = |lp: Option<&Loop>, names: (&[u8], &[u8])| {
+      match lp {
+        Some(lp) => {
+          let begin_id = markers.len() as i16 + 1;
+          markers.push(Marker {
+            id: begin_id, position: lp.start, name: names.0.to_vec()
+          });
+          markers.push(Marker {
+            id: begin_id + 1, position: lp.end + 1, name: names.1.to_vec()
+          });
+          InstrumentLoop {
+            play_mode:
+              match lp.mode {
+                LoopMode::Alternating => 2,
+                _                     => 1
+              },
+            begin_loop: begin_id,
+            end_loop:   begin_id + 1
+          }
+        },
+        None => InstrumentLoop { play_mode: 0, begin_loop: 0, end_loop: 0 }
+      }
+    };
+    let sustain_loop =
+      make_loop(instrument.sustain_loop.as_ref(), (b"beg sus", b"end sus"));
+    let release_loop =
+      make_loop(instrument.release_loop.as_ref(), (b"beg rel", b"end rel"));
+    (
+      InstrumentChunk {
+        base_note:      instrument.root_note as i8,
+        detune:         instrument.fine_tune,
+        low_note:       instrument.low_note as i8,
+        high_note:      instrument.high_note as i8,
+        low_velocity:   instrument.low_velocity as i8,
+        high_velocity:  instrument.high_velocity as i8,
+        gain:           instrument.gain as i16,
+        sustain_loop:   sustain_loop,
+        release_loop:   release_loop
+      },
+      MarkerChunk { markers: markers }
+    )
+  }
+
+  /// Creates an `Instrument` from this chunk, resolving loop points using the
+  /// given marker chunk. Loops referring to missing markers are ignored.
+  pub fn to_instrument(&self, markers: Option<&MarkerChunk>) -> Instrument {
+    let to_loop = |lp: &InstrumentLoop| -> Option<Loop> {
+      let mode =
+        match lp.play_mode {
+          1 => LoopMode::Forward,
+          2 => LoopMode::Alternating,
+          _ => return None
+        };
+      let markers = match markers {
+        Some(markers) => markers,
+        None          => return None
+      };
+      match (markers.position(lp.begin_loop), markers.position(lp.end_loop)) {
+        (Some(start), Some(end)) if end > start => Some(Loop {
+          mode:       mode,
+          start:      start,
+          end:        end - 1,
+          play_count: 0
+        }),
+        _ => None
+      }
+    };
+    Instrument {
+      root_note:      self.base_note.max(0) as u8,
+      fine_tune:      self.detune,
+      gain:           self.gain.max(i8::min_value() as i16)
+                               .min(i8::max_value() as i16) as i8,
+      low_note:       self.low_note.max(0) as u8,
+      high_note:      self.high_note.max(0) as u8,
+      low_velocity:   self.low_velocity.max(0) as u8,
+      high_velocity:  self.high_velocity.max(0) as u8,
+      sustain_loop:   to_loop(&self.sustain_loop),
+      release_loop:   to_loop(&self.release_loop)
+    }
+  }
+
+  #[inline]
+  pub fn calculate_size() -> u32 {
+    20
+  }
+
+  pub fn write<W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(INST));
+    try!(writer.write_u32::<BigEndian>(Self::calculate_size()));
+    try!(writer.write_i8(self.base_note));
+    try!(writer.write_i8(self.detune));
+    try!(writer.write_i8(self.low_note));
+    try!(writer.write_i8(self.high_note));
+    try!(writer.write_i8(self.low_velocity));
+    try!(writer.write_i8(self.high_velocity));
+    try!(writer.write_i16::<BigEndian>(self.gain));
+    for lp in [self.sustain_loop, self.release_loop].iter() {
+      try!(writer.write_i16::<BigEndian>(lp.play_mode));
+      try!(writer.write_i16::<BigEndian>(lp.begin_loop));
+      try!(writer.write_i16::<BigEndian>(lp.end_loop));
+    }
+    Ok(())
+  }
+}
+
+impl Chunk for InstrumentChunk {
+  fn read(buffer: &[u8]) -> AudioResult<InstrumentChunk> {
+    if buffer.len() < 20 {
+      return Err(AudioError::Format(
+        "Instrument chunk is too small".to_string()
+      ))
+    }
+    let read_loop = |bytes: &[u8]| InstrumentLoop {
+      play_mode:  BigEndian::read_i16(&bytes[0..2]),
+      begin_loop: BigEndian::read_i16(&bytes[2..4]),
+      end_loop:   BigEndian::read_i16(&bytes[4..6])
+    };
+    Ok(
+      InstrumentChunk {
+        base_note:      buffer[0] as i8,
+        detune:         buffer[1] as i8,
+        low_note:       buffer[2] as i8,
+        high_note:      buffer[3] as i8,
+        low_velocity:   buffer[4] as i8,
+        high_velocity:  buffer[5] as i8,
+        gain:           BigEndian::read_i16(&buffer[6..8]),
+        sustain_loop:   read_loop(&buffer[8..14]),
+        release_loop:   read_loop(&buffer[14..20])
+      }
+    )
+  }
+}
+
+/// The Apple Loops Basic Chunk.
+///
+/// This chunk is written by Apple's loop-based applications to describe the
+/// number of beats, meter, and key of the audio. The tempo is not stored, and
+/// is instead derived from the number of beats and the duration of the audio.
+/// The chunk layout is not documented by Apple, and only the known fields at
+/// the start of the chunk are read. Transient markers stored in the `trns`
+/// chunk are not decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppleLoopChunk {
+  pub version:          u32,
+  pub num_beats:        u32,
+  pub root_note:        u16,
+  /// 1 for minor, 2 for major, 3 for neither, and 4 for both
+  pub scale_type:       u16,
+  pub sig_numerator:    u16,
+  pub sig_denominator:  u16,
+  /// 0 for a loop and 1 for a one-shot
+  pub loop_type:        u16
+}
+
+impl AppleLoopChunk {
+  /// Creates the chunk from `LoopInfo`. If the number of beats is not known,
+  /// it is calculated from the tempo and duration of the audio.
+  pub fn from_loop_info(info: &LoopInfo, audio: &AudioBuffer) -> AppleLoopChunk {
+    let num_beats =
+      if info.beats == 0 && audio.sample_rate > 0 && audio.channels > 0 {
+        let frames = (audio.samples.len() / audio.channels as usize) as f64;
+        (info.tempo as f64 * frames / audio.sample_rate as f64 / 60f64).round() as u32
+      }
+      else {
+        info.beats
+      };
+    AppleLoopChunk {
+      version:          1,
+      num_beats:        num_beats,
+      root_note:        info.root_note.unwrap_or(0) as u16,
+      scale_type:       3,
+      sig_numerator:    info.meter_numerator,
+      sig_denominator:  info.meter_denominator,
+      loop_type:        if info.one_shot { 1 } else { 0 }
+    }
+  }
+
+  /// Returns the loop information, calculating the tempo from the duration
+  /// of the audio given by its number of frames and sample rate.
+  pub fn to_loop_info(&self, num_frames: u32, sample_rate: u32) -> LoopInfo {
+    let tempo =
+      if num_frames > 0 {
+        self.num_beats as f64 * 60f64 * sample_rate as f64 / num_frames as f64
+      }
+      else {
+        0f64
+      };
+    LoopInfo {
+      one_shot:           self.loop_type == 1,
+      stretch:            true,
+      root_note:
+        if self.root_note == 0 { None } else { Some(self.root_note.min(127) as u8) },
+      beats:              self.num_beats,
+      meter_numerator:    self.sig_numerator,
+      meter_denominator:  self.sig_denominator,
+      tempo:              tempo as f32
+    }
+  }
+
+  #[inline]
+  pub fn calculate_size() -> u32 {
+    84
+  }
+
+  pub fn write<W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(BASC));
+    try!(writer.write_u32::<BigEndian>(Self::calculate_size()));
+    try!(writer.write_u32::<BigEndian>(self.version));
+    try!(writer.write_u32::<BigEndian>(self.num_beats));
+    try!(writer.write_u16::<BigEndian>(self.root_note));
+    try!(writer.write_u16::<BigEndian>(self.scale_type));
+    try!(writer.write_u16::<BigEndian>(self.sig_numerator));
+    try!(writer.write_u16::<BigEndian>(self.sig_denominator));
+    try!(writer.write_u16::<BigEndian>(self.loop_type));
+    // Remaining fields are unknown and left empty.
+    try!(writer.write(&[0u8; 66]));
+    Ok(())
+  }
+}
+
+impl Chunk for AppleLoopChunk {
+  fn read(buffer: &[u8]) -> AudioResult<AppleLoopChunk> {
+    if buffer.len() < 18 {
+      return Err(AudioError::Format(
+        "Apple Loops basic chunk is too small".to_string()
+      ))
+    }
+    Ok(
+      AppleLoopChunk {
+        version:          BigEndian::read_u32(&buffer[0..4]),
+        num_beats:        BigEndian::read_u32(&buffer[4..8]),
+        root_note:        BigEndian::read_u16(&buffer[8..10]),
+        scale_type:       BigEndian::read_u16(&buffer[10..12]),
+        sig_numerator:    BigEndian::read_u16(&buffer[12..14]),
+        sig_denominator:  BigEndian::read_u16(&buffer[14..16]),
+        loop_type:        BigEndian::read_u16(&buffer[16..18])
+      }
+    )
+  }
+}
+
+/// Returns the number of bytes used by a Pascal-style string, including the
+/// count byte and padding.
+#[inline]
+fn pstring_size(text: &[u8]) -> u32 {
+  let size = 1 + text.len() as u32;
+  size + size % 2
+}
+
 /// Breaks number into a normalized fraction and a base-2 exponent, satisfying:
 /// > - `self = x * 2^exp`
 /// > - `0.5 <= abs(x) < 1.0`
@@ -1,5 +1,5 @@
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
-use aiff::{AIFF, AIFC, AIFC_VERSION_1, FORM, FVER, COMM, SSND};
+use aiff::{AIFF, AIFC, AIFC_VERSION_1, BASC, FORM, FVER, COMM, ID3, INST, MARK, SSND};
 use aiff::chunks::*;
 use aiff::chunks::AiffChunk::*;
 use aiff::chunks::CompressionType::*;
@@ -7,11 +7,16 @@ use buffer::*;
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use codecs::Codec;
 use codecs::Codec::*;
+use audio::AudioFormat;
 use error::*;
+use id3::Id3Tag;
+use metadata::{Metadata, UnknownChunk};
 use sample::*;
 use sample::SampleOrder::*;
 use traits::{Chunk, Container};
 
+pub use aiff::chunks::write_chunk;
+
 /// Struct containing all necessary information for encoding and decoding
 /// bytes to an `AudioBuffer`.
 pub struct AiffContainer {
@@ -21,28 +26,20 @@ pub struct AiffContainer {
   pub channels:     u32,
   pub num_frames:   u32,
   pub order:        SampleOrder,
-  pub samples:      Vec<Sample>
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
 }
 
 impl Container for AiffContainer {
   fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<AiffContainer> {
-    // Read and validate IFF header
-    let mut iff_header: [u8; 12] = [0u8; 12];
-    try!(reader.read(&mut iff_header));
-    if &iff_header[0..4] != FORM {
-      return Err(AudioError::Format(
-        "Not valid IFF".to_string()
-      ));
-    }
+    let (form_type, mut buffer) = try!(read_form(reader));
     // Determine if container is AIFF or AIFF-C
-    if &iff_header[8..12] != AIFF && &iff_header[8..12] != AIFC {
+    if &form_type != AIFF && &form_type != AIFC {
       return Err(AudioError::Format(
         "Not valid AIFF or AIFF-C".to_string()
       ));
     }
-    let file_size: i32 = BigEndian::read_i32(&iff_header[4..8]) - 4;
-    let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0u8; file_size as usize]);
-    try!(reader.read(buffer.get_mut()));
+    let file_size = buffer.get_ref().len();
 
     // Read all supported chunks
     let mut container = 
@@ -53,25 +50,19 @@ impl Container for AiffContainer {
         channels:       1u32,
         num_frames:     0u32,
         order:          SampleOrder::Interleaved,
-        samples:        Vec::with_capacity(1024)
+        samples:        Vec::with_capacity(1024),
+        metadata:       Metadata::default()
       };
-    let mut chunk_header    : [u8; 8] = [0u8; 8];
     let mut read_fver_chunk : bool    = false;
     let mut read_comm_chunk : bool    = false;
     let mut read_ssnd_chunk : bool    = false;
+    let mut mark_chunk      : Option<MarkerChunk>     = None;
+    let mut inst_chunk      : Option<InstrumentChunk> = None;
+    let mut basc_chunk      : Option<AppleLoopChunk>  = None;
     while buffer.position() < file_size as u64 {
-      try!(buffer.read(&mut chunk_header));
-      let mut chunk_size: usize =
-        BigEndian::read_i32(&chunk_header[4..8]) as usize;
-      // AIFF chunk sizes must always be even and may not specify the trailing
-      // byte in the read chunk_size. This can occur in the sound data chunk,
-      // textual chunks, the midi chunk, and the application specific chunk.
-      // The last two are not supported in this library.
-      if chunk_size % 2 != 0 {
-        chunk_size += 1;
-      }
+      let (chunk_id, data_size, chunk_size) = try!(read_chunk_header(&mut buffer));
       let pos: usize = buffer.position() as usize;
-      match identify(&chunk_header[0..4]).ok() {
+      match identify(&chunk_id).ok() {
         Some(FormatVersion) => {
           read_fver_chunk = true;
         }
@@ -106,7 +97,57 @@ impl Container for AiffContainer {
           container.samples = try!(read_codec(&chunk_bytes[8..], container.codec));
           read_ssnd_chunk   = true;
         },
-        None => {}
+        Some(Marker) => {
+          // Sampler chunks that cannot be read are kept as unrecognized
+          // chunks.
+          let chunk_end   = (pos + data_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match MarkerChunk::read(&chunk_bytes) {
+            Ok(chunk) => mark_chunk = Some(chunk),
+            Err(_)    => container.metadata.chunks.push(
+              unknown_chunk(&chunk_id, chunk_bytes, read_ssnd_chunk)
+            )
+          }
+        },
+        Some(Instrument) => {
+          let chunk_end   = (pos + data_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match InstrumentChunk::read(&chunk_bytes) {
+            Ok(chunk) => inst_chunk = Some(chunk),
+            Err(_)    => container.metadata.chunks.push(
+              unknown_chunk(&chunk_id, chunk_bytes, read_ssnd_chunk)
+            )
+          }
+        },
+        Some(AppleLoop) => {
+          // Loop chunks that cannot be read are kept as unrecognized chunks.
+          let chunk_end   = (pos + data_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match AppleLoopChunk::read(&chunk_bytes) {
+            Ok(chunk) => basc_chunk = Some(chunk),
+            Err(_)    => container.metadata.chunks.push(
+              unknown_chunk(&chunk_id, chunk_bytes, read_ssnd_chunk)
+            )
+          }
+        },
+        Some(Id3) => {
+          // Tags that cannot be decoded are kept as unrecognized chunks.
+          let chunk_end   = (pos + data_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match Id3Tag::read(&chunk_bytes) {
+            Ok(tag) => container.metadata.id3 = Some(tag),
+            Err(_)  => container.metadata.chunks.push(
+              unknown_chunk(&chunk_id, chunk_bytes, read_ssnd_chunk)
+            )
+          }
+        },
+        None => {
+          let chunk_end   = (pos + data_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          container.metadata.chunks.push(
+            unknown_chunk(&chunk_id, chunk_bytes, read_ssnd_chunk)
+          );
+        }
       }
       try!(buffer.seek(SeekFrom::Current(chunk_size as i64)));
     }
@@ -130,6 +171,14 @@ impl Container for AiffContainer {
         (Missing required SoundData chunk)".to_string()
       ))
     }
+    container.metadata.instrument =
+      inst_chunk.map(|inst| inst.to_instrument(mark_chunk.as_ref()));
+    // The tempo of a loop depends on the duration of the audio, which is only
+    // known once the common chunk is read.
+    container.metadata.loop_info =
+      basc_chunk.map(|basc|
+        basc.to_loop_info(container.num_frames, container.sample_rate)
+      );
     Ok(container)
   }
   fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
@@ -147,6 +196,47 @@ impl Container for AiffContainer {
     if aifc {
       total_bytes += 12;
     }
+    // Sampler settings are written using the instrument chunk, with loop
+    // points stored in the marker chunk.
+    let sampler_chunks =
+      audio.metadata.instrument.as_ref().map(InstrumentChunk::from_instrument);
+    if let Some((_, ref mark_chunk)) = sampler_chunks {
+      total_bytes += (8 + mark_chunk.calculate_size())
+                   + (8 + InstrumentChunk::calculate_size());
+    }
+    let basc_chunk =
+      audio.metadata.loop_info.as_ref()
+      .map(|info| AppleLoopChunk::from_loop_info(info, audio));
+    if basc_chunk.is_some() {
+      total_bytes += 8 + AppleLoopChunk::calculate_size();
+    }
+    // Metadata chunks are encoded before writing so their sizes are known.
+    // Unrecognized chunks read from an AIFF file are written back in order,
+    // either before or after the sound data chunk, and ID3 tags are written
+    // after the sound data chunk.
+    let mut chunks_before_data: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    let mut chunks_after_data:  Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    for chunk in audio.metadata.chunks.iter()
+                 .filter(|chunk| chunk.format == AudioFormat::AIFF) {
+      if chunk.after_data {
+        chunks_after_data.push((chunk.id, chunk.data.clone()));
+      }
+      else {
+        chunks_before_data.push((chunk.id, chunk.data.clone()));
+      }
+    }
+    if let Some(ref tag) = audio.metadata.id3 {
+      let mut tag_bytes = Vec::new();
+      try!(tag.write(&mut tag_bytes));
+      chunks_after_data.push((*ID3, tag_bytes));
+    }
+    for &(_, ref chunk_data) in
+        chunks_before_data.iter().chain(chunks_after_data.iter()) {
+      let size = chunk_data.len() as u32;
+      total_bytes += 8 + size + size % 2;
+    }
+    // The sound data chunk is padded to an even size.
+    total_bytes += data.len() as u32 % 2;
 
     // Write the iff header to the writer.
     try!(writer.write(FORM));
@@ -163,14 +253,82 @@ impl Container for AiffContainer {
     }
     // Write comm chunk to the writer.
     try!(CommonChunk::write(writer, audio, codec));
+    // Write marker and instrument chunks if the audio has sampler settings
+    if let Some((ref inst_chunk, ref mark_chunk)) = sampler_chunks {
+      try!(mark_chunk.write(writer));
+      try!(inst_chunk.write(writer));
+    }
+    // Write basic chunk if the audio has loop information
+    if let Some(ref basc_chunk) = basc_chunk {
+      try!(basc_chunk.write(writer));
+    }
+    for &(ref id, ref chunk_data) in chunks_before_data.iter() {
+      try!(write_chunk(writer, id, chunk_data));
+    }
     // Write ssnd chunk to the writer.
     try!(SoundDataChunk::write(writer, &data));
+    for &(ref id, ref chunk_data) in chunks_after_data.iter() {
+      try!(write_chunk(writer, id, chunk_data));
+    }
     Ok(())
   }
 }
 
+/// Reads the header of an IFF `FORM` and the chunks it contains, returning
+/// the form type along with the chunk bytes.
+pub fn read_form<R: Read>(reader: &mut R) -> AudioResult<([u8; 4], Cursor<Vec<u8>>)> {
+  let mut iff_header: [u8; 12] = [0u8; 12];
+  try!(reader.read(&mut iff_header));
+  if &iff_header[0..4] != FORM {
+    return Err(AudioError::Format(
+      "Not valid IFF".to_string()
+    ));
+  }
+  let form_type = [iff_header[8], iff_header[9], iff_header[10], iff_header[11]];
+  let file_size: i32 = BigEndian::read_i32(&iff_header[4..8]) - 4;
+  if file_size < 0 {
+    return Err(AudioError::Format(
+      "File is not valid IFF (Invalid FORM size)".to_string()
+    ));
+  }
+  let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0u8; file_size as usize]);
+  try!(reader.read(buffer.get_mut()));
+  Ok((form_type, buffer))
+}
+
+/// Reads the header of the next chunk in a `FORM`, returning the chunk
+/// identifier, the size of its data, and its size once padded to an even
+/// number of bytes.
+pub fn read_chunk_header(buffer: &mut Cursor<Vec<u8>>) -> AudioResult<([u8; 4], usize, usize)> {
+  let mut chunk_header: [u8; 8] = [0u8; 8];
+  try!(buffer.read(&mut chunk_header));
+  let data_size: usize =
+    BigEndian::read_i32(&chunk_header[4..8]) as usize;
+  let mut chunk_size = data_size;
+  // IFF chunk sizes must always be even and may not specify the trailing
+  // byte in the read chunk_size. In AIFF, this can occur in the sound data
+  // chunk, textual chunks, the midi chunk, and the application specific
+  // chunk. The last two are not supported in this library.
+  if chunk_size % 2 != 0 {
+    chunk_size += 1;
+  }
+  Ok(([chunk_header[0], chunk_header[1], chunk_header[2], chunk_header[3]],
+      data_size, chunk_size))
+}
+
 // Private functions
 
+/// Creates an `UnknownChunk` from a chunk header and the chunk data.
+#[inline]
+fn unknown_chunk(header: &[u8], data: &[u8], after_data: bool) -> UnknownChunk {
+  UnknownChunk {
+    format:     AudioFormat::AIFF,
+    id:         [header[0], header[1], header[2], header[3]],
+    data:       data.to_vec(),
+    after_data: after_data
+  }
+}
+
 /// This function reads the four byte identifier for each AIFF chunk.
 #[inline]
 fn identify(bytes: &[u8]) -> AudioResult<AiffChunk> {
@@ -178,6 +336,10 @@ fn identify(bytes: &[u8]) -> AudioResult<AiffChunk> {
     FVER => Ok(FormatVersion),
     COMM => Ok(Common),
     SSND => Ok(SoundData),
+    MARK => Ok(Marker),
+    INST => Ok(Instrument),
+    ID3  => Ok(Id3),
+    BASC => Ok(AppleLoop),
     err @ _ => 
       Err(AudioError::Format(
         format!("Do not recognize AIFF chunk with identifier {:?}", err)
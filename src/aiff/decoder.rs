@@ -27,10 +27,11 @@ impl<'r, R> AudioDecoder for Decoder<'r, R> where R: Read + Seek {
   #[inline]
   fn decode(mut self) -> AudioResult<AudioBuffer> {
     let container = try!(AiffContainer::open(&mut self.reader));
-    Ok(AudioBuffer::from_samples(
-      container.sample_rate,
-      container.channels,
-      container.samples
-    ))
+    Ok(AudioBuffer {
+      sample_rate:  container.sample_rate,
+      channels:     container.channels,
+      samples:      container.samples,
+      metadata:     container.metadata
+    })
   }
 }
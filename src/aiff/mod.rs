@@ -9,7 +9,7 @@
 //! - [AIFF Spec](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/AIFF/Docs/AIFF-1.3.pdf)
 //! - [AIFF/AIFFC Spec from Apple](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/AIFF/Docs/MacOS_Sound-extract.pdf)
 
-mod container;
+pub mod container;
 mod chunks;
 pub mod decoder;
 pub mod encoder;
@@ -24,6 +24,10 @@ const AIFC: &'static [u8; 4] = b"AIFC";
 const FVER: &'static [u8; 4] = b"FVER";
 const COMM: &'static [u8; 4] = b"COMM";
 const SSND: &'static [u8; 4] = b"SSND";
+const MARK: &'static [u8; 4] = b"MARK";
+const INST: &'static [u8; 4] = b"INST";
+const ID3:  &'static [u8; 4] = b"ID3 ";
+const BASC: &'static [u8; 4] = b"basc";
 
 /// AIFF-C Version 1 timestamp for the FVER chunk.
 const AIFC_VERSION_1: u32 = 0xA2805140;
@@ -261,7 +265,7 @@ mod io {
       let written_file = File::open(&write_path).unwrap();
       for (inital_byte, written_byte) in
           read_file.bytes().skip(154)
-          .zip(written_file.bytes().skip(72)) {
+          .zip(written_file.bytes().skip(154)) {
         assert_eq!(inital_byte.ok(), written_byte.ok());
       }
     }
@@ -292,7 +296,7 @@ mod io {
       let written_file = File::open(&write_path).unwrap();
       for (inital_byte, written_byte) in
           read_file.bytes().skip(154)
-          .zip(written_file.bytes().skip(72)) {
+          .zip(written_file.bytes().skip(154)) {
         assert_eq!(inital_byte.ok(), written_byte.ok());
       }
     }
@@ -323,7 +327,7 @@ mod io {
       let written_file = File::open(&write_path).unwrap();
       for (inital_byte, written_byte) in
           read_file.bytes().skip(146)
-          .zip(written_file.bytes().skip(64)) {
+          .zip(written_file.bytes().skip(146)) {
         assert_eq!(inital_byte.ok(), written_byte.ok());
       }
     }
@@ -354,9 +358,74 @@ mod io {
       let written_file = File::open(&write_path).unwrap();
       for (inital_byte, written_byte) in
           read_file.bytes().skip(146)
-          .zip(written_file.bytes().skip(64)) {
+          .zip(written_file.bytes().skip(146)) {
         assert_eq!(inital_byte.ok(), written_byte.ok());
       }
     }
   }
+  mod metadata {
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+    use ::audio;
+
+    #[test]
+    fn unknown_chunks_round_trip() {
+      let path = Path::new("tests/aiff/M1F1-int16-AFsp.aif");
+      let audio = audio::open(&path).unwrap();
+      assert_eq!(1, audio.metadata.chunks.len());
+      assert_eq!(b"ANNO", &audio.metadata.chunks[0].id);
+      assert_eq!(73, audio.metadata.chunks[0].data.len());
+      assert!(!audio.metadata.chunks[0].after_data);
+
+      let write_path = Path::new("tests/results/tmp_unknown_i16.aiff");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let mut read_bytes = Vec::new();
+      let mut written_bytes = Vec::new();
+      File::open(&path).unwrap().read_to_end(&mut read_bytes).unwrap();
+      File::open(&write_path).unwrap().read_to_end(&mut written_bytes).unwrap();
+      assert_eq!(read_bytes, written_bytes);
+    }
+
+    #[test]
+    fn malformed_sampler_chunks() {
+      use std::io::Cursor;
+      use byteorder::{BigEndian, ByteOrder};
+      use ::audio::AudioFormat;
+
+      // An instrument chunk that is too short, and a marker chunk that is
+      // cut off by the end of the file, are kept as unrecognized chunks.
+      let mut bytes = Vec::new();
+      File::open("tests/aiff/M1F1-int16-AFsp.aif").unwrap().read_to_end(&mut bytes).unwrap();
+      bytes.extend_from_slice(b"INST\x00\x00\x00\x04\x3C\x00\x00\x7F");
+      bytes.extend_from_slice(b"MARK\x00\x00\x00\x20\x00\x02\x00\x01");
+      let form_size = bytes.len() as u32 - 8;
+      BigEndian::write_u32(&mut bytes[4..8], form_size);
+      let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::AIFF).unwrap();
+      assert_eq!(None, audio.metadata.instrument);
+      let chunks: Vec<(&[u8], usize)> =
+        audio.metadata.chunks.iter().map(|c| (&c.id[..], c.data.len())).collect();
+      assert_eq!(vec![(&b"ANNO"[..], 73), (&b"INST"[..], 4), (&b"MARK"[..], 4)], chunks);
+    }
+
+    #[test]
+    fn malformed_loop_chunks() {
+      use std::io::Cursor;
+      use byteorder::{BigEndian, ByteOrder};
+      use ::audio::AudioFormat;
+
+      // An Apple Loops chunk that is too short is kept as an unrecognized
+      // chunk.
+      let mut bytes = Vec::new();
+      File::open("tests/aiff/M1F1-int16-AFsp.aif").unwrap().read_to_end(&mut bytes).unwrap();
+      bytes.extend_from_slice(b"basc\x00\x00\x00\x06\x00\x00\x00\x01\x00\x04");
+      let form_size = bytes.len() as u32 - 8;
+      BigEndian::write_u32(&mut bytes[4..8], form_size);
+      let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::AIFF).unwrap();
+      assert_eq!(None, audio.metadata.loop_info);
+      assert_eq!(2, audio.metadata.chunks.len());
+      assert_eq!(b"basc", &audio.metadata.chunks[1].id);
+      assert_eq!(6, audio.metadata.chunks[1].data.len());
+    }
+  }
 }
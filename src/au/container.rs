@@ -0,0 +1,168 @@
+use std::io::{Read, Seek, Write};
+use au::{AU_MAGIC, HEADER_SIZE, UNKNOWN_DATA_SIZE};
+use buffer::*;
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use codecs::Codec;
+use codecs::Codec::*;
+use error::*;
+use metadata::Metadata;
+use sample::*;
+use traits::Container;
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct AuContainer {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for AuContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<AuContainer> {
+    // Read and validate header
+    let mut header: [u8; 24] = [0u8; 24];
+    try!(reader.read_exact(&mut header));
+    if &header[0..4] != AU_MAGIC {
+      return Err(AudioError::Format(
+        "Not valid AU".to_string()
+      ));
+    }
+    let data_offset: u32 = BigEndian::read_u32(&header[4..8]);
+    let data_size:   u32 = BigEndian::read_u32(&header[8..12]);
+    let encoding:    u32 = BigEndian::read_u32(&header[12..16]);
+    let sample_rate: u32 = BigEndian::read_u32(&header[16..20]);
+    let channels:    u32 = BigEndian::read_u32(&header[20..24]);
+    if data_offset < HEADER_SIZE {
+      return Err(AudioError::Format(
+        "File is not valid AU (Data offset is within the header)".to_string()
+      ));
+    }
+    if channels == 0 {
+      return Err(AudioError::Format(
+        "File is not valid AU (Audio must have at least one channel)".to_string()
+      ));
+    }
+    let codec = try!(determine_codec(encoding));
+
+    // The annotation fills the space between the header and the audio data.
+    let mut annotation: Vec<u8> = vec![0u8; (data_offset - HEADER_SIZE) as usize];
+    try!(reader.read_exact(&mut annotation));
+
+    // Streaming writers cannot know the size of the audio data in advance, so
+    // an unknown data size means the audio continues to the end of the file.
+    let mut data: Vec<u8> = Vec::new();
+    try!(reader.read_to_end(&mut data));
+    if data_size != UNKNOWN_DATA_SIZE && (data_size as usize) < data.len() {
+      data.truncate(data_size as usize);
+    }
+    // Incomplete frames at the end of the data are ignored.
+    let frame_size = codec.sample_size() * channels as usize;
+    let complete_frames_len = data.len() - data.len() % frame_size;
+    data.truncate(complete_frames_len);
+
+    let mut metadata = Metadata::default();
+    metadata.annotation = read_annotation(&annotation);
+    Ok(AuContainer {
+      bit_depth:    codec.bit_depth() as u32,
+      sample_rate:  sample_rate,
+      channels:     channels,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:      try!(::codecs::decode(&data, codec)),
+      metadata:     metadata
+    })
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    let encoding: u32    = try!(determine_encoding(codec));
+    let data: Vec<u8>    = try!(::codecs::encode(audio, codec));
+    let annotation       = write_annotation(audio.metadata.annotation.as_ref());
+    let data_offset: u32 = HEADER_SIZE + annotation.len() as u32;
+
+    // Write the header to the writer.
+    try!(writer.write(AU_MAGIC));
+    try!(writer.write_u32::<BigEndian>(data_offset));
+    try!(writer.write_u32::<BigEndian>(data.len() as u32));
+    try!(writer.write_u32::<BigEndian>(encoding));
+    try!(writer.write_u32::<BigEndian>(audio.sample_rate));
+    try!(writer.write_u32::<BigEndian>(audio.channels));
+    try!(writer.write(&annotation));
+    // Write audio data to the writer.
+    try!(writer.write(&data));
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Reads the annotation as text, removing the trailing null bytes. Empty
+/// annotations are ignored.
+#[inline]
+fn read_annotation(bytes: &[u8]) -> Option<String> {
+  let text = String::from_utf8_lossy(bytes).trim_right_matches('\u{0}').to_string();
+  if text.is_empty() {
+    None
+  }
+  else {
+    Some(text)
+  }
+}
+
+/// Returns the annotation as null terminated bytes. The annotation must be at
+/// least four bytes long, and is padded to a multiple of four bytes.
+#[inline]
+fn write_annotation(annotation: Option<&String>) -> Vec<u8> {
+  let mut bytes: Vec<u8> =
+    match annotation {
+      Some(text) => text.as_bytes().to_vec(),
+      None       => Vec::new()
+    };
+  bytes.push(0u8);
+  while bytes.len() % 4 != 0 {
+    bytes.push(0u8);
+  }
+  bytes
+}
+
+/// Returns the `Codec` used by the encoding field of the header.
+fn determine_codec(encoding: u32) -> AudioResult<Codec> {
+  match encoding {
+    1  => Ok(G711_ULAW),
+    2  => Ok(LPCM_I8),
+    3  => Ok(LPCM_I16_BE),
+    4  => Ok(LPCM_I24_BE),
+    5  => Ok(LPCM_I32_BE),
+    6  => Ok(LPCM_F32_BE),
+    7  => Ok(LPCM_F64_BE),
+    27 => Ok(G711_ALAW),
+    e @ _ =>
+      return Err(AudioError::Unsupported(
+        format!("Audio encoded with unsupported AU encoding {}", e)
+      ))
+  }
+}
+
+/// Returns the encoding field of the header for a `Codec`. If the container
+/// does not support a codec, an error is returned.
+fn determine_encoding(codec: Codec) -> AudioResult<u32> {
+  match codec {
+    G711_ULAW   => Ok(1),
+    LPCM_I8     => Ok(2),
+    LPCM_I16_BE => Ok(3),
+    LPCM_I24_BE => Ok(4),
+    LPCM_I32_BE => Ok(5),
+    LPCM_F32_BE => Ok(6),
+    LPCM_F64_BE => Ok(7),
+    G711_ALAW   => Ok(27),
+    c @ _ =>
+      return Err(AudioError::Unsupported(
+        format!("AU does not support the {:?} codec", c)
+      ))
+  }
+}
@@ -0,0 +1,115 @@
+//! The Sun/NeXT Audio File Format
+//!
+//! AU files begin with a fixed header describing the encoding, sample rate,
+//! and number of channels of the audio, followed by an optional text
+//! annotation and the audio data. All integers are stored in big-endian
+//! format. Streaming writers that cannot seek back to update the header store
+//! an unknown data size, in which case the audio data continues to the end of
+//! the file.
+//!
+//! References
+//! - [McGill University](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/AU/AU.html)
+//! - [Sun Audio File Format](http://pubs.opengroup.org/external/auformat.html)
+
+mod container;
+pub mod decoder;
+pub mod encoder;
+
+pub use au::decoder::Decoder as Decoder;
+pub use au::encoder::Encoder as Encoder;
+
+/// AU magic number.
+const AU_MAGIC: &'static [u8; 4] = b".snd";
+
+/// Size of the fixed header, excluding the annotation.
+const HEADER_SIZE: u32 = 24;
+
+/// Data size written by streaming writers when the size is not known.
+const UNKNOWN_DATA_SIZE: u32 = 0xFFFFFFFF;
+
+#[cfg(test)]
+mod io {
+  use std::io::Cursor;
+  use std::path::{Path, PathBuf};
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+
+  #[test]
+  fn codecs_eq() {
+    let mut path = PathBuf::from("tests");
+    path.push("aiff");
+    path.push("empty.aiff");
+    let files = vec![
+      ("M1F1-int8-AFsp.aif",      LPCM_I8),
+      ("M1F1-int16-AFsp.aif",     LPCM_I16_BE),
+      ("M1F1-int24-AFsp.aif",     LPCM_I24_BE),
+      ("M1F1-int32-AFsp.aif",     LPCM_I32_BE),
+      ("M1F1-float32C-AFsp.aif",  LPCM_F32_BE),
+      ("M1F1-float64C-AFsp.aif",  LPCM_F64_BE),
+      ("M1F1-mulawC-AFsp.aif",    G711_ULAW),
+      ("M1F1-AlawC-AFsp.aif",     G711_ALAW)
+    ];
+
+    for &(file, codec) in files.iter() {
+      path.set_file_name(file);
+      println!("{:?}", path.as_path());
+      let audio = audio::open(path.as_path()).unwrap();
+
+      let write_path = Path::new("tests/results/tmp_codec.au");
+      assert!(audio::save_as(&write_path, &audio, codec).is_ok());
+
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(audio.channels,      verify.channels);
+      assert_eq!(audio.sample_rate,   verify.sample_rate);
+      assert_eq!(audio.samples.len(), verify.samples.len());
+      for (inital_sample, written_sample) in
+          audio.samples.iter().zip(&verify.samples) {
+        assert_eq!(inital_sample, written_sample);
+      }
+    }
+  }
+
+  #[test]
+  fn snd_extension() {
+    let audio = AudioBuffer::from_samples(8000, 2, vec![0f32, 0.5f32, -0.5f32, 0.25f32]);
+    let write_path = Path::new("tests/results/tmp_default.snd");
+    assert!(audio::save(&write_path, &audio).is_ok());
+    let verify = audio::open(&write_path).unwrap();
+    assert_eq!(audio.samples, verify.samples);
+  }
+
+  #[test]
+  fn unsupported_codec() {
+    let audio = AudioBuffer::from_samples(8000, 1, vec![0f32; 4]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::AU, LPCM_I16_LE).is_err());
+  }
+
+  #[test]
+  fn annotation_round_trip() {
+    let mut audio = AudioBuffer::from_samples(8000, 1, vec![0f32, 0.5f32, -0.5f32]);
+    audio.metadata.annotation = Some("Recorded on line 2".to_string());
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::AU).is_ok());
+    // The annotation is null terminated and padded to a multiple of 4 bytes.
+    assert_eq!(44, bytes[7]);
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::AU).unwrap();
+    assert_eq!(audio.metadata.annotation, verify.metadata.annotation);
+    assert_eq!(audio.samples, verify.samples);
+  }
+
+  #[test]
+  fn unknown_data_size() {
+    let audio = AudioBuffer::from_samples(8000, 1, vec![0f32, 0.5f32, -0.5f32]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::AU, G711_ULAW).is_ok());
+    for byte in bytes[8..12].iter_mut() {
+      *byte = 0xFF;
+    }
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::AU).unwrap();
+    assert_eq!(None, verify.metadata.annotation);
+    assert_eq!(3, verify.samples.len());
+  }
+}
@@ -1,42 +1,114 @@
 use std::fs::File;
 use std::io::{BufWriter, Read, Seek, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use aiff::Decoder as AiffDecoder;
 use aiff::Encoder as AiffEncoder;
+use au::Decoder as AuDecoder;
+use au::Encoder as AuEncoder;
 use buffer::*;
+use byteorder::BigEndian;
+use caf::Decoder as CafDecoder;
+use caf::Encoder as CafEncoder;
 use codecs::Codec;
+use dsp::silence::SilenceSpec;
 use error::*;
+use mp3::Decoder as Mp3Decoder;
+use mp4::Decoder as Mp4Decoder;
+use mp4::Encoder as Mp4Encoder;
+use ogg::Decoder as OggDecoder;
+use opus::Decoder as OpusDecoder;
+use opus::Encoder as OpusEncoder;
+use raw::RawSpec;
+use raw::Decoder as RawDecoder;
+use raw::Encoder as RawEncoder;
+use shorten::Decoder as ShortenDecoder;
+use sphere::Decoder as SphereDecoder;
+use sphere::Encoder as SphereEncoder;
+use svx::Decoder as SvxDecoder;
+use svx::Encoder as SvxEncoder;
 use traits::{AudioDecoder, AudioEncoder};
+use tta::Decoder as TtaDecoder;
+use voc::Decoder as VocDecoder;
+use voc::Encoder as VocEncoder;
+use w64::Decoder as W64Decoder;
+use w64::Encoder as W64Encoder;
+use wavpack::Decoder as WavPackDecoder;
+use wavpack::Encoder as WavPackEncoder;
 use wave::Decoder as WaveDecoder;
 use wave::Encoder as WaveEncoder;
+use wave::chunks::RiffByteOrder;
 
 /// All supported audio formats.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum AudioFormat {
   /// Waveform Audio File Format
   WAVE,
+  /// Big-endian Waveform Audio File Format, which uses the `.wav` extension
+  /// of WAVE files
+  RIFX,
   /// Audio Interchange File Format
-  AIFF
+  AIFF,
+  /// Sun/NeXT Audio File Format
+  AU,
+  /// Core Audio Format
+  CAF,
+  /// Sony Wave64 Format
+  W64,
+  /// Headerless audio described by a `RawSpec`
+  Raw(RawSpec),
+  /// NIST SPHERE Format
+  Sphere,
+  /// Ogg Vorbis Format
+  Ogg,
+  /// Ogg Opus Format
+  Opus,
+  /// MPEG Audio Layer III Format
+  MP3,
+  /// WavPack Format
+  WavPack,
+  /// MPEG-4 Audio Format
+  MP4,
+  /// True Audio Format
+  TTA,
+  /// Shorten Format
+  Shorten,
+  /// Creative Voice Format
+  VOC,
+  /// Amiga 8-Bit Sampled Voice Format
+  SVX
 }
 
-/// Opens and loads the audio file into memory from a `Path`.
-/// 
-/// The necessary decoder is determined by the `Path` file extension. An
-/// `AudioError` is returned if the file type is not supported or if an error
-/// occurred in the decoding process.
-pub fn open(path: &Path) -> AudioResult<AudioBuffer> {
+/// Determines the `AudioFormat` of a file from its `Path` extension. Raw
+/// audio files are assumed to use the default layout of their extension.
+fn determine_format(path: &Path) -> AudioResult<AudioFormat> {
   let ext = path.extension().and_then(|s| s.to_str());
   if let Some(file_format) = ext {
-    let format = match file_format {
-      "wav"|"wave"        => AudioFormat::WAVE,
-      "aif"|"aiff"|"aifc" => AudioFormat::AIFF,
-      f_ext @ _           => return
+    match file_format {
+      "wav"|"wave"        => Ok(AudioFormat::WAVE),
+      "aif"|"aiff"|"aifc" => Ok(AudioFormat::AIFF),
+      "au"|"snd"          => Ok(AudioFormat::AU),
+      "caf"               => Ok(AudioFormat::CAF),
+      "w64"               => Ok(AudioFormat::W64),
+      "sph"|"nist"        => Ok(AudioFormat::Sphere),
+      "ogg"|"oga"         => Ok(AudioFormat::Ogg),
+      "opus"              => Ok(AudioFormat::Opus),
+      "mp3"               => Ok(AudioFormat::MP3),
+      "wv"                => Ok(AudioFormat::WavPack),
+      "m4a"|"mp4"         => Ok(AudioFormat::MP4),
+      "tta"               => Ok(AudioFormat::TTA),
+      "shn"               => Ok(AudioFormat::Shorten),
+      "voc"               => Ok(AudioFormat::VOC),
+      "8svx"|"svx"|"iff"  => Ok(AudioFormat::SVX),
+      "raw"|"pcm"         => Ok(AudioFormat::Raw(RawSpec::default())),
+      "ul"                =>
+        Ok(AudioFormat::Raw(RawSpec::new(Codec::G711_ULAW, 8000, 1))),
+      "al"                =>
+        Ok(AudioFormat::Raw(RawSpec::new(Codec::G711_ALAW, 8000, 1))),
+      f_ext @ _           =>
         Err(AudioError::Format(
           format!("Did not recognize audio file format .{}", f_ext)
         ))
-    };      
-    let mut file = try!(File::open(path));
-    load(&mut file, format)
+    }
   }
   else {
     Err(AudioError::Format(
@@ -45,6 +117,28 @@ pub fn open(path: &Path) -> AudioResult<AudioBuffer> {
   }
 }
 
+/// Opens and loads the audio file into memory from a `Path`.
+/// 
+/// The necessary decoder is determined by the `Path` file extension. An
+/// `AudioError` is returned if the file type is not supported or if an error
+/// occurred in the decoding process.
+pub fn open(path: &Path) -> AudioResult<AudioBuffer> {
+  let format = try!(determine_format(path));
+  let mut file = try!(File::open(path));
+  load(&mut file, format)
+}
+
+/// Opens and loads the audio file into memory from a `Path` using a specified
+/// `AudioFormat`.
+///
+/// The file extension is ignored, which allows raw audio to be described by
+/// a `RawSpec`. An `AudioError` is returned if an error occurred in the
+/// decoding process.
+pub fn open_as(path: &Path, format: AudioFormat) -> AudioResult<AudioBuffer> {
+  let mut file = try!(File::open(path));
+  load(&mut file, format)
+}
+
 /// Loads the audio from a reader into memory.
 ///
 /// The necessary decoder is determined by the provided `AudioFormat`. An
@@ -53,8 +147,55 @@ pub fn open(path: &Path) -> AudioResult<AudioBuffer> {
 #[inline]
 pub fn load<R: Read+Seek>(reader: &mut R, format: AudioFormat) -> AudioResult<AudioBuffer> {
   match format {
-    AudioFormat::WAVE => WaveDecoder::new(reader).decode(),
+    AudioFormat::WAVE |
+    AudioFormat::RIFX => WaveDecoder::new(reader).decode(),
     AudioFormat::AIFF => AiffDecoder::new(reader).decode(),
+    AudioFormat::AU   => AuDecoder::new(reader).decode(),
+    AudioFormat::CAF  => CafDecoder::new(reader).decode(),
+    AudioFormat::W64  => W64Decoder::new(reader).decode(),
+    AudioFormat::Raw(spec) => RawDecoder::new(reader, spec).decode(),
+    AudioFormat::Sphere => SphereDecoder::new(reader).decode(),
+    AudioFormat::Ogg  => OggDecoder::new(reader).decode(),
+    AudioFormat::Opus => OpusDecoder::new(reader).decode(),
+    AudioFormat::MP3  => Mp3Decoder::new(reader).decode(),
+    AudioFormat::WavPack => WavPackDecoder::new(reader).decode(),
+    AudioFormat::MP4  => Mp4Decoder::new(reader).decode(),
+    AudioFormat::TTA  => TtaDecoder::new(reader).decode(),
+    AudioFormat::Shorten => ShortenDecoder::new(reader).decode(),
+    AudioFormat::VOC  => VocDecoder::new(reader).decode(),
+    AudioFormat::SVX  => SvxDecoder::new(reader).decode(),
+  }
+}
+
+/// Opens and loads the audio file into memory from a `Path`, starting at a
+/// frame.
+///
+/// The necessary decoder is determined by the `Path` file extension. An
+/// `AudioError` is returned if the file type is not supported or if an error
+/// occurred in the decoding process.
+pub fn open_from(path: &Path, frame: u64) -> AudioResult<AudioBuffer> {
+  let format = try!(determine_format(path));
+  let mut file = try!(File::open(path));
+  load_from(&mut file, format, frame)
+}
+
+/// Loads the audio from a reader into memory, starting at a frame.
+///
+/// Ogg Vorbis streams are seeked by the granule positions of their pages, so
+/// the frames before the seek point are not decoded. Other formats are
+/// decoded in full and the frames before the seek point are discarded. An
+/// `AudioError` is returned if the format is not supported or if an error
+/// occurred in the decoding process.
+pub fn load_from<R: Read+Seek>(reader: &mut R, format: AudioFormat, frame: u64) -> AudioResult<AudioBuffer> {
+  match format {
+    AudioFormat::Ogg => OggDecoder::new(reader).decode_from(frame),
+    _ => {
+      let mut audio = try!(load(reader, format));
+      let start = (frame as usize).saturating_mul(audio.channels as usize)
+                  .min(audio.samples.len());
+      audio.samples.drain(.. start);
+      Ok(audio)
+    }
   }
 }
 
@@ -64,24 +205,9 @@ pub fn load<R: Read+Seek>(reader: &mut R, format: AudioFormat) -> AudioResult<Au
 /// the default codec of the `AudioFormat`. An `AudioError` is returned if the
 /// file type is not supported or if an error occurred in the encoding process.
 pub fn save(path: &Path, audio: &AudioBuffer) -> AudioResult<()> {
-  let ext = path.extension().and_then(|s| s.to_str());
-  if let Some(file_format) = ext {
-    let format = match file_format {
-      "wav"|"wave"        => AudioFormat::WAVE,
-      "aif"|"aiff"|"aifc" => AudioFormat::AIFF,
-      f_ext @ _           => return
-        Err(AudioError::Format(
-          format!("Did not recognize audio file format .{}", f_ext)
-        ))
-    };
-    let mut file = try!(File::create(path));
-    write(&mut file, audio, format)
-  }
-  else {
-    Err(AudioError::Format(
-      format!("Did not recognize file {}", path.display())
-    ))
-  }
+  let format = try!(determine_format(path));
+  let mut file = try!(File::create(path));
+  write(&mut file, audio, format)
 }
 
 /// Saves an `AudioBuffer` to a `Path` using a specified `Codec`.
@@ -91,24 +217,60 @@ pub fn save(path: &Path, audio: &AudioBuffer) -> AudioResult<()> {
 /// supported, the `Codec` is not supported by the `AudioFormat`, or if an error
 /// occurred in the encoding process.
 pub fn save_as(path: &Path, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
-  let ext = path.extension().and_then(|s| s.to_str());
-  if let Some(file_format) = ext {
-    let format = match file_format {
-      "wav"|"wave"        => AudioFormat::WAVE,
-      "aif"|"aiff"|"aifc" => AudioFormat::AIFF,
-      f_ext @ _           => return
-        Err(AudioError::Format(
-          format!("Did not recognize audio file format .{}", f_ext)
-        ))
-    };
+  let format = try!(determine_format(path));
+  let mut file = try!(File::create(path));
+  write_as(&mut file, audio, format, codec)
+}
+
+/// Splits an `AudioBuffer` at its silences and saves each part to a file
+/// next to a `Path`, returning the paths of the files in order.
+///
+/// The parts are numbered from 1 after the file stem, so splitting to
+/// `prompt.wav` saves `prompt-001.wav`, `prompt-002.wav` and so on. The
+/// necessary encoder is determined by the `Path` file extension and uses the
+/// default codec of the `AudioFormat`. An `AudioError` is returned if the
+/// file type is not supported or if an error occurred in the encoding process.
+pub fn save_split(path: &Path, audio: &AudioBuffer, spec: SilenceSpec) -> AudioResult<Vec<PathBuf>> {
+  let format = try!(determine_format(path));
+  let parts = try!(audio.split_at_silence(spec));
+  let paths = try!(split_paths(path, parts.len()));
+  for (part, path) in parts.iter().zip(paths.iter()) {
     let mut file = try!(File::create(path));
-    write_as(&mut file, audio, format, codec)
+    try!(write(&mut file, part, format));
   }
-  else {
-    Err(AudioError::Format(
-      format!("Did not recognize file {}", path.display())
-    ))
+  Ok(paths)
+}
+
+/// Splits an `AudioBuffer` at its silences and saves each part to a file
+/// next to a `Path` using a specified `Codec`, returning the paths of the
+/// files in order.
+///
+/// The parts are numbered as for `save_split`. An `AudioError` is returned if
+/// the file type is not supported, the `Codec` is not supported by the
+/// `AudioFormat`, or if an error occurred in the encoding process.
+pub fn save_split_as(path: &Path, audio: &AudioBuffer, spec: SilenceSpec, codec: Codec) -> AudioResult<Vec<PathBuf>> {
+  let format = try!(determine_format(path));
+  let parts = try!(audio.split_at_silence(spec));
+  let paths = try!(split_paths(path, parts.len()));
+  for (part, path) in parts.iter().zip(paths.iter()) {
+    let mut file = try!(File::create(path));
+    try!(write_as(&mut file, part, format, codec));
   }
+  Ok(paths)
+}
+
+/// Returns the numbered paths of the parts of a split file.
+fn split_paths(path: &Path, count: usize) -> AudioResult<Vec<PathBuf>> {
+  let stem = match path.file_stem().and_then(|s| s.to_str()) {
+    Some(stem) => stem,
+    None => return Err(AudioError::Format(
+      "Cannot split audio to a path without a file name".to_string()
+    ))
+  };
+  let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+  Ok((1..count + 1)
+     .map(|i| path.with_file_name(format!("{}-{:03}.{}", stem, i, ext)))
+     .collect())
 }
 
 /// Buffers and writes an `AudioBuffer` to a writer using a specified
@@ -124,7 +286,41 @@ pub fn write<W: Write>(writer: &mut W,
   match format {
     AudioFormat::WAVE => WaveEncoder::new(&mut BufWriter::new(writer))
                          .encode(audio),
+    AudioFormat::RIFX => WaveEncoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, Codec::LPCM_I16_BE),
     AudioFormat::AIFF => AiffEncoder::new(&mut BufWriter::new(writer))
+                         .encode(audio),
+    AudioFormat::AU   => AuEncoder::new(&mut BufWriter::new(writer))
+                         .encode(audio),
+    AudioFormat::CAF  => CafEncoder::new(&mut BufWriter::new(writer))
+                         .encode(audio),
+    AudioFormat::W64  => W64Encoder::new(&mut BufWriter::new(writer))
+                         .encode(audio),
+    AudioFormat::Raw(spec) => RawEncoder::new(&mut BufWriter::new(writer), spec)
+                              .encode(audio),
+    AudioFormat::Sphere => SphereEncoder::new(&mut BufWriter::new(writer))
+                           .encode(audio),
+    AudioFormat::Ogg  => Err(AudioError::Unsupported(
+                           "Encoding Ogg Vorbis is not supported".to_string()
+                         )),
+    AudioFormat::Opus => OpusEncoder::new(&mut BufWriter::new(writer))
+                         .encode(audio),
+    AudioFormat::MP3  => Err(AudioError::Unsupported(
+                           "Encoding MP3 is not supported".to_string()
+                         )),
+    AudioFormat::WavPack => WavPackEncoder::new(&mut BufWriter::new(writer))
+                            .encode(audio),
+    AudioFormat::MP4  => Mp4Encoder::new(&mut BufWriter::new(writer))
+                         .encode(audio),
+    AudioFormat::TTA  => Err(AudioError::Unsupported(
+                           "Encoding TTA is not supported".to_string()
+                         )),
+    AudioFormat::Shorten => Err(AudioError::Unsupported(
+                              "Encoding Shorten is not supported".to_string()
+                            )),
+    AudioFormat::VOC  => VocEncoder::new(&mut BufWriter::new(writer))
+                         .encode(audio),
+    AudioFormat::SVX  => SvxEncoder::new(&mut BufWriter::new(writer))
                          .encode(audio)
   }
 }
@@ -133,7 +329,8 @@ pub fn write<W: Write>(writer: &mut W,
 /// `AudioFormat` and `Codec`.
 ///
 /// The necessary encoder is determined by the given `AudioFormat` and uses
-/// the given `Codec`. An `AudioError` is returned if the `Codec` is not
+/// the given `Codec`. RIFX files store the samples of the `Codec` in
+/// big-endian byte order. An `AudioError` is returned if the `Codec` is not
 /// supported by the `AudioFormat` or if an error occurred in the encoding
 /// process.
 #[inline]
@@ -144,7 +341,41 @@ pub fn write_as<W: Write>(writer: &mut W,
   match format {
     AudioFormat::WAVE => WaveEncoder::new(&mut BufWriter::new(writer))
                          .encode_as(audio, codec),
+    AudioFormat::RIFX => WaveEncoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, <BigEndian as RiffByteOrder>::codec(codec)),
     AudioFormat::AIFF => AiffEncoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, codec),
+    AudioFormat::AU   => AuEncoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, codec),
+    AudioFormat::CAF  => CafEncoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, codec),
+    AudioFormat::W64  => W64Encoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, codec),
+    AudioFormat::Raw(spec) => RawEncoder::new(&mut BufWriter::new(writer), spec)
+                              .encode_as(audio, codec),
+    AudioFormat::Sphere => SphereEncoder::new(&mut BufWriter::new(writer))
+                           .encode_as(audio, codec),
+    AudioFormat::Ogg  => Err(AudioError::Unsupported(
+                           "Encoding Ogg Vorbis is not supported".to_string()
+                         )),
+    AudioFormat::Opus => OpusEncoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, codec),
+    AudioFormat::MP3  => Err(AudioError::Unsupported(
+                           "Encoding MP3 is not supported".to_string()
+                         )),
+    AudioFormat::WavPack => WavPackEncoder::new(&mut BufWriter::new(writer))
+                            .encode_as(audio, codec),
+    AudioFormat::MP4  => Mp4Encoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, codec),
+    AudioFormat::TTA  => Err(AudioError::Unsupported(
+                           "Encoding TTA is not supported".to_string()
+                         )),
+    AudioFormat::Shorten => Err(AudioError::Unsupported(
+                              "Encoding Shorten is not supported".to_string()
+                            )),
+    AudioFormat::VOC  => VocEncoder::new(&mut BufWriter::new(writer))
+                         .encode_as(audio, codec),
+    AudioFormat::SVX  => SvxEncoder::new(&mut BufWriter::new(writer))
                          .encode_as(audio, codec)
   }
 }
@@ -1,4 +1,14 @@
+use std::ops::Range;
+use std::time::Duration;
+use dsp::fade::FadeCurve;
+use dsp::filter::Biquad;
+use dsp::loudness::Loudness;
+use dsp::remix::ChannelMatrix;
+use dsp::resample::ResampleQuality;
+use dsp::silence::SilenceSpec;
+use dsp::stats::Stats;
 use error::AudioResult;
+use metadata::Metadata;
 use sample::Sample;
 
 /// A container for audio samples and important attributes.
@@ -9,7 +19,9 @@ pub struct AudioBuffer {
   /// Number of channels
   pub channels: u32,
   /// Decoded audio samples
-  pub samples: Vec<Sample>
+  pub samples: Vec<Sample>,
+  /// Information describing the audio
+  pub metadata: Metadata
 }
 
 impl AudioBuffer {
@@ -18,7 +30,8 @@ impl AudioBuffer {
     AudioBuffer {
       sample_rate: sample_rate,
       channels: channels,
-      samples: vec![0f32; 0]
+      samples: vec![0f32; 0],
+      metadata: Metadata::default()
     }
   }
 
@@ -27,7 +40,8 @@ impl AudioBuffer {
     AudioBuffer {
       sample_rate: sample_rate,
       channels: channels,
-      samples: samples
+      samples: samples,
+      metadata: Metadata::default()
     }
   }
 
@@ -39,7 +53,8 @@ impl AudioBuffer {
     Ok(AudioBuffer {
       sample_rate: sample_rate,
       channels: channels,
-      samples: try!(::codecs::decode(bytes, codec))
+      samples: try!(::codecs::decode(bytes, codec)),
+      metadata: Metadata::default()
     })
   }
 
@@ -48,6 +63,100 @@ impl AudioBuffer {
     self.samples.len() / self.channels as usize * 1000
                        / self.sample_rate as usize
   }
+
+  /// Converts the audio to a different sample rate using band-limited
+  /// interpolation of the given quality. The frames of loops in the metadata
+  /// are moved to the same times at the new sample rate.
+  pub fn resample(&mut self, sample_rate: u32, quality: ResampleQuality) -> AudioResult<()> {
+    ::dsp::resample::resample(self, sample_rate, quality)
+  }
+
+  /// Remixes the channels of the audio using a matrix of gains, such as one
+  /// of the ITU-R BS.775 downmixes. The number of channels, and any channel
+  /// layout kept in the metadata, are changed to those of the output.
+  pub fn remix(&mut self, matrix: &ChannelMatrix) -> AudioResult<()> {
+    ::dsp::remix::remix(self, matrix)
+  }
+
+  /// Changes the gain of the audio by the given dB.
+  pub fn apply_gain(&mut self, db: f64) {
+    ::dsp::fade::gain(&mut self.samples, db)
+  }
+
+  /// Fades in the audio over a range of frames, silencing the frames before
+  /// it.
+  pub fn fade_in(&mut self, frames: Range<usize>, curve: FadeCurve) -> AudioResult<()> {
+    ::dsp::fade::fade(self, true, frames, curve)
+  }
+
+  /// Fades out the audio over a range of frames, silencing the frames after
+  /// it.
+  pub fn fade_out(&mut self, frames: Range<usize>, curve: FadeCurve) -> AudioResult<()> {
+    ::dsp::fade::fade(self, false, frames, curve)
+  }
+
+  /// Fades in the start of the audio for a duration, or all of the audio if
+  /// it is shorter.
+  pub fn fade_in_for(&mut self, duration: Duration, curve: FadeCurve) -> AudioResult<()> {
+    let num_frames = self.samples.len() / self.channels.max(1) as usize;
+    let frames = ::dsp::fade::frames_in(duration, self.sample_rate).min(num_frames);
+    self.fade_in(0 .. frames, curve)
+  }
+
+  /// Fades out the end of the audio for a duration, or all of the audio if
+  /// it is shorter.
+  pub fn fade_out_for(&mut self, duration: Duration, curve: FadeCurve) -> AudioResult<()> {
+    let num_frames = self.samples.len() / self.channels.max(1) as usize;
+    let frames = ::dsp::fade::frames_in(duration, self.sample_rate).min(num_frames);
+    self.fade_out(num_frames - frames .. num_frames, curve)
+  }
+
+  /// Joins the audio with the audio following it, crossfading between them
+  /// for a duration. Both must have the same sample rate and channels.
+  pub fn crossfade(&self, next: &AudioBuffer, duration: Duration, curve: FadeCurve) -> AudioResult<AudioBuffer> {
+    let frames = ::dsp::fade::frames_in(duration, self.sample_rate);
+    ::dsp::fade::crossfade(self, next, frames, curve)
+  }
+
+  /// Filters each channel of the audio through a cascade of biquads, such as
+  /// a Butterworth high pass or a set of EQ bands.
+  pub fn filter(&mut self, stages: &[Biquad]) -> AudioResult<()> {
+    ::dsp::filter::filter(self, stages)
+  }
+
+  /// Measures the integrated loudness, loudness range and true peak of the
+  /// audio following EBU R 128.
+  pub fn loudness(&self) -> AudioResult<Loudness> {
+    ::dsp::loudness::measure(self)
+  }
+
+  /// Changes the gain of the audio so its integrated loudness matches a
+  /// target in LUFS, such as -23 LUFS for EBU R 128. Returns the loudness of
+  /// the audio after the change.
+  pub fn normalize_loudness(&mut self, target: f64) -> AudioResult<Loudness> {
+    ::dsp::loudness::normalize(self, target)
+  }
+
+  /// Gathers the peak, true peak, RMS, DC offset, crest factor, extremes and
+  /// number of clipped samples of each channel of the audio.
+  pub fn analyze(&self) -> AudioResult<Stats> {
+    ::dsp::stats::analyze(self)
+  }
+
+  /// Returns the ranges of frames that are silent, in order.
+  pub fn silences(&self, spec: SilenceSpec) -> AudioResult<Vec<Range<usize>>> {
+    ::dsp::silence::detect(self, spec)
+  }
+
+  /// Removes the silence at the start and end of the audio.
+  pub fn trim_silence(&mut self, spec: SilenceSpec) -> AudioResult<()> {
+    ::dsp::silence::trim(self, spec)
+  }
+
+  /// Splits the audio into the parts between its silences.
+  pub fn split_at_silence(&self, spec: SilenceSpec) -> AudioResult<Vec<AudioBuffer>> {
+    ::dsp::silence::split(self, spec)
+  }
 }
 
 #[cfg(test)]
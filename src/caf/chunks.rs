@@ -0,0 +1,477 @@
+//! CAF Chunks
+use std::io::Write;
+use buffer::AudioBuffer;
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use caf::{ALAC, ALAW, CHAN, DESC, INFO, LPCM, PAKT, ULAW};
+use codecs::alac::FRAME_LENGTH;
+use codecs::Codec;
+use codecs::Codec::*;
+use error::*;
+use traits::Chunk;
+
+/// Format flag set when linear PCM samples are floating point.
+const FLAG_IS_FLOAT: u32 = 1;
+/// Format flag set when linear PCM samples are little endian.
+const FLAG_IS_LITTLE_ENDIAN: u32 = 2;
+
+/// Format flags giving the bit depth of Apple Lossless samples.
+const FLAG_16_BIT_SOURCE: u32 = 1;
+const FLAG_20_BIT_SOURCE: u32 = 2;
+const FLAG_24_BIT_SOURCE: u32 = 3;
+const FLAG_32_BIT_SOURCE: u32 = 4;
+
+/// Channel layout tag used when the layout is given by channel descriptions.
+const LAYOUT_USE_DESCRIPTIONS: u32 = 0;
+/// Channel layout tag used when the layout is given by the channel bitmap.
+const LAYOUT_USE_BITMAP: u32 = 1 << 16;
+/// Channel layout tags for common layouts. The lower 16 bits of a layout tag
+/// hold the number of channels.
+const LAYOUT_MONO: u32 = (100 << 16) | 1;
+const LAYOUT_STEREO: u32 = (101 << 16) | 2;
+const LAYOUT_DISCRETE_IN_ORDER: u32 = 147 << 16;
+/// Channel layout tags of ALAC for three to eight channels.
+const LAYOUTS_ALAC: [u32; 6] = [
+  (113 << 16) | 3,
+  (116 << 16) | 4,
+  (120 << 16) | 5,
+  (124 << 16) | 6,
+  (142 << 16) | 7,
+  (127 << 16) | 8
+];
+
+/// Supported CAF chunks
+///
+/// The audio data chunk is read directly by the container since its size may
+/// be unknown, and free chunks only reserve space in the file. A free chunk is
+/// written to pad the header, so the audio data starts on a page boundary as
+/// in files written by Apple tools.
+pub enum CafChunk {
+  Description,
+  AudioData,
+  ChannelLayout,
+  Information,
+  PacketTable,
+  MagicCookie,
+  Free
+}
+
+/// The CAF Audio Description Chunk.
+///
+/// This chunk is required to be the first chunk of the file, and describes
+/// how the audio data is encoded.
+#[derive(Debug, Clone, Copy)]
+pub struct DescriptionChunk {
+  pub sample_rate:        f64,
+  pub format_id:          [u8; 4],
+  pub format_flags:       u32,
+  pub bytes_per_packet:   u32,
+  pub frames_per_packet:  u32,
+  pub channels_per_frame: u32,
+  pub bits_per_channel:   u32
+}
+
+impl DescriptionChunk {
+  #[inline]
+  pub fn calculate_size() -> i64 {
+    32
+  }
+
+  /// Returns the `Codec` used to encode the audio data.
+  pub fn codec(&self) -> AudioResult<Codec> {
+    let float         = self.format_flags & FLAG_IS_FLOAT != 0;
+    let little_endian = self.format_flags & FLAG_IS_LITTLE_ENDIAN != 0;
+    match (&self.format_id, float, little_endian, self.bits_per_channel) {
+      (LPCM, false, _,      8) => Ok(LPCM_I8),
+      (LPCM, false, true,  16) => Ok(LPCM_I16_LE),
+      (LPCM, false, false, 16) => Ok(LPCM_I16_BE),
+      (LPCM, false, true,  24) => Ok(LPCM_I24_LE),
+      (LPCM, false, false, 24) => Ok(LPCM_I24_BE),
+      (LPCM, false, true,  32) => Ok(LPCM_I32_LE),
+      (LPCM, false, false, 32) => Ok(LPCM_I32_BE),
+      (LPCM, true,  true,  32) => Ok(LPCM_F32_LE),
+      (LPCM, true,  false, 32) => Ok(LPCM_F32_BE),
+      (LPCM, true,  true,  64) => Ok(LPCM_F64_LE),
+      (LPCM, true,  false, 64) => Ok(LPCM_F64_BE),
+      (ULAW, _,     _,      _) => Ok(G711_ULAW),
+      (ALAW, _,     _,      _) => Ok(G711_ALAW),
+      (ALAC, _,     _,      _) =>
+        match self.format_flags {
+          FLAG_16_BIT_SOURCE => Ok(ALAC_I16),
+          FLAG_20_BIT_SOURCE => Ok(ALAC_I20),
+          FLAG_24_BIT_SOURCE => Ok(ALAC_I24),
+          FLAG_32_BIT_SOURCE => Ok(ALAC_I32),
+          f =>
+            Err(AudioError::Unsupported(
+              format!("Apple Lossless audio with format flags {} is not supported", f)
+            ))
+        },
+      (_, _, _, _) =>
+        Err(AudioError::Unsupported(
+          format!("Audio encoded with unsupported CAF format {:?}",
+                  String::from_utf8_lossy(&self.format_id))
+        ))
+    }
+  }
+
+  pub fn write<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    let (format_id, format_flags, bits_per_channel) =
+      match codec {
+        LPCM_I8     => (LPCM, 0,                     8),
+        LPCM_I16_LE => (LPCM, FLAG_IS_LITTLE_ENDIAN, 16),
+        LPCM_I16_BE => (LPCM, 0,                     16),
+        LPCM_I24_LE => (LPCM, FLAG_IS_LITTLE_ENDIAN, 24),
+        LPCM_I24_BE => (LPCM, 0,                     24),
+        LPCM_I32_LE => (LPCM, FLAG_IS_LITTLE_ENDIAN, 32),
+        LPCM_I32_BE => (LPCM, 0,                     32),
+        LPCM_F32_LE => (LPCM, FLAG_IS_FLOAT | FLAG_IS_LITTLE_ENDIAN, 32),
+        LPCM_F32_BE => (LPCM, FLAG_IS_FLOAT,         32),
+        LPCM_F64_LE => (LPCM, FLAG_IS_FLOAT | FLAG_IS_LITTLE_ENDIAN, 64),
+        LPCM_F64_BE => (LPCM, FLAG_IS_FLOAT,         64),
+        G711_ULAW   => (ULAW, 0,                     8),
+        G711_ALAW   => (ALAW, 0,                     8),
+        ALAC_I16    => (ALAC, FLAG_16_BIT_SOURCE,    0),
+        ALAC_I20    => (ALAC, FLAG_20_BIT_SOURCE,    0),
+        ALAC_I24    => (ALAC, FLAG_24_BIT_SOURCE,    0),
+        ALAC_I32    => (ALAC, FLAG_32_BIT_SOURCE,    0),
+        c @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("CAF does not support the {:?} codec", c)
+          ))
+      };
+    try!(writer.write(DESC));
+    try!(writer.write_i64::<BigEndian>(DescriptionChunk::calculate_size()));
+    try!(writer.write_f64::<BigEndian>(audio.sample_rate as f64));
+    try!(writer.write(format_id));
+    try!(writer.write_u32::<BigEndian>(format_flags));
+    // Packets of compressed audio vary in size and hold many frames.
+    let frames_per_packet = if format_id == ALAC { FRAME_LENGTH } else { 1 };
+    try!(writer.write_u32::<BigEndian>(bits_per_channel / 8 * audio.channels));
+    try!(writer.write_u32::<BigEndian>(frames_per_packet));
+    try!(writer.write_u32::<BigEndian>(audio.channels));
+    try!(writer.write_u32::<BigEndian>(bits_per_channel));
+    Ok(())
+  }
+}
+
+impl Chunk for DescriptionChunk {
+  #[inline]
+  fn read(buffer: &[u8]) -> AudioResult<DescriptionChunk> {
+    if buffer.len() < 32 {
+      return Err(AudioError::Format(
+        "Audio description chunk is too small".to_string()
+      ));
+    }
+    Ok(
+      DescriptionChunk {
+        sample_rate:        BigEndian::read_f64(&buffer[0..8]),
+        format_id:          [buffer[8], buffer[9], buffer[10], buffer[11]],
+        format_flags:       BigEndian::read_u32(&buffer[12..16]),
+        bytes_per_packet:   BigEndian::read_u32(&buffer[16..20]),
+        frames_per_packet:  BigEndian::read_u32(&buffer[20..24]),
+        channels_per_frame: BigEndian::read_u32(&buffer[24..28]),
+        bits_per_channel:   BigEndian::read_u32(&buffer[28..32])
+      }
+    )
+  }
+}
+
+/// A description of a single channel in a channel layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelDescription {
+  pub label:        u32,
+  pub flags:        u32,
+  pub coordinates:  [f32; 3]
+}
+
+/// The CAF Channel Layout Chunk.
+///
+/// The layout is given by a layout tag, a bitmap of speaker positions, or a
+/// list of channel descriptions. This chunk is required for audio with more
+/// than two channels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelLayoutChunk {
+  pub tag:          u32,
+  pub bitmap:       u32,
+  pub descriptions: Vec<ChannelDescription>
+}
+
+impl ChannelLayoutChunk {
+  /// Creates the default layout for the number of channels.
+  pub fn from_channels(channels: u32) -> ChannelLayoutChunk {
+    ChannelLayoutChunk {
+      tag:
+        match channels {
+          1 => LAYOUT_MONO,
+          2 => LAYOUT_STEREO,
+          n => LAYOUT_DISCRETE_IN_ORDER | n
+        },
+      bitmap:       0,
+      descriptions: Vec::new()
+    }
+  }
+
+  /// Creates the layout in which ALAC orders the given number of channels,
+  /// or the default layout if ALAC does not order them.
+  pub fn from_alac_channels(channels: u32) -> ChannelLayoutChunk {
+    match LAYOUTS_ALAC.iter().find(|&&tag| tag & 0xFFFF == channels) {
+      Some(&tag) =>
+        ChannelLayoutChunk {
+          tag:          tag,
+          bitmap:       0,
+          descriptions: Vec::new()
+        },
+      None => ChannelLayoutChunk::from_channels(channels)
+    }
+  }
+
+  /// Creates a layout from a bitmap of speaker positions, whose bits are the
+  /// same as those of the WAVE channel mask.
+  pub fn from_bitmap(bitmap: u32) -> ChannelLayoutChunk {
+    ChannelLayoutChunk {
+      tag:          LAYOUT_USE_BITMAP,
+      bitmap:       bitmap,
+      descriptions: Vec::new()
+    }
+  }
+
+  /// Returns the number of channels in the layout.
+  pub fn num_channels(&self) -> u32 {
+    match self.tag {
+      LAYOUT_USE_DESCRIPTIONS => self.descriptions.len() as u32,
+      LAYOUT_USE_BITMAP       => self.bitmap.count_ones(),
+      tag                     => tag & 0xFFFF
+    }
+  }
+
+  #[inline]
+  pub fn calculate_size(&self) -> i64 {
+    12 + 20 * self.descriptions.len() as i64
+  }
+
+  pub fn write<W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(CHAN));
+    try!(writer.write_i64::<BigEndian>(self.calculate_size()));
+    try!(writer.write_u32::<BigEndian>(self.tag));
+    try!(writer.write_u32::<BigEndian>(self.bitmap));
+    try!(writer.write_u32::<BigEndian>(self.descriptions.len() as u32));
+    for description in self.descriptions.iter() {
+      try!(writer.write_u32::<BigEndian>(description.label));
+      try!(writer.write_u32::<BigEndian>(description.flags));
+      for coordinate in description.coordinates.iter() {
+        try!(writer.write_f32::<BigEndian>(*coordinate));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Chunk for ChannelLayoutChunk {
+  #[inline]
+  fn read(buffer: &[u8]) -> AudioResult<ChannelLayoutChunk> {
+    if buffer.len() < 12 {
+      return Err(AudioError::Format(
+        "Channel layout chunk is too small".to_string()
+      ));
+    }
+    let num_descriptions = BigEndian::read_u32(&buffer[8..12]) as usize;
+    if buffer.len() < 12 + 20 * num_descriptions {
+      return Err(AudioError::Format(
+        "Channel layout chunk is too small for its channel descriptions".to_string()
+      ));
+    }
+    let descriptions =
+      buffer[12 .. 12 + 20 * num_descriptions].chunks(20)
+      .map(|bytes| ChannelDescription {
+        label:        BigEndian::read_u32(&bytes[0..4]),
+        flags:        BigEndian::read_u32(&bytes[4..8]),
+        coordinates:  [BigEndian::read_f32(&bytes[8..12]),
+                       BigEndian::read_f32(&bytes[12..16]),
+                       BigEndian::read_f32(&bytes[16..20])]
+      })
+      .collect();
+    Ok(
+      ChannelLayoutChunk {
+        tag:          BigEndian::read_u32(&buffer[0..4]),
+        bitmap:       BigEndian::read_u32(&buffer[4..8]),
+        descriptions: descriptions
+      }
+    )
+  }
+}
+
+/// The CAF Information Chunk.
+///
+/// Contains pairs of null terminated UTF-8 strings, such as a `title` key
+/// followed by the title of the audio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InformationChunk {
+  pub entries: Vec<(String, String)>
+}
+
+impl InformationChunk {
+  #[inline]
+  pub fn calculate_size(&self) -> i64 {
+    self.entries.iter()
+      .fold(4, |size, &(ref key, ref value)|
+        size + key.len() as i64 + value.len() as i64 + 2
+      )
+  }
+
+  pub fn write<W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(INFO));
+    try!(writer.write_i64::<BigEndian>(self.calculate_size()));
+    try!(writer.write_u32::<BigEndian>(self.entries.len() as u32));
+    for &(ref key, ref value) in self.entries.iter() {
+      try!(writer.write(key.as_bytes()));
+      try!(writer.write_u8(0));
+      try!(writer.write(value.as_bytes()));
+      try!(writer.write_u8(0));
+    }
+    Ok(())
+  }
+}
+
+impl Chunk for InformationChunk {
+  #[inline]
+  fn read(buffer: &[u8]) -> AudioResult<InformationChunk> {
+    if buffer.len() < 4 {
+      return Err(AudioError::Format(
+        "Information chunk is too small".to_string()
+      ));
+    }
+    let num_entries = BigEndian::read_u32(&buffer[0..4]) as usize;
+    let mut strings =
+      buffer[4..].split(|byte| *byte == 0)
+      .map(|bytes| String::from_utf8_lossy(bytes).into_owned());
+    // The number of entries is not trusted for the capacity, since each entry
+    // takes at least two bytes.
+    let mut entries = Vec::with_capacity(num_entries.min(buffer.len() / 2));
+    for _ in 0..num_entries {
+      match (strings.next(), strings.next()) {
+        (Some(key), Some(value)) => entries.push((key, value)),
+        _ =>
+          return Err(AudioError::Format(
+            "Information chunk is missing entries".to_string()
+          ))
+      }
+    }
+    Ok(InformationChunk { entries: entries })
+  }
+}
+
+/// The CAF Packet Table Chunk.
+///
+/// Describes the size of each packet for formats with variable packet sizes,
+/// as well as the number of priming and remainder frames that are not part of
+/// the audio. Packet sizes are stored as variable length integers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketTableChunk {
+  pub num_packets:      i64,
+  pub num_valid_frames: i64,
+  pub priming_frames:   i32,
+  pub remainder_frames: i32,
+  pub packet_sizes:     Vec<u64>
+}
+
+impl PacketTableChunk {
+  #[inline]
+  pub fn calculate_size(&self) -> i64 {
+    self.packet_sizes.iter()
+      .fold(24, |size, packet_size| size + varint_size(*packet_size) as i64)
+  }
+
+  pub fn write<W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(PAKT));
+    try!(writer.write_i64::<BigEndian>(self.calculate_size()));
+    try!(writer.write_i64::<BigEndian>(self.num_packets));
+    try!(writer.write_i64::<BigEndian>(self.num_valid_frames));
+    try!(writer.write_i32::<BigEndian>(self.priming_frames));
+    try!(writer.write_i32::<BigEndian>(self.remainder_frames));
+    for packet_size in self.packet_sizes.iter() {
+      let size = varint_size(*packet_size);
+      for i in (0..size).rev() {
+        let mut byte = (packet_size >> (7 * i)) as u8 & 0x7F;
+        if i > 0 {
+          byte |= 0x80;
+        }
+        try!(writer.write_u8(byte));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Chunk for PacketTableChunk {
+  #[inline]
+  fn read(buffer: &[u8]) -> AudioResult<PacketTableChunk> {
+    if buffer.len() < 24 {
+      return Err(AudioError::Format(
+        "Packet table chunk is too small".to_string()
+      ));
+    }
+    let num_packets = BigEndian::read_i64(&buffer[0..8]);
+    let mut packet_sizes: Vec<u64> = Vec::new();
+    let mut value: u64 = 0;
+    for byte in buffer[24..].iter() {
+      value = value << 7 | (byte & 0x7F) as u64;
+      if byte & 0x80 == 0 {
+        packet_sizes.push(value);
+        value = 0;
+      }
+    }
+    if (packet_sizes.len() as i64) < num_packets {
+      packet_sizes.clear();
+    }
+    packet_sizes.truncate(num_packets.max(0) as usize);
+    Ok(
+      PacketTableChunk {
+        num_packets:      num_packets,
+        num_valid_frames: BigEndian::read_i64(&buffer[8..16]),
+        priming_frames:   BigEndian::read_i32(&buffer[16..20]),
+        remainder_frames: BigEndian::read_i32(&buffer[20..24]),
+        packet_sizes:     packet_sizes
+      }
+    )
+  }
+}
+
+/// Returns the number of bytes used to store a variable length integer.
+#[inline]
+fn varint_size(value: u64) -> usize {
+  let mut size = 1;
+  while value >> (7 * size) != 0 {
+    size += 1;
+  }
+  size
+}
+
+/// Writes a chunk containing the given data. This is used for chunks whose
+/// contents are encoded separately, such as unrecognized chunks.
+pub fn write_chunk<W: Write>(writer: &mut W, id: &[u8; 4], data: &[u8]) -> AudioResult<()> {
+  try!(writer.write(id));
+  try!(writer.write_i64::<BigEndian>(data.len() as i64));
+  try!(writer.write_all(data));
+  Ok(())
+}
+
+#[cfg(test)]
+mod packet_table {
+  use super::*;
+
+  #[test]
+  fn varint_round_trip() {
+    let pakt = PacketTableChunk {
+      num_packets:      4,
+      num_valid_frames: 14000,
+      priming_frames:   2112,
+      remainder_frames: 272,
+      packet_sizes:     vec![0, 127, 128, 1 << 21]
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    pakt.write(&mut bytes).unwrap();
+    assert_eq!(12 + 24 + 1 + 1 + 2 + 4, bytes.len());
+    assert_eq!(pakt.calculate_size() as usize, bytes.len() - 12);
+    assert_eq!(&[0x81, 0x00], &bytes[38..40]);
+    assert_eq!(pakt, PacketTableChunk::read(&bytes[12..]).unwrap());
+  }
+}
@@ -0,0 +1,391 @@
+use std::io::{Read, Seek, Write};
+use audio::AudioFormat;
+use buffer::*;
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use caf::{CAFF, CAF_VERSION, CHAN, DATA, DATA_ALIGNMENT, DESC, FREE, INFO, KUKI, PAKT,
+          UNKNOWN_DATA_SIZE};
+use caf::chunks::*;
+use caf::chunks::CafChunk::*;
+use codecs::Codec;
+use codecs::Codec::*;
+use codecs::alac;
+use codecs::alac::AlacConfig;
+use error::*;
+use metadata::{Metadata, UnknownChunk};
+use sample::*;
+use traits::{Chunk, Container};
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct CafContainer {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for CafContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<CafContainer> {
+    // Read and validate file header
+    let mut caf_header: [u8; 8] = [0u8; 8];
+    try!(reader.read_exact(&mut caf_header));
+    if &caf_header[0..4] != CAFF {
+      return Err(AudioError::Format(
+        "Not valid CAF".to_string()
+      ));
+    }
+    if BigEndian::read_u16(&caf_header[4..6]) != CAF_VERSION {
+      return Err(AudioError::Unsupported(
+        "Unsupported CAF file version".to_string()
+      ));
+    }
+
+    // Read all supported chunks
+    let mut container =
+      CafContainer {
+        bit_depth:      0u32,
+        sample_rate:    0u32,
+        channels:       1u32,
+        order:          SampleOrder::Interleaved,
+        samples:        Vec::with_capacity(1024),
+        metadata:       Metadata::default()
+      };
+    let mut chunk_header : [u8; 12] = [0u8; 12];
+    let mut desc_chunk   : Option<DescriptionChunk>   = None;
+    let mut chan_chunk   : Option<ChannelLayoutChunk> = None;
+    let mut pakt_chunk   : Option<PacketTableChunk>   = None;
+    let mut kuki_chunk   : Option<Vec<u8>>            = None;
+    let mut data         : Option<Vec<u8>>            = None;
+    while try!(read_chunk_header(reader, &mut chunk_header)) {
+      let chunk_size: i64 = BigEndian::read_i64(&chunk_header[4..12]);
+      let chunk_kind = identify(&chunk_header[0..4]).ok();
+      // Only the audio data chunk may have an unknown size, in which case it
+      // is the last chunk and continues to the end of the file.
+      if chunk_size < 0 && !(chunk_size == UNKNOWN_DATA_SIZE
+                             && match chunk_kind { Some(AudioData) => true, _ => false }) {
+        return Err(AudioError::Format(
+          "File is not valid CAF (Chunk has a negative size)".to_string()
+        ));
+      }
+      let mut chunk_bytes: Vec<u8> = Vec::new();
+      if chunk_size == UNKNOWN_DATA_SIZE {
+        try!(reader.read_to_end(&mut chunk_bytes));
+      }
+      else {
+        try!(reader.take(chunk_size as u64).read_to_end(&mut chunk_bytes));
+        if (chunk_bytes.len() as i64) < chunk_size {
+          return Err(AudioError::Format(
+            "File is not valid CAF (Chunk extends past the end of the file)".to_string()
+          ));
+        }
+      }
+      match chunk_kind {
+        Some(Description) => {
+          desc_chunk = Some(try!(DescriptionChunk::read(&chunk_bytes)));
+        },
+        Some(_) if desc_chunk.is_none() => {
+          return Err(AudioError::Format(
+            "File is not valid CAF \
+            (Audio description chunk is not the first chunk)".to_string()
+          ))
+        },
+        Some(AudioData) => {
+          // The audio data follows a four byte edit count.
+          if chunk_bytes.len() < 4 {
+            return Err(AudioError::Format(
+              "File is not valid CAF (Audio data chunk is too small)".to_string()
+            ))
+          }
+          chunk_bytes.drain(0..4);
+          data = Some(chunk_bytes);
+        },
+        Some(ChannelLayout) => {
+          chan_chunk = Some(try!(ChannelLayoutChunk::read(&chunk_bytes)));
+          // Channel layouts are not represented by the library, so the chunk
+          // is kept as read and written back if the channels are unchanged.
+          container.metadata.chunks.push(
+            unknown_chunk(&chunk_header, chunk_bytes, data.is_some())
+          );
+        },
+        Some(Information) => {
+          container.metadata.info =
+            try!(InformationChunk::read(&chunk_bytes)).entries;
+        },
+        Some(PacketTable) => {
+          pakt_chunk = Some(try!(PacketTableChunk::read(&chunk_bytes)));
+        },
+        Some(MagicCookie) => {
+          kuki_chunk = Some(chunk_bytes);
+        },
+        Some(Free) => {},
+        None => {
+          container.metadata.chunks.push(
+            unknown_chunk(&chunk_header, chunk_bytes, data.is_some())
+          );
+        }
+      }
+    }
+
+    // Check if required chunks were read
+    let desc_chunk =
+      match desc_chunk {
+        Some(desc) => desc,
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid CAF \
+            (Missing required Audio Description chunk)".to_string()
+          ))
+      };
+    let mut data =
+      match data {
+        Some(data) => data,
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid CAF \
+            (Missing required Audio Data chunk)".to_string()
+          ))
+      };
+    if desc_chunk.channels_per_frame == 0 {
+      return Err(AudioError::Format(
+        "File is not valid CAF (Audio must have at least one channel)".to_string()
+      ))
+    }
+    if let Some(ref chan) = chan_chunk {
+      if chan.num_channels() != desc_chunk.channels_per_frame {
+        return Err(AudioError::Format(
+          "File is not valid CAF \
+          (Channel layout does not match the number of channels)".to_string()
+        ))
+      }
+    }
+    let codec = try!(desc_chunk.codec());
+    let alac = match codec { ALAC_I16 | ALAC_I20 | ALAC_I24 | ALAC_I32 => true, _ => false };
+    // ALAC audio is decoded to the order of the WAVE channel mask, so the
+    // layout of the channels as coded no longer applies.
+    if alac {
+      container.metadata.chunks.retain(|chunk| &chunk.id != CHAN);
+    }
+    container.bit_depth   = codec.bit_depth() as u32;
+    container.sample_rate = desc_chunk.sample_rate as u32;
+    container.channels    = desc_chunk.channels_per_frame;
+    container.order       =
+      if container.channels == 1 {
+        SampleOrder::Mono
+      } else {
+        SampleOrder::Interleaved
+      };
+    container.samples =
+      match codec {
+        _ if alac =>
+          try!(read_packets(&data, container.channels, kuki_chunk.as_ref(), pakt_chunk.as_ref())),
+        _ => {
+          // Incomplete frames at the end of the data are ignored.
+          let frame_size = codec.sample_size() * container.channels as usize;
+          let complete_frames_len = data.len() - data.len() % frame_size;
+          data.truncate(complete_frames_len);
+          try!(::codecs::decode(&data, codec))
+        }
+      };
+    // The packet table excludes priming and remainder frames from the audio.
+    if let Some(pakt) = pakt_chunk {
+      let channels = container.channels as usize;
+      let start = (pakt.priming_frames.max(0) as usize * channels)
+                  .min(container.samples.len());
+      container.samples.drain(0..start);
+      if pakt.num_valid_frames >= 0 {
+        let valid = pakt.num_valid_frames as usize * channels;
+        container.samples.truncate(valid);
+      }
+    }
+    Ok(container)
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    // Encode audio samples using codec. Compressed audio is stored in
+    // packets described by a packet table, and its configuration is stored
+    // in the magic cookie.
+    let mut kuki_chunk: Option<Vec<u8>>          = None;
+    let mut pakt_chunk: Option<PacketTableChunk> = None;
+    let data: Vec<u8> =
+      match codec {
+        ALAC_I16 | ALAC_I20 | ALAC_I24 | ALAC_I32 => {
+          let (config, packets) = try!(alac::create(audio, codec));
+          let frames = audio.samples.len() as i64 / audio.channels as i64;
+          kuki_chunk = Some(config.to_bytes());
+          pakt_chunk = Some(PacketTableChunk {
+            num_packets:      packets.len() as i64,
+            num_valid_frames: frames,
+            priming_frames:   0,
+            remainder_frames:
+              (packets.len() as i64 * config.frame_length as i64 - frames) as i32,
+            packet_sizes:     packets.iter().map(|packet| packet.len() as u64).collect()
+          });
+          packets.concat()
+        },
+        _ => try!(write_codec(audio, codec))
+      };
+
+    // A preserved channel layout is only written if it has the same number
+    // of channels as the audio. Audio with more than two channels requires a
+    // channel layout, which for ALAC is the order of its channels.
+    let chan_chunk =
+      if kuki_chunk.is_some() && audio.channels > 2 {
+        Some(ChannelLayoutChunk::from_alac_channels(audio.channels))
+      } else {
+        audio.metadata.chunks.iter()
+        .filter(|chunk| chunk.format == AudioFormat::CAF && &chunk.id == CHAN)
+        .filter_map(|chunk| ChannelLayoutChunk::read(&chunk.data).ok())
+        .find(|chan| chan.num_channels() == audio.channels)
+        .or_else(||
+          if audio.channels > 2 {
+            Some(ChannelLayoutChunk::from_channels(audio.channels))
+          } else {
+            None
+          }
+        )
+      };
+    let info_chunk = InformationChunk { entries: audio.metadata.info.clone() };
+    let chunks = audio.metadata.chunks.iter()
+                 .filter(|chunk| chunk.format == AudioFormat::CAF && &chunk.id != CHAN);
+
+    // Write the file header and the chunks preceding the audio data to a
+    // buffer, so its size is known before padding.
+    let mut header: Vec<u8> = Vec::new();
+    try!(header.write(CAFF));
+    try!(header.write_u16::<BigEndian>(CAF_VERSION));
+    try!(header.write_u16::<BigEndian>(0));
+    // Write desc chunk to the header.
+    try!(DescriptionChunk::write(&mut header, audio, codec));
+    // Write chan chunk if the audio has a channel layout
+    if let Some(ref chan_chunk) = chan_chunk {
+      try!(chan_chunk.write(&mut header));
+    }
+    // Write info chunk if the audio has textual information
+    if !info_chunk.entries.is_empty() {
+      try!(info_chunk.write(&mut header));
+    }
+    for chunk in chunks.clone().filter(|chunk| !chunk.after_data) {
+      try!(write_chunk(&mut header, &chunk.id, &chunk.data));
+    }
+    // Write kuki and pakt chunks if the audio is compressed
+    if let Some(ref kuki_chunk) = kuki_chunk {
+      try!(write_chunk(&mut header, KUKI, kuki_chunk));
+    }
+    if let Some(ref pakt_chunk) = pakt_chunk {
+      try!(pakt_chunk.write(&mut header));
+    }
+    // Write a free chunk so the audio data, following the data chunk header
+    // and edit count, starts on a page boundary.
+    let mut padding = (DATA_ALIGNMENT - (header.len() + 16) % DATA_ALIGNMENT) % DATA_ALIGNMENT;
+    if padding > 0 && padding < 12 {
+      padding += DATA_ALIGNMENT;
+    }
+    if padding > 0 {
+      try!(write_chunk(&mut header, FREE, &vec![0u8; padding - 12]));
+    }
+    try!(writer.write_all(&header));
+    // Write data chunk to the writer, starting with the edit count.
+    try!(writer.write(DATA));
+    try!(writer.write_i64::<BigEndian>(4 + data.len() as i64));
+    try!(writer.write_u32::<BigEndian>(0));
+    try!(writer.write_all(&data));
+    for chunk in chunks.filter(|chunk| chunk.after_data) {
+      try!(write_chunk(writer, &chunk.id, &chunk.data));
+    }
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Reads the next chunk header, returning false at the end of the file.
+fn read_chunk_header<R: Read>(reader: &mut R, header: &mut [u8; 12]) -> AudioResult<bool> {
+  let mut read = 0;
+  while read < header.len() {
+    match try!(reader.read(&mut header[read..])) {
+      0 if read == 0 => return Ok(false),
+      0 =>
+        return Err(AudioError::Format(
+          "File is not valid CAF (Incomplete chunk header)".to_string()
+        )),
+      n => read += n
+    }
+  }
+  Ok(true)
+}
+
+/// Creates an `UnknownChunk` from a chunk header and the chunk data.
+#[inline]
+fn unknown_chunk(header: &[u8], data: Vec<u8>, after_data: bool) -> UnknownChunk {
+  UnknownChunk {
+    format:     AudioFormat::CAF,
+    id:         [header[0], header[1], header[2], header[3]],
+    data:       data,
+    after_data: after_data
+  }
+}
+
+/// This function reads the four byte identifier for each CAF chunk.
+#[inline]
+fn identify(bytes: &[u8]) -> AudioResult<CafChunk> {
+  match &[bytes[0], bytes[1], bytes[2], bytes[3]] {
+    DESC => Ok(Description),
+    DATA => Ok(AudioData),
+    CHAN => Ok(ChannelLayout),
+    INFO => Ok(Information),
+    PAKT => Ok(PacketTable),
+    KUKI => Ok(MagicCookie),
+    FREE => Ok(Free),
+    err @ _ =>
+      Err(AudioError::Format(
+        format!("Do not recognize CAF chunk with identifier {:?}", err)
+      ))
+  }
+}
+
+/// Decodes Apple Lossless audio data, split into packets by the packet
+/// table.
+fn read_packets(data: &[u8], channels: u32, kuki: Option<&Vec<u8>>, pakt: Option<&PacketTableChunk>) -> AudioResult<Vec<Sample>> {
+  let (kuki, pakt) =
+    match (kuki, pakt) {
+      (Some(kuki), Some(pakt)) => (kuki, pakt),
+      _ =>
+        return Err(AudioError::Format(
+          "File is not valid CAF \
+          (Compressed audio requires magic cookie and packet table chunks)".to_string()
+        ))
+    };
+  let config = try!(AlacConfig::read(kuki));
+  if config.channels as u32 != channels {
+    return Err(AudioError::Format(
+      "File is not valid CAF (Magic cookie does not match the number of channels)".to_string()
+    ));
+  }
+  let mut packets: Vec<&[u8]> = Vec::with_capacity(pakt.packet_sizes.len());
+  let mut position = 0;
+  for size in pakt.packet_sizes.iter() {
+    let end = position + *size as usize;
+    if end > data.len() {
+      return Err(AudioError::Format(
+        "File is not valid CAF (Packets extend past the audio data)".to_string()
+      ));
+    }
+    packets.push(&data[position .. end]);
+    position = end;
+  }
+  alac::read(&config, &packets)
+}
+
+/// Returns samples as bytes created using the given codec. If the container
+/// does not support a codec, an error is returned.
+#[inline]
+fn write_codec(audio: &AudioBuffer, codec: Codec) -> AudioResult<Vec<u8>> {
+  match codec {
+    LPCM_U8 =>
+      Err(AudioError::Unsupported(
+        format!("CAF does not support the {:?} codec", codec)
+      )),
+    _ => ::codecs::encode(audio, codec)
+  }
+}
@@ -0,0 +1,37 @@
+use std::io::{Read, Seek};
+use caf::container::CafContainer;
+use buffer::AudioBuffer;
+use error::AudioResult;
+use traits::{AudioDecoder, Container};
+
+/// Decodes audio in CAF format from the
+/// provided reader.
+pub struct Decoder<'r, R: 'r> where R: Read + Seek {
+  reader: &'r mut R,
+}
+
+impl<'r, R> Decoder<'r, R> where R: Read + Seek {
+  /// Create a new CAF format `Decoder` using
+  /// the provided reader.
+  #[inline]
+  pub fn new(reader: &'r mut R) -> Decoder<R> {
+    Decoder {
+      reader: reader
+    }
+  }
+}
+
+impl<'r, R> AudioDecoder for Decoder<'r, R> where R: Read + Seek {
+  /// Creates an `AudioBuffer` from the included reader via
+  /// a `CafContainer`.
+  #[inline]
+  fn decode(mut self) -> AudioResult<AudioBuffer> {
+    let container = try!(CafContainer::open(self.reader));
+    Ok(AudioBuffer {
+      sample_rate:  container.sample_rate,
+      channels:     container.channels,
+      samples:      container.samples,
+      metadata:     container.metadata
+    })
+  }
+}
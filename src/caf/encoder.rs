@@ -0,0 +1,38 @@
+use std::io::Write;
+use caf::container::CafContainer;
+use buffer::AudioBuffer;
+use codecs::Codec;
+use codecs::Codec::LPCM_I16_LE;
+use error::AudioResult;
+use traits::{AudioEncoder, Container};
+
+/// Encodes audio to CAF format to the provided writer.
+pub struct Encoder<'w, W: 'w> {
+  writer: &'w mut W,
+}
+
+impl<'w, W> Encoder<'w, W> where W: Write {
+  /// Create a new CAF format `Encoder` using the provided writer.
+  #[inline]
+  pub fn new(writer: &'w mut W) -> Encoder<'w, W> {
+    Encoder {
+      writer: writer
+    }
+  }
+}
+
+impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
+  /// Creates and writes a `CafContainer` to the included writer. The audio
+  /// is encoded to standard 16-bit, uncompressed LPCM audio.
+  #[inline]
+  fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
+    CafContainer::create(&mut self.writer, audio, LPCM_I16_LE)
+  }
+  /// Creates and writes a `CafContainer` using the provided `SampleFormat`
+  /// to the included writer. This is how audio can be encoded to different
+  /// bit rates supported by the format.
+  #[inline]
+  fn encode_as(&mut self, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    CafContainer::create(&mut self.writer, audio, codec)
+  }
+}
@@ -0,0 +1,282 @@
+//! The Core Audio Format
+//!
+//! CAF files begin with a file header followed by chunks that use 64-bit
+//! sizes, allowing files larger than 4 GB. The audio description chunk is
+//! always the first chunk, and the audio data chunk may have an unknown size
+//! if it is the last chunk of the file. All integers are stored in big-endian
+//! format, while the audio data may be either little- or big-endian.
+//!
+//! Apple Lossless audio is stored in packets of variable size, given by the
+//! packet table chunk, and the configuration of the stream is stored in the
+//! magic cookie chunk.
+//!
+//! References
+//! - [Core Audio Format Specification](https://developer.apple.com/library/archive/documentation/MusicAudio/Reference/CAFSpec/CAF_spec/CAF_spec.html)
+//! - [McGill University](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/CAF/CAF.html)
+
+mod container;
+mod chunks;
+pub mod decoder;
+pub mod encoder;
+
+pub use caf::decoder::Decoder as Decoder;
+pub use caf::encoder::Encoder as Encoder;
+
+use caf::chunks::ChannelLayoutChunk;
+use error::AudioResult;
+
+/// CAF file type and chunk identifiers.
+const CAFF: &'static [u8; 4] = b"caff";
+const DESC: &'static [u8; 4] = b"desc";
+const DATA: &'static [u8; 4] = b"data";
+const CHAN: &'static [u8; 4] = b"chan";
+const INFO: &'static [u8; 4] = b"info";
+const PAKT: &'static [u8; 4] = b"pakt";
+const FREE: &'static [u8; 4] = b"free";
+const KUKI: &'static [u8; 4] = b"kuki";
+
+/// CAF audio format identifiers.
+const LPCM: &'static [u8; 4] = b"lpcm";
+const ULAW: &'static [u8; 4] = b"ulaw";
+const ALAW: &'static [u8; 4] = b"alaw";
+const ALAC: &'static [u8; 4] = b"alac";
+
+/// CAF file version.
+const CAF_VERSION: u16 = 1;
+
+/// Alignment of the audio data, reached by padding the header with a free
+/// chunk.
+const DATA_ALIGNMENT: usize = 4096;
+
+/// Size of an audio data chunk that continues to the end of the file.
+const UNKNOWN_DATA_SIZE: i64 = -1;
+
+/// Returns the data of a channel layout chunk for audio with the given
+/// number of channels. The speakers are given by a bitmap when known,
+/// otherwise the default layout for the number of channels is used.
+pub fn channel_layout_data(channels: u32, bitmap: Option<u32>) -> AudioResult<Vec<u8>> {
+  let chan = match bitmap {
+    Some(bitmap) if bitmap.count_ones() == channels =>
+      ChannelLayoutChunk::from_bitmap(bitmap),
+    _ => ChannelLayoutChunk::from_channels(channels)
+  };
+  let mut bytes: Vec<u8> = Vec::new();
+  try!(chan.write(&mut bytes));
+  Ok(bytes.split_off(12))
+}
+
+#[cfg(test)]
+mod io {
+  use std::io::Cursor;
+  use std::path::{Path, PathBuf};
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::caf::chunks::InformationChunk;
+  use ::codecs::Codec::*;
+  use ::metadata::UnknownChunk;
+  use ::traits::Chunk;
+
+  #[test]
+  fn codecs_eq() {
+    let mut path = PathBuf::from("tests");
+    path.push("aiff");
+    path.push("empty.aiff");
+    let files = vec![
+      ("M1F1-int8-AFsp.aif",      vec![LPCM_I8]),
+      ("M1F1-int16-AFsp.aif",     vec![LPCM_I16_LE, LPCM_I16_BE]),
+      ("M1F1-int24-AFsp.aif",     vec![LPCM_I24_LE, LPCM_I24_BE]),
+      ("M1F1-int32-AFsp.aif",     vec![LPCM_I32_LE, LPCM_I32_BE]),
+      ("M1F1-float32C-AFsp.aif",  vec![LPCM_F32_LE, LPCM_F32_BE]),
+      ("M1F1-float64C-AFsp.aif",  vec![LPCM_F64_LE, LPCM_F64_BE]),
+      ("M1F1-mulawC-AFsp.aif",    vec![G711_ULAW]),
+      ("M1F1-AlawC-AFsp.aif",     vec![G711_ALAW])
+    ];
+
+    for &(file, ref codecs) in files.iter() {
+      path.set_file_name(file);
+      println!("{:?}", path.as_path());
+      let audio = audio::open(path.as_path()).unwrap();
+
+      for codec in codecs.iter() {
+        let write_path = Path::new("tests/results/tmp_codec.caf");
+        assert!(audio::save_as(&write_path, &audio, *codec).is_ok());
+
+        let verify = audio::open(&write_path).unwrap();
+        assert_eq!(audio.channels,      verify.channels);
+        assert_eq!(audio.sample_rate,   verify.sample_rate);
+        assert_eq!(audio.samples.len(), verify.samples.len());
+        for (inital_sample, written_sample) in
+            audio.samples.iter().zip(&verify.samples) {
+          assert_eq!(inital_sample, written_sample);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn apple_lossless() {
+    let audio = audio::open(Path::new("tests/aiff/M1F1-int16-AFsp.aif")).unwrap();
+    for &(codec, lpcm) in [(ALAC_I16, LPCM_I16_BE), (ALAC_I20, LPCM_I16_BE),
+                           (ALAC_I24, LPCM_I24_BE), (ALAC_I32, LPCM_I32_BE)].iter() {
+      let write_path = Path::new("tests/results/tmp_alac.caf");
+      assert!(audio::save_as(&write_path, &audio, codec).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      let expected = AudioBuffer::from_bytes(audio.sample_rate, audio.channels,
+        &::codecs::encode(&audio, lpcm).unwrap(), lpcm).unwrap();
+      assert_eq!(audio.channels,    verify.channels);
+      assert_eq!(audio.sample_rate, verify.sample_rate);
+      assert_eq!(expected.samples,  verify.samples);
+    }
+
+    // Audio is stored in packets described by the packet table.
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::CAF, ALAC_I16).is_ok());
+    assert_eq!(b"alac", &bytes[28..32]);
+    assert_eq!(b"kuki", &bytes[52..56]);
+    assert_eq!(b"pakt", &bytes[88..92]);
+    let data = bytes.len() - 1;
+    bytes[data] ^= 0xFF;
+    bytes.truncate(data - 100);
+    assert!(audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).is_err());
+  }
+
+  #[test]
+  fn read_apple_lossless() {
+    for &(path, source) in [
+      ("tests/caf/M1F1-int16-AFsp.caf", "tests/aiff/M1F1-int16-AFsp.aif"),
+      ("tests/caf/M1F1-int24-AFsp.caf", "tests/aiff/M1F1-int24-AFsp.aif"),
+      ("tests/caf/sine-5.1.caf",        "tests/wavpack/sine-5.1.wav")
+    ].iter() {
+      let audio = audio::open(Path::new(path)).unwrap();
+      let expected = audio::open(Path::new(source)).unwrap();
+      assert_eq!(expected.channels,    audio.channels);
+      assert_eq!(expected.sample_rate, audio.sample_rate);
+      assert_eq!(expected.samples,     audio.samples);
+      // The layout of the coded channels is not kept.
+      assert!(audio.metadata.chunks.is_empty());
+    }
+
+    // Surround audio is written with the channel layout of ALAC.
+    let audio = audio::open(Path::new("tests/wavpack/sine-5.1.wav")).unwrap();
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::CAF, ALAC_I16).is_ok());
+    assert_eq!(b"chan", &bytes[52..56]);
+    assert_eq!(&[0, 124, 0, 6], &bytes[64..68]);
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    assert_eq!(audio.samples, verify.samples);
+  }
+
+  #[test]
+  fn unsupported_codec() {
+    let audio = AudioBuffer::from_samples(8000, 1, vec![0f32; 4]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::CAF, LPCM_U8).is_err());
+  }
+
+  #[test]
+  fn metadata_round_trip() {
+    let mut audio = AudioBuffer::from_samples(44100, 1, vec![0f32, 0.5f32, -0.5f32]);
+    audio.metadata.info = vec![
+      ("title".to_string(),  "Test Tone".to_string()),
+      ("artist".to_string(), "audio".to_string())
+    ];
+    audio.metadata.chunks = vec![
+      UnknownChunk {
+        format:     AudioFormat::CAF,
+        id:         *b"uuid",
+        data:       vec![1u8; 17],
+        after_data: false
+      },
+      UnknownChunk {
+        format:     AudioFormat::CAF,
+        id:         *b"mark",
+        data:       vec![2u8; 3],
+        after_data: true
+      }
+    ];
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::CAF).is_ok());
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    assert_eq!(audio.metadata, verify.metadata);
+    assert_eq!(audio.samples,  verify.samples);
+  }
+
+  #[test]
+  fn channel_layout() {
+    let audio = AudioBuffer::from_samples(48000, 3, vec![0f32; 12]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::CAF).is_ok());
+    // The chan chunk follows the 8 byte file header and the desc chunk.
+    assert_eq!(b"chan", &bytes[52..56]);
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    assert_eq!(3, verify.channels);
+    assert_eq!(1, verify.metadata.chunks.len());
+
+    // A layout read from a file is only kept while the channels are unchanged.
+    let stereo = AudioBuffer {
+      channels: 2,
+      samples:  vec![0f32; 8],
+      .. verify.clone()
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &verify, AudioFormat::CAF).is_ok());
+    assert_eq!(b"chan", &bytes[52..56]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &stereo, AudioFormat::CAF).is_ok());
+    assert_eq!(b"free", &bytes[52..56]);
+  }
+
+  #[test]
+  fn header_padding() {
+    let audio = AudioBuffer::from_samples(8000, 2, vec![0f32, 0.5f32, -0.5f32, 0.25f32]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::CAF, LPCM_I16_BE).is_ok());
+    // A free chunk follows the desc chunk, so the audio data that follows the
+    // data chunk header and edit count starts at 4096 bytes.
+    assert_eq!(b"free", &bytes[52..56]);
+    assert_eq!(b"data", &bytes[4080..4084]);
+    assert_eq!(4096 + 8, bytes.len());
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    assert_eq!(audio.samples, verify.samples);
+    assert!(verify.metadata.chunks.is_empty());
+  }
+
+  #[test]
+  fn information_entries() {
+    // An entry count larger than the chunk is an error rather than an
+    // allocation of the given size.
+    assert!(InformationChunk::read(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]).is_err());
+    let info = InformationChunk::read(&[0, 0, 0, 1, b'a', 0, b'b', 0]).unwrap();
+    assert_eq!(vec![("a".to_string(), "b".to_string())], info.entries);
+  }
+
+  #[test]
+  fn unknown_data_size() {
+    let audio = AudioBuffer::from_samples(8000, 2, vec![0f32, 0.5f32, -0.5f32, 0.25f32]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::CAF, LPCM_I16_BE).is_ok());
+    // The data chunk size follows the free chunk and the data identifier.
+    for byte in bytes[4084..4092].iter_mut() {
+      *byte = 0xFF;
+    }
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    assert_eq!(audio.samples, verify.samples);
+  }
+
+  #[test]
+  fn packet_table() {
+    let audio = AudioBuffer::from_samples(8000, 1, vec![0f32, 0.5f32, -0.5f32, 0.25f32]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::CAF, LPCM_I16_LE).is_ok());
+    // Append a packet table with one priming frame and one remainder frame.
+    bytes.extend_from_slice(b"pakt");
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 24]);
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 4]);
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 2]);
+    bytes.extend_from_slice(&[0, 0, 0, 1]);
+    bytes.extend_from_slice(&[0, 0, 0, 1]);
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    assert_eq!(&audio.samples[1..3], &verify.samples[..]);
+  }
+}
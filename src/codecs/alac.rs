@@ -0,0 +1,1069 @@
+//! ALAC
+//!
+//! Apple Lossless Audio Codec
+//!
+//! Audio is coded in packets of up to 4096 frames. Each packet holds channel
+//! elements of one or two channels, where the samples of a pair of channels
+//! are first mixed into a weighted average and a difference. The samples of
+//! each channel are predicted by an adaptive filter, and the prediction
+//! residuals are stored using adaptive Golomb-Rice codes. Packets that would
+//! be larger than the samples themselves store them uncompressed. Packets of
+//! more than two channels start with the centre channel, and are reordered
+//! to and from the order of the WAVE channel mask.
+//!
+//! The parameters of the stream are kept by the container in a separate
+//! configuration, known as the magic cookie.
+//!
+//! References
+//! - [ALAC Source](https://github.com/macosforge/alac)
+
+use buffer::*;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use codecs::Codec;
+use codecs::Codec::*;
+use codecs::lpcm;
+use error::*;
+use sample::*;
+
+/// Number of frames in each packet, except the last.
+pub const FRAME_LENGTH: u32 = 4096;
+
+/// Size of the stream configuration.
+const CONFIG_SIZE: usize = 24;
+
+/// Default parameters of the adaptive Golomb-Rice coder.
+const PB0: u8 = 40;
+const MB0: u8 = 10;
+const KB0: u8 = 14;
+const MAX_RUN: u16 = 255;
+
+/// Identifiers of the elements of a packet.
+const ID_SCE: u32 = 0;
+const ID_CPE: u32 = 1;
+const ID_CCE: u32 = 2;
+const ID_LFE: u32 = 3;
+const ID_DSE: u32 = 4;
+const ID_PCE: u32 = 5;
+const ID_FIL: u32 = 6;
+const ID_END: u32 = 7;
+
+/// Channel elements used for each number of channels.
+const CHANNEL_ELEMENTS: [&'static [u32]; 8] = [
+  &[ID_SCE],
+  &[ID_CPE],
+  &[ID_SCE, ID_CPE],
+  &[ID_SCE, ID_CPE, ID_SCE],
+  &[ID_SCE, ID_CPE, ID_CPE],
+  &[ID_SCE, ID_CPE, ID_CPE, ID_LFE],
+  &[ID_SCE, ID_CPE, ID_CPE, ID_SCE, ID_LFE],
+  &[ID_SCE, ID_CPE, ID_CPE, ID_CPE, ID_LFE]
+];
+
+/// Channel of the audio, ordered as in the WAVE channel mask, coded by each
+/// channel of a packet for each number of channels. ALAC orders channels as
+/// in its channel layouts, starting with the centre and ending with the LFE.
+const CHANNEL_ORDERS: [&'static [usize]; 8] = [
+  &[0],
+  &[0, 1],
+  &[2, 0, 1],
+  &[2, 0, 1, 3],
+  &[2, 0, 1, 3, 4],
+  &[2, 0, 1, 4, 5, 3],
+  &[2, 0, 1, 4, 5, 6, 3],
+  &[2, 6, 7, 0, 1, 4, 5, 3]
+];
+
+/// Fixed point precision of the mean tracked by the Golomb-Rice coder.
+const QB_SHIFT: u32 = 9;
+const QB: u32 = 1 << QB_SHIFT;
+const MMUL_SHIFT: u32 = 2;
+const MDEN_SHIFT: u32 = QB_SHIFT - MMUL_SHIFT - 1;
+const MOFF: u32 = 1 << (MDEN_SHIFT - 2);
+const BIT_OFF: u32 = 24;
+/// Length of the unary prefix of an escaped value.
+const MAX_PREFIX: u32 = 9;
+/// Number of bits of an escaped run of zeros.
+const RUN_BITS: u32 = 16;
+const MEAN_CLAMP: u32 = 0xFFFF;
+
+/// Prediction filter used by the encoder.
+const PREDICTOR_ORDER: usize = 8;
+const DEN_SHIFT: u32 = 9;
+const PB_FACTOR: u32 = 4;
+
+/// Mixing of stereo pairs tried by the encoder, where the weight of the left
+/// channel in the average is the mixing residue over four.
+const MIX_BITS: u32 = 2;
+const MIX_RESIDUES: [i32; 3] = [0, 1, 2];
+
+/// The ALAC stream configuration, or magic cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlacConfig {
+  /// Number of frames in each packet
+  pub frame_length:     u32,
+  pub bit_depth:        u8,
+  /// Parameters of the adaptive Golomb-Rice coder
+  pub pb:               u8,
+  pub mb:               u8,
+  pub kb:               u8,
+  pub channels:         u8,
+  pub max_run:          u16,
+  /// Size of the largest packet, if known
+  pub max_frame_bytes:  u32,
+  /// Average bit rate, if known
+  pub avg_bit_rate:     u32,
+  pub sample_rate:      u32
+}
+
+impl AlacConfig {
+  /// Reads the configuration from a magic cookie. Older cookies wrap the
+  /// configuration in `frma` and `alac` atoms, which are skipped.
+  pub fn read(cookie: &[u8]) -> AudioResult<AlacConfig> {
+    let mut bytes = cookie;
+    if bytes.len() >= 12 && &bytes[4..8] == b"frma" {
+      bytes = &bytes[12..];
+    }
+    if bytes.len() >= 12 && &bytes[4..8] == b"alac" {
+      bytes = &bytes[12..];
+    }
+    if bytes.len() < CONFIG_SIZE {
+      return Err(AudioError::Format(
+        "ALAC configuration is too small".to_string()
+      ));
+    }
+    let config = AlacConfig {
+      frame_length:     BigEndian::read_u32(&bytes[0..4]),
+      bit_depth:        bytes[5],
+      pb:               bytes[6],
+      mb:               bytes[7],
+      kb:               bytes[8],
+      channels:         bytes[9],
+      max_run:          BigEndian::read_u16(&bytes[10..12]),
+      max_frame_bytes:  BigEndian::read_u32(&bytes[12..16]),
+      avg_bit_rate:     BigEndian::read_u32(&bytes[16..20]),
+      sample_rate:      BigEndian::read_u32(&bytes[20..24])
+    };
+    if bytes[4] != 0 {
+      return Err(AudioError::Unsupported(
+        format!("ALAC version {} is not supported", bytes[4])
+      ));
+    }
+    if config.channels == 0 || config.frame_length == 0 || config.kb > 31 {
+      return Err(AudioError::Format(
+        "ALAC configuration is not valid".to_string()
+      ));
+    }
+    Ok(config)
+  }
+
+  /// Returns the configuration as stored in a magic cookie.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = vec![0u8; CONFIG_SIZE];
+    BigEndian::write_u32(&mut bytes[0..4], self.frame_length);
+    bytes[5] = self.bit_depth;
+    bytes[6] = self.pb;
+    bytes[7] = self.mb;
+    bytes[8] = self.kb;
+    bytes[9] = self.channels;
+    BigEndian::write_u16(&mut bytes[10..12], self.max_run);
+    BigEndian::write_u32(&mut bytes[12..16], self.max_frame_bytes);
+    BigEndian::write_u32(&mut bytes[16..20], self.avg_bit_rate);
+    BigEndian::write_u32(&mut bytes[20..24], self.sample_rate);
+    bytes
+  }
+
+  /// Returns the `Codec` of the audio.
+  pub fn codec(&self) -> AudioResult<Codec> {
+    match self.bit_depth {
+      16 => Ok(ALAC_I16),
+      20 => Ok(ALAC_I20),
+      24 => Ok(ALAC_I24),
+      32 => Ok(ALAC_I32),
+      d  =>
+        Err(AudioError::Unsupported(
+          format!("ALAC bit depth of {} is not supported", d)
+        ))
+    }
+  }
+}
+
+/// Decodes the packets of an ALAC stream.
+pub fn read(config: &AlacConfig, packets: &[&[u8]]) -> AudioResult<Vec<Sample>> {
+  let codec = try!(config.codec());
+  let mut values: Vec<i32> = Vec::new();
+  for packet in packets.iter() {
+    try!(read_packet(config, packet, &mut values));
+  }
+  reorder(&mut values, config.channels as usize, false);
+  // Samples are converted as if they were read from LPCM, so the audio is
+  // encoded identically by the LPCM codecs of the same bit depth.
+  let (lpcm_codec, shift) = lpcm_equivalent(codec);
+  let size = lpcm_codec.sample_size();
+  let mut bytes = vec![0u8; values.len() * size];
+  for (value, sample) in values.iter().zip(bytes.chunks_mut(size)) {
+    LittleEndian::write_int(sample, (*value << shift) as i64, size);
+  }
+  lpcm::read(&bytes, lpcm_codec)
+}
+
+/// Encodes audio to the packets of an ALAC stream, returning the stream
+/// configuration and the packets.
+pub fn create(audio: &AudioBuffer, codec: Codec) -> AudioResult<(AlacConfig, Vec<Vec<u8>>)> {
+  let bit_depth: u8 =
+    match codec {
+      ALAC_I16 => 16,
+      ALAC_I20 => 20,
+      ALAC_I24 => 24,
+      ALAC_I32 => 32,
+      c =>
+        return Err(AudioError::Unsupported(
+          format!("Unsupported codec {} was passed into the ALAC encoder", c)
+        ))
+    };
+  let channels = audio.channels as usize;
+  if channels == 0 || channels > CHANNEL_ELEMENTS.len() {
+    return Err(AudioError::Unsupported(
+      format!("ALAC does not support {} channels", channels)
+    ));
+  }
+  let (lpcm_codec, shift) = lpcm_equivalent(codec);
+  let size = lpcm_codec.sample_size();
+  let mut values: Vec<i32> =
+    try!(lpcm::create(audio, lpcm_codec)).chunks(size)
+    .map(|sample| LittleEndian::read_int(sample, size) as i32 >> shift)
+    .collect();
+  reorder(&mut values, channels, true);
+
+  let mut config = AlacConfig {
+    frame_length:     FRAME_LENGTH,
+    bit_depth:        bit_depth,
+    pb:               PB0,
+    mb:               MB0,
+    kb:               KB0,
+    channels:         channels as u8,
+    max_run:          MAX_RUN,
+    max_frame_bytes:  0,
+    avg_bit_rate:     0,
+    sample_rate:      audio.sample_rate
+  };
+  let mut encoder = Encoder::new(&config);
+  let packets: Vec<Vec<u8>> =
+    values.chunks(FRAME_LENGTH as usize * channels)
+    .map(|frames| encoder.write_packet(frames))
+    .collect();
+  let total_bytes = packets.iter().fold(0u64, |total, packet| total + packet.len() as u64);
+  let frames = (values.len() / channels) as u64;
+  config.max_frame_bytes =
+    packets.iter().map(|packet| packet.len() as u32).max().unwrap_or(0);
+  if frames > 0 {
+    config.avg_bit_rate =
+      (total_bytes * 8 * audio.sample_rate as u64 / frames).min(u32::max_value() as u64) as u32;
+  }
+  Ok((config, packets))
+}
+
+// Private functions
+
+/// Returns the LPCM codec holding the samples of an ALAC codec, and the
+/// number of unused low bits of each LPCM sample.
+#[inline]
+fn lpcm_equivalent(codec: Codec) -> (Codec, u32) {
+  match codec {
+    ALAC_I20 => (LPCM_I24_LE, 4),
+    ALAC_I24 => (LPCM_I24_LE, 0),
+    ALAC_I32 => (LPCM_I32_LE, 0),
+    _        => (LPCM_I16_LE, 0)
+  }
+}
+
+/// Sign extends the lowest bits of a value.
+#[inline]
+fn sign_extend(value: i32, bits: u32) -> i32 {
+  let shift = 32 - bits;
+  value.wrapping_shl(shift) >> shift
+}
+
+/// Reorders the channels of interleaved values to the order of ALAC, or
+/// from it. Streams with more channels than ALAC orders are left as they
+/// are.
+fn reorder(values: &mut [i32], channels: usize, to_alac: bool) {
+  let order =
+    match CHANNEL_ORDERS.get(channels.wrapping_sub(1)) {
+      Some(order) if channels > 2 => order,
+      _ => return
+    };
+  let mut frame = vec![0i32; channels];
+  for values in values.chunks_mut(channels) {
+    frame.copy_from_slice(values);
+    for (c, &channel) in order.iter().enumerate() {
+      if to_alac {
+        values[c] = frame[channel];
+      } else {
+        values[channel] = frame[c];
+      }
+    }
+  }
+}
+
+/// Decodes a packet, appending its interleaved samples to the values.
+fn read_packet(config: &AlacConfig, packet: &[u8], values: &mut Vec<i32>) -> AudioResult<()> {
+  let channels = config.channels as usize;
+  let start    = values.len();
+  let mut bits = BitReader::new(packet);
+  let mut channel = 0;
+  let mut frames: Option<usize> = None;
+  loop {
+    match bits.read(3) {
+      tag @ ID_SCE | tag @ ID_CPE | tag @ ID_LFE => {
+        let count = if tag == ID_CPE { 2 } else { 1 };
+        if channel + count > channels {
+          return Err(AudioError::Format(
+            "ALAC packet has more channels than the configuration".to_string()
+          ));
+        }
+        let element = try!(read_element(config, &mut bits, count));
+        let len = element[0].len();
+        match frames {
+          None => {
+            frames = Some(len);
+            values.resize(start + len * channels, 0);
+          },
+          Some(frames) if frames != len =>
+            return Err(AudioError::Format(
+              "ALAC packet has channels of different lengths".to_string()
+            )),
+          _ => {}
+        }
+        for (c, samples) in element.iter().enumerate() {
+          for (i, sample) in samples.iter().enumerate() {
+            values[start + i * channels + channel + c] = *sample;
+          }
+        }
+        channel += count;
+      },
+      ID_CCE | ID_PCE =>
+        return Err(AudioError::Unsupported(
+          "ALAC coupling and program configuration elements are not supported".to_string()
+        )),
+      ID_DSE => {
+        bits.skip(4);
+        let aligned = bits.read(1) == 1;
+        let mut count = bits.read(8);
+        if count == 255 {
+          count += bits.read(8);
+        }
+        if aligned {
+          bits.align();
+        }
+        bits.skip(count as usize * 8);
+      },
+      ID_FIL => {
+        let mut count = bits.read(4);
+        if count == 15 {
+          count += bits.read(8).wrapping_sub(1);
+        }
+        bits.skip(count as usize * 8);
+      },
+      _ => break
+    }
+    if bits.is_past_end() {
+      return Err(AudioError::Format(
+        "ALAC packet ends before its last element".to_string()
+      ));
+    }
+  }
+  if channel != channels {
+    return Err(AudioError::Format(
+      "ALAC packet is missing channels".to_string()
+    ));
+  }
+  Ok(())
+}
+
+/// Decodes the channels of a single or channel pair element.
+fn read_element(config: &AlacConfig, bits: &mut BitReader, count: usize) -> AudioResult<Vec<Vec<i32>>> {
+  // Element instance tag
+  bits.skip(4);
+  if bits.read(12) != 0 {
+    return Err(AudioError::Format(
+      "ALAC element header is not valid".to_string()
+    ));
+  }
+  let partial       = bits.read(1) == 1;
+  let bytes_shifted = bits.read(2);
+  let escaped       = bits.read(1) == 1;
+  let frames =
+    if partial {
+      bits.read(32) as usize
+    } else {
+      config.frame_length as usize
+    };
+  let bit_depth = config.bit_depth as u32;
+  if frames > config.frame_length as usize || bytes_shifted == 3 {
+    return Err(AudioError::Format(
+      "ALAC element header is not valid".to_string()
+    ));
+  }
+  let mut channels = vec![vec![0i32; frames]; count];
+
+  if escaped {
+    for i in 0..frames {
+      for channel in channels.iter_mut() {
+        channel[i] = sign_extend(bits.read(bit_depth) as i32, bit_depth);
+      }
+    }
+    return Ok(channels);
+  }
+
+  let shift = bytes_shifted * 8;
+  let chan_bits = bit_depth + count as u32 - 1 - shift;
+  if chan_bits > 32 || chan_bits == 0 {
+    return Err(AudioError::Format(
+      "ALAC element has too many bits per sample".to_string()
+    ));
+  }
+  let mix_bits = bits.read(8);
+  let mix_res  = bits.read(8) as u8 as i8 as i32;
+  let mut filters: Vec<(u32, u32, u32, Vec<i16>)> = Vec::with_capacity(count);
+  for _ in 0..count {
+    let mode_byte = bits.read(8);
+    let pb_byte   = bits.read(8);
+    let coefs = (0..pb_byte & 0x1F).map(|_| bits.read(16) as u16 as i16).collect();
+    filters.push((mode_byte >> 4, mode_byte & 0xF, pb_byte >> 5, coefs));
+  }
+  // The shifted low bits of the samples precede the residuals.
+  let mut shifted_bits = bits.clone();
+  bits.skip(shift as usize * count * frames);
+
+  let mut residuals = vec![0i32; frames];
+  for (channel, &mut (mode, den_shift, pb_factor, ref mut coefs)) in
+      channels.iter_mut().zip(filters.iter_mut()) {
+    let mut rice = RiceParams::new(config, config.pb as u32 * pb_factor / 4);
+    try!(rice.read(bits, &mut residuals, chan_bits));
+    if mode != 0 {
+      // A first order filter is applied before the adaptive filter.
+      let first = residuals.clone();
+      unpredict(&first, &mut residuals, &mut [0i16; 31], chan_bits, 0);
+    }
+    unpredict(&residuals, channel, coefs, chan_bits, den_shift);
+  }
+
+  if count == 2 && mix_res != 0 {
+    let (left, right) = channels.split_at_mut(1);
+    for (u, v) in left[0].iter_mut().zip(right[0].iter_mut()) {
+      let r = u.wrapping_sub(mix_res.wrapping_mul(*v) >> mix_bits.min(31));
+      *u = r.wrapping_add(*v);
+      *v = r;
+    }
+  }
+  if shift > 0 {
+    for i in 0..frames {
+      for channel in channels.iter_mut() {
+        channel[i] = channel[i].wrapping_shl(shift) | shifted_bits.read(shift) as i32;
+      }
+    }
+  }
+  Ok(channels)
+}
+
+/// Restores samples from the residuals of the adaptive prediction filter,
+/// adapting the coefficients of the filter. A filter of order 31 is a fixed
+/// first order filter.
+fn unpredict(residuals: &[i32], samples: &mut [i32], coefs: &mut [i16], chan_bits: u32, den_shift: u32) {
+  let len = residuals.len();
+  if len == 0 {
+    return;
+  }
+  let order = coefs.len();
+  samples[0] = residuals[0];
+  if order == 0 {
+    samples.copy_from_slice(residuals);
+    return;
+  }
+  let first_order = if order == 31 { len } else { (order + 1).min(len) };
+  for j in 1..first_order {
+    samples[j] = sign_extend(residuals[j].wrapping_add(samples[j - 1]), chan_bits);
+  }
+  let den_half = if den_shift > 0 { 1 << (den_shift - 1) } else { 0 };
+  for j in first_order..len {
+    let history  = &samples[j - order - 1 .. j];
+    let residual = residuals[j];
+    let value    = residual.wrapping_add(predict(history, coefs, den_half, den_shift));
+    adapt(history, coefs, residual, den_shift);
+    samples[j] = sign_extend(value, chan_bits);
+  }
+}
+
+/// Returns the residuals of the adaptive prediction filter.
+fn predict_block(samples: &[i32], residuals: &mut [i32], coefs: &mut [i16], chan_bits: u32, den_shift: u32) {
+  let len = samples.len();
+  if len == 0 {
+    return;
+  }
+  let order = coefs.len();
+  residuals[0] = samples[0];
+  let first_order = (order + 1).min(len);
+  for j in 1..first_order {
+    residuals[j] = sign_extend(samples[j].wrapping_sub(samples[j - 1]), chan_bits);
+  }
+  let den_half = if den_shift > 0 { 1 << (den_shift - 1) } else { 0 };
+  for j in first_order..len {
+    let history  = &samples[j - order - 1 .. j];
+    let residual = sign_extend(samples[j].wrapping_sub(predict(history, coefs, den_half, den_shift)), chan_bits);
+    adapt(history, coefs, residual, den_shift);
+    residuals[j] = residual;
+  }
+}
+
+/// Predicts the next sample from the previous samples, where the first is
+/// the oldest sample and the filter predicts differences from it.
+#[inline]
+fn predict(history: &[i32], coefs: &[i16], den_half: i32, den_shift: u32) -> i32 {
+  let order = coefs.len();
+  let top   = history[0];
+  let sum   = coefs.iter().enumerate().fold(0i32, |sum, (k, &coef)|
+    sum.wrapping_add((coef as i32).wrapping_mul(top.wrapping_sub(history[order - k])))
+  );
+  top.wrapping_add(den_half.wrapping_sub(sum) >> den_shift)
+}
+
+/// Adapts the coefficients of the filter towards the sign of the residual,
+/// starting with the coefficient of the oldest sample.
+#[inline]
+fn adapt(history: &[i32], coefs: &mut [i16], residual: i32, den_shift: u32) {
+  let order = coefs.len();
+  let top   = history[0];
+  let mut remaining = residual;
+  if residual > 0 {
+    for k in (0..order).rev() {
+      let difference = top.wrapping_sub(history[order - k]);
+      let sign = difference.signum();
+      coefs[k] = coefs[k].wrapping_sub(sign as i16);
+      remaining -= (order - k) as i32 * (sign.wrapping_mul(difference) >> den_shift);
+      if remaining <= 0 {
+        break;
+      }
+    }
+  } else if residual < 0 {
+    for k in (0..order).rev() {
+      let difference = top.wrapping_sub(history[order - k]);
+      let sign = difference.signum();
+      coefs[k] = coefs[k].wrapping_add(sign as i16);
+      remaining -= (order - k) as i32 * ((-sign).wrapping_mul(difference) >> den_shift);
+      if remaining >= 0 {
+        break;
+      }
+    }
+  }
+}
+
+/// State of the adaptive Golomb-Rice coder of a channel.
+struct RiceParams {
+  mean:     u32,
+  pb:       u32,
+  kb:       u32,
+  wb:       u32
+}
+
+impl RiceParams {
+  fn new(config: &AlacConfig, pb: u32) -> RiceParams {
+    RiceParams {
+      mean: config.mb as u32,
+      pb:   pb,
+      kb:   config.kb as u32,
+      wb:   (1u32 << config.kb) - 1
+    }
+  }
+
+  /// Returns the parameter of the code of the next value.
+  #[inline]
+  fn value_k(&self) -> u32 {
+    let k = 31 - ((self.mean >> QB_SHIFT) + 3).leading_zeros();
+    k.min(self.kb)
+  }
+
+  /// Updates the mean with a coded value, returning whether a run of zeros
+  /// follows.
+  #[inline]
+  fn update(&mut self, value: u32) -> bool {
+    self.mean = self.pb.wrapping_mul(value)
+                .wrapping_add(self.mean)
+                .wrapping_sub(self.pb.wrapping_mul(self.mean) >> QB_SHIFT);
+    if value > MEAN_CLAMP {
+      self.mean = MEAN_CLAMP;
+    }
+    self.mean.wrapping_shl(MMUL_SHIFT) < QB
+  }
+
+  /// Returns the parameter of the code of a run of zeros.
+  #[inline]
+  fn run_k(&self) -> u32 {
+    self.mean.leading_zeros() - BIT_OFF + ((self.mean + MOFF) >> MDEN_SHIFT)
+  }
+
+  fn read(&mut self, bits: &mut BitReader, residuals: &mut [i32], chan_bits: u32) -> AudioResult<()> {
+    let len = residuals.len();
+    let mut zero_mode = 0u32;
+    let mut i = 0;
+    while i < len {
+      let k = self.value_k();
+      let value = read_code(bits, (1 << k) - 1, k, chan_bits);
+      let folded = value as u64 + zero_mode as u64;
+      let magnitude = ((folded + 1) >> 1) as i32;
+      residuals[i] = if folded & 1 == 1 { magnitude.wrapping_neg() } else { magnitude };
+      i += 1;
+      let run = self.update(value.wrapping_add(zero_mode));
+      zero_mode = 0;
+      if run && i < len {
+        zero_mode = 1;
+        let k = self.run_k();
+        let zeros = read_code(bits, ((1 << k) - 1) & self.wb, k, RUN_BITS) as usize;
+        if i + zeros > len {
+          return Err(AudioError::Format(
+            "ALAC run of zeros exceeds the packet".to_string()
+          ));
+        }
+        for residual in residuals[i .. i + zeros].iter_mut() {
+          *residual = 0;
+        }
+        i += zeros;
+        if zeros >= 0xFFFF {
+          zero_mode = 0;
+        }
+        self.mean = 0;
+      }
+    }
+    Ok(())
+  }
+
+  fn write(&mut self, bits: &mut BitWriter, residuals: &[i32], chan_bits: u32) {
+    let len = residuals.len();
+    let mut zero_mode = 0u32;
+    let mut i = 0;
+    while i < len {
+      let k = self.value_k();
+      let residual = residuals[i];
+      let folded = if residual < 0 {
+        (residual as i64).abs() as u32 * 2 - 1
+      } else {
+        residual as u32 * 2
+      };
+      let value = folded.wrapping_sub(zero_mode);
+      write_code(bits, value, (1 << k) - 1, k, chan_bits);
+      i += 1;
+      let run = self.update(value.wrapping_add(zero_mode));
+      zero_mode = 0;
+      if run && i < len {
+        zero_mode = 1;
+        let mut zeros = 0;
+        while i < len && residuals[i] == 0 {
+          zeros += 1;
+          i += 1;
+          if zeros >= 0xFFFF {
+            zero_mode = 0;
+            break;
+          }
+        }
+        let k = self.run_k();
+        write_code(bits, zeros, ((1 << k) - 1) & self.wb, k, RUN_BITS);
+        self.mean = 0;
+      }
+    }
+  }
+}
+
+/// Reads a value coded as a unary quotient and a truncated remainder, or
+/// escaped after a prefix of nine ones.
+#[inline]
+fn read_code(bits: &mut BitReader, m: u32, k: u32, escape_bits: u32) -> u32 {
+  let prefix = bits.read_ones(MAX_PREFIX);
+  if prefix >= MAX_PREFIX {
+    return bits.read(escape_bits);
+  }
+  let value = prefix.wrapping_mul(m);
+  let remainder = bits.peek(k);
+  if remainder < 2 {
+    bits.skip(k as usize - 1);
+    value
+  } else {
+    bits.skip(k as usize);
+    value.wrapping_add(remainder - 1)
+  }
+}
+
+#[inline]
+fn write_code(bits: &mut BitWriter, value: u32, m: u32, k: u32, escape_bits: u32) {
+  let quotient  = value / m;
+  let remainder = value % m;
+  if quotient < MAX_PREFIX && quotient + k + 1 <= 25 {
+    bits.write((1 << quotient) - 1 << 1, quotient + 1);
+    if remainder == 0 {
+      bits.write(0, k - 1);
+    } else {
+      bits.write(remainder + 1, k);
+    }
+  } else {
+    bits.write((1 << MAX_PREFIX) - 1, MAX_PREFIX);
+    bits.write(value, escape_bits);
+  }
+}
+
+/// Encodes packets, keeping the prediction filter of each channel from one
+/// packet to the next.
+struct Encoder {
+  config:   AlacConfig,
+  coefs:    Vec<[i16; PREDICTOR_ORDER]>
+}
+
+impl Encoder {
+  fn new(config: &AlacConfig) -> Encoder {
+    let den = 1i32 << DEN_SHIFT;
+    let mut coefs = [0i16; PREDICTOR_ORDER];
+    coefs[0] = (38 * den >> 4) as i16;
+    coefs[1] = (-29 * den >> 4) as i16;
+    coefs[2] = (-2 * den >> 4) as i16;
+    Encoder {
+      config: *config,
+      coefs:  vec![coefs; config.channels as usize]
+    }
+  }
+
+  /// Encodes the interleaved samples of up to a frame length of frames.
+  fn write_packet(&mut self, values: &[i32]) -> Vec<u8> {
+    let channels = self.config.channels as usize;
+    let frames   = values.len() / channels;
+    let mut bits = BitWriter::new();
+    let mut channel = 0;
+    let mut instances = [0u32; 8];
+    for &tag in CHANNEL_ELEMENTS[channels - 1].iter() {
+      let count = if tag == ID_CPE { 2 } else { 1 };
+      let samples: Vec<Vec<i32>> =
+        (channel .. channel + count)
+        .map(|c| values.iter().skip(c).step_by(channels).cloned().collect())
+        .collect();
+      bits.write(tag, 3);
+      bits.write(instances[tag as usize], 4);
+      instances[tag as usize] += 1;
+      self.write_element(&mut bits, &samples, channel, frames);
+      channel += count;
+    }
+    bits.write(ID_END, 3);
+    bits.finish()
+  }
+
+  /// Writes a single or channel pair element, after its tag.
+  fn write_element(&mut self, bits: &mut BitWriter, samples: &[Vec<i32>], channel: usize, frames: usize) {
+    let count     = samples.len();
+    let bit_depth = self.config.bit_depth as u32;
+    let partial   = frames != self.config.frame_length as usize;
+    let write_header = |bits: &mut BitWriter, bytes_shifted: u32, escaped: bool| {
+      bits.write(0, 12);
+      bits.write(partial as u32, 1);
+      bits.write(bytes_shifted, 2);
+      bits.write(escaped as u32, 1);
+      if partial {
+        bits.write(frames as u32, 32);
+      }
+    };
+
+    // 32-bit samples are shifted to keep the filters within 32 bits.
+    let bytes_shifted = if bit_depth == 32 { 2 } else { 0 };
+    let shift = bytes_shifted * 8;
+    let chan_bits = bit_depth - shift + count as u32 - 1;
+    let shifted: Vec<Vec<i32>> =
+      samples.iter().map(|channel| channel.iter().map(|&s| s >> shift).collect()).collect();
+
+    let mut best: Option<(BitWriter, Vec<[i16; PREDICTOR_ORDER]>)> = None;
+    let residues: &[i32] = if count == 2 { &MIX_RESIDUES } else { &[0] };
+    for &mix_res in residues.iter() {
+      let mut trial = BitWriter::new();
+      write_header(&mut trial, bytes_shifted, false);
+      trial.write(if mix_res != 0 { MIX_BITS } else { 0 }, 8);
+      trial.write(mix_res as u8 as u32, 8);
+      let mixed: Vec<Vec<i32>> =
+        if count == 2 && mix_res != 0 {
+          let (l, r) = (&shifted[0], &shifted[1]);
+          let weight = (1 << MIX_BITS) - mix_res;
+          vec![
+            l.iter().zip(r.iter()).map(|(&l, &r)| (mix_res * l + weight * r) >> MIX_BITS).collect(),
+            l.iter().zip(r.iter()).map(|(&l, &r)| l - r).collect()
+          ]
+        } else {
+          shifted.clone()
+        };
+
+      // The filters are adapted to the packet before they are stored.
+      let mut coefs: Vec<[i16; PREDICTOR_ORDER]> = self.coefs[channel .. channel + count].to_vec();
+      let mut residuals: Vec<Vec<i32>> = vec![vec![0i32; frames]; count];
+      for ((samples, residuals), coefs) in
+          mixed.iter().zip(residuals.iter_mut()).zip(coefs.iter_mut()) {
+        predict_block(samples, residuals, coefs, chan_bits, DEN_SHIFT);
+      }
+      for coefs in coefs.iter() {
+        trial.write(DEN_SHIFT, 8);
+        trial.write(PB_FACTOR << 5 | PREDICTOR_ORDER as u32, 8);
+        for &coef in coefs.iter() {
+          trial.write(coef as u16 as u32, 16);
+        }
+      }
+      if shift > 0 {
+        for i in 0..frames {
+          for channel in samples.iter() {
+            trial.write(channel[i] as u32 & ((1 << shift) - 1), shift);
+          }
+        }
+      }
+      for (samples, coefs) in mixed.iter().zip(coefs.iter()) {
+        let mut filter = *coefs;
+        let mut residuals = vec![0i32; frames];
+        predict_block(samples, &mut residuals, &mut filter, chan_bits, DEN_SHIFT);
+        let mut rice = RiceParams::new(&self.config, self.config.pb as u32 * PB_FACTOR / 4);
+        rice.write(&mut trial, &residuals, chan_bits);
+      }
+      let better = match best {
+        Some((ref bits, _)) => trial.len() < bits.len(),
+        None => true
+      };
+      if better {
+        best = Some((trial, coefs));
+      }
+    }
+
+    let (compressed, coefs) = best.unwrap();
+    if compressed.len() <= 16 + 32 + frames * count * bit_depth as usize {
+      bits.append(&compressed);
+      self.coefs[channel .. channel + count].copy_from_slice(&coefs);
+    } else {
+      write_header(bits, 0, true);
+      for i in 0..frames {
+        for channel in samples.iter() {
+          bits.write(channel[i] as u32 & (u32::max_value() >> (32 - bit_depth)), bit_depth);
+        }
+      }
+    }
+  }
+}
+
+/// Reads bits of a packet, starting with the most significant bit. Reading
+/// past the end gives zeros.
+#[derive(Clone)]
+struct BitReader<'a> {
+  bytes:    &'a [u8],
+  position: usize
+}
+
+impl<'a> BitReader<'a> {
+  fn new(bytes: &'a [u8]) -> BitReader<'a> {
+    BitReader {
+      bytes:    bytes,
+      position: 0
+    }
+  }
+
+  #[inline]
+  fn is_past_end(&self) -> bool {
+    self.position > self.bytes.len() * 8
+  }
+
+  /// Returns the next bits without reading them, up to 32 bits.
+  #[inline]
+  fn peek(&self, bits: u32) -> u32 {
+    if bits == 0 {
+      return 0;
+    }
+    let byte = self.position / 8;
+    let mut window = 0u64;
+    for i in 0..5 {
+      window = window << 8 | *self.bytes.get(byte + i).unwrap_or(&0) as u64;
+    }
+    (window << (24 + self.position % 8) >> (64 - bits)) as u32
+  }
+
+  #[inline]
+  fn read(&mut self, bits: u32) -> u32 {
+    let value = self.peek(bits);
+    self.position += bits as usize;
+    value
+  }
+
+  #[inline]
+  fn skip(&mut self, bits: usize) {
+    self.position += bits;
+  }
+
+  #[inline]
+  fn align(&mut self) {
+    self.position = (self.position + 7) / 8 * 8;
+  }
+
+  /// Reads consecutive one bits, up to the maximum. The zero bit following
+  /// fewer ones is also read.
+  #[inline]
+  fn read_ones(&mut self, max: u32) -> u32 {
+    let ones = (!(self.peek(max) << (32 - max))).leading_zeros().min(max);
+    self.position += ones as usize + if ones < max { 1 } else { 0 };
+    ones
+  }
+}
+
+/// Writes bits of a packet, starting with the most significant bit.
+struct BitWriter {
+  bytes:    Vec<u8>,
+  bits:     usize
+}
+
+impl BitWriter {
+  fn new() -> BitWriter {
+    BitWriter {
+      bytes:  Vec::new(),
+      bits:   0
+    }
+  }
+
+  /// Returns the number of bits written.
+  #[inline]
+  fn len(&self) -> usize {
+    self.bits
+  }
+
+  /// Writes the lowest bits of a value, up to 32 bits.
+  #[inline]
+  fn write(&mut self, value: u32, bits: u32) {
+    for i in (0..bits).rev() {
+      if self.bits % 8 == 0 {
+        self.bytes.push(0);
+      }
+      if (value >> i) & 1 == 1 {
+        let last = self.bytes.len() - 1;
+        self.bytes[last] |= 0x80 >> (self.bits % 8);
+      }
+      self.bits += 1;
+    }
+  }
+
+  /// Writes all bits written to another writer.
+  fn append(&mut self, other: &BitWriter) {
+    let whole = other.bits / 8;
+    for &byte in other.bytes[..whole].iter() {
+      self.write(byte as u32, 8);
+    }
+    if other.bits % 8 != 0 {
+      let rest = (other.bits % 8) as u32;
+      self.write((other.bytes[whole] >> (8 - rest)) as u32, rest);
+    }
+  }
+
+  /// Returns the bytes written, padded with zeros to a whole byte.
+  fn finish(self) -> Vec<u8> {
+    self.bytes
+  }
+}
+
+#[cfg(test)]
+mod coding {
+  use ::buffer::*;
+  use ::codecs::Codec::*;
+  use super::*;
+  use super::{BitReader, BitWriter};
+
+  #[test]
+  fn bits_round_trip() {
+    let mut bits = BitWriter::new();
+    bits.write(0b101, 3);
+    bits.write(0xDEADBEEF, 32);
+    bits.write(0, 0);
+    bits.write(0b1110, 4);
+    assert_eq!(39, bits.len());
+    let mut appended = BitWriter::new();
+    appended.write(1, 1);
+    appended.append(&bits);
+    let bytes = appended.finish();
+    assert_eq!(0xDD, bytes[0]);
+    let mut reader = BitReader::new(&bytes);
+    assert_eq!(1, reader.read(1));
+    assert_eq!(0b101, reader.read(3));
+    assert_eq!(0xDEADBEEF, reader.read(32));
+    assert_eq!(3, reader.read_ones(9));
+    assert!(!reader.is_past_end());
+  }
+
+  #[test]
+  fn config_round_trip() {
+    let audio = AudioBuffer::from_samples(96000, 2, vec![0f32; 8]);
+    let (config, packets) = create(&audio, ALAC_I24).unwrap();
+    assert_eq!(1, packets.len());
+    let bytes = config.to_bytes();
+    assert_eq!(24, bytes.len());
+    assert_eq!(&[0, 0, 16, 0, 0, 24, 40, 10, 14, 2], &bytes[0..10]);
+    assert_eq!(config, AlacConfig::read(&bytes).unwrap());
+    assert_eq!(ALAC_I24, config.codec().unwrap());
+
+    // Cookies may wrap the configuration in atoms.
+    let mut wrapped = vec![0, 0, 0, 12, b'f', b'r', b'm', b'a', b'a', b'l', b'a', b'c',
+                           0, 0, 0, 36, b'a', b'l', b'a', b'c', 0, 0, 0, 0];
+    wrapped.extend_from_slice(&bytes);
+    assert_eq!(config, AlacConfig::read(&wrapped).unwrap());
+    assert!(AlacConfig::read(&bytes[..20]).is_err());
+  }
+
+  #[test]
+  fn codecs_eq() {
+    let mut samples = Vec::new();
+    for i in 0..10000 {
+      let t = i as f32 / 44100f32;
+      samples.push(0.7 * (t * 440f32 * 6.2831855).sin());
+      samples.push(0.5 * (t * 660f32 * 6.2831855).sin() + 0.01 * ((i * 7919) % 13) as f32 / 13f32);
+    }
+    let audio = AudioBuffer::from_samples(44100, 2, samples);
+    for &(codec, lpcm) in [(ALAC_I16, LPCM_I16_LE), (ALAC_I20, LPCM_I24_LE),
+                           (ALAC_I24, LPCM_I24_LE), (ALAC_I32, LPCM_I32_LE)].iter() {
+      let (config, packets) = create(&audio, codec).unwrap();
+      assert_eq!(3, packets.len());
+      let packets: Vec<&[u8]> = packets.iter().map(|packet| &packet[..]).collect();
+      let total = packets.iter().fold(0, |total, packet| total + packet.len());
+      assert!(total < audio.samples.len() * lpcm.sample_size());
+      let samples = read(&config, &packets).unwrap();
+      let expected = lpcm::read(&lpcm::create(&audio, lpcm).unwrap(), lpcm).unwrap();
+      if codec == ALAC_I20 {
+        assert_eq!(expected.len(), samples.len());
+        for (expected, sample) in expected.iter().zip(samples.iter()) {
+          assert!((expected - sample).abs() < 2f32 / (1 << 19) as f32);
+        }
+      } else {
+        assert_eq!(expected, samples);
+      }
+      // Decoded samples are encoded identically.
+      let decoded = AudioBuffer::from_samples(44100, 2, samples.clone());
+      let (config, packets) = create(&decoded, codec).unwrap();
+      let packets: Vec<&[u8]> = packets.iter().map(|packet| &packet[..]).collect();
+      assert_eq!(samples, read(&config, &packets).unwrap());
+    }
+  }
+
+  #[test]
+  fn escaped_packets() {
+    // Noise does not compress, so it is stored uncompressed.
+    let mut seed = 12345u32;
+    let samples: Vec<f32> = (0..3000).map(|_| {
+      seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+      (seed >> 16) as i16 as f32 / 32768f32
+    }).collect();
+    let audio = AudioBuffer::from_samples(8000, 3, samples);
+    let (config, packets) = create(&audio, ALAC_I16).unwrap();
+    let packets: Vec<&[u8]> = packets.iter().map(|packet| &packet[..]).collect();
+    assert_eq!(audio.samples, read(&config, &packets).unwrap());
+  }
+
+  #[test]
+  fn silence_and_channels() {
+    for channels in 1..9 {
+      let audio = AudioBuffer::from_samples(48000, channels, vec![0f32; 5000 * channels as usize]);
+      let (config, packets) = create(&audio, ALAC_I16).unwrap();
+      let packets: Vec<&[u8]> = packets.iter().map(|packet| &packet[..]).collect();
+      assert_eq!(audio.samples, read(&config, &packets).unwrap());
+    }
+    let audio = AudioBuffer::from_samples(48000, 9, vec![0f32; 9]);
+    assert!(create(&audio, ALAC_I16).is_err());
+    assert!(create(&audio, LPCM_I16_LE).is_err());
+  }
+}
@@ -0,0 +1,152 @@
+//! Creative ADPCM
+//!
+//! 4-bit ADPCM of the Sound Blaster, used by Creative Voice files.
+//!
+//! Each block of audio begins with a reference byte holding the first
+//! unsigned 8-bit sample. Every following sample is stored as a nibble, high
+//! nibble first, whose top bit gives the sign of the difference from the
+//! previous sample and whose remaining bits give its magnitude. The magnitude
+//! is scaled by a step that grows after large differences and shrinks after
+//! repeated samples.
+//!
+//! References
+//! - [FFmpeg ADPCM Decoder](https://github.com/FFmpeg/FFmpeg/blob/master/libavcodec/adpcm.c)
+
+/// Largest shift applied to the magnitude of a difference.
+const MAX_STEP: u32 = 3;
+
+/// State of a decoder or encoder between the nibbles of a block.
+///
+/// Values are signed 8-bit samples, which are stored in the reference byte
+/// offset by 128 as they are in unsigned 8-bit PCM.
+#[derive(Clone, Copy, Debug)]
+pub struct AdpcmState {
+  predictor:  i32,
+  step:       u32
+}
+
+impl AdpcmState {
+  /// Creates the state of a block from its reference byte.
+  pub fn new(reference: u8) -> AdpcmState {
+    AdpcmState {
+      predictor:  reference as i32 - 128,
+      step:       0
+    }
+  }
+
+  /// Returns the sample decoded from a nibble, updating the state.
+  #[inline]
+  fn expand(&mut self, nibble: u8) -> i32 {
+    let delta = (nibble & 0x7) as i32;
+    let diff  = delta << self.step;
+    self.predictor =
+      if nibble & 0x8 != 0 {
+        (self.predictor - diff).max(-128)
+      } else {
+        (self.predictor + diff).min(127)
+      };
+    if delta >= 5 && self.step < MAX_STEP {
+      self.step += 1;
+    } else if delta == 0 && self.step > 0 {
+      self.step -= 1;
+    }
+    self.predictor
+  }
+
+  /// Returns the nibble whose decoded sample is closest to the given sample,
+  /// updating the state as if it were decoded.
+  #[inline]
+  fn compress(&mut self, sample: i32) -> u8 {
+    let mut best = (0u8, i32::max_value());
+    for nibble in 0..16u8 {
+      let mut state = *self;
+      let error = (state.expand(nibble) - sample).abs();
+      if error < best.1 {
+        best = (nibble, error);
+      }
+    }
+    self.expand(best.0);
+    best.0
+  }
+
+  /// Decodes the nibbles of a block, or of the blocks continuing it, to
+  /// signed 8-bit samples.
+  pub fn decode(&mut self, bytes: &[u8], values: &mut Vec<i32>) {
+    values.reserve(bytes.len() * 2);
+    for &byte in bytes.iter() {
+      values.push(self.expand(byte >> 4));
+      values.push(self.expand(byte & 0xf));
+    }
+  }
+}
+
+/// Decodes a block of audio, including its reference byte, to signed 8-bit
+/// samples. The state after the block is returned for decoding the blocks
+/// continuing it.
+pub fn read(bytes: &[u8]) -> (Vec<i32>, Option<AdpcmState>) {
+  let mut values = Vec::new();
+  match bytes.split_first() {
+    Some((&reference, nibbles)) => {
+      let mut state = AdpcmState::new(reference);
+      values.push(state.predictor);
+      state.decode(nibbles, &mut values);
+      (values, Some(state))
+    },
+    None => (values, None)
+  }
+}
+
+/// Encodes signed 8-bit samples to a block of audio. A block holds an odd
+/// number of samples, so the last sample is repeated when the samples would
+/// leave half of the last byte unused.
+pub fn create(values: &[i32]) -> Vec<u8> {
+  let mut bytes = Vec::with_capacity(1 + values.len() / 2);
+  if let Some((&first, rest)) = values.split_first() {
+    let first = first.max(-128).min(127);
+    bytes.push((first + 128) as u8);
+    let mut state = AdpcmState::new(bytes[0]);
+    for pair in rest.chunks(2) {
+      let high = state.compress(pair[0]);
+      let low  = state.compress(*pair.get(1).unwrap_or(&pair[0]));
+      bytes.push(high << 4 | low);
+    }
+  }
+  bytes
+}
+
+#[cfg(test)]
+mod coding {
+  use super::*;
+
+  #[test]
+  fn reference_byte() {
+    assert_eq!(vec![-128, -127, -125], read(&[0x00, 0x12]).0);
+    // Differences are clipped to the range of 8-bit samples.
+    assert_eq!(vec![127, 127, 120], read(&[0xFF, 0x0F]).0);
+    assert!(read(&[]).1.is_none());
+  }
+
+  #[test]
+  fn step_adapts() {
+    // Large differences double the step, up to a shift of 3.
+    assert_eq!(vec![0, 7, 21, 49, 105, 113, 113], read(&[0x80, 0x77, 0x77, 0x10]).0);
+    // Repeated samples halve it again.
+    let (values, _) = read(&[0x80, 0x70, 0x01]);
+    assert_eq!(vec![0, 7, 7, 7, 8], values);
+  }
+
+  #[test]
+  fn round_trip() {
+    let values: Vec<i32> =
+      (0..1001).map(|i| ((i as f64 * 0.05).sin() * 100f64) as i32).collect();
+    let bytes = create(&values);
+    assert_eq!(501, bytes.len());
+    let (decoded, _) = read(&bytes);
+    assert_eq!(values.len(), decoded.len());
+    for (value, decoded) in values.iter().zip(decoded.iter()) {
+      assert!((value - decoded).abs() <= 8);
+    }
+    // An even number of samples repeats the last one.
+    assert_eq!(vec![10; 5], read(&create(&[10; 4])).0);
+  }
+}
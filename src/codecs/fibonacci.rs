@@ -0,0 +1,86 @@
+//! Fibonacci-delta
+//!
+//! 4-bit delta coding of signed 8-bit samples, used by Amiga 8SVX files.
+//!
+//! The samples of a channel are compressed together. The first byte is
+//! unused and the second holds the initial value, which is not itself a
+//! sample. Each following nibble, high nibble first, indexes a table of
+//! differences taken from the Fibonacci sequence, which is added to the
+//! previous value to give the next sample. Values wrap around on overflow.
+//!
+//! References
+//! - [8SVX IFF 8-Bit Sampled Voice](http://amigadev.elowar.com/read/ADCD_2.1/Devices_Manual_guide/node02D8.html)
+
+/// Differences from the previous sample given by each nibble.
+const DELTAS: [i32; 16] = [-34, -21, -13, -8, -5, -3, -2, -1, 0, 1, 2, 3, 5, 8, 13, 21];
+
+/// Decodes the compressed samples of a channel to signed 8-bit samples.
+/// Each byte after the first two holds two samples.
+pub fn read(bytes: &[u8]) -> Vec<i8> {
+  if bytes.len() < 2 {
+    return Vec::new();
+  }
+  let mut value = bytes[1] as i8;
+  let mut values = Vec::with_capacity(2 * (bytes.len() - 2));
+  for &byte in bytes[2..].iter() {
+    for &nibble in [byte >> 4, byte & 0xf].iter() {
+      value = value.wrapping_add(DELTAS[nibble as usize] as i8);
+      values.push(value);
+    }
+  }
+  values
+}
+
+/// Compresses the signed 8-bit samples of a channel. The last sample is
+/// repeated when the samples would leave half of the last byte unused.
+pub fn create(values: &[i8]) -> Vec<u8> {
+  let mut value = values.first().map(|&v| v as i32).unwrap_or(0);
+  let mut bytes = Vec::with_capacity(2 + (values.len() + 1) / 2);
+  bytes.push(0);
+  bytes.push(value as u8);
+  for pair in values.chunks(2) {
+    let mut byte = 0u8;
+    for &target in [pair[0], *pair.get(1).unwrap_or(&pair[0])].iter() {
+      // The closest difference is chosen from those that do not overflow.
+      let mut best = 8;
+      for (nibble, &delta) in DELTAS.iter().enumerate() {
+        let next = value + delta;
+        if next >= -128 && next <= 127 &&
+           (next - target as i32).abs() < (value + DELTAS[best] - target as i32).abs() {
+          best = nibble;
+        }
+      }
+      value += DELTAS[best];
+      byte = byte << 4 | best as u8;
+    }
+    bytes.push(byte);
+  }
+  bytes
+}
+
+#[cfg(test)]
+mod coding {
+  use super::*;
+
+  #[test]
+  fn deltas() {
+    assert_eq!(vec![31, 31, 31, -3], read(&[0x00, 0x0A, 0xF8, 0x80]));
+    // Values wrap around on overflow.
+    assert_eq!(vec![-108, -87], read(&[0x00, 0x7F, 0xFF]));
+    assert!(read(&[0x00]).is_empty());
+  }
+
+  #[test]
+  fn round_trip() {
+    let values: Vec<i8> =
+      (0..999).map(|i| ((i as f64 * 0.02).sin() * 120f64) as i8).collect();
+    let bytes = create(&values);
+    assert_eq!(502, bytes.len());
+    let decoded = read(&bytes);
+    assert_eq!(values.len() + 1, decoded.len());
+    for (value, decoded) in values.iter().zip(decoded.iter()) {
+      assert!((*value as i32 - *decoded as i32).abs() <= 1);
+    }
+    assert_eq!(decoded[998], decoded[999]);
+  }
+}
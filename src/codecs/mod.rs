@@ -5,6 +5,9 @@ use sample::*;
 
 mod lpcm;
 mod g711;
+pub mod alac;
+pub mod creative;
+pub mod fibonacci;
 
 /// All supported audio codecs.
 ///
@@ -41,7 +44,19 @@ pub enum Codec {
   /// G.711 8-bit A-law
   G711_ALAW,
   /// G.711 8-bit µ-law
-  G711_ULAW
+  G711_ULAW,
+  /// Apple Lossless of 16-bit samples
+  ALAC_I16,
+  /// Apple Lossless of 20-bit samples
+  ALAC_I20,
+  /// Apple Lossless of 24-bit samples
+  ALAC_I24,
+  /// Apple Lossless of 32-bit samples
+  ALAC_I32,
+  /// Creative 4-bit ADPCM of 8-bit samples
+  CREATIVE_ADPCM,
+  /// Fibonacci-delta 4-bit compression of 8-bit samples
+  FIBONACCI_DELTA
 }
 
 impl Codec {
@@ -50,21 +65,38 @@ impl Codec {
     use Codec::*;
     match *self {
       LPCM_U8     |
-      LPCM_I8     => 8,
+      LPCM_I8     |
+      CREATIVE_ADPCM  |
+      FIBONACCI_DELTA => 8,
       G711_ALAW   |
       G711_ULAW   |
       LPCM_I16_LE |
-      LPCM_I16_BE => 16,
+      LPCM_I16_BE |
+      ALAC_I16    => 16,
+      ALAC_I20    => 20,
       LPCM_I24_LE |
-      LPCM_I24_BE => 24,
+      LPCM_I24_BE |
+      ALAC_I24    => 24,
       LPCM_I32_LE |
       LPCM_I32_BE |
       LPCM_F32_LE |
-      LPCM_F32_BE => 32,
+      LPCM_F32_BE |
+      ALAC_I32    => 32,
       LPCM_F64_LE |
       LPCM_F64_BE => 64
     }
   }
+
+  /// Returns the number of bytes used to store a single encoded sample. The
+  /// samples of compressed codecs are given the size of a decoded sample.
+  pub fn sample_size(&self) -> usize {
+    use Codec::*;
+    match *self {
+      G711_ALAW   |
+      G711_ULAW   => 1,
+      c @ _       => (c.bit_depth() + 7) / 8
+    }
+  }
 }
 
 impl fmt::Display for Codec {
@@ -84,7 +116,13 @@ impl fmt::Display for Codec {
       &LPCM_F64_LE => fmt.write_str("64-bit little endian floating-point PCM"),
       &LPCM_F64_BE => fmt.write_str("64-bit big endian floating-point PCM"),
       &G711_ALAW   => fmt.write_str("G.711 8-bit A-law"),
-      &G711_ULAW   => fmt.write_str("G.711 8-bit µ-law")
+      &G711_ULAW   => fmt.write_str("G.711 8-bit µ-law"),
+      &ALAC_I16    => fmt.write_str("Apple Lossless 16-bit"),
+      &ALAC_I20    => fmt.write_str("Apple Lossless 20-bit"),
+      &ALAC_I24    => fmt.write_str("Apple Lossless 24-bit"),
+      &ALAC_I32    => fmt.write_str("Apple Lossless 32-bit"),
+      &CREATIVE_ADPCM  => fmt.write_str("Creative 4-bit ADPCM"),
+      &FIBONACCI_DELTA => fmt.write_str("Fibonacci-delta 4-bit")
     }
   }
 }
@@ -110,6 +148,20 @@ pub fn decode(bytes: &[u8], codec: Codec) -> AudioResult<Vec<Sample>> {
     G711_ALAW |
     G711_ULAW => {
       g711::read(bytes, codec)
+    },
+    ALAC_I16 |
+    ALAC_I20 |
+    ALAC_I24 |
+    ALAC_I32 => {
+      Err(AudioError::Unsupported(
+        format!("{} is coded in packets, which are decoded by the container", codec)
+      ))
+    },
+    CREATIVE_ADPCM  |
+    FIBONACCI_DELTA => {
+      Err(AudioError::Unsupported(
+        format!("{} is coded in blocks, which are decoded by the container", codec)
+      ))
     }
   }
 }
@@ -135,6 +187,20 @@ pub fn encode(audio: &AudioBuffer, codec: Codec) -> AudioResult<Vec<u8>> {
     G711_ALAW |
     G711_ULAW => {
       g711::create(audio, codec)
+    },
+    ALAC_I16 |
+    ALAC_I20 |
+    ALAC_I24 |
+    ALAC_I32 => {
+      Err(AudioError::Unsupported(
+        format!("{} is coded in packets, which are encoded by the container", codec)
+      ))
+    },
+    CREATIVE_ADPCM  |
+    FIBONACCI_DELTA => {
+      Err(AudioError::Unsupported(
+        format!("{} is coded in blocks, which are encoded by the container", codec)
+      ))
     }
   }
 }
@@ -160,7 +226,13 @@ mod formatting {
         "64-bit little endian floating-point PCM",
         "64-bit big endian floating-point PCM",
         "G.711 8-bit A-law",
-        "G.711 8-bit µ-law"
+        "G.711 8-bit µ-law",
+        "Apple Lossless 16-bit",
+        "Apple Lossless 20-bit",
+        "Apple Lossless 24-bit",
+        "Apple Lossless 32-bit",
+        "Creative 4-bit ADPCM",
+        "Fibonacci-delta 4-bit"
       ];
     let codecs =
       vec![
@@ -177,7 +249,13 @@ mod formatting {
         LPCM_F64_LE,
         LPCM_F64_BE,
         G711_ALAW,
-        G711_ULAW
+        G711_ULAW,
+        ALAC_I16,
+        ALAC_I20,
+        ALAC_I24,
+        ALAC_I32,
+        CREATIVE_ADPCM,
+        FIBONACCI_DELTA
       ];
     for (expected_str, codec) in formatted_strs.iter().zip(codecs.iter()) {
       assert_eq!(*expected_str, format!("{}", codec));
@@ -202,7 +280,13 @@ mod formatting {
         "LPCM_F64_LE",
         "LPCM_F64_BE",
         "G711_ALAW",
-        "G711_ULAW"
+        "G711_ULAW",
+        "ALAC_I16",
+        "ALAC_I20",
+        "ALAC_I24",
+        "ALAC_I32",
+        "CREATIVE_ADPCM",
+        "FIBONACCI_DELTA"
       ];
     let codecs =
       vec![
@@ -219,7 +303,13 @@ mod formatting {
         LPCM_F64_LE,
         LPCM_F64_BE,
         G711_ALAW,
-        G711_ULAW
+        G711_ULAW,
+        ALAC_I16,
+        ALAC_I20,
+        ALAC_I24,
+        ALAC_I32,
+        CREATIVE_ADPCM,
+        FIBONACCI_DELTA
       ];
     for (expected_str, codec) in debug_strs.iter().zip(codecs.iter()) {
       assert_eq!(*expected_str, format!("{:?}", codec));
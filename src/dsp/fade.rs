@@ -0,0 +1,315 @@
+//! Gain and Fades
+//!
+//! Gain is given in decibels, where -6.02 dB halves the amplitude. Fades
+//! change the gain of a range of frames following a curve, rising from
+//! silence to full level for a fade in, and falling from full level to
+//! silence for a fade out. Frames before a fade in are silenced, as are
+//! frames after a fade out.
+//!
+//! A crossfade joins two pieces of audio by fading out the end of the first
+//! while fading in the start of the second over the same frames. A linear
+//! crossfade keeps the amplitude of correlated audio constant, while an
+//! equal-power crossfade keeps the power of uncorrelated audio constant.
+//!
+//! References
+//! - [Fade (audio engineering)](https://en.wikipedia.org/wiki/Fade_(audio_engineering))
+
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::time::Duration;
+use buffer::AudioBuffer;
+use error::*;
+use sample::Sample;
+
+/// Range of a logarithmic fade, in dB.
+const LOGARITHMIC_RANGE: f64 = 60.0;
+
+/// Shape of the gain of a fade over time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FadeCurve {
+  /// Amplitude changes at a constant rate
+  Linear,
+  /// Quarter of a sine, keeping the power of a crossfade constant
+  EqualPower,
+  /// Gain in dB changes at a constant rate over 60 dB
+  Logarithmic,
+  /// Half of a cosine, starting and ending slowly
+  SCurve
+}
+
+impl FadeCurve {
+  /// Returns the gain of a fade in at a position from 0 to 1 through the
+  /// fade. The gain of a fade out at a position is the gain of a fade in at
+  /// the remaining distance.
+  pub fn gain(&self, position: f64) -> f64 {
+    if position <= 0f64 {
+      return 0f64;
+    }
+    if position >= 1f64 {
+      return 1f64;
+    }
+    match *self {
+      FadeCurve::Linear      => position,
+      FadeCurve::EqualPower  => (position * PI / 2f64).sin(),
+      FadeCurve::Logarithmic => db_to_gain((position - 1f64) * LOGARITHMIC_RANGE),
+      FadeCurve::SCurve      => (1f64 - (position * PI).cos()) / 2f64
+    }
+  }
+}
+
+impl Default for FadeCurve {
+  fn default() -> Self {
+    FadeCurve::EqualPower
+  }
+}
+
+/// Fades interleaved frames in or out as they are streamed.
+#[derive(Clone, Debug)]
+pub struct Fade {
+  channels:     usize,
+  fade_in:      bool,
+  curve:        FadeCurve,
+  /// Frames of the audio covered by the fade
+  start:        usize,
+  length:       usize,
+  /// Number of frames processed
+  position:     usize
+}
+
+impl Fade {
+  /// Creates a fade in over the given frames of audio with the given number
+  /// of channels.
+  pub fn fade_in(channels: u32, frames: Range<usize>, curve: FadeCurve) -> AudioResult<Fade> {
+    Fade::new(channels, true, frames, curve)
+  }
+
+  /// Creates a fade out over the given frames of audio with the given number
+  /// of channels.
+  pub fn fade_out(channels: u32, frames: Range<usize>, curve: FadeCurve) -> AudioResult<Fade> {
+    Fade::new(channels, false, frames, curve)
+  }
+
+  fn new(channels: u32, fade_in: bool, frames: Range<usize>, curve: FadeCurve) -> AudioResult<Fade> {
+    if channels == 0 {
+      return Err(AudioError::Format(
+        "Cannot fade audio without channels".to_string()
+      ));
+    }
+    if frames.end < frames.start {
+      return Err(AudioError::Format(
+        format!("Cannot fade frames {} to {}", frames.start, frames.end)
+      ));
+    }
+    Ok(Fade {
+      channels:     channels as usize,
+      fade_in:      fade_in,
+      curve:        curve,
+      start:        frames.start,
+      length:       frames.end - frames.start,
+      position:     0
+    })
+  }
+
+  /// Returns the gain of a frame of the audio.
+  pub fn gain(&self, frame: usize) -> f64 {
+    let before = frame < self.start;
+    let after  = frame >= self.start + self.length;
+    match (self.fade_in, before, after) {
+      (true,  true, _) => 0f64,
+      (true,  _, true) => 1f64,
+      (false, true, _) => 1f64,
+      (false, _, true) => 0f64,
+      // A fade in starts from silence, and a fade out ends in silence.
+      (true,  _, _) =>
+        self.curve.gain((frame - self.start) as f64 / self.length as f64),
+      (false, _, _) =>
+        self.curve.gain((self.start + self.length - 1 - frame) as f64 / self.length as f64)
+    }
+  }
+
+  /// Fades a block of interleaved frames. Samples of an incomplete frame at
+  /// the end of the block are ignored.
+  pub fn process(&mut self, samples: &[Sample]) -> Vec<Sample> {
+    let frames = samples.len() / self.channels;
+    let mut output = Vec::with_capacity(frames * self.channels);
+    for frame in samples[.. frames * self.channels].chunks(self.channels) {
+      let gain = self.gain(self.position);
+      output.extend(frame.iter().map(|&sample| (sample as f64 * gain) as Sample));
+      self.position += 1;
+    }
+    output
+  }
+
+  /// Clears all state, so the `Fade` can be used for new audio.
+  pub fn reset(&mut self) {
+    self.position = 0;
+  }
+}
+
+/// Returns the linear gain of a gain in dB.
+#[inline]
+pub fn db_to_gain(db: f64) -> f64 {
+  10f64.powf(db / 20f64)
+}
+
+/// Changes the gain of samples by the given dB.
+pub fn gain(samples: &mut [Sample], db: f64) {
+  let gain = db_to_gain(db);
+  for sample in samples.iter_mut() {
+    *sample = (*sample as f64 * gain) as Sample;
+  }
+}
+
+/// Returns the number of frames lasting a duration at a sample rate.
+pub fn frames_in(duration: Duration, sample_rate: u32) -> usize {
+  let nanos = duration.as_secs() as f64 * 1e9 + duration.subsec_nanos() as f64;
+  (nanos * sample_rate as f64 / 1e9).round() as usize
+}
+
+/// Fades an `AudioBuffer` in or out over a range of frames.
+pub fn fade(audio: &mut AudioBuffer, fade_in: bool, frames: Range<usize>, curve: FadeCurve) -> AudioResult<()> {
+  let num_frames = audio.samples.len() / audio.channels.max(1) as usize;
+  if frames.end > num_frames {
+    return Err(AudioError::Format(
+      format!("Cannot fade frames {} to {} of audio with {} frames",
+              frames.start, frames.end, num_frames)
+    ));
+  }
+  let mut fade = try!(Fade::new(audio.channels, fade_in, frames, curve));
+  audio.samples = fade.process(&audio.samples);
+  Ok(())
+}
+
+/// Joins two `AudioBuffer`s, fading out the end of the first while fading in
+/// the start of the second over the given number of frames. A crossfade
+/// longer than either buffer is shortened to fit. The joined audio keeps the
+/// metadata of the first buffer.
+pub fn crossfade(first: &AudioBuffer, second: &AudioBuffer, frames: usize, curve: FadeCurve) -> AudioResult<AudioBuffer> {
+  if first.channels != second.channels || first.sample_rate != second.sample_rate {
+    return Err(AudioError::Format(
+      format!("Cannot join audio with {} channels at {} Hz to audio with {} channels at {} Hz",
+              first.channels, first.sample_rate, second.channels, second.sample_rate)
+    ));
+  }
+  if first.channels == 0 {
+    return Err(AudioError::Format(
+      "Cannot join audio without channels".to_string()
+    ));
+  }
+  let channels = first.channels as usize;
+  let frames = frames.min(first.samples.len() / channels)
+                     .min(second.samples.len() / channels);
+  let overlap = first.samples.len() - frames * channels;
+  let mut samples = Vec::with_capacity(first.samples.len() + second.samples.len() - frames * channels);
+  samples.extend_from_slice(&first.samples[.. overlap]);
+  // Each curve is sampled at the center of its frames, so a linear crossfade
+  // sums to full level.
+  for k in 0..frames {
+    let position = (k as f64 + 0.5) / frames as f64;
+    let fade_out = curve.gain(1f64 - position);
+    let fade_in  = curve.gain(position);
+    for c in 0..channels {
+      let a = first.samples[overlap + k * channels + c] as f64;
+      let b = second.samples[k * channels + c] as f64;
+      samples.push((a * fade_out + b * fade_in) as Sample);
+    }
+  }
+  samples.extend_from_slice(&second.samples[frames * channels ..]);
+  Ok(AudioBuffer {
+    sample_rate: first.sample_rate,
+    channels:    first.channels,
+    samples:     samples,
+    metadata:    first.metadata.clone()
+  })
+}
+
+#[cfg(test)]
+mod fading {
+  use std::time::Duration;
+  use ::buffer::AudioBuffer;
+  use super::*;
+
+  #[test]
+  fn curves() {
+    for curve in [FadeCurve::Linear, FadeCurve::EqualPower,
+                  FadeCurve::Logarithmic, FadeCurve::SCurve].iter() {
+      assert_eq!(0f64, curve.gain(0f64));
+      assert_eq!(1f64, curve.gain(1f64));
+      // Every curve rises steadily.
+      let gains: Vec<f64> = (0..101).map(|i| curve.gain(i as f64 / 100f64)).collect();
+      assert!(gains.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+    assert!((0.5 - FadeCurve::Linear.gain(0.5)).abs() < 1e-9);
+    assert!((0.5f64.sqrt() - FadeCurve::EqualPower.gain(0.5)).abs() < 1e-9);
+    assert!((db_to_gain(-30f64) - FadeCurve::Logarithmic.gain(0.5)).abs() < 1e-9);
+    assert!((0.5 - FadeCurve::SCurve.gain(0.5)).abs() < 1e-9);
+  }
+
+  #[test]
+  fn gains() {
+    let mut audio = AudioBuffer::from_samples(44100, 1, vec![1f32, -0.5]);
+    audio.apply_gain(-6.0206);
+    assert!((0.5 - audio.samples[0]).abs() < 1e-4);
+    assert!((-0.25 - audio.samples[1]).abs() < 1e-4);
+    assert_eq!(44100, frames_in(Duration::from_secs(1), 44100));
+    assert_eq!(4410,  frames_in(Duration::from_millis(100), 44100));
+  }
+
+  #[test]
+  fn fades() {
+    let mut audio = AudioBuffer::from_samples(10, 2, vec![1f32; 40]);
+    audio.fade_in(2..6, FadeCurve::Linear).unwrap();
+    let left: Vec<f32> = audio.samples.iter().step_by(2).cloned().collect();
+    assert_eq!(vec![0f32, 0f32, 0f32, 0.25, 0.5, 0.75, 1f32, 1f32,
+                    1f32, 1f32, 1f32, 1f32, 1f32, 1f32, 1f32, 1f32,
+                    1f32, 1f32, 1f32, 1f32], left);
+    assert_eq!(audio.samples[6], audio.samples[7]);
+    // The fade out lasts the last 0.4 s of the audio, ending in silence.
+    audio.fade_out_for(Duration::from_millis(400), FadeCurve::Linear).unwrap();
+    let left: Vec<f32> = audio.samples.iter().step_by(2).cloned().collect();
+    assert_eq!(vec![1f32, 0.75, 0.5, 0.25, 0f32], left[15..].to_vec());
+    assert!(audio.fade_in(18..21, FadeCurve::Linear).is_err());
+    // A fade longer than the audio covers all of it.
+    let mut audio = AudioBuffer::from_samples(10, 1, vec![1f32; 4]);
+    audio.fade_in_for(Duration::from_secs(10), FadeCurve::SCurve).unwrap();
+    assert_eq!(0f32, audio.samples[0]);
+    assert!(audio.samples[3] < 1f32);
+  }
+
+  #[test]
+  fn streaming() {
+    let audio = AudioBuffer::from_samples(100, 2, vec![0.5; 400]);
+    let mut faded = audio.clone();
+    faded.fade_out(50..150, FadeCurve::EqualPower).unwrap();
+    let mut fade = Fade::fade_out(2, 50..150, FadeCurve::EqualPower).unwrap();
+    let mut streamed = Vec::new();
+    for block in audio.samples.chunks(14) {
+      streamed.extend(fade.process(block));
+    }
+    assert_eq!(faded.samples, streamed);
+    fade.reset();
+    assert_eq!(vec![0.5f32, 0.5], fade.process(&[0.5, 0.5]));
+    assert!(Fade::fade_in(0, 0..1, FadeCurve::Linear).is_err());
+  }
+
+  #[test]
+  fn crossfades() {
+    let first  = AudioBuffer::from_samples(1000, 2, vec![0.5; 2000]);
+    let second = AudioBuffer::from_samples(1000, 2, vec![0.5; 1000]);
+    // A linear crossfade of the same level keeps that level throughout.
+    let joined = first.crossfade(&second, Duration::from_millis(100), FadeCurve::Linear).unwrap();
+    assert_eq!(2, joined.channels);
+    assert_eq!(2000 + 1000 - 200, joined.samples.len());
+    assert!(joined.samples.iter().all(|&s| (0.5 - s).abs() < 1e-6));
+    // An equal-power crossfade raises the level of correlated audio by up
+    // to 3 dB in the middle.
+    let joined = first.crossfade(&second, Duration::from_millis(100), FadeCurve::EqualPower).unwrap();
+    let middle = joined.samples[(1000 - 50) * 2];
+    assert!((0.5 * 2f32.sqrt() - middle).abs() < 1e-2);
+    // The crossfade is shortened to fit the second buffer.
+    let joined = first.crossfade(&second, Duration::from_secs(5), FadeCurve::Linear).unwrap();
+    assert_eq!(2000, joined.samples.len());
+    let mono = AudioBuffer::from_samples(1000, 1, vec![0.5; 1000]);
+    assert!(first.crossfade(&mono, Duration::from_millis(100), FadeCurve::Linear).is_err());
+  }
+}
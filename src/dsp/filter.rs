@@ -0,0 +1,411 @@
+//! Biquad Filters
+//!
+//! Filters are built from second-order sections, or biquads, designed as in
+//! the Audio EQ Cookbook by transforming analog prototypes with the bilinear
+//! transform, warped so the frequency of the filter is kept exactly. A
+//! `Filter` applies a cascade of biquads to each channel of interleaved
+//! frames, holding the state of each channel between blocks.
+//!
+//! Butterworth filters of any order are a cascade of biquads, with a single
+//! first-order section when the order is odd. Linkwitz-Riley filters are two
+//! Butterworth filters of half the order in cascade, so the low pass and high
+//! pass are both -6 dB at the crossover frequency and sum to a flat response.
+//! When half the order is odd, as for LR2 and LR6, the outputs are in
+//! opposite phase, so the polarity of the high pass is inverted.
+//!
+//! References
+//! - [Audio EQ Cookbook](https://www.w3.org/TR/audio-eq-cookbook/)
+//! - [Butterworth Filter](https://en.wikipedia.org/wiki/Butterworth_filter)
+//! - [Linkwitz-Riley Filter](https://en.wikipedia.org/wiki/Linkwitz%E2%80%93Riley_filter)
+
+use std::f64::consts::PI;
+use buffer::AudioBuffer;
+use error::*;
+use sample::Sample;
+
+/// Responses of a biquad from the Audio EQ Cookbook.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterType {
+  LowPass,
+  HighPass,
+  /// Band pass with a gain of 0 dB at its center
+  BandPass,
+  Notch,
+  /// All pass, changing only the phase around its center
+  AllPass,
+  /// Peaking EQ with the given gain in dB at its center
+  Peaking(f64),
+  /// Low shelf with the given gain in dB below its frequency
+  LowShelf(f64),
+  /// High shelf with the given gain in dB above its frequency
+  HighShelf(f64)
+}
+
+/// Coefficients of a second-order IIR filter, normalized so the first
+/// feedback coefficient is 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Biquad {
+  pub b0: f64,
+  pub b1: f64,
+  pub b2: f64,
+  pub a1: f64,
+  pub a2: f64
+}
+
+impl Biquad {
+  /// Designs a biquad of the given type at a frequency in Hz. The quality
+  /// factor sets the bandwidth of band filters and the slope of shelves,
+  /// where 0.7071 gives a low or high pass with no peak.
+  pub fn new(filter_type: FilterType, sample_rate: u32, frequency: f64, q: f64) -> AudioResult<Biquad> {
+    try!(check_frequency(sample_rate, frequency));
+    if !(q > 0f64) {
+      return Err(AudioError::Format(
+        format!("Cannot design a filter with a quality factor of {}", q)
+      ));
+    }
+    let w0    = 2f64 * PI * frequency / sample_rate as f64;
+    let cos   = w0.cos();
+    let alpha = w0.sin() / (2f64 * q);
+    let (b, a) =
+      match filter_type {
+        FilterType::LowPass =>
+          ([(1f64 - cos) / 2f64, 1f64 - cos, (1f64 - cos) / 2f64],
+           [1f64 + alpha, -2f64 * cos, 1f64 - alpha]),
+        FilterType::HighPass =>
+          ([(1f64 + cos) / 2f64, -(1f64 + cos), (1f64 + cos) / 2f64],
+           [1f64 + alpha, -2f64 * cos, 1f64 - alpha]),
+        FilterType::BandPass =>
+          ([alpha, 0f64, -alpha],
+           [1f64 + alpha, -2f64 * cos, 1f64 - alpha]),
+        FilterType::Notch =>
+          ([1f64, -2f64 * cos, 1f64],
+           [1f64 + alpha, -2f64 * cos, 1f64 - alpha]),
+        FilterType::AllPass =>
+          ([1f64 - alpha, -2f64 * cos, 1f64 + alpha],
+           [1f64 + alpha, -2f64 * cos, 1f64 - alpha]),
+        FilterType::Peaking(gain) => {
+          let a = 10f64.powf(gain / 40f64);
+          ([1f64 + alpha * a, -2f64 * cos, 1f64 - alpha * a],
+           [1f64 + alpha / a, -2f64 * cos, 1f64 - alpha / a])
+        },
+        FilterType::LowShelf(gain) => {
+          let a = 10f64.powf(gain / 40f64);
+          let s = 2f64 * a.sqrt() * alpha;
+          ([a * ((a + 1f64) - (a - 1f64) * cos + s),
+            2f64 * a * ((a - 1f64) - (a + 1f64) * cos),
+            a * ((a + 1f64) - (a - 1f64) * cos - s)],
+           [(a + 1f64) + (a - 1f64) * cos + s,
+            -2f64 * ((a - 1f64) + (a + 1f64) * cos),
+            (a + 1f64) + (a - 1f64) * cos - s])
+        },
+        FilterType::HighShelf(gain) => {
+          let a = 10f64.powf(gain / 40f64);
+          let s = 2f64 * a.sqrt() * alpha;
+          ([a * ((a + 1f64) + (a - 1f64) * cos + s),
+            -2f64 * a * ((a - 1f64) + (a + 1f64) * cos),
+            a * ((a + 1f64) + (a - 1f64) * cos - s)],
+           [(a + 1f64) - (a - 1f64) * cos + s,
+            2f64 * ((a - 1f64) - (a + 1f64) * cos),
+            (a + 1f64) - (a - 1f64) * cos - s])
+        }
+      };
+    Ok(Biquad::from_coefficients(b, a))
+  }
+
+  /// Creates a biquad from its feedforward and feedback coefficients, which
+  /// are normalized by the first feedback coefficient.
+  pub fn from_coefficients(b: [f64; 3], a: [f64; 3]) -> Biquad {
+    Biquad {
+      b0: b[0] / a[0],
+      b1: b[1] / a[0],
+      b2: b[2] / a[0],
+      a1: a[1] / a[0],
+      a2: a[2] / a[0]
+    }
+  }
+
+  /// Designs a Butterworth low or high pass of any order as a cascade of
+  /// biquads.
+  pub fn butterworth(filter_type: FilterType, sample_rate: u32, frequency: f64, order: u32) -> AudioResult<Vec<Biquad>> {
+    try!(check_frequency(sample_rate, frequency));
+    if order == 0 {
+      return Err(AudioError::Format(
+        "Cannot design a filter of order 0".to_string()
+      ));
+    }
+    let high_pass =
+      match filter_type {
+        FilterType::LowPass  => false,
+        FilterType::HighPass => true,
+        t @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("Butterworth filters cannot be of type {:?}", t)
+          ))
+      };
+    // Each pair of poles is a biquad whose quality factor depends on the
+    // angle of the poles from the negative real axis.
+    let mut stages = Vec::with_capacity(order as usize / 2 + 1);
+    for k in 1 .. order / 2 + 1 {
+      let angle = PI * (2 * k - 1 + order % 2) as f64 / (2 * order) as f64;
+      stages.push(try!(Biquad::new(filter_type, sample_rate, frequency,
+                                   1f64 / (2f64 * angle.cos()))));
+    }
+    // An odd order leaves a single real pole.
+    if order % 2 == 1 {
+      let k = (PI * frequency / sample_rate as f64).tan();
+      stages.push(
+        if high_pass {
+          Biquad::from_coefficients([1f64, -1f64, 0f64], [1f64 + k, k - 1f64, 0f64])
+        } else {
+          Biquad::from_coefficients([k, k, 0f64], [1f64 + k, k - 1f64, 0f64])
+        }
+      );
+    }
+    Ok(stages)
+  }
+
+  /// Designs a Linkwitz-Riley low or high pass of any even order as a
+  /// cascade of biquads. The high pass is inverted when half the order is
+  /// odd, so the low and high pass of the same order sum to a flat response.
+  pub fn linkwitz_riley(filter_type: FilterType, sample_rate: u32, frequency: f64, order: u32) -> AudioResult<Vec<Biquad>> {
+    if order == 0 || order % 2 == 1 {
+      return Err(AudioError::Format(
+        format!("Linkwitz-Riley filters must have an even order, not {}", order)
+      ));
+    }
+    let butterworth = try!(Biquad::butterworth(filter_type, sample_rate, frequency, order / 2));
+    let mut stages: Vec<Biquad> = butterworth.iter().chain(butterworth.iter()).cloned().collect();
+    if filter_type == FilterType::HighPass && (order / 2) % 2 == 1 {
+      let stage = &mut stages[0];
+      stage.b0 = -stage.b0;
+      stage.b1 = -stage.b1;
+      stage.b2 = -stage.b2;
+    }
+    Ok(stages)
+  }
+
+  /// Returns the gain of the biquad at a frequency in Hz as a linear value.
+  pub fn magnitude(&self, frequency: f64, sample_rate: u32) -> f64 {
+    // Evaluates the transfer function at z = e^jw.
+    let w = 2f64 * PI * frequency / sample_rate as f64;
+    let (cos1, sin1) = (w.cos(), w.sin());
+    let (cos2, sin2) = ((2f64 * w).cos(), (2f64 * w).sin());
+    let num_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+    let num_im = -(self.b1 * sin1 + self.b2 * sin2);
+    let den_re = 1f64 + self.a1 * cos1 + self.a2 * cos2;
+    let den_im = -(self.a1 * sin1 + self.a2 * sin2);
+    ((num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im)).sqrt()
+  }
+}
+
+/// Applies a cascade of biquads to each channel of interleaved frames as
+/// they are streamed.
+#[derive(Clone, Debug)]
+pub struct Filter {
+  channels:     usize,
+  stages:       Vec<Biquad>,
+  /// State of each stage in transposed direct form II, for each channel in
+  /// turn
+  state:        Vec<[f64; 2]>
+}
+
+impl Filter {
+  /// Creates a `Filter` applying the biquads in order to audio with the
+  /// given number of channels.
+  pub fn new(channels: u32, stages: Vec<Biquad>) -> AudioResult<Filter> {
+    if channels == 0 {
+      return Err(AudioError::Format(
+        "Cannot filter audio without channels".to_string()
+      ));
+    }
+    let state = vec![[0f64; 2]; channels as usize * stages.len()];
+    Ok(Filter {
+      channels:     channels as usize,
+      stages:       stages,
+      state:        state
+    })
+  }
+
+  /// Filters a block of interleaved frames. Samples of an incomplete frame at
+  /// the end of the block are ignored.
+  pub fn process(&mut self, samples: &[Sample]) -> Vec<Sample> {
+    let frames = samples.len() / self.channels;
+    let mut output = Vec::with_capacity(frames * self.channels);
+    for (i, &sample) in samples[.. frames * self.channels].iter().enumerate() {
+      output.push(self.filter(i % self.channels, sample as f64) as Sample);
+    }
+    output
+  }
+
+  /// Filters a sample of a channel through every stage.
+  #[inline]
+  fn filter(&mut self, channel: usize, sample: f64) -> f64 {
+    let first = channel * self.stages.len();
+    let mut x = sample;
+    for (stage, z) in self.stages.iter().zip(self.state[first ..].iter_mut()) {
+      let y = stage.b0 * x + z[0];
+      z[0] = stage.b1 * x - stage.a1 * y + z[1];
+      z[1] = stage.b2 * x - stage.a2 * y;
+      x = y;
+    }
+    x
+  }
+
+  /// Filters the samples of a single frame in place, keeping the precision
+  /// of the samples.
+  pub fn process_frame(&mut self, frame: &mut [f64]) {
+    for (c, sample) in frame.iter_mut().enumerate().take(self.channels) {
+      *sample = self.filter(c, *sample);
+    }
+  }
+
+  /// Returns the biquads of the filter, in the order they are applied.
+  pub fn stages(&self) -> &[Biquad] {
+    &self.stages
+  }
+
+  /// Returns the gain of the filter at a frequency in Hz as a linear value.
+  pub fn magnitude(&self, frequency: f64, sample_rate: u32) -> f64 {
+    self.stages.iter().map(|stage| stage.magnitude(frequency, sample_rate)).product()
+  }
+
+  /// Clears all state, so the `Filter` can be used for new audio.
+  pub fn reset(&mut self) {
+    for z in self.state.iter_mut() {
+      *z = [0f64; 2];
+    }
+  }
+}
+
+/// Applies a cascade of biquads to each channel of an `AudioBuffer`.
+pub fn filter(audio: &mut AudioBuffer, stages: &[Biquad]) -> AudioResult<()> {
+  let mut filter = try!(Filter::new(audio.channels, stages.to_vec()));
+  audio.samples = filter.process(&audio.samples);
+  Ok(())
+}
+
+// Private functions
+
+/// Checks that a frequency lies between 0 Hz and the Nyquist frequency.
+fn check_frequency(sample_rate: u32, frequency: f64) -> AudioResult<()> {
+  if frequency > 0f64 && frequency < sample_rate as f64 / 2f64 {
+    Ok(())
+  } else {
+    Err(AudioError::Format(
+      format!("Cannot design a filter at {} Hz for audio at {} Hz", frequency, sample_rate)
+    ))
+  }
+}
+
+#[cfg(test)]
+mod filtering {
+  use std::f64::consts::PI;
+  use ::buffer::AudioBuffer;
+  use super::*;
+
+  fn db(gain: f64) -> f64 {
+    20f64 * gain.log10()
+  }
+
+  /// Returns the RMS of a filtered sine after the filter has settled.
+  fn sine_rms(stages: &[Biquad], frequency: f64) -> f64 {
+    let samples: Vec<f32> =
+      (0..48000).map(|i| (2f64 * PI * frequency * i as f64 / 48000f64).sin() as f32).collect();
+    let mut audio = AudioBuffer::from_samples(48000, 1, samples);
+    audio.filter(stages).unwrap();
+    let settled = &audio.samples[24000..];
+    (settled.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / settled.len() as f64).sqrt()
+      * 2f64.sqrt()
+  }
+
+  #[test]
+  fn cookbook() {
+    let low = Biquad::new(FilterType::LowPass, 48000, 1000f64, 0.7071).unwrap();
+    assert!((-3.01 - db(low.magnitude(1000f64, 48000))).abs() < 0.01);
+    assert!(db(low.magnitude(10f64, 48000)).abs() < 0.01);
+    assert!(db(low.magnitude(10000f64, 48000)) < -25f64);
+    let high = Biquad::new(FilterType::HighPass, 48000, 1000f64, 0.7071).unwrap();
+    assert!(db(high.magnitude(20000f64, 48000)).abs() < 0.01);
+    let band = Biquad::new(FilterType::BandPass, 48000, 1000f64, 2f64).unwrap();
+    assert!(db(band.magnitude(1000f64, 48000)).abs() < 0.01);
+    let notch = Biquad::new(FilterType::Notch, 48000, 1000f64, 2f64).unwrap();
+    assert!(notch.magnitude(1000f64, 48000) < 1e-6);
+    let all = Biquad::new(FilterType::AllPass, 48000, 1000f64, 2f64).unwrap();
+    assert!(db(all.magnitude(700f64, 48000)).abs() < 0.01);
+    let peak = Biquad::new(FilterType::Peaking(6f64), 48000, 1000f64, 1f64).unwrap();
+    assert!((6f64 - db(peak.magnitude(1000f64, 48000))).abs() < 0.01);
+    let shelf = Biquad::new(FilterType::LowShelf(-12f64), 48000, 200f64, 0.7071).unwrap();
+    assert!((-12f64 - db(shelf.magnitude(10f64, 48000))).abs() < 0.1);
+    assert!((-6f64 - db(shelf.magnitude(200f64, 48000))).abs() < 0.01);
+    let shelf = Biquad::new(FilterType::HighShelf(4f64), 48000, 5000f64, 0.7071).unwrap();
+    assert!((4f64 - db(shelf.magnitude(23000f64, 48000))).abs() < 0.1);
+    assert!(Biquad::new(FilterType::LowPass, 48000, 24000f64, 0.7071).is_err());
+    assert!(Biquad::new(FilterType::LowPass, 48000, 1000f64, 0f64).is_err());
+  }
+
+  #[test]
+  fn cascades() {
+    for order in 1..9 {
+      let low = Biquad::butterworth(FilterType::LowPass, 48000, 2000f64, order).unwrap();
+      assert_eq!((order as usize + 1) / 2, low.len());
+      let filter = Filter::new(1, low).unwrap();
+      assert!((-3.01 - db(filter.magnitude(2000f64, 48000))).abs() < 0.01);
+      // An octave above the cutoff, the gain falls by about 6 dB per order.
+      let octave = db(filter.magnitude(4000f64, 48000));
+      assert!(octave < -6f64 * order as f64 + 1f64);
+    }
+    for order in [2, 4, 8].iter() {
+      let low  = Biquad::linkwitz_riley(FilterType::LowPass,  48000, 2000f64, *order).unwrap();
+      let high = Biquad::linkwitz_riley(FilterType::HighPass, 48000, 2000f64, *order).unwrap();
+      let low  = Filter::new(1, low).unwrap();
+      let high = Filter::new(1, high).unwrap();
+      assert!((-6.02 - db(low.magnitude(2000f64, 48000))).abs() < 0.01);
+      assert!((-6.02 - db(high.magnitude(2000f64, 48000))).abs() < 0.01);
+    }
+    // Linkwitz-Riley crossovers sum to a flat response.
+    for &order in [2, 4, 6, 8].iter() {
+      let low  = Biquad::linkwitz_riley(FilterType::LowPass,  48000, 2000f64, order).unwrap();
+      let high = Biquad::linkwitz_riley(FilterType::HighPass, 48000, 2000f64, order).unwrap();
+      let mut impulse = vec![0f32; 4096];
+      impulse[0] = 1f32;
+      let mut sum = Filter::new(1, low).unwrap().process(&impulse);
+      for (s, h) in sum.iter_mut().zip(Filter::new(1, high).unwrap().process(&impulse)) {
+        *s += h;
+      }
+      // The sum is an all pass, so its impulse response keeps the energy of
+      // the impulse.
+      let energy: f64 = sum.iter().map(|&s| (s as f64) * (s as f64)).sum();
+      assert!((1f64 - energy).abs() < 1e-3);
+    }
+    assert!(Biquad::linkwitz_riley(FilterType::LowPass, 48000, 2000f64, 3).is_err());
+    assert!(Biquad::butterworth(FilterType::Notch, 48000, 2000f64, 2).is_err());
+    assert!(Biquad::butterworth(FilterType::LowPass, 48000, 2000f64, 0).is_err());
+  }
+
+  #[test]
+  fn buffers() {
+    let low = Biquad::butterworth(FilterType::LowPass, 48000, 1000f64, 4).unwrap();
+    assert!((1f64 - sine_rms(&low, 100f64)).abs() < 0.01);
+    assert!(sine_rms(&low, 8000f64) < 1e-3);
+  }
+
+  #[test]
+  fn streaming() {
+    // Each channel is filtered separately, and blocks give the same samples
+    // as filtering the audio at once.
+    let samples: Vec<f32> = (0..2000).map(|i| if i % 2 == 0 { 1f32 } else { 0f32 }).collect();
+    let stages = vec![Biquad::new(FilterType::Peaking(3f64), 44100, 500f64, 1f64).unwrap()];
+    let mut audio = AudioBuffer::from_samples(44100, 2, samples.clone());
+    audio.filter(&stages).unwrap();
+    assert!(audio.samples.iter().skip(1).step_by(2).all(|&s| s == 0f32));
+    let mut filter = Filter::new(2, stages).unwrap();
+    let mut streamed = Vec::new();
+    for block in samples.chunks(300) {
+      streamed.extend(filter.process(block));
+    }
+    assert_eq!(audio.samples, streamed);
+    filter.reset();
+    assert_eq!(audio.samples[0..2].to_vec(), filter.process(&samples[0..2]));
+    assert!(Filter::new(0, Vec::new()).is_err());
+  }
+}
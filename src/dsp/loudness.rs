@@ -0,0 +1,606 @@
+//! Loudness Measurement
+//!
+//! Loudness is measured following ITU-R BS.1770 and EBU R 128. Each channel
+//! is filtered by the K-weighting curve, a high shelf modelling the head
+//! followed by a high pass, and the mean square of the filtered channels is
+//! summed using the weight of each channel. Loudness is given in LUFS, where
+//! a full scale sine of 997 Hz in either channel of stereo audio measures
+//! -3.01 LUFS.
+//!
+//! The mean square is computed over blocks of 100 ms. Momentary loudness
+//! covers the last 400 ms, and short-term loudness the last 3 s. Integrated
+//! loudness is the loudness of every 400 ms block, overlapping by 75%, that
+//! passes an absolute gate at -70 LUFS and a relative gate 10 LU below the
+//! loudness of the blocks passing the absolute gate. Loudness range is the
+//! spread between the 10th and 95th percentiles of the short-term loudness,
+//! gated at -70 LUFS and 20 LU below the loudness of the blocks passing that
+//! gate.
+//!
+//! Loudness and true peak can be stored in the loudness fields of the
+//! broadcast extension chunk, which is written back when the audio is saved
+//! as WAVE.
+//!
+//! References
+//! - [ITU-R BS.1770](https://www.itu.int/rec/R-REC-BS.1770/en)
+//! - [EBU R 128](https://tech.ebu.ch/publications/r128)
+//! - [EBU Tech 3341, Loudness Metering](https://tech.ebu.ch/publications/tech3341)
+//! - [EBU Tech 3342, Loudness Range](https://tech.ebu.ch/publications/tech3342)
+//! - [EBU Tech 3285, Broadcast Wave Format](https://tech.ebu.ch/publications/tech3285)
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::f64::NEG_INFINITY;
+use audio::AudioFormat;
+use buffer::AudioBuffer;
+use byteorder::{ByteOrder, LittleEndian};
+use dsp::filter::{Biquad, Filter};
+use dsp::peak::TruePeakMeter;
+use error::*;
+use metadata::{Metadata, UnknownChunk};
+use sample::Sample;
+
+/// Number of 100 ms blocks in the momentary and short-term windows.
+const MOMENTARY_BLOCKS:  usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// Loudness of blocks ignored by the gates, in LUFS.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Distance of the relative gates below the loudness of the ungated blocks,
+/// in LU.
+const INTEGRATED_GATE: f64 = 10.0;
+const RANGE_GATE:      f64 = 20.0;
+
+/// Weight of the surround channels, +1.5 dB.
+const SURROUND_WEIGHT: f64 = 1.41;
+
+/// Speakers of the WAVE channel mask weighted as surround channels, the back
+/// and side speakers within 60° and 120° of the front. The LFE speaker is
+/// not measured.
+const SURROUND_SPEAKERS: u32 = 0x10 | 0x20 | 0x200 | 0x400;
+const LFE_SPEAKER:       u32 = 0x8;
+
+/// Identifier and size of the broadcast extension chunk of version 2, which
+/// added the loudness fields.
+const BEXT: &'static [u8; 4] = b"bext";
+const BEXT_SIZE: usize = 602;
+const BEXT_VERSION: usize = 346;
+const BEXT_LOUDNESS: usize = 412;
+/// Value of a loudness field that is not set.
+const BEXT_UNSET: i16 = 0x7FFF;
+
+/// Loudness of audio measured by a `LoudnessMeter`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loudness {
+  /// Integrated loudness in LUFS
+  pub integrated:     f64,
+  /// Loudness range in LU
+  pub range:          f64,
+  /// Largest true peak of all channels in dBTP
+  pub true_peak:      f64,
+  /// Largest momentary loudness in LUFS
+  pub max_momentary:  f64,
+  /// Largest short-term loudness in LUFS
+  pub max_short_term: f64
+}
+
+impl Loudness {
+  /// Sets the loudness fields of the broadcast extension chunk kept in the
+  /// metadata, adding the chunk if there is none, so they are written when
+  /// the audio is saved as WAVE. Silent audio leaves the fields unset.
+  pub fn write_bext(&self, metadata: &mut Metadata) {
+    if !metadata.chunks.iter().any(|chunk| is_bext(chunk)) {
+      metadata.chunks.push(UnknownChunk {
+        format:     AudioFormat::WAVE,
+        id:         *BEXT,
+        data:       vec![0u8; BEXT_SIZE],
+        after_data: false
+      });
+    }
+    let chunk = metadata.chunks.iter_mut().find(|chunk| is_bext(chunk)).unwrap();
+    if chunk.data.len() < BEXT_SIZE {
+      chunk.data.resize(BEXT_SIZE, 0u8);
+    }
+    let data = &mut chunk.data;
+    if LittleEndian::read_u16(&data[BEXT_VERSION .. BEXT_VERSION + 2]) < 2 {
+      LittleEndian::write_u16(&mut data[BEXT_VERSION .. BEXT_VERSION + 2], 2);
+    }
+    let fields = [self.integrated, self.range, self.true_peak,
+                  self.max_momentary, self.max_short_term];
+    for (i, &value) in fields.iter().enumerate() {
+      let offset = BEXT_LOUDNESS + 2 * i;
+      LittleEndian::write_i16(&mut data[offset .. offset + 2], bext_value(value));
+    }
+  }
+
+  /// Reads the loudness fields of the broadcast extension chunk kept in the
+  /// metadata, if it has them. Fields that are not set are read as negative
+  /// infinity.
+  pub fn read_bext(metadata: &Metadata) -> Option<Loudness> {
+    let data = match metadata.chunks.iter().find(|chunk| is_bext(chunk)) {
+      Some(chunk) => &chunk.data,
+      None        => return None
+    };
+    if data.len() < BEXT_LOUDNESS + 10
+       || LittleEndian::read_u16(&data[BEXT_VERSION .. BEXT_VERSION + 2]) < 2 {
+      return None;
+    }
+    let field = |i: usize| {
+      let value = LittleEndian::read_i16(&data[BEXT_LOUDNESS + 2 * i ..]);
+      if value == BEXT_UNSET { NEG_INFINITY } else { value as f64 / 100f64 }
+    };
+    Some(Loudness {
+      integrated:     field(0),
+      range:          field(1),
+      true_peak:      field(2),
+      max_momentary:  field(3),
+      max_short_term: field(4)
+    })
+  }
+}
+
+/// Measures the loudness and true peak of interleaved frames as they are
+/// streamed.
+#[derive(Clone, Debug)]
+pub struct LoudnessMeter {
+  channels:     usize,
+  weights:      Vec<f64>,
+  /// K-weighting filter of each channel
+  filter:       Filter,
+  /// Filtered samples of the frame being measured
+  frame:        Vec<f64>,
+  /// Number of frames in a 100 ms block
+  block_size:   usize,
+  /// Frames and weighted sum of squares of the block being measured
+  frames:       usize,
+  energy:       f64,
+  /// Weighted sums of squares of the last 100 ms blocks, oldest first
+  recent:       VecDeque<f64>,
+  /// Mean squares of the momentary and short-term windows measured so far
+  momentary:    Vec<f64>,
+  short_term:   Vec<f64>,
+  peak:         TruePeakMeter
+}
+
+impl LoudnessMeter {
+  /// Creates a `LoudnessMeter` for audio with the given number of channels.
+  ///
+  /// Channels are weighted according to their usual position in a WAVE file:
+  /// 4.0, 5.0, 5.1, 6.1 and 7.1 audio use the speakers of their usual
+  /// channel masks, and other audio the first speakers of the mask. The LFE
+  /// channel is not measured, and the back and side surround channels are
+  /// weighted by +1.5 dB.
+  pub fn new(sample_rate: u32, channels: u32) -> AudioResult<LoudnessMeter> {
+    let mask =
+      match channels {
+        1 => 0x4,
+        2 => 0x3,
+        3 => 0x7,
+        4 => 0x33,
+        5 => 0x37,
+        6 => 0x3F,
+        7 => 0x13F,
+        8 => 0x63F,
+        n if n < 32 => (1 << n) - 1,
+        _ => !0
+      };
+    LoudnessMeter::with_channel_mask(sample_rate, channels, mask)
+  }
+
+  /// Creates a `LoudnessMeter` weighting the channels by the speakers of a
+  /// WAVE channel mask, in the order of its bits. Channels without a speaker
+  /// in the mask are weighted as front channels.
+  pub fn with_channel_mask(sample_rate: u32, channels: u32, mask: u32)
+                           -> AudioResult<LoudnessMeter> {
+    let mut speakers = (0..32).map(|bit| 1u32 << bit).filter(|speaker| mask & speaker != 0);
+    let weights =
+      (0..channels).map(|_|
+        match speakers.next() {
+          Some(LFE_SPEAKER) => 0f64,
+          Some(speaker) if speaker & SURROUND_SPEAKERS != 0 => SURROUND_WEIGHT,
+          _ => 1f64
+        }
+      ).collect();
+    LoudnessMeter::with_weights(sample_rate, weights)
+  }
+
+  /// Creates a `LoudnessMeter` using the given weight of each channel.
+  pub fn with_weights(sample_rate: u32, weights: Vec<f64>) -> AudioResult<LoudnessMeter> {
+    if weights.is_empty() {
+      return Err(AudioError::Format(
+        "Cannot measure audio without channels".to_string()
+      ));
+    }
+    if sample_rate < 10 {
+      return Err(AudioError::Format(
+        format!("Cannot measure the loudness of audio at {} Hz", sample_rate)
+      ));
+    }
+    let channels = weights.len();
+    Ok(LoudnessMeter {
+      channels:     channels,
+      weights:      weights,
+      filter:       try!(Filter::new(channels as u32, k_weighting(sample_rate as f64))),
+      frame:        vec![0f64; channels],
+      block_size:   (sample_rate as f64 / 10f64).round() as usize,
+      frames:       0,
+      energy:       0f64,
+      recent:       VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+      momentary:    Vec::new(),
+      short_term:   Vec::new(),
+      peak:         try!(TruePeakMeter::new(channels as u32))
+    })
+  }
+
+  /// Measures a block of interleaved frames. Samples of an incomplete frame
+  /// at the end of the block are ignored.
+  pub fn process(&mut self, samples: &[Sample]) {
+    self.peak.process(samples);
+    let channels = self.channels;
+    for frame in samples.chunks(channels).filter(|frame| frame.len() == channels) {
+      for (filtered, &sample) in self.frame.iter_mut().zip(frame.iter()) {
+        *filtered = sample as f64;
+      }
+      self.filter.process_frame(&mut self.frame);
+      for (weight, filtered) in self.weights.iter().zip(self.frame.iter()) {
+        self.energy += weight * filtered * filtered;
+      }
+      self.frames += 1;
+      if self.frames == self.block_size {
+        self.end_block();
+      }
+    }
+  }
+
+  /// Returns the loudness of the last 400 ms in LUFS, or negative infinity
+  /// if less audio has been measured.
+  pub fn momentary(&self) -> f64 {
+    self.window(MOMENTARY_BLOCKS).map(to_lufs).unwrap_or(NEG_INFINITY)
+  }
+
+  /// Returns the loudness of the last 3 s in LUFS, or negative infinity if
+  /// less audio has been measured.
+  pub fn short_term(&self) -> f64 {
+    self.window(SHORT_TERM_BLOCKS).map(to_lufs).unwrap_or(NEG_INFINITY)
+  }
+
+  /// Returns the largest momentary loudness measured so far in LUFS.
+  pub fn max_momentary(&self) -> f64 {
+    to_lufs(self.momentary.iter().cloned().fold(0f64, f64::max))
+  }
+
+  /// Returns the largest short-term loudness measured so far in LUFS.
+  pub fn max_short_term(&self) -> f64 {
+    to_lufs(self.short_term.iter().cloned().fold(0f64, f64::max))
+  }
+
+  /// Returns the gated loudness of all audio measured so far in LUFS, or
+  /// negative infinity if every block is below the absolute gate.
+  pub fn integrated(&self) -> f64 {
+    let gated = gate(&self.momentary, INTEGRATED_GATE);
+    if gated.is_empty() {
+      return NEG_INFINITY;
+    }
+    to_lufs(gated.iter().sum::<f64>() / gated.len() as f64)
+  }
+
+  /// Returns the loudness range of all audio measured so far in LU.
+  pub fn loudness_range(&self) -> f64 {
+    let mut gated: Vec<f64> =
+      gate(&self.short_term, RANGE_GATE).into_iter().map(to_lufs).collect();
+    if gated.is_empty() {
+      return 0f64;
+    }
+    gated.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let percentile = |p: f64| gated[((gated.len() - 1) as f64 * p).round() as usize];
+    percentile(0.95) - percentile(0.10)
+  }
+
+  /// Returns the largest true peak of all channels measured so far in dBTP.
+  pub fn true_peak(&self) -> f64 {
+    self.peak.peak_db()
+  }
+
+  /// Returns all measurements of the audio measured so far.
+  pub fn loudness(&self) -> Loudness {
+    Loudness {
+      integrated:     self.integrated(),
+      range:          self.loudness_range(),
+      true_peak:      self.true_peak(),
+      max_momentary:  self.max_momentary(),
+      max_short_term: self.max_short_term()
+    }
+  }
+
+  /// Clears all state, so the `LoudnessMeter` can be used for new audio.
+  pub fn reset(&mut self) {
+    self.filter.reset();
+    self.frames = 0;
+    self.energy = 0f64;
+    self.recent.clear();
+    self.momentary.clear();
+    self.short_term.clear();
+    self.peak.reset();
+  }
+
+  /// Completes a 100 ms block, measuring the windows that end with it.
+  fn end_block(&mut self) {
+    if self.recent.len() == SHORT_TERM_BLOCKS {
+      self.recent.pop_front();
+    }
+    self.recent.push_back(self.energy);
+    self.frames = 0;
+    self.energy = 0f64;
+    if let Some(mean_square) = self.window(MOMENTARY_BLOCKS) {
+      self.momentary.push(mean_square);
+    }
+    if let Some(mean_square) = self.window(SHORT_TERM_BLOCKS) {
+      self.short_term.push(mean_square);
+    }
+  }
+
+  /// Returns the weighted mean square of the last blocks, if that many have
+  /// been measured.
+  fn window(&self, blocks: usize) -> Option<f64> {
+    if self.recent.len() < blocks {
+      return None;
+    }
+    let sum: f64 = self.recent.iter().rev().take(blocks).sum();
+    Some(sum / (blocks * self.block_size) as f64)
+  }
+}
+
+/// Measures the loudness of an `AudioBuffer`, weighting the channels by the
+/// channel mask in its metadata when it has a speaker for each channel.
+pub fn measure(audio: &AudioBuffer) -> AudioResult<Loudness> {
+  let mut meter =
+    match audio.metadata.channel_mask {
+      Some(mask) if mask.count_ones() == audio.channels =>
+        try!(LoudnessMeter::with_channel_mask(audio.sample_rate, audio.channels, mask)),
+      _ => try!(LoudnessMeter::new(audio.sample_rate, audio.channels))
+    };
+  meter.process(&audio.samples);
+  Ok(meter.loudness())
+}
+
+/// Changes the gain of an `AudioBuffer` so its integrated loudness matches a
+/// target in LUFS, returning the loudness of the audio after the change.
+/// Samples are not limited, so the audio may exceed full scale when made
+/// louder.
+pub fn normalize(audio: &mut AudioBuffer, target: f64) -> AudioResult<Loudness> {
+  let loudness = try!(measure(audio));
+  if loudness.integrated == NEG_INFINITY {
+    return Err(AudioError::Format(
+      "Cannot normalize the loudness of silent audio".to_string()
+    ));
+  }
+  let change = target - loudness.integrated;
+  let gain = 10f64.powf(change / 20f64);
+  for sample in audio.samples.iter_mut() {
+    *sample = (*sample as f64 * gain) as Sample;
+  }
+  // Every measurement scales with the gain, except the loudness range.
+  Ok(Loudness {
+    integrated:     target,
+    range:          loudness.range,
+    true_peak:      loudness.true_peak + change,
+    max_momentary:  loudness.max_momentary + change,
+    max_short_term: loudness.max_short_term + change
+  })
+}
+
+// Private functions
+
+/// Returns the two stages of the K-weighting filter at a sample rate. The
+/// coefficients of BS.1770 are given at 48 kHz, so the analog filters they
+/// were derived from are used at other sample rates.
+fn k_weighting(sample_rate: f64) -> Vec<Biquad> {
+  // High shelf of +4 dB above 1.5 kHz
+  let f0   = 1681.974450955533;
+  let gain = 3.999843853973347;
+  let q    = 0.7071752369554196;
+  let k    = (PI * f0 / sample_rate).tan();
+  let vh   = 10f64.powf(gain / 20f64);
+  let vb   = vh.powf(0.4996667741545416);
+  let a0   = 1f64 + k / q + k * k;
+  let shelf = Biquad::from_coefficients(
+    [vh + vb * k / q + k * k, 2f64 * (k * k - vh), vh - vb * k / q + k * k],
+    [a0, 2f64 * (k * k - 1f64), 1f64 - k / q + k * k]
+  );
+  // High pass at 38 Hz
+  let f0 = 38.13547087602444;
+  let q  = 0.5003270373238773;
+  let k  = (PI * f0 / sample_rate).tan();
+  let a0 = 1f64 + k / q + k * k;
+  let high_pass = Biquad::from_coefficients(
+    [a0, -2f64 * a0, a0],
+    [a0, 2f64 * (k * k - 1f64), 1f64 - k / q + k * k]
+  );
+  vec![shelf, high_pass]
+}
+
+/// Converts a weighted mean square to LUFS.
+#[inline]
+fn to_lufs(mean_square: f64) -> f64 {
+  -0.691 + 10f64 * mean_square.log10()
+}
+
+/// Returns the mean squares passing the absolute gate and a relative gate
+/// below the loudness of the blocks passing the absolute gate.
+fn gate(blocks: &[f64], relative: f64) -> Vec<f64> {
+  let absolute: Vec<f64> =
+    blocks.iter().cloned().filter(|&block| to_lufs(block) > ABSOLUTE_GATE).collect();
+  if absolute.is_empty() {
+    return absolute;
+  }
+  let threshold = to_lufs(absolute.iter().sum::<f64>() / absolute.len() as f64) - relative;
+  absolute.into_iter().filter(|&block| to_lufs(block) > threshold).collect()
+}
+
+/// Returns the value of a bext loudness field, in hundredths.
+#[inline]
+fn bext_value(value: f64) -> i16 {
+  if value.is_finite() {
+    (value * 100f64).round().max(-32768f64).min(32766f64) as i16
+  } else {
+    BEXT_UNSET
+  }
+}
+
+#[inline]
+fn is_bext(chunk: &UnknownChunk) -> bool {
+  chunk.format == AudioFormat::WAVE && &chunk.id == BEXT
+}
+
+#[cfg(test)]
+mod measurement {
+  use std::io::Cursor;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::LPCM_I16_LE;
+  use ::testing::{Sine, signal};
+  use super::*;
+
+  /// Returns a sine of 997 Hz in the given channels, at a level in dBFS.
+  fn sine(sample_rate: u32, channels: &[bool], level: f64, seconds: f64) -> AudioBuffer {
+    let amplitude = 10f64.powf(level / 20f64);
+    let waves: Vec<Sine> =
+      channels.iter().map(|&on| Sine::new(997f64, if on { amplitude } else { 0f64 })).collect();
+    signal(sample_rate, (sample_rate as f64 * seconds) as usize, &waves)
+  }
+
+  #[test]
+  fn reference_levels() {
+    // EBU Tech 3341: a stereo sine at -23 dBFS in both channels measures
+    // -23 LUFS, and at 0 dBFS in one channel measures -3.01 LUFS.
+    for &rate in [44100, 48000, 96000].iter() {
+      let audio = sine(rate, &[true, true], -23f64, 20f64);
+      let loudness = audio.loudness().unwrap();
+      assert!((-23f64 - loudness.integrated).abs() < 0.1);
+      assert!((-23f64 - loudness.max_momentary).abs() < 0.1);
+      assert!((-23f64 - loudness.max_short_term).abs() < 0.1);
+      assert!(loudness.range < 0.1);
+      let audio = sine(rate, &[true, false], 0f64, 5f64);
+      assert!((-3.01 - audio.loudness().unwrap().integrated).abs() < 0.1);
+    }
+  }
+
+  #[test]
+  fn channel_weights() {
+    // Surround channels are 1.5 dB louder, and the LFE channel is ignored.
+    let front = sine(48000, &[true, false, false, false, false, false], -20f64, 5f64);
+    let lfe   = sine(48000, &[false, false, false, true, false, false], -20f64, 5f64);
+    let rear  = sine(48000, &[false, false, false, false, true, false], -20f64, 5f64);
+    let front = front.loudness().unwrap().integrated;
+    assert!((-23.01 - front).abs() < 0.1);
+    assert_eq!(NEG_INFINITY, lfe.loudness().unwrap().integrated);
+    assert!((1.5 - (rear.loudness().unwrap().integrated - front)).abs() < 0.05);
+  }
+
+  #[test]
+  fn surround_weights() {
+    // The back and side channels of 7.1 audio are 1.5 dB louder.
+    let front = sine(48000, &[false, false, true, false, false, false, false, false],
+                     -20f64, 5f64).loudness().unwrap().integrated;
+    assert!((-23.01 - front).abs() < 0.1);
+    for c in 3..8 {
+      let mut channels = [false; 8];
+      channels[c] = true;
+      let loudness = sine(48000, &channels, -20f64, 5f64).loudness().unwrap().integrated;
+      if c == 3 {
+        assert_eq!(NEG_INFINITY, loudness);
+      } else {
+        assert!((1.5 - (loudness - front)).abs() < 0.05);
+      }
+    }
+    // A channel mask places the channels, where the back centre of 3/1
+    // audio is behind the surround positions.
+    let mut audio = sine(48000, &[false, false, false, true], -20f64, 5f64);
+    assert!((1.5 - (audio.loudness().unwrap().integrated - front)).abs() < 0.05);
+    audio.metadata.channel_mask = Some(0x107);
+    assert!((audio.loudness().unwrap().integrated - front).abs() < 0.05);
+  }
+
+  #[test]
+  fn gating() {
+    // EBU Tech 3341: 10 s at -36 dBFS, 60 s at -23 dBFS and 10 s at -36 dBFS
+    // measure -23 LUFS, as the quiet parts fall below the relative gate.
+    let mut samples = Vec::new();
+    for &(level, seconds) in [(-36f64, 10f64), (-23f64, 60f64), (-36f64, 10f64)].iter() {
+      samples.extend(sine(48000, &[true, true], level, seconds).samples);
+    }
+    let audio = AudioBuffer::from_samples(48000, 2, samples);
+    assert!((-23f64 - audio.loudness().unwrap().integrated).abs() < 0.1);
+
+    // EBU Tech 3342: 20 s at -20 dBFS and 20 s at -30 dBFS have a loudness
+    // range of 10 LU.
+    let mut samples = sine(48000, &[true, true], -20f64, 20f64).samples;
+    samples.extend(sine(48000, &[true, true], -30f64, 20f64).samples);
+    let audio = AudioBuffer::from_samples(48000, 2, samples);
+    assert!((10f64 - audio.loudness().unwrap().range).abs() < 0.1);
+
+    let silence = AudioBuffer::from_samples(48000, 2, vec![0f32; 96000]);
+    assert_eq!(NEG_INFINITY, silence.loudness().unwrap().integrated);
+  }
+
+  #[test]
+  fn streaming() {
+    let audio = sine(48000, &[true, true], -18f64, 6f64);
+    let mut meter = LoudnessMeter::new(48000, 2).unwrap();
+    assert_eq!(NEG_INFINITY, meter.momentary());
+    for block in audio.samples.chunks(1234 * 2) {
+      meter.process(block);
+    }
+    assert!((-18f64 - meter.momentary()).abs() < 0.1);
+    assert!((-18f64 - meter.short_term()).abs() < 0.1);
+    assert_eq!(audio.loudness().unwrap(), meter.loudness());
+    assert!((-18f64 - meter.true_peak()).abs() < 0.1);
+    meter.reset();
+    assert_eq!(NEG_INFINITY, meter.short_term());
+    assert!(LoudnessMeter::with_weights(48000, vec![]).is_err());
+  }
+
+  #[test]
+  fn normalization() {
+    let mut audio = sine(48000, &[true, true], -30f64, 10f64);
+    let loudness = audio.normalize_loudness(-23f64).unwrap();
+    assert_eq!(-23f64, loudness.integrated);
+    let verify = audio.loudness().unwrap();
+    assert!((-23f64 - verify.integrated).abs() < 0.01);
+    assert!((loudness.true_peak - verify.true_peak).abs() < 0.01);
+    let mut silence = AudioBuffer::from_samples(48000, 1, vec![0f32; 48000]);
+    assert!(silence.normalize_loudness(-23f64).is_err());
+  }
+
+  #[test]
+  fn bext_fields() {
+    let mut audio = sine(48000, &[true, true], -23f64, 5f64);
+    let loudness = audio.loudness().unwrap();
+    loudness.write_bext(&mut audio.metadata);
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write_as(&mut bytes, &audio, AudioFormat::WAVE, LPCM_I16_LE).unwrap();
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+    let fields = Loudness::read_bext(&verify.metadata).unwrap();
+    assert!((loudness.integrated - fields.integrated).abs() <= 0.005);
+    assert!((loudness.true_peak  - fields.true_peak).abs()  <= 0.005);
+    assert!((loudness.range      - fields.range).abs()      <= 0.005);
+
+    // An existing chunk keeps its other fields, and is upgraded to version 2.
+    let mut metadata = ::metadata::Metadata::default();
+    let mut data = vec![0u8; 602];
+    data[0..5].copy_from_slice(b"Intro");
+    data[346] = 1;
+    metadata.chunks.push(::metadata::UnknownChunk {
+      format: AudioFormat::WAVE, id: *b"bext", data: data, after_data: false
+    });
+    assert_eq!(None, Loudness::read_bext(&metadata));
+    let silent = Loudness {
+      integrated: NEG_INFINITY, range: 0f64, true_peak: NEG_INFINITY,
+      max_momentary: NEG_INFINITY, max_short_term: NEG_INFINITY
+    };
+    silent.write_bext(&mut metadata);
+    assert_eq!(1, metadata.chunks.len());
+    assert_eq!(b"Intro", &metadata.chunks[0].data[0..5]);
+    assert_eq!(Some(silent), Loudness::read_bext(&metadata));
+  }
+}
@@ -0,0 +1,16 @@
+//! Signal Processing
+//!
+//! Operations on the samples of an `AudioBuffer`. Each operation is also
+//! available as a processor holding its state between blocks of interleaved
+//! frames, so audio can be processed as it is streamed rather than loaded in
+//! full. Processing a buffer in blocks gives the same samples as processing
+//! it at once.
+
+pub mod fade;
+pub mod filter;
+pub mod loudness;
+pub mod peak;
+pub mod remix;
+pub mod resample;
+pub mod silence;
+pub mod stats;
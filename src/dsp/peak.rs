@@ -0,0 +1,154 @@
+//! True Peak Measurement
+//!
+//! The peak of a signal may lie between its samples, so it can exceed the
+//! largest sample once the audio is converted to analog or resampled. The
+//! true peak is estimated by interpolating three points between each pair of
+//! samples, as if the audio were oversampled four times, using a polyphase
+//! filter of 48 taps. Samples themselves are always included, so the true
+//! peak is never below the sample peak.
+//!
+//! References
+//! - [ITU-R BS.1770, Annex 2](https://www.itu.int/rec/R-REC-BS.1770/en)
+
+use dsp::resample::{kaiser, sinc};
+use error::*;
+use sample::Sample;
+
+/// Number of points computed for each sample.
+const OVERSAMPLING: usize = 4;
+
+/// Number of samples used to interpolate each point.
+const TAPS: usize = 12;
+
+/// Shape parameter of the Kaiser window of the filter.
+const BETA: f64 = 6.0;
+
+/// Measures the true peak of each channel of interleaved frames as they are
+/// streamed.
+#[derive(Clone, Debug)]
+pub struct TruePeakMeter {
+  channels:     usize,
+  /// Filter of each interpolated point, applied to the last `TAPS` samples
+  phases:       Vec<[f64; TAPS]>,
+  /// Last `TAPS` samples of each channel, oldest first
+  history:      Vec<[f64; TAPS]>,
+  /// Largest absolute value of each channel, interpolated or not
+  peaks:        Vec<f64>
+}
+
+impl TruePeakMeter {
+  /// Creates a `TruePeakMeter` for audio with the given number of channels.
+  pub fn new(channels: u32) -> AudioResult<TruePeakMeter> {
+    if channels == 0 {
+      return Err(AudioError::Format(
+        "Cannot measure audio without channels".to_string()
+      ));
+    }
+    // Each point lies between the middle two samples of the history, and its
+    // filter is normalized so constant input is kept at the same level.
+    let center = (TAPS / 2 - 1) as f64;
+    let phases: Vec<[f64; TAPS]> =
+      (1 .. OVERSAMPLING)
+      .map(|phase| {
+        let mut taps = [0f64; TAPS];
+        for (k, tap) in taps.iter_mut().enumerate() {
+          let x = k as f64 - center - phase as f64 / OVERSAMPLING as f64;
+          *tap = sinc(x) * kaiser(x / (TAPS / 2) as f64, BETA);
+        }
+        let sum: f64 = taps.iter().sum();
+        for tap in taps.iter_mut() {
+          *tap /= sum;
+        }
+        taps
+      })
+      .collect();
+    Ok(TruePeakMeter {
+      channels:     channels as usize,
+      phases:       phases,
+      history:      vec![[0f64; TAPS]; channels as usize],
+      peaks:        vec![0f64; channels as usize]
+    })
+  }
+
+  /// Measures a block of interleaved frames. Samples of an incomplete frame
+  /// at the end of the block are ignored.
+  pub fn process(&mut self, samples: &[Sample]) {
+    let channels = self.channels;
+    for frame in samples.chunks(channels).filter(|frame| frame.len() == channels) {
+      for (c, &sample) in frame.iter().enumerate() {
+        let history = &mut self.history[c];
+        for k in 0 .. TAPS - 1 {
+          history[k] = history[k + 1];
+        }
+        history[TAPS - 1] = sample as f64;
+        let mut peak = self.peaks[c].max((sample as f64).abs());
+        for taps in self.phases.iter() {
+          let point: f64 = taps.iter().zip(history.iter()).map(|(t, s)| t * s).sum();
+          peak = peak.max(point.abs());
+        }
+        self.peaks[c] = peak;
+      }
+    }
+  }
+
+  /// Returns the true peak of each channel as a linear value, where 1 is
+  /// full scale.
+  pub fn channel_peaks(&self) -> Vec<f64> {
+    self.peaks.clone()
+  }
+
+  /// Returns the largest true peak of all channels as a linear value.
+  pub fn peak(&self) -> f64 {
+    self.peaks.iter().cloned().fold(0f64, f64::max)
+  }
+
+  /// Returns the largest true peak of all channels in dBTP, decibels
+  /// relative to full scale.
+  pub fn peak_db(&self) -> f64 {
+    20f64 * self.peak().log10()
+  }
+
+  /// Clears all state, so the `TruePeakMeter` can be used for new audio.
+  pub fn reset(&mut self) {
+    for history in self.history.iter_mut() {
+      *history = [0f64; TAPS];
+    }
+    for peak in self.peaks.iter_mut() {
+      *peak = 0f64;
+    }
+  }
+}
+
+#[cfg(test)]
+mod measurement {
+  use std::f64::consts::PI;
+  use super::*;
+
+  #[test]
+  fn intersample_peaks() {
+    // A sine at a quarter of the sample rate, sampled halfway between its
+    // peaks, has samples at -3 dB of its true peak.
+    let samples: Vec<f32> =
+      (0..1000).map(|i| (PI / 2f64 * i as f64 + PI / 4f64).sin() as f32).collect();
+    let mut meter = TruePeakMeter::new(1).unwrap();
+    meter.process(&samples);
+    assert!((1f64 - meter.peak()).abs() < 0.02);
+    let largest = samples.iter().fold(0f32, |a, &b| a.max(b.abs()));
+    assert!((0.7071 - largest).abs() < 1e-3);
+  }
+
+  #[test]
+  fn channels() {
+    let mut meter = TruePeakMeter::new(2).unwrap();
+    // Peaks are kept between blocks
+    meter.process(&[0.5, -0.25, 0.5, -0.25]);
+    meter.process(&[0f32, 0f32]);
+    let peaks = meter.channel_peaks();
+    assert!(peaks[0] >= 0.5 && peaks[0] < 0.6);
+    assert!(peaks[1] >= 0.25 && peaks[1] < 0.3);
+    assert!((20f64 * peaks[0].log10() - meter.peak_db()).abs() < 1e-9);
+    meter.reset();
+    assert_eq!(0f64, meter.peak());
+    assert!(TruePeakMeter::new(0).is_err());
+  }
+}
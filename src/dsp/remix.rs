@@ -0,0 +1,421 @@
+//! Channel Remixing
+//!
+//! Audio is remixed by a matrix of gains, where each output channel is the
+//! sum of the input channels weighted by a row of the matrix. Matrices for
+//! the downmixes of ITU-R BS.775 are built from the speaker layouts of the
+//! input and output, where each speaker missing from the output is spread
+//! across its neighbours at -3 dB. The low-frequency effects channel is left
+//! out of a downmix, as the recommendation does not include it.
+//!
+//! Channels are ordered as in the channel mask of WAVE_FORMAT_EXTENSIBLE, so
+//! 5.1 audio is ordered left, right, centre, LFE, left surround and right
+//! surround. A downmix may exceed full scale when every input channel is
+//! loud; `normalized` scales a matrix so that it cannot.
+//!
+//! References
+//! - [ITU-R BS.775](https://www.itu.int/rec/R-REC-BS.775/en)
+//! - [Multiple Channel Audio Data and WAVE Files](https://learn.microsoft.com/en-us/windows-hardware/drivers/audio/multiple-channel-audio-data-and-wave-files)
+
+use std::f32::consts::FRAC_1_SQRT_2;
+use audio::AudioFormat;
+use buffer::AudioBuffer;
+use error::*;
+use sample::Sample;
+
+/// Positions of the speakers in a `SpeakerLayout`. The values are the bits of
+/// the WAVE channel mask.
+const LEFT:           u32 = 0x1;
+const RIGHT:          u32 = 0x2;
+const CENTER:         u32 = 0x4;
+const LFE:            u32 = 0x8;
+const LEFT_SURROUND:  u32 = 0x10;
+const RIGHT_SURROUND: u32 = 0x20;
+const BACK_CENTER:    u32 = 0x100;
+
+/// Arrangements of speakers used by ITU-R BS.775, named by the number of
+/// front and rear speakers.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpeakerLayout {
+  /// 1/0: centre
+  Mono,
+  /// 2/0: left, right
+  Stereo,
+  /// 3/0: left, right, centre
+  Surround30,
+  /// 2/1: left, right, surround
+  Surround21,
+  /// 3/1: left, right, centre, surround
+  Surround31,
+  /// 2/2: left, right, left surround, right surround
+  Quad,
+  /// 3/2: left, right, centre, left surround, right surround
+  Surround50,
+  /// 3/2 with LFE: left, right, centre, LFE, left surround, right surround
+  Surround51
+}
+
+impl SpeakerLayout {
+  /// Returns the WAVE channel mask of the layout.
+  pub fn channel_mask(&self) -> u32 {
+    match *self {
+      SpeakerLayout::Mono       => CENTER,
+      SpeakerLayout::Stereo     => LEFT | RIGHT,
+      SpeakerLayout::Surround30 => LEFT | RIGHT | CENTER,
+      SpeakerLayout::Surround21 => LEFT | RIGHT | BACK_CENTER,
+      SpeakerLayout::Surround31 => LEFT | RIGHT | CENTER | BACK_CENTER,
+      SpeakerLayout::Quad       => LEFT | RIGHT | LEFT_SURROUND | RIGHT_SURROUND,
+      SpeakerLayout::Surround50 => LEFT | RIGHT | CENTER | LEFT_SURROUND | RIGHT_SURROUND,
+      SpeakerLayout::Surround51 => LEFT | RIGHT | CENTER | LFE | LEFT_SURROUND | RIGHT_SURROUND
+    }
+  }
+
+  /// Returns the number of channels in the layout.
+  pub fn channels(&self) -> u32 {
+    self.channel_mask().count_ones()
+  }
+
+  /// Returns the speakers of the layout in channel order.
+  fn speakers(&self) -> Vec<u32> {
+    let mask = self.channel_mask();
+    (0..32).map(|bit| 1 << bit).filter(|speaker| mask & speaker != 0).collect()
+  }
+}
+
+/// Gains mixing each channel of the input into each channel of the output.
+///
+/// The matrix holds a row for each output channel, with a gain for each input
+/// channel. As each frame is remixed on its own, the matrix also remixes
+/// interleaved frames as they are streamed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMatrix {
+  inputs:       usize,
+  /// Gains of each output channel, one row after another
+  gains:        Vec<f32>,
+  /// Speakers of the input, if known
+  input_layout: Option<SpeakerLayout>,
+  /// Speakers of the output, if known
+  layout:       Option<SpeakerLayout>
+}
+
+impl ChannelMatrix {
+  /// Creates a matrix from a row of gains for each output channel. Every row
+  /// must hold a gain for each input channel.
+  pub fn new(rows: Vec<Vec<f32>>) -> AudioResult<ChannelMatrix> {
+    let inputs = rows.first().map(|row| row.len()).unwrap_or(0);
+    if inputs == 0 {
+      return Err(AudioError::Format(
+        "Cannot remix audio to or from zero channels".to_string()
+      ));
+    }
+    if rows.iter().any(|row| row.len() != inputs) {
+      return Err(AudioError::Format(
+        "Every output channel must have a gain for each input channel".to_string()
+      ));
+    }
+    Ok(ChannelMatrix {
+      inputs:       inputs,
+      gains:        rows.concat(),
+      input_layout: None,
+      layout:       None
+    })
+  }
+
+  /// Creates a matrix passing each channel through unchanged.
+  pub fn identity(channels: u32) -> AudioResult<ChannelMatrix> {
+    let channels = channels as usize;
+    ChannelMatrix::new(
+      (0..channels)
+      .map(|o| (0..channels).map(|i| if i == o { 1f32 } else { 0f32 }).collect())
+      .collect()
+    )
+  }
+
+  /// Creates a matrix copying a single channel to each output channel, such
+  /// as for playing mono audio in stereo.
+  pub fn duplicate(channels: u32) -> AudioResult<ChannelMatrix> {
+    let mut matrix = try!(ChannelMatrix::new(vec![vec![1f32]; channels as usize]));
+    if channels == 2 {
+      matrix.layout = Some(SpeakerLayout::Stereo);
+    }
+    Ok(matrix)
+  }
+
+  /// Creates the ITU-R BS.775 downmix from one speaker layout to another
+  /// with no more channels.
+  pub fn downmix(from: SpeakerLayout, to: SpeakerLayout) -> AudioResult<ChannelMatrix> {
+    if to.channels() > from.channels() {
+      return Err(AudioError::Unsupported(
+        format!("Cannot downmix {:?} audio to {:?}", from, to)
+      ));
+    }
+    let inputs  = from.speakers();
+    let outputs = to.speakers();
+    let mut rows = vec![vec![0f32; inputs.len()]; outputs.len()];
+    for (i, &speaker) in inputs.iter().enumerate() {
+      for (position, gain) in route(speaker, to.channel_mask()) {
+        if let Some(o) = outputs.iter().position(|&s| s == position) {
+          rows[o][i] += gain;
+        }
+      }
+    }
+    let mut matrix = try!(ChannelMatrix::new(rows));
+    matrix.input_layout = Some(from);
+    matrix.layout       = Some(to);
+    Ok(matrix)
+  }
+
+  /// Downmix of 5.1 audio to stereo, which leaves out the LFE channel.
+  pub fn surround_to_stereo() -> ChannelMatrix {
+    ChannelMatrix::downmix(SpeakerLayout::Surround51, SpeakerLayout::Stereo).unwrap()
+  }
+
+  /// Downmix of 5.1 audio to mono, which leaves out the LFE channel.
+  pub fn surround_to_mono() -> ChannelMatrix {
+    ChannelMatrix::downmix(SpeakerLayout::Surround51, SpeakerLayout::Mono).unwrap()
+  }
+
+  /// Downmix of stereo audio to mono.
+  pub fn stereo_to_mono() -> ChannelMatrix {
+    ChannelMatrix::downmix(SpeakerLayout::Stereo, SpeakerLayout::Mono).unwrap()
+  }
+
+  /// Duplicates mono audio to both channels of stereo audio.
+  pub fn mono_to_stereo() -> ChannelMatrix {
+    ChannelMatrix::duplicate(2).unwrap()
+  }
+
+  /// Returns the matrix scaled so that no output channel exceeds full scale
+  /// when the input channels do not.
+  pub fn normalized(mut self) -> ChannelMatrix {
+    let largest =
+      self.gains.chunks(self.inputs)
+      .map(|row| row.iter().map(|gain| gain.abs()).sum::<f32>())
+      .fold(0f32, f32::max);
+    if largest > 1f32 {
+      for gain in self.gains.iter_mut() {
+        *gain /= largest;
+      }
+    }
+    self
+  }
+
+  /// Returns the number of input channels.
+  pub fn inputs(&self) -> u32 {
+    self.inputs as u32
+  }
+
+  /// Returns the number of output channels.
+  pub fn outputs(&self) -> u32 {
+    (self.gains.len() / self.inputs) as u32
+  }
+
+  /// Returns the gain of an input channel in an output channel.
+  pub fn gain(&self, output: u32, input: u32) -> f32 {
+    self.gains[output as usize * self.inputs + input as usize]
+  }
+
+  /// Returns the speaker layout of the input, if known.
+  pub fn input_layout(&self) -> Option<SpeakerLayout> {
+    self.input_layout
+  }
+
+  /// Returns the speaker layout of the output, if known.
+  pub fn layout(&self) -> Option<SpeakerLayout> {
+    self.layout
+  }
+
+  /// Remixes interleaved frames. Samples of an incomplete frame at the end
+  /// of the block are ignored.
+  pub fn process(&self, samples: &[Sample]) -> Vec<Sample> {
+    let outputs = self.outputs() as usize;
+    let mut output = Vec::with_capacity(samples.len() / self.inputs * outputs);
+    for frame in samples.chunks(self.inputs).filter(|frame| frame.len() == self.inputs) {
+      for row in self.gains.chunks(self.inputs) {
+        output.push(row.iter().zip(frame.iter()).map(|(gain, sample)| gain * sample).sum());
+      }
+    }
+    output
+  }
+}
+
+/// Remixes the channels of an `AudioBuffer`. The channel mask and channel
+/// layout kept in its metadata are replaced with the layout of the output.
+/// Audio with a channel mask must have the speakers of the input of a matrix
+/// built from speaker layouts.
+pub fn remix(audio: &mut AudioBuffer, matrix: &ChannelMatrix) -> AudioResult<()> {
+  if audio.channels != matrix.inputs() {
+    return Err(AudioError::Format(
+      format!("Cannot remix audio with {} channels using a matrix for {} channels",
+              audio.channels, matrix.inputs())
+    ));
+  }
+  if let (Some(mask), Some(layout)) = (audio.metadata.channel_mask, matrix.input_layout()) {
+    if mask != layout.channel_mask() {
+      return Err(AudioError::Format(
+        format!("Cannot remix audio with channel mask {:#x} using a matrix for {:?} audio",
+                mask, layout)
+      ));
+    }
+  }
+  let channels = matrix.outputs();
+  let bitmap   = matrix.layout().map(|layout| layout.channel_mask());
+  for chunk in audio.metadata.chunks.iter_mut()
+               .filter(|chunk| chunk.format == AudioFormat::CAF && &chunk.id == b"chan") {
+    chunk.data = try!(::caf::channel_layout_data(channels, bitmap));
+  }
+  audio.metadata.channel_mask = bitmap;
+  audio.samples  = matrix.process(&audio.samples);
+  audio.channels = channels;
+  Ok(())
+}
+
+// Private functions
+
+/// Returns the speakers of a layout that play a speaker, with their gains.
+/// A missing speaker is played by its neighbours at -3 dB, following ITU-R
+/// BS.775.
+fn route(speaker: u32, layout: u32) -> Vec<(u32, f32)> {
+  if layout & speaker != 0 {
+    return vec![(speaker, 1f32)];
+  }
+  let spread = |speakers: &[u32]| -> Vec<(u32, f32)> {
+    speakers.iter()
+    .flat_map(|&s| route(s, layout))
+    .map(|(s, gain)| (s, gain * FRAC_1_SQRT_2))
+    .collect()
+  };
+  match speaker {
+    LEFT | RIGHT => spread(&[CENTER]),
+    CENTER => spread(&[LEFT, RIGHT]),
+    LEFT_SURROUND =>
+      if layout & BACK_CENTER != 0 { spread(&[BACK_CENTER]) } else { spread(&[LEFT]) },
+    RIGHT_SURROUND =>
+      if layout & BACK_CENTER != 0 { spread(&[BACK_CENTER]) } else { spread(&[RIGHT]) },
+    BACK_CENTER => spread(&[LEFT_SURROUND, RIGHT_SURROUND]),
+    _ => Vec::new()
+  }
+}
+
+#[cfg(test)]
+mod remixing {
+  use std::io::Cursor;
+  use std::path::Path;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::LPCM_I16_LE;
+  use super::*;
+
+  const G: f32 = 0.70710677;
+
+  fn assert_rows(expected: &[&[f32]], matrix: &ChannelMatrix) {
+    assert_eq!(expected.len() as u32, matrix.outputs());
+    for (o, row) in expected.iter().enumerate() {
+      assert_eq!(row.len() as u32, matrix.inputs());
+      for (i, gain) in row.iter().enumerate() {
+        assert!((gain - matrix.gain(o as u32, i as u32)).abs() < 1e-6);
+      }
+    }
+  }
+
+  #[test]
+  fn presets() {
+    assert_rows(&[&[1f32, 0f32, G, 0f32, G, 0f32],
+                  &[0f32, 1f32, G, 0f32, 0f32, G]],
+                &ChannelMatrix::surround_to_stereo());
+    assert_rows(&[&[G, G, 1f32, 0f32, 0.5, 0.5]],
+                &ChannelMatrix::surround_to_mono());
+    assert_rows(&[&[G, G]], &ChannelMatrix::stereo_to_mono());
+    assert_rows(&[&[1f32], &[1f32]], &ChannelMatrix::mono_to_stereo());
+    assert_eq!(Some(SpeakerLayout::Stereo), ChannelMatrix::mono_to_stereo().layout());
+  }
+
+  #[test]
+  fn downmixes() {
+    use super::SpeakerLayout::*;
+    // The downmixes of 3/2 audio in ITU-R BS.775
+    assert_rows(&[&[1f32, 0f32, 0f32, G, 0f32],
+                  &[0f32, 1f32, 0f32, 0f32, G],
+                  &[0f32, 0f32, 1f32, 0f32, 0f32]],
+                &ChannelMatrix::downmix(Surround50, Surround30).unwrap());
+    assert_rows(&[&[1f32, 0f32, G, 0f32, 0f32],
+                  &[0f32, 1f32, G, 0f32, 0f32],
+                  &[0f32, 0f32, 0f32, G, G]],
+                &ChannelMatrix::downmix(Surround50, Surround21).unwrap());
+    assert_rows(&[&[1f32, 0f32, 0f32, 0f32, 0f32],
+                  &[0f32, 1f32, 0f32, 0f32, 0f32],
+                  &[0f32, 0f32, 1f32, 0f32, 0f32],
+                  &[0f32, 0f32, 0f32, G, G]],
+                &ChannelMatrix::downmix(Surround50, Surround31).unwrap());
+    assert_rows(&[&[1f32, 0f32, G, 0f32, 0f32],
+                  &[0f32, 1f32, G, 0f32, 0f32],
+                  &[0f32, 0f32, 0f32, 1f32, 0f32],
+                  &[0f32, 0f32, 0f32, 0f32, 1f32]],
+                &ChannelMatrix::downmix(Surround50, Quad).unwrap());
+    assert!(ChannelMatrix::downmix(Stereo, Surround51).is_err());
+  }
+
+  #[test]
+  fn matrices() {
+    assert!(ChannelMatrix::new(vec![]).is_err());
+    assert!(ChannelMatrix::new(vec![vec![1f32, 0f32], vec![1f32]]).is_err());
+    let matrix = ChannelMatrix::new(vec![vec![1f32, 0f32], vec![0.5, 0.5]]).unwrap();
+    assert_eq!(vec![0.25, 0.5, -1f32, 0f32], matrix.process(&[0.25, 0.75, -1f32, 1f32, 0.5]));
+    assert_eq!(vec![0.5, -0.5], ChannelMatrix::identity(2).unwrap().process(&[0.5, -0.5]));
+    // The louder row of the 5.1 downmix sums to 1 + 2 / √2.
+    let normalized = ChannelMatrix::surround_to_stereo().normalized();
+    assert!((1f32 / (1f32 + 2f32 * G) - normalized.gain(0, 0)).abs() < 1e-6);
+    let output = normalized.process(&[1f32, -1f32, 1f32, 1f32, 1f32, -1f32]);
+    assert!((1f32 - output[0]).abs() < 1e-6);
+  }
+
+  #[test]
+  fn buffers() {
+    let mut audio = AudioBuffer::from_samples(48000, 1, vec![0.5, -0.25]);
+    audio.remix(&ChannelMatrix::mono_to_stereo()).unwrap();
+    assert_eq!(2, audio.channels);
+    assert_eq!(vec![0.5, 0.5, -0.25, -0.25], audio.samples);
+    audio.remix(&ChannelMatrix::stereo_to_mono()).unwrap();
+    assert_eq!(1, audio.channels);
+    assert!((0.5 * 2f32 * G - audio.samples[0]).abs() < 1e-6);
+    assert!(audio.remix(&ChannelMatrix::surround_to_stereo()).is_err());
+  }
+
+  #[test]
+  fn channel_layout() {
+    // A 5.1 CAF file keeps its channel layout, which follows the audio when
+    // it is folded down to stereo.
+    let audio = AudioBuffer::from_samples(48000, 6, vec![0.25; 600]);
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write_as(&mut bytes, &audio, AudioFormat::CAF, LPCM_I16_LE).unwrap();
+    let mut audio = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    audio.remix(&ChannelMatrix::surround_to_stereo().normalized()).unwrap();
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write_as(&mut bytes, &audio, AudioFormat::CAF, LPCM_I16_LE).unwrap();
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::CAF).unwrap();
+    assert_eq!(2,   verify.channels);
+    assert_eq!(200, verify.samples.len());
+    let chan: Vec<&Vec<u8>> =
+      verify.metadata.chunks.iter()
+      .filter(|chunk| &chunk.id == b"chan").map(|chunk| &chunk.data).collect();
+    assert_eq!(vec![&vec![0, 1, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0]], chan);
+  }
+
+  #[test]
+  fn channel_mask() {
+    // The mask of a 5.1 WAVE file must match the input of the downmix, and
+    // is replaced with the mask of its output.
+    let mut audio = audio::open(Path::new("tests/wavpack/sine-5.1.wav")).unwrap();
+    assert_eq!(Some(0x3F), audio.metadata.channel_mask);
+    assert_eq!(Some(SpeakerLayout::Surround51),
+               ChannelMatrix::surround_to_stereo().input_layout());
+    let mut sides = audio.clone();
+    sides.metadata.channel_mask = Some(0x60F);
+    assert!(sides.remix(&ChannelMatrix::surround_to_stereo()).is_err());
+    audio.remix(&ChannelMatrix::surround_to_mono()).unwrap();
+    assert_eq!(Some(0x4), audio.metadata.channel_mask);
+    // Matrices without speaker layouts leave the mask unknown.
+    audio.remix(&ChannelMatrix::identity(1).unwrap()).unwrap();
+    assert_eq!(None, audio.metadata.channel_mask);
+  }
+}
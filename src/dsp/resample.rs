@@ -0,0 +1,475 @@
+//! Sample Rate Conversion
+//!
+//! Audio is resampled using band-limited interpolation, where each output
+//! frame is the sum of the surrounding input frames weighted by a
+//! Kaiser-windowed sinc function. The function is centered on the time of the
+//! output frame, so any ratio between sample rates is supported. When the
+//! sample rate is lowered, the cutoff of the filter is lowered with it to
+//! remove frequencies above the new Nyquist frequency.
+//!
+//! The filter is linear-phase, delaying the audio by half its length. This
+//! delay is compensated, so resampled audio stays aligned with the original,
+//! at the cost of a stream holding back frames until the input following them
+//! is received.
+//!
+//! References
+//! - [Digital Audio Resampling Home Page](https://ccrma.stanford.edu/~jos/resample/)
+//! - [Kaiser Window](https://en.wikipedia.org/wiki/Kaiser_window)
+
+use std::f64::consts::PI;
+use buffer::AudioBuffer;
+use error::*;
+use sample::Sample;
+
+/// Number of values of the filter stored between each of its zero crossings.
+const TABLE_RESOLUTION: usize = 512;
+
+/// Trade-off between the accuracy and speed of a `Resampler`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ResampleQuality {
+  /// 8 zero crossings, with a passband up to 85% of the Nyquist frequency
+  Low,
+  /// 16 zero crossings, with a passband up to 91% of the Nyquist frequency
+  Medium,
+  /// 32 zero crossings, with a passband up to 95% of the Nyquist frequency
+  High,
+  /// 64 zero crossings, with a passband up to 97% of the Nyquist frequency
+  Best
+}
+
+impl ResampleQuality {
+  /// Returns the number of zero crossings on each side of the filter, the
+  /// shape parameter of the Kaiser window, and the cutoff as a fraction of
+  /// the Nyquist frequency.
+  fn parameters(&self) -> (usize, f64, f64) {
+    match *self {
+      ResampleQuality::Low    => (8,  5.0,  0.85),
+      ResampleQuality::Medium => (16, 7.0,  0.91),
+      ResampleQuality::High   => (32, 9.0,  0.95),
+      ResampleQuality::Best   => (64, 11.0, 0.97)
+    }
+  }
+}
+
+impl Default for ResampleQuality {
+  fn default() -> Self {
+    ResampleQuality::High
+  }
+}
+
+/// Converts interleaved frames from one sample rate to another as they are
+/// streamed.
+///
+/// Blocks of any size are passed to `process`, which returns the frames that
+/// can be computed from the input received so far. A block may end within a
+/// frame, which is completed by the next block. Once all input has been
+/// passed, `flush` returns the remaining frames.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+  channels:     usize,
+  /// Input and output sample rates, divided by their greatest common divisor
+  from_rate:    u64,
+  to_rate:      u64,
+  /// Cutoff of the filter as a fraction of the input Nyquist frequency
+  cutoff:       f64,
+  zero_crossings: usize,
+  /// Number of input frames on each side of an output frame that are used
+  half_width:   usize,
+  /// Values of the windowed sinc function from its center to its end
+  table:        Vec<f64>,
+  /// Input frames kept for the output frames still to be computed, starting
+  /// at frame `offset` of the input, followed by the samples of an incomplete
+  /// frame
+  input:        Vec<Sample>,
+  offset:       u64,
+  /// Number of complete input frames received and output frames returned
+  frames_in:    u64,
+  frames_out:   u64
+}
+
+impl Resampler {
+  /// Creates a `Resampler` for audio with the given number of channels.
+  pub fn new(channels: u32, from_rate: u32, to_rate: u32, quality: ResampleQuality) -> AudioResult<Resampler> {
+    if channels == 0 {
+      return Err(AudioError::Format(
+        "Cannot resample audio without channels".to_string()
+      ));
+    }
+    if from_rate == 0 || to_rate == 0 {
+      return Err(AudioError::Format(
+        "Cannot resample audio to or from a sample rate of 0 Hz".to_string()
+      ));
+    }
+    let divisor = gcd(from_rate as u64, to_rate as u64);
+    let (zero_crossings, beta, cutoff) = quality.parameters();
+    let cutoff = cutoff * (to_rate as f64 / from_rate as f64).min(1f64);
+    let half_width = (zero_crossings as f64 / cutoff).ceil() as usize;
+    let table: Vec<f64> =
+      (0 .. zero_crossings * TABLE_RESOLUTION + 2)
+      .map(|i| {
+        let x = i as f64 / TABLE_RESOLUTION as f64;
+        sinc(x) * kaiser(x / zero_crossings as f64, beta)
+      })
+      .collect();
+    Ok(Resampler {
+      channels:       channels as usize,
+      from_rate:      from_rate as u64 / divisor,
+      to_rate:        to_rate as u64 / divisor,
+      cutoff:         cutoff,
+      zero_crossings: zero_crossings,
+      half_width:     half_width,
+      table:          table,
+      input:          Vec::new(),
+      offset:         0,
+      frames_in:      0,
+      frames_out:     0
+    })
+  }
+
+  /// Resamples a block of interleaved frames, returning the frames that are
+  /// no longer affected by later input.
+  pub fn process(&mut self, samples: &[Sample]) -> Vec<Sample> {
+    self.input.extend_from_slice(samples);
+    self.frames_in = self.offset + (self.input.len() / self.channels) as u64;
+    let mut output = Vec::new();
+    // An output frame is computed once every input frame it uses is known.
+    while self.position(self.frames_out).0 + (self.half_width as u64) < self.frames_in {
+      self.compute(&mut output);
+    }
+    self.discard();
+    output
+  }
+
+  /// Returns the remaining frames once all input has been processed, treating
+  /// the audio as silent past its end. The `Resampler` is then reset.
+  pub fn flush(&mut self) -> Vec<Sample> {
+    // The output holds as many frames as fit within the duration of the input.
+    let total = (self.frames_in * self.to_rate + self.from_rate - 1) / self.from_rate;
+    let mut output = Vec::new();
+    while self.frames_out < total {
+      self.compute(&mut output);
+    }
+    self.reset();
+    output
+  }
+
+  /// Clears all state, so the `Resampler` can be used for new audio.
+  pub fn reset(&mut self) {
+    self.input.clear();
+    self.offset     = 0;
+    self.frames_in  = 0;
+    self.frames_out = 0;
+  }
+
+  /// Returns the ratio of the output sample rate to the input sample rate.
+  pub fn ratio(&self) -> f64 {
+    self.to_rate as f64 / self.from_rate as f64
+  }
+
+  /// Returns the group delay of the filter in output frames. The delay is
+  /// compensated, so the output is aligned with the input.
+  pub fn group_delay(&self) -> f64 {
+    self.zero_crossings as f64 / self.cutoff * self.ratio()
+  }
+
+  /// Returns the number of output frames held back by `process` to compensate
+  /// for the group delay of the filter, until later input is received.
+  pub fn latency(&self) -> usize {
+    (self.half_width as f64 * self.ratio()).ceil() as usize
+  }
+
+  /// Returns the input frame preceding an output frame and the time between
+  /// them, as a fraction of the `to_rate`.
+  #[inline]
+  fn position(&self, frame: u64) -> (u64, u64) {
+    let time = frame * self.from_rate;
+    (time / self.to_rate, time % self.to_rate)
+  }
+
+  /// Computes the next output frame from the surrounding input frames.
+  fn compute(&mut self, output: &mut Vec<Sample>) {
+    let (index, remainder) = self.position(self.frames_out);
+    let fraction = remainder as f64 / self.to_rate as f64;
+    let start    = output.len();
+    output.resize(start + self.channels, 0f32);
+    let mut sums = vec![0f64; self.channels];
+    let mut total_weight = 0f64;
+    let first = index as i64 - self.half_width as i64 + 1;
+    for k in first .. index as i64 + self.half_width as i64 + 1 {
+      let distance = ((index as i64 - k) as f64 + fraction).abs() * self.cutoff;
+      let weight   = self.filter(distance);
+      total_weight += weight;
+      // Frames before the start and past the end of the input are silent.
+      if k < self.offset as i64 || k >= self.frames_in as i64 || weight == 0f64 {
+        continue;
+      }
+      let frame = (k as u64 - self.offset) as usize * self.channels;
+      for (sum, &sample) in sums.iter_mut().zip(self.input[frame .. frame + self.channels].iter()) {
+        *sum += sample as f64 * weight;
+      }
+    }
+    // Weights are normalized so constant input is kept at the same level.
+    for (out, sum) in output[start..].iter_mut().zip(sums.iter()) {
+      *out = if total_weight != 0f64 { (sum / total_weight) as f32 } else { 0f32 };
+    }
+    self.frames_out += 1;
+  }
+
+  /// Returns the value of the filter at a distance from its center, given in
+  /// zero crossings, by interpolating its table.
+  #[inline]
+  fn filter(&self, distance: f64) -> f64 {
+    if distance >= self.zero_crossings as f64 {
+      return 0f64;
+    }
+    let position = distance * TABLE_RESOLUTION as f64;
+    let i = position as usize;
+    let t = position - i as f64;
+    self.table[i] + (self.table[i + 1] - self.table[i]) * t
+  }
+
+  /// Removes input frames that are not used by the remaining output frames.
+  fn discard(&mut self) {
+    let (index, _) = self.position(self.frames_out);
+    let needed = (index + 1).saturating_sub(self.half_width as u64);
+    if needed > self.offset {
+      let count = ((needed - self.offset) as usize * self.channels).min(self.input.len());
+      self.input.drain(..count);
+      self.offset = needed;
+    }
+  }
+}
+
+/// Resamples interleaved frames from one sample rate to another. Frames
+/// already at the output sample rate are returned unchanged.
+pub fn convert(samples: &[Sample], channels: u32, from_rate: u32, to_rate: u32,
+               quality: ResampleQuality) -> AudioResult<Vec<Sample>> {
+  let mut resampler = try!(Resampler::new(channels, from_rate, to_rate, quality));
+  if from_rate == to_rate {
+    return Ok(samples.to_vec());
+  }
+  let mut output = resampler.process(samples);
+  output.extend(resampler.flush());
+  Ok(output)
+}
+
+/// Resamples the audio of an `AudioBuffer`, scaling the frames of loops in
+/// its metadata to the new sample rate.
+pub fn resample(audio: &mut AudioBuffer, sample_rate: u32, quality: ResampleQuality) -> AudioResult<()> {
+  let samples = try!(convert(&audio.samples, audio.channels, audio.sample_rate, sample_rate, quality));
+  let ratio = sample_rate as f64 / audio.sample_rate as f64;
+  let scale = |frame: u32| (frame as f64 * ratio).round() as u32;
+  if let Some(ref mut instrument) = audio.metadata.instrument {
+    for l in instrument.sustain_loop.iter_mut().chain(instrument.release_loop.iter_mut()) {
+      l.start = scale(l.start);
+      l.end   = scale(l.end);
+    }
+  }
+  audio.samples     = samples;
+  audio.sample_rate = sample_rate;
+  Ok(())
+}
+
+/// Returns the normalized sinc function, sin(πx) / πx.
+#[inline]
+pub fn sinc(x: f64) -> f64 {
+  if x == 0f64 {
+    1f64
+  } else {
+    (PI * x).sin() / (PI * x)
+  }
+}
+
+/// Returns the Kaiser window at a position from -1 to 1.
+pub fn kaiser(x: f64, beta: f64) -> f64 {
+  if x.abs() > 1f64 {
+    return 0f64;
+  }
+  bessel_i0(beta * (1f64 - x * x).sqrt()) / bessel_i0(beta)
+}
+
+// Private functions
+
+/// Returns the greatest common divisor of two integers.
+fn gcd(a: u64, b: u64) -> u64 {
+  if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Returns the zeroth-order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+  let mut sum  = 1f64;
+  let mut term = 1f64;
+  let mut k    = 1f64;
+  while term > sum * 1e-12 {
+    term *= (x / (2f64 * k)) * (x / (2f64 * k));
+    sum  += term;
+    k    += 1f64;
+  }
+  sum
+}
+
+#[cfg(test)]
+mod resampling {
+  use std::f64::consts::PI;
+  use ::buffer::AudioBuffer;
+  use ::metadata::{Instrument, Loop, LoopMode};
+  use ::testing::{Sine, signal};
+  use super::*;
+
+  fn sine(sample_rate: u32, channels: u32, frequency: f64, frames: usize) -> AudioBuffer {
+    signal(sample_rate, frames, &vec![Sine::new(frequency, 0.5); channels as usize])
+  }
+
+  #[test]
+  fn upsample() {
+    let mut audio = sine(44100, 2, 1000f64, 44100);
+    audio.resample(48000, ResampleQuality::High).unwrap();
+    assert_eq!(48000, audio.sample_rate);
+    assert_eq!(96000, audio.samples.len());
+    // Away from the edges, the audio matches the same sine at the new rate.
+    let expected = sine(48000, 2, 1000f64, 48000);
+    for (sample, expected) in audio.samples.iter().zip(expected.samples.iter())
+                              .skip(2000).take(92000) {
+      assert!((sample - expected).abs() < 1e-3);
+    }
+  }
+
+  #[test]
+  fn downsample() {
+    // Frequencies above the new Nyquist frequency are removed.
+    let mut audio = sine(48000, 1, 6000f64, 4800);
+    audio.resample(8000, ResampleQuality::Medium).unwrap();
+    assert_eq!(800, audio.samples.len());
+    let rms = (audio.samples[100..700].iter().map(|s| s * s).sum::<f32>() / 600f32).sqrt();
+    assert!(rms < 1e-3);
+
+    let mut audio = sine(48000, 1, 1000f64, 4800);
+    audio.resample(8000, ResampleQuality::Medium).unwrap();
+    let expected = sine(8000, 1, 1000f64, 800);
+    for (sample, expected) in audio.samples.iter().zip(expected.samples.iter())
+                              .skip(100).take(600) {
+      assert!((sample - expected).abs() < 5e-3);
+    }
+  }
+
+  #[test]
+  fn arbitrary_ratio() {
+    let mut audio = sine(8000, 1, 440f64, 8000);
+    audio.metadata.instrument = Some(Instrument {
+      sustain_loop: Some(Loop {
+        mode:       LoopMode::Forward,
+        start:      1000,
+        end:        7999,
+        play_count: 0
+      }),
+      ..Instrument::default()
+    });
+    audio.resample(11025, ResampleQuality::Low).unwrap();
+    assert_eq!(11025, audio.samples.len());
+    let sustain_loop = audio.metadata.instrument.unwrap().sustain_loop.unwrap();
+    assert_eq!(1378,  sustain_loop.start);
+    assert_eq!(11024, sustain_loop.end);
+  }
+
+  #[test]
+  fn streaming() {
+    let audio = sine(44100, 2, 3000f64, 5000);
+    let mut expected = audio.clone();
+    expected.resample(32000, ResampleQuality::Best).unwrap();
+
+    let mut resampler = Resampler::new(2, 44100, 32000, ResampleQuality::Best).unwrap();
+    let mut samples = Vec::new();
+    let mut position = 0;
+    for (i, size) in [1, 7, 300, 0, 1024, 2000].iter().cycle().enumerate() {
+      let end = (position + size * 2).min(audio.samples.len());
+      let output = resampler.process(&audio.samples[position .. end]);
+      if i == 0 {
+        assert!(output.is_empty());
+      }
+      samples.extend(output);
+      position = end;
+      if position == audio.samples.len() {
+        break;
+      }
+    }
+    // Frames are held back by the latency of the resampler until flushed.
+    let held = resampler.flush();
+    assert!(held.len() / 2 <= resampler.latency() + 1);
+    samples.extend(held);
+    assert_eq!(expected.samples, samples);
+
+    // Blocks ending within a frame are completed by the next block.
+    let mut samples = Vec::new();
+    for block in audio.samples.chunks(333) {
+      samples.extend(resampler.process(block));
+    }
+    samples.extend(resampler.flush());
+    assert_eq!(expected.samples, samples);
+  }
+
+  #[test]
+  fn delay() {
+    let resampler = Resampler::new(1, 48000, 96000, ResampleQuality::High).unwrap();
+    assert_eq!(2f64, resampler.ratio());
+    assert!((resampler.group_delay() - 64f64 / 0.95).abs() < 1e-9);
+    assert_eq!(68, resampler.latency());
+    let resampler = Resampler::new(1, 96000, 48000, ResampleQuality::Low).unwrap();
+    assert_eq!(10, resampler.latency());
+
+    // An impulse is not delayed.
+    let mut audio = AudioBuffer::from_samples(48000, 1, vec![0f32; 201]);
+    audio.samples[100] = 1f32;
+    audio.resample(96000, ResampleQuality::High).unwrap();
+    let peak = (0 .. audio.samples.len())
+               .max_by(|&a, &b| audio.samples[a].partial_cmp(&audio.samples[b]).unwrap())
+               .unwrap();
+    assert_eq!(200, peak);
+  }
+
+  #[test]
+  fn upsample_sine() {
+    let input: Vec<f32> = (0..800).map(|i| (2f64 * PI * 440f64 * i as f64 / 8000f64).sin() as f32)
+                                  .collect();
+    let output = convert(&input, 1, 8000, 48000, ResampleQuality::Medium).unwrap();
+    assert_eq!(4800, output.len());
+    // Ignores the edges, where the filter reaches past the input.
+    for i in 200..4600 {
+      let expected = (2f64 * PI * 440f64 * i as f64 / 48000f64).sin() as f32;
+      assert!((output[i] - expected).abs() < 0.01);
+    }
+  }
+
+  #[test]
+  fn downsample_removes_high_frequencies() {
+    let input: Vec<f32> = (0..4410).map(|i| {
+      let t = i as f64 / 44100f64;
+      (0.5 * (2f64 * PI * 1000f64 * t).sin() + 0.5 * (2f64 * PI * 15000f64 * t).sin()) as f32
+    }).collect();
+    let output = convert(&input, 1, 44100, 16000, ResampleQuality::Medium).unwrap();
+    assert_eq!(1600, output.len());
+    for i in 100..1500 {
+      let expected = (0.5 * (2f64 * PI * 1000f64 * i as f64 / 16000f64).sin()) as f32;
+      assert!((output[i] - expected).abs() < 0.02);
+    }
+  }
+
+  #[test]
+  fn interleaved_channels() {
+    let input: Vec<f32> = (0..200).map(|i| if i % 2 == 0 { 0.5 } else { -0.25 }).collect();
+    let output = convert(&input, 2, 24000, 48000, ResampleQuality::Medium).unwrap();
+    assert_eq!(400, output.len());
+    assert!((output[100] - 0.5).abs() < 1e-3);
+    assert!((output[101] + 0.25).abs() < 1e-3);
+  }
+
+  #[test]
+  fn errors() {
+    assert!(Resampler::new(0, 44100, 48000, ResampleQuality::High).is_err());
+    let mut audio = AudioBuffer::from_samples(44100, 1, vec![0f32; 4]);
+    assert!(audio.resample(0, ResampleQuality::High).is_err());
+    // Audio already at the sample rate is unchanged.
+    audio.samples[1] = 0.5f32;
+    audio.resample(44100, ResampleQuality::High).unwrap();
+    assert_eq!(vec![0f32, 0.5f32, 0f32, 0f32], audio.samples);
+  }
+}
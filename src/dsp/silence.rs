@@ -0,0 +1,296 @@
+//! Silence Detection
+//!
+//! A frame is silent when every channel is at or below a threshold given in
+//! dBFS. Runs of silent frames shorter than a minimum duration, such as the
+//! gaps between words, are not treated as silence. The sound on either side
+//! of a silence is held for a time, shortening the silence so the attack and
+//! decay of the sound are kept when the silence is removed. Silence at the
+//! start and end of the audio is only shortened on the side next to sound.
+//!
+//! Silences are given as ranges of frames, which are used to trim silence
+//! from the ends of audio or to split audio into the parts between silences.
+
+use std::ops::Range;
+use std::time::Duration;
+use buffer::AudioBuffer;
+use dsp::fade::{db_to_gain, frames_in};
+use error::*;
+use metadata::{Loop, Metadata};
+use sample::Sample;
+
+/// Describes what is detected as silence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SilenceSpec {
+  /// Level in dBFS at or below which a frame is silent
+  pub threshold:    f64,
+  /// Shortest run of silent frames that is detected as silence
+  pub min_duration: Duration,
+  /// Time the sound on either side of a silence is held for
+  pub hold:         Duration
+}
+
+impl SilenceSpec {
+  /// Creates a `SilenceSpec` without any hold time.
+  pub fn new(threshold: f64, min_duration: Duration) -> SilenceSpec {
+    SilenceSpec {
+      threshold:    threshold,
+      min_duration: min_duration,
+      hold:         Duration::from_millis(0)
+    }
+  }
+}
+
+impl Default for SilenceSpec {
+  /// Silence of at least half a second at or below -60 dBFS.
+  fn default() -> Self {
+    SilenceSpec::new(-60f64, Duration::from_millis(500))
+  }
+}
+
+/// Detects silence in interleaved frames as they are streamed.
+///
+/// Blocks of any size are passed to `process`, which returns the silences
+/// that have ended. Once all input has been passed, `flush` returns the
+/// silence at the end of the audio, if any.
+#[derive(Clone, Debug)]
+pub struct SilenceDetector {
+  channels:     usize,
+  /// Threshold as a linear level
+  threshold:    f64,
+  min_frames:   usize,
+  hold_frames:  usize,
+  /// Number of frames processed
+  position:     usize,
+  /// First frame of the current run of silent frames
+  run_start:    Option<usize>
+}
+
+impl SilenceDetector {
+  /// Creates a `SilenceDetector` for audio with the given number of channels
+  /// and sample rate.
+  pub fn new(channels: u32, sample_rate: u32, spec: SilenceSpec) -> AudioResult<SilenceDetector> {
+    if channels == 0 {
+      return Err(AudioError::Format(
+        "Cannot detect silence in audio without channels".to_string()
+      ));
+    }
+    Ok(SilenceDetector {
+      channels:     channels as usize,
+      threshold:    db_to_gain(spec.threshold),
+      min_frames:   frames_in(spec.min_duration, sample_rate).max(1),
+      hold_frames:  frames_in(spec.hold, sample_rate),
+      position:     0,
+      run_start:    None
+    })
+  }
+
+  /// Detects silence in a block of interleaved frames, returning the
+  /// silences ended by the block. Samples of an incomplete frame at the end
+  /// of the block are ignored.
+  pub fn process(&mut self, samples: &[Sample]) -> Vec<Range<usize>> {
+    let mut silences = Vec::new();
+    let channels = self.channels;
+    for frame in samples.chunks(channels).filter(|frame| frame.len() == channels) {
+      let silent = frame.iter().all(|&sample| (sample as f64).abs() <= self.threshold);
+      match (silent, self.run_start) {
+        (true, None) => self.run_start = Some(self.position),
+        (false, Some(start)) => {
+          if let Some(silence) = self.silence(start, self.position, true) {
+            silences.push(silence);
+          }
+          self.run_start = None;
+        },
+        _ => {}
+      }
+      self.position += 1;
+    }
+    silences
+  }
+
+  /// Returns the silence at the end of the audio, if any. The
+  /// `SilenceDetector` is then reset.
+  pub fn flush(&mut self) -> Option<Range<usize>> {
+    let silence =
+      self.run_start.and_then(|start| self.silence(start, self.position, false));
+    self.reset();
+    silence
+  }
+
+  /// Clears all state, so the `SilenceDetector` can be used for new audio.
+  pub fn reset(&mut self) {
+    self.position  = 0;
+    self.run_start = None;
+  }
+
+  /// Returns the silence of a run of silent frames, if it lasts long enough
+  /// once the sound around it is held.
+  fn silence(&self, start: usize, end: usize, sound_after: bool) -> Option<Range<usize>> {
+    if end - start < self.min_frames {
+      return None;
+    }
+    let start = if start > 0   { start + self.hold_frames } else { start };
+    let end   = if sound_after { end.saturating_sub(self.hold_frames) } else { end };
+    if start < end { Some(start .. end) } else { None }
+  }
+}
+
+/// Returns the silences of an `AudioBuffer` in order.
+pub fn detect(audio: &AudioBuffer, spec: SilenceSpec) -> AudioResult<Vec<Range<usize>>> {
+  let mut detector = try!(SilenceDetector::new(audio.channels, audio.sample_rate, spec));
+  let mut silences = detector.process(&audio.samples);
+  silences.extend(detector.flush());
+  Ok(silences)
+}
+
+/// Removes the silence at the start and end of an `AudioBuffer`. Loops in
+/// the metadata are moved with the audio, and removed if they no longer fit.
+pub fn trim(audio: &mut AudioBuffer, spec: SilenceSpec) -> AudioResult<()> {
+  let silences = try!(detect(audio, spec));
+  let channels = audio.channels as usize;
+  let frames   = audio.samples.len() / channels;
+  let start =
+    silences.first().filter(|s| s.start == 0).map(|s| s.end).unwrap_or(0);
+  let end =
+    silences.last().filter(|s| s.end == frames).map(|s| s.start).unwrap_or(frames)
+    .max(start);
+  audio.samples.truncate(end * channels);
+  audio.samples.drain(.. start * channels);
+  if let Some(ref mut instrument) = audio.metadata.instrument {
+    let (start, end) = (start as u32, end as u32);
+    let shift = |l: Loop| {
+      if l.start >= start && l.end >= l.start && l.end < end {
+        Some(Loop { start: l.start - start, end: l.end - start, ..l })
+      } else {
+        None
+      }
+    };
+    instrument.sustain_loop = instrument.sustain_loop.and_then(&shift);
+    instrument.release_loop = instrument.release_loop.and_then(&shift);
+  }
+  Ok(())
+}
+
+/// Splits an `AudioBuffer` into the parts between its silences, removing the
+/// silences. The parts do not keep the metadata of the audio.
+pub fn split(audio: &AudioBuffer, spec: SilenceSpec) -> AudioResult<Vec<AudioBuffer>> {
+  let silences = try!(detect(audio, spec));
+  let channels = audio.channels as usize;
+  let frames   = audio.samples.len() / channels;
+  let mut parts = Vec::with_capacity(silences.len() + 1);
+  let mut start = 0;
+  for bound in silences.iter().map(|s| s.clone()).chain(Some(frames .. frames)) {
+    if bound.start > start {
+      parts.push(AudioBuffer {
+        sample_rate: audio.sample_rate,
+        channels:    audio.channels,
+        samples:     audio.samples[start * channels .. bound.start * channels].to_vec(),
+        metadata:    Metadata::default()
+      });
+    }
+    start = bound.end;
+  }
+  Ok(parts)
+}
+
+#[cfg(test)]
+mod detection {
+  use std::fs;
+  use std::path::Path;
+  use std::time::Duration;
+  use ::audio;
+  use ::buffer::AudioBuffer;
+  use ::metadata::{Instrument, Loop, LoopMode};
+  use super::*;
+
+  /// Returns stereo audio at 100 Hz, with sound in the given ranges of
+  /// frames and silence elsewhere.
+  fn bursts(frames: usize, sound: &[Range<usize>]) -> AudioBuffer {
+    let mut samples = vec![0f32; frames * 2];
+    for range in sound.iter() {
+      for frame in range.clone() {
+        samples[frame * 2 + 1] = if frame % 2 == 0 { 0.5 } else { -0.5 };
+      }
+    }
+    AudioBuffer::from_samples(100, 2, samples)
+  }
+
+  #[test]
+  fn silences() {
+    let audio = bursts(100, &[10..30, 35..40, 70..80]);
+    let spec = SilenceSpec::new(-40f64, Duration::from_millis(100));
+    // The gap of 5 frames is shorter than the minimum duration.
+    assert_eq!(vec![0..10, 40..70, 80..100], audio.silences(spec).unwrap());
+    let held = SilenceSpec { hold: Duration::from_millis(20), ..spec };
+    assert_eq!(vec![0..8, 42..68, 82..100], audio.silences(held).unwrap());
+    // Noise at -60 dBFS is only silent at a higher threshold.
+    let mut noisy = audio.clone();
+    for sample in noisy.samples.iter_mut().filter(|s| **s == 0f32) {
+      *sample = 0.001;
+    }
+    assert_eq!(3, noisy.silences(spec).unwrap().len());
+    assert_eq!(Vec::<Range<usize>>::new(),
+               noisy.silences(SilenceSpec::new(-70f64, Duration::from_millis(100))).unwrap());
+    let silent = bursts(50, &[]);
+    assert_eq!(vec![0..50], silent.silences(spec).unwrap());
+  }
+
+  #[test]
+  fn streaming() {
+    let audio = bursts(100, &[10..30, 70..80]);
+    let spec = SilenceSpec { hold: Duration::from_millis(30), ..SilenceSpec::default() };
+    let spec = SilenceSpec { min_duration: Duration::from_millis(50), ..spec };
+    let mut detector = SilenceDetector::new(2, 100, spec).unwrap();
+    let mut silences = Vec::new();
+    for block in audio.samples.chunks(14) {
+      silences.extend(detector.process(block));
+    }
+    silences.extend(detector.flush());
+    assert_eq!(audio.silences(spec).unwrap(), silences);
+    assert_eq!(vec![0..7, 33..67, 83..100], silences);
+    assert_eq!(None, detector.flush());
+    assert!(SilenceDetector::new(0, 100, spec).is_err());
+  }
+
+  #[test]
+  fn trimming() {
+    let mut audio = bursts(100, &[10..30, 70..80]);
+    audio.metadata.instrument = Some(Instrument {
+      sustain_loop: Some(Loop { mode: LoopMode::Forward, start: 20, end: 29, play_count: 0 }),
+      release_loop: Some(Loop { mode: LoopMode::Forward, start: 75, end: 90, play_count: 1 }),
+      ..Instrument::default()
+    });
+    audio.trim_silence(SilenceSpec::new(-40f64, Duration::from_millis(100))).unwrap();
+    assert_eq!(70 * 2, audio.samples.len());
+    assert_eq!(0.5, audio.samples[1]);
+    let instrument = audio.metadata.instrument.unwrap();
+    assert_eq!(Some(Loop { mode: LoopMode::Forward, start: 10, end: 19, play_count: 0 }),
+               instrument.sustain_loop);
+    assert_eq!(None, instrument.release_loop);
+    let mut silent = bursts(50, &[]);
+    silent.trim_silence(SilenceSpec::default()).unwrap();
+    assert_eq!(0, silent.samples.len());
+  }
+
+  #[test]
+  fn splitting() {
+    let audio = bursts(100, &[10..30, 70..80]);
+    let spec = SilenceSpec::new(-40f64, Duration::from_millis(100));
+    let parts = audio.split_at_silence(spec).unwrap();
+    assert_eq!(2, parts.len());
+    assert_eq!(20 * 2, parts[0].samples.len());
+    assert_eq!(10 * 2, parts[1].samples.len());
+    assert_eq!(&audio.samples[140..160], &parts[1].samples[..]);
+
+    fs::create_dir_all("tests/results").unwrap();
+    for ext in ["wav", "aiff"].iter() {
+      let path = format!("tests/results/tmp_prompt.{}", ext);
+      let paths = audio::save_split(Path::new(&path), &audio, spec).unwrap();
+      assert_eq!(vec![Path::new(&format!("tests/results/tmp_prompt-001.{}", ext)).to_path_buf(),
+                      Path::new(&format!("tests/results/tmp_prompt-002.{}", ext)).to_path_buf()],
+                 paths);
+      let verify = audio::open(&paths[1]).unwrap();
+      assert_eq!(100, verify.sample_rate);
+      assert_eq!(10 * 2, verify.samples.len());
+    }
+  }
+}
@@ -0,0 +1,238 @@
+//! Signal Statistics
+//!
+//! Statistics of each channel are gathered in a single pass over the frames,
+//! so they can be computed while audio is streamed. Levels are linear values
+//! where 1 is full scale, and the true peak is measured by oversampling four
+//! times as described by ITU-R BS.1770.
+//!
+//! References
+//! - [SoX, the stats effect](http://sox.sourceforge.net/sox.html)
+//! - [Crest Factor](https://en.wikipedia.org/wiki/Crest_factor)
+
+use buffer::AudioBuffer;
+use dsp::peak::TruePeakMeter;
+use error::*;
+use sample::Sample;
+
+/// Statistics of a single channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelStats {
+  /// Smallest sample
+  pub min:          f64,
+  /// Largest sample
+  pub max:          f64,
+  /// Largest absolute value of the samples
+  pub peak:         f64,
+  /// Largest absolute value between the samples, or of the samples
+  pub true_peak:    f64,
+  /// Root mean square of the samples
+  pub rms:          f64,
+  /// Mean of the samples
+  pub dc_offset:    f64,
+  /// Ratio of the peak to the RMS, or 0 for silence
+  pub crest_factor: f64,
+  /// Number of samples at or beyond full scale
+  pub clipped:      usize
+}
+
+impl ChannelStats {
+  /// Returns the peak in dBFS.
+  pub fn peak_db(&self) -> f64 {
+    20f64 * self.peak.log10()
+  }
+
+  /// Returns the true peak in dBTP.
+  pub fn true_peak_db(&self) -> f64 {
+    20f64 * self.true_peak.log10()
+  }
+
+  /// Returns the RMS in dBFS.
+  pub fn rms_db(&self) -> f64 {
+    20f64 * self.rms.log10()
+  }
+}
+
+/// Statistics of each channel of audio.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+  /// Number of frames measured
+  pub frames:   usize,
+  /// Statistics of each channel, in order
+  pub channels: Vec<ChannelStats>
+}
+
+impl Stats {
+  /// Returns the largest peak of all channels.
+  pub fn peak(&self) -> f64 {
+    self.channels.iter().map(|c| c.peak).fold(0f64, f64::max)
+  }
+
+  /// Returns the largest true peak of all channels.
+  pub fn true_peak(&self) -> f64 {
+    self.channels.iter().map(|c| c.true_peak).fold(0f64, f64::max)
+  }
+
+  /// Returns the number of clipped samples in all channels.
+  pub fn clipped(&self) -> usize {
+    self.channels.iter().map(|c| c.clipped).sum()
+  }
+}
+
+/// Gathers the statistics of interleaved frames as they are streamed.
+#[derive(Clone, Debug)]
+pub struct StatsMeter {
+  channels:     usize,
+  frames:       usize,
+  /// Sums of the samples and of their squares, for each channel
+  sums:         Vec<f64>,
+  squares:      Vec<f64>,
+  mins:         Vec<f64>,
+  maxs:         Vec<f64>,
+  clipped:      Vec<usize>,
+  peak:         TruePeakMeter
+}
+
+impl StatsMeter {
+  /// Creates a `StatsMeter` for audio with the given number of channels.
+  pub fn new(channels: u32) -> AudioResult<StatsMeter> {
+    let peak = try!(TruePeakMeter::new(channels));
+    let channels = channels as usize;
+    Ok(StatsMeter {
+      channels:     channels,
+      frames:       0,
+      sums:         vec![0f64; channels],
+      squares:      vec![0f64; channels],
+      mins:         vec![0f64; channels],
+      maxs:         vec![0f64; channels],
+      clipped:      vec![0; channels],
+      peak:         peak
+    })
+  }
+
+  /// Measures a block of interleaved frames. Samples of an incomplete frame
+  /// at the end of the block are ignored.
+  pub fn process(&mut self, samples: &[Sample]) {
+    self.peak.process(samples);
+    let channels = self.channels;
+    for frame in samples.chunks(channels).filter(|frame| frame.len() == channels) {
+      for (c, &sample) in frame.iter().enumerate() {
+        let sample = sample as f64;
+        self.sums[c]    += sample;
+        self.squares[c] += sample * sample;
+        if self.frames == 0 || sample < self.mins[c] {
+          self.mins[c] = sample;
+        }
+        if self.frames == 0 || sample > self.maxs[c] {
+          self.maxs[c] = sample;
+        }
+        if sample.abs() >= 1f64 {
+          self.clipped[c] += 1;
+        }
+      }
+      self.frames += 1;
+    }
+  }
+
+  /// Returns the statistics of the audio measured so far.
+  pub fn stats(&self) -> Stats {
+    let true_peaks = self.peak.channel_peaks();
+    let frames = self.frames.max(1) as f64;
+    let channels =
+      (0..self.channels)
+      .map(|c| {
+        let peak = self.mins[c].abs().max(self.maxs[c].abs());
+        let rms  = (self.squares[c] / frames).sqrt();
+        ChannelStats {
+          min:          self.mins[c],
+          max:          self.maxs[c],
+          peak:         peak,
+          true_peak:    true_peaks[c],
+          rms:          rms,
+          dc_offset:    self.sums[c] / frames,
+          crest_factor: if rms > 0f64 { peak / rms } else { 0f64 },
+          clipped:      self.clipped[c]
+        }
+      })
+      .collect();
+    Stats {
+      frames:   self.frames,
+      channels: channels
+    }
+  }
+
+  /// Clears all state, so the `StatsMeter` can be used for new audio.
+  pub fn reset(&mut self) {
+    self.frames = 0;
+    for c in 0..self.channels {
+      self.sums[c]    = 0f64;
+      self.squares[c] = 0f64;
+      self.mins[c]    = 0f64;
+      self.maxs[c]    = 0f64;
+      self.clipped[c] = 0;
+    }
+    self.peak.reset();
+  }
+}
+
+/// Gathers the statistics of an `AudioBuffer`.
+pub fn analyze(audio: &AudioBuffer) -> AudioResult<Stats> {
+  let mut meter = try!(StatsMeter::new(audio.channels));
+  meter.process(&audio.samples);
+  Ok(meter.stats())
+}
+
+#[cfg(test)]
+mod statistics {
+  use std::f64::consts::PI;
+  use ::buffer::AudioBuffer;
+  use super::*;
+
+  #[test]
+  fn levels() {
+    // A sine has a crest factor of √2, here with a DC offset in the second
+    // channel and a square wave clipping in the third.
+    let samples: Vec<f32> =
+      (0..48000).flat_map(|i| {
+        let sine = (2f64 * PI * 1000f64 * i as f64 / 48000f64).sin() * 0.5;
+        vec![sine as f32, (sine + 0.25) as f32, if i % 2 == 0 { 1f32 } else { -1f32 }]
+      })
+      .collect();
+    let audio = AudioBuffer::from_samples(48000, 3, samples);
+    let stats = audio.analyze().unwrap();
+    assert_eq!(48000, stats.frames);
+    let sine = stats.channels[0];
+    assert!((0.5 - sine.peak).abs() < 1e-3);
+    assert!((-0.5 - sine.min).abs() < 1e-3);
+    assert!((0.5 / 2f64.sqrt() - sine.rms).abs() < 1e-4);
+    assert!((2f64.sqrt() - sine.crest_factor).abs() < 1e-3);
+    assert!(sine.dc_offset.abs() < 1e-6);
+    assert!((sine.peak_db() - 20f64 * 0.5f64.log10()).abs() < 1e-2);
+    assert_eq!(0, sine.clipped);
+    let offset = stats.channels[1];
+    assert!((0.25 - offset.dc_offset).abs() < 1e-6);
+    assert!((0.75 - offset.max).abs() < 1e-3);
+    assert!((-0.25 - offset.min).abs() < 1e-3);
+    let square = stats.channels[2];
+    assert_eq!(48000, square.clipped);
+    assert_eq!(48000, stats.clipped());
+    assert!((1f64 - square.crest_factor).abs() < 1e-9);
+    assert!(stats.true_peak() >= stats.peak());
+  }
+
+  #[test]
+  fn streaming() {
+    let samples: Vec<f32> = (0..10000).map(|i| ((i as f32) * 0.37).sin() * 0.9).collect();
+    let audio = AudioBuffer::from_samples(44100, 2, samples);
+    let mut meter = StatsMeter::new(2).unwrap();
+    for block in audio.samples.chunks(98) {
+      meter.process(block);
+    }
+    assert_eq!(audio.analyze().unwrap(), meter.stats());
+    meter.reset();
+    let empty = meter.stats();
+    assert_eq!(0, empty.frames);
+    assert_eq!(0f64, empty.channels[1].rms);
+    assert_eq!(0f64, empty.channels[1].crest_factor);
+    assert!(StatsMeter::new(0).is_err());
+  }
+}
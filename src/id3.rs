@@ -0,0 +1,509 @@
+//! ID3v2
+//!
+//! ID3v2 tags store track metadata such as the title, artist, album, and
+//! cover art. They are embedded in WAVE files using the `id3 ` chunk and in
+//! AIFF files using the `ID3 ` chunk. Versions 2.3 and 2.4 are supported.
+//!
+//! A tag is a header followed by a list of frames. Text and picture frames
+//! are decoded, and all other frames are kept as raw bytes so they are
+//! written back unchanged.
+//!
+//! References
+//! - [ID3v2.3](http://id3.org/id3v2.3.0)
+//! - [ID3v2.4 Structure](http://id3.org/id3v2.4.0-structure)
+//! - [ID3v2.4 Frames](http://id3.org/id3v2.4.0-frames)
+
+use std::io::Write;
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use error::*;
+
+/// Text encodings used by ID3v2 frames.
+const LATIN1:   u8 = 0;
+const UTF16:    u8 = 1;
+const UTF16BE:  u8 = 2;
+const UTF8:     u8 = 3;
+
+/// An ID3v2 tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Id3Tag {
+  /// Major version of the tag, either 3 or 4
+  pub version:  u8,
+  /// Frames in the order they were read
+  pub frames:   Vec<Id3Frame>
+}
+
+/// A frame of an ID3v2 tag.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Id3Frame {
+  /// A text information frame, such as `TIT2` for the title. Multiple values
+  /// are separated by null characters.
+  Text { id: [u8; 4], text: String },
+  /// A user defined text frame (`TXXX`)
+  UserText { description: String, text: String },
+  /// An attached picture frame (`APIC`)
+  Picture(Picture),
+  /// Any other frame, including compressed and encrypted frames
+  Other { id: [u8; 4], flags: u16, data: Vec<u8> }
+}
+
+/// An image attached to an ID3v2 tag, such as cover art.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Picture {
+  pub mime_type:    String,
+  /// Type of picture, where 3 is the front cover
+  pub picture_type: u8,
+  pub description:  String,
+  pub data:         Vec<u8>
+}
+
+impl Id3Frame {
+  /// Returns the four character frame identifier.
+  pub fn id(&self) -> [u8; 4] {
+    match *self {
+      Id3Frame::Text { id, .. }     => id,
+      Id3Frame::UserText { .. }     => *b"TXXX",
+      Id3Frame::Picture(_)          => *b"APIC",
+      Id3Frame::Other { id, .. }    => id
+    }
+  }
+}
+
+impl Default for Id3Tag {
+  fn default() -> Self {
+    Id3Tag {
+      version:  4,
+      frames:   Vec::new()
+    }
+  }
+}
+
+impl Id3Tag {
+  /// Returns the text of the first text frame with the given identifier.
+  pub fn text(&self, id: &str) -> Option<&str> {
+    self.frames.iter().filter_map(|frame|
+      match *frame {
+        Id3Frame::Text { id: ref frame_id, ref text }
+          if &frame_id[..] == id.as_bytes() => Some(&text[..]),
+        _ => None
+      }
+    ).next()
+  }
+
+  /// Sets the text of a text frame, replacing any existing frames with the
+  /// same identifier.
+  pub fn set_text(&mut self, id: &str, text: &str) {
+    let mut frame_id = [b' '; 4];
+    for (dst, src) in frame_id.iter_mut().zip(id.bytes()) {
+      *dst = src;
+    }
+    self.frames.retain(|frame| frame.id() != frame_id);
+    self.frames.push(Id3Frame::Text { id: frame_id, text: text.to_string() });
+  }
+
+  /// Returns the track title (`TIT2`).
+  #[inline]
+  pub fn title(&self) -> Option<&str> {
+    self.text("TIT2")
+  }
+
+  /// Returns the lead artist (`TPE1`).
+  #[inline]
+  pub fn artist(&self) -> Option<&str> {
+    self.text("TPE1")
+  }
+
+  /// Returns the album title (`TALB`).
+  #[inline]
+  pub fn album(&self) -> Option<&str> {
+    self.text("TALB")
+  }
+
+  /// Returns all attached pictures.
+  pub fn pictures(&self) -> Vec<&Picture> {
+    self.frames.iter().filter_map(|frame|
+      match *frame {
+        Id3Frame::Picture(ref picture) => Some(picture),
+        _ => None
+      }
+    ).collect()
+  }
+
+  /// Decodes an ID3v2 tag from bytes beginning with the tag header.
+  pub fn read(bytes: &[u8]) -> AudioResult<Id3Tag> {
+    if bytes.len() < 10 || &bytes[0..3] != b"ID3" {
+      return Err(AudioError::Format(
+        "Not a valid ID3v2 tag".to_string()
+      ))
+    }
+    let version = bytes[3];
+    if version != 3 && version != 4 {
+      return Err(AudioError::Unsupported(
+        format!("ID3v2.{} tags are not supported", version)
+      ))
+    }
+    let flags    = bytes[5];
+    let tag_size = read_syncsafe(&bytes[6..10]) as usize;
+    let tag_end  = (10 + tag_size).min(bytes.len());
+    // Version 2.3 applies unsynchronisation to the entire tag, while version
+    // 2.4 applies it to individual frames.
+    let body: Vec<u8> =
+      if version == 3 && flags & 0x80 != 0 {
+        resynchronise(&bytes[10..tag_end])
+      }
+      else {
+        bytes[10..tag_end].to_vec()
+      };
+    let mut pos = 0;
+    if flags & 0x40 != 0 && body.len() >= 4 {
+      pos =
+        if version == 3 {
+          4 + BigEndian::read_u32(&body[0..4]) as usize
+        }
+        else {
+          read_syncsafe(&body[0..4]) as usize
+        };
+    }
+
+    let mut frames = Vec::new();
+    while pos + 10 <= body.len() {
+      // The remainder of the tag is padding once a null byte is reached.
+      if body[pos] == 0 {
+        break;
+      }
+      let id = [body[pos], body[pos + 1], body[pos + 2], body[pos + 3]];
+      let size =
+        if version == 3 {
+          BigEndian::read_u32(&body[pos + 4 .. pos + 8]) as usize
+        }
+        else {
+          read_syncsafe(&body[pos + 4 .. pos + 8]) as usize
+        };
+      let frame_flags = BigEndian::read_u16(&body[pos + 8 .. pos + 10]);
+      let start = pos + 10;
+      let end   = start + size;
+      if end > body.len() {
+        return Err(AudioError::Format(
+          "ID3v2 frame exceeds the size of the tag".to_string()
+        ))
+      }
+      frames.push(try!(read_frame(id, frame_flags, &body[start..end], version)));
+      pos = end;
+    }
+    Ok(Id3Tag { version: version, frames: frames })
+  }
+
+  /// Encodes the tag. Unsynchronisation, extended headers, and padding are
+  /// not used.
+  pub fn write<W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    let version = if self.version == 3 { 3 } else { 4 };
+    let mut body: Vec<u8> = Vec::new();
+    for frame in self.frames.iter() {
+      let (flags, data) =
+        match *frame {
+          Id3Frame::Text { ref text, .. } => {
+            let mut data = Vec::new();
+            let encoding = choose_encoding(text, version);
+            data.push(encoding);
+            data.extend(encode_text(text, encoding));
+            (0, data)
+          },
+          Id3Frame::UserText { ref description, ref text } => {
+            let mut data = Vec::new();
+            let encoding = choose_encoding(&format!("{}{}", description, text), version);
+            data.push(encoding);
+            data.extend(encode_text(description, encoding));
+            data.extend(terminator(encoding));
+            data.extend(encode_text(text, encoding));
+            (0, data)
+          },
+          Id3Frame::Picture(ref picture) => {
+            let mut data = Vec::new();
+            let encoding = choose_encoding(&picture.description, version);
+            data.push(encoding);
+            data.extend(encode_text(&picture.mime_type, LATIN1));
+            data.push(0);
+            data.push(picture.picture_type);
+            data.extend(encode_text(&picture.description, encoding));
+            data.extend(terminator(encoding));
+            data.extend(picture.data.iter().cloned());
+            (0, data)
+          },
+          Id3Frame::Other { flags, ref data, .. } => (flags, data.clone())
+        };
+      try!(body.write(&frame.id()));
+      if version == 3 {
+        try!(body.write_u32::<BigEndian>(data.len() as u32));
+      }
+      else {
+        try!(body.write(&write_syncsafe(data.len() as u32)));
+      }
+      try!(body.write_u16::<BigEndian>(flags));
+      try!(body.write(&data));
+    }
+    try!(writer.write(b"ID3"));
+    try!(writer.write_u8(version));
+    try!(writer.write_u8(0));
+    try!(writer.write_u8(0));
+    try!(writer.write(&write_syncsafe(body.len() as u32)));
+    try!(writer.write_all(&body));
+    Ok(())
+  }
+}
+
+/// Returns the length of the ID3v2 tag at the start of the bytes, for
+/// formats that allow a tag to precede the audio.
+pub fn tag_len(bytes: &[u8]) -> Option<usize> {
+  if bytes.len() < 10 || &bytes[0..3] != b"ID3" || bytes[3] == 0xFF {
+    return None;
+  }
+  let size = bytes[6..10].iter().fold(0, |size, byte| size << 7 | (*byte & 0x7F) as usize);
+  let footer = if bytes[5] & 0x10 != 0 { 10 } else { 0 };
+  Some((10 + size + footer).min(bytes.len()))
+}
+
+/// Decodes a single frame. Frames that are compressed, encrypted, or use an
+/// unknown encoding are kept as raw bytes.
+fn read_frame(id: [u8; 4], flags: u16, data: &[u8], version: u8) -> AudioResult<Id3Frame> {
+  let other = || Id3Frame::Other { id: id, flags: flags, data: data.to_vec() };
+  let format_flags = flags as u8;
+  let mut data = data.to_vec();
+  if version == 4 {
+    // Compression, encryption, and grouping
+    if format_flags & 0x4C != 0 {
+      return Ok(other());
+    }
+    if format_flags & 0x01 != 0 {
+      if data.len() < 4 {
+        return Ok(other());
+      }
+      data.drain(0..4);
+    }
+    if format_flags & 0x02 != 0 {
+      data = resynchronise(&data);
+    }
+  }
+  else if format_flags & 0xE0 != 0 {
+    return Ok(other());
+  }
+  if data.is_empty() || data[0] > UTF8 {
+    return Ok(other());
+  }
+
+  let encoding = data[0];
+  let frame =
+    match &id {
+      b"TXXX" => {
+        let (description, text) = split_text(&data[1..], encoding);
+        Id3Frame::UserText {
+          description:  decode_text(description, encoding),
+          text:         decode_text(text, encoding)
+        }
+      },
+      b"APIC" => {
+        let mime_end =
+          match data[1..].iter().position(|b| *b == 0) {
+            Some(p) => 1 + p,
+            None    => return Ok(other())
+          };
+        if mime_end + 2 > data.len() {
+          return Ok(other());
+        }
+        let picture_type = data[mime_end + 1];
+        let (description, image) = split_text(&data[mime_end + 2 ..], encoding);
+        Id3Frame::Picture(Picture {
+          mime_type:    decode_text(&data[1..mime_end], LATIN1),
+          picture_type: picture_type,
+          description:  decode_text(description, encoding),
+          data:         image.to_vec()
+        })
+      },
+      _ if id[0] == b'T' => Id3Frame::Text {
+        id:   id,
+        text: decode_text(&data[1..], encoding)
+      },
+      _ => other()
+    };
+  Ok(frame)
+}
+
+/// Reads a 28-bit integer stored in four bytes with the top bit of each
+/// byte cleared.
+#[inline]
+fn read_syncsafe(bytes: &[u8]) -> u32 {
+  bytes.iter().take(4).fold(0, |n, b| (n << 7) | (*b as u32 & 0x7F))
+}
+
+#[inline]
+fn write_syncsafe(n: u32) -> [u8; 4] {
+  [((n >> 21) & 0x7F) as u8, ((n >> 14) & 0x7F) as u8,
+   ((n >> 7)  & 0x7F) as u8,  (n        & 0x7F) as u8]
+}
+
+/// Removes the null bytes inserted after each 0xFF byte by unsynchronisation.
+fn resynchronise(bytes: &[u8]) -> Vec<u8> {
+  let mut result = Vec::with_capacity(bytes.len());
+  let mut previous = 0u8;
+  for b in bytes.iter() {
+    if !(previous == 0xFF && *b == 0) {
+      result.push(*b);
+    }
+    previous = *b;
+  }
+  result
+}
+
+/// Splits bytes at the first string terminator of the given encoding.
+fn split_text(bytes: &[u8], encoding: u8) -> (&[u8], &[u8]) {
+  match encoding {
+    UTF16 | UTF16BE => {
+      let mut i = 0;
+      while i + 1 < bytes.len() {
+        if bytes[i] == 0 && bytes[i + 1] == 0 {
+          return (&bytes[..i], &bytes[i + 2 ..]);
+        }
+        i += 2;
+      }
+      (bytes, &[])
+    },
+    _ => {
+      match bytes.iter().position(|b| *b == 0) {
+        Some(i) => (&bytes[..i], &bytes[i + 1 ..]),
+        None    => (bytes, &[])
+      }
+    }
+  }
+}
+
+/// Decodes text, removing any trailing terminators.
+fn decode_text(bytes: &[u8], encoding: u8) -> String {
+  let text =
+    match encoding {
+      LATIN1 => bytes.iter().map(|b| *b as char).collect(),
+      UTF8   => String::from_utf8_lossy(bytes).into_owned(),
+      _ => {
+        let mut big_endian = encoding == UTF16BE;
+        let mut units = Vec::with_capacity(bytes.len() / 2);
+        for pair in bytes.chunks(2).filter(|pair| pair.len() == 2) {
+          let unit =
+            if big_endian {
+              (pair[0] as u16) << 8 | pair[1] as u16
+            }
+            else {
+              (pair[1] as u16) << 8 | pair[0] as u16
+            };
+          // Byte order marks may occur at the start of each value.
+          match unit {
+            0xFEFF => continue,
+            0xFFFE => { big_endian = !big_endian; continue },
+            _      => units.push(unit)
+          }
+        }
+        String::from_utf16_lossy(&units)
+      }
+    };
+  text.trim_right_matches('\u{0}').to_string()
+}
+
+/// Version 2.4 tags are written using UTF-8. Version 2.3 does not support
+/// UTF-8, so text is written as ISO-8859-1 when possible, or as UTF-16.
+fn choose_encoding(text: &str, version: u8) -> u8 {
+  if version == 4 {
+    UTF8
+  }
+  else if text.chars().all(|c| (c as u32) < 0x100) {
+    LATIN1
+  }
+  else {
+    UTF16
+  }
+}
+
+fn encode_text(text: &str, encoding: u8) -> Vec<u8> {
+  match encoding {
+    LATIN1 => text.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect(),
+    UTF8   => text.as_bytes().to_vec(),
+    _ => {
+      let mut bytes = vec![0xFF, 0xFE];
+      for unit in text.encode_utf16() {
+        bytes.push(unit as u8);
+        bytes.push((unit >> 8) as u8);
+      }
+      bytes
+    }
+  }
+}
+
+#[inline]
+fn terminator(encoding: u8) -> Vec<u8> {
+  match encoding {
+    UTF16 | UTF16BE => vec![0, 0],
+    _               => vec![0]
+  }
+}
+
+#[cfg(test)]
+mod coding {
+  use super::*;
+
+  #[test]
+  fn read_v3() {
+    let mut bytes: Vec<u8> = vec![b'I', b'D', b'3', 3, 0, 0];
+    let mut body: Vec<u8> = Vec::new();
+    // Latin-1 title
+    body.extend(b"TIT2\x00\x00\x00\x06\x00\x00\x00Title");
+    // UTF-16 artist with byte order mark and terminator
+    body.extend(b"TPE1\x00\x00\x00\x09\x00\x00\x01\xFF\xFEA\x00r\x00\x00\x00");
+    // Picture
+    body.extend(b"APIC\x00\x00\x00\x12\x00\x00\x00image/png\x00\x03cov\x00\x89P");
+    // Padding
+    body.extend(vec![0u8; 16]);
+    bytes.extend(&[0, 0, 0, body.len() as u8]);
+    bytes.extend(body);
+
+    let tag = Id3Tag::read(&bytes).unwrap();
+    assert_eq!(3, tag.version);
+    assert_eq!(3, tag.frames.len());
+    assert_eq!(Some("Title"), tag.title());
+    assert_eq!(Some("Ar"), tag.artist());
+    assert_eq!(None, tag.album());
+    let pictures = tag.pictures();
+    assert_eq!(1, pictures.len());
+    assert_eq!("image/png", pictures[0].mime_type);
+    assert_eq!(3, pictures[0].picture_type);
+    assert_eq!("cov", pictures[0].description);
+    assert_eq!(vec![0x89, b'P'], pictures[0].data);
+  }
+
+  #[test]
+  fn write_read() {
+    for version in [3, 4].iter() {
+      let mut tag = Id3Tag { version: *version, frames: Vec::new() };
+      tag.set_text("TIT2", "Amen, brother");
+      tag.set_text("TPE1", "The Winstons");
+      tag.set_text("TALB", "Amen – Brother");
+      tag.frames.push(Id3Frame::UserText {
+        description: "BPM".to_string(), text: "136".to_string()
+      });
+      tag.frames.push(Id3Frame::Picture(Picture {
+        mime_type:    "image/jpeg".to_string(),
+        picture_type: 3,
+        description:  String::new(),
+        data:         vec![0xFF, 0xD8, 0xFF, 0x00]
+      }));
+      tag.frames.push(Id3Frame::Other {
+        id: *b"PRIV", flags: 0, data: vec![1, 2, 3]
+      });
+      let mut bytes = Vec::new();
+      tag.write(&mut bytes).unwrap();
+      assert_eq!(tag, Id3Tag::read(&bytes).unwrap());
+    }
+  }
+
+  #[test]
+  fn syncsafe() {
+    assert_eq!([0x00, 0x00, 0x02, 0x01], write_syncsafe(257));
+    assert_eq!(257, read_syncsafe(&[0x00, 0x00, 0x02, 0x01]));
+    assert_eq!(vec![0xFF, 0xE0, 0xFF, 0x00],
+               resynchronise(&[0xFF, 0x00, 0xE0, 0xFF, 0x00, 0x00]));
+  }
+}
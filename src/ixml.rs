@@ -0,0 +1,302 @@
+//! iXML
+//!
+//! iXML is an XML document stored in the `iXML` chunk of WAVE files by field
+//! and production sound recorders. It describes the project, scene, take,
+//! track names, and timecode of a recording.
+//!
+//! Only the fields commonly used to organize recordings are parsed. The full
+//! document remains available as raw XML in the `Metadata` of the audio.
+//!
+//! References
+//! - [iXML Specification](http://www.ixml.info/)
+
+/// Common fields parsed from an iXML document.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ixml {
+  pub project:                Option<String>,
+  pub scene:                  Option<String>,
+  pub take:                   Option<String>,
+  pub tape:                   Option<String>,
+  pub note:                   Option<String>,
+  /// Tracks listed in `TRACK_LIST`
+  pub tracks:                 Vec<IxmlTrack>,
+  /// Timecode rate from `SPEED`, such as `25/1` or `30000/1001`
+  pub timecode_rate:          Option<String>,
+  /// Either `DF` for drop frame or `NDF` for non-drop frame timecode
+  pub timecode_flag:          Option<String>,
+  /// Start of the recording as the number of samples since midnight
+  pub timestamp:              Option<u64>,
+  /// Sample rate used to count `timestamp`
+  pub timestamp_sample_rate:  Option<u32>
+}
+
+/// A track listed in an iXML document.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IxmlTrack {
+  pub channel_index:    Option<u32>,
+  pub interleave_index: Option<u32>,
+  pub name:             Option<String>,
+  pub function:         Option<String>
+}
+
+impl Ixml {
+  /// Parses the common fields of an iXML document. Fields that are missing
+  /// or cannot be read are left empty.
+  pub fn parse(xml: &str) -> Ixml {
+    let root =
+      match parse_xml(xml) {
+        Some(root) => root,
+        None       => return Ixml::default()
+      };
+    let mut ixml = Ixml {
+      project:  root.child_text("PROJECT"),
+      scene:    root.child_text("SCENE"),
+      take:     root.child_text("TAKE"),
+      tape:     root.child_text("TAPE"),
+      note:     root.child_text("NOTE"),
+      .. Ixml::default()
+    };
+    if let Some(track_list) = root.child("TRACK_LIST") {
+      ixml.tracks =
+        track_list.children.iter()
+        .filter(|element| element.name == "TRACK")
+        .map(|track| IxmlTrack {
+          channel_index:    track.child_number("CHANNEL_INDEX"),
+          interleave_index: track.child_number("INTERLEAVE_INDEX"),
+          name:             track.child_text("NAME"),
+          function:         track.child_text("FUNCTION")
+        })
+        .collect();
+    }
+    if let Some(speed) = root.child("SPEED") {
+      ixml.timecode_rate = speed.child_text("TIMECODE_RATE");
+      ixml.timecode_flag = speed.child_text("TIMECODE_FLAG");
+      let hi: Option<u64> = speed.child_number("TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_HI");
+      let lo: Option<u64> = speed.child_number("TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_LO");
+      ixml.timestamp =
+        match (hi, lo) {
+          (None, None) => None,
+          (hi, lo)     => Some(hi.unwrap_or(0) << 32 | lo.unwrap_or(0))
+        };
+      ixml.timestamp_sample_rate = speed.child_number("TIMESTAMP_SAMPLE_RATE");
+    }
+    ixml
+  }
+
+  /// Returns the start of the recording as `HH:MM:SS:FF` timecode. Drop frame
+  /// counting is not applied.
+  pub fn timecode(&self) -> Option<String> {
+    let samples     = match self.timestamp { Some(t) => t, None => return None };
+    let sample_rate = match self.timestamp_sample_rate {
+      Some(r) if r > 0 => r as u64,
+      _                => return None
+    };
+    let fps =
+      match self.timecode_rate {
+        Some(ref rate) => {
+          let mut parts = rate.split('/');
+          let num: f64 = match parts.next().and_then(|n| n.trim().parse().ok()) {
+            Some(n) => n,
+            None    => return None
+          };
+          let den: f64 = parts.next().and_then(|d| d.trim().parse().ok()).unwrap_or(1f64);
+          if den == 0f64 {
+            return None;
+          }
+          (num / den).round() as u64
+        },
+        None => return None
+      };
+    if fps == 0 {
+      return None;
+    }
+    let seconds = samples / sample_rate;
+    let frames  = (samples % sample_rate) * fps / sample_rate;
+    Some(format!("{:02}:{:02}:{:02}:{:02}",
+                 seconds / 3600, seconds / 60 % 60, seconds % 60, frames))
+  }
+}
+
+/// An element of an XML document. Attributes are ignored.
+#[derive(Debug)]
+struct Element {
+  name:     String,
+  text:     String,
+  children: Vec<Element>
+}
+
+impl Element {
+  fn child(&self, name: &str) -> Option<&Element> {
+    self.children.iter().find(|element| element.name == name)
+  }
+
+  fn child_text(&self, name: &str) -> Option<String> {
+    self.child(name)
+      .map(|element| element.text.trim().to_string())
+      .and_then(|text| if text.is_empty() { None } else { Some(text) })
+  }
+
+  fn child_number<T: ::std::str::FromStr>(&self, name: &str) -> Option<T> {
+    self.child_text(name).and_then(|text| text.parse().ok())
+  }
+}
+
+/// Parses an XML document into its root element. Declarations, comments, and
+/// processing instructions are skipped. Returns `None` if the document is not
+/// well formed.
+fn parse_xml(xml: &str) -> Option<Element> {
+  let mut stack: Vec<Element> = Vec::new();
+  let mut rest = xml;
+  loop {
+    let open =
+      match rest.find('<') {
+        Some(i) => i,
+        None    => return None
+      };
+    if let Some(element) = stack.last_mut() {
+      element.text.push_str(&unescape(&rest[..open]));
+    }
+    rest = &rest[open..];
+    if rest.starts_with("<!--") {
+      let end = match rest.find("-->") { Some(i) => i, None => return None };
+      rest = &rest[end + 3 ..];
+      continue;
+    }
+    if rest.starts_with("<![CDATA[") {
+      let end = match rest.find("]]>") { Some(i) => i, None => return None };
+      if let Some(element) = stack.last_mut() {
+        element.text.push_str(&rest[9..end]);
+      }
+      rest = &rest[end + 3 ..];
+      continue;
+    }
+    let close = match rest.find('>') { Some(i) => i, None => return None };
+    let tag = &rest[1..close];
+    rest = &rest[close + 1 ..];
+    if tag.starts_with('?') || tag.starts_with('!') {
+      continue;
+    }
+    if tag.starts_with('/') {
+      let element = match stack.pop() { Some(e) => e, None => return None };
+      if element.name != tag[1..].trim() {
+        return None;
+      }
+      match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None         => return Some(element)
+      }
+      continue;
+    }
+    let self_closing = tag.ends_with('/');
+    let tag = tag.trim_right_matches('/');
+    let name = tag.split_whitespace().next().unwrap_or("").to_string();
+    let element = Element { name: name, text: String::new(), children: Vec::new() };
+    if self_closing {
+      match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None         => return Some(element)
+      }
+    }
+    else {
+      stack.push(element);
+    }
+  }
+}
+
+/// Replaces the predefined XML entities and numeric character references.
+fn unescape(text: &str) -> String {
+  let mut result = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(amp) = rest.find('&') {
+    result.push_str(&rest[..amp]);
+    rest = &rest[amp..];
+    let semi = match rest.find(';') { Some(i) => i, None => break };
+    let entity = &rest[1..semi];
+    let replacement =
+      match entity {
+        "amp"  => Some('&'),
+        "lt"   => Some('<'),
+        "gt"   => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ if entity.starts_with("#x") =>
+          u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32),
+        _ if entity.starts_with('#') =>
+          entity[1..].parse().ok().and_then(::std::char::from_u32),
+        _ => None
+      };
+    match replacement {
+      Some(c) => {
+        result.push(c);
+        rest = &rest[semi + 1 ..];
+      },
+      None => {
+        result.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  result.push_str(rest);
+  result
+}
+
+#[cfg(test)]
+mod parsing {
+  use super::*;
+
+  const DOCUMENT: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<BWFXML>
+  <IXML_VERSION>1.61</IXML_VERSION>
+  <PROJECT>Feature &amp; Film</PROJECT>
+  <SCENE>42A</SCENE>
+  <TAKE>3</TAKE>
+  <NOTE/>
+  <!-- Speed and timecode -->
+  <SPEED>
+    <NOTE>Not the take note</NOTE>
+    <TIMECODE_RATE>25/1</TIMECODE_RATE>
+    <TIMECODE_FLAG>NDF</TIMECODE_FLAG>
+    <TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_HI>0</TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_HI>
+    <TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_LO>1800024000</TIMESTAMP_SAMPLES_SINCE_MIDNIGHT_LO>
+    <TIMESTAMP_SAMPLE_RATE>48000</TIMESTAMP_SAMPLE_RATE>
+  </SPEED>
+  <TRACK_LIST>
+    <TRACK_COUNT>2</TRACK_COUNT>
+    <TRACK>
+      <CHANNEL_INDEX>1</CHANNEL_INDEX>
+      <INTERLEAVE_INDEX>1</INTERLEAVE_INDEX>
+      <NAME>Boom</NAME>
+    </TRACK>
+    <TRACK>
+      <CHANNEL_INDEX>2</CHANNEL_INDEX>
+      <INTERLEAVE_INDEX>2</INTERLEAVE_INDEX>
+      <NAME><![CDATA[Lav <1>]]></NAME>
+      <FUNCTION>LAV</FUNCTION>
+    </TRACK>
+  </TRACK_LIST>
+</BWFXML>"#;
+
+  #[test]
+  fn fields() {
+    let ixml = Ixml::parse(DOCUMENT);
+    assert_eq!(Some("Feature & Film".to_string()), ixml.project);
+    assert_eq!(Some("42A".to_string()), ixml.scene);
+    assert_eq!(Some("3".to_string()), ixml.take);
+    assert_eq!(None, ixml.tape);
+    assert_eq!(None, ixml.note);
+    assert_eq!(2, ixml.tracks.len());
+    assert_eq!(Some(1), ixml.tracks[0].channel_index);
+    assert_eq!(Some("Boom".to_string()), ixml.tracks[0].name);
+    assert_eq!(None, ixml.tracks[0].function);
+    assert_eq!(Some("Lav <1>".to_string()), ixml.tracks[1].name);
+    assert_eq!(Some("LAV".to_string()), ixml.tracks[1].function);
+    assert_eq!(Some(1800024000), ixml.timestamp);
+    assert_eq!(Some("10:25:00:12".to_string()), ixml.timecode());
+  }
+
+  #[test]
+  fn malformed() {
+    assert_eq!(Ixml::default(), Ixml::parse("<BWFXML><SCENE>1</TAKE></BWFXML>"));
+    assert_eq!(Ixml::default(), Ixml::parse("not xml"));
+  }
+}
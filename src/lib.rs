@@ -10,9 +10,14 @@ mod audio;
 pub use audio::{
   AudioFormat,
   open,
+  open_as,
+  open_from,
   load,
+  load_from,
   save,
   save_as,
+  save_split,
+  save_split_as,
   write,
   write_as
 };
@@ -23,12 +28,72 @@ pub use buffer::AudioBuffer;
 mod codecs;
 pub use codecs::Codec as Codec;
 
+mod dsp;
+pub use dsp::fade::{
+  Fade,
+  FadeCurve
+};
+pub use dsp::filter::{
+  Biquad,
+  Filter,
+  FilterType
+};
+pub use dsp::loudness::{
+  Loudness,
+  LoudnessMeter
+};
+pub use dsp::peak::TruePeakMeter;
+pub use dsp::remix::{
+  ChannelMatrix,
+  SpeakerLayout
+};
+pub use dsp::resample::{
+  ResampleQuality,
+  Resampler
+};
+pub use dsp::silence::{
+  SilenceDetector,
+  SilenceSpec
+};
+pub use dsp::stats::{
+  ChannelStats,
+  Stats,
+  StatsMeter
+};
+
 mod error;
 pub use error::{
   AudioResult,
   AudioError
 };
 
+mod id3;
+pub use id3::{
+  Id3Frame,
+  Id3Tag,
+  Picture
+};
+
+mod ixml;
+pub use ixml::{
+  Ixml,
+  IxmlTrack
+};
+
+mod metadata;
+pub use metadata::{
+  Instrument,
+  Loop,
+  LoopInfo,
+  LoopMode,
+  Metadata,
+  RiffWrapper,
+  UnknownChunk
+};
+
+mod raw;
+pub use raw::RawSpec;
+
 mod sample;
 pub use sample::{
   FromSample,
@@ -41,5 +106,21 @@ mod traits;
 
 mod wave;
 mod aiff;
+mod au;
+mod caf;
+mod w64;
+mod sphere;
+mod ogg;
+mod opus;
+mod mp3;
+mod wavpack;
+mod mp4;
+mod tta;
+mod shorten;
+mod voc;
+mod svx;
+
+#[cfg(test)]
+mod testing;
 
 
@@ -0,0 +1,177 @@
+//! Metadata
+//!
+//! Information carried alongside the decoded samples of an `AudioBuffer`.
+//! Containers read the chunks they recognize into these format-independent
+//! types and write them back using their own chunk layouts when saving.
+//! Chunks a container does not recognize are kept as they were read, so
+//! saving to the same format does not discard them.
+
+use audio::AudioFormat;
+use id3::Id3Tag;
+use ixml::Ixml;
+
+/// All metadata associated with an `AudioBuffer`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+  /// Sampler settings such as root note, key range, and loops
+  pub instrument: Option<Instrument>,
+  /// Tempo, meter, and root note used by loop-based applications
+  pub loop_info: Option<LoopInfo>,
+  /// Track information such as the title, artist, and cover art
+  pub id3: Option<Id3Tag>,
+  /// Raw iXML document describing a production sound recording
+  pub ixml: Option<String>,
+  /// Raw XML document from the `axml` chunk, such as EBU ADM metadata
+  pub axml: Option<String>,
+  /// Text annotation, such as the header text of AU files or the text
+  /// blocks of VOC files
+  pub annotation: Option<String>,
+  /// Key and value pairs of textual information, such as a title or artist
+  pub info: Vec<(String, String)>,
+  /// Unrecognized chunks in the order they were read
+  pub chunks: Vec<UnknownChunk>,
+  /// Header and trailer of the WAVE file the audio was compressed from
+  pub riff: Option<RiffWrapper>,
+  /// Speakers of the channels, as the bits of a WAVE channel mask
+  pub channel_mask: Option<u32>
+}
+
+impl Metadata {
+  /// Parses common fields of the iXML document, if one is present.
+  pub fn ixml_fields(&self) -> Option<Ixml> {
+    self.ixml.as_ref().map(|xml| Ixml::parse(xml))
+  }
+}
+
+/// A chunk that was not recognized when reading an audio file.
+///
+/// The chunk data is kept as raw bytes, so it is only written back when the
+/// audio is saved using the `AudioFormat` it was read from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnknownChunk {
+  /// Format of the file the chunk was read from
+  pub format:     AudioFormat,
+  /// Four byte chunk identifier
+  pub id:         [u8; 4],
+  /// Chunk data, excluding the chunk header and any padding byte
+  pub data:       Vec<u8>,
+  /// Whether the chunk occurred after the chunk containing the audio data
+  pub after_data: bool
+}
+
+impl UnknownChunk {
+  /// Returns the number of bytes the chunk occupies in a file, including its
+  /// header and the padding byte required by odd sized chunks.
+  #[inline]
+  pub fn total_size(&self) -> u32 {
+    let size = self.data.len() as u32;
+    8 + size + size % 2
+  }
+}
+
+/// The bytes of a WAVE file surrounding its audio data.
+///
+/// Lossless formats such as WavPack store these bytes along with the
+/// compressed audio, so the original WAVE file can be restored exactly.
+/// They are used when saving to WAVE only while they still describe the
+/// audio and its metadata; otherwise the file is written as usual.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RiffWrapper {
+  /// Bytes from the start of the file up to the audio data, ending with the
+  /// header of the data chunk
+  pub header:   Vec<u8>,
+  /// Bytes following the audio data, including the padding byte of the data
+  /// chunk
+  pub trailer:  Vec<u8>
+}
+
+/// How a sampler plays back a loop.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LoopMode {
+  /// Plays from the start to the end of the loop, then repeats
+  Forward,
+  /// Alternates playing forward and backward through the loop
+  Alternating,
+  /// Plays from the end to the start of the loop, then repeats
+  Backward
+}
+
+/// A range of frames repeated by a sampler.
+///
+/// Both `start` and `end` are frame offsets from the beginning of the audio,
+/// and the frame at `end` is the last one played in the loop.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Loop {
+  pub mode:       LoopMode,
+  pub start:      u32,
+  pub end:        u32,
+  /// Number of times the loop is played, where 0 means infinitely
+  pub play_count: u32
+}
+
+/// Settings used to map audio onto a sampler instrument.
+///
+/// Notes are MIDI note numbers from 0 to 127 and velocities range from 1 to
+/// 127. Tuning is given in cents and gain in decibels.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Instrument {
+  pub root_note:      u8,
+  pub fine_tune:      i8,
+  pub gain:           i8,
+  pub low_note:       u8,
+  pub high_note:      u8,
+  pub low_velocity:   u8,
+  pub high_velocity:  u8,
+  /// Loop played while a note is held
+  pub sustain_loop:   Option<Loop>,
+  /// Loop played after a note is released
+  pub release_loop:   Option<Loop>
+}
+
+impl Default for Instrument {
+  fn default() -> Self {
+    Instrument {
+      root_note:      60,
+      fine_tune:      0,
+      gain:           0,
+      low_note:       0,
+      high_note:      127,
+      low_velocity:   1,
+      high_velocity:  127,
+      sustain_loop:   None,
+      release_loop:   None
+    }
+  }
+}
+
+/// Musical information used by loop-based applications to match the tempo
+/// and key of the audio to a project.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopInfo {
+  /// Whether the audio is played once rather than looped
+  pub one_shot:           bool,
+  /// Whether the audio may be time stretched to match the project tempo
+  pub stretch:            bool,
+  /// MIDI note number of the key of the audio
+  pub root_note:          Option<u8>,
+  /// Number of beats in the audio
+  pub beats:              u32,
+  pub meter_numerator:    u16,
+  pub meter_denominator:  u16,
+  /// Tempo in beats per minute
+  pub tempo:              f32
+}
+
+impl Default for LoopInfo {
+  fn default() -> Self {
+    LoopInfo {
+      one_shot:           false,
+      stretch:            true,
+      root_note:          None,
+      beats:              0,
+      meter_numerator:    4,
+      meter_denominator:  4,
+      tempo:              0f32
+    }
+  }
+}
@@ -0,0 +1,71 @@
+//! MPEG Audio Bitstream
+
+/// Reads integers packed into a byte slice, starting at the most
+/// significant bit of each byte.
+///
+/// Reading past the end of the slice gives zero bits. The Layer III
+/// decoding process reads the main data of each granule up to a limit given
+/// by the side information, and checks the position against it instead.
+pub struct BitReader<'a> {
+  data:     &'a [u8],
+  position: usize
+}
+
+impl<'a> BitReader<'a> {
+  #[inline]
+  pub fn new(data: &'a [u8]) -> BitReader<'a> {
+    BitReader {
+      data:     data,
+      position: 0
+    }
+  }
+
+  /// Returns the position of the next bit to read.
+  #[inline]
+  pub fn position(&self) -> usize {
+    self.position
+  }
+
+  /// Moves to the given bit position.
+  #[inline]
+  pub fn seek(&mut self, position: usize) {
+    self.position = position;
+  }
+
+  /// Reads an unsigned integer of up to 32 bits.
+  pub fn read(&mut self, bits: u32) -> u32 {
+    debug_assert!(bits <= 32);
+    let mut value: u64 = 0;
+    for _ in 0..bits {
+      let byte = self.data.get(self.position / 8).cloned().unwrap_or(0);
+      let bit  = (byte >> (7 - self.position % 8)) & 1;
+      value = value << 1 | bit as u64;
+      self.position += 1;
+    }
+    value as u32
+  }
+
+  #[inline]
+  pub fn read_bool(&mut self) -> bool {
+    self.read(1) == 1
+  }
+}
+
+#[cfg(test)]
+mod bitstream {
+  use super::*;
+
+  #[test]
+  fn read_msb_first() {
+    let data = [0b1010_0110u8, 0b0000_0001, 0xFF];
+    let mut bits = BitReader::new(&data);
+    assert_eq!(0b101, bits.read(3));
+    assert_eq!(0b0_0110_0, bits.read(6));
+    assert_eq!(0b000_0001_1111_1111, bits.read(15));
+    assert_eq!(24, bits.position());
+    assert_eq!(0, bits.read(8));
+    bits.seek(7);
+    assert!(!bits.read_bool());
+    assert_eq!(0, bits.read(0));
+  }
+}
@@ -0,0 +1,207 @@
+use std::io::{Read, Seek, Write};
+use buffer::*;
+use codecs::Codec;
+use error::*;
+use id3;
+use id3::Id3Tag;
+use metadata::Metadata;
+use mp3::*;
+use mp3::header::FrameHeader;
+use mp3::layer3::Layer3Decoder;
+use sample::*;
+use traits::Container;
+
+/// Struct containing all necessary information for decoding an MP3 stream
+/// to an `AudioBuffer`.
+pub struct Mp3Container {
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for Mp3Container {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<Mp3Container> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(reader.read_to_end(&mut bytes));
+
+    // Skips the ID3v2 tags at the start of the file, keeping the first
+    // that can be read, and the ID3v1 tag at the end.
+    let mut metadata = Metadata::default();
+    let mut start = 0;
+    while let Some(len) = id3::tag_len(&bytes[start..]) {
+      if metadata.id3.is_none() {
+        if let Ok(tag) = Id3Tag::read(&bytes[start .. start + len]) {
+          metadata.id3 = Some(tag);
+        }
+      }
+      start += len;
+    }
+    let mut end = bytes.len();
+    if end >= start + 128 && &bytes[end - 128 .. end - 125] == ID3V1 {
+      end -= 128;
+    }
+    let data = &bytes[start..end];
+
+    let (mut position, first) = try!(find_frame(data, 0));
+    if first.layer != 3 {
+      return Err(AudioError::Unsupported(
+        format!("Decoding MPEG Layer {} is not supported", if first.layer == 1 { "I" } else { "II" })
+      ));
+    }
+    let channels = first.channels();
+
+    // The first frame may hold a Xing or VBRI tag instead of audio.
+    let tag = VbrTag::read(&data[position .. position + first.frame_len()], &first);
+    if tag.is_some() {
+      position += first.frame_len();
+    }
+
+    let mut decoder = Layer3Decoder::new(channels);
+    let mut samples: Vec<Sample> = Vec::new();
+    while position < data.len() {
+      let header =
+        match FrameHeader::read(&data[position..]) {
+          Some(header) if header.matches(&first) && header.bitrate > 0 => header,
+          _ => {
+            // Skips data that does not belong to a frame.
+            match find_frame(data, position + 1) {
+              Ok((next, header)) if header.matches(&first) => {
+                position = next;
+                header
+              },
+              _ => break
+            }
+          }
+        };
+      let len = header.frame_len();
+      if position + len > data.len() {
+        break;
+      }
+      decoder.decode(&header, &data[position .. position + len], &mut samples);
+      position += len;
+    }
+
+    // The encoder delay and padding of a LAME tag give the frames to trim,
+    // in addition to the delay of the decoder.
+    if let Some(VbrTag { frames, gapless: Some((delay, padding)) }) = tag {
+      let decoded = samples.len() / channels;
+      let total   = frames.map(|frames| frames * first.samples()).unwrap_or(decoded);
+      let last    = (total + DECODER_DELAY).saturating_sub(padding).min(decoded);
+      let first   = (delay + DECODER_DELAY).min(last);
+      samples.truncate(last * channels);
+      samples.drain(.. first * channels);
+    }
+
+    Ok(Mp3Container {
+      sample_rate:  first.sample_rate,
+      channels:     channels as u32,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:      samples,
+      metadata:     metadata
+    })
+  }
+  fn create<W: Write>(_: &mut W, _: &AudioBuffer, _: Codec) -> AudioResult<()> {
+    Err(AudioError::Unsupported(
+      "Encoding MP3 is not supported".to_string()
+    ))
+  }
+}
+
+/// The Xing or VBRI tag of the first frame, giving the number of frames
+/// of the stream, and the encoder delay and padding of a LAME tag.
+struct VbrTag {
+  frames:   Option<usize>,
+  gapless:  Option<(usize, usize)>
+}
+
+impl VbrTag {
+  /// Reads the tag of a frame, returning `None` if the frame holds audio.
+  fn read(frame: &[u8], header: &FrameHeader) -> Option<VbrTag> {
+    // The Xing tag follows the side information, and the VBRI tag is
+    // always placed 32 bytes after the header.
+    let offset = 4 + if header.crc { 2 } else { 0 } + header.side_info_len();
+    if frame.len() >= 36 + 4 && &frame[36..40] == VBRI {
+      return Some(VbrTag { frames: None, gapless: None });
+    }
+    if frame.len() < offset + 8 {
+      return None;
+    }
+    let tag = &frame[offset..];
+    if &tag[0..4] != XING && &tag[0..4] != INFO {
+      return None;
+    }
+    let flags = read_u32(&tag[4..8]);
+    let mut position = 8;
+    let mut frames   = None;
+    if flags & 1 != 0 && tag.len() >= position + 4 {
+      frames = Some(read_u32(&tag[position .. position + 4]) as usize);
+      position += 4;
+    }
+    for &(flag, len) in [(2, 4), (4, 100), (8, 4)].iter() {
+      if flags & flag != 0 {
+        position += len;
+      }
+    }
+    // The encoder delay and padding are 12 bit fields at byte 21 of the
+    // LAME tag, whose version string starts with a nonzero byte.
+    let gapless =
+      if tag.len() >= position + 24 && tag[position] != 0 {
+        let lame = &tag[position + 21 .. position + 24];
+        let delay   = (lame[0] as usize) << 4 | (lame[1] as usize) >> 4;
+        let padding = ((lame[1] & 0xF) as usize) << 8 | lame[2] as usize;
+        Some((delay, padding))
+      } else {
+        None
+      };
+    Some(VbrTag {
+      frames:   frames,
+      gapless:  gapless
+    })
+  }
+}
+
+// Private functions
+
+#[inline]
+fn read_u32(bytes: &[u8]) -> u32 {
+  (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+/// Finds the first frame from a position, whose header is followed by the
+/// header of another frame of the stream, or by the end of the data.
+fn find_frame(data: &[u8], from: usize) -> AudioResult<(usize, FrameHeader)> {
+  let mut free_format = false;
+  for position in from .. data.len() {
+    if let Some(header) = FrameHeader::read(&data[position..]) {
+      if header.bitrate == 0 {
+        free_format = true;
+        continue;
+      }
+      let next = position + header.frame_len();
+      if next == data.len() {
+        return Ok((position, header));
+      }
+      if let Some(next) = FrameHeader::read(&data[next.min(data.len())..]) {
+        if next.matches(&header) {
+          return Ok((position, header));
+        }
+      }
+    }
+  }
+  if free_format && from == 0 {
+    Err(AudioError::Unsupported(
+      "Decoding free format MP3 is not supported".to_string()
+    ))
+  } else {
+    Err(AudioError::Format(
+      "File is not valid MP3 (No frames found)".to_string()
+    ))
+  }
+}
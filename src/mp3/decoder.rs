@@ -0,0 +1,37 @@
+use std::io::{Read, Seek};
+use buffer::AudioBuffer;
+use error::AudioResult;
+use mp3::container::Mp3Container;
+use traits::{AudioDecoder, Container};
+
+/// Decodes audio in MP3 format from the
+/// provided reader.
+pub struct Decoder<'r, R: 'r> where R: Read + Seek {
+  reader: &'r mut R,
+}
+
+impl<'r, R> Decoder<'r, R> where R: Read + Seek {
+  /// Create a new MP3 format `Decoder` using
+  /// the provided reader.
+  #[inline]
+  pub fn new(reader: &'r mut R) -> Decoder<R> {
+    Decoder {
+      reader: reader
+    }
+  }
+}
+
+impl<'r, R> AudioDecoder for Decoder<'r, R> where R: Read + Seek {
+  /// Creates an `AudioBuffer` from the included reader via
+  /// an `Mp3Container`.
+  #[inline]
+  fn decode(mut self) -> AudioResult<AudioBuffer> {
+    let container = try!(Mp3Container::open(self.reader));
+    Ok(AudioBuffer {
+      sample_rate:  container.sample_rate,
+      channels:     container.channels,
+      samples:      container.samples,
+      metadata:     container.metadata
+    })
+  }
+}
@@ -0,0 +1,216 @@
+//! MPEG Audio Frame Header
+
+/// Bitrates in kbps of MPEG-1, indexed by layer and bitrate index. An index
+/// of zero is used by free format streams.
+const MPEG1_BITRATES: [[u32; 15]; 3] = [
+  [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+  [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+  [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320]
+];
+
+/// Bitrates in kbps of MPEG-2 and MPEG-2.5, indexed by layer and bitrate
+/// index. Layers II and III share a table.
+const MPEG2_BITRATES: [[u32; 15]; 3] = [
+  [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+  [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+  [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160]
+];
+
+/// Sample rates of MPEG-1. MPEG-2 halves them, and MPEG-2.5 quarters them.
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// Channel mode of a single channel.
+const MONO: u8 = 3;
+
+/// Channel mode of joint stereo, whose mode extension selects middle/side
+/// and intensity stereo.
+const JOINT_STEREO: u8 = 1;
+
+/// Versions of the MPEG audio standard.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Version {
+  Mpeg1,
+  Mpeg2,
+  Mpeg25
+}
+
+/// The four byte header starting each frame of an MPEG audio stream.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameHeader {
+  pub version:        Version,
+  pub layer:          u8,
+  pub crc:            bool,
+  pub bitrate:        u32,
+  pub sample_rate:    u32,
+  pub padding:        bool,
+  pub mode:           u8,
+  pub mode_extension: u8,
+  sample_rate_index:  usize
+}
+
+impl FrameHeader {
+  /// Reads a frame header from the start of the bytes, returning `None`
+  /// if they do not begin with a valid header.
+  pub fn read(bytes: &[u8]) -> Option<FrameHeader> {
+    if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] & 0xE0 != 0xE0 {
+      return None;
+    }
+    let version =
+      match (bytes[1] >> 3) & 3 {
+        0 => Version::Mpeg25,
+        2 => Version::Mpeg2,
+        3 => Version::Mpeg1,
+        _ => return None
+      };
+    let layer = 4 - ((bytes[1] >> 1) & 3);
+    let bitrate_index     = (bytes[2] >> 4) as usize;
+    let sample_rate_index = ((bytes[2] >> 2) & 3) as usize;
+    if layer == 4 || bitrate_index == 15 || sample_rate_index == 3 {
+      return None;
+    }
+    // MPEG-2.5 is an extension of MPEG-2 for Layer III only.
+    if version == Version::Mpeg25 && layer != 3 {
+      return None;
+    }
+    let (bitrate, sample_rate) =
+      match version {
+        Version::Mpeg1  => (MPEG1_BITRATES[layer as usize - 1][bitrate_index],
+                            SAMPLE_RATES[sample_rate_index]),
+        Version::Mpeg2  => (MPEG2_BITRATES[layer as usize - 1][bitrate_index],
+                            SAMPLE_RATES[sample_rate_index] / 2),
+        Version::Mpeg25 => (MPEG2_BITRATES[layer as usize - 1][bitrate_index],
+                            SAMPLE_RATES[sample_rate_index] / 4)
+      };
+    Some(FrameHeader {
+      version:            version,
+      layer:              layer,
+      crc:                bytes[1] & 1 == 0,
+      bitrate:            bitrate,
+      sample_rate:        sample_rate,
+      padding:            (bytes[2] >> 1) & 1 == 1,
+      mode:               bytes[3] >> 6,
+      mode_extension:     (bytes[3] >> 4) & 3,
+      sample_rate_index:  sample_rate_index
+    })
+  }
+
+  #[inline]
+  pub fn channels(&self) -> usize {
+    if self.mode == MONO { 1 } else { 2 }
+  }
+
+  /// Returns the number of granules of 576 samples in a Layer III frame.
+  #[inline]
+  pub fn granules(&self) -> usize {
+    if self.version == Version::Mpeg1 { 2 } else { 1 }
+  }
+
+  /// Returns the number of samples of each channel coded in the frame.
+  pub fn samples(&self) -> usize {
+    match self.layer {
+      1 => 384,
+      2 => 1152,
+      _ => 576 * self.granules()
+    }
+  }
+
+  /// Returns the length of the frame in bytes, including the header, or
+  /// zero for free format streams.
+  pub fn frame_len(&self) -> usize {
+    let bitrate     = self.bitrate as usize * 1000;
+    let sample_rate = self.sample_rate as usize;
+    let padding     = self.padding as usize;
+    match self.layer {
+      1 => (12 * bitrate / sample_rate + padding) * 4,
+      2 => 144 * bitrate / sample_rate + padding,
+      _ => 72 * self.granules() * bitrate / sample_rate + padding
+    }
+  }
+
+  /// Returns the length of the Layer III side information in bytes.
+  pub fn side_info_len(&self) -> usize {
+    match (self.version, self.channels()) {
+      (Version::Mpeg1, 1) => 17,
+      (Version::Mpeg1, _) => 32,
+      (_, 1)              => 9,
+      _                   => 17
+    }
+  }
+
+  /// Returns the index of the sample rate into the scale factor band
+  /// tables, which share a row for 11025 Hz and 12000 Hz.
+  pub fn band_index(&self) -> usize {
+    match self.version {
+      Version::Mpeg1  => 5 + self.sample_rate_index,
+      Version::Mpeg2  => 2 + self.sample_rate_index,
+      Version::Mpeg25 => self.sample_rate_index.max(1) - 1
+    }
+  }
+
+  /// Returns true if the channels are coded as middle and side channels.
+  #[inline]
+  pub fn ms_stereo(&self) -> bool {
+    self.mode == JOINT_STEREO && self.mode_extension & 2 != 0
+  }
+
+  /// Returns true if the upper bands of the right channel are coded as
+  /// intensity positions.
+  #[inline]
+  pub fn intensity_stereo(&self) -> bool {
+    self.mode == JOINT_STEREO && self.mode_extension & 1 != 0
+  }
+
+  /// Returns true if the frames of both headers belong to the same stream.
+  pub fn matches(&self, other: &FrameHeader) -> bool {
+    self.version == other.version
+      && self.layer == other.layer
+      && self.sample_rate == other.sample_rate
+      && self.channels() == other.channels()
+  }
+}
+
+#[cfg(test)]
+mod frame_header {
+  use super::*;
+
+  #[test]
+  fn read_mpeg1_layer3() {
+    let header = FrameHeader::read(&[0xFF, 0xFB, 0x92, 0x64]).unwrap();
+    assert_eq!(Version::Mpeg1, header.version);
+    assert_eq!(3, header.layer);
+    assert!(!header.crc);
+    assert_eq!(128, header.bitrate);
+    assert_eq!(44100, header.sample_rate);
+    assert!(header.padding);
+    assert_eq!(2, header.channels());
+    assert!(header.ms_stereo());
+    assert!(!header.intensity_stereo());
+    assert_eq!(418, header.frame_len());
+    assert_eq!(1152, header.samples());
+    assert_eq!(32, header.side_info_len());
+    assert_eq!(5, header.band_index());
+  }
+
+  #[test]
+  fn read_mpeg25_layer3() {
+    let header = FrameHeader::read(&[0xFF, 0xE3, 0x88, 0xC4]).unwrap();
+    assert_eq!(Version::Mpeg25, header.version);
+    assert_eq!(8000, header.sample_rate);
+    assert_eq!(64, header.bitrate);
+    assert_eq!(1, header.channels());
+    assert_eq!(576, header.frame_len());
+    assert_eq!(576, header.samples());
+    assert_eq!(9, header.side_info_len());
+    assert_eq!(1, header.band_index());
+  }
+
+  #[test]
+  fn invalid_headers() {
+    assert!(FrameHeader::read(&[0xFF, 0xFB, 0xF0, 0x00]).is_none());
+    assert!(FrameHeader::read(&[0xFF, 0xFB, 0x9C, 0x00]).is_none());
+    assert!(FrameHeader::read(&[0xFF, 0xF9, 0x90, 0x00]).is_none());
+    assert!(FrameHeader::read(&[0xFF, 0xE5, 0x90, 0x00]).is_none());
+    assert!(FrameHeader::read(&[0xFE, 0xFB, 0x90, 0x00]).is_none());
+    assert!(FrameHeader::read(&[0xFF, 0xFB, 0x90]).is_none());
+  }
+}
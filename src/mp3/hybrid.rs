@@ -0,0 +1,144 @@
+//! Layer III Hybrid Filterbank
+//!
+//! The 576 frequency lines of a granule are split into 32 subbands of 18
+//! lines. After the lines of short blocks are reordered by window, and the
+//! aliasing between the subbands of long blocks is reduced, each subband is
+//! transformed by an IMDCT of 36 samples for long blocks, or three of 12
+//! samples for short blocks, whose first half overlaps with the second half
+//! of the previous granule.
+use std::f32::consts::PI;
+use mp3::tables::ALIAS_COEFFICIENTS;
+
+/// Block type of short blocks.
+pub const SHORT_BLOCK: u8 = 2;
+
+/// The IMDCT and overlap of the subbands of a channel.
+pub struct Hybrid {
+  long:    Vec<f32>,
+  short:   Vec<f32>,
+  windows: [[f32; 36]; 4],
+  overlap: [f32; 576]
+}
+
+impl Hybrid {
+  pub fn new() -> Hybrid {
+    let mut long = Vec::with_capacity(36 * 18);
+    for i in 0..36 {
+      for k in 0..18 {
+        long.push((PI / 72f32 * (2 * i + 19) as f32 * (2 * k + 1) as f32).cos());
+      }
+    }
+    let mut short = Vec::with_capacity(12 * 6);
+    for i in 0..12 {
+      for k in 0..6 {
+        short.push((PI / 24f32 * (2 * i + 7) as f32 * (2 * k + 1) as f32).cos());
+      }
+    }
+    // The windows of normal, start, short and stop blocks, where the short
+    // window is used by each of the three short transforms.
+    let mut windows = [[0f32; 36]; 4];
+    for i in 0..36 {
+      let long  = (PI / 36f32 * (i as f32 + 0.5)).sin();
+      windows[0][i] = long;
+      windows[1][i] =
+        match i {
+          0 ..= 17  => long,
+          18 ..= 23 => 1f32,
+          24 ..= 29 => (PI / 12f32 * (i as f32 - 17.5)).sin(),
+          _         => 0f32
+        };
+      windows[3][i] =
+        match i {
+          0 ..= 5   => 0f32,
+          6 ..= 11  => (PI / 12f32 * (i as f32 - 5.5)).sin(),
+          12 ..= 17 => 1f32,
+          _         => long
+        };
+    }
+    for i in 0..12 {
+      windows[2][i] = (PI / 12f32 * (i as f32 + 0.5)).sin();
+    }
+    Hybrid {
+      long:    long,
+      short:   short,
+      windows: windows,
+      overlap: [0f32; 576]
+    }
+  }
+
+  /// Transforms the lines of a granule into 18 samples of each subband in
+  /// place. The lowest `long_subbands` of short blocks are mixed blocks,
+  /// which use the normal window.
+  pub fn transform(&mut self, lines: &mut [f32; 576], block_type: u8, long_subbands: usize) {
+    let mut output = [0f32; 36];
+    for subband in 0..32 {
+      let x = &mut lines[subband * 18 .. subband * 18 + 18];
+      for sample in output.iter_mut() {
+        *sample = 0f32;
+      }
+      if block_type == SHORT_BLOCK && subband >= long_subbands {
+        for window in 0..3 {
+          for i in 0..12 {
+            let row = &self.short[i * 6 .. i * 6 + 6];
+            let sum: f32 = (0..6).map(|k| row[k] * x[3 * k + window]).sum();
+            output[6 + 6 * window + i] += sum * self.windows[2][i];
+          }
+        }
+      } else {
+        let window = if block_type == SHORT_BLOCK { &self.windows[0] } else { &self.windows[block_type as usize] };
+        for i in 0..36 {
+          let row = &self.long[i * 18 .. i * 18 + 18];
+          let sum: f32 = row.iter().zip(x.iter()).map(|(c, x)| c * x).sum();
+          output[i] = sum * window[i];
+        }
+      }
+      let overlap = &mut self.overlap[subband * 18 .. subband * 18 + 18];
+      for i in 0..18 {
+        x[i] = output[i] + overlap[i];
+        overlap[i] = output[18 + i];
+      }
+      // Inverts the odd samples of the odd subbands, which are mirrored by
+      // the synthesis filterbank.
+      if subband % 2 == 1 {
+        for i in 0..9 {
+          x[2 * i + 1] = -x[2 * i + 1];
+        }
+      }
+    }
+  }
+}
+
+/// Reorders the lines of the short bands, from the start line, so that the
+/// lines of the three windows are interleaved.
+pub fn reorder(lines: &mut [f32; 576], start: usize, widths: &[u8]) {
+  let mut reordered: Vec<f32> = Vec::with_capacity(576);
+  let mut line = start;
+  for width in widths.chunks(3).map(|band| band[0] as usize) {
+    for i in 0..width {
+      for window in 0..3 {
+        reordered.push(lines[line + window * width + i]);
+      }
+    }
+    line += 3 * width;
+  }
+  for (i, value) in reordered.into_iter().enumerate() {
+    lines[start + i] = value;
+  }
+}
+
+/// Reduces the aliasing between the lowest `subbands + 1` subbands.
+pub fn antialias(lines: &mut [f32; 576], subbands: usize) {
+  for subband in 0..subbands {
+    for i in 0..8 {
+      let c  = ALIAS_COEFFICIENTS[i];
+      let cs = 1f32 / (1f32 + c * c).sqrt();
+      let ca = c * cs;
+      let lower = subband * 18 + 17 - i;
+      let upper = (subband + 1) * 18 + i;
+      let a = lines[lower];
+      let b = lines[upper];
+      lines[lower] = a * cs - b * ca;
+      lines[upper] = b * cs + a * ca;
+    }
+  }
+}
@@ -0,0 +1,487 @@
+//! Layer III Decoding
+//!
+//! The side information of a frame gives the location of each granule of
+//! each channel in the main data, which may begin in the frames before it
+//! through the bit reservoir. A granule starts with its scale factors,
+//! followed by the Huffman coded frequency lines. The lines are
+//! requantized with the gains of the side information and the scale
+//! factors of their bands, the stereo channels are reconstructed, and the
+//! hybrid and synthesis filterbanks convert them to samples.
+use std::f32::consts::PI;
+use mp3::bits::BitReader;
+use mp3::header::{FrameHeader, Version};
+use mp3::huffman::HuffmanDecoder;
+use mp3::hybrid::{Hybrid, SHORT_BLOCK, antialias, reorder};
+use mp3::synthesis::Synthesis;
+use mp3::tables::*;
+use sample::Sample;
+
+/// Number of frequency lines of a granule.
+const LINES: usize = 576;
+
+/// Maximum number of bytes of main data taken from the frames before.
+const MAX_RESERVOIR: usize = 511;
+
+/// Side information of a granule of a channel.
+#[derive(Clone, Copy, Default)]
+struct GranuleInfo {
+  part2_3_length:     usize,
+  big_values:         usize,
+  global_gain:        i32,
+  scalefac_compress:  usize,
+  block_type:         u8,
+  mixed_block:        bool,
+  table_select:       [usize; 3],
+  subblock_gain:      [i32; 3],
+  region_count:       [usize; 2],
+  preflag:            bool,
+  scalefac_scale:     bool,
+  count1_table:       bool
+}
+
+/// Side information of a frame.
+struct SideInfo {
+  main_data_begin:  usize,
+  scfsi:            [[bool; 4]; 2],
+  granules:         [[GranuleInfo; 2]; 2]
+}
+
+impl SideInfo {
+  /// Reads the side information, returning `None` if it is invalid.
+  fn read(bytes: &[u8], header: &FrameHeader) -> Option<SideInfo> {
+    let mut bits = BitReader::new(bytes);
+    let channels = header.channels();
+    let mpeg1    = header.version == Version::Mpeg1;
+    let mut side_info = SideInfo {
+      main_data_begin:  0,
+      scfsi:            [[false; 4]; 2],
+      granules:         [[GranuleInfo::default(); 2]; 2]
+    };
+    if mpeg1 {
+      side_info.main_data_begin = bits.read(9) as usize;
+      bits.read(if channels == 1 { 5 } else { 3 });
+      for ch in 0..channels {
+        for group in 0..4 {
+          side_info.scfsi[ch][group] = bits.read_bool();
+        }
+      }
+    } else {
+      side_info.main_data_begin = bits.read(8) as usize;
+      bits.read(channels as u32);
+    }
+    for gr in 0..header.granules() {
+      for ch in 0..channels {
+        let info = &mut side_info.granules[gr][ch];
+        info.part2_3_length    = bits.read(12) as usize;
+        info.big_values        = bits.read(9) as usize;
+        info.global_gain       = bits.read(8) as i32;
+        info.scalefac_compress = bits.read(if mpeg1 { 4 } else { 9 }) as usize;
+        if info.big_values > LINES / 2 {
+          return None;
+        }
+        if bits.read_bool() {
+          info.block_type  = bits.read(2) as u8;
+          info.mixed_block = bits.read_bool();
+          if info.block_type == 0 {
+            return None;
+          }
+          for region in 0..2 {
+            info.table_select[region] = bits.read(5) as usize;
+          }
+          for window in 0..3 {
+            info.subblock_gain[window] = bits.read(3) as i32;
+          }
+          // The regions of the big values are implicit, and the second
+          // region covers the remaining bands.
+          let short = info.block_type == SHORT_BLOCK && !info.mixed_block;
+          info.region_count = [if short { 9 } else { 8 }, LINES];
+        } else {
+          for region in 0..3 {
+            info.table_select[region] = bits.read(5) as usize;
+          }
+          info.region_count[0] = bits.read(4) as usize + 1;
+          info.region_count[1] = bits.read(3) as usize + 1;
+        }
+        info.preflag =
+          if mpeg1 {
+            bits.read_bool()
+          } else {
+            info.scalefac_compress >= 500
+          };
+        info.scalefac_scale = bits.read_bool();
+        info.count1_table   = bits.read_bool();
+      }
+    }
+    Some(side_info)
+  }
+}
+
+/// The scale factor bands of a granule.
+struct Bands {
+  widths: &'static [u8],
+  long:   usize,
+  short:  usize
+}
+
+impl Bands {
+  fn new(header: &FrameHeader, info: &GranuleInfo) -> Bands {
+    let index = header.band_index();
+    if info.block_type != SHORT_BLOCK {
+      Bands { widths: &LONG_BANDS[index], long: 22, short: 0 }
+    } else if !info.mixed_block {
+      Bands { widths: &SHORT_BANDS[index], long: 0, short: 39 }
+    } else {
+      let long = if header.version == Version::Mpeg1 { 8 } else { 6 };
+      Bands { widths: MIXED_BANDS[index], long: long, short: 30 }
+    }
+  }
+
+  /// Returns the index of the partitions of the bands, for long, short and
+  /// mixed blocks.
+  fn kind(&self) -> usize {
+    if self.short == 0 { 0 } else if self.long == 0 { 1 } else { 2 }
+  }
+
+  /// Returns the number of lines covered by the bands.
+  fn lines(&self) -> usize {
+    self.widths.iter().map(|width| *width as usize).sum()
+  }
+}
+
+/// Decodes the frames of a Layer III stream.
+pub struct Layer3Decoder {
+  huffman:      HuffmanDecoder,
+  reservoir:    Vec<u8>,
+  scalefactors: [[u8; 40]; 2],
+  positions:    [i32; 40],
+  hybrid:       Vec<Hybrid>,
+  synthesis:    Vec<Synthesis>
+}
+
+impl Layer3Decoder {
+  pub fn new(channels: usize) -> Layer3Decoder {
+    Layer3Decoder {
+      huffman:      HuffmanDecoder::new(),
+      reservoir:    Vec::new(),
+      scalefactors: [[0u8; 40]; 2],
+      positions:    [0i32; 40],
+      hybrid:       (0..channels).map(|_| Hybrid::new()).collect(),
+      synthesis:    (0..channels).map(|_| Synthesis::new()).collect()
+    }
+  }
+
+  /// Decodes a frame, adding its interleaved samples. A frame whose main
+  /// data is not available is decoded as silence.
+  pub fn decode(&mut self, header: &FrameHeader, frame: &[u8], samples: &mut Vec<Sample>) {
+    let channels = header.channels();
+    let start    = if header.crc { 6 } else { 4 };
+    let end      = (start + header.side_info_len()).min(frame.len());
+    let side_info =
+      if end - start == header.side_info_len() {
+        SideInfo::read(&frame[start..end], header)
+      } else {
+        None
+      };
+    let main_data = &frame[end..];
+    let available =
+      match side_info {
+        Some(ref side_info) => side_info.main_data_begin <= self.reservoir.len(),
+        None                => false
+      };
+    let mut data: Vec<u8> = Vec::new();
+    if let Some(ref side_info) = side_info {
+      if available {
+        data.extend_from_slice(&self.reservoir[self.reservoir.len() - side_info.main_data_begin ..]);
+        data.extend_from_slice(main_data);
+      }
+    }
+    self.reservoir.extend_from_slice(main_data);
+    if self.reservoir.len() > MAX_RESERVOIR {
+      let excess = self.reservoir.len() - MAX_RESERVOIR;
+      self.reservoir.drain(.. excess);
+    }
+
+    let mut bits = BitReader::new(&data);
+    for gr in 0..header.granules() {
+      let mut lines = [[0f32; LINES]; 2];
+      let mut infos = [GranuleInfo::default(); 2];
+      if let Some(ref side_info) = side_info {
+        if available {
+          infos = side_info.granules[gr];
+          for ch in 0..channels {
+            let scfsi = if gr == 1 { side_info.scfsi[ch] } else { [false; 4] };
+            self.decode_channel(header, &infos[ch], scfsi, ch, &mut bits, &mut lines[ch]);
+          }
+          if channels == 2 {
+            self.stereo(header, &infos, &mut lines);
+          }
+        }
+      }
+      let mut output = [[0f32; LINES]; 2];
+      for ch in 0..channels {
+        let info  = &infos[ch];
+        let bands = Bands::new(header, info);
+        let lines = &mut lines[ch];
+        let long_subbands =
+          if info.mixed_block {
+            if header.band_index() == 1 { 4 } else { 2 }
+          } else {
+            0
+          };
+        if info.block_type == SHORT_BLOCK {
+          reorder(lines, long_subbands * 18, &bands.widths[bands.long..]);
+          antialias(lines, long_subbands.max(1) - 1);
+        } else {
+          antialias(lines, 31);
+        }
+        self.hybrid[ch].transform(lines, info.block_type, long_subbands);
+        let mut subbands = [0f32; 32];
+        let mut slot     = [0f32; 32];
+        for t in 0..18 {
+          for subband in 0..32 {
+            subbands[subband] = lines[subband * 18 + t];
+          }
+          self.synthesis[ch].synthesize(&subbands, &mut slot);
+          output[ch][t * 32 .. t * 32 + 32].copy_from_slice(&slot);
+        }
+      }
+      for i in 0..LINES {
+        for ch in 0..channels {
+          samples.push(output[ch][i]);
+        }
+      }
+    }
+  }
+
+  /// Decodes the scale factors and frequency lines of a granule of a
+  /// channel, and requantizes the lines.
+  fn decode_channel(&mut self, header: &FrameHeader, info: &GranuleInfo, scfsi: [bool; 4],
+                    ch: usize, bits: &mut BitReader, lines: &mut [f32; LINES]) {
+    let limit = bits.position() + info.part2_3_length;
+    let bands = Bands::new(header, info);
+    self.read_scalefactors(header, info, &bands, scfsi, ch, bits);
+    let mut values = [0i32; LINES];
+    self.read_lines(info, &bands, limit, bits, &mut values);
+    bits.seek(limit);
+
+    // The scale factors are amplified by the subblock gains of short bands
+    // or the preemphasis of the upper long bands.
+    let mut scalefactors = [0i32; 40];
+    for (scalefactor, value) in scalefactors.iter_mut().zip(self.scalefactors[ch].iter()) {
+      *scalefactor = *value as i32;
+    }
+    let shift = info.scalefac_scale as i32 + 1;
+    if bands.short > 0 {
+      for i in 0..bands.short {
+        scalefactors[bands.long + i] += info.subblock_gain[i % 3] << (3 - shift);
+      }
+    } else if info.preflag {
+      for i in 0..PRETAB.len() {
+        scalefactors[11 + i] += PRETAB[i] as i32;
+      }
+    }
+    // Middle/side stereo is scaled by the square root of two here.
+    let gain = info.global_gain - 210 - if header.ms_stereo() { 2 } else { 0 };
+    let mut line = 0;
+    for (i, width) in bands.widths.iter().enumerate() {
+      let scale = 2f32.powf((gain - (scalefactors[i] << shift)) as f32 / 4f32);
+      for _ in 0 .. *width {
+        let value = values[line];
+        lines[line] = (value.abs() as f32).powf(4f32 / 3f32) * scale * value.signum() as f32;
+        line += 1;
+      }
+    }
+  }
+
+  /// Reads the scale factors of a granule of a channel. The scale factor
+  /// selection information of MPEG-1 reuses the scale factors of the first
+  /// granule in the second. The scale factors of the right channel of
+  /// intensity stereo are kept as its intensity positions, where illegal
+  /// positions of MPEG-2 are -1.
+  fn read_scalefactors(&mut self, header: &FrameHeader, info: &GranuleInfo, bands: &Bands,
+                       scfsi: [bool; 4], ch: usize, bits: &mut BitReader) {
+    let mpeg1 = header.version == Version::Mpeg1;
+    let (lengths, counts) =
+      if mpeg1 {
+        let (lower, upper) = SLEN[info.scalefac_compress];
+        ([lower, lower, upper, upper], MPEG1_PARTITIONS[bands.kind()])
+      } else {
+        let (lengths, table) = mpeg2_lengths(info.scalefac_compress,
+                                             header.intensity_stereo() && ch == 1);
+        (lengths, MPEG2_PARTITIONS[table][bands.kind()])
+      };
+    let scfsi = if bands.short == 0 { scfsi } else { [false; 4] };
+    let mut i = 0;
+    for group in 0..4 {
+      for _ in 0..counts[group] {
+        if !scfsi[group] {
+          let length = lengths[group];
+          let value  = bits.read(length);
+          self.scalefactors[ch][i] = value as u8;
+          if ch == 1 {
+            self.positions[i] =
+              if !mpeg1 && length > 0 && value == (1 << length) - 1 { -1 } else { value as i32 };
+          }
+        }
+        i += 1;
+      }
+    }
+    for scalefactor in self.scalefactors[ch][i..].iter_mut() {
+      *scalefactor = 0;
+    }
+  }
+
+  /// Reads the Huffman coded values of the big values and count1 regions
+  /// of a granule. Quadruples of the count1 region are read until the end
+  /// of the granule at `limit`.
+  fn read_lines(&self, info: &GranuleInfo, bands: &Bands, limit: usize, bits: &mut BitReader,
+                values: &mut [i32; LINES]) {
+    let total = bands.lines();
+    let regions = [
+      band_start(bands.widths, info.region_count[0]),
+      band_start(bands.widths, info.region_count[0] + info.region_count[1])
+    ];
+    let big_values = (info.big_values * 2).min(total);
+    let mut line = 0;
+    while line < big_values {
+      let region = if line < regions[0] { 0 } else if line < regions[1] { 1 } else { 2 };
+      let (x, y) = self.huffman.decode_pair(info.table_select[region], bits);
+      values[line]     = x;
+      values[line + 1] = y;
+      line += 2;
+    }
+    while line < total {
+      let quadruple = self.huffman.decode_quadruple(info.count1_table, bits);
+      if bits.position() > limit {
+        break;
+      }
+      for i in 0..4 {
+        if line + i < total && (quadruple >> (3 - i)) & 1 == 1 {
+          values[line + i] = if bits.read_bool() { -1 } else { 1 };
+        }
+      }
+      line += 4;
+    }
+  }
+
+  /// Reconstructs the left and right channels from middle/side or
+  /// intensity stereo. The bands of the right channel above its highest
+  /// nonzero band are coded as intensity positions, with the upper bands
+  /// of the left channel shared between both channels.
+  fn stereo(&mut self, header: &FrameHeader, infos: &[GranuleInfo; 2],
+            lines: &mut [[f32; LINES]; 2]) {
+    let ms = header.ms_stereo();
+    if !header.intensity_stereo() {
+      if ms {
+        for i in 0..LINES {
+          let (m, s) = (lines[0][i], lines[1][i]);
+          lines[0][i] = m + s;
+          lines[1][i] = m - s;
+        }
+      }
+      return;
+    }
+    let bands  = Bands::new(header, &infos[0]);
+    let count  = bands.long + bands.short;
+    let mpeg1  = header.version == Version::Mpeg1;
+
+    // Finds the highest nonzero band of the right channel, of each window
+    // of short bands.
+    let mut top  = [-1i32; 3];
+    let mut line = 0;
+    for i in 0..count {
+      let width = bands.widths[i] as usize;
+      if lines[1][line .. line + width].iter().any(|value| *value != 0f32) {
+        top[i % 3] = i as i32;
+      }
+      line += width;
+    }
+    if bands.long > 0 {
+      let max = top[0].max(top[1]).max(top[2]);
+      top = [max; 3];
+    }
+    // The highest bands have no scale factor, and take the intensity
+    // position of the band below.
+    let blocks = if bands.short > 0 { 3 } else { 1 };
+    for i in 0..blocks {
+      let highest = count - blocks + i;
+      let below   = highest - blocks;
+      self.positions[highest] =
+        if top[i] >= below as i32 {
+          if mpeg1 { 3 } else { 0 }
+        } else {
+          self.positions[below]
+        };
+    }
+
+    let intensity_scale = infos[1].scalefac_compress & 1;
+    let scale = if ms { 2f32.sqrt() } else { 1f32 };
+    let mut line = 0;
+    for i in 0..count {
+      let width    = bands.widths[i] as usize;
+      let position = self.positions[i];
+      let legal    = if mpeg1 { position < 7 } else { position >= 0 };
+      if i as i32 > top[i % 3] && legal {
+        let (left, right) =
+          if mpeg1 {
+            let angle = position as f32 * PI / 12f32;
+            let (sin, cos) = (angle.sin(), angle.cos());
+            (sin / (sin + cos), cos / (sin + cos))
+          } else {
+            let k = 2f32.powf(-((((position + 1) >> 1) << intensity_scale) as f32) / 4f32);
+            if position & 1 == 1 { (k, 1f32) } else { (1f32, k) }
+          };
+        for j in line .. line + width {
+          let value = lines[0][j];
+          lines[0][j] = value * left * scale;
+          lines[1][j] = value * right * scale;
+        }
+      } else if ms {
+        for j in line .. line + width {
+          let (m, s) = (lines[0][j], lines[1][j]);
+          lines[0][j] = m + s;
+          lines[1][j] = m - s;
+        }
+      }
+      line += width;
+    }
+  }
+}
+
+// Private functions
+
+/// Returns the first line of a band.
+fn band_start(widths: &[u8], band: usize) -> usize {
+  widths.iter().take(band).map(|width| *width as usize).sum()
+}
+
+/// Returns the lengths of the scale factors of MPEG-2 in each group of
+/// bands, and the row of their partitions.
+fn mpeg2_lengths(scalefac_compress: usize, intensity: bool) -> ([u32; 4], usize) {
+  let lengths = |values: [usize; 4]| {
+    [values[0] as u32, values[1] as u32, values[2] as u32, values[3] as u32]
+  };
+  if !intensity {
+    let s = scalefac_compress;
+    if s < 400 {
+      (lengths([(s >> 4) / 5, (s >> 4) % 5, (s & 15) >> 2, s & 3]), 0)
+    } else if s < 500 {
+      let s = s - 400;
+      (lengths([(s >> 2) / 5, (s >> 2) % 5, s & 3, 0]), 1)
+    } else {
+      let s = s - 500;
+      (lengths([s / 3, s % 3, 0, 0]), 2)
+    }
+  } else {
+    let s = scalefac_compress >> 1;
+    if s < 180 {
+      (lengths([s / 36, (s % 36) / 6, s % 6, 0]), 3)
+    } else if s < 244 {
+      let s = s - 180;
+      (lengths([(s & 63) >> 4, (s & 15) >> 2, s & 3, 0]), 4)
+    } else {
+      let s = s - 244;
+      (lengths([s / 3, s % 3, 0, 0]), 5)
+    }
+  }
+}
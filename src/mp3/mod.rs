@@ -0,0 +1,147 @@
+//! The MP3 Format
+//!
+//! MP3 files are a sequence of frames of MPEG-1, MPEG-2 or MPEG-2.5 Audio
+//! Layer III, each starting with a four byte header. A frame codes 1152
+//! samples of each channel at the sample rates of MPEG-1, and 576 samples
+//! at the lower sample rates of MPEG-2 and MPEG-2.5. The bitrate may change
+//! between frames, and the main data of a frame may begin in the frames
+//! before it through the bit reservoir.
+//!
+//! An ID3v2 tag at the start of the file is kept in the metadata, and an
+//! ID3v1 tag at its end is skipped. The first frame may hold a Xing or Info
+//! tag instead of audio, giving the number of frames of the stream. The
+//! encoder delay and padding of a LAME tag that follows it are used to trim
+//! the samples at the start and end of the stream, along with the delay of
+//! the decoder.
+//!
+//! Only Layer III is decoded, and free format streams are not supported.
+//! Frames whose main data is missing are decoded as silence.
+//!
+//! References
+//! - [ISO/IEC 11172-3](https://www.iso.org/standard/22412.html)
+//! - [ISO/IEC 13818-3](https://www.iso.org/standard/26797.html)
+//! - [LAME tag](http://gabriel.mp3-tech.org/mp3infotag.html)
+
+mod bits;
+mod container;
+mod header;
+mod huffman;
+mod hybrid;
+mod layer3;
+mod synthesis;
+mod tables;
+pub mod decoder;
+
+pub use mp3::decoder::Decoder as Decoder;
+
+/// Signature of an ID3v1 tag.
+const ID3V1: &'static [u8; 3] = b"TAG";
+
+/// Signature of a Xing tag of a variable bitrate stream.
+const XING: &'static [u8; 4] = b"Xing";
+
+/// Signature of a Xing tag of a constant bitrate stream.
+const INFO: &'static [u8; 4] = b"Info";
+
+/// Signature of a VBRI tag.
+const VBRI: &'static [u8; 4] = b"VBRI";
+
+/// Delay in samples of the hybrid and synthesis filterbanks of a decoder.
+const DECODER_DELAY: usize = 529;
+
+#[cfg(test)]
+mod io {
+  use std::fs::File;
+  use std::io::{Cursor, Read};
+  use std::path::Path;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::error::AudioError;
+  use ::id3::Id3Tag;
+  use ::testing::{SINE_LEFT, SINE_RIGHT, sine_error};
+
+  fn read_bytes(path: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn read_mono() {
+    let audio = audio::open(Path::new("tests/mp3/sine-mono.mp3")).unwrap();
+    assert_eq!(1,     audio.channels);
+    assert_eq!(44100, audio.sample_rate);
+    assert_eq!(10000, audio.samples.len());
+    assert!(sine_error(&audio, 0, SINE_LEFT, 0, 0) < 0.01);
+  }
+
+  #[test]
+  fn read_stereo() {
+    let audio = audio::open(Path::new("tests/mp3/sine-stereo.mp3")).unwrap();
+    assert_eq!(2,     audio.channels);
+    assert_eq!(22050, audio.sample_rate);
+    assert_eq!(6000,  audio.samples.len() / 2);
+    assert!(sine_error(&audio, 0, SINE_LEFT,  0, 0) < 0.01);
+    assert!(sine_error(&audio, 1, SINE_RIGHT, 0, 0) < 0.01);
+  }
+
+  #[test]
+  fn without_lame_tag() {
+    // Without the tag frame, all frames are decoded without trimming.
+    let bytes = read_bytes("tests/mp3/sine-mono.mp3");
+    let audio = audio::load(&mut Cursor::new(bytes[208..].to_vec()), AudioFormat::MP3).unwrap();
+    assert_eq!(10 * 1152, audio.samples.len());
+  }
+
+  #[test]
+  fn id3_tags() {
+    let plain = audio::open(Path::new("tests/mp3/sine-mono.mp3")).unwrap();
+    let mut tag = Id3Tag::default();
+    tag.set_text("TIT2", "Sine");
+    tag.set_text("TPE1", "Oscillator");
+    let mut bytes: Vec<u8> = Vec::new();
+    tag.write(&mut bytes).unwrap();
+    bytes.extend(read_bytes("tests/mp3/sine-mono.mp3"));
+    let mut id3v1 = b"TAG".to_vec();
+    id3v1.resize(128, 0xFF);
+    bytes.extend(id3v1);
+
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::MP3).unwrap();
+    assert_eq!(Some(&tag), audio.metadata.id3.as_ref());
+    assert_eq!(plain.samples, audio.samples);
+  }
+
+  #[test]
+  fn layer2_unsupported() {
+    // Two frames of MPEG-1 Layer II at 128 kbps and 44.1 kHz.
+    let mut bytes: Vec<u8> = Vec::new();
+    for _ in 0..2 {
+      let mut frame = vec![0xFF, 0xFD, 0x80, 0xC0];
+      frame.resize(417, 0);
+      bytes.extend(frame);
+    }
+    match audio::load(&mut Cursor::new(bytes), AudioFormat::MP3) {
+      Err(AudioError::Unsupported(_)) => {},
+      _ => panic!("Decoding Layer II should be unsupported")
+    }
+  }
+
+  #[test]
+  fn not_mp3() {
+    let mut bytes = Cursor::new(b"RIFF\x24\x00\x00\x00WAVEfmt ".to_vec());
+    match audio::load(&mut bytes, AudioFormat::MP3) {
+      Err(AudioError::Format(_)) => {},
+      _ => panic!("File without frames was not rejected")
+    }
+  }
+
+  #[test]
+  fn write_unsupported() {
+    let audio = audio::open(Path::new("tests/mp3/sine-mono.mp3")).unwrap();
+    let mut bytes: Vec<u8> = Vec::new();
+    match audio::write(&mut bytes, &audio, AudioFormat::MP3) {
+      Err(AudioError::Unsupported(_)) => {},
+      _ => panic!("Writing MP3 should be unsupported")
+    }
+  }
+}
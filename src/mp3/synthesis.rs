@@ -0,0 +1,57 @@
+//! Polyphase Synthesis Filterbank
+//!
+//! Each time slot of 32 subband samples is matrixed into 64 values that are
+//! shifted into a vector of 1024, and 32 output samples are made from the
+//! windowed sum of 16 of its parts.
+use std::f32::consts::PI;
+use mp3::tables::SYNTHESIS_WINDOW;
+
+/// The synthesis filterbank of a channel.
+pub struct Synthesis {
+  matrix: Vec<f32>,
+  window: Vec<f32>,
+  v:      Vec<f32>,
+  offset: usize
+}
+
+impl Synthesis {
+  pub fn new() -> Synthesis {
+    let mut matrix = Vec::with_capacity(64 * 32);
+    for i in 0..64 {
+      for k in 0..32 {
+        matrix.push(((16 + i) as f32 * (2 * k + 1) as f32 * PI / 64f32).cos());
+      }
+    }
+    let mut window = vec![0f32; 512];
+    for i in 0..257 {
+      let coefficient = SYNTHESIS_WINDOW[i] as f32 / 65536f32;
+      window[i] = coefficient;
+      if i > 0 && i < 256 {
+        window[512 - i] = if i % 64 == 0 { coefficient } else { -coefficient };
+      }
+    }
+    Synthesis {
+      matrix: matrix,
+      window: window,
+      v:      vec![0f32; 1024],
+      offset: 0
+    }
+  }
+
+  /// Synthesizes 32 output samples from a time slot of subband samples.
+  pub fn synthesize(&mut self, subbands: &[f32; 32], output: &mut [f32; 32]) {
+    self.offset = (self.offset + 1024 - 64) % 1024;
+    for i in 0..64 {
+      let row = &self.matrix[i * 32 .. i * 32 + 32];
+      self.v[self.offset + i] = row.iter().zip(subbands.iter()).map(|(n, s)| n * s).sum();
+    }
+    for j in 0..32 {
+      let mut sum = 0f32;
+      for i in 0..8 {
+        sum += self.v[(self.offset + 128 * i + j) % 1024] * self.window[64 * i + j];
+        sum += self.v[(self.offset + 128 * i + 96 + j) % 1024] * self.window[64 * i + 32 + j];
+      }
+      output[j] = sum;
+    }
+  }
+}
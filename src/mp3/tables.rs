@@ -0,0 +1,108 @@
+//! Layer III Tables
+//!
+//! The widths of the scale factor bands depend on the sample rate, and are
+//! indexed by `FrameHeader::band_index`. The bands of 8000 Hz mixed blocks
+//! follow those of common decoders.
+
+/// Widths of the scale factor bands of long blocks.
+pub const LONG_BANDS: [[u8; 22]; 8] = [
+  [6, 6, 6, 6, 6, 6, 8, 10, 12, 14, 16, 20, 24, 28, 32, 38, 46, 52, 60, 68, 58, 54],
+  [12, 12, 12, 12, 12, 12, 16, 20, 24, 28, 32, 40, 48, 56, 64, 76, 90, 2, 2, 2, 2, 2],
+  [6, 6, 6, 6, 6, 6, 8, 10, 12, 14, 16, 20, 24, 28, 32, 38, 46, 52, 60, 68, 58, 54],
+  [6, 6, 6, 6, 6, 6, 8, 10, 12, 14, 16, 18, 22, 26, 32, 38, 46, 54, 62, 70, 76, 36],
+  [6, 6, 6, 6, 6, 6, 8, 10, 12, 14, 16, 20, 24, 28, 32, 38, 46, 52, 60, 68, 58, 54],
+  [4, 4, 4, 4, 4, 4, 6, 6, 8, 8, 10, 12, 16, 20, 24, 28, 34, 42, 50, 54, 76, 158],
+  [4, 4, 4, 4, 4, 4, 6, 6, 6, 8, 10, 12, 16, 18, 22, 28, 34, 40, 46, 54, 54, 192],
+  [4, 4, 4, 4, 4, 4, 6, 6, 8, 10, 12, 16, 20, 24, 30, 38, 46, 56, 68, 84, 102, 26],
+];
+
+/// Widths of the scale factor bands of short blocks, repeated for each of
+/// the three windows.
+pub const SHORT_BANDS: [[u8; 39]; 8] = [
+  [4, 4, 4, 4, 4, 4, 4, 4, 4, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 24, 24, 24, 30, 30, 30, 40, 40, 40, 18, 18, 18],
+  [8, 8, 8, 8, 8, 8, 8, 8, 8, 12, 12, 12, 16, 16, 16, 20, 20, 20, 24, 24, 24, 28, 28, 28, 36, 36, 36, 2, 2, 2, 2, 2, 2, 2, 2, 2, 26, 26, 26],
+  [4, 4, 4, 4, 4, 4, 4, 4, 4, 6, 6, 6, 6, 6, 6, 8, 8, 8, 10, 10, 10, 14, 14, 14, 18, 18, 18, 26, 26, 26, 32, 32, 32, 42, 42, 42, 18, 18, 18],
+  [4, 4, 4, 4, 4, 4, 4, 4, 4, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 24, 24, 24, 32, 32, 32, 44, 44, 44, 12, 12, 12],
+  [4, 4, 4, 4, 4, 4, 4, 4, 4, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 24, 24, 24, 30, 30, 30, 40, 40, 40, 18, 18, 18],
+  [4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 22, 22, 22, 30, 30, 30, 56, 56, 56],
+  [4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 6, 6, 6, 6, 6, 6, 10, 10, 10, 12, 12, 12, 14, 14, 14, 16, 16, 16, 20, 20, 20, 26, 26, 26, 66, 66, 66],
+  [4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 6, 6, 6, 8, 8, 8, 12, 12, 12, 16, 16, 16, 20, 20, 20, 26, 26, 26, 34, 34, 34, 42, 42, 42, 12, 12, 12],
+];
+
+/// Widths of the scale factor bands of mixed blocks, where the long bands
+/// of the lowest subbands are followed by the remaining short bands.
+pub const MIXED_BANDS: [&'static [u8]; 8] = [
+  &[6, 6, 6, 6, 6, 6, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 24, 24, 24, 30, 30, 30, 40, 40, 40, 18, 18, 18],
+  &[12, 12, 12, 4, 4, 4, 8, 8, 8, 12, 12, 12, 16, 16, 16, 20, 20, 20, 24, 24, 24, 28, 28, 28, 36, 36, 36, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+  &[6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 8, 8, 8, 10, 10, 10, 14, 14, 14, 18, 18, 18, 26, 26, 26, 32, 32, 32, 42, 42, 42, 18, 18, 18],
+  &[6, 6, 6, 6, 6, 6, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 24, 24, 24, 32, 32, 32, 44, 44, 44, 12, 12, 12],
+  &[6, 6, 6, 6, 6, 6, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 24, 24, 24, 30, 30, 30, 40, 40, 40, 18, 18, 18],
+  &[4, 4, 4, 4, 4, 4, 6, 6, 4, 4, 4, 6, 6, 6, 8, 8, 8, 10, 10, 10, 12, 12, 12, 14, 14, 14, 18, 18, 18, 22, 22, 22, 30, 30, 30, 56, 56, 56],
+  &[4, 4, 4, 4, 4, 4, 6, 6, 4, 4, 4, 6, 6, 6, 6, 6, 6, 10, 10, 10, 12, 12, 12, 14, 14, 14, 16, 16, 16, 20, 20, 20, 26, 26, 26, 66, 66, 66],
+  &[4, 4, 4, 4, 4, 4, 6, 6, 4, 4, 4, 6, 6, 6, 8, 8, 8, 12, 12, 12, 16, 16, 16, 20, 20, 20, 26, 26, 26, 34, 34, 34, 42, 42, 42, 12, 12, 12],
+];
+
+/// Lengths of the scale factors of MPEG-1 of the lower and upper bands,
+/// indexed by `scalefac_compress`.
+pub const SLEN: [(u32, u32); 16] = [
+  (0, 0), (0, 1), (0, 2), (0, 3), (3, 0), (1, 1), (1, 2), (1, 3),
+  (2, 1), (2, 2), (2, 3), (3, 1), (3, 2), (3, 3), (4, 2), (4, 3)
+];
+
+/// Numbers of scale factors of MPEG-1 in each of the four groups of bands,
+/// for long, short and mixed blocks. The first two groups use the length
+/// of the lower bands, and the last two that of the upper bands. The
+/// groups of long blocks are also those of the scale factor selection
+/// information.
+pub const MPEG1_PARTITIONS: [[usize; 4]; 3] = [
+  [6, 5, 5, 5], [9, 9, 6, 12], [8, 9, 6, 12]
+];
+
+/// Numbers of scale factors of MPEG-2 in each of the four groups of bands,
+/// indexed by the range of `scalefac_compress` and by long, short and
+/// mixed blocks. The last three rows are used by the right channel of
+/// intensity stereo.
+pub const MPEG2_PARTITIONS: [[[usize; 4]; 3]; 6] = [
+  [[6, 5, 5, 5], [9, 9, 9, 9], [6, 9, 9, 9]],
+  [[6, 5, 7, 3], [9, 9, 12, 6], [6, 9, 12, 6]],
+  [[11, 10, 0, 0], [18, 18, 0, 0], [15, 18, 0, 0]],
+  [[7, 7, 7, 0], [12, 12, 12, 0], [6, 15, 12, 0]],
+  [[6, 6, 6, 3], [12, 9, 9, 6], [6, 12, 9, 6]],
+  [[8, 8, 5, 0], [15, 12, 9, 0], [6, 18, 9, 0]]
+];
+
+/// Amplification of the upper long bands, from band 11, added to their
+/// scale factors when `preflag` is set.
+pub const PRETAB: [u8; 10] = [1, 1, 1, 1, 2, 2, 3, 3, 3, 2];
+
+/// Coefficients of the alias reduction butterflies.
+pub const ALIAS_COEFFICIENTS: [f32; 8] = [
+  -0.6, -0.535, -0.33, -0.185, -0.095, -0.041, -0.0142, -0.0037
+];
+
+/// First half of the window of the synthesis filterbank, scaled by 65536.
+/// The second half mirrors it, negating all but every 64th coefficient.
+pub const SYNTHESIS_WINDOW: [i32; 257] = [
+  0, -1, -1, -1, -1, -1, -1, -2, -2, -2, -2, -3,
+  -3, -4, -4, -5, -5, -6, -7, -7, -8, -9, -10, -11,
+  -13, -14, -16, -17, -19, -21, -24, -26, -29, -31, -35, -38,
+  -41, -45, -49, -53, -58, -63, -68, -73, -79, -85, -91, -97,
+  -104, -111, -117, -125, -132, -139, -147, -154, -161, -169, -176, -183,
+  -190, -196, -202, -208, 213, 218, 222, 225, 227, 228, 228, 227,
+  224, 221, 215, 208, 200, 189, 177, 163, 146, 127, 106, 83,
+  57, 29, -2, -36, -72, -111, -153, -197, -244, -294, -347, -401,
+  -459, -519, -581, -645, -711, -779, -848, -919, -991, -1064, -1137, -1210,
+  -1283, -1356, -1428, -1498, -1567, -1634, -1698, -1759, -1817, -1870, -1919, -1962,
+  -2001, -2032, -2057, -2075, -2085, -2087, -2080, -2063, 2037, 2000, 1952, 1893,
+  1822, 1739, 1644, 1535, 1414, 1280, 1131, 970, 794, 605, 402, 185,
+  -45, -288, -545, -814, -1095, -1388, -1692, -2006, -2330, -2663, -3004, -3351,
+  -3705, -4063, -4425, -4788, -5153, -5517, -5879, -6237, -6589, -6935, -7271, -7597,
+  -7910, -8209, -8491, -8755, -8998, -9219, -9416, -9585, -9727, -9838, -9916, -9959,
+  -9966, -9935, -9863, -9750, -9592, -9389, -9139, -8840, -8492, -8092, -7640, -7134,
+  6574, 5959, 5288, 4561, 3776, 2935, 2037, 1082, 70, -998, -2122, -3300,
+  -4533, -5818, -7154, -8540, -9975, -11455, -12980, -14548, -16155, -17799, -19478, -21189,
+  -22929, -24694, -26482, -28289, -30112, -31947, -33791, -35640, -37489, -39336, -41176, -43006,
+  -44821, -46617, -48390, -50137, -51853, -53534, -55178, -56778, -58333, -59838, -61289, -62684,
+  -64019, -65290, -66494, -67629, -68692, -69679, -70590, -71420, -72169, -72835, -73415, -73908,
+  -74313, -74630, -74856, -74992, 75038,
+];
@@ -0,0 +1,135 @@
+//! MP4 Boxes
+//!
+//! Each box starts with its size and a four byte type. A size of one is
+//! followed by a 64-bit size, and a size of zero extends the box to the end
+//! of the file. Full boxes start with a version and 24 bits of flags.
+use byteorder::{BigEndian, ByteOrder};
+use error::*;
+
+/// A box and its contents, excluding its header.
+pub struct Mp4Box<'a> {
+  pub kind:   [u8; 4],
+  pub data:   &'a [u8]
+}
+
+/// Reads the boxes contained in the bytes.
+pub fn read_boxes(bytes: &[u8]) -> AudioResult<Vec<Mp4Box>> {
+  let mut boxes = Vec::new();
+  let mut position = 0;
+  while position + 8 <= bytes.len() {
+    let size = BigEndian::read_u32(&bytes[position .. position + 4]) as u64;
+    let kind = [bytes[position + 4], bytes[position + 5],
+                bytes[position + 6], bytes[position + 7]];
+    let (start, size) =
+      match size {
+        0 => (position + 8, (bytes.len() - position) as u64),
+        1 => {
+          if position + 16 > bytes.len() {
+            return Err(AudioError::Format(
+              "File is not valid MP4 (Incomplete box header)".to_string()
+            ));
+          }
+          (position + 16, BigEndian::read_u64(&bytes[position + 8 .. position + 16]))
+        },
+        s => (position + 8, s)
+      };
+    let end = position as u64 + size;
+    if end < start as u64 || end > bytes.len() as u64 {
+      return Err(AudioError::Format(
+        format!("File is not valid MP4 (Box {:?} has an invalid size)",
+                String::from_utf8_lossy(&kind))
+      ));
+    }
+    boxes.push(Mp4Box {
+      kind: kind,
+      data: &bytes[start .. end as usize]
+    });
+    position = end as usize;
+  }
+  Ok(boxes)
+}
+
+/// Returns the contents of the first box of the given type.
+pub fn find<'a>(boxes: &[Mp4Box<'a>], kind: &[u8; 4]) -> Option<&'a [u8]> {
+  boxes.iter().find(|b| &b.kind == kind).map(|b| b.data)
+}
+
+/// Returns the contents of the box found by following the path of box types
+/// through the contained boxes.
+pub fn find_path<'a>(bytes: &'a [u8], path: &[&[u8; 4]]) -> AudioResult<Option<&'a [u8]>> {
+  let mut data = bytes;
+  for kind in path.iter() {
+    data =
+      match find(&try!(read_boxes(data)), kind) {
+        Some(data) => data,
+        None       => return Ok(None)
+      };
+  }
+  Ok(Some(data))
+}
+
+/// Returns the contents of a full box following its version and flags.
+pub fn full_box_data(data: &[u8]) -> AudioResult<&[u8]> {
+  if data.len() < 4 {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Full box is too small)".to_string()
+    ));
+  }
+  Ok(&data[4..])
+}
+
+/// Writes a box with the given contents.
+pub fn write_box(kind: &[u8; 4], data: &[u8], bytes: &mut Vec<u8>) {
+  push_u32(bytes, 8 + data.len() as u32);
+  bytes.extend_from_slice(kind);
+  bytes.extend_from_slice(data);
+}
+
+/// Writes a full box of version zero with the given flags and contents.
+pub fn write_full_box(kind: &[u8; 4], flags: u32, data: &[u8], bytes: &mut Vec<u8>) {
+  push_u32(bytes, 12 + data.len() as u32);
+  bytes.extend_from_slice(kind);
+  push_u32(bytes, flags & 0xFFFFFF);
+  bytes.extend_from_slice(data);
+}
+
+#[inline]
+pub fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+  bytes.push((value >> 8) as u8);
+  bytes.push(value as u8);
+}
+
+#[inline]
+pub fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+  push_u16(bytes, (value >> 16) as u16);
+  push_u16(bytes, value as u16);
+}
+
+#[cfg(test)]
+mod boxes {
+  use super::*;
+
+  #[test]
+  fn read_and_write() {
+    let mut inner: Vec<u8> = Vec::new();
+    write_full_box(b"mdhd", 0, &[1, 2, 3, 4], &mut inner);
+    write_box(b"free", &[], &mut inner);
+    let mut bytes: Vec<u8> = Vec::new();
+    write_box(b"mdia", &inner, &mut bytes);
+    // A box with a 64-bit size, followed by one extending to the end.
+    bytes.extend_from_slice(&[0, 0, 0, 1, b'w', b'i', b'd', b'e',
+                              0, 0, 0, 0, 0, 0, 0, 18, 7, 7]);
+    bytes.extend_from_slice(&[0, 0, 0, 0, b'm', b'd', b'a', b't', 9]);
+
+    let boxes = read_boxes(&bytes).unwrap();
+    assert_eq!(3, boxes.len());
+    assert_eq!(&[7, 7], find(&boxes, b"wide").unwrap());
+    assert_eq!(&[9], find(&boxes, b"mdat").unwrap());
+    let mdhd = find_path(&bytes, &[b"mdia", b"mdhd"]).unwrap().unwrap();
+    assert_eq!(&[1, 2, 3, 4], full_box_data(mdhd).unwrap());
+    assert!(find_path(&bytes, &[b"mdia", b"minf"]).unwrap().is_none());
+
+    bytes[3] = 200;
+    assert!(read_boxes(&bytes).is_err());
+  }
+}
@@ -0,0 +1,441 @@
+use std::io::{Read, Seek, Write};
+use buffer::*;
+use byteorder::{BigEndian, ByteOrder};
+use codecs::Codec;
+use codecs::Codec::*;
+use codecs::alac;
+use codecs::alac::AlacConfig;
+use error::*;
+use metadata::Metadata;
+use mp4::*;
+use mp4::boxes::*;
+use sample::*;
+use traits::Container;
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct Mp4Container {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for Mp4Container {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<Mp4Container> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(reader.read_to_end(&mut bytes));
+    let top = try!(read_boxes(&bytes));
+    if top.is_empty() || (&top[0].kind != FTYP && find(&top, MOOV).is_none()) {
+      return Err(AudioError::Format(
+        "Not valid MP4".to_string()
+      ));
+    }
+    let moov =
+      match find(&top, MOOV) {
+        Some(moov) => moov,
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid MP4 (Missing movie box)".to_string()
+          ))
+      };
+
+    // The sample table of the first audio track is read.
+    let mut stbl: Option<&[u8]> = None;
+    for trak in try!(read_boxes(moov)).iter().filter(|b| &b.kind == TRAK) {
+      if let Some(hdlr) = try!(find_path(trak.data, &[MDIA, HDLR])) {
+        if hdlr.len() >= 12 && &hdlr[8..12] == SOUN {
+          stbl = try!(find_path(trak.data, &[MDIA, MINF, STBL]));
+          break;
+        }
+      }
+    }
+    let stbl =
+      match stbl {
+        Some(stbl) => try!(read_boxes(stbl)),
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid MP4 (Missing audio track)".to_string()
+          ))
+      };
+    let (stsd, stsz) =
+      match (find(&stbl, STSD), find(&stbl, STSZ)) {
+        (Some(stsd), Some(stsz)) => (stsd, stsz),
+        _ =>
+          return Err(AudioError::Format(
+            "File is not valid MP4 \
+            (Missing sample description or sample size box)".to_string()
+          ))
+      };
+    let (config, sample_rate) = try!(read_sample_description(try!(full_box_data(stsd))));
+    let sizes   = try!(read_sample_sizes(try!(full_box_data(stsz))));
+    let offsets =
+      match (find(&stbl, STCO), find(&stbl, CO64)) {
+        (Some(stco), _) => try!(read_chunk_offsets(try!(full_box_data(stco)), 4)),
+        (_, Some(co64)) => try!(read_chunk_offsets(try!(full_box_data(co64)), 8)),
+        _ =>
+          return Err(AudioError::Format(
+            "File is not valid MP4 (Missing chunk offset box)".to_string()
+          ))
+      };
+    // Without a sample to chunk box, each chunk holds one sample.
+    let chunk_runs =
+      match find(&stbl, STSC) {
+        Some(stsc) => try!(read_sample_to_chunk(try!(full_box_data(stsc)))),
+        None       => vec![(1, 1)]
+      };
+
+    // Samples are stored in chunks of consecutive samples, usually within
+    // the media data box.
+    let mut packets: Vec<&[u8]> = Vec::with_capacity(sizes.len());
+    for (i, offset) in offsets.iter().enumerate() {
+      let chunk = i as u32 + 1;
+      let count = chunk_runs.iter()
+                  .take_while(|&&(first_chunk, _)| first_chunk <= chunk)
+                  .last()
+                  .map(|&(_, count)| count)
+                  .unwrap_or(0);
+      let mut position = *offset;
+      for _ in 0..count {
+        if packets.len() == sizes.len() {
+          break;
+        }
+        let end = position + sizes[packets.len()] as u64;
+        if end > bytes.len() as u64 {
+          return Err(AudioError::Format(
+            "File is not valid MP4 (Sample extends past the end of the file)".to_string()
+          ));
+        }
+        packets.push(&bytes[position as usize .. end as usize]);
+        position = end;
+      }
+    }
+    if packets.len() < sizes.len() {
+      return Err(AudioError::Format(
+        "File is not valid MP4 (Chunks do not hold every sample)".to_string()
+      ));
+    }
+    let samples = try!(alac::read(&config, &packets));
+
+    let channels = config.channels as u32;
+    Ok(Mp4Container {
+      bit_depth:      config.bit_depth as u32,
+      sample_rate:    sample_rate,
+      channels:       channels,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:        samples,
+      metadata:       Metadata::default()
+    })
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    match codec {
+      ALAC_I16 | ALAC_I20 | ALAC_I24 | ALAC_I32 => {},
+      c @ _ =>
+        return Err(AudioError::Unsupported(
+          format!("MP4 does not support the {:?} codec", c)
+        ))
+    }
+    let (config, packets) = try!(alac::create(audio, codec));
+    let frames = (audio.samples.len() / audio.channels as usize) as u64;
+    let data_size = packets.iter().fold(0u64, |size, packet| size + packet.len() as u64);
+
+    let mut ftyp: Vec<u8> = Vec::new();
+    ftyp.extend_from_slice(M4A_BRAND);
+    push_u32(&mut ftyp, 0);
+    for brand in [M4A_BRAND, b"mp42", b"isom"].iter() {
+      ftyp.extend_from_slice(*brand);
+    }
+    let mut header: Vec<u8> = Vec::new();
+    write_box(FTYP, &ftyp, &mut header);
+
+    // The movie box precedes the media data, so the offset of the samples
+    // depends on its size, which does not depend on the offset.
+    let moov_size = movie_box(audio, &config, &packets, frames, 0).len();
+    let offset = header.len() + moov_size + 8;
+    if offset as u64 + data_size > u32::max_value() as u64 {
+      return Err(AudioError::Unsupported(
+        "MP4 files larger than 4 GB are not supported".to_string()
+      ));
+    }
+    header.extend_from_slice(&movie_box(audio, &config, &packets, frames, offset as u32));
+    push_u32(&mut header, 8 + data_size as u32);
+    header.extend_from_slice(MDAT);
+
+    try!(writer.write_all(&header));
+    for packet in packets.iter() {
+      try!(writer.write_all(packet));
+    }
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Reads the sample description box, returning the configuration of the
+/// Apple Lossless stream and the sample rate.
+fn read_sample_description(data: &[u8]) -> AudioResult<(AlacConfig, u32)> {
+  if data.len() < 4 {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Sample description box is too small)".to_string()
+    ));
+  }
+  let entries = try!(read_boxes(&data[4..]));
+  let entry =
+    match entries.first() {
+      Some(entry) if &entry.kind == ALAC => entry.data,
+      Some(entry) =>
+        return Err(AudioError::Unsupported(
+          format!("MP4 audio with {:?} sample entries is not supported",
+                  String::from_utf8_lossy(&entry.kind))
+        )),
+      None =>
+        return Err(AudioError::Format(
+          "File is not valid MP4 (Missing sample entry)".to_string()
+        ))
+    };
+  if entry.len() < 28 {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Sample entry is too small)".to_string()
+    ));
+  }
+  // QuickTime sound sample descriptions of later versions have more fields
+  // before the contained boxes.
+  let fields_size =
+    match BigEndian::read_u16(&entry[8..10]) {
+      0 => 28,
+      1 => 44,
+      2 => 64,
+      v =>
+        return Err(AudioError::Unsupported(
+          format!("MP4 sound sample description version {} is not supported", v)
+        ))
+    };
+  if entry.len() < fields_size {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Sample entry is too small)".to_string()
+    ));
+  }
+  let cookie =
+    match find(&try!(read_boxes(&entry[fields_size..])), ALAC) {
+      Some(cookie) => try!(full_box_data(cookie)),
+      None =>
+        return Err(AudioError::Format(
+          "File is not valid MP4 (Missing Apple Lossless configuration)".to_string()
+        ))
+    };
+  let config = try!(AlacConfig::read(cookie));
+  let sample_rate =
+    if config.sample_rate != 0 {
+      config.sample_rate
+    } else {
+      BigEndian::read_u32(&entry[24..28]) >> 16
+    };
+  Ok((config, sample_rate))
+}
+
+/// Reads the size of each sample from the sample size box.
+fn read_sample_sizes(data: &[u8]) -> AudioResult<Vec<u32>> {
+  if data.len() < 8 {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Sample size box is too small)".to_string()
+    ));
+  }
+  let sample_size = BigEndian::read_u32(&data[0..4]);
+  let count = BigEndian::read_u32(&data[4..8]) as usize;
+  if sample_size != 0 {
+    return Ok(vec![sample_size; count]);
+  }
+  if data.len() < 8 + 4 * count {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Sample size box is missing entries)".to_string()
+    ));
+  }
+  Ok(data[8 .. 8 + 4 * count].chunks(4).map(BigEndian::read_u32).collect())
+}
+
+/// Reads the runs of chunks with the same number of samples, as the first
+/// chunk of each run and the number of samples.
+fn read_sample_to_chunk(data: &[u8]) -> AudioResult<Vec<(u32, u32)>> {
+  if data.len() < 4 {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Sample to chunk box is too small)".to_string()
+    ));
+  }
+  let count = BigEndian::read_u32(&data[0..4]) as usize;
+  if data.len() < 4 + 12 * count {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Sample to chunk box is missing entries)".to_string()
+    ));
+  }
+  Ok(data[4 .. 4 + 12 * count].chunks(12)
+     .map(|entry| (BigEndian::read_u32(&entry[0..4]), BigEndian::read_u32(&entry[4..8])))
+     .collect())
+}
+
+/// Reads the file offset of each chunk, stored in 32 or 64 bits.
+fn read_chunk_offsets(data: &[u8], size: usize) -> AudioResult<Vec<u64>> {
+  if data.len() < 4 {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Chunk offset box is too small)".to_string()
+    ));
+  }
+  let count = BigEndian::read_u32(&data[0..4]) as usize;
+  if data.len() < 4 + size * count {
+    return Err(AudioError::Format(
+      "File is not valid MP4 (Chunk offset box is missing entries)".to_string()
+    ));
+  }
+  Ok(data[4 .. 4 + size * count].chunks(size)
+     .map(|offset|
+       if size == 8 {
+         BigEndian::read_u64(offset)
+       } else {
+         BigEndian::read_u32(offset) as u64
+       }
+     )
+     .collect())
+}
+
+/// Returns the movie box of a single audio track, whose samples are stored
+/// in one chunk at the given offset.
+fn movie_box(audio: &AudioBuffer, config: &AlacConfig, packets: &[Vec<u8>], frames: u64, offset: u32) -> Vec<u8> {
+  let duration = frames.min(u32::max_value() as u64) as u32;
+  let matrix: [u32; 9] = [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000];
+
+  let mut mvhd: Vec<u8> = Vec::new();
+  push_u32(&mut mvhd, 0);
+  push_u32(&mut mvhd, 0);
+  push_u32(&mut mvhd, audio.sample_rate);
+  push_u32(&mut mvhd, duration);
+  push_u32(&mut mvhd, 0x10000);
+  push_u16(&mut mvhd, 0x100);
+  mvhd.extend_from_slice(&[0u8; 10]);
+  for value in matrix.iter() {
+    push_u32(&mut mvhd, *value);
+  }
+  mvhd.extend_from_slice(&[0u8; 24]);
+  push_u32(&mut mvhd, 2);
+
+  let mut tkhd: Vec<u8> = Vec::new();
+  push_u32(&mut tkhd, 0);
+  push_u32(&mut tkhd, 0);
+  push_u32(&mut tkhd, 1);
+  push_u32(&mut tkhd, 0);
+  push_u32(&mut tkhd, duration);
+  tkhd.extend_from_slice(&[0u8; 12]);
+  push_u16(&mut tkhd, 0x100);
+  push_u16(&mut tkhd, 0);
+  for value in matrix.iter() {
+    push_u32(&mut tkhd, *value);
+  }
+  tkhd.extend_from_slice(&[0u8; 8]);
+
+  let mut mdhd: Vec<u8> = Vec::new();
+  push_u32(&mut mdhd, 0);
+  push_u32(&mut mdhd, 0);
+  push_u32(&mut mdhd, audio.sample_rate);
+  push_u32(&mut mdhd, duration);
+  push_u16(&mut mdhd, LANGUAGE_UNDETERMINED);
+  push_u16(&mut mdhd, 0);
+
+  let mut hdlr: Vec<u8> = Vec::new();
+  push_u32(&mut hdlr, 0);
+  hdlr.extend_from_slice(SOUN);
+  hdlr.extend_from_slice(&[0u8; 12]);
+  hdlr.extend_from_slice(b"SoundHandler\0");
+
+  // Sample entry
+  let mut entry: Vec<u8> = vec![0u8; 6];
+  push_u16(&mut entry, 1);
+  entry.extend_from_slice(&[0u8; 8]);
+  push_u16(&mut entry, audio.channels as u16);
+  push_u16(&mut entry, config.bit_depth as u16);
+  push_u32(&mut entry, 0);
+  push_u32(&mut entry, if audio.sample_rate <= 0xFFFF { audio.sample_rate << 16 } else { 0 });
+  write_full_box(ALAC, 0, &config.to_bytes(), &mut entry);
+  let mut stsd: Vec<u8> = Vec::new();
+  push_u32(&mut stsd, 1);
+  write_box(ALAC, &entry, &mut stsd);
+
+  // Every packet holds a frame length of frames, except the last.
+  let mut stts: Vec<u8> = Vec::new();
+  let last = frames - (packets.len() as u64).saturating_sub(1) * config.frame_length as u64;
+  match packets.len() {
+    0 => push_u32(&mut stts, 0),
+    1 => {
+      push_u32(&mut stts, 1);
+      push_u32(&mut stts, 1);
+      push_u32(&mut stts, last as u32);
+    },
+    n => {
+      push_u32(&mut stts, 2);
+      push_u32(&mut stts, n as u32 - 1);
+      push_u32(&mut stts, config.frame_length);
+      push_u32(&mut stts, 1);
+      push_u32(&mut stts, last as u32);
+    }
+  }
+  let mut stsc: Vec<u8> = Vec::new();
+  let mut stco: Vec<u8> = Vec::new();
+  if packets.is_empty() {
+    push_u32(&mut stsc, 0);
+    push_u32(&mut stco, 0);
+  } else {
+    push_u32(&mut stsc, 1);
+    push_u32(&mut stsc, 1);
+    push_u32(&mut stsc, packets.len() as u32);
+    push_u32(&mut stsc, 1);
+    push_u32(&mut stco, 1);
+    push_u32(&mut stco, offset);
+  }
+  let mut stsz: Vec<u8> = Vec::new();
+  push_u32(&mut stsz, 0);
+  push_u32(&mut stsz, packets.len() as u32);
+  for packet in packets.iter() {
+    push_u32(&mut stsz, packet.len() as u32);
+  }
+
+  let mut stbl: Vec<u8> = Vec::new();
+  write_full_box(STSD, 0, &stsd, &mut stbl);
+  write_full_box(STTS, 0, &stts, &mut stbl);
+  write_full_box(STSC, 0, &stsc, &mut stbl);
+  write_full_box(STSZ, 0, &stsz, &mut stbl);
+  write_full_box(STCO, 0, &stco, &mut stbl);
+
+  let mut dref: Vec<u8> = Vec::new();
+  push_u32(&mut dref, 1);
+  // The media data is in the same file.
+  write_full_box(b"url ", 1, &[], &mut dref);
+  let mut dinf: Vec<u8> = Vec::new();
+  write_full_box(b"dref", 0, &dref, &mut dinf);
+
+  let mut minf: Vec<u8> = Vec::new();
+  write_full_box(b"smhd", 0, &[0u8; 4], &mut minf);
+  write_box(b"dinf", &dinf, &mut minf);
+  write_box(STBL, &stbl, &mut minf);
+
+  let mut mdia: Vec<u8> = Vec::new();
+  write_full_box(b"mdhd", 0, &mdhd, &mut mdia);
+  write_full_box(HDLR, 0, &hdlr, &mut mdia);
+  write_box(MINF, &minf, &mut mdia);
+
+  let mut trak: Vec<u8> = Vec::new();
+  // The track is enabled and used in the movie and its preview.
+  write_full_box(b"tkhd", 7, &tkhd, &mut trak);
+  write_box(MDIA, &mdia, &mut trak);
+
+  let mut moov: Vec<u8> = Vec::new();
+  write_full_box(b"mvhd", 0, &mvhd, &mut moov);
+  write_box(TRAK, &trak, &mut moov);
+
+  let mut bytes: Vec<u8> = Vec::new();
+  write_box(MOOV, &moov, &mut bytes);
+  bytes
+}
@@ -0,0 +1,37 @@
+use std::io::{Read, Seek};
+use buffer::AudioBuffer;
+use error::AudioResult;
+use mp4::container::Mp4Container;
+use traits::{AudioDecoder, Container};
+
+/// Decodes audio in MPEG-4 Audio format from the
+/// provided reader.
+pub struct Decoder<'r, R: 'r> where R: Read + Seek {
+  reader: &'r mut R,
+}
+
+impl<'r, R> Decoder<'r, R> where R: Read + Seek {
+  /// Create a new MPEG-4 Audio format `Decoder` using
+  /// the provided reader.
+  #[inline]
+  pub fn new(reader: &'r mut R) -> Decoder<R> {
+    Decoder {
+      reader: reader
+    }
+  }
+}
+
+impl<'r, R> AudioDecoder for Decoder<'r, R> where R: Read + Seek {
+  /// Creates an `AudioBuffer` from the included reader via
+  /// a `Mp4Container`.
+  #[inline]
+  fn decode(mut self) -> AudioResult<AudioBuffer> {
+    let container = try!(Mp4Container::open(self.reader));
+    Ok(AudioBuffer {
+      sample_rate:  container.sample_rate,
+      channels:     container.channels,
+      samples:      container.samples,
+      metadata:     container.metadata
+    })
+  }
+}
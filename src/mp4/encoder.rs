@@ -0,0 +1,38 @@
+use std::io::Write;
+use buffer::AudioBuffer;
+use codecs::Codec;
+use codecs::Codec::ALAC_I16;
+use error::AudioResult;
+use mp4::container::Mp4Container;
+use traits::{AudioEncoder, Container};
+
+/// Encodes audio to MPEG-4 Audio format to the provided writer.
+pub struct Encoder<'w, W: 'w> {
+  writer: &'w mut W,
+}
+
+impl<'w, W> Encoder<'w, W> where W: Write {
+  /// Create a new MPEG-4 Audio format `Encoder` using the provided writer.
+  #[inline]
+  pub fn new(writer: &'w mut W) -> Encoder<'w, W> {
+    Encoder {
+      writer: writer
+    }
+  }
+}
+
+impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
+  /// Creates and writes a `Mp4Container` to the included writer. The audio
+  /// is encoded to 16-bit Apple Lossless audio.
+  #[inline]
+  fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
+    Mp4Container::create(&mut self.writer, audio, ALAC_I16)
+  }
+  /// Creates and writes a `Mp4Container` using the provided `SampleFormat`
+  /// to the included writer. This is how audio can be encoded to different
+  /// bit rates supported by the format.
+  #[inline]
+  fn encode_as(&mut self, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    Mp4Container::create(&mut self.writer, audio, codec)
+  }
+}
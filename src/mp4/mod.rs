@@ -0,0 +1,142 @@
+//! The MPEG-4 Audio Format
+//!
+//! MP4 files are made of nested boxes. The movie box describes each track,
+//! including a sample table locating the samples of the track within the
+//! media data box. Samples are grouped into chunks of consecutive samples,
+//! whose offsets are stored in the chunk offset box, and the size of each
+//! sample is stored in the sample size box.
+//!
+//! Only the first audio track is read, and only Apple Lossless audio is
+//! supported. Each sample of an Apple Lossless track is a packet, and the
+//! configuration of the stream is stored in an `alac` box within the sample
+//! entry. Files are written with the movie box before the media data, which
+//! holds every packet in a single chunk.
+//!
+//! References
+//! - [ISO/IEC 14496-12](https://www.iso.org/standard/68960.html)
+//! - [QuickTime File Format](https://developer.apple.com/library/archive/documentation/QuickTime/QTFF/QTFFChap2/qtff2.html)
+//! - [Apple Lossless](https://github.com/macosforge/alac)
+
+mod boxes;
+mod container;
+pub mod decoder;
+pub mod encoder;
+
+pub use mp4::decoder::Decoder as Decoder;
+pub use mp4::encoder::Encoder as Encoder;
+
+const FTYP: &'static [u8; 4] = b"ftyp";
+const MOOV: &'static [u8; 4] = b"moov";
+const TRAK: &'static [u8; 4] = b"trak";
+const MDIA: &'static [u8; 4] = b"mdia";
+const HDLR: &'static [u8; 4] = b"hdlr";
+const MINF: &'static [u8; 4] = b"minf";
+const STBL: &'static [u8; 4] = b"stbl";
+const STSD: &'static [u8; 4] = b"stsd";
+const STTS: &'static [u8; 4] = b"stts";
+const STSC: &'static [u8; 4] = b"stsc";
+const STSZ: &'static [u8; 4] = b"stsz";
+const STCO: &'static [u8; 4] = b"stco";
+const CO64: &'static [u8; 4] = b"co64";
+const MDAT: &'static [u8; 4] = b"mdat";
+const ALAC: &'static [u8; 4] = b"alac";
+
+/// Handler type of audio tracks.
+const SOUN: &'static [u8; 4] = b"soun";
+
+/// Brand of MPEG-4 audio files.
+const M4A_BRAND: &'static [u8; 4] = b"M4A ";
+
+/// Packed ISO 639-2 language code "und".
+const LANGUAGE_UNDETERMINED: u16 = 0x55C4;
+
+#[cfg(test)]
+mod io {
+  use std::io::Cursor;
+  use std::path::Path;
+  use byteorder::{BigEndian, ByteOrder};
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+
+  #[test]
+  fn codecs_eq() {
+    let audio = audio::open(Path::new("tests/aiff/M1F1-int16-AFsp.aif")).unwrap();
+    for &(codec, lpcm) in [(ALAC_I16, LPCM_I16_BE), (ALAC_I20, LPCM_I16_BE),
+                           (ALAC_I24, LPCM_I24_BE), (ALAC_I32, LPCM_I32_BE)].iter() {
+      let write_path = Path::new("tests/results/tmp_alac.m4a");
+      assert!(audio::save_as(&write_path, &audio, codec).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      let expected = AudioBuffer::from_bytes(audio.sample_rate, audio.channels,
+        &::codecs::encode(&audio, lpcm).unwrap(), lpcm).unwrap();
+      assert_eq!(audio.channels,    verify.channels);
+      assert_eq!(audio.sample_rate, verify.sample_rate);
+      assert_eq!(expected.samples,  verify.samples);
+    }
+  }
+
+  #[test]
+  fn multichannel() {
+    let samples = (0..6 * 5000).map(|i| ((i % 97) as f32 / 48f32) - 1f32).collect();
+    let audio = AudioBuffer::from_samples(96000, 6, samples);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::MP4).is_ok());
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::MP4).unwrap();
+    let expected = AudioBuffer::from_bytes(audio.sample_rate, audio.channels,
+      &::codecs::encode(&audio, LPCM_I16_BE).unwrap(), LPCM_I16_BE).unwrap();
+    assert_eq!(6,     verify.channels);
+    assert_eq!(96000, verify.sample_rate);
+    assert_eq!(expected.samples, verify.samples);
+  }
+
+  #[test]
+  fn read_apple_lossless() {
+    for &(path, source) in [
+      ("tests/mp4/M1F1-int16-AFsp.m4a", "tests/aiff/M1F1-int16-AFsp.aif"),
+      ("tests/mp4/M1F1-int24-AFsp.m4a", "tests/aiff/M1F1-int24-AFsp.aif"),
+      ("tests/mp4/sine-5.1.m4a",        "tests/wavpack/sine-5.1.wav")
+    ].iter() {
+      let audio = audio::open(Path::new(path)).unwrap();
+      let expected = audio::open(Path::new(source)).unwrap();
+      assert_eq!(expected.channels,    audio.channels);
+      assert_eq!(expected.sample_rate, audio.sample_rate);
+      assert_eq!(expected.samples,     audio.samples);
+    }
+  }
+
+  #[test]
+  fn box_layout() {
+    let audio = AudioBuffer::from_samples(44100, 2, vec![0.25f32; 2 * 10000]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::MP4).is_ok());
+    assert_eq!(b"ftyp", &bytes[4..8]);
+    assert_eq!(b"M4A ", &bytes[8..12]);
+    let moov = BigEndian::read_u32(&bytes[0..4]) as usize;
+    assert_eq!(b"moov", &bytes[moov + 4 .. moov + 8]);
+    let mdat = moov + BigEndian::read_u32(&bytes[moov .. moov + 4]) as usize;
+    assert_eq!(b"mdat", &bytes[mdat + 4 .. mdat + 8]);
+    assert_eq!(bytes.len(), mdat + BigEndian::read_u32(&bytes[mdat .. mdat + 4]) as usize);
+
+    // The single chunk starts at the beginning of the media data.
+    let stco = (moov..mdat).find(|&i| &bytes[i .. i + 4] == b"stco").unwrap();
+    assert_eq!(1, BigEndian::read_u32(&bytes[stco + 8 .. stco + 12]));
+    assert_eq!(mdat + 8, BigEndian::read_u32(&bytes[stco + 12 .. stco + 16]) as usize);
+  }
+
+  #[test]
+  fn errors() {
+    let audio = AudioBuffer::from_samples(8000, 1, vec![0f32; 4]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::MP4, LPCM_I16_BE).is_err());
+
+    let not_mp4 = b"RIFF\x04\x00\x00\x00WAVE".to_vec();
+    assert!(audio::load(&mut Cursor::new(not_mp4), AudioFormat::MP4).is_err());
+
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::MP4).is_ok());
+    let length = bytes.len();
+    bytes.truncate(length - 1);
+    assert!(audio::load(&mut Cursor::new(bytes), AudioFormat::MP4).is_err());
+  }
+}
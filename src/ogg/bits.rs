@@ -0,0 +1,78 @@
+//! Vorbis Bitpacking
+use error::*;
+
+/// Reads integers packed into a byte slice, starting at the least
+/// significant bit of each byte as described by the Vorbis bitpacking
+/// convention.
+///
+/// Reading past the end of the packet returns `AudioError::AudioEnd`, which
+/// the audio decoding process uses to detect the end of a packet.
+pub struct BitReader<'a> {
+  data:     &'a [u8],
+  position: usize
+}
+
+impl<'a> BitReader<'a> {
+  #[inline]
+  pub fn new(data: &'a [u8]) -> BitReader<'a> {
+    BitReader {
+      data:     data,
+      position: 0
+    }
+  }
+
+  /// Reads an unsigned integer of up to 32 bits.
+  pub fn read(&mut self, bits: u32) -> AudioResult<u32> {
+    debug_assert!(bits <= 32);
+    if self.position + bits as usize > self.data.len() * 8 {
+      self.position = self.data.len() * 8;
+      return Err(AudioError::AudioEnd);
+    }
+    let mut value: u64 = 0;
+    let mut read = 0;
+    while read < bits {
+      let byte   = self.data[self.position / 8] as u64;
+      let offset = (self.position % 8) as u32;
+      let count  = (8 - offset).min(bits - read);
+      value |= ((byte >> offset) & ((1 << count) - 1)) << read;
+      read          += count;
+      self.position += count as usize;
+    }
+    Ok(value as u32)
+  }
+
+  #[inline]
+  pub fn read_bool(&mut self) -> AudioResult<bool> {
+    Ok(try!(self.read(1)) == 1)
+  }
+}
+
+/// Returns the number of bits needed to represent a value.
+#[inline]
+pub fn ilog(value: u32) -> u32 {
+  32 - value.leading_zeros()
+}
+
+#[cfg(test)]
+mod bitpacking {
+  use super::*;
+
+  #[test]
+  fn read_lsb_first() {
+    let data = [0b1010_0110u8, 0b0000_0001, 0xFF];
+    let mut bits = BitReader::new(&data);
+    assert_eq!(0b110, bits.read(3).unwrap());
+    assert_eq!(0b1_1010_0, bits.read(6).unwrap());
+    assert_eq!(0x7F80, bits.read(15).unwrap());
+    assert!(bits.read(1).is_err());
+  }
+
+  #[test]
+  fn ilog_values() {
+    assert_eq!(0, ilog(0));
+    assert_eq!(1, ilog(1));
+    assert_eq!(2, ilog(3));
+    assert_eq!(3, ilog(4));
+    assert_eq!(32, ilog(0xFFFFFFFF));
+  }
+}
@@ -0,0 +1,302 @@
+//! Vorbis Codebooks
+use error::*;
+use ogg::bits::{BitReader, ilog};
+
+/// Sync pattern starting each codebook of the setup header.
+const CODEBOOK_SYNC: u32 = 0x564342;
+
+/// A codebook, which maps Huffman codewords to entry numbers, and entry
+/// numbers to vectors of values for books with a lookup table.
+///
+/// Codewords are decoded by walking a binary tree. Each node holds two
+/// children, where a positive value is the index of the next node, a
+/// negative value is a leaf holding `-(entry + 1)`, and zero is an unused
+/// codeword.
+#[derive(Debug)]
+pub struct Codebook {
+  pub dimensions: usize,
+  tree:           Vec<[i32; 2]>,
+  values:         Option<Vec<f32>>
+}
+
+impl Codebook {
+  /// Reads a codebook from the setup header.
+  pub fn read(bits: &mut BitReader) -> AudioResult<Codebook> {
+    if try!(bits.read(24)) != CODEBOOK_SYNC {
+      return Err(AudioError::Format(
+        "File is not valid Vorbis (Invalid codebook sync pattern)".to_string()
+      ));
+    }
+    let dimensions = try!(bits.read(16)) as usize;
+    let entries    = try!(bits.read(24)) as usize;
+    if dimensions == 0 && entries > 0 {
+      return Err(AudioError::Format(
+        "File is not valid Vorbis (Codebook has no dimensions)".to_string()
+      ));
+    }
+
+    // Read the codeword lengths, where a length of zero is an unused entry.
+    let mut lengths: Vec<u8> = vec![0u8; entries];
+    if try!(bits.read_bool()) {
+      // Ordered lengths are given as the number of entries of each length.
+      let mut length = try!(bits.read(5)) + 1;
+      let mut entry  = 0;
+      while entry < entries {
+        let count = try!(bits.read(ilog((entries - entry) as u32))) as usize;
+        if length > 32 || entry + count > entries {
+          return Err(AudioError::Format(
+            "File is not valid Vorbis (Invalid codebook lengths)".to_string()
+          ));
+        }
+        for len in lengths[entry .. entry + count].iter_mut() {
+          *len = length as u8;
+        }
+        entry  += count;
+        length += 1;
+      }
+    }
+    else {
+      let sparse = try!(bits.read_bool());
+      for len in lengths.iter_mut() {
+        if !sparse || try!(bits.read_bool()) {
+          *len = try!(bits.read(5)) as u8 + 1;
+        }
+      }
+    }
+    let tree = try!(build_tree(&lengths));
+
+    // Read the lookup table used to create the vector of each entry.
+    let lookup_type = try!(bits.read(4));
+    let values =
+      match lookup_type {
+        0 => None,
+        1 | 2 => {
+          let minimum    = float32_unpack(try!(bits.read(32)));
+          let delta      = float32_unpack(try!(bits.read(32)));
+          let value_bits = try!(bits.read(4)) + 1;
+          let sequence_p = try!(bits.read_bool());
+          let lookup_values =
+            if lookup_type == 1 {
+              lookup1_values(entries, dimensions)
+            } else {
+              entries * dimensions
+            };
+          let mut multiplicands: Vec<u32> = Vec::with_capacity(lookup_values);
+          for _ in 0..lookup_values {
+            multiplicands.push(try!(bits.read(value_bits)));
+          }
+          if multiplicands.is_empty() && entries > 0 {
+            return Err(AudioError::Format(
+              "File is not valid Vorbis (Codebook lookup table is empty)".to_string()
+            ));
+          }
+          let mut values: Vec<f32> = Vec::with_capacity(entries * dimensions);
+          for entry in 0..entries {
+            let mut last          = 0f32;
+            let mut index_divisor = 1;
+            for i in 0..dimensions {
+              let offset =
+                if lookup_type == 1 {
+                  (entry / index_divisor) % lookup_values
+                } else {
+                  entry * dimensions + i
+                };
+              let value = multiplicands[offset] as f32 * delta + minimum + last;
+              if sequence_p {
+                last = value;
+              }
+              values.push(value);
+              index_divisor = index_divisor.saturating_mul(lookup_values);
+            }
+          }
+          Some(values)
+        },
+        t @ _ =>
+          return Err(AudioError::Format(
+            format!("File is not valid Vorbis (Invalid codebook lookup type {})", t)
+          ))
+      };
+    Ok(Codebook {
+      dimensions: dimensions,
+      tree:       tree,
+      values:     values
+    })
+  }
+
+  /// Returns whether the codebook can be used to decode vectors.
+  #[inline]
+  pub fn has_values(&self) -> bool {
+    self.values.is_some()
+  }
+
+  /// Decodes the next codeword, returning its entry number.
+  pub fn decode_scalar(&self, bits: &mut BitReader) -> AudioResult<usize> {
+    let mut node = 0usize;
+    loop {
+      let child =
+        match self.tree.get(node) {
+          Some(children) => children[try!(bits.read(1)) as usize],
+          None           => 0
+        };
+      if child < 0 {
+        return Ok((-child - 1) as usize);
+      }
+      if child == 0 {
+        return Err(AudioError::Format(
+          "File is not valid Vorbis (Undecodable codeword)".to_string()
+        ));
+      }
+      node = child as usize;
+    }
+  }
+
+  /// Decodes the next codeword, returning the vector of its entry.
+  pub fn decode_vector(&self, bits: &mut BitReader) -> AudioResult<&[f32]> {
+    let entry = try!(self.decode_scalar(bits));
+    match self.values {
+      Some(ref values) =>
+        Ok(&values[entry * self.dimensions .. (entry + 1) * self.dimensions]),
+      None =>
+        Err(AudioError::Format(
+          "File is not valid Vorbis (Codebook has no lookup table)".to_string()
+        ))
+    }
+  }
+}
+
+// Private functions
+
+/// Builds the decoding tree from the codeword lengths.
+///
+/// Each codeword is the lowest valued codeword of its length that is not
+/// already used or prefixed by a used codeword, taken in entry order.
+/// `available[len]` holds the next free codeword of each length, left
+/// aligned in 32 bits.
+fn build_tree(lengths: &[u8]) -> AudioResult<Vec<[i32; 2]>> {
+  let mut tree: Vec<[i32; 2]> = vec![[0, 0]];
+  let mut available: [u32; 33] = [0u32; 33];
+  let mut first = true;
+  for (entry, length) in lengths.iter().enumerate() {
+    let length = *length as usize;
+    if length == 0 {
+      continue;
+    }
+    let codeword =
+      if first {
+        first = false;
+        for i in 1 .. length + 1 {
+          available[i] = 1 << (32 - i);
+        }
+        0
+      }
+      else {
+        let mut z = length;
+        while z > 0 && available[z] == 0 {
+          z -= 1;
+        }
+        if z == 0 {
+          return Err(AudioError::Format(
+            "File is not valid Vorbis (Overspecified codebook)".to_string()
+          ));
+        }
+        let codeword = available[z];
+        available[z] = 0;
+        for y in (z + 1 .. length + 1).rev() {
+          available[y] = codeword + (1 << (32 - y));
+        }
+        codeword
+      };
+    // Insert the codeword, most significant bit first.
+    let mut node = 0usize;
+    for i in 0..length {
+      let bit = ((codeword >> (31 - i)) & 1) as usize;
+      if i + 1 == length {
+        tree[node][bit] = -(entry as i32) - 1;
+      }
+      else {
+        if tree[node][bit] == 0 {
+          tree.push([0, 0]);
+          tree[node][bit] = (tree.len() - 1) as i32;
+        }
+        node = tree[node][bit] as usize;
+      }
+    }
+  }
+  Ok(tree)
+}
+
+/// Unpacks the floating point format used by codebook lookup tables.
+pub fn float32_unpack(value: u32) -> f32 {
+  let mantissa = (value & 0x1FFFFF) as f64;
+  let exponent = ((value & 0x7FE00000) >> 21) as i32 - 788;
+  let unsigned = mantissa * (2f64).powi(exponent);
+  if value & 0x80000000 != 0 {
+    -unsigned as f32
+  } else {
+    unsigned as f32
+  }
+}
+
+/// Returns the number of values of a lookup table of type 1, which is the
+/// greatest integer whose power of `dimensions` is at most `entries`.
+fn lookup1_values(entries: usize, dimensions: usize) -> usize {
+  let mut values = (entries as f64).powf(1f64 / dimensions as f64).floor() as usize;
+  let pow = |base: usize|
+    (0..dimensions).fold(Some(1usize), |acc, _| acc.and_then(|a| a.checked_mul(base)));
+  while pow(values + 1).map_or(false, |p| p <= entries) {
+    values += 1;
+  }
+  while values > 0 && pow(values).map_or(true, |p| p > entries) {
+    values -= 1;
+  }
+  values
+}
+
+#[cfg(test)]
+mod huffman {
+  use super::*;
+  use super::build_tree;
+  use ogg::bits::BitReader;
+
+  fn book(lengths: &[u8]) -> Codebook {
+    Codebook {
+      dimensions: 1,
+      tree:       build_tree(lengths).unwrap(),
+      values:     None
+    }
+  }
+
+  #[test]
+  fn codeword_assignment() {
+    // Lengths from the example in the Vorbis specification, giving the
+    // codewords 00, 0100, 0101, 0110, 0111, 10, 110 and 111.
+    let codebook = book(&[2, 4, 4, 4, 4, 2, 3, 3]);
+    // The codewords of entries 1, 0, 5 and 7, packed least significant bit
+    // first.
+    let data = [0b0100_0010u8, 0b0000_0111];
+    let mut bits = BitReader::new(&data);
+    assert_eq!(1, codebook.decode_scalar(&mut bits).unwrap());
+    assert_eq!(0, codebook.decode_scalar(&mut bits).unwrap());
+    assert_eq!(5, codebook.decode_scalar(&mut bits).unwrap());
+    assert_eq!(7, codebook.decode_scalar(&mut bits).unwrap());
+  }
+
+  #[test]
+  fn overspecified() {
+    assert!(build_tree(&[1, 1, 1]).is_err());
+  }
+
+  #[test]
+  fn lookup1() {
+    assert_eq!(4,  lookup1_values(16, 2));
+    assert_eq!(4,  lookup1_values(17, 2));
+    assert_eq!(2,  lookup1_values(8, 3));
+    assert_eq!(81, lookup1_values(81, 1));
+  }
+
+  #[test]
+  fn unpack() {
+    assert_eq!(1f32,    float32_unpack((788 << 21) | 1));
+    assert_eq!(-0.5f32, float32_unpack(0x80000000 | (787 << 21) | 1));
+  }
+}
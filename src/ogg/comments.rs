@@ -0,0 +1,72 @@
+//! Vorbis Comments
+use byteorder::{ByteOrder, LittleEndian};
+use error::*;
+
+/// Reads a comment list following the packet signature, made up of a
+/// vendor string and user comments of the form `KEY=value`. Each string is
+/// preceded by its length. The vendor string is not kept, and comments
+/// without a separator are ignored.
+pub fn read(data: &[u8]) -> AudioResult<Vec<(String, String)>> {
+  let mut offset = 0usize;
+  let vendor_length = try!(read_length(data, &mut offset));
+  offset += vendor_length;
+  let count = try!(read_length(data, &mut offset));
+  let mut comments: Vec<(String, String)> = Vec::new();
+  for _ in 0..count {
+    let length  = try!(read_length(data, &mut offset));
+    let comment = String::from_utf8_lossy(&data[offset .. offset + length]).into_owned();
+    offset += length;
+    if let Some(separator) = comment.find('=') {
+      comments.push((comment[..separator].to_string(),
+                     comment[separator + 1 ..].to_string()));
+    }
+  }
+  Ok(comments)
+}
+
+/// Writes a comment list with the given vendor string, to follow the
+/// packet signature.
+pub fn write(vendor: &str, comments: &[(String, String)]) -> Vec<u8> {
+  let mut data: Vec<u8> = Vec::new();
+  write_string(&mut data, vendor.as_bytes());
+  let mut length = [0u8; 4];
+  LittleEndian::write_u32(&mut length, comments.len() as u32);
+  data.extend_from_slice(&length);
+  for &(ref key, ref value) in comments.iter() {
+    write_string(&mut data, format!("{}={}", key, value).as_bytes());
+  }
+  data
+}
+
+// Private functions
+
+/// Writes a string preceded by its length.
+#[inline]
+fn write_string(data: &mut Vec<u8>, string: &[u8]) {
+  let mut length = [0u8; 4];
+  LittleEndian::write_u32(&mut length, string.len() as u32);
+  data.extend_from_slice(&length);
+  data.extend_from_slice(string);
+}
+
+/// Reads a length, checking that the data it describes is within the
+/// comment list.
+#[inline]
+fn read_length(data: &[u8], offset: &mut usize) -> AudioResult<usize> {
+  if *offset + 4 > data.len() {
+    return Err(invalid());
+  }
+  let length = LittleEndian::read_u32(&data[*offset .. *offset + 4]) as usize;
+  *offset += 4;
+  if length > data.len() - *offset {
+    return Err(invalid());
+  }
+  Ok(length)
+}
+
+#[inline]
+fn invalid() -> AudioError {
+  AudioError::Format(
+    "File is not valid Ogg (Comment list extends past the end of the packet)".to_string()
+  )
+}
@@ -0,0 +1,201 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use buffer::*;
+use codecs::Codec;
+use error::*;
+use metadata::Metadata;
+use ogg::page::{Packet, PacketReader, seek_page};
+use ogg::setup::{Identification, Setup, read_comments};
+use ogg::vorbis::VorbisDecoder;
+use sample::*;
+use traits::Container;
+
+/// Struct containing all necessary information for decoding an Ogg Vorbis
+/// stream to an `AudioBuffer`.
+pub struct OggContainer {
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl OggContainer {
+  /// Decodes the stream from a frame to its end. The page before the frame
+  /// is found by bisecting the granule positions of the pages, and decoding
+  /// resumes there, so the frames before it are not decoded.
+  pub fn open_from<R: Read + Seek>(reader: &mut R, frame: u64) -> AudioResult<OggContainer> {
+    let mut packets = PacketReader::new();
+    let mut headers: Vec<Vec<u8>> = Vec::with_capacity(3);
+    while headers.len() < 3 {
+      match try!(packets.next(reader)) {
+        Some(packet) => headers.push(packet.data),
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid Ogg Vorbis (Missing header packets)".to_string()
+          ))
+      }
+    }
+    let ident    = try!(Identification::read(&headers[0]));
+    let comments = try!(read_comments(&headers[1]));
+    let setup    = try!(Setup::read(&headers[2], &ident));
+    let channels = ident.channels as usize;
+    let sample_rate = ident.sample_rate;
+    let serial   = packets.serial().unwrap_or(0);
+
+    // The granule position of a packet is the number of frames decoded
+    // through the end of the packet, which places the decoded frames in the
+    // stream. The first granule position gives the number of frames to
+    // discard at the start of the stream, and the last gives the number of
+    // frames to keep at the end. A stream whose first granule position is on
+    // its last page starts at zero, so only the end is discarded.
+    let mut decoder = VorbisDecoder::new(ident, setup);
+    let mut stream  = Stream::new(channels);
+    while stream.base.is_none() {
+      match try!(packets.next(reader)) {
+        Some(packet) => try!(stream.decode(&mut decoder, &packet)),
+        None         => break
+      }
+    }
+    let target = stream.base.unwrap_or(0).max(0) + frame as i64;
+
+    // A packet only returns samples once the previous packet has been
+    // decoded, so decoding resumes at the page before the last page ending
+    // before the frame.
+    if target > stream.decoded_end() {
+      let position = try!(reader.seek(SeekFrom::Current(0)));
+      let end      = try!(reader.seek(SeekFrom::End(0)));
+      let previous =
+        match try!(seek_page(reader, serial, target, position, end)) {
+          Some((offset, granule_position)) =>
+            try!(seek_page(reader, serial, granule_position - 1, position, offset)),
+          None => None
+        };
+      match previous {
+        Some((offset, _)) => {
+          try!(reader.seek(SeekFrom::Start(offset)));
+          packets = PacketReader::resume(serial);
+          decoder.reset();
+          stream = Stream::new(channels);
+        },
+        None => {
+          try!(reader.seek(SeekFrom::Start(position)));
+        }
+      }
+    }
+    while let Some(packet) = try!(packets.next(reader)) {
+      try!(stream.decode(&mut decoder, &packet));
+    }
+
+    let base  = stream.base.unwrap_or(0);
+    let total = stream.samples.len() / channels;
+    let first = ((target - base).max(0) as usize).min(total);
+    let last  =
+      match stream.end {
+        Some(end) => ((end - base).max(0) as usize).min(total),
+        None      => total
+      };
+    let samples: Vec<Sample> =
+      if first < last {
+        let mut samples = stream.samples;
+        samples.truncate(last * channels);
+        samples.drain(.. first * channels);
+        reorder_channels(samples, channels)
+      } else {
+        Vec::new()
+      };
+
+    let mut metadata = Metadata::default();
+    metadata.info = comments;
+    Ok(OggContainer {
+      sample_rate:  sample_rate,
+      channels:     channels as u32,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:      samples,
+      metadata:     metadata
+    })
+  }
+}
+
+impl Container for OggContainer {
+  #[inline]
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<OggContainer> {
+    OggContainer::open_from(reader, 0)
+  }
+  fn create<W: Write>(_: &mut W, _: &AudioBuffer, _: Codec) -> AudioResult<()> {
+    Err(AudioError::Unsupported(
+      "Encoding Ogg Vorbis is not supported".to_string()
+    ))
+  }
+}
+
+// Private functions
+
+/// Samples decoded from a run of packets, placed in the stream by the
+/// granule positions of the packets.
+struct Stream {
+  channels: usize,
+  samples:  Vec<Sample>,
+  /// Granule position of the first decoded frame
+  base:     Option<i64>,
+  /// Last granule position read
+  end:      Option<i64>
+}
+
+impl Stream {
+  fn new(channels: usize) -> Stream {
+    Stream {
+      channels: channels,
+      samples:  Vec::new(),
+      base:     None,
+      end:      None
+    }
+  }
+
+  /// Decodes a packet, placing the decoded frames once a granule position
+  /// is read.
+  fn decode(&mut self, decoder: &mut VorbisDecoder, packet: &Packet) -> AudioResult<()> {
+    try!(decoder.decode(&packet.data, &mut self.samples));
+    if let Some(granule_position) = packet.granule_position {
+      if granule_position >= 0 {
+        if self.base.is_none() {
+          let base = granule_position - (self.samples.len() / self.channels) as i64;
+          self.base = Some(if packet.end_of_stream { base.max(0) } else { base });
+        }
+        self.end = Some(granule_position);
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the granule position following the decoded frames.
+  fn decoded_end(&self) -> i64 {
+    self.base.unwrap_or(0) + (self.samples.len() / self.channels) as i64
+  }
+}
+
+/// Reorders the channels of interleaved samples from the Vorbis channel
+/// order into the order used by WAVE files. Vorbis places the center channel
+/// between the front left and right channels, and the LFE channel last.
+fn reorder_channels(samples: Vec<Sample>, channels: usize) -> Vec<Sample> {
+  let order: &[usize] =
+    match channels {
+      3 => &[0, 2, 1],
+      5 => &[0, 2, 1, 3, 4],
+      6 => &[0, 2, 1, 5, 3, 4],
+      7 => &[0, 2, 1, 6, 5, 3, 4],
+      8 => &[0, 2, 1, 7, 5, 6, 3, 4],
+      _ => return samples
+    };
+  let mut reordered: Vec<Sample> = Vec::with_capacity(samples.len());
+  for frame in samples.chunks(channels) {
+    for i in order.iter() {
+      reordered.push(frame[*i]);
+    }
+  }
+  reordered
+}
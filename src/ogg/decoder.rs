@@ -0,0 +1,52 @@
+use std::io::{Read, Seek};
+use buffer::AudioBuffer;
+use error::AudioResult;
+use ogg::container::OggContainer;
+use traits::{AudioDecoder, Container};
+
+/// Decodes audio in Ogg Vorbis format from the
+/// provided reader.
+pub struct Decoder<'r, R: 'r> where R: Read + Seek {
+  reader: &'r mut R,
+}
+
+impl<'r, R> Decoder<'r, R> where R: Read + Seek {
+  /// Create a new Ogg Vorbis format `Decoder` using
+  /// the provided reader.
+  #[inline]
+  pub fn new(reader: &'r mut R) -> Decoder<R> {
+    Decoder {
+      reader: reader
+    }
+  }
+}
+
+impl<'r, R> Decoder<'r, R> where R: Read + Seek {
+  /// Creates an `AudioBuffer` starting at a frame of the stream. Decoding
+  /// resumes at the page before the frame, found by its granule position,
+  /// rather than decoding all of the frames before it.
+  pub fn decode_from(self, frame: u64) -> AudioResult<AudioBuffer> {
+    let container = try!(OggContainer::open_from(self.reader, frame));
+    Ok(AudioBuffer {
+      sample_rate:  container.sample_rate,
+      channels:     container.channels,
+      samples:      container.samples,
+      metadata:     container.metadata
+    })
+  }
+}
+
+impl<'r, R> AudioDecoder for Decoder<'r, R> where R: Read + Seek {
+  /// Creates an `AudioBuffer` from the included reader via
+  /// an `OggContainer`.
+  #[inline]
+  fn decode(mut self) -> AudioResult<AudioBuffer> {
+    let container = try!(OggContainer::open(self.reader));
+    Ok(AudioBuffer {
+      sample_rate:  container.sample_rate,
+      channels:     container.channels,
+      samples:      container.samples,
+      metadata:     container.metadata
+    })
+  }
+}
@@ -0,0 +1,122 @@
+//! Inverse Modified Discrete Cosine Transform
+use std::f64::consts::PI;
+
+/// Computes the inverse MDCT of a block of `n` samples from `n/2`
+/// coefficients, as defined by the Vorbis specification:
+///
+/// `y[i] = sum(X[k] * cos(2 * PI / n * (i + 1/2 + n/4) * (k + 1/2)))`
+///
+/// The transform is calculated using a DCT-IV of `n/2` coefficients, which
+/// is computed with a complex FFT of `n/4` points.
+pub struct Imdct {
+  n:        usize,
+  twiddles: Vec<(f32, f32)>,
+  roots:    Vec<(f32, f32)>,
+  reversed: Vec<usize>
+}
+
+impl Imdct {
+  pub fn new(n: usize) -> Imdct {
+    let m = n / 2;
+    let points = n / 4;
+    let twiddles = (0..points).map(|k| {
+      let angle = -PI * (k as f64 + 0.125) / m as f64;
+      (angle.cos() as f32, angle.sin() as f32)
+    }).collect();
+    let roots = (0..points / 2).map(|k| {
+      let angle = -2f64 * PI * k as f64 / points as f64;
+      (angle.cos() as f32, angle.sin() as f32)
+    }).collect();
+    let bits = points.trailing_zeros();
+    let reversed = (0..points).map(|i|
+      (0..bits).fold(0, |reversed, bit| (reversed << 1) | ((i >> bit) & 1))
+    ).collect();
+    Imdct {
+      n:        n,
+      twiddles: twiddles,
+      roots:    roots,
+      reversed: reversed
+    }
+  }
+
+  /// Transforms `n/2` coefficients into `n` samples.
+  pub fn inverse(&self, input: &[f32], output: &mut [f32]) {
+    let n = self.n;
+    let m = n / 2;
+    let points = n / 4;
+    debug_assert!(input.len() >= m && output.len() >= n);
+
+    // Pre-twiddle pairs of coefficients into complex values in bit reversed
+    // order.
+    let mut data: Vec<(f32, f32)> = vec![(0f32, 0f32); points];
+    for k in 0..points {
+      let (re, im) = (input[2 * k], input[m - 1 - 2 * k]);
+      let (c, s)   = self.twiddles[k];
+      data[self.reversed[k]] = (re * c - im * s, re * s + im * c);
+    }
+    fft(&mut data, &self.roots);
+
+    // Post-twiddle into the DCT-IV, then unfold it into the output.
+    let mut dct: Vec<f32> = vec![0f32; m];
+    for j in 0..points {
+      let (re, im) = data[j];
+      let (c, s)   = self.twiddles[j];
+      dct[2 * j]         =   re * c - im * s;
+      dct[m - 1 - 2 * j] = -(re * s + im * c);
+    }
+    let half = m / 2;
+    for i in 0..half {
+      output[i] = dct[i + half];
+    }
+    for i in half .. 3 * half {
+      output[i] = -dct[3 * half - 1 - i];
+    }
+    for i in 3 * half .. n {
+      output[i] = -dct[i - 3 * half];
+    }
+  }
+}
+
+/// Computes an in-place radix-2 FFT of data given in bit reversed order.
+fn fft(data: &mut [(f32, f32)], roots: &[(f32, f32)]) {
+  let points = data.len();
+  let mut size = 2;
+  while size <= points {
+    let half   = size / 2;
+    let stride = points / size;
+    for start in (0..points).step_by(size) {
+      for k in 0..half {
+        let (wr, wi) = roots[k * stride];
+        let (ar, ai) = data[start + k];
+        let (br, bi) = data[start + k + half];
+        let (tr, ti) = (br * wr - bi * wi, br * wi + bi * wr);
+        data[start + k]        = (ar + tr, ai + ti);
+        data[start + k + half] = (ar - tr, ai - ti);
+      }
+    }
+    size *= 2;
+  }
+}
+
+#[cfg(test)]
+mod transform {
+  use super::*;
+  use std::f64::consts::PI;
+
+  #[test]
+  fn matches_definition() {
+    for &n in [64usize, 256, 2048].iter() {
+      let input: Vec<f32> = (0..n / 2).map(|k| ((k * 7919) % 13) as f32 / 6.5 - 1.0).collect();
+      let mut output = vec![0f32; n];
+      Imdct::new(n).inverse(&input, &mut output);
+      for i in 0..n {
+        let expected: f64 = (0..n / 2).map(|k|
+          input[k] as f64 * (2f64 * PI / n as f64 * (i as f64 + 0.5 + n as f64 / 4f64)
+                                                  * (k as f64 + 0.5)).cos()
+        ).sum();
+        assert!((expected - output[i] as f64).abs() < 1e-3 * (n as f64).sqrt(),
+                "n = {}, i = {}: {} != {}", n, i, expected, output[i]);
+      }
+    }
+  }
+}
@@ -0,0 +1,201 @@
+//! The Ogg Vorbis Format
+//!
+//! Ogg files are made up of pages, which carry the packets of one or more
+//! logical bitstreams. Only the first logical bitstream of a file is
+//! decoded, and it must contain Vorbis I audio. The first three packets of
+//! a Vorbis stream are the identification, comment, and setup headers, and
+//! the remaining packets each hold a block of audio.
+//!
+//! The granule position of each page gives the number of frames decoded
+//! through the end of the page, which is used to trim the samples at the
+//! start and end of the stream. Decoding from a frame bisects the pages by
+//! their granule positions, and resumes at the page before the frame since
+//! each packet overlaps the previous one. Vorbis comments are stored as
+//! textual information in the metadata.
+//!
+//! Encoding Ogg Vorbis is not supported.
+//!
+//! References
+//! - [Ogg](https://www.xiph.org/ogg/doc/framing.html)
+//! - [Vorbis I](https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html)
+
+mod bits;
+mod codebook;
+pub mod comments;
+mod container;
+mod mdct;
+pub mod page;
+mod setup;
+mod vorbis;
+pub mod decoder;
+
+pub use ogg::decoder::Decoder as Decoder;
+
+/// Capture pattern starting each page.
+const OGGS: &'static [u8; 4] = b"OggS";
+
+/// Signature following the packet type of Vorbis header packets.
+const VORBIS: &'static [u8; 6] = b"vorbis";
+
+/// Page header flag for the first page of a logical bitstream.
+const BEGINNING_OF_STREAM: u8 = 0x02;
+
+/// Page header flag for the last page of a logical bitstream.
+const END_OF_STREAM: u8 = 0x04;
+
+#[cfg(test)]
+mod io {
+  use std::fs::File;
+  use std::io::{Cursor, Read};
+  use std::path::Path;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::error::AudioError;
+  use ::testing::{SINE_LEFT, SINE_RIGHT, sine_error};
+  use super::page::{read_packets, seek_page};
+  use super::setup::{Identification, Setup};
+  use super::vorbis::VorbisDecoder;
+
+  /// Serial number of the logical bitstream of the stereo test file.
+  const SERIAL: u32 = 4660;
+
+  #[test]
+  fn read_mono() {
+    let audio = audio::open(Path::new("tests/ogg/sine-mono.ogg")).unwrap();
+    assert_eq!(1,    audio.channels);
+    assert_eq!(8000, audio.sample_rate);
+    assert_eq!(3000, audio.samples.len());
+    assert!(sine_error(&audio, 0, SINE_LEFT, 0, 0) < 0.05);
+  }
+
+  #[test]
+  fn read_stereo() {
+    let audio = audio::open(Path::new("tests/ogg/sine-stereo.ogg")).unwrap();
+    assert_eq!(2,     audio.channels);
+    assert_eq!(22050, audio.sample_rate);
+    assert_eq!(5936,  audio.samples.len() / 2);
+    // The first granule position starts the stream 64 frames into the first
+    // decoded block.
+    assert!(sine_error(&audio, 0, SINE_LEFT,  64, 0) < 0.05);
+    assert!(sine_error(&audio, 1, SINE_RIGHT, 64, 0) < 0.05);
+    assert!(sine_error(&audio, 0, SINE_LEFT,  0,  0) > 0.1);
+  }
+
+  #[test]
+  fn read_vorbis_files() {
+    // Files from the libvorbis encoder, checked against the samples decoded
+    // by libvorbis. The mono and stereo files fit on a single page, so their
+    // first granule position trims the end of the stream.
+    for &(path, reference, channels, sample_rate) in [
+      ("tests/ogg/vorbis-mono.ogg",   "tests/ogg/vorbis-mono.wav",   1, 44100),
+      ("tests/ogg/vorbis-stereo.ogg", "tests/ogg/vorbis-stereo.wav", 2, 44100),
+      ("tests/ogg/vorbis-low.ogg",    "tests/ogg/vorbis-low.wav",    2, 8000),
+      ("tests/ogg/vorbis-high.ogg",   "tests/ogg/vorbis-high.wav",   2, 48000)
+    ].iter() {
+      let audio = audio::open(Path::new(path)).unwrap();
+      let expected = audio::open(Path::new(reference)).unwrap();
+      assert_eq!(channels,               audio.channels);
+      assert_eq!(sample_rate,            audio.sample_rate);
+      assert_eq!(expected.samples.len(), audio.samples.len());
+      for (sample, expected) in audio.samples.iter().zip(expected.samples.iter()) {
+        let sample = (sample * 32768f32).round().max(-32768f32).min(32767f32);
+        assert!((sample - expected * 32768f32).abs() <= 1f32);
+      }
+    }
+  }
+
+  #[test]
+  fn seek() {
+    let path = Path::new("tests/ogg/sine-stereo.ogg");
+    let audio = audio::open(path).unwrap();
+    // Seeking gives the same samples as decoding from the start, whether the
+    // frame is on the first pages or on a page found by bisection.
+    for &frame in [0usize, 1, 500, 1216, 2000, 3000, 4416, 5000].iter() {
+      let seeked = audio::open_from(path, frame as u64).unwrap();
+      assert_eq!(audio.sample_rate, seeked.sample_rate);
+      assert_eq!(&audio.samples[frame * 2 ..], &seeked.samples[..]);
+      assert!(sine_error(&seeked, 0, SINE_LEFT,  64 + frame, 0) < 0.05);
+      assert!(sine_error(&seeked, 1, SINE_RIGHT, 64 + frame, 0) < 0.05);
+    }
+    assert_eq!(2, audio::open_from(path, 5935).unwrap().samples.len());
+    assert_eq!(0, audio::open_from(path, 5936).unwrap().samples.len());
+    assert_eq!(0, audio::open_from(path, 100000).unwrap().samples.len());
+
+    // The last page ending before granule position 3000 ends at 2816, so
+    // decoding resumes at the page before it, ending at 1216.
+    let mut file = File::open(path).unwrap();
+    assert_eq!(Some((1836, 2816)), seek_page(&mut file, SERIAL, 3000, 686, 5676).unwrap());
+    assert_eq!(Some((1593, 1216)), seek_page(&mut file, SERIAL, 2815, 686, 1836).unwrap());
+    assert_eq!(None, seek_page(&mut file, SERIAL, 100, 686, 5676).unwrap());
+
+    // Files from the libvorbis encoder span several pages, with long and
+    // short blocks.
+    for path in ["tests/ogg/vorbis-low.ogg", "tests/ogg/vorbis-high.ogg"].iter() {
+      let path  = Path::new(path);
+      let audio = audio::open(path).unwrap();
+      let frames = audio.samples.len() / 2;
+      for &frame in [1usize, frames / 3, frames / 2, frames - 1].iter() {
+        let seeked = audio::open_from(path, frame as u64).unwrap();
+        assert_eq!(&audio.samples[frame * 2 ..], &seeked.samples[..]);
+      }
+    }
+  }
+
+  #[test]
+  fn corrupt_packet() {
+    let mut file = File::open("tests/ogg/sine-mono.ogg").unwrap();
+    let packets = read_packets(&mut file).unwrap();
+    let ident = Identification::read(&packets[0].data).unwrap();
+    let mut setup = Setup::read(&packets[2].data, &ident).unwrap();
+    // With three modes, the mode number takes two bits and may be invalid.
+    let mode = setup.modes[0];
+    setup.modes.push(mode);
+    let mut decoder = VorbisDecoder::new(ident, setup);
+    let mut samples: Vec<f32> = Vec::new();
+    assert_eq!(0, decoder.decode(&[], &mut samples).unwrap());
+    assert_eq!(0, decoder.decode(&[0x01], &mut samples).unwrap());
+    match decoder.decode(&[0x06], &mut samples) {
+      Err(AudioError::Format(_)) => {},
+      _ => panic!("Packet with an invalid mode was not rejected")
+    }
+    assert!(samples.is_empty());
+  }
+
+  #[test]
+  fn comments() {
+    let audio = audio::open(Path::new("tests/ogg/sine-mono.ogg")).unwrap();
+    assert_eq!(vec![
+      ("TITLE".to_string(),   "Sine".to_string()),
+      ("ARTIST".to_string(),  "Test".to_string()),
+      ("comment".to_string(), "440 Hz".to_string())
+    ], audio.metadata.info);
+  }
+
+  #[test]
+  fn corrupt_page() {
+    let mut bytes: Vec<u8> = Vec::new();
+    File::open("tests/ogg/sine-mono.ogg").unwrap().read_to_end(&mut bytes).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xFF;
+    match audio::load(&mut Cursor::new(bytes), AudioFormat::Ogg) {
+      Err(AudioError::Format(_)) => {},
+      _ => panic!("Page with an invalid checksum was not rejected")
+    }
+  }
+
+  #[test]
+  fn not_ogg() {
+    let mut bytes = Cursor::new(b"RIFF\x24\x00\x00\x00WAVEfmt ".to_vec());
+    assert!(audio::load(&mut bytes, AudioFormat::Ogg).is_err());
+  }
+
+  #[test]
+  fn write_unsupported() {
+    let audio = audio::open(Path::new("tests/ogg/sine-mono.ogg")).unwrap();
+    let mut bytes: Vec<u8> = Vec::new();
+    match audio::write(&mut bytes, &audio, AudioFormat::Ogg) {
+      Err(AudioError::Unsupported(_)) => {},
+      _ => panic!("Writing Ogg Vorbis should be unsupported")
+    }
+  }
+}
@@ -0,0 +1,387 @@
+//! Ogg Pages and Packets
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use byteorder::{ByteOrder, LittleEndian};
+use error::*;
+use ogg::{OGGS, BEGINNING_OF_STREAM, END_OF_STREAM};
+
+/// Size of the page header before the segment table.
+const PAGE_HEADER_SIZE: usize = 27;
+
+/// Size of the data of a page written by `PageWriter`, at which the page is
+/// written out.
+const PAGE_DATA_SIZE: usize = 4096;
+
+/// Number of bytes read at a time while searching for a page.
+const SEARCH_SIZE: usize = 4096;
+
+/// Page header flag for a page starting with the rest of a packet.
+const CONTINUED_PACKET: u8 = 0x01;
+
+/// A packet of a logical bitstream.
+///
+/// Packets may span several pages. The granule position of a page is given
+/// to the last packet that ends on the page.
+#[derive(Debug, Clone)]
+pub struct Packet {
+  pub data:             Vec<u8>,
+  pub granule_position: Option<i64>,
+  /// Whether the packet is the last packet of the logical bitstream
+  pub end_of_stream:    bool
+}
+
+/// An Ogg page, which holds the segments of one or more packets.
+struct Page {
+  header_type:      u8,
+  granule_position: i64,
+  serial:           u32,
+  segments:         Vec<u8>,
+  data:             Vec<u8>
+}
+
+impl Page {
+  /// Reads the next page, returning `None` at the end of the stream. A page
+  /// cut off by the end of the stream is ignored.
+  fn read<R: Read>(reader: &mut R, crc_table: &[u32; 256]) -> AudioResult<Option<Page>> {
+    let mut header: [u8; PAGE_HEADER_SIZE] = [0u8; PAGE_HEADER_SIZE];
+    if !try!(read_all(reader, &mut header)) {
+      return Ok(None);
+    }
+    if &header[0..4] != OGGS {
+      return Err(AudioError::Format(
+        "Not valid Ogg (Missing page capture pattern)".to_string()
+      ));
+    }
+    if header[4] != 0 {
+      return Err(AudioError::Unsupported(
+        format!("Unsupported Ogg stream structure version {}", header[4])
+      ));
+    }
+    let mut segments: Vec<u8> = vec![0u8; header[26] as usize];
+    if !try!(read_all(reader, &mut segments)) {
+      return Ok(None);
+    }
+    let data_size = segments.iter().fold(0usize, |sum, size| sum + *size as usize);
+    let mut data: Vec<u8> = vec![0u8; data_size];
+    if !try!(read_all(reader, &mut data)) {
+      return Ok(None);
+    }
+    // The checksum is calculated with the checksum field set to zero.
+    let checksum = LittleEndian::read_u32(&header[22..26]);
+    for byte in header[22..26].iter_mut() {
+      *byte = 0;
+    }
+    let mut crc = crc32(0, &header, crc_table);
+    crc = crc32(crc, &segments, crc_table);
+    crc = crc32(crc, &data, crc_table);
+    if crc != checksum {
+      return Err(AudioError::Format(
+        "File is not valid Ogg (Page checksum does not match)".to_string()
+      ));
+    }
+    Ok(Some(Page {
+      header_type:      header[5],
+      granule_position: LittleEndian::read_i64(&header[6..14]),
+      serial:           LittleEndian::read_u32(&header[14..18]),
+      segments:         segments,
+      data:             data
+    }))
+  }
+
+  /// Returns the number of bytes taken by the page.
+  #[inline]
+  fn size(&self) -> u64 {
+    (PAGE_HEADER_SIZE + self.segments.len() + self.data.len()) as u64
+  }
+}
+
+/// Reads the packets of the first logical bitstream, one page at a time.
+/// Pages of other multiplexed or chained bitstreams are skipped.
+pub struct PacketReader {
+  serial:     Option<u32>,
+  /// Segments of a packet continuing on the next page
+  partial:    Vec<u8>,
+  /// Packets read from the last page that have not been returned
+  packets:    VecDeque<Packet>,
+  /// Whether the packets ending on the next page are dropped
+  skip_page:  bool,
+  ended:      bool,
+  crc_table:  [u32; 256]
+}
+
+impl PacketReader {
+  /// Creates a `PacketReader` for a stream starting at the first page.
+  pub fn new() -> PacketReader {
+    PacketReader {
+      serial:     None,
+      partial:    Vec::new(),
+      packets:    VecDeque::new(),
+      skip_page:  false,
+      ended:      false,
+      crc_table:  crc_table()
+    }
+  }
+
+  /// Creates a `PacketReader` for a logical bitstream that resumes at the
+  /// start of a page. Packets ending on that page are dropped, since they may
+  /// have begun on an earlier page.
+  pub fn resume(serial: u32) -> PacketReader {
+    PacketReader {
+      serial:     Some(serial),
+      skip_page:  true,
+      .. PacketReader::new()
+    }
+  }
+
+  /// Returns the serial number of the logical bitstream, once its first page
+  /// has been read.
+  #[inline]
+  pub fn serial(&self) -> Option<u32> {
+    self.serial
+  }
+
+  /// Returns the next packet, reading pages as needed, or `None` at the end
+  /// of the stream.
+  pub fn next<R: Read>(&mut self, reader: &mut R) -> AudioResult<Option<Packet>> {
+    while self.packets.is_empty() && !self.ended {
+      let page =
+        match try!(Page::read(reader, &self.crc_table)) {
+          Some(page) => page,
+          None       => break
+        };
+      match self.serial {
+        None if page.header_type & BEGINNING_OF_STREAM != 0 =>
+          self.serial = Some(page.serial),
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid Ogg (Missing beginning of stream page)".to_string()
+          )),
+        Some(s) if s != page.serial => continue,
+        Some(_) => {}
+      }
+      // Each packet is made up of segments ending with a segment shorter
+      // than 255 bytes.
+      let mut offset = 0usize;
+      for size in page.segments.iter() {
+        let size = *size as usize;
+        self.partial.extend_from_slice(&page.data[offset .. offset + size]);
+        offset += size;
+        if size < 255 {
+          self.packets.push_back(Packet {
+            data:             ::std::mem::replace(&mut self.partial, Vec::new()),
+            granule_position: None,
+            end_of_stream:    false
+          });
+        }
+      }
+      self.ended = page.header_type & END_OF_STREAM != 0;
+      if let Some(packet) = self.packets.back_mut() {
+        if page.granule_position >= 0 {
+          packet.granule_position = Some(page.granule_position);
+        }
+        packet.end_of_stream = self.ended;
+      }
+      if self.skip_page {
+        self.packets.clear();
+        self.skip_page = false;
+      }
+    }
+    Ok(self.packets.pop_front())
+  }
+}
+
+/// Reads all packets of the first logical bitstream. Pages of other
+/// multiplexed or chained bitstreams are skipped.
+pub fn read_packets<R: Read>(reader: &mut R) -> AudioResult<Vec<Packet>> {
+  let mut packet_reader = PacketReader::new();
+  let mut packets: Vec<Packet> = Vec::new();
+  while let Some(packet) = try!(packet_reader.next(reader)) {
+    packets.push(packet);
+  }
+  Ok(packets)
+}
+
+/// Finds the last page of a logical bitstream with a granule position no
+/// greater than the given one, among the pages starting between two byte
+/// offsets. Returns the offset and granule position of the page.
+///
+/// Granule positions increase through the stream, so the pages are bisected
+/// rather than read in order.
+pub fn seek_page<R: Read + Seek>(reader: &mut R, serial: u32, granule_position: i64,
+                                 begin: u64, end: u64) -> AudioResult<Option<(u64, i64)>> {
+  let crc_table = crc_table();
+  let mut found: Option<(u64, i64)> = None;
+  let (mut low, mut high) = (begin, end);
+  while low < high {
+    let middle = low + (high - low) / 2;
+    match try!(find_page(reader, serial, middle, high, &crc_table)) {
+      Some((offset, next, position)) if position <= granule_position => {
+        found = Some((offset, position));
+        low   = next;
+      },
+      _ => high = middle
+    }
+  }
+  Ok(found)
+}
+
+/// Writes the packets of a logical bitstream into pages.
+pub struct PageWriter {
+  serial:           u32,
+  sequence:         u32,
+  segments:         Vec<u8>,
+  data:             Vec<u8>,
+  granule_position: i64,
+  /// Whether the page being filled starts with the rest of a packet
+  continued:        bool,
+  /// Whether a packet ends on the page being filled
+  complete:         bool,
+  crc_table:        [u32; 256]
+}
+
+impl PageWriter {
+  pub fn new(serial: u32) -> PageWriter {
+    PageWriter {
+      serial:           serial,
+      sequence:         0,
+      segments:         Vec::new(),
+      data:             Vec::new(),
+      granule_position: -1,
+      continued:        false,
+      complete:         false,
+      crc_table:        crc_table()
+    }
+  }
+
+  /// Adds a packet ending at the given granule position. The page is
+  /// written out once it is full, after the packet when `flush` is set, and
+  /// at the end of the stream.
+  pub fn write_packet<W: Write>(&mut self, writer: &mut W, packet: &[u8],
+                                granule_position: i64, flush: bool,
+                                end_of_stream: bool) -> AudioResult<()> {
+    // A packet is made up of 255 byte segments and a shorter last segment.
+    let count = packet.len() / 255 + 1;
+    for i in 0..count {
+      if self.segments.len() == 255 {
+        try!(self.write_page(writer, false));
+        self.continued = i > 0;
+      }
+      let size = if i + 1 < count { 255 } else { packet.len() % 255 };
+      self.segments.push(size as u8);
+      self.data.extend_from_slice(&packet[i * 255 .. i * 255 + size]);
+    }
+    self.granule_position = granule_position;
+    self.complete = true;
+    if flush || end_of_stream || self.data.len() >= PAGE_DATA_SIZE {
+      try!(self.write_page(writer, end_of_stream));
+    }
+    Ok(())
+  }
+
+  /// Writes out the page being filled.
+  fn write_page<W: Write>(&mut self, writer: &mut W, end_of_stream: bool) -> AudioResult<()> {
+    let mut header: [u8; PAGE_HEADER_SIZE] = [0u8; PAGE_HEADER_SIZE];
+    header[0..4].copy_from_slice(OGGS);
+    header[5] =
+      if self.continued { CONTINUED_PACKET } else { 0 } |
+      if self.sequence == 0 { BEGINNING_OF_STREAM } else { 0 } |
+      if end_of_stream { END_OF_STREAM } else { 0 };
+    // Pages on which no packet ends have no granule position.
+    LittleEndian::write_i64(&mut header[6..14],
+                            if self.complete { self.granule_position } else { -1 });
+    LittleEndian::write_u32(&mut header[14..18], self.serial);
+    LittleEndian::write_u32(&mut header[18..22], self.sequence);
+    header[26] = self.segments.len() as u8;
+    let mut crc = crc32(0, &header, &self.crc_table);
+    crc = crc32(crc, &self.segments, &self.crc_table);
+    crc = crc32(crc, &self.data, &self.crc_table);
+    LittleEndian::write_u32(&mut header[22..26], crc);
+    try!(writer.write_all(&header));
+    try!(writer.write_all(&self.segments));
+    try!(writer.write_all(&self.data));
+    self.segments.clear();
+    self.data.clear();
+    self.sequence += 1;
+    self.continued = false;
+    self.complete  = false;
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Fills the buffer from the reader, returning false if the reader ends
+/// before the buffer is filled.
+fn read_all<R: Read>(reader: &mut R, buffer: &mut [u8]) -> AudioResult<bool> {
+  match reader.read_exact(buffer) {
+    Ok(()) => Ok(true),
+    Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+    Err(e) => Err(AudioError::Io(e))
+  }
+}
+
+/// Finds the first page of a logical bitstream with a granule position that
+/// starts at or after an offset and before a limit. Returns the offset of the
+/// page, the offset following it, and its granule position.
+fn find_page<R: Read + Seek>(reader: &mut R, serial: u32, offset: u64, limit: u64,
+                             crc_table: &[u32; 256]) -> AudioResult<Option<(u64, u64, i64)>> {
+  let mut position = offset;
+  let mut buffer: Vec<u8> = Vec::with_capacity(SEARCH_SIZE);
+  while position < limit {
+    try!(reader.seek(SeekFrom::Start(position)));
+    buffer.clear();
+    try!(reader.take(SEARCH_SIZE as u64).read_to_end(&mut buffer));
+    if buffer.len() < OGGS.len() {
+      return Ok(None);
+    }
+    // A capture pattern is only taken as the start of a page if a page with
+    // a matching checksum follows.
+    match buffer.windows(OGGS.len()).position(|bytes| bytes == OGGS) {
+      Some(index) => {
+        let start = position + index as u64;
+        if start >= limit {
+          return Ok(None);
+        }
+        try!(reader.seek(SeekFrom::Start(start)));
+        match Page::read(reader, crc_table) {
+          Ok(Some(page)) => {
+            if page.serial == serial && page.granule_position >= 0 {
+              return Ok(Some((start, start + page.size(), page.granule_position)));
+            }
+            position = start + page.size();
+          },
+          _ => position = start + 1
+        }
+      },
+      None => position += (buffer.len() - OGGS.len() + 1) as u64
+    }
+  }
+  Ok(None)
+}
+
+/// Returns the lookup table of the CRC-32 used by Ogg pages, which uses the
+/// polynomial 0x04C11DB7 without reflection.
+fn crc_table() -> [u32; 256] {
+  let mut table: [u32; 256] = [0u32; 256];
+  for i in 0..256 {
+    let mut crc = (i as u32) << 24;
+    for _ in 0..8 {
+      crc =
+        if crc & 0x80000000 != 0 {
+          (crc << 1) ^ 0x04C11DB7
+        } else {
+          crc << 1
+        };
+    }
+    table[i] = crc;
+  }
+  table
+}
+
+/// Updates a page checksum with the given bytes.
+#[inline]
+fn crc32(crc: u32, bytes: &[u8], table: &[u32; 256]) -> u32 {
+  bytes.iter().fold(crc, |crc, byte|
+    (crc << 8) ^ table[((crc >> 24) as u8 ^ *byte) as usize]
+  )
+}
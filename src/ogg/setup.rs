@@ -0,0 +1,457 @@
+//! Vorbis Headers
+use byteorder::{ByteOrder, LittleEndian};
+use error::*;
+use ogg::VORBIS;
+use ogg::bits::{BitReader, ilog};
+use ogg::codebook::Codebook;
+
+/// Vorbis header packet types.
+const IDENTIFICATION_HEADER: u8 = 1;
+const COMMENT_HEADER:        u8 = 3;
+const SETUP_HEADER:          u8 = 5;
+
+/// The Vorbis Identification Header.
+///
+/// This header is the first packet of the stream, and describes the number
+/// of channels, sample rate, and the two block sizes used by the stream.
+#[derive(Debug, Clone, Copy)]
+pub struct Identification {
+  pub channels:    u32,
+  pub sample_rate: u32,
+  pub blocksizes:  [usize; 2]
+}
+
+impl Identification {
+  pub fn read(packet: &[u8]) -> AudioResult<Identification> {
+    try!(check_header(packet, IDENTIFICATION_HEADER));
+    if packet.len() < 30 {
+      return Err(AudioError::Format(
+        "File is not valid Vorbis (Identification header is too small)".to_string()
+      ));
+    }
+    let version     = LittleEndian::read_u32(&packet[7..11]);
+    let channels    = packet[11] as u32;
+    let sample_rate = LittleEndian::read_u32(&packet[12..16]);
+    let blocksizes  = [1usize << (packet[28] & 0x0F), 1usize << (packet[28] >> 4)];
+    if version != 0 {
+      return Err(AudioError::Unsupported(
+        format!("Unsupported Vorbis version {}", version)
+      ));
+    }
+    if channels == 0 || sample_rate == 0 {
+      return Err(AudioError::Format(
+        "File is not valid Vorbis \
+        (Invalid number of channels or sample rate)".to_string()
+      ));
+    }
+    if blocksizes[0] < 64 || blocksizes[1] > 8192 || blocksizes[0] > blocksizes[1]
+    || packet[29] & 1 == 0 {
+      return Err(AudioError::Format(
+        "File is not valid Vorbis (Invalid identification header)".to_string()
+      ));
+    }
+    Ok(Identification {
+      channels:    channels,
+      sample_rate: sample_rate,
+      blocksizes:  blocksizes
+    })
+  }
+}
+
+/// Reads the user comments of the Vorbis Comment Header as key and value
+/// pairs. The vendor string is not kept.
+pub fn read_comments(packet: &[u8]) -> AudioResult<Vec<(String, String)>> {
+  try!(check_header(packet, COMMENT_HEADER));
+  ::ogg::comments::read(&packet[7..])
+}
+
+/// The floor type 0 configuration, which describes the spectral envelope
+/// using line spectral pairs.
+#[derive(Debug)]
+pub struct Floor0 {
+  pub order:            usize,
+  pub rate:             u32,
+  pub bark_map_size:    u32,
+  pub amplitude_bits:   u32,
+  pub amplitude_offset: u32,
+  pub books:            Vec<usize>
+}
+
+/// The floor type 1 configuration, which describes the spectral envelope as
+/// a piecewise linear curve.
+#[derive(Debug)]
+pub struct Floor1 {
+  pub partition_classes: Vec<usize>,
+  pub class_dimensions:  Vec<usize>,
+  pub class_subclasses:  Vec<u32>,
+  pub class_masterbooks: Vec<usize>,
+  pub subclass_books:    Vec<Vec<Option<usize>>>,
+  pub multiplier:        u32,
+  /// X coordinates of the curve points in the order they are decoded
+  pub x_list:            Vec<u32>,
+  /// Indices of the X list sorted by coordinate
+  pub sorted:            Vec<usize>,
+  /// Indices of the closest preceding points below and above each point
+  pub neighbors:         Vec<(usize, usize)>
+}
+
+#[derive(Debug)]
+pub enum Floor {
+  Zero(Floor0),
+  One(Floor1)
+}
+
+/// A residue configuration, which describes how the fine spectral structure
+/// is coded in partitions classified by the classbook.
+#[derive(Debug)]
+pub struct Residue {
+  pub residue_type:    u16,
+  pub begin:           usize,
+  pub end:             usize,
+  pub partition_size:  usize,
+  pub classifications: usize,
+  pub classbook:       usize,
+  /// Codebooks of each classification for each of the eight passes
+  pub books:           Vec<[Option<usize>; 8]>
+}
+
+/// A mapping, which assigns a floor and residue to each channel and
+/// describes the channel coupling.
+#[derive(Debug)]
+pub struct Mapping {
+  /// Floor and residue numbers of each submap
+  pub submaps:  Vec<(usize, usize)>,
+  /// Magnitude and angle channels of each coupling step
+  pub coupling: Vec<(usize, usize)>,
+  /// Submap number of each channel
+  pub mux:      Vec<usize>
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mode {
+  pub blockflag: bool,
+  pub mapping:   usize
+}
+
+/// The Vorbis Setup Header.
+///
+/// This header holds the codebooks and the configurations needed to decode
+/// audio packets.
+#[derive(Debug)]
+pub struct Setup {
+  pub codebooks: Vec<Codebook>,
+  pub floors:    Vec<Floor>,
+  pub residues:  Vec<Residue>,
+  pub mappings:  Vec<Mapping>,
+  pub modes:     Vec<Mode>
+}
+
+impl Setup {
+  pub fn read(packet: &[u8], ident: &Identification) -> AudioResult<Setup> {
+    try!(check_header(packet, SETUP_HEADER));
+    let mut bits = BitReader::new(&packet[7..]);
+    let bits = &mut bits;
+
+    let codebook_count = try!(bits.read(8)) as usize + 1;
+    let mut codebooks: Vec<Codebook> = Vec::with_capacity(codebook_count);
+    for _ in 0..codebook_count {
+      codebooks.push(try!(Codebook::read(bits)));
+    }
+    // Time domain transforms are placeholders and must be zero.
+    let time_count = try!(bits.read(6)) + 1;
+    for _ in 0..time_count {
+      if try!(bits.read(16)) != 0 {
+        return Err(invalid("time domain transform"));
+      }
+    }
+    let floor_count = try!(bits.read(6)) as usize + 1;
+    let mut floors: Vec<Floor> = Vec::with_capacity(floor_count);
+    for _ in 0..floor_count {
+      floors.push(
+        match try!(bits.read(16)) {
+          0 => Floor::Zero(try!(read_floor0(bits, &codebooks))),
+          1 => Floor::One(try!(read_floor1(bits, &codebooks))),
+          _ => return Err(invalid("floor type"))
+        }
+      );
+    }
+    let residue_count = try!(bits.read(6)) as usize + 1;
+    let mut residues: Vec<Residue> = Vec::with_capacity(residue_count);
+    for _ in 0..residue_count {
+      residues.push(try!(read_residue(bits, &codebooks)));
+    }
+    let mapping_count = try!(bits.read(6)) as usize + 1;
+    let mut mappings: Vec<Mapping> = Vec::with_capacity(mapping_count);
+    for _ in 0..mapping_count {
+      mappings.push(try!(read_mapping(bits, ident, floor_count, residue_count)));
+    }
+    let mode_count = try!(bits.read(6)) as usize + 1;
+    let mut modes: Vec<Mode> = Vec::with_capacity(mode_count);
+    for _ in 0..mode_count {
+      let blockflag      = try!(bits.read_bool());
+      let window_type    = try!(bits.read(16));
+      let transform_type = try!(bits.read(16));
+      let mapping        = try!(bits.read(8)) as usize;
+      if window_type != 0 || transform_type != 0 || mapping >= mapping_count {
+        return Err(invalid("mode"));
+      }
+      modes.push(Mode {
+        blockflag: blockflag,
+        mapping:   mapping
+      });
+    }
+    if !try!(bits.read_bool()) {
+      return Err(invalid("setup header framing bit"));
+    }
+    Ok(Setup {
+      codebooks: codebooks,
+      floors:    floors,
+      residues:  residues,
+      mappings:  mappings,
+      modes:     modes
+    })
+  }
+}
+
+// Private functions
+
+/// Checks the packet type and the `vorbis` signature of a header packet.
+fn check_header(packet: &[u8], packet_type: u8) -> AudioResult<()> {
+  if packet.len() < 7 || packet[0] != packet_type || &packet[1..7] != VORBIS {
+    return Err(AudioError::Format(
+      "File is not valid Vorbis (Missing header packet)".to_string()
+    ));
+  }
+  Ok(())
+}
+
+#[inline]
+fn invalid(field: &str) -> AudioError {
+  AudioError::Format(
+    format!("File is not valid Vorbis (Invalid {} in setup header)", field)
+  )
+}
+
+/// Reads a codebook number, which must refer to a codebook of the header.
+#[inline]
+fn read_book(bits: &mut BitReader, codebooks: &[Codebook], vector: bool) -> AudioResult<usize> {
+  let book = try!(bits.read(8)) as usize;
+  match codebooks.get(book) {
+    Some(codebook) if !vector || codebook.has_values() => Ok(book),
+    _ => Err(invalid("codebook number"))
+  }
+}
+
+fn read_floor0(bits: &mut BitReader, codebooks: &[Codebook]) -> AudioResult<Floor0> {
+  let order            = try!(bits.read(8)) as usize;
+  let rate             = try!(bits.read(16));
+  let bark_map_size    = try!(bits.read(16));
+  let amplitude_bits   = try!(bits.read(6));
+  let amplitude_offset = try!(bits.read(8));
+  let book_count       = try!(bits.read(4)) + 1;
+  let mut books: Vec<usize> = Vec::with_capacity(book_count as usize);
+  for _ in 0..book_count {
+    books.push(try!(read_book(bits, codebooks, true)));
+  }
+  if order == 0 || rate == 0 || bark_map_size == 0 {
+    return Err(invalid("floor"));
+  }
+  if amplitude_bits > 32 {
+    return Err(AudioError::Unsupported(
+      format!("Floor amplitudes of {} bits are not supported", amplitude_bits)
+    ));
+  }
+  Ok(Floor0 {
+    order:            order,
+    rate:             rate,
+    bark_map_size:    bark_map_size,
+    amplitude_bits:   amplitude_bits,
+    amplitude_offset: amplitude_offset,
+    books:            books
+  })
+}
+
+fn read_floor1(bits: &mut BitReader, codebooks: &[Codebook]) -> AudioResult<Floor1> {
+  let partitions = try!(bits.read(5)) as usize;
+  let mut partition_classes: Vec<usize> = Vec::with_capacity(partitions);
+  for _ in 0..partitions {
+    partition_classes.push(try!(bits.read(4)) as usize);
+  }
+  let class_count = partition_classes.iter().max().map_or(0, |max| max + 1);
+  let mut class_dimensions:  Vec<usize> = Vec::with_capacity(class_count);
+  let mut class_subclasses:  Vec<u32>   = Vec::with_capacity(class_count);
+  let mut class_masterbooks: Vec<usize> = Vec::with_capacity(class_count);
+  let mut subclass_books: Vec<Vec<Option<usize>>> = Vec::with_capacity(class_count);
+  for _ in 0..class_count {
+    class_dimensions.push(try!(bits.read(3)) as usize + 1);
+    let subclasses = try!(bits.read(2));
+    class_subclasses.push(subclasses);
+    class_masterbooks.push(
+      if subclasses > 0 {
+        try!(read_book(bits, codebooks, false))
+      } else {
+        0
+      }
+    );
+    let mut books: Vec<Option<usize>> = Vec::with_capacity(1 << subclasses);
+    for _ in 0 .. 1 << subclasses {
+      let book = try!(bits.read(8)) as usize;
+      if book == 0 {
+        books.push(None);
+      }
+      else if book - 1 < codebooks.len() {
+        books.push(Some(book - 1));
+      }
+      else {
+        return Err(invalid("codebook number"));
+      }
+    }
+    subclass_books.push(books);
+  }
+  let multiplier = try!(bits.read(2)) + 1;
+  let range_bits = try!(bits.read(4));
+  let mut x_list: Vec<u32> = vec![0, 1 << range_bits];
+  for class in partition_classes.iter() {
+    for _ in 0..class_dimensions[*class] {
+      x_list.push(try!(bits.read(range_bits)));
+    }
+  }
+  if x_list.len() > 65 {
+    return Err(invalid("floor"));
+  }
+  let mut sorted: Vec<usize> = (0..x_list.len()).collect();
+  sorted.sort_by_key(|i| x_list[*i]);
+  if sorted.windows(2).any(|pair| x_list[pair[0]] == x_list[pair[1]]) {
+    return Err(invalid("floor"));
+  }
+  // The neighbors of a point are the closest points before it in the list
+  // that are below and above its coordinate.
+  let mut neighbors: Vec<(usize, usize)> = vec![(0, 0); x_list.len()];
+  for i in 2..x_list.len() {
+    let x = x_list[i];
+    let mut low  = 0;
+    let mut high = 1;
+    for j in 0..i {
+      if x_list[j] < x && x_list[j] > x_list[low] {
+        low = j;
+      }
+      if x_list[j] > x && x_list[j] < x_list[high] {
+        high = j;
+      }
+    }
+    neighbors[i] = (low, high);
+  }
+  Ok(Floor1 {
+    partition_classes: partition_classes,
+    class_dimensions:  class_dimensions,
+    class_subclasses:  class_subclasses,
+    class_masterbooks: class_masterbooks,
+    subclass_books:    subclass_books,
+    multiplier:        multiplier,
+    x_list:            x_list,
+    sorted:            sorted,
+    neighbors:         neighbors
+  })
+}
+
+fn read_residue(bits: &mut BitReader, codebooks: &[Codebook]) -> AudioResult<Residue> {
+  let residue_type = try!(bits.read(16)) as u16;
+  if residue_type > 2 {
+    return Err(invalid("residue type"));
+  }
+  let begin           = try!(bits.read(24)) as usize;
+  let end             = try!(bits.read(24)) as usize;
+  let partition_size  = try!(bits.read(24)) as usize + 1;
+  let classifications = try!(bits.read(6)) as usize + 1;
+  let classbook       = try!(read_book(bits, codebooks, false));
+  let mut cascades: Vec<u32> = Vec::with_capacity(classifications);
+  for _ in 0..classifications {
+    let low_bits  = try!(bits.read(3));
+    let high_bits =
+      if try!(bits.read_bool()) {
+        try!(bits.read(5))
+      } else {
+        0
+      };
+    cascades.push(high_bits << 3 | low_bits);
+  }
+  let mut books: Vec<[Option<usize>; 8]> = Vec::with_capacity(classifications);
+  for cascade in cascades.iter() {
+    let mut class_books: [Option<usize>; 8] = [None; 8];
+    for pass in 0..8 {
+      if cascade & (1 << pass) != 0 {
+        class_books[pass] = Some(try!(read_book(bits, codebooks, true)));
+      }
+    }
+    books.push(class_books);
+  }
+  // Each classbook entry holds the classifications of several partitions.
+  if codebooks[classbook].dimensions == 0 {
+    return Err(invalid("residue classbook"));
+  }
+  Ok(Residue {
+    residue_type:    residue_type,
+    begin:           begin,
+    end:             end,
+    partition_size:  partition_size,
+    classifications: classifications,
+    classbook:       classbook,
+    books:           books
+  })
+}
+
+fn read_mapping(bits: &mut BitReader,
+                ident: &Identification,
+                floor_count: usize,
+                residue_count: usize) -> AudioResult<Mapping> {
+  if try!(bits.read(16)) != 0 {
+    return Err(invalid("mapping type"));
+  }
+  let channels = ident.channels as usize;
+  let submap_count =
+    if try!(bits.read_bool()) {
+      try!(bits.read(4)) as usize + 1
+    } else {
+      1
+    };
+  let mut coupling: Vec<(usize, usize)> = Vec::new();
+  if try!(bits.read_bool()) {
+    let steps = try!(bits.read(8)) + 1;
+    let channel_bits = ilog(channels as u32 - 1);
+    for _ in 0..steps {
+      let magnitude = try!(bits.read(channel_bits)) as usize;
+      let angle     = try!(bits.read(channel_bits)) as usize;
+      if magnitude == angle || magnitude >= channels || angle >= channels {
+        return Err(invalid("channel coupling"));
+      }
+      coupling.push((magnitude, angle));
+    }
+  }
+  if try!(bits.read(2)) != 0 {
+    return Err(invalid("mapping"));
+  }
+  let mut mux: Vec<usize> = vec![0; channels];
+  if submap_count > 1 {
+    for submap in mux.iter_mut() {
+      *submap = try!(bits.read(4)) as usize;
+      if *submap >= submap_count {
+        return Err(invalid("mapping"));
+      }
+    }
+  }
+  let mut submaps: Vec<(usize, usize)> = Vec::with_capacity(submap_count);
+  for _ in 0..submap_count {
+    try!(bits.read(8));
+    let floor   = try!(bits.read(8)) as usize;
+    let residue = try!(bits.read(8)) as usize;
+    if floor >= floor_count || residue >= residue_count {
+      return Err(invalid("mapping"));
+    }
+    submaps.push((floor, residue));
+  }
+  Ok(Mapping {
+    submaps:  submaps,
+    coupling: coupling,
+    mux:      mux
+  })
+}
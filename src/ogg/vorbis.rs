@@ -0,0 +1,648 @@
+//! Vorbis Audio Packets
+use std::f32::consts::PI;
+use error::*;
+use ogg::bits::{BitReader, ilog};
+use ogg::codebook::Codebook;
+use ogg::mdct::Imdct;
+use ogg::setup::*;
+
+/// Amplitudes of the floor type 1 curve values, as given by the Vorbis
+/// specification.
+const FLOOR1_INVERSE_DB: [f32; 256] = [
+  1.0649863e-07, 1.1341951e-07, 1.2079015e-07, 1.2863978e-07,
+  1.3699951e-07, 1.4590251e-07, 1.5538408e-07, 1.6548181e-07,
+  1.7623575e-07, 1.8768855e-07, 1.9988561e-07, 2.1287530e-07,
+  2.2670913e-07, 2.4144197e-07, 2.5713223e-07, 2.7384213e-07,
+  2.9163793e-07, 3.1059021e-07, 3.3077411e-07, 3.5226968e-07,
+  3.7516214e-07, 3.9954229e-07, 4.2550680e-07, 4.5315863e-07,
+  4.8260743e-07, 5.1396998e-07, 5.4737065e-07, 5.8294187e-07,
+  6.2082472e-07, 6.6116941e-07, 7.0413592e-07, 7.4989464e-07,
+  7.9862701e-07, 8.5052630e-07, 9.0579828e-07, 9.6466216e-07,
+  1.0273513e-06, 1.0941144e-06, 1.1652161e-06, 1.2409384e-06,
+  1.3215816e-06, 1.4074654e-06, 1.4989305e-06, 1.5963394e-06,
+  1.7000785e-06, 1.8105592e-06, 1.9282195e-06, 2.0535261e-06,
+  2.1869758e-06, 2.3290978e-06, 2.4804557e-06, 2.6416497e-06,
+  2.8133190e-06, 2.9961443e-06, 3.1908506e-06, 3.3982101e-06,
+  3.6190449e-06, 3.8542308e-06, 4.1047004e-06, 4.3714470e-06,
+  4.6555282e-06, 4.9580707e-06, 5.2802740e-06, 5.6234160e-06,
+  5.9888572e-06, 6.3780469e-06, 6.7925283e-06, 7.2339451e-06,
+  7.7040476e-06, 8.2047000e-06, 8.7378876e-06, 9.3057248e-06,
+  9.9104632e-06, 1.0554501e-05, 1.1240392e-05, 1.1970856e-05,
+  1.2748789e-05, 1.3577278e-05, 1.4459606e-05, 1.5399272e-05,
+  1.6400004e-05, 1.7465768e-05, 1.8600792e-05, 1.9809576e-05,
+  2.1096914e-05, 2.2467911e-05, 2.3928002e-05, 2.5482978e-05,
+  2.7139006e-05, 2.8902651e-05, 3.0780908e-05, 3.2781225e-05,
+  3.4911534e-05, 3.7180282e-05, 3.9596466e-05, 4.2169667e-05,
+  4.4910090e-05, 4.7828601e-05, 5.0936773e-05, 5.4246931e-05,
+  5.7772202e-05, 6.1526565e-05, 6.5524908e-05, 6.9783085e-05,
+  7.4317983e-05, 7.9147585e-05, 8.4291040e-05, 8.9768747e-05,
+  9.5602426e-05, 0.00010181521, 0.00010843174, 0.00011547824,
+  0.00012298267, 0.00013097477, 0.00013948625, 0.00014855085,
+  0.00015820453, 0.00016848555, 0.00017943469, 0.00019109536,
+  0.00020351382, 0.00021673929, 0.00023082423, 0.00024582449,
+  0.00026179955, 0.00027881276, 0.00029693158, 0.00031622787,
+  0.00033677814, 0.00035866388, 0.00038197188, 0.00040679456,
+  0.00043323036, 0.00046138411, 0.00049136745, 0.00052329927,
+  0.00055730621, 0.00059352311, 0.00063209358, 0.00067317058,
+  0.00071691700, 0.00076350630, 0.00081312324, 0.00086596457,
+  0.00092223983, 0.00098217216, 0.0010459992,  0.0011139742,
+  0.0011863665,  0.0012634633,  0.0013455702,  0.0014330129,
+  0.0015261382,  0.0016253153,  0.0017309374,  0.0018434235,
+  0.0019632195,  0.0020908006,  0.0022266726,  0.0023713743,
+  0.0025254795,  0.0026895994,  0.0028643847,  0.0030505286,
+  0.0032487691,  0.0034598925,  0.0036847358,  0.0039241906,
+  0.0041792066,  0.0044507950,  0.0047400328,  0.0050480668,
+  0.0053761186,  0.0057254891,  0.0060975636,  0.0064938176,
+  0.0069158225,  0.0073652516,  0.0078438871,  0.0083536271,
+  0.0088964928,  0.009474637,   0.010090352,   0.010746080,
+  0.011444421,   0.012188144,   0.012980198,   0.013823725,
+  0.014722068,   0.015678791,   0.016697687,   0.017782797,
+  0.018938423,   0.020169149,   0.021479854,   0.022875735,
+  0.024362330,   0.025945531,   0.027631618,   0.029427276,
+  0.031339626,   0.033376252,   0.035545228,   0.037855157,
+  0.040315199,   0.042935108,   0.045725273,   0.048696758,
+  0.051861348,   0.055231591,   0.058820850,   0.062643361,
+  0.066714279,   0.071049749,   0.075666962,   0.080584227,
+  0.085821044,   0.091398179,   0.097337747,   0.10366330,
+  0.11039993,    0.11757434,    0.12521498,    0.13335215,
+  0.14201813,    0.15124727,    0.16107617,    0.17154380,
+  0.18269168,    0.19456402,    0.20720788,    0.22067342,
+  0.23501402,    0.25028656,    0.26655159,    0.28387361,
+  0.30232132,    0.32196786,    0.34289114,    0.36517414,
+  0.38890521,    0.41417847,    0.44109412,    0.46975890,
+  0.50028648,    0.53279791,    0.56742212,    0.60429640,
+  0.64356699,    0.68538959,    0.72993007,    0.77736504,
+  0.82788260,    0.88168307,    0.9389798,     1.0
+];
+
+/// Decoded floor of a single channel, which is turned into a curve once the
+/// residue has been decoded.
+enum FloorData {
+  Zero(u64, Vec<f32>),
+  One(Vec<i32>)
+}
+
+/// Decodes Vorbis audio packets into samples.
+///
+/// Each packet holds a block of audio, which overlaps half of the previous
+/// block. Samples are returned once the blocks overlapping them have been
+/// decoded, so the first packet does not return any samples.
+pub struct VorbisDecoder {
+  ident:         Identification,
+  setup:         Setup,
+  imdct:         [Imdct; 2],
+  /// Window slopes for short and long blocks
+  slopes:        [Vec<f32>; 2],
+  /// Cosine of the bark map of each floor type 0 for both block sizes
+  floor0_maps:   Vec<[Vec<f32>; 2]>,
+  /// Windowed samples of each channel from the previous block
+  previous:      Option<Vec<Vec<f32>>>
+}
+
+impl VorbisDecoder {
+  pub fn new(ident: Identification, setup: Setup) -> VorbisDecoder {
+    let slope = |size: usize| {
+      let half = size / 2;
+      (0..half).map(|i| {
+        let x = ((i as f32 + 0.5) / half as f32 * PI / 2.0).sin();
+        (PI / 2.0 * x * x).sin()
+      }).collect::<Vec<f32>>()
+    };
+    let floor0_maps = setup.floors.iter().map(|floor|
+      match *floor {
+        Floor::Zero(ref floor) =>
+          [bark_map(floor, ident.blocksizes[0] / 2),
+           bark_map(floor, ident.blocksizes[1] / 2)],
+        Floor::One(_) => [Vec::new(), Vec::new()]
+      }
+    ).collect();
+    VorbisDecoder {
+      imdct:       [Imdct::new(ident.blocksizes[0]), Imdct::new(ident.blocksizes[1])],
+      slopes:      [slope(ident.blocksizes[0]), slope(ident.blocksizes[1])],
+      floor0_maps: floor0_maps,
+      previous:    None,
+      ident:       ident,
+      setup:       setup
+    }
+  }
+
+  /// Decodes an audio packet, appending any complete samples to `output`
+  /// in interleaved order. Returns the number of frames appended.
+  ///
+  /// Empty packets and packets that are not audio packets are ignored. An
+  /// error is returned for an audio packet that cannot be decoded, since
+  /// dropping it would move the samples that follow.
+  pub fn decode(&mut self, packet: &[u8], output: &mut Vec<f32>) -> AudioResult<usize> {
+    let mut bits = BitReader::new(packet);
+    let block = match try!(self.decode_block(&mut bits)) {
+      Some(block) => block,
+      None        => return Ok(0)
+    };
+    let n = block[0].len();
+    let frames =
+      match self.previous {
+        Some(ref previous) => {
+          // Samples are returned from the center of the previous block to
+          // the center of the current block.
+          let prev_n = previous[0].len();
+          let frames = prev_n / 4 + n / 4;
+          let offset = prev_n / 4;
+          output.reserve(frames * block.len());
+          for i in 0..frames {
+            for (prev, cur) in previous.iter().zip(block.iter()) {
+              let mut sample = prev.get(prev_n / 2 + i).cloned().unwrap_or(0f32);
+              if i + n / 4 >= offset {
+                sample += cur[i + n / 4 - offset];
+              }
+              output.push(sample);
+            }
+          }
+          frames
+        },
+        None => 0
+      };
+    self.previous = Some(block);
+    Ok(frames)
+  }
+
+  /// Clears the previous block, so decoding can resume at another packet.
+  /// The next packet then does not return any samples.
+  pub fn reset(&mut self) {
+    self.previous = None;
+  }
+
+  /// Decodes the windowed samples of each channel of a packet, or returns
+  /// `None` for a packet that is ignored.
+  fn decode_block(&self, bits: &mut BitReader) -> AudioResult<Option<Vec<Vec<f32>>>> {
+    let setup    = &self.setup;
+    let channels = self.ident.channels as usize;
+    match bits.read_bool() {
+      Ok(false) => {},
+      Ok(true) | Err(AudioError::AudioEnd) => return Ok(None),
+      Err(e) => return Err(e)
+    }
+    let truncated = |_: AudioError| AudioError::Format(
+      "File is not valid Vorbis (Audio packet is truncated)".to_string()
+    );
+    let mode_bits = ilog(setup.modes.len() as u32 - 1);
+    let mode      = match setup.modes.get(try!(bits.read(mode_bits).map_err(&truncated)) as usize) {
+      Some(mode) => *mode,
+      None =>
+        return Err(AudioError::Format(
+          "File is not valid Vorbis (Invalid mode number)".to_string()
+        ))
+    };
+    let long = mode.blockflag as usize;
+    let n = self.ident.blocksizes[long];
+    let (previous_long, next_long) =
+      if mode.blockflag {
+        (try!(bits.read_bool().map_err(&truncated)), try!(bits.read_bool().map_err(&truncated)))
+      } else {
+        (false, false)
+      };
+    let mapping = &setup.mappings[mode.mapping];
+
+    // Decode the floor of each channel. A floor that is unused or cannot be
+    // decoded silences the channel.
+    let floors: Vec<Option<FloorData>> = (0..channels).map(|channel| {
+      let floor = &setup.floors[mapping.submaps[mapping.mux[channel]].0];
+      decode_floor(floor, &setup.codebooks, bits).unwrap_or(None)
+    }).collect();
+    // Coupled channels are decoded if either channel has a floor.
+    let mut no_residue: Vec<bool> = floors.iter().map(|floor| floor.is_none()).collect();
+    for &(magnitude, angle) in mapping.coupling.iter() {
+      if !no_residue[magnitude] || !no_residue[angle] {
+        no_residue[magnitude] = false;
+        no_residue[angle]     = false;
+      }
+    }
+
+    // Decode the residue of each submap. Decoding stops at the end of the
+    // packet, keeping the values decoded so far.
+    let mut spectra: Vec<Vec<f32>> = vec![vec![0f32; n / 2]; channels];
+    for (submap, &(_, residue)) in mapping.submaps.iter().enumerate() {
+      let bundle: Vec<usize> =
+        (0..channels).filter(|channel| mapping.mux[*channel] == submap).collect();
+      let _ = decode_residue(&setup.residues[residue], &setup.codebooks, bits,
+                             &mut spectra, &bundle, &no_residue);
+    }
+
+    // Undo the channel coupling, which stores square polar magnitude and
+    // angle values.
+    for &(magnitude, angle) in mapping.coupling.iter().rev() {
+      for i in 0 .. n / 2 {
+        let m = spectra[magnitude][i];
+        let a = spectra[angle][i];
+        let (new_m, new_a) =
+          if m > 0f32 {
+            if a > 0f32 { (m, m - a) } else { (m + a, m) }
+          } else {
+            if a > 0f32 { (m, m + a) } else { (m - a, m) }
+          };
+        spectra[magnitude][i] = new_m;
+        spectra[angle][i]     = new_a;
+      }
+    }
+
+    // Apply the floor curve to the spectrum, then transform and window the
+    // spectrum of each channel.
+    let mut block: Vec<Vec<f32>> = Vec::with_capacity(channels);
+    for (channel, floor) in floors.iter().enumerate() {
+      let mut samples = vec![0f32; n];
+      if let Some(ref floor_data) = *floor {
+        let spectrum = &mut spectra[channel];
+        match (floor_data, &setup.floors[mapping.submaps[mapping.mux[channel]].0]) {
+          (&FloorData::Zero(amplitude, ref coefficients), &Floor::Zero(ref floor)) => {
+            let index = mapping.submaps[mapping.mux[channel]].0;
+            apply_floor0(floor, amplitude, coefficients,
+                         &self.floor0_maps[index][long], spectrum)
+          },
+          (&FloorData::One(ref y), &Floor::One(ref floor)) =>
+            apply_floor1(floor, y, spectrum),
+          _ => unreachable!()
+        }
+        self.imdct[long].inverse(spectrum, &mut samples);
+        self.window(&mut samples, mode.blockflag, previous_long, next_long);
+      }
+      block.push(samples);
+    }
+    Ok(Some(block))
+  }
+
+  /// Applies the window of a block, which depends on the size of the
+  /// neighboring blocks.
+  fn window(&self, samples: &mut [f32], long: bool, previous_long: bool, next_long: bool) {
+    let n     = samples.len();
+    let short = self.ident.blocksizes[0];
+    let (left_start, left_slope) =
+      if long && !previous_long {
+        (n / 4 - short / 4, &self.slopes[0])
+      } else {
+        (0, &self.slopes[long as usize])
+      };
+    let (right_start, right_slope) =
+      if long && !next_long {
+        (n * 3 / 4 - short / 4, &self.slopes[0])
+      } else {
+        (n / 2, &self.slopes[long as usize])
+      };
+    for sample in samples[.. left_start].iter_mut() {
+      *sample = 0f32;
+    }
+    for (sample, w) in samples[left_start ..].iter_mut().zip(left_slope.iter()) {
+      *sample *= *w;
+    }
+    for (sample, w) in samples[right_start ..].iter_mut().zip(right_slope.iter().rev()) {
+      *sample *= *w;
+    }
+    for sample in samples[right_start + right_slope.len() ..].iter_mut() {
+      *sample = 0f32;
+    }
+  }
+}
+
+// Private functions
+
+/// Decodes the floor of a channel, returning `None` if the floor is unused.
+fn decode_floor(floor: &Floor,
+                codebooks: &[Codebook],
+                bits: &mut BitReader) -> AudioResult<Option<FloorData>> {
+  match *floor {
+    Floor::Zero(ref floor) => {
+      let amplitude = try!(bits.read(floor.amplitude_bits)) as u64;
+      if amplitude == 0 {
+        return Ok(None);
+      }
+      let book_bits = ilog(floor.books.len() as u32);
+      let book = match floor.books.get(try!(bits.read(book_bits)) as usize) {
+        Some(book) => &codebooks[*book],
+        None       => return Ok(None)
+      };
+      let mut coefficients: Vec<f32> = Vec::with_capacity(floor.order + book.dimensions);
+      let mut last = 0f32;
+      while coefficients.len() < floor.order {
+        for value in try!(book.decode_vector(bits)).iter() {
+          coefficients.push(value + last);
+        }
+        last = *coefficients.last().unwrap();
+      }
+      coefficients.truncate(floor.order);
+      Ok(Some(FloorData::Zero(amplitude, coefficients)))
+    },
+    Floor::One(ref floor) => {
+      if !try!(bits.read_bool()) {
+        return Ok(None);
+      }
+      let range_bits = ilog(floor1_range(floor) as u32 - 1);
+      let mut y: Vec<i32> = Vec::with_capacity(floor.x_list.len());
+      y.push(try!(bits.read(range_bits)) as i32);
+      y.push(try!(bits.read(range_bits)) as i32);
+      for class in floor.partition_classes.iter() {
+        let dimensions = floor.class_dimensions[*class];
+        let subclasses = floor.class_subclasses[*class];
+        let mask = (1 << subclasses) - 1;
+        let mut class_value =
+          if subclasses > 0 {
+            try!(codebooks[floor.class_masterbooks[*class]].decode_scalar(bits))
+          } else {
+            0
+          };
+        for _ in 0..dimensions {
+          y.push(
+            match floor.subclass_books[*class][class_value & mask] {
+              Some(book) => try!(codebooks[book].decode_scalar(bits)) as i32,
+              None       => 0
+            }
+          );
+          class_value >>= subclasses;
+        }
+      }
+      Ok(Some(FloorData::One(y)))
+    }
+  }
+}
+
+/// Returns the range of floor type 1 values for the floor multiplier.
+#[inline]
+fn floor1_range(floor: &Floor1) -> i32 {
+  [256, 128, 86, 64][floor.multiplier as usize - 1]
+}
+
+/// Multiplies the spectrum by the curve of a floor type 0, given by the
+/// line spectral pair coefficients.
+fn apply_floor0(floor: &Floor0,
+                amplitude: u64,
+                coefficients: &[f32],
+                map: &[f32],
+                spectrum: &mut [f32]) {
+  let cosines: Vec<f32> = coefficients.iter().map(|c| c.cos()).collect();
+  let order  = floor.order;
+  let offset = floor.amplitude_offset as f32;
+  let scale  = amplitude as f32 * offset / ((1u64 << floor.amplitude_bits) - 1) as f32;
+  let n = spectrum.len();
+  let mut i = 0;
+  while i < n {
+    let cos_omega = map[i];
+    let (mut p, mut q) =
+      if order % 2 == 1 {
+        (1f32 - cos_omega * cos_omega, 0.25f32)
+      } else {
+        ((1f32 - cos_omega) / 2f32, (1f32 + cos_omega) / 2f32)
+      };
+    for (j, cosine) in cosines.iter().enumerate() {
+      let term = cosine - cos_omega;
+      if j % 2 == 1 {
+        p *= 4f32 * term * term;
+      } else {
+        q *= 4f32 * term * term;
+      }
+    }
+    let value = (0.11512925f32 * (scale / (p + q).sqrt() - offset)).exp();
+    // Consecutive values with the same bark map value share the curve
+    // value.
+    while i < n && map[i] == cos_omega {
+      spectrum[i] *= value;
+      i += 1;
+    }
+  }
+}
+
+/// Returns the cosine of the bark map of a floor type 0 for half of a block
+/// of samples.
+fn bark_map(floor: &Floor0, n: usize) -> Vec<f32> {
+  let bark = |x: f32|
+    13.1f32 * (0.00074f32 * x).atan() + 2.24f32 * (0.0000000185f32 * x * x).atan()
+    + 0.0001f32 * x;
+  let rate = floor.rate as f32;
+  let size = floor.bark_map_size as f32;
+  (0..n).map(|i| {
+    let map = (bark(rate * i as f32 / (2f32 * n as f32)) * size / bark(0.5f32 * rate))
+              .floor().min(size - 1f32);
+    (PI * map / size).cos()
+  }).collect()
+}
+
+/// Multiplies the spectrum by the curve of a floor type 1, which is a line
+/// through the decoded points.
+fn apply_floor1(floor: &Floor1, y: &[i32], spectrum: &mut [f32]) {
+  let range  = floor1_range(floor);
+  let x_list = &floor.x_list;
+  let values = x_list.len();
+
+  // Each point is coded as an offset from the line between its neighbors.
+  let mut final_y: Vec<i32>  = vec![0; values];
+  let mut used:    Vec<bool> = vec![false; values];
+  final_y[0] = y[0];
+  final_y[1] = y[1];
+  used[0]    = true;
+  used[1]    = true;
+  for i in 2..values {
+    let (low, high) = floor.neighbors[i];
+    let predicted = render_point(x_list[low], final_y[low],
+                                 x_list[high], final_y[high], x_list[i]);
+    let value     = y[i];
+    let high_room = range - predicted;
+    let low_room  = predicted;
+    let room      = if high_room < low_room { high_room * 2 } else { low_room * 2 };
+    if value != 0 {
+      used[low]  = true;
+      used[high] = true;
+      used[i]    = true;
+      final_y[i] =
+        if value >= room {
+          if high_room > low_room {
+            value - low_room + predicted
+          } else {
+            predicted - value + high_room - 1
+          }
+        }
+        else if value % 2 == 1 {
+          predicted - (value + 1) / 2
+        }
+        else {
+          predicted + value / 2
+        };
+    }
+    else {
+      final_y[i] = predicted;
+    }
+  }
+
+  // Draw the lines between the used points in order of their coordinates.
+  let n = spectrum.len();
+  let multiplier = floor.multiplier as i32;
+  let mut curve: Vec<i32> = vec![0; n];
+  let mut low_x  = 0;
+  let mut low_y  = final_y[floor.sorted[0]] * multiplier;
+  let mut high_x = 0;
+  let mut high_y = low_y;
+  for &i in floor.sorted[1..].iter() {
+    if used[i] {
+      high_x = x_list[i] as usize;
+      high_y = final_y[i] * multiplier;
+      render_line(low_x, low_y, high_x, high_y, &mut curve);
+      low_x = high_x;
+      low_y = high_y;
+    }
+  }
+  if high_x < n {
+    render_line(high_x, high_y, n, high_y, &mut curve);
+  }
+  for (value, y) in spectrum.iter_mut().zip(curve.iter()) {
+    *value *= FLOOR1_INVERSE_DB[(*y).max(0).min(255) as usize];
+  }
+}
+
+/// Returns the Y coordinate of a point on the line between two points.
+#[inline]
+fn render_point(x0: u32, y0: i32, x1: u32, y1: i32, x: u32) -> i32 {
+  let dy  = y1 - y0;
+  let adx = (x1 - x0) as i32;
+  let err = dy.abs() * (x - x0) as i32;
+  let off = err / adx;
+  if dy < 0 { y0 - off } else { y0 + off }
+}
+
+/// Draws a line between two points using integer steps, as given by the
+/// Vorbis specification. Points past the end of the curve are not drawn.
+fn render_line(x0: usize, y0: i32, x1: usize, y1: i32, curve: &mut [i32]) {
+  if x1 <= x0 {
+    return;
+  }
+  let dy   = y1 - y0;
+  let adx  = (x1 - x0) as i32;
+  let base = dy / adx;
+  let sy   = if dy < 0 { base - 1 } else { base + 1 };
+  let ady  = dy.abs() - base.abs() * adx;
+  let mut y   = y0;
+  let mut err = 0;
+  if x0 < curve.len() {
+    curve[x0] = y;
+  }
+  for x in x0 + 1 .. x1.min(curve.len()) {
+    err += ady;
+    if err >= adx {
+      err -= adx;
+      y   += sy;
+    }
+    else {
+      y += base;
+    }
+    curve[x] = y;
+  }
+}
+
+/// Decodes the residue of the channels of a submap into their spectra.
+/// Channels are skipped if they do not have residue, unless the residue is
+/// interleaved.
+fn decode_residue(residue: &Residue,
+                  codebooks: &[Codebook],
+                  bits: &mut BitReader,
+                  spectra: &mut Vec<Vec<f32>>,
+                  bundle: &[usize],
+                  no_residue: &[bool]) -> AudioResult<()> {
+  if residue.residue_type == 2 {
+    // Type 2 residue interleaves the channels into a single vector, which is
+    // decoded as type 1.
+    if bundle.iter().all(|channel| no_residue[*channel]) {
+      return Ok(());
+    }
+    let channels = bundle.len();
+    let n = spectra[bundle[0]].len();
+    let mut interleaved: Vec<Vec<f32>> = vec![vec![0f32; n * channels]];
+    let result = decode_partitions(residue, codebooks, bits, &mut interleaved, &[false], 1);
+    for (i, value) in interleaved[0].iter().enumerate() {
+      spectra[bundle[i % channels]][i / channels] = *value;
+    }
+    result
+  }
+  else {
+    let mut vectors: Vec<Vec<f32>> =
+      bundle.iter().map(|channel| spectra[*channel].clone()).collect();
+    let skip: Vec<bool> = bundle.iter().map(|channel| no_residue[*channel]).collect();
+    let result = decode_partitions(residue, codebooks, bits, &mut vectors, &skip,
+                                   residue.residue_type);
+    for (channel, vector) in bundle.iter().zip(vectors.into_iter()) {
+      spectra[*channel] = vector;
+    }
+    result
+  }
+}
+
+/// Decodes the partitions of residue type 0 or 1 into the given vectors.
+///
+/// The classification of each partition is decoded in the first pass, and
+/// each pass adds the values of the codebook the classification gives for
+/// the pass.
+fn decode_partitions(residue: &Residue,
+                     codebooks: &[Codebook],
+                     bits: &mut BitReader,
+                     vectors: &mut [Vec<f32>],
+                     skip: &[bool],
+                     residue_type: u16) -> AudioResult<()> {
+  let size           = vectors[0].len();
+  let begin          = residue.begin.min(size);
+  let end            = residue.end.min(size);
+  let partition_size = residue.partition_size;
+  let partitions     = (end.saturating_sub(begin)) / partition_size;
+  let classbook      = &codebooks[residue.classbook];
+  let classwords     = classbook.dimensions;
+  if partitions == 0 {
+    return Ok(());
+  }
+  let mut classes: Vec<Vec<usize>> = vec![vec![0; partitions + classwords]; vectors.len()];
+  for pass in 0..8 {
+    let mut partition = 0;
+    while partition < partitions {
+      if pass == 0 {
+        for (channel, classes) in classes.iter_mut().enumerate() {
+          if skip[channel] {
+            continue;
+          }
+          let mut word = try!(classbook.decode_scalar(bits));
+          for i in (0..classwords).rev() {
+            classes[partition + i] = word % residue.classifications;
+            word /= residue.classifications;
+          }
+        }
+      }
+      let mut i = 0;
+      while i < classwords && partition < partitions {
+        for (channel, vector) in vectors.iter_mut().enumerate() {
+          if skip[channel] {
+            continue;
+          }
+          let book = match residue.books[classes[channel][partition]][pass] {
+            Some(book) => &codebooks[book],
+            None       => continue
+          };
+          let offset = begin + partition * partition_size;
+          let vector = &mut vector[offset .. offset + partition_size];
+          if residue_type == 0 {
+            // Values of each codeword are spread across the partition.
+            let step = partition_size / book.dimensions;
+            for j in 0..step {
+              for (k, value) in try!(book.decode_vector(bits)).iter().enumerate() {
+                vector[j + k * step] += *value;
+              }
+            }
+          }
+          else {
+            let mut j = 0;
+            while j < partition_size {
+              for value in try!(book.decode_vector(bits)).iter() {
+                if j < partition_size {
+                  vector[j] += *value;
+                }
+                j += 1;
+              }
+            }
+          }
+        }
+        i         += 1;
+        partition += 1;
+      }
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,982 @@
+//! CELT Band Coding
+//!
+//! The spectrum of a frame is split into bands, and each band is coded as
+//! its energy and its normalized shape. A shape is coded with pulses when
+//! enough bits are allocated, otherwise it is split in two and the angle
+//! between the halves is coded before each half. Bands without pulses are
+//! filled by folding the shapes of lower bands, or with noise.
+use opus::range::{RangeCoder, ilog, BITRES};
+use opus::rate::{bits2pulses, get_pulses, pulses2bits};
+use opus::tables::*;
+use opus::vq::*;
+
+/// Returns the next value of the linear congruential generator used for
+/// folding noise.
+#[inline]
+pub fn lcg_rand(seed: u32) -> u32 {
+  seed.wrapping_mul(1664525).wrapping_add(1013904223)
+}
+
+/// Multiplies two Q15 values with the rounding of the reference decoder.
+#[inline]
+fn frac_mul16(a: i32, b: i32) -> i32 {
+  (16384 + (a as i16 as i32) * (b as i16 as i32)) >> 15
+}
+
+/// Approximates the cosine of an angle, where 16384 is a right angle, in a
+/// way which is identical on every platform, as it affects the allocation.
+fn bitexact_cos(x: i32) -> i32 {
+  let tmp = (4096 + x * x) >> 13;
+  let x2 = tmp;
+  let x2 = (32767 - x2) + frac_mul16(x2, -7651 + frac_mul16(x2, 8277 + frac_mul16(-626, x2)));
+  1 + x2
+}
+
+/// Approximates the base 2 logarithm of the ratio of two cosines, in Q11.
+fn bitexact_log2tan(isin: i32, icos: i32) -> i32 {
+  let lc = ilog(icos as u32);
+  let ls = ilog(isin as u32);
+  let icos = icos << (15 - lc);
+  let isin = isin << (15 - ls);
+  (ls - lc) * (1 << 11)
+    + frac_mul16(isin, frac_mul16(isin, -2597) + 7932)
+    - frac_mul16(icos, frac_mul16(icos, -2597) + 7932)
+}
+
+/// Returns the integer square root of a value.
+fn isqrt(value: u32) -> u32 {
+  let mut value = value;
+  let mut g = 0u32;
+  let mut bshift = (ilog(value) - 1) >> 1;
+  let mut b = 1u32 << bshift;
+  loop {
+    let t = ((g << 1) + b) << bshift;
+    if t <= value {
+      g += b;
+      value -= t;
+    }
+    b >>= 1;
+    bshift -= 1;
+    if bshift < 0 {
+      break;
+    }
+  }
+  g
+}
+
+/// Computes the amplitude of each band of each channel.
+pub fn compute_band_energies(freq: &[f32], band_e: &mut [f32], end: usize,
+                             channels: usize, lm: usize) {
+  let n = SHORT_BLOCK_SIZE << lm;
+  for c in 0..channels {
+    for i in 0..end {
+      let band = &freq[c * n + (EBANDS[i] << lm) .. c * n + (EBANDS[i + 1] << lm)];
+      let sum = 1e-27f32 + band.iter().map(|v| v * v).sum::<f32>();
+      band_e[i + c * BANDS] = sum.sqrt();
+    }
+  }
+}
+
+/// Normalizes each band of each channel to unit energy.
+pub fn normalise_bands(freq: &[f32], x: &mut [f32], band_e: &[f32], end: usize,
+                       channels: usize, m: usize) {
+  let n = m * SHORT_BLOCK_SIZE;
+  for c in 0..channels {
+    for i in 0..end {
+      let g = 1f32 / (1e-27f32 + band_e[i + c * BANDS]);
+      for j in m * EBANDS[i] .. m * EBANDS[i + 1] {
+        x[j + c * n] = freq[j + c * n] * g;
+      }
+    }
+  }
+}
+
+/// Scales the normalized bands of a channel by their energies, given in
+/// base 2 logarithms relative to the mean energy of each band.
+pub fn denormalise_bands(x: &[f32], freq: &mut [f32], band_log_e: &[f32],
+                         start: usize, end: usize, m: usize, silence: bool) {
+  let n = m * SHORT_BLOCK_SIZE;
+  let (start, end) = if silence { (0, 0) } else { (start, end) };
+  let bound = m * EBANDS[end];
+  for j in 0 .. m * EBANDS[start] {
+    freq[j] = 0f32;
+  }
+  for i in start..end {
+    let lg = band_log_e[i] + E_MEANS[i];
+    let g = (lg.min(32f32) * ::std::f32::consts::LN_2).exp();
+    for j in m * EBANDS[i] .. m * EBANDS[i + 1] {
+      freq[j] = x[j] * g;
+    }
+  }
+  for j in bound..n {
+    freq[j] = 0f32;
+  }
+}
+
+/// Fills the blocks of transient bands which received no pulses with noise,
+/// to prevent their energy from collapsing.
+pub fn anti_collapse(x: &mut [f32], collapse_masks: &[u8], lm: usize, channels: usize,
+                     size: usize, start: usize, end: usize, log_e: &[f32],
+                     prev1_log_e: &[f32], prev2_log_e: &[f32], pulses: &[i32],
+                     seed: u32) -> u32 {
+  let mut seed = seed;
+  for i in start..end {
+    let n0 = EBANDS[i + 1] - EBANDS[i];
+    // Depth in eighths of a bit.
+    let depth = ((1 + pulses[i]) as u32 / n0 as u32) >> lm;
+    let thresh = 0.5 * (-0.125 * depth as f32 * ::std::f32::consts::LN_2).exp();
+    let sqrt_1 = 1f32 / ((n0 << lm) as f32).sqrt();
+    for c in 0..channels {
+      let mut prev1 = prev1_log_e[c * BANDS + i];
+      let mut prev2 = prev2_log_e[c * BANDS + i];
+      if channels == 1 {
+        prev1 = prev1.max(prev1_log_e[BANDS + i]);
+        prev2 = prev2.max(prev2_log_e[BANDS + i]);
+      }
+      let ediff = (log_e[c * BANDS + i] - prev1.min(prev2)).max(0f32);
+      // Short blocks have less energy than long blocks.
+      let mut r = 2f32 * (-ediff * ::std::f32::consts::LN_2).exp();
+      if lm == 3 {
+        r *= 1.41421356;
+      }
+      r = thresh.min(r) * sqrt_1;
+      let band = &mut x[c * size + (EBANDS[i] << lm) .. c * size + (EBANDS[i + 1] << lm)];
+      let mut renormalize = false;
+      for k in 0 .. 1 << lm {
+        if collapse_masks[i * channels + c] & (1 << k) == 0 {
+          for j in 0..n0 {
+            seed = lcg_rand(seed);
+            band[(j << lm) + k] = if seed & 0x8000 != 0 { r } else { -r };
+          }
+          renormalize = true;
+        }
+      }
+      if renormalize {
+        renormalise_vector(band, 1f32);
+      }
+    }
+  }
+  seed
+}
+
+/// Replaces the mid of a band coded with intensity stereo by the weighted
+/// sum of both channels.
+fn intensity_stereo(x: &mut [f32], y: &[f32], band_e: &[f32], band: usize, n: usize) {
+  let left  = band_e[band];
+  let right = band_e[band + BANDS];
+  let norm = EPSILON + (EPSILON + left * left + right * right).sqrt();
+  let a1 = left / norm;
+  let a2 = right / norm;
+  for j in 0..n {
+    x[j] = a1 * x[j] + a2 * y[j];
+  }
+}
+
+/// Converts the channels of a band to mid and side.
+fn stereo_split(x: &mut [f32], y: &mut [f32], n: usize) {
+  for j in 0..n {
+    let l = 0.70710678 * x[j];
+    let r = 0.70710678 * y[j];
+    x[j] = l + r;
+    y[j] = r - l;
+  }
+}
+
+/// Converts the mid and side of a band back to normalized channels.
+fn stereo_merge(x: &mut [f32], y: &mut [f32], mid: f32, n: usize) {
+  let mut xp   = 0f32;
+  let mut side = 0f32;
+  for j in 0..n {
+    xp   += y[j] * x[j];
+    side += y[j] * y[j];
+  }
+  // Compensates for the mid normalization.
+  let xp = mid * xp;
+  let el = mid * mid + side - 2f32 * xp;
+  let er = mid * mid + side + 2f32 * xp;
+  if er < 6e-4 || el < 6e-4 {
+    for j in 0..n {
+      y[j] = x[j];
+    }
+    return;
+  }
+  let lgain = 1f32 / el.sqrt();
+  let rgain = 1f32 / er.sqrt();
+  for j in 0..n {
+    let l = mid * x[j];
+    let r = y[j];
+    x[j] = lgain * (l - r);
+    y[j] = rgain * (l + r);
+  }
+}
+
+/// Order of the Hadamard transform blocks for 2, 4, 8, and 16 blocks, with
+/// the DC at the end.
+const ORDERY_TABLE: [usize; 30] = [
+   1,  0,
+   3,  0,  2,  1,
+   7,  0,  4,  3,  6,  1,  5,  2,
+  15,  0,  8,  7, 12,  3, 11,  4, 14,  1,  9,  6, 13,  2, 10,  5
+];
+
+/// Reorders interleaved blocks into consecutive blocks.
+fn deinterleave_hadamard(x: &mut [f32], n0: usize, stride: usize, hadamard: bool) {
+  let n = n0 * stride;
+  let mut tmp = vec![0f32; n];
+  for i in 0..stride {
+    let block = if hadamard { ORDERY_TABLE[stride - 2 + i] } else { i };
+    for j in 0..n0 {
+      tmp[block * n0 + j] = x[j * stride + i];
+    }
+  }
+  x[..n].copy_from_slice(&tmp);
+}
+
+/// Reorders consecutive blocks into interleaved blocks.
+fn interleave_hadamard(x: &mut [f32], n0: usize, stride: usize, hadamard: bool) {
+  let n = n0 * stride;
+  let mut tmp = vec![0f32; n];
+  for i in 0..stride {
+    let block = if hadamard { ORDERY_TABLE[stride - 2 + i] } else { i };
+    for j in 0..n0 {
+      tmp[j * stride + i] = x[block * n0 + j];
+    }
+  }
+  x[..n].copy_from_slice(&tmp);
+}
+
+/// Applies a Haar wavelet step to pairs of values.
+pub fn haar1(x: &mut [f32], n0: usize, stride: usize) {
+  let n0 = n0 >> 1;
+  for i in 0..stride {
+    for j in 0..n0 {
+      let tmp1 = 0.70710678 * x[stride * 2 * j + i];
+      let tmp2 = 0.70710678 * x[stride * (2 * j + 1) + i];
+      x[stride * 2 * j + i]       = tmp1 + tmp2;
+      x[stride * (2 * j + 1) + i] = tmp1 - tmp2;
+    }
+  }
+}
+
+/// Returns the number of steps used to quantize the angle of a split.
+fn compute_qn(n: usize, b: i32, offset: i32, pulse_cap: i32, stereo: bool) -> i32 {
+  const EXP2_TABLE8: [i32; 8] = [16384, 17866, 19483, 21247, 23170, 25267, 27554, 30048];
+  let mut n2 = 2 * n as i32 - 1;
+  if stereo && n == 2 {
+    n2 -= 1;
+  }
+  // Leaves enough bits to code a pulse in the side of a stereo split.
+  let mut qb = (b + n2 * offset) / n2;
+  qb = qb.min(b - pulse_cap - (4 << BITRES));
+  qb = qb.min(8 << BITRES);
+  if qb < (1 << BITRES >> 1) {
+    1
+  }
+  else {
+    let qn = EXP2_TABLE8[(qb & 0x7) as usize] >> (14 - (qb >> BITRES));
+    (qn + 1) >> 1 << 1
+  }
+}
+
+/// Parameters of a split of a band into two halves, or into mid and side.
+struct Split {
+  inv:    bool,
+  imid:   i32,
+  iside:  i32,
+  delta:  i32,
+  itheta: i32,
+  qalloc: i32
+}
+
+/// State shared by the bands of a frame.
+struct Bands<'a> {
+  encode:            bool,
+  resynth:           bool,
+  band:              usize,
+  intensity:         usize,
+  spread:            usize,
+  tf_change:         i32,
+  coder:             &'a mut RangeCoder,
+  remaining_bits:    i32,
+  band_e:            &'a [f32],
+  seed:              u32,
+  avoid_split_noise: bool,
+  /// Whether the channels are never inverted, for a decoder downmixing
+  /// to mono
+  disable_inv:       bool
+}
+
+impl<'a> Bands<'a> {
+  /// Codes the angle of a split, from which the gains of each half and the
+  /// division of the bits between them are derived.
+  fn compute_theta(&mut self, x: &mut [f32], y: &mut [f32], n: usize, b: &mut i32,
+                   blocks: usize, blocks0: usize, lm: i32, stereo: bool,
+                   fill: &mut u32) -> Split {
+    let band = self.band;
+    let pulse_cap = LOG_N[band] + lm * (1 << BITRES);
+    let offset = (pulse_cap >> 1)
+      - if stereo && n == 2 { QTHETA_OFFSET_TWOPHASE } else { QTHETA_OFFSET };
+    let mut qn = compute_qn(n, *b, offset, pulse_cap, stereo);
+    if stereo && band >= self.intensity {
+      qn = 1;
+    }
+    let mut itheta = 0;
+    let mut inv = false;
+    if self.encode {
+      itheta = stereo_itheta(x, y, stereo, n);
+    }
+    let tell = self.coder.tell_frac();
+    if qn != 1 {
+      if self.encode {
+        itheta = (itheta * qn + 8192) >> 14;
+        if !stereo && self.avoid_split_noise && itheta > 0 && itheta < qn {
+          // Avoids a split which would inject noise into the silent half.
+          let unquantized = itheta * 16384 / qn;
+          let imid  = bitexact_cos(unquantized);
+          let iside = bitexact_cos(16384 - unquantized);
+          let delta = frac_mul16((n as i32 - 1) << 7, bitexact_log2tan(iside, imid));
+          if delta > *b {
+            itheta = qn;
+          }
+          else if delta < -*b {
+            itheta = 0;
+          }
+        }
+      }
+      if stereo && n > 2 {
+        // A step distribution, more likely up to a half right angle.
+        let p0 = 3;
+        let x0 = qn / 2;
+        let ft = (p0 * (x0 + 1) + x0) as u32;
+        let mut v = itheta;
+        if !self.encode {
+          let fs = self.coder.decode(ft) as i32;
+          v = if fs < (x0 + 1) * p0 { fs / p0 } else { x0 + 1 + (fs - (x0 + 1) * p0) };
+        }
+        let low  = if v <= x0 { p0 * v } else { (v - 1 - x0) + (x0 + 1) * p0 };
+        let high = if v <= x0 { p0 * (v + 1) } else { (v - x0) + (x0 + 1) * p0 };
+        if self.encode {
+          self.coder.encode(low as u32, high as u32, ft);
+        }
+        else {
+          self.coder.update(low as u32, high as u32, ft);
+          itheta = v;
+        }
+      }
+      else if blocks0 > 1 || stereo {
+        // A uniform distribution.
+        if self.encode {
+          self.coder.encode_uint(itheta as u32, qn as u32 + 1);
+        }
+        else {
+          itheta = self.coder.decode_uint(qn as u32 + 1) as i32;
+        }
+      }
+      else {
+        // A triangular distribution.
+        let ft = ((qn >> 1) + 1) * ((qn >> 1) + 1);
+        let (fl, fs);
+        if self.encode {
+          fs = if itheta <= qn >> 1 { itheta + 1 } else { qn + 1 - itheta };
+          fl = if itheta <= qn >> 1 {
+            itheta * (itheta + 1) >> 1
+          } else {
+            ft - ((qn + 1 - itheta) * (qn + 2 - itheta) >> 1)
+          };
+          self.coder.encode(fl as u32, (fl + fs) as u32, ft as u32);
+        }
+        else {
+          let fm = self.coder.decode(ft as u32) as i32;
+          if fm < ((qn >> 1) * ((qn >> 1) + 1) >> 1) {
+            itheta = (isqrt(8 * fm as u32 + 1) as i32 - 1) >> 1;
+            fs = itheta + 1;
+            fl = itheta * (itheta + 1) >> 1;
+          }
+          else {
+            itheta = (2 * (qn + 1) - isqrt(8 * (ft - fm - 1) as u32 + 1) as i32) >> 1;
+            fs = qn + 1 - itheta;
+            fl = ft - ((qn + 1 - itheta) * (qn + 2 - itheta) >> 1);
+          }
+          self.coder.update(fl as u32, (fl + fs) as u32, ft as u32);
+        }
+      }
+      itheta = itheta * 16384 / qn;
+      if self.encode && stereo {
+        if itheta == 0 {
+          intensity_stereo(x, y, self.band_e, band, n);
+        }
+        else {
+          stereo_split(x, y, n);
+        }
+      }
+    }
+    else if stereo {
+      if self.encode {
+        inv = itheta > 8192;
+        if inv {
+          for v in y[..n].iter_mut() {
+            *v = -*v;
+          }
+        }
+        intensity_stereo(x, y, self.band_e, band, n);
+      }
+      if *b > 2 << BITRES && self.remaining_bits > 2 << BITRES {
+        if self.encode {
+          self.coder.encode_bit_logp(inv, 2);
+        }
+        else {
+          inv = self.coder.decode_bit_logp(2);
+        }
+      }
+      else {
+        inv = false;
+      }
+      if self.disable_inv {
+        inv = false;
+      }
+      itheta = 0;
+    }
+    let qalloc = self.coder.tell_frac() - tell;
+    *b -= qalloc;
+
+    let (imid, iside, delta);
+    if itheta == 0 {
+      imid  = 32767;
+      iside = 0;
+      *fill &= (1 << blocks) - 1;
+      delta = -16384;
+    }
+    else if itheta == 16384 {
+      imid  = 0;
+      iside = 32767;
+      *fill &= ((1 << blocks) - 1) << blocks;
+      delta = 16384;
+    }
+    else {
+      imid  = bitexact_cos(itheta);
+      iside = bitexact_cos(16384 - itheta);
+      // Division of the bits which minimizes the squared error.
+      delta = frac_mul16((n as i32 - 1) << 7, bitexact_log2tan(iside, imid));
+    }
+    Split {
+      inv:    inv,
+      imid:   imid,
+      iside:  iside,
+      delta:  delta,
+      itheta: itheta,
+      qalloc: qalloc
+    }
+  }
+
+  /// Codes a band of a single value as its sign.
+  fn quant_band_n1(&mut self, x: &mut [f32], y: Option<&mut [f32]>,
+                   lowband_out: Option<&mut [f32]>) -> u32 {
+    let mut channels: Vec<&mut [f32]> = vec![x];
+    if let Some(y) = y {
+      channels.push(y);
+    }
+    for channel in channels.iter_mut() {
+      let mut sign = false;
+      if self.remaining_bits >= 1 << BITRES {
+        if self.encode {
+          sign = channel[0] < 0f32;
+          self.coder.encode_bits(sign as u32, 1);
+        }
+        else {
+          sign = self.coder.decode_bits(1) != 0;
+        }
+        self.remaining_bits -= 1 << BITRES;
+      }
+      if self.resynth {
+        channel[0] = if sign { -1f32 } else { 1f32 };
+      }
+    }
+    if let Some(lowband_out) = lowband_out {
+      lowband_out[0] = channels[0][0];
+    }
+    1
+  }
+
+  /// Codes a mono partition, splitting it in two when it has more bits than
+  /// pulses can use, in which case the halves are coded recursively.
+  fn quant_partition(&mut self, x: &mut [f32], n: usize, b: i32, blocks: usize,
+                     lowband: Option<&[f32]>, lm: i32, gain: f32, fill: u32) -> u32 {
+    let band = self.band;
+    let blocks0 = blocks;
+    let mut fill = fill;
+    let cache = &CACHE_BITS[CACHE_INDEX[((lm + 1) as usize) * BANDS + band] as usize ..];
+    if lm != -1 && b > cache[cache[0] as usize] as i32 + 12 && n > 2 {
+      let n = n >> 1;
+      let lm = lm - 1;
+      if blocks == 1 {
+        fill = (fill & 1) | (fill << 1);
+      }
+      let blocks = (blocks + 1) >> 1;
+      let (x, y) = x.split_at_mut(n);
+      let mut b = b;
+      let split = self.compute_theta(x, y, n, &mut b, blocks, blocks0, lm, false, &mut fill);
+      let mid  = split.imid as f32 / 32768f32;
+      let side = split.iside as f32 / 32768f32;
+      let itheta = split.itheta;
+      let mut delta = split.delta;
+
+      // Gives more bits to low-energy blocks than they would otherwise get.
+      if blocks0 > 1 && (itheta & 0x3fff) != 0 {
+        if itheta > 8192 {
+          // A rough approximation of pre-echo masking.
+          delta -= delta >> (4 - lm);
+        }
+        else {
+          // A forward-masking slope of 1.5 dB per 10 ms.
+          delta = 0.min(delta + ((n as i32) << BITRES >> (5 - lm)));
+        }
+      }
+      let mut mbits = 0.max(b.min((b - delta) / 2));
+      let mut sbits = b - mbits;
+      self.remaining_bits -= split.qalloc;
+
+      let next_lowband2 = lowband.map(|lowband| &lowband[n..]);
+      let mut rebalance = self.remaining_bits;
+      let mut cm;
+      if mbits >= sbits {
+        cm = self.quant_partition(x, n, mbits, blocks, lowband, lm, gain * mid, fill);
+        rebalance = mbits - (rebalance - self.remaining_bits);
+        if rebalance > 3 << BITRES && itheta != 0 {
+          sbits += rebalance - (3 << BITRES);
+        }
+        cm |= self.quant_partition(y, n, sbits, blocks, next_lowband2, lm, gain * side,
+                                   fill >> blocks) << (blocks0 >> 1);
+      }
+      else {
+        cm = self.quant_partition(y, n, sbits, blocks, next_lowband2, lm, gain * side,
+                                  fill >> blocks) << (blocks0 >> 1);
+        rebalance = sbits - (rebalance - self.remaining_bits);
+        if rebalance > 3 << BITRES && itheta != 16384 {
+          mbits += rebalance - (3 << BITRES);
+        }
+        cm |= self.quant_partition(x, n, mbits, blocks, lowband, lm, gain * mid, fill);
+      }
+      return cm;
+    }
+
+    let mut q = bits2pulses(band, lm, b);
+    let mut curr_bits = pulses2bits(band, lm, q);
+    self.remaining_bits -= curr_bits;
+    // Ensures the budget is never exceeded.
+    while self.remaining_bits < 0 && q > 0 {
+      self.remaining_bits += curr_bits;
+      q -= 1;
+      curr_bits = pulses2bits(band, lm, q);
+      self.remaining_bits -= curr_bits;
+    }
+
+    if q != 0 {
+      let k = get_pulses(q) as usize;
+      if self.encode {
+        quantize(x, n, k, self.spread, blocks, self.coder, gain, self.resynth)
+      }
+      else {
+        unquantize(x, n, k, self.spread, blocks, self.coder, gain)
+      }
+    }
+    else if self.resynth {
+      // Fills a band without pulses anyway.
+      let cm_mask = ((1u64 << blocks) - 1) as u32;
+      fill &= cm_mask;
+      if fill == 0 {
+        for v in x[..n].iter_mut() {
+          *v = 0f32;
+        }
+        return 0;
+      }
+      let cm;
+      match lowband {
+        None => {
+          for j in 0..n {
+            self.seed = lcg_rand(self.seed);
+            x[j] = (self.seed as i32 >> 20) as f32;
+          }
+          cm = cm_mask;
+        },
+        Some(lowband) => {
+          // Folds the spectrum about 48 dB below the usual level.
+          for j in 0..n {
+            self.seed = lcg_rand(self.seed);
+            let tmp = if self.seed & 0x8000 != 0 { 1f32 / 256f32 } else { -1f32 / 256f32 };
+            x[j] = lowband[j] + tmp;
+          }
+          cm = fill;
+        }
+      }
+      renormalise_vector(&mut x[..n], gain);
+      cm
+    }
+    else {
+      0
+    }
+  }
+
+  /// Codes a mono band, changing its time-frequency resolution first.
+  fn quant_band(&mut self, x: &mut [f32], n: usize, b: i32, blocks: usize,
+                lowband: Option<&[f32]>, lm: i32, lowband_out: Option<&mut [f32]>,
+                gain: f32, fill: u32) -> u32 {
+    const BIT_INTERLEAVE_TABLE: [u32; 16] = [0, 1, 1, 1, 2, 3, 3, 3, 2, 3, 3, 3, 2, 3, 3, 3];
+    const BIT_DEINTERLEAVE_TABLE: [u32; 16] = [
+      0x00, 0x03, 0x0C, 0x0F, 0x30, 0x33, 0x3C, 0x3F,
+      0xC0, 0xC3, 0xCC, 0xCF, 0xF0, 0xF3, 0xFC, 0xFF
+    ];
+    let n0 = n;
+    let long_blocks = blocks == 1;
+    let mut blocks = blocks;
+    let mut n_b = n / blocks;
+    let mut fill = fill;
+    let mut tf_change = self.tf_change;
+
+    if n == 1 {
+      return self.quant_band_n1(x, None, lowband_out);
+    }
+
+    let recombine = if tf_change > 0 { tf_change as usize } else { 0 };
+    let mut lowband: Option<Vec<f32>> = lowband.map(|lowband| lowband[..n].to_vec());
+
+    // Recombines blocks to increase the frequency resolution.
+    for k in 0..recombine {
+      if self.encode {
+        haar1(x, n >> k, 1 << k);
+      }
+      if let Some(ref mut lowband) = lowband {
+        haar1(lowband, n >> k, 1 << k);
+      }
+      fill = BIT_INTERLEAVE_TABLE[(fill & 0xF) as usize]
+             | BIT_INTERLEAVE_TABLE[(fill >> 4) as usize] << 2;
+    }
+    blocks >>= recombine;
+    n_b <<= recombine;
+
+    // Divides blocks to increase the time resolution.
+    let mut time_divide = 0;
+    while (n_b & 1) == 0 && tf_change < 0 {
+      if self.encode {
+        haar1(x, n_b, blocks);
+      }
+      if let Some(ref mut lowband) = lowband {
+        haar1(lowband, n_b, blocks);
+      }
+      fill |= fill << blocks;
+      blocks <<= 1;
+      n_b >>= 1;
+      time_divide += 1;
+      tf_change += 1;
+    }
+    let blocks0 = blocks;
+    let n_b0 = n_b;
+
+    // Orders the values by time instead of frequency.
+    if blocks0 > 1 {
+      if self.encode {
+        deinterleave_hadamard(x, n_b >> recombine, blocks0 << recombine, long_blocks);
+      }
+      if let Some(ref mut lowband) = lowband {
+        deinterleave_hadamard(lowband, n_b >> recombine, blocks0 << recombine, long_blocks);
+      }
+    }
+
+    let mut cm = self.quant_partition(x, n, b, blocks, lowband.as_ref().map(|l| &l[..]),
+                                      lm, gain, fill);
+
+    if self.resynth {
+      if blocks0 > 1 {
+        interleave_hadamard(x, n_b >> recombine, blocks0 << recombine, long_blocks);
+      }
+      n_b = n_b0;
+      blocks = blocks0;
+      for _ in 0..time_divide {
+        blocks >>= 1;
+        n_b <<= 1;
+        cm |= cm >> blocks;
+        haar1(x, n_b, blocks);
+      }
+      for k in 0..recombine {
+        cm = BIT_DEINTERLEAVE_TABLE[cm as usize];
+        haar1(x, n0 >> k, 1 << k);
+      }
+      blocks <<= recombine;
+
+      // Scales the output for folding.
+      if let Some(lowband_out) = lowband_out {
+        let scale = (n0 as f32).sqrt();
+        for j in 0..n0 {
+          lowband_out[j] = scale * x[j];
+        }
+      }
+      cm &= (1 << blocks) - 1;
+    }
+    cm
+  }
+
+  /// Codes a stereo band as its mid and side.
+  fn quant_band_stereo(&mut self, x: &mut [f32], y: &mut [f32], n: usize, b: i32,
+                       blocks: usize, lowband: Option<&[f32]>, lm: i32,
+                       lowband_out: Option<&mut [f32]>, fill: u32) -> u32 {
+    if n == 1 {
+      return self.quant_band_n1(x, Some(y), lowband_out);
+    }
+    let orig_fill = fill;
+    let mut fill = fill;
+    let mut b = b;
+    let split = self.compute_theta(x, y, n, &mut b, blocks, blocks, lm, true, &mut fill);
+    let mid  = split.imid as f32 / 32768f32;
+    let side = split.iside as f32 / 32768f32;
+    let itheta = split.itheta;
+    let mut cm;
+
+    if n == 2 {
+      // The side is orthogonal to the mid, so only its sign is coded.
+      let mut sbits = 0;
+      if itheta != 0 && itheta != 16384 {
+        sbits = 1 << BITRES;
+      }
+      let mbits = b - sbits;
+      let c = itheta > 8192;
+      self.remaining_bits -= split.qalloc + sbits;
+      {
+        let (x2, y2): (&mut [f32], &mut [f32]) = if c { (&mut *y, &mut *x) } else { (&mut *x, &mut *y) };
+        let mut sign = false;
+        if sbits != 0 {
+          if self.encode {
+            sign = x2[0] * y2[1] - x2[1] * y2[0] < 0f32;
+            self.coder.encode_bits(sign as u32, 1);
+          }
+          else {
+            sign = self.coder.decode_bits(1) != 0;
+          }
+        }
+        let sign = if sign { -1f32 } else { 1f32 };
+        // The original fill folds into the side even when it is cleared.
+        cm = self.quant_band(x2, n, mbits, blocks, lowband, lm, lowband_out, 1f32, orig_fill);
+        y2[0] = -sign * x2[1];
+        y2[1] =  sign * x2[0];
+      }
+      if self.resynth {
+        x[0] *= mid;
+        x[1] *= mid;
+        y[0] *= side;
+        y[1] *= side;
+        let tmp = x[0];
+        x[0] = tmp - y[0];
+        y[0] = tmp + y[0];
+        let tmp = x[1];
+        x[1] = tmp - y[1];
+        y[1] = tmp + y[1];
+      }
+    }
+    else {
+      let mut mbits = 0.max(b.min((b - split.delta) / 2));
+      let mut sbits = b - mbits;
+      self.remaining_bits -= split.qalloc;
+      let mut rebalance = self.remaining_bits;
+      // The mid is not scaled, as it is folded into later bands.
+      if mbits >= sbits {
+        cm = self.quant_band(x, n, mbits, blocks, lowband, lm, lowband_out, 1f32, fill);
+        rebalance = mbits - (rebalance - self.remaining_bits);
+        if rebalance > 3 << BITRES && itheta != 0 {
+          sbits += rebalance - (3 << BITRES);
+        }
+        cm |= self.quant_band(y, n, sbits, blocks, None, lm, None, side, fill >> blocks);
+      }
+      else {
+        cm = self.quant_band(y, n, sbits, blocks, None, lm, None, side, fill >> blocks);
+        rebalance = sbits - (rebalance - self.remaining_bits);
+        if rebalance > 3 << BITRES && itheta != 16384 {
+          mbits += rebalance - (3 << BITRES);
+        }
+        cm |= self.quant_band(x, n, mbits, blocks, lowband, lm, lowband_out, 1f32, fill);
+      }
+    }
+
+    if self.resynth {
+      if n != 2 {
+        stereo_merge(x, y, mid, n);
+      }
+      if split.inv {
+        for v in y[..n].iter_mut() {
+          *v = -*v;
+        }
+      }
+    }
+    cm
+  }
+}
+
+/// Codes the shapes of the bands of a frame, filling `collapse_masks` with
+/// the blocks of each band which received pulses, and returning the new
+/// seed of the noise generator. The encoder does not reconstruct the shapes,
+/// and a decoder downmixing to mono leaves out the inversion of channels.
+pub fn quant_all_bands(encode: bool, start: usize, end: usize, x: &mut [f32],
+                       y: Option<&mut [f32]>, collapse_masks: &mut [u8], band_e: &[f32],
+                       pulses: &[i32], short_blocks: bool, spread: usize,
+                       dual_stereo: bool, intensity: usize, tf_res: &[i32],
+                       total_bits: i32, balance: i32, coder: &mut RangeCoder, lm: usize,
+                       coded_bands: usize, seed: u32, disable_inv: bool) -> u32 {
+  let m = 1 << lm;
+  let blocks = if short_blocks { m } else { 1 };
+  let channels = if y.is_some() { 2 } else { 1 };
+  let norm_offset = m * EBANDS[start];
+  let norm_len = m * EBANDS[BANDS - 1] - norm_offset;
+  let mut norm  = vec![0f32; norm_len];
+  let mut norm2 = vec![0f32; norm_len];
+  let mut y = y;
+  let mut dual_stereo = dual_stereo;
+  let mut balance = balance;
+  let mut lowband_offset = 0;
+  let mut update_lowband = true;
+  let resynth = !encode;
+
+  let mut bands = Bands {
+    encode:            encode,
+    resynth:           resynth,
+    band:              start,
+    intensity:         intensity,
+    spread:            spread,
+    tf_change:         0,
+    coder:             coder,
+    remaining_bits:    0,
+    band_e:            band_e,
+    seed:              seed,
+    // Avoids injecting noise into the first band of transients.
+    avoid_split_noise: blocks > 1,
+    disable_inv:       disable_inv
+  };
+
+  for i in start..end {
+    bands.band = i;
+    let last = i == end - 1;
+    let band_start = m * EBANDS[i];
+    let n = m * EBANDS[i + 1] - band_start;
+    let tell = bands.coder.tell_frac();
+
+    // Allocates the bits of the band, with a share of the balance.
+    if i != start {
+      balance -= tell;
+    }
+    let remaining_bits = total_bits - tell - 1;
+    bands.remaining_bits = remaining_bits;
+    let b =
+      if i + 1 <= coded_bands {
+        let curr_balance = balance / 3.min(coded_bands as i32 - i as i32);
+        0.max(16383.min((remaining_bits + 1).min(pulses[i] + curr_balance)))
+      } else {
+        0
+      };
+
+    if resynth && (band_start >= n + m * EBANDS[start] || i == start + 1)
+       && (update_lowband || lowband_offset == 0) {
+      lowband_offset = i;
+    }
+    if i == start + 1 {
+      // Duplicates the folding data of the first band to fold the second.
+      let n1 = m * (EBANDS[start + 1] - EBANDS[start]);
+      let n2 = m * (EBANDS[start + 2] - EBANDS[start + 1]);
+      if n2 > n1 {
+        norm.copy_within(2 * n1 - n2 .. n1, n1);
+        if dual_stereo {
+          norm2.copy_within(2 * n1 - n2 .. n1, n1);
+        }
+      }
+    }
+
+    bands.tf_change = tf_res[i];
+
+    // Estimates the collapse masks of the bands being folded.
+    let mut effective_lowband: Option<usize> = None;
+    let mut x_cm: u32;
+    let mut y_cm: u32;
+    if lowband_offset != 0 && (spread != SPREAD_AGGRESSIVE || blocks > 1 || bands.tf_change < 0) {
+      // Never repeats spectral content within a band.
+      let effective = (m * EBANDS[lowband_offset]).saturating_sub(norm_offset + n);
+      effective_lowband = Some(effective);
+      let mut fold_start = lowband_offset;
+      loop {
+        fold_start -= 1;
+        if m * EBANDS[fold_start] <= effective + norm_offset {
+          break;
+        }
+      }
+      let mut fold_end = lowband_offset - 1;
+      loop {
+        fold_end += 1;
+        if !(fold_end < i && m * EBANDS[fold_end] < effective + norm_offset + n) {
+          break;
+        }
+      }
+      x_cm = 0;
+      y_cm = 0;
+      let mut fold_i = fold_start;
+      loop {
+        x_cm |= collapse_masks[fold_i * channels] as u32;
+        y_cm |= collapse_masks[fold_i * channels + channels - 1] as u32;
+        fold_i += 1;
+        if fold_i >= fold_end {
+          break;
+        }
+      }
+    }
+    else {
+      // Otherwise the noise generator fills every block.
+      x_cm = (1 << blocks) - 1;
+      y_cm = x_cm;
+    }
+
+    if dual_stereo && i == intensity {
+      // Switches from dual stereo to intensity stereo.
+      dual_stereo = false;
+      if resynth {
+        for j in 0 .. band_start - norm_offset {
+          norm[j] = 0.5 * (norm[j] + norm2[j]);
+        }
+      }
+    }
+
+    // Copies the folding data, which runs into the output of the first
+    // band when it is duplicated for the second band.
+    let out = band_start - norm_offset;
+    let lowband  = effective_lowband.map(|e| norm[e .. e + n].to_vec());
+    let lowband2 = effective_lowband.map(|e| norm2[e .. e + n].to_vec());
+    let xb = &mut x[band_start .. band_start + n];
+    if dual_stereo {
+      let yb = &mut y.as_mut().unwrap()[band_start .. band_start + n];
+      x_cm = bands.quant_band(xb, n, b / 2, blocks, lowband.as_ref().map(|l| &l[..]),
+                              lm as i32, if last { None } else { Some(&mut norm[out .. out + n]) },
+                              1f32, x_cm);
+      y_cm = bands.quant_band(yb, n, b / 2, blocks, lowband2.as_ref().map(|l| &l[..]),
+                              lm as i32, if last { None } else { Some(&mut norm2[out .. out + n]) },
+                              1f32, y_cm);
+    }
+    else {
+      let lowband = lowband.as_ref().map(|l| &l[..]);
+      let lowband_out = if last { None } else { Some(&mut norm[out .. out + n]) };
+      x_cm =
+        match y {
+          Some(ref mut y) => {
+            let yb = &mut y[band_start .. band_start + n];
+            bands.quant_band_stereo(xb, yb, n, b, blocks, lowband, lm as i32, lowband_out,
+                                    x_cm | y_cm)
+          },
+          None => {
+            bands.quant_band(xb, n, b, blocks, lowband, lm as i32, lowband_out, 1f32,
+                             x_cm | y_cm)
+          }
+        };
+      y_cm = x_cm;
+    }
+    collapse_masks[i * channels] = x_cm as u8;
+    collapse_masks[i * channels + channels - 1] = y_cm as u8;
+    balance += pulses[i] + tell;
+
+    // Updates the folding position only while there is a bit per value.
+    update_lowband = b > (n as i32) << BITRES;
+    // Only the first band needs to avoid noise from a split.
+    bands.avoid_split_noise = false;
+  }
+  bands.seed
+}
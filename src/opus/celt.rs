@@ -0,0 +1,734 @@
+//! CELT Frames
+//!
+//! CELT codes the MDCT of each frame as the energy of each band, and the
+//! normalized shape of each band with pyramid vector quantization. The
+//! decoder follows the reference decoder of RFC 6716 closely. The encoder
+//! codes frames of 20 ms at a constant bitrate with long blocks only, and
+//! leaves out the pitch pre-filter, which keeps it simple at the cost of
+//! some quality at low bitrates.
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+use opus::bands;
+use opus::energy;
+use opus::mdct::Mdct;
+use opus::range::{RangeCoder, BITRES};
+use opus::rate;
+use opus::tables::*;
+use opus::vq::SPREAD_NORMAL;
+
+/// Number of samples of each channel kept by the decoder, for the overlap
+/// of the MDCT and the pitch post-filter.
+const DECODE_BUFFER_SIZE: usize = 2048;
+
+/// Scale of the signal within CELT, matching 16-bit samples.
+const SIGNAL_SCALE: f32 = 32768f32;
+
+/// Value added when de-emphasizing, to avoid denormal numbers.
+const VERY_SMALL: f32 = 1e-30;
+
+/// Returns the window of the overlap between MDCT blocks, which is
+/// power-complementary to satisfy the Princen-Bradley condition.
+pub fn window() -> Vec<f32> {
+  (0..OVERLAP).map(|i| {
+    let x = (0.5 * PI * (i as f32 + 0.5) / OVERLAP as f32).sin();
+    (0.5 * PI * x * x).sin()
+  }).collect()
+}
+
+/// Returns the band after the last coded band for a bandwidth, where 0 is
+/// narrowband, 1 wideband, 2 super-wideband, and 3 fullband.
+pub fn end_band(bandwidth: usize) -> usize {
+  [13, 17, 19, 21][bandwidth]
+}
+
+/// Applies the pitch comb filter in place to `n` samples of the buffer from
+/// `start`, moving from the filter with period `t0` and gain `g0` to the
+/// filter with period `t1` and gain `g1` over the overlap.
+fn comb_filter(buf: &mut [f32], start: usize, t0: usize, t1: usize, n: usize, g0: f32,
+               g1: f32, tapset0: usize, tapset1: usize, window: &[f32], overlap: usize) {
+  if g0 == 0f32 && g1 == 0f32 {
+    return;
+  }
+  let t0 = t0.max(COMBFILTER_MINPERIOD);
+  let t1 = t1.max(COMBFILTER_MINPERIOD);
+  let g00 = g0 * COMB_FILTER_GAINS[tapset0][0];
+  let g01 = g0 * COMB_FILTER_GAINS[tapset0][1];
+  let g02 = g0 * COMB_FILTER_GAINS[tapset0][2];
+  let g10 = g1 * COMB_FILTER_GAINS[tapset1][0];
+  let g11 = g1 * COMB_FILTER_GAINS[tapset1][1];
+  let g12 = g1 * COMB_FILTER_GAINS[tapset1][2];
+  let mut x1 = buf[start + 1 - t1];
+  let mut x2 = buf[start - t1];
+  let mut x3 = buf[start - t1 - 1];
+  let mut x4 = buf[start - t1 - 2];
+  // The overlap is only needed when the filter changes.
+  let overlap = if g0 == g1 && t0 == t1 && tapset0 == tapset1 { 0 } else { overlap };
+  for i in 0..overlap {
+    let j = start + i;
+    let x0 = buf[j + 2 - t1];
+    let f = window[i] * window[i];
+    buf[j] = buf[j]
+      + (1f32 - f) * g00 * buf[j - t0]
+      + (1f32 - f) * g01 * (buf[j + 1 - t0] + buf[j - t0 - 1])
+      + (1f32 - f) * g02 * (buf[j + 2 - t0] + buf[j - t0 - 2])
+      + f * g10 * x2
+      + f * g11 * (x1 + x3)
+      + f * g12 * (x0 + x4);
+    x4 = x3;
+    x3 = x2;
+    x2 = x1;
+    x1 = x0;
+  }
+  if g1 == 0f32 {
+    return;
+  }
+  for i in overlap..n {
+    let j = start + i;
+    let x0 = buf[j + 2 - t1];
+    buf[j] = buf[j] + g10 * x2 + g11 * (x1 + x3) + g12 * (x0 + x4);
+    x4 = x3;
+    x3 = x2;
+    x2 = x1;
+    x1 = x0;
+  }
+}
+
+/// Decodes the time-frequency resolution of each band.
+fn tf_decode(start: usize, end: usize, transient: bool, tf_res: &mut [i32], lm: usize,
+             coder: &mut RangeCoder) {
+  let mut budget = coder.storage() as i32 * 8;
+  let mut tell = coder.tell();
+  let mut logp = if transient { 2 } else { 4 };
+  let tf_select_rsv = lm > 0 && tell + logp as i32 + 1 <= budget;
+  budget -= tf_select_rsv as i32;
+  let mut curr = 0;
+  let mut tf_changed = 0;
+  for i in start..end {
+    if tell + logp as i32 <= budget {
+      curr ^= coder.decode_bit_logp(logp) as i32;
+      tell = coder.tell();
+      tf_changed |= curr;
+    }
+    tf_res[i] = curr;
+    logp = if transient { 4 } else { 5 };
+  }
+  let table = &TF_SELECT_TABLE[lm];
+  let t = 4 * transient as usize;
+  let mut tf_select = 0;
+  if tf_select_rsv && table[t + tf_changed as usize] != table[t + 2 + tf_changed as usize] {
+    tf_select = coder.decode_bit_logp(1) as usize;
+  }
+  for i in start..end {
+    tf_res[i] = table[t + 2 * tf_select + tf_res[i] as usize];
+  }
+}
+
+/// Encodes the time-frequency resolution of each band, which is never
+/// changed by the encoder.
+fn tf_encode(start: usize, end: usize, tf_res: &mut [i32], lm: usize,
+             coder: &mut RangeCoder) {
+  let mut budget = coder.storage() as i32 * 8;
+  let mut tell = coder.tell();
+  let mut logp = 4;
+  let tf_select_rsv = lm > 0 && tell + logp as i32 + 1 <= budget;
+  budget -= tf_select_rsv as i32;
+  for _ in start..end {
+    if tell + logp as i32 <= budget {
+      coder.encode_bit_logp(false, logp);
+      tell = coder.tell();
+    }
+    logp = 5;
+  }
+  let table = &TF_SELECT_TABLE[lm];
+  if tf_select_rsv && table[0] != table[2] {
+    coder.encode_bit_logp(false, 1);
+  }
+  for i in start..end {
+    tf_res[i] = table[0];
+  }
+}
+
+/// Returns the number of boosts of each band, which give extra bits to bands
+/// standing out from their neighbours, such as the bands of strong tones.
+fn dynalloc_analysis(band_log_e: &[f32], end: usize, channels: usize, lm: usize,
+                     bytes: usize) -> [i32; BANDS] {
+  let mut offsets = [0i32; BANDS];
+  if bytes <= 50 {
+    return offsets;
+  }
+  // The noise floor takes the mean energy, the width of the band, and the
+  // pre-emphasis into account, for input of 24 bits.
+  let mut noise_floor = [0f32; BANDS];
+  for i in 0..end {
+    noise_floor[i] = 0.0625 * LOG_N[i] as f32 + 0.5 + (9 - 24) as f32 - E_MEANS[i]
+                     + 0.0062 * ((i + 5) * (i + 5)) as f32;
+  }
+
+  let mut follower = [0f32; 2 * BANDS];
+  for c in 0..channels {
+    let e = &band_log_e[c * BANDS .. c * BANDS + end];
+    let f = &mut follower[c * BANDS .. c * BANDS + end];
+    // Only bands up to the last one at least 3 dB above the band before it
+    // are followed downwards, so band-limited signals are not boosted.
+    let mut last = 0;
+    f[0] = e[0];
+    for i in 1..end {
+      if e[i] > e[i - 1] + 0.5 {
+        last = i;
+      }
+      f[i] = (f[i - 1] + 1.5).min(e[i]);
+    }
+    for i in (0..last).rev() {
+      f[i] = f[i].min((f[i + 1] + 2f32).min(e[i]));
+    }
+    // Combines with a median filter, so single bands do not trigger boosts.
+    for i in 2 .. end - 2 {
+      f[i] = f[i].max(median(&e[i - 2 .. i + 3]) - 1f32);
+    }
+    let low = median(&e[..3]) - 1f32;
+    f[0] = f[0].max(low);
+    f[1] = f[1].max(low);
+    let high = median(&e[end - 3 ..]) - 1f32;
+    f[end - 2] = f[end - 2].max(high);
+    f[end - 1] = f[end - 1].max(high);
+    for i in 0..end {
+      f[i] = f[i].max(noise_floor[i]);
+    }
+  }
+  for i in 0..end {
+    follower[i] =
+      if channels == 2 {
+        // Considers 24 dB of cross-talk between the channels.
+        let right = follower[BANDS + i].max(follower[i] - 4f32);
+        let left  = follower[i].max(follower[BANDS + i] - 4f32);
+        0.5 * ((band_log_e[i] - left).max(0f32) + (band_log_e[BANDS + i] - right).max(0f32))
+      } else {
+        (band_log_e[i] - follower[i]).max(0f32)
+      };
+  }
+
+  // Boosts less at a constant bitrate, and never with more than two thirds
+  // of the frame.
+  let cap = ((2 * bytes / 3) << BITRES << 3) as i32;
+  let mut total_boost = 0;
+  for i in 0..end {
+    let mut f = 0.5 * follower[i];
+    if i < 8 {
+      f *= 2f32;
+    }
+    if i >= 12 {
+      f *= 0.5;
+    }
+    let width = ((channels * (EBANDS[i + 1] - EBANDS[i])) << lm) as i32;
+    let (boost, boost_bits) =
+      if width < 6 {
+        let boost = f as i32;
+        (boost, boost * width << BITRES)
+      } else if width > 48 {
+        let boost = (f * 8f32) as i32;
+        (boost, (boost * width << BITRES) / 8)
+      } else {
+        let boost = (f * width as f32 / 6f32) as i32;
+        (boost, boost * 6 << BITRES)
+      };
+    if total_boost + boost_bits > cap {
+      break;
+    }
+    offsets[i] = boost;
+    total_boost += boost_bits;
+  }
+  offsets
+}
+
+/// Returns the allocation trim, which tilts the allocation towards lower
+/// bands at low bitrates and for spectra falling off towards high bands.
+fn alloc_trim_analysis(band_log_e: &[f32], end: usize, channels: usize, bytes: usize) -> usize {
+  let rate = bytes as i32 * 8 * 50;
+  let mut trim =
+    if rate < 64000 { 4f32 }
+    else if rate < 80000 { 4f32 + ((rate - 64000) >> 10) as f32 / 16f32 }
+    else { 5f32 };
+  let mut diff = 0f32;
+  for c in 0..channels {
+    for i in 0 .. end - 1 {
+      diff += band_log_e[c * BANDS + i] * (2 + 2 * i as i32 - end as i32) as f32;
+    }
+  }
+  diff /= (channels * (end - 1)) as f32;
+  trim -= ((diff + 1f32) / 6f32).max(-2f32).min(2f32);
+  ((trim + 0.5).floor().max(0f32) as usize).min(10)
+}
+
+/// Returns the median of a few values.
+fn median(values: &[f32]) -> f32 {
+  let mut sorted = values.to_vec();
+  sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+  sorted[sorted.len() / 2]
+}
+
+/// Decoder of the CELT frames of a stream.
+pub struct CeltDecoder {
+  /// Number of output channels
+  channels:             usize,
+  mdct:                 Mdct,
+  window:               Vec<f32>,
+  decode_mem:           Vec<Vec<f32>>,
+  old_band_e:           [f32; 2 * BANDS],
+  old_log_e:            [f32; 2 * BANDS],
+  old_log_e2:           [f32; 2 * BANDS],
+  postfilter_period:    usize,
+  postfilter_period_old: usize,
+  postfilter_gain:      f32,
+  postfilter_gain_old:  f32,
+  postfilter_tapset:    usize,
+  postfilter_tapset_old: usize,
+  preemph_mem:          [f32; 2],
+  rng:                  u32
+}
+
+impl CeltDecoder {
+  pub fn new(channels: usize) -> CeltDecoder {
+    CeltDecoder {
+      channels:              channels,
+      mdct:                  Mdct::new(),
+      window:                window(),
+      decode_mem:            vec![vec![0f32; DECODE_BUFFER_SIZE + OVERLAP]; channels],
+      old_band_e:            [0f32; 2 * BANDS],
+      old_log_e:             [-28f32; 2 * BANDS],
+      old_log_e2:            [-28f32; 2 * BANDS],
+      postfilter_period:     0,
+      postfilter_period_old: 0,
+      postfilter_gain:       0f32,
+      postfilter_gain_old:   0f32,
+      postfilter_tapset:     0,
+      postfilter_tapset_old: 0,
+      preemph_mem:           [0f32; 2],
+      rng:                   0
+    }
+  }
+
+  /// Resets the state, as when the previous frame was coded in another
+  /// mode.
+  pub fn reset(&mut self) {
+    *self = CeltDecoder::new(self.channels);
+  }
+
+  /// Decodes a frame of `120 << lm` samples coded in `len` bytes with
+  /// `stream_channels` channels from the band `start` up to the band `end`,
+  /// appending the interleaved samples to the output. The coder may be
+  /// shared with SILK in hybrid frames, which code the bands from 17. Frames
+  /// of no more than a byte signal a lost frame, which is decoded as
+  /// silence.
+  pub fn decode(&mut self, coder: &mut RangeCoder, len: usize, stream_channels: usize,
+                lm: usize, start: usize, end: usize, output: &mut Vec<f32>) {
+    let c_count  = stream_channels;
+    let cc_count = self.channels;
+    let n = SHORT_BLOCK_SIZE << lm;
+    let len = if len <= 1 { 0 } else { len as i32 };
+
+    if c_count == 1 {
+      for i in 0..BANDS {
+        self.old_band_e[i] = self.old_band_e[i].max(self.old_band_e[BANDS + i]);
+      }
+    }
+
+    let mut total_bits = len * 8;
+    let mut tell = coder.tell();
+    let silence =
+      if tell >= total_bits { true }
+      else if tell == 1 { coder.decode_bit_logp(15) }
+      else { false };
+    if silence {
+      coder.skip_to_end();
+      tell = len * 8;
+    }
+
+    let mut postfilter_gain   = 0f32;
+    let mut postfilter_pitch  = 0;
+    let mut postfilter_tapset = 0;
+    if start == 0 && tell + 16 <= total_bits {
+      if coder.decode_bit_logp(1) {
+        let octave = coder.decode_uint(6);
+        postfilter_pitch = ((16 << octave) + coder.decode_bits(4 + octave) - 1) as usize;
+        let qg = coder.decode_bits(3);
+        if coder.tell() + 2 <= total_bits {
+          postfilter_tapset = coder.decode_icdf(&TAPSET_ICDF, 2);
+        }
+        postfilter_gain = 0.09375 * (qg + 1) as f32;
+      }
+      tell = coder.tell();
+    }
+
+    let transient =
+      if lm > 0 && tell + 3 <= total_bits {
+        let transient = coder.decode_bit_logp(3);
+        tell = coder.tell();
+        transient
+      } else {
+        false
+      };
+    let intra = if tell + 3 <= total_bits { coder.decode_bit_logp(3) } else { false };
+    energy::unquant_coarse_energy(start, end, &mut self.old_band_e, intra, coder,
+                                  c_count, lm);
+
+    let mut tf_res = [0i32; BANDS];
+    tf_decode(start, end, transient, &mut tf_res, lm, coder);
+
+    let spread =
+      if coder.tell() + 4 <= total_bits { coder.decode_icdf(&SPREAD_ICDF, 5) }
+      else { SPREAD_NORMAL };
+
+    // Decodes the boosts of the bands.
+    let caps = rate::init_caps(lm, c_count);
+    let mut offsets = [0i32; BANDS];
+    let mut dynalloc_logp = 6;
+    total_bits <<= BITRES;
+    let mut tell = coder.tell_frac();
+    for i in start..end {
+      let width = (c_count * (EBANDS[i + 1] - EBANDS[i]) << lm) as i32;
+      // Quanta of 6 bits, but no more than a bit and no less than an eighth
+      // of a bit for each value.
+      let quanta = (width << BITRES).min((6 << BITRES).max(width));
+      let mut loop_logp = dynalloc_logp;
+      let mut boost = 0;
+      while tell + ((loop_logp as i32) << BITRES) < total_bits && boost < caps[i] {
+        let flag = coder.decode_bit_logp(loop_logp);
+        tell = coder.tell_frac();
+        if !flag {
+          break;
+        }
+        boost += quanta;
+        total_bits -= quanta;
+        loop_logp = 1;
+      }
+      offsets[i] = boost;
+      if boost > 0 {
+        dynalloc_logp = 2.max(dynalloc_logp - 1);
+      }
+    }
+
+    let alloc_trim =
+      if tell + (6 << BITRES) <= total_bits { coder.decode_icdf(&TRIM_ICDF, 7) as i32 }
+      else { 5 };
+
+    let mut bits = ((len * 8) << BITRES) - coder.tell_frac() - 1;
+    let anti_collapse_rsv =
+      if transient && lm >= 2 && bits >= (lm as i32 + 2) << BITRES { 1 << BITRES } else { 0 };
+    bits -= anti_collapse_rsv;
+    let alloc = rate::compute_allocation(start, end, &offsets, &caps, alloc_trim, 0, false,
+                                         bits, c_count, lm, coder, false, 0, 0);
+    energy::unquant_fine_energy(start, end, &mut self.old_band_e, &alloc.fine_quant,
+                                coder, c_count);
+
+    for mem in self.decode_mem.iter_mut() {
+      mem.copy_within(n .. DECODE_BUFFER_SIZE + OVERLAP / 2, 0);
+    }
+
+    // Decodes the shapes of the bands.
+    let mut x = vec![0f32; c_count * n];
+    let mut collapse_masks = [0u8; 2 * BANDS];
+    {
+      let (x0, x1) = x.split_at_mut(n);
+      self.rng = bands::quant_all_bands(false, start, end, x0,
+                                        if c_count == 2 { Some(x1) } else { None },
+                                        &mut collapse_masks, &[], &alloc.pulses, transient,
+                                        spread, alloc.dual_stereo, alloc.intensity, &tf_res,
+                                        len * (8 << BITRES) - anti_collapse_rsv, alloc.balance,
+                                        coder, lm, alloc.coded_bands, self.rng,
+                                        self.channels == 1);
+    }
+    let anti_collapse_on = anti_collapse_rsv > 0 && coder.decode_bits(1) == 1;
+    let bits_left = len * 8 - coder.tell();
+    energy::unquant_energy_finalise(start, end, &mut self.old_band_e, &alloc.fine_quant,
+                                    &alloc.fine_priority, bits_left, coder, c_count);
+    if anti_collapse_on {
+      bands::anti_collapse(&mut x, &collapse_masks, lm, c_count, n, start, end,
+                           &self.old_band_e, &self.old_log_e, &self.old_log_e2, &alloc.pulses,
+                           self.rng);
+    }
+    if silence {
+      for e in self.old_band_e[.. c_count * BANDS].iter_mut() {
+        *e = -28f32;
+      }
+    }
+
+    self.synthesize(&x, c_count, start, end, transient, lm, silence);
+
+    // Applies the pitch post-filter, changing from the previous filter over
+    // the first short block.
+    let syn = DECODE_BUFFER_SIZE - n;
+    self.postfilter_period     = self.postfilter_period.max(COMBFILTER_MINPERIOD);
+    self.postfilter_period_old = self.postfilter_period_old.max(COMBFILTER_MINPERIOD);
+    for mem in self.decode_mem.iter_mut() {
+      comb_filter(mem, syn, self.postfilter_period_old, self.postfilter_period,
+                  SHORT_BLOCK_SIZE, self.postfilter_gain_old, self.postfilter_gain,
+                  self.postfilter_tapset_old, self.postfilter_tapset, &self.window, OVERLAP);
+      if lm != 0 {
+        comb_filter(mem, syn + SHORT_BLOCK_SIZE, self.postfilter_period, postfilter_pitch,
+                    n - SHORT_BLOCK_SIZE, self.postfilter_gain, postfilter_gain,
+                    self.postfilter_tapset, postfilter_tapset, &self.window, OVERLAP);
+      }
+    }
+    self.postfilter_period_old = self.postfilter_period;
+    self.postfilter_gain_old   = self.postfilter_gain;
+    self.postfilter_tapset_old = self.postfilter_tapset;
+    self.postfilter_period = postfilter_pitch;
+    self.postfilter_gain   = postfilter_gain;
+    self.postfilter_tapset = postfilter_tapset;
+    if lm != 0 {
+      self.postfilter_period_old = self.postfilter_period;
+      self.postfilter_gain_old   = self.postfilter_gain;
+      self.postfilter_tapset_old = self.postfilter_tapset;
+    }
+
+    // Updates the energies used to predict the next frame.
+    if c_count == 1 {
+      for i in 0..BANDS {
+        self.old_band_e[BANDS + i] = self.old_band_e[i];
+      }
+    }
+    if !transient {
+      self.old_log_e2 = self.old_log_e;
+      self.old_log_e  = self.old_band_e;
+    }
+    else {
+      for i in 0 .. 2 * BANDS {
+        self.old_log_e[i] = self.old_log_e[i].min(self.old_band_e[i]);
+      }
+    }
+    for c in 0..2 {
+      for i in 0..start {
+        self.old_band_e[c * BANDS + i] = 0f32;
+        self.old_log_e[c * BANDS + i]  = -28f32;
+        self.old_log_e2[c * BANDS + i] = -28f32;
+      }
+      for i in end..BANDS {
+        self.old_band_e[c * BANDS + i] = 0f32;
+        self.old_log_e[c * BANDS + i]  = -28f32;
+        self.old_log_e2[c * BANDS + i] = -28f32;
+      }
+    }
+    self.rng = coder.range();
+
+    // Removes the pre-emphasis.
+    let first = output.len();
+    output.resize(first + n * cc_count, 0f32);
+    for c in 0..cc_count {
+      let mut mem = self.preemph_mem[c];
+      for j in 0..n {
+        let tmp = self.decode_mem[c][syn + j] + VERY_SMALL + mem;
+        mem = PREEMPHASIS * tmp;
+        output[first + j * cc_count + c] = tmp * (1f32 / SIGNAL_SCALE);
+      }
+      self.preemph_mem[c] = mem;
+    }
+  }
+
+  /// Computes the inverse MDCT of the decoded bands into the end of the
+  /// decoder buffer of each channel.
+  fn synthesize(&mut self, x: &[f32], c_count: usize, start: usize, end: usize,
+                transient: bool, lm: usize, silence: bool) {
+    let m = 1 << lm;
+    let n = SHORT_BLOCK_SIZE << lm;
+    let (blocks, nb, shift) =
+      if transient { (m, SHORT_BLOCK_SIZE, MAX_LM) } else { (1, n, MAX_LM - lm) };
+    let syn = DECODE_BUFFER_SIZE - n;
+    let mut freq = vec![0f32; n];
+    for c in 0..self.channels {
+      if c_count == 2 && self.channels == 1 {
+        // Downmixes a stereo stream to mono.
+        let mut freq2 = vec![0f32; n];
+        bands::denormalise_bands(&x[..n], &mut freq, &self.old_band_e, start, end, m, silence);
+        bands::denormalise_bands(&x[n..], &mut freq2, &self.old_band_e[BANDS..], start, end,
+                                 m, silence);
+        for i in 0..n {
+          freq[i] = 0.5 * freq[i] + 0.5 * freq2[i];
+        }
+      }
+      else {
+        // Copies a mono stream to each channel.
+        let k = c.min(c_count - 1);
+        bands::denormalise_bands(&x[k * n .. (k + 1) * n], &mut freq,
+                                 &self.old_band_e[k * BANDS..], start, end, m, silence);
+      }
+      for b in 0..blocks {
+        self.mdct.backward(&freq[b..], &mut self.decode_mem[c][syn + nb * b ..], &self.window,
+                           OVERLAP, shift, blocks);
+      }
+    }
+  }
+}
+
+/// Encoder of the CELT frames of a stream, coding frames of 20 ms.
+pub struct CeltEncoder {
+  channels:         usize,
+  mdct:             Mdct,
+  window:           Vec<f32>,
+  /// End of the input of the previous frame of each channel
+  in_mem:           Vec<f32>,
+  preemph_mem:      [f32; 2],
+  old_band_e:       [f32; 2 * BANDS],
+  last_coded_bands: usize,
+  first_frame:      bool
+}
+
+/// Number of samples of each channel in a frame coded by the encoder.
+pub const ENCODER_FRAME_SIZE: usize = FRAME_SIZE;
+
+impl CeltEncoder {
+  pub fn new(channels: usize) -> CeltEncoder {
+    CeltEncoder {
+      channels:         channels,
+      mdct:             Mdct::new(),
+      window:           window(),
+      in_mem:           vec![0f32; channels * OVERLAP],
+      preemph_mem:      [0f32; 2],
+      old_band_e:       [0f32; 2 * BANDS],
+      last_coded_bands: 0,
+      first_frame:      true
+    }
+  }
+
+  /// Encodes a frame of interleaved samples into the given number of bytes.
+  pub fn encode(&mut self, pcm: &[f32], bytes: usize) -> Vec<u8> {
+    let channels = self.channels;
+    let lm = MAX_LM;
+    let m = 1 << lm;
+    let n = ENCODER_FRAME_SIZE;
+    let start = 0;
+    let end = BANDS;
+    let total_bits = bytes as i32 * 8;
+    let mut coder = RangeCoder::encoder(bytes);
+
+    // Signals silence when the samples are all below 24-bit resolution.
+    let sample_max = pcm.iter().fold(0f32, |max, s| max.max(s.abs()));
+    let silence = sample_max <= 1f32 / (1 << 24) as f32;
+    coder.encode_bit_logp(silence, 15);
+
+    // Applies the pre-emphasis after the end of the previous frame.
+    let mut input = vec![0f32; channels * (n + OVERLAP)];
+    for c in 0..channels {
+      let buf = &mut input[c * (n + OVERLAP) .. (c + 1) * (n + OVERLAP)];
+      buf[..OVERLAP].copy_from_slice(&self.in_mem[c * OVERLAP .. (c + 1) * OVERLAP]);
+      let mut mem = self.preemph_mem[c];
+      for i in 0..n {
+        let x = pcm[i * channels + c] * SIGNAL_SCALE;
+        buf[OVERLAP + i] = x - mem;
+        mem = PREEMPHASIS * x;
+      }
+      self.preemph_mem[c] = mem;
+      self.in_mem[c * OVERLAP .. (c + 1) * OVERLAP].copy_from_slice(&buf[n..]);
+    }
+
+    if silence {
+      // Counts the rest of the frame as zeros, which the decoder reads as
+      // silence.
+      coder.skip_to_end();
+      for e in self.old_band_e.iter_mut() {
+        *e = -28f32;
+      }
+      self.first_frame = false;
+      return coder.finish();
+    }
+
+    // Post-filter disabled, and long blocks.
+    if coder.tell() + 16 <= total_bits {
+      coder.encode_bit_logp(false, 1);
+    }
+    if coder.tell() + 3 <= total_bits {
+      coder.encode_bit_logp(false, 3);
+    }
+
+    let mut freq = vec![0f32; channels * n];
+    for c in 0..channels {
+      self.mdct.forward(&input[c * (n + OVERLAP) ..], &mut freq[c * n ..], &self.window,
+                        OVERLAP, 0, 1);
+    }
+    let mut band_e     = [0f32; 2 * BANDS];
+    let mut band_log_e = [0f32; 2 * BANDS];
+    bands::compute_band_energies(&freq, &mut band_e, end, channels, lm);
+    energy::amp_to_log2(&band_e, &mut band_log_e, end, channels);
+    let mut x = vec![0f32; channels * n];
+    bands::normalise_bands(&freq, &mut x, &band_e, end, channels, m);
+
+    // Codes the first frame without prediction from the previous frame.
+    let intra = self.first_frame && coder.tell() + 3 <= total_bits;
+    let max_decay = 16f32.min(0.125 * bytes as f32);
+    let mut error = [0f32; 2 * BANDS];
+    energy::quant_coarse_energy(start, end, &band_log_e, &mut self.old_band_e, total_bits,
+                                &mut error, &mut coder, channels, lm, intra, max_decay);
+
+    let mut tf_res = [0i32; BANDS];
+    tf_encode(start, end, &mut tf_res, lm, &mut coder);
+    if coder.tell() + 4 <= total_bits {
+      coder.encode_icdf(SPREAD_NORMAL, &SPREAD_ICDF, 5);
+    }
+
+    // Codes the boosts of the bands, and the allocation trim.
+    let caps = rate::init_caps(lm, channels);
+    let boosts = dynalloc_analysis(&band_log_e, end, channels, lm, bytes);
+    let mut offsets = [0i32; BANDS];
+    let total_bits_frac = total_bits << BITRES;
+    let mut total_boost = 0;
+    let mut dynalloc_logp = 6;
+    let mut tell = coder.tell_frac();
+    for i in start..end {
+      let width = (channels * (EBANDS[i + 1] - EBANDS[i]) << lm) as i32;
+      let quanta = (width << BITRES).min((6 << BITRES).max(width));
+      let mut loop_logp = dynalloc_logp;
+      let mut boost = 0;
+      let mut j = 0;
+      while tell + ((loop_logp as i32) << BITRES) < total_bits_frac - total_boost
+            && boost < caps[i] {
+        let flag = j < boosts[i];
+        coder.encode_bit_logp(flag, loop_logp);
+        tell = coder.tell_frac();
+        if !flag {
+          break;
+        }
+        j += 1;
+        boost += quanta;
+        total_boost += quanta;
+        loop_logp = 1;
+      }
+      if j > 0 {
+        dynalloc_logp = 2.max(dynalloc_logp - 1);
+      }
+      offsets[i] = boost;
+    }
+    let mut alloc_trim = 5;
+    if tell + (6 << BITRES) <= total_bits_frac - total_boost {
+      alloc_trim = alloc_trim_analysis(&band_log_e, end, channels, bytes);
+      coder.encode_icdf(alloc_trim, &TRIM_ICDF, 7);
+    }
+
+    let bits = total_bits_frac - coder.tell_frac() - 1;
+    let alloc = rate::compute_allocation(start, end, &offsets, &caps, alloc_trim as i32, end,
+                                         false, bits, channels, lm, &mut coder, true,
+                                         self.last_coded_bands, end - 1);
+    self.last_coded_bands =
+      if self.last_coded_bands != 0 {
+        (self.last_coded_bands + 1).min((self.last_coded_bands - 1).max(alloc.coded_bands))
+      } else {
+        alloc.coded_bands
+      };
+    energy::quant_fine_energy(start, end, &mut self.old_band_e, &mut error, &alloc.fine_quant,
+                              &mut coder, channels);
+
+    let mut collapse_masks = [0u8; 2 * BANDS];
+    {
+      let (x0, x1) = x.split_at_mut(n);
+      bands::quant_all_bands(true, start, end, x0, if channels == 2 { Some(x1) } else { None },
+                             &mut collapse_masks, &band_e, &alloc.pulses, false, SPREAD_NORMAL,
+                             alloc.dual_stereo, alloc.intensity, &tf_res,
+                             bytes as i32 * (8 << BITRES), alloc.balance, &mut coder, lm,
+                             alloc.coded_bands, 0, false);
+    }
+    let bits_left = total_bits - coder.tell();
+    energy::quant_energy_finalise(start, end, &mut self.old_band_e, &mut error,
+                                  &alloc.fine_quant, &alloc.fine_priority, bits_left, &mut coder,
+                                  channels);
+    self.first_frame = false;
+    coder.finish()
+  }
+}
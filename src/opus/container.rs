@@ -0,0 +1,433 @@
+use std::io::{Read, Seek, Write};
+use byteorder::{ByteOrder, LittleEndian};
+use buffer::*;
+use codecs::Codec;
+use dsp::resample::{convert, ResampleQuality};
+use error::*;
+use metadata::Metadata;
+use ogg::comments;
+use ogg::page::{PageWriter, read_packets};
+use opus::{OPUS_HEAD, OPUS_TAGS, SAMPLE_RATE, SERIAL, VENDOR};
+use opus::celt::{CeltEncoder, ENCODER_FRAME_SIZE};
+use opus::packet::{self, Packet};
+use opus::stream::StreamDecoder;
+use sample::*;
+use traits::Container;
+
+/// Number of bytes of each frame of a mono stream written by the encoder,
+/// for a bitrate of 48 kbit/s.
+const MONO_FRAME_BYTES: usize = 120;
+
+/// Number of bytes of each frame of a stereo stream written by the encoder,
+/// for a bitrate of 80 kbit/s.
+const STEREO_FRAME_BYTES: usize = 200;
+
+/// Table of contents of the frames written by the encoder, for fullband
+/// CELT frames of 20 ms.
+const ENCODER_TOC: u8 = 31 << 3;
+
+/// Number of frames at the start of the decoded audio written by the
+/// encoder to be discarded, which is the delay of the MDCT overlap.
+const ENCODER_PRE_SKIP: usize = 120;
+
+/// Struct containing all necessary information for decoding an Ogg Opus
+/// stream to an `AudioBuffer`.
+pub struct OpusContainer {
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for OpusContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<OpusContainer> {
+    let packets = try!(read_packets(reader));
+    if packets.len() < 2 {
+      return Err(AudioError::Format(
+        "File is not valid Ogg Opus (Missing header packets)".to_string()
+      ));
+    }
+    let head = try!(OpusHead::read(&packets[0].data));
+    if packets[1].data.len() < 8 || &packets[1].data[0..8] != OPUS_TAGS {
+      return Err(AudioError::Format(
+        "File is not valid Ogg Opus (Missing OpusTags packet)".to_string()
+      ));
+    }
+    let comments = try!(comments::read(&packets[1].data[8..]));
+    let channels = head.channels as usize;
+
+    // As with Vorbis, the granule positions give the frames to discard at
+    // the start and keep at the end of the stream, with the pre-skip
+    // discarded in addition. A stream with a single page of audio starts at
+    // zero, as its granule position only trims the end.
+    let mut decoder = MultistreamDecoder::new(&head);
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut start: Option<i64> = None;
+    let mut end:   Option<i64> = None;
+    let mut pages = 0;
+    for packet in packets[2..].iter() {
+      try!(decoder.decode(&packet.data, &mut samples));
+      if let Some(granule_position) = packet.granule_position {
+        if granule_position >= 0 {
+          if start.is_none() {
+            start = Some(granule_position - (samples.len() / channels) as i64);
+          }
+          end = Some(granule_position);
+          pages += 1;
+        }
+      }
+    }
+    let start = if pages > 1 { start.unwrap_or(0) } else { 0 };
+    let first = ((head.pre_skip as i64 - start).max(0) as usize).min(samples.len() / channels);
+    let last  =
+      match end {
+        Some(end) => ((end - start).max(0) as usize).min(samples.len() / channels),
+        None      => samples.len() / channels
+      };
+    let mut samples: Vec<Sample> =
+      if first < last {
+        let mut samples = samples;
+        samples.truncate(last * channels);
+        samples.drain(.. first * channels);
+        reorder_channels(&samples, channels, false)
+      } else {
+        Vec::new()
+      };
+    if head.output_gain != 0 {
+      let gain = 10f32.powf(head.output_gain as f32 / (20f32 * 256f32));
+      for sample in samples.iter_mut() {
+        *sample *= gain;
+      }
+    }
+
+    let mut metadata = Metadata::default();
+    metadata.info = comments;
+    Ok(OpusContainer {
+      sample_rate:  SAMPLE_RATE,
+      channels:     channels as u32,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:      samples,
+      metadata:     metadata
+    })
+  }
+  fn create<W: Write>(_: &mut W, _: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    Err(AudioError::Unsupported(
+      format!("Ogg Opus does not support the {:?} codec", codec)
+    ))
+  }
+}
+
+impl OpusContainer {
+  /// Encodes the audio to Ogg Opus, after converting it to 48 kHz.
+  pub fn write<W: Write>(writer: &mut W, audio: &AudioBuffer) -> AudioResult<()> {
+    let channels = audio.channels as usize;
+    let head = try!(OpusHead::new(audio.channels, audio.sample_rate));
+    let samples = try!(convert(&audio.samples, audio.channels, audio.sample_rate,
+                               SAMPLE_RATE, ResampleQuality::Medium));
+    let samples = reorder_channels(&samples, channels, true);
+    let frames = samples.len() / channels;
+
+    let mut pages = PageWriter::new(SERIAL);
+    try!(pages.write_packet(writer, &head.to_bytes(), 0, true, false));
+    let mut tags: Vec<u8> = OPUS_TAGS.to_vec();
+    tags.extend_from_slice(&comments::write(VENDOR, &audio.metadata.info));
+    try!(pages.write_packet(writer, &tags, 0, true, false));
+
+    // Codes enough frames to decode the last sample after the pre-skip.
+    let mut encoder = MultistreamEncoder::new(&head);
+    let count = (frames + ENCODER_PRE_SKIP + ENCODER_FRAME_SIZE - 1) / ENCODER_FRAME_SIZE;
+    let mut frame: Vec<f32> = vec![0f32; ENCODER_FRAME_SIZE * channels];
+    for i in 0..count {
+      let first = i * ENCODER_FRAME_SIZE;
+      let last  = ((i + 1) * ENCODER_FRAME_SIZE).min(frames);
+      for sample in frame.iter_mut() {
+        *sample = 0f32;
+      }
+      if first < last {
+        frame[.. (last - first) * channels]
+          .copy_from_slice(&samples[first * channels .. last * channels]);
+      }
+      let data = encoder.encode(&frame);
+      let end_of_stream = i + 1 == count;
+      let granule_position =
+        if end_of_stream {
+          (frames + ENCODER_PRE_SKIP) as i64
+        } else {
+          ((i + 1) * ENCODER_FRAME_SIZE) as i64
+        };
+      try!(pages.write_packet(writer, &data, granule_position, false, end_of_stream));
+    }
+    Ok(())
+  }
+}
+
+/// The identification header of an Ogg Opus stream.
+struct OpusHead {
+  channels:       u8,
+  pre_skip:       u16,
+  sample_rate:    u32,
+  /// Gain to apply to the decoded audio, in 1/256 dB
+  output_gain:    i16,
+  mapping_family: u8,
+  streams:        usize,
+  coupled:        usize,
+  /// Index of the decoded channel of each output channel, where 255 is a
+  /// silent channel
+  mapping:        Vec<u8>
+}
+
+impl OpusHead {
+  /// Returns the header for encoding audio, choosing the channel mapping of
+  /// the channels.
+  fn new(channels: u32, sample_rate: u32) -> AudioResult<OpusHead> {
+    let (family, streams, coupled, mapping): (u8, usize, usize, &[u8]) =
+      match channels {
+        1 => (0, 1, 0, &[0]),
+        2 => (0, 1, 1, &[0, 1]),
+        3 => (1, 2, 1, &[0, 2, 1]),
+        4 => (1, 2, 2, &[0, 1, 2, 3]),
+        5 => (1, 3, 2, &[0, 4, 1, 2, 3]),
+        6 => (1, 4, 2, &[0, 4, 1, 2, 3, 5]),
+        7 => (1, 4, 3, &[0, 4, 1, 2, 3, 5, 6]),
+        8 => (1, 5, 3, &[0, 6, 1, 2, 3, 4, 5, 7]),
+        c @ _ => return Err(AudioError::Unsupported(
+          format!("Ogg Opus does not support {} channels", c)
+        ))
+      };
+    Ok(OpusHead {
+      channels:       channels as u8,
+      pre_skip:       ENCODER_PRE_SKIP as u16,
+      sample_rate:    sample_rate,
+      output_gain:    0,
+      mapping_family: family,
+      streams:        streams,
+      coupled:        coupled,
+      mapping:        mapping.to_vec()
+    })
+  }
+
+  fn read(data: &[u8]) -> AudioResult<OpusHead> {
+    if data.len() < 19 || &data[0..8] != OPUS_HEAD {
+      return Err(AudioError::Format(
+        "File is not valid Ogg Opus (Missing OpusHead packet)".to_string()
+      ));
+    }
+    // Versions with the same major version are compatible.
+    if data[8] >> 4 != 0 {
+      return Err(AudioError::Unsupported(
+        format!("Unsupported Ogg Opus version {}", data[8])
+      ));
+    }
+    let channels = data[9];
+    let mapping_family = data[18];
+    let (streams, coupled, mapping) =
+      match mapping_family {
+        0 => {
+          if channels < 1 || channels > 2 {
+            return Err(AudioError::Format(
+              format!("File is not valid Ogg Opus ({} channels without a mapping)", channels)
+            ));
+          }
+          (1, channels as usize - 1, vec![0, 1][.. channels as usize].to_vec())
+        },
+        1 => {
+          if channels < 1 || channels > 8 {
+            return Err(AudioError::Format(
+              format!("File is not valid Ogg Opus ({} channels in Vorbis order)", channels)
+            ));
+          }
+          if data.len() < 21 + channels as usize {
+            return Err(AudioError::Format(
+              "File is not valid Ogg Opus (Missing channel mapping)".to_string()
+            ));
+          }
+          let streams = data[19] as usize;
+          let coupled = data[20] as usize;
+          let mapping = data[21 .. 21 + channels as usize].to_vec();
+          if streams == 0 || coupled > streams ||
+             mapping.iter().any(|m| *m != 255 && *m as usize >= streams + coupled) {
+            return Err(AudioError::Format(
+              "File is not valid Ogg Opus (Invalid channel mapping)".to_string()
+            ));
+          }
+          (streams, coupled, mapping)
+        },
+        f @ _ => return Err(AudioError::Unsupported(
+          format!("Unsupported Ogg Opus channel mapping family {}", f)
+        ))
+      };
+    Ok(OpusHead {
+      channels:       channels,
+      pre_skip:       LittleEndian::read_u16(&data[10..12]),
+      sample_rate:    LittleEndian::read_u32(&data[12..16]),
+      output_gain:    LittleEndian::read_i16(&data[16..18]),
+      mapping_family: mapping_family,
+      streams:        streams,
+      coupled:        coupled,
+      mapping:        mapping
+    })
+  }
+
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut data: Vec<u8> = vec![0u8; 19];
+    data[0..8].copy_from_slice(OPUS_HEAD);
+    data[8] = 1;
+    data[9] = self.channels;
+    LittleEndian::write_u16(&mut data[10..12], self.pre_skip);
+    LittleEndian::write_u32(&mut data[12..16], self.sample_rate);
+    LittleEndian::write_i16(&mut data[16..18], self.output_gain);
+    data[18] = self.mapping_family;
+    if self.mapping_family != 0 {
+      data.push(self.streams as u8);
+      data.push(self.coupled as u8);
+      data.extend_from_slice(&self.mapping);
+    }
+    data
+  }
+}
+
+/// Decoder of the streams of a packet, with a stereo stream for each coupled
+/// pair of channels followed by a mono stream for each other channel.
+struct MultistreamDecoder {
+  channels: usize,
+  coupled:  usize,
+  mapping:  Vec<u8>,
+  decoders: Vec<StreamDecoder>
+}
+
+impl MultistreamDecoder {
+  fn new(head: &OpusHead) -> MultistreamDecoder {
+    MultistreamDecoder {
+      channels: head.channels as usize,
+      coupled:  head.coupled,
+      mapping:  head.mapping.clone(),
+      decoders: (0..head.streams).map(|s|
+        StreamDecoder::new(if s < head.coupled { 2 } else { 1 })
+      ).collect()
+    }
+  }
+
+  /// Decodes a packet, appending the interleaved samples of each channel.
+  fn decode(&mut self, data: &[u8], samples: &mut Vec<Sample>) -> AudioResult<()> {
+    let streams = self.decoders.len();
+    let mut decoded: Vec<Vec<f32>> = Vec::with_capacity(streams);
+    let mut offset = 0;
+    for s in 0..streams {
+      let (packet, len) = try!(Packet::parse(&data[offset..], s + 1 < streams));
+      offset += len;
+      let mut output: Vec<f32> = Vec::new();
+      self.decoders[s].decode(&packet, &mut output);
+      decoded.push(output);
+    }
+    let frames = decoded[0].len() / if self.coupled > 0 { 2 } else { 1 };
+    for (s, output) in decoded.iter().enumerate() {
+      if output.len() != frames * if s < self.coupled { 2 } else { 1 } {
+        return Err(AudioError::Format(
+          "File is not valid Ogg Opus (Streams of a packet differ in duration)".to_string()
+        ));
+      }
+    }
+    // Maps the decoded channels to the output channels.
+    let first = samples.len();
+    samples.resize(first + frames * self.channels, 0f32);
+    for (c, index) in self.mapping.iter().enumerate() {
+      let index = *index as usize;
+      if index == 255 {
+        continue;
+      }
+      let (output, stride, channel) =
+        if index < 2 * self.coupled {
+          (&decoded[index / 2], 2, index % 2)
+        } else {
+          (&decoded[index - self.coupled], 1, 0)
+        };
+      for i in 0..frames {
+        samples[first + i * self.channels + c] = output[i * stride + channel];
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Encoder of the streams of each packet.
+struct MultistreamEncoder {
+  channels: usize,
+  coupled:  usize,
+  mapping:  Vec<u8>,
+  encoders: Vec<CeltEncoder>
+}
+
+impl MultistreamEncoder {
+  fn new(head: &OpusHead) -> MultistreamEncoder {
+    MultistreamEncoder {
+      channels: head.channels as usize,
+      coupled:  head.coupled,
+      mapping:  head.mapping.clone(),
+      encoders: (0..head.streams).map(|s|
+        CeltEncoder::new(if s < head.coupled { 2 } else { 1 })
+      ).collect()
+    }
+  }
+
+  /// Encodes a frame of interleaved samples into a packet.
+  fn encode(&mut self, frame: &[f32]) -> Vec<u8> {
+    let frames = frame.len() / self.channels;
+    let streams = self.encoders.len();
+    let mut data: Vec<u8> = Vec::new();
+    for s in 0..streams {
+      let stereo = s < self.coupled;
+      let stream_channels = if stereo { 2 } else { 1 };
+      let mut input: Vec<f32> = vec![0f32; frames * stream_channels];
+      for (c, index) in self.mapping.iter().enumerate() {
+        let index = *index as usize;
+        let channel =
+          if stereo && index / 2 == s && index < 2 * self.coupled { index % 2 }
+          else if !stereo && index == s + self.coupled { 0 }
+          else { continue };
+        for i in 0..frames {
+          input[i * stream_channels + channel] = frame[i * self.channels + c];
+        }
+      }
+      let bytes = if stereo { STEREO_FRAME_BYTES } else { MONO_FRAME_BYTES };
+      let celt = self.encoders[s].encode(&input, bytes);
+      let toc = ENCODER_TOC | if stereo { 0x04 } else { 0 };
+      packet::write(toc, &celt, s + 1 < streams, &mut data);
+    }
+    data
+  }
+}
+
+// Private functions
+
+/// Reorders the channels of interleaved samples from the Vorbis channel
+/// order of mapping family 1 into the order used by WAVE files, or back
+/// when `to_vorbis` is set.
+fn reorder_channels(samples: &[Sample], channels: usize, to_vorbis: bool) -> Vec<Sample> {
+  let order: &[usize] =
+    match channels {
+      3 => &[0, 2, 1],
+      5 => &[0, 2, 1, 3, 4],
+      6 => &[0, 2, 1, 5, 3, 4],
+      7 => &[0, 2, 1, 6, 5, 3, 4],
+      8 => &[0, 2, 1, 7, 5, 6, 3, 4],
+      _ => return samples.to_vec()
+    };
+  let mut reordered: Vec<Sample> = vec![0f32; samples.len()];
+  for (frame, out) in samples.chunks(channels).zip(reordered.chunks_mut(channels)) {
+    for (i, j) in order.iter().enumerate() {
+      if to_vorbis {
+        out[*j] = frame[i];
+      }
+      else {
+        out[i] = frame[*j];
+      }
+    }
+  }
+  reordered
+}
@@ -0,0 +1,149 @@
+//! Pulse Vector Codewords
+//!
+//! The shape of a band is a vector of `n` integers whose absolute values sum
+//! to `k` pulses. Each such vector is coded as its index among all vectors,
+//! which are counted using the row `n` of the table `U(n, k)`, the number of
+//! vectors whose first value is not zero.
+
+/// Computes the next row of a table following the recurrence
+/// `u[i][j] = u[i-1][j] + u[i][j-1] + u[i-1][j-1]`, whose first value is
+/// `u0`.
+fn unext(u: &mut [u32], u0: u32) {
+  let mut u0 = u0;
+  for j in 1..u.len() {
+    let u1 = u[j].wrapping_add(u[j - 1]).wrapping_add(u0);
+    u[j - 1] = u0;
+    u0 = u1;
+  }
+  let last = u.len() - 1;
+  u[last] = u0;
+}
+
+/// Computes the previous row of a table following the same recurrence as
+/// `unext`.
+fn uprev(u: &mut [u32], u0: u32) {
+  let mut u0 = u0;
+  for j in 1..u.len() {
+    let u1 = u[j].wrapping_sub(u[j - 1]).wrapping_sub(u0);
+    u[j - 1] = u0;
+    u0 = u1;
+  }
+  let last = u.len() - 1;
+  u[last] = u0;
+}
+
+/// Fills `u` with `U(n, 0..k + 2)`, returning the number of vectors
+/// `V(n, k)`.
+fn ncwrs_urow(n: usize, k: usize, u: &mut [u32]) -> u32 {
+  debug_assert!(n >= 2 && k > 0);
+  u[0] = 0;
+  u[1] = 1;
+  for i in 2 .. k + 2 {
+    u[i] = ((i as u32) << 1) - 1;
+  }
+  for _ in 2..n {
+    unext(&mut u[1 .. k + 2], 1);
+  }
+  u[k].wrapping_add(u[k + 1])
+}
+
+/// Returns the vector of the given index, along with its squared norm.
+fn cwrsi(n: usize, k: usize, index: u32, y: &mut [i32], u: &mut [u32]) -> f32 {
+  let mut k = k;
+  let mut i = index;
+  let mut yy = 0f32;
+  for j in 0..n {
+    let mut p = u[k + 1];
+    let negative = i >= p;
+    if negative {
+      i -= p;
+    }
+    let yj = k;
+    p = u[k];
+    while p > i {
+      k -= 1;
+      p = u[k];
+    }
+    i -= p;
+    let value = (yj - k) as i32;
+    y[j] = if negative { -value } else { value };
+    yy += (value * value) as f32;
+    uprev(&mut u[.. k + 2], 0);
+  }
+  yy
+}
+
+/// Returns the index of a vector, along with the number of vectors.
+fn icwrs(n: usize, k: usize, y: &[i32], u: &mut [u32]) -> (u32, u32) {
+  debug_assert!(n >= 2);
+  u[0] = 0;
+  for i in 1 .. k + 2 {
+    u[i] = ((i as u32) << 1) - 1;
+  }
+  let mut index = (y[n - 1] < 0) as u32;
+  let mut pulses = y[n - 1].abs() as usize;
+  let mut j = n - 2;
+  index = index.wrapping_add(u[pulses]);
+  pulses += y[j].abs() as usize;
+  if y[j] < 0 {
+    index = index.wrapping_add(u[pulses + 1]);
+  }
+  while j > 0 {
+    j -= 1;
+    unext(&mut u[.. k + 2], 0);
+    index = index.wrapping_add(u[pulses]);
+    pulses += y[j].abs() as usize;
+    if y[j] < 0 {
+      index = index.wrapping_add(u[pulses + 1]);
+    }
+  }
+  (index, u[pulses].wrapping_add(u[pulses + 1]))
+}
+
+/// Returns the number of vectors of `n` values with `k` pulses.
+pub fn count(n: usize, k: usize) -> u32 {
+  let mut u = vec![0u32; k + 2];
+  ncwrs_urow(n, k, &mut u)
+}
+
+/// Returns the index of a vector of `n` values with `k` pulses.
+pub fn index(y: &[i32], n: usize, k: usize) -> u32 {
+  let mut u = vec![0u32; k + 2];
+  icwrs(n, k, y, &mut u).0
+}
+
+/// Fills `y` with the vector of the given index, returning its squared
+/// norm.
+pub fn vector(index: u32, y: &mut [i32], n: usize, k: usize) -> f32 {
+  let mut u = vec![0u32; k + 2];
+  ncwrs_urow(n, k, &mut u);
+  cwrsi(n, k, index, y, &mut u)
+}
+
+#[cfg(test)]
+mod codewords {
+  use super::*;
+
+  #[test]
+  fn counts() {
+    // V(n, k) is the number of points of the lattice with an L1 norm of k.
+    assert_eq!(4,   count(2, 1));
+    assert_eq!(8,   count(2, 2));
+    assert_eq!(6,   count(3, 1));
+    assert_eq!(18,  count(3, 2));
+    assert_eq!(32,  count(4, 2));
+  }
+
+  #[test]
+  fn round_trip() {
+    for &(n, k) in [(2usize, 3usize), (3, 2), (5, 4), (8, 6)].iter() {
+      let total = count(n, k);
+      let mut y = vec![0i32; n];
+      for i in 0..total {
+        vector(i, &mut y, n, k);
+        assert_eq!(k as i32, y.iter().map(|v| v.abs()).sum::<i32>());
+        assert_eq!(i, index(&y, n, k));
+      }
+    }
+  }
+}
@@ -0,0 +1,36 @@
+use std::io::Write;
+use buffer::AudioBuffer;
+use codecs::Codec;
+use error::AudioResult;
+use opus::container::OpusContainer;
+use traits::{AudioEncoder, Container};
+
+/// Encodes audio to Ogg Opus format to the provided writer.
+pub struct Encoder<'w, W: 'w> {
+  writer: &'w mut W,
+}
+
+impl<'w, W> Encoder<'w, W> where W: Write {
+  /// Create a new Ogg Opus format `Encoder` using the provided writer.
+  #[inline]
+  pub fn new(writer: &'w mut W) -> Encoder<'w, W> {
+    Encoder {
+      writer: writer
+    }
+  }
+}
+
+impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
+  /// Encodes the audio with Opus and writes it in Ogg pages to the included
+  /// writer. Audio at other sample rates is converted to 48 kHz.
+  #[inline]
+  fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
+    OpusContainer::write(&mut self.writer, audio)
+  }
+  /// Ogg Opus audio is always coded with Opus, so encoding with any of the
+  /// supported codecs is unsupported.
+  #[inline]
+  fn encode_as(&mut self, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    OpusContainer::create(&mut self.writer, audio, codec)
+  }
+}
@@ -0,0 +1,198 @@
+//! CELT Band Energy Coding
+//!
+//! The energy of each band is coded as a base 2 logarithm in three steps. A
+//! coarse step of 6 dB is predicted from the previous frame and the previous
+//! band, and coded with a Laplace distribution. Fine bits allocated to each
+//! band then refine the energy, and bits left over at the end of the frame
+//! refine it further.
+use opus::range::RangeCoder;
+use opus::tables::*;
+
+/// Returns the base 2 logarithm of the energy of each band, relative to the
+/// mean energy of the band.
+pub fn amp_to_log2(band_e: &[f32], band_log_e: &mut [f32], end: usize, channels: usize) {
+  for c in 0..channels {
+    for i in 0..end {
+      band_log_e[i + c * BANDS] =
+        ::std::f32::consts::LOG2_E * band_e[i + c * BANDS].ln() - E_MEANS[i];
+    }
+  }
+}
+
+/// Returns the prediction coefficients of the coarse energy.
+fn coefficients(lm: usize, intra: bool) -> (f32, f32) {
+  if intra {
+    (0f32, BETA_INTRA)
+  }
+  else {
+    (PRED_COEF[lm], BETA_COEF[lm])
+  }
+}
+
+/// Encodes the coarse energy of the bands, updating `old_e` to the
+/// quantized energy and filling `error` with the remaining difference.
+pub fn quant_coarse_energy(start: usize, end: usize, band_log_e: &[f32], old_e: &mut [f32],
+                           budget: i32, error: &mut [f32], coder: &mut RangeCoder,
+                           channels: usize, lm: usize, intra: bool, max_decay: f32) {
+  let prob_model = &E_PROB_MODEL[lm][intra as usize];
+  let (coef, beta) = coefficients(lm, intra);
+  let mut prev = [0f32; 2];
+  if coder.tell() + 3 <= budget {
+    coder.encode_bit_logp(intra, 3);
+  }
+  for i in start..end {
+    for c in 0..channels {
+      let x = band_log_e[i + c * BANDS];
+      let old = old_e[i + c * BANDS].max(-9f32);
+      let f = x - coef * old - prev[c];
+      let mut qi = (0.5 + f).floor() as i32;
+      // Prevents the energy from decreasing too quickly.
+      let decay_bound = old_e[i + c * BANDS].max(-28f32) - max_decay;
+      if qi < 0 && x < decay_bound {
+        qi += (decay_bound - x) as i32;
+        if qi > 0 {
+          qi = 0;
+        }
+      }
+      // Assumes something safe when the bits run out.
+      let tell = coder.tell();
+      let bits_left = budget - tell - 3 * (channels * (end - i)) as i32;
+      if i != start && bits_left < 30 {
+        if bits_left < 24 {
+          qi = qi.min(1);
+        }
+        if bits_left < 16 {
+          qi = qi.max(-1);
+        }
+      }
+      if budget - tell >= 15 {
+        let pi = 2 * i.min(20);
+        qi = coder.encode_laplace(qi, (prob_model[pi] as u32) << 7,
+                                  (prob_model[pi + 1] as i32) << 6);
+      }
+      else if budget - tell >= 2 {
+        qi = qi.min(1).max(-1);
+        coder.encode_icdf((2 * qi ^ -((qi < 0) as i32)) as usize, &SMALL_ENERGY_ICDF, 2);
+      }
+      else if budget - tell >= 1 {
+        qi = qi.min(0);
+        coder.encode_bit_logp(qi != 0, 1);
+      }
+      else {
+        qi = -1;
+      }
+      let q = qi as f32;
+      error[i + c * BANDS] = f - q;
+      old_e[i + c * BANDS] = coef * old + prev[c] + q;
+      prev[c] = prev[c] + q - beta * q;
+    }
+  }
+}
+
+/// Decodes the coarse energy of the bands into `old_e`.
+pub fn unquant_coarse_energy(start: usize, end: usize, old_e: &mut [f32], intra: bool,
+                             coder: &mut RangeCoder, channels: usize, lm: usize) {
+  let prob_model = &E_PROB_MODEL[lm][intra as usize];
+  let (coef, beta) = coefficients(lm, intra);
+  let mut prev = [0f32; 2];
+  let budget = coder.storage() as i32 * 8;
+  for i in start..end {
+    for c in 0..channels {
+      let tell = coder.tell();
+      let qi =
+        if budget - tell >= 15 {
+          let pi = 2 * i.min(20);
+          coder.decode_laplace((prob_model[pi] as u32) << 7, (prob_model[pi + 1] as i32) << 6)
+        } else if budget - tell >= 2 {
+          let qi = coder.decode_icdf(&SMALL_ENERGY_ICDF, 2) as i32;
+          (qi >> 1) ^ -(qi & 1)
+        } else if budget - tell >= 1 {
+          -(coder.decode_bit_logp(1) as i32)
+        } else {
+          -1
+        };
+      let q = qi as f32;
+      let old = old_e[i + c * BANDS].max(-9f32);
+      old_e[i + c * BANDS] = coef * old + prev[c] + q;
+      prev[c] = prev[c] + q - beta * q;
+    }
+  }
+}
+
+/// Encodes the fine energy bits of each band.
+pub fn quant_fine_energy(start: usize, end: usize, old_e: &mut [f32], error: &mut [f32],
+                         fine_quant: &[i32], coder: &mut RangeCoder, channels: usize) {
+  for i in start..end {
+    if fine_quant[i] <= 0 {
+      continue;
+    }
+    let frac = 1 << fine_quant[i];
+    for c in 0..channels {
+      let q2 = (((error[i + c * BANDS] + 0.5) * frac as f32).floor() as i32).min(frac - 1).max(0);
+      coder.encode_bits(q2 as u32, fine_quant[i] as u32);
+      let offset = (q2 as f32 + 0.5) * (1 << (14 - fine_quant[i])) as f32 / 16384f32 - 0.5;
+      old_e[i + c * BANDS] += offset;
+      error[i + c * BANDS] -= offset;
+    }
+  }
+}
+
+/// Decodes the fine energy bits of each band.
+pub fn unquant_fine_energy(start: usize, end: usize, old_e: &mut [f32], fine_quant: &[i32],
+                           coder: &mut RangeCoder, channels: usize) {
+  for i in start..end {
+    if fine_quant[i] <= 0 {
+      continue;
+    }
+    for c in 0..channels {
+      let q2 = coder.decode_bits(fine_quant[i] as u32);
+      let offset = (q2 as f32 + 0.5) * (1 << (14 - fine_quant[i])) as f32 / 16384f32 - 0.5;
+      old_e[i + c * BANDS] += offset;
+    }
+  }
+}
+
+/// Encodes a final bit of energy for the bands with room for it, using the
+/// bits left over at the end of the frame.
+pub fn quant_energy_finalise(start: usize, end: usize, old_e: &mut [f32], error: &mut [f32],
+                             fine_quant: &[i32], fine_priority: &[i32], bits_left: i32,
+                             coder: &mut RangeCoder, channels: usize) {
+  let mut bits_left = bits_left;
+  for prio in 0..2 {
+    let mut i = start;
+    while i < end && bits_left >= channels as i32 {
+      if fine_quant[i] < MAX_FINE_BITS && fine_priority[i] == prio {
+        for c in 0..channels {
+          let q2 = if error[i + c * BANDS] < 0f32 { 0 } else { 1 };
+          coder.encode_bits(q2, 1);
+          let offset = (q2 as f32 - 0.5) * (1 << (14 - fine_quant[i] - 1)) as f32 / 16384f32;
+          old_e[i + c * BANDS] += offset;
+          error[i + c * BANDS] -= offset;
+          bits_left -= 1;
+        }
+      }
+      i += 1;
+    }
+  }
+}
+
+/// Decodes the final bits of energy.
+pub fn unquant_energy_finalise(start: usize, end: usize, old_e: &mut [f32],
+                               fine_quant: &[i32], fine_priority: &[i32], bits_left: i32,
+                               coder: &mut RangeCoder, channels: usize) {
+  let mut bits_left = bits_left;
+  for prio in 0..2 {
+    let mut i = start;
+    while i < end && bits_left >= channels as i32 {
+      if fine_quant[i] < MAX_FINE_BITS && fine_priority[i] == prio {
+        for c in 0..channels {
+          let q2 = coder.decode_bits(1);
+          let offset = (q2 as f32 - 0.5) * (1 << (14 - fine_quant[i] - 1)) as f32 / 16384f32;
+          old_e[i + c * BANDS] += offset;
+          bits_left -= 1;
+        }
+      }
+      i += 1;
+    }
+  }
+}
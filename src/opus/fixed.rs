@@ -0,0 +1,159 @@
+//! Fixed-Point Arithmetic of SILK
+//!
+//! SILK is decoded with integer arithmetic, which must match the reference
+//! decoder of RFC 6716 exactly. These are the macros of the reference
+//! decoder, where values in Qn have n fractional bits, and the names give
+//! the operation and the halves of the operands used: `W` is a 32-bit word
+//! and `B` the bottom 16 bits of a word.
+
+/// Returns `(a * b) >> 16`, with `b` taken as 16 bits.
+#[inline]
+pub fn smulwb(a: i32, b: i32) -> i32 {
+  ((a as i64 * (b as i16) as i64) >> 16) as i32
+}
+
+/// Returns `a + ((b * c) >> 16)`, with `c` taken as 16 bits.
+#[inline]
+pub fn smlawb(a: i32, b: i32, c: i32) -> i32 {
+  a.wrapping_add(smulwb(b, c))
+}
+
+/// Returns `(a * b) >> 16`.
+#[inline]
+pub fn smulww(a: i32, b: i32) -> i32 {
+  ((a as i64 * b as i64) >> 16) as i32
+}
+
+/// Returns `a + ((b * c) >> 16)`.
+#[inline]
+pub fn smlaww(a: i32, b: i32, c: i32) -> i32 {
+  a.wrapping_add(smulww(b, c))
+}
+
+/// Returns `(a * b) >> 32`.
+#[inline]
+pub fn smmul(a: i32, b: i32) -> i32 {
+  ((a as i64 * b as i64) >> 32) as i32
+}
+
+/// Returns the product of the bottom 16 bits of `a` and `b`.
+#[inline]
+pub fn smulbb(a: i32, b: i32) -> i32 {
+  (a as i16) as i32 * (b as i16) as i32
+}
+
+/// Returns `a` plus the product of the bottom 16 bits of `b` and `c`.
+#[inline]
+pub fn smlabb(a: i32, b: i32, c: i32) -> i32 {
+  a.wrapping_add(smulbb(b, c))
+}
+
+/// Returns `a >> shift`, rounded to the nearest integer.
+#[inline]
+pub fn rshift_round(a: i32, shift: u32) -> i32 {
+  if shift == 1 {
+    (a >> 1) + (a & 1)
+  } else {
+    ((a >> (shift - 1)) + 1) >> 1
+  }
+}
+
+/// Same as `rshift_round` for 64-bit values.
+#[inline]
+pub fn rshift_round64(a: i64, shift: u32) -> i64 {
+  if shift == 1 {
+    (a >> 1) + (a & 1)
+  } else {
+    ((a >> (shift - 1)) + 1) >> 1
+  }
+}
+
+/// Limits `a` to the range between `limit1` and `limit2`, in either order.
+#[inline]
+pub fn limit(a: i32, limit1: i32, limit2: i32) -> i32 {
+  if limit1 > limit2 {
+    if a > limit1 { limit1 } else if a < limit2 { limit2 } else { a }
+  } else {
+    if a > limit2 { limit2 } else if a < limit1 { limit1 } else { a }
+  }
+}
+
+/// Saturates a value to 16 bits.
+#[inline]
+pub fn sat16(a: i32) -> i16 {
+  a.max(i16::min_value() as i32).min(i16::max_value() as i32) as i16
+}
+
+/// Returns `a << shift`, saturated to 32 bits.
+#[inline]
+pub fn lshift_sat32(a: i32, shift: u32) -> i32 {
+  limit(a, i32::min_value() >> shift, i32::max_value() >> shift) << shift
+}
+
+/// Returns the next value of the pseudo-random generator of SILK.
+#[inline]
+pub fn rand(seed: i32) -> i32 {
+  907633515i32.wrapping_add(seed.wrapping_mul(196314165))
+}
+
+/// Returns an approximation of `(a << q) / b`.
+pub fn div32_var_q(a: i32, b: i32, q: i32) -> i32 {
+  debug_assert!(b != 0 && q >= 0);
+  // Normalizes the inputs, leaving a bit of headroom.
+  let a_headroom = a.wrapping_abs().leading_zeros() as i32 - 1;
+  let mut a_nrm = a << a_headroom;
+  let b_headroom = b.wrapping_abs().leading_zeros() as i32 - 1;
+  let b_nrm = b << b_headroom;
+  // Inverse of b with 14 bits of precision, in Q(29 + 16 - b_headroom).
+  let b_inv = (i32::max_value() >> 2) / (b_nrm >> 16);
+  // First approximation, refined by the remainder.
+  let mut result = smulwb(a_nrm, b_inv);
+  a_nrm = a_nrm.wrapping_sub(smmul(b_nrm, result).wrapping_shl(3));
+  result = smlawb(result, a_nrm, b_inv);
+  let shift = 29 + a_headroom - b_headroom - q;
+  if shift < 0 {
+    lshift_sat32(result, -shift as u32)
+  } else if shift < 32 {
+    result >> shift
+  } else {
+    0
+  }
+}
+
+/// Returns an approximation of `(1 << q) / b`.
+pub fn inverse32_var_q(b: i32, q: i32) -> i32 {
+  debug_assert!(b != 0 && q > 0);
+  let b_headroom = b.wrapping_abs().leading_zeros() as i32 - 1;
+  let b_nrm = b << b_headroom;
+  let b_inv = (i32::max_value() >> 2) / (b_nrm >> 16);
+  // First approximation in Q(61 - b_headroom), refined by the remainder.
+  let mut result = b_inv << 16;
+  let error_q32 = ((1 << 29) - smulwb(b_nrm, b_inv)) << 3;
+  result = smlaww(result, error_q32, b_inv);
+  let shift = 61 - b_headroom - q;
+  if shift <= 0 {
+    lshift_sat32(result, -shift as u32)
+  } else if shift < 32 {
+    result >> shift
+  } else {
+    0
+  }
+}
+
+/// Returns an approximation of `2^(x / 128)`.
+pub fn log2lin(x_q7: i32) -> i32 {
+  if x_q7 < 0 {
+    return 0;
+  } else if x_q7 >= 3967 {
+    return i32::max_value();
+  }
+  let out: i32 = 1 << (x_q7 >> 7);
+  let frac_q7 = x_q7 & 0x7F;
+  // Piecewise parabolic approximation of the fraction.
+  let frac = smlawb(frac_q7, smulbb(frac_q7, 128 - frac_q7), -174);
+  if x_q7 < 2048 {
+    out + (out.wrapping_mul(frac) >> 7)
+  } else {
+    out + (out >> 7) * frac
+  }
+}
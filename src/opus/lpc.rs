@@ -0,0 +1,340 @@
+//! Linear Prediction of SILK
+//!
+//! SILK codes the short-term prediction filter of each frame as normalized
+//! line spectral frequencies, NLSFs, which are quantized with two stages of
+//! vector quantization. The decoder converts them back to the coefficients
+//! of the prediction filter, making sure the filter is stable.
+use opus::fixed::*;
+use opus::silk_tables::*;
+
+/// Largest order of the prediction filter.
+pub const MAX_LPC_ORDER: usize = 16;
+
+/// Largest amplitude of a second stage NLSF index coded without extension.
+pub const NLSF_QUANT_MAX_AMPLITUDE: i32 = 4;
+
+/// Adjustment of the second stage NLSF residuals towards zero, in Q10.
+const NLSF_QUANT_LEVEL_ADJ_Q10: i32 = 102;
+
+/// Number of fractional bits of the polynomials of `nlsf2a`.
+const QA: u32 = 16;
+
+/// Number of fractional bits of the coefficients of `inverse_pred_gain`.
+const QA_GAIN: u32 = 24;
+
+/// Largest absolute value of a stable reflection coefficient, 0.99975 in
+/// Q24.
+const A_LIMIT: i32 = 16773022;
+
+/// Smallest inverse prediction gain of a stable filter, 1 / 1e4 in Q30.
+const MIN_INV_GAIN_Q30: i32 = 107374;
+
+/// Number of rounds of bandwidth expansion applied to an unstable filter.
+const MAX_LPC_STABILIZE_ITERATIONS: usize = 16;
+
+/// Number of rounds of moving NLSFs apart before falling back to sorting.
+const MAX_STABILIZE_LOOPS: usize = 20;
+
+/// Returns the index of the distribution and the prediction coefficient of
+/// each second stage residual, for a vector of the first stage.
+pub fn nlsf_unpack(cb: &NlsfCodebook, index: usize) -> ([usize; MAX_LPC_ORDER],
+                                                       [u8; MAX_LPC_ORDER]) {
+  let mut ec_ix   = [0usize; MAX_LPC_ORDER];
+  let mut pred_q8 = [0u8; MAX_LPC_ORDER];
+  let order = cb.order;
+  let levels = 2 * NLSF_QUANT_MAX_AMPLITUDE as usize + 1;
+  for i in (0..order).step_by(2) {
+    let entry = cb.cb2_select[(index * order + i) / 2] as usize;
+    ec_ix[i]       = ((entry >> 1) & 7) * levels;
+    pred_q8[i]     = cb.pred_q8[i + (entry & 1) * (order - 1)];
+    ec_ix[i + 1]   = ((entry >> 5) & 7) * levels;
+    pred_q8[i + 1] = cb.pred_q8[i + ((entry >> 4) & 1) * (order - 1) + 1];
+  }
+  (ec_ix, pred_q8)
+}
+
+/// Decodes the NLSFs in Q15 from the index of the first stage followed by
+/// the second stage residuals.
+pub fn nlsf_decode(indices: &[i32], cb: &NlsfCodebook) -> [i16; MAX_LPC_ORDER] {
+  let order = cb.order;
+  let index = indices[0] as usize;
+  let (_, pred_q8) = nlsf_unpack(cb, index);
+
+  // Dequantizes the residuals, which are predicted backwards.
+  let mut res_q10 = [0i32; MAX_LPC_ORDER];
+  let mut out_q10 = 0;
+  for i in (0..order).rev() {
+    let pred_q10 = smulbb(out_q10, pred_q8[i] as i32) >> 8;
+    out_q10 = indices[i + 1] << 10;
+    if out_q10 > 0 {
+      out_q10 -= NLSF_QUANT_LEVEL_ADJ_Q10;
+    } else if out_q10 < 0 {
+      out_q10 += NLSF_QUANT_LEVEL_ADJ_Q10;
+    }
+    out_q10 = smlawb(pred_q10, out_q10, cb.quant_step_q16);
+    res_q10[i] = out_q10;
+  }
+
+  // Adds the residuals, weighted by the first stage, to the first stage.
+  let mut nlsf_q15 = [0i16; MAX_LPC_ORDER];
+  let cb1  = &cb.cb1_q8[index * order ..];
+  let wght = &cb.cb1_wght_q9[index * order ..];
+  for i in 0..order {
+    let value = (res_q10[i] << 14) / wght[i] as i32 + ((cb1[i] as i32) << 7);
+    nlsf_q15[i] = limit(value, 0, 32767) as i16;
+  }
+  nlsf_stabilize(&mut nlsf_q15[..order], cb.delta_min_q15);
+  nlsf_q15
+}
+
+/// Moves the NLSFs apart so that they are increasing with at least the
+/// given distances between them, and from 0 and 1.
+pub fn nlsf_stabilize(nlsf_q15: &mut [i16], delta_min_q15: &[i16]) {
+  let l = nlsf_q15.len();
+  for _ in 0..MAX_STABILIZE_LOOPS {
+    // Finds the smallest distance.
+    let mut min_diff = nlsf_q15[0] as i32 - delta_min_q15[0] as i32;
+    let mut index = 0;
+    for i in 1..l {
+      let diff = nlsf_q15[i] as i32 - (nlsf_q15[i - 1] as i32 + delta_min_q15[i] as i32);
+      if diff < min_diff {
+        min_diff = diff;
+        index = i;
+      }
+    }
+    let diff = (1 << 15) - (nlsf_q15[l - 1] as i32 + delta_min_q15[l] as i32);
+    if diff < min_diff {
+      min_diff = diff;
+      index = l;
+    }
+    if min_diff >= 0 {
+      return;
+    }
+
+    if index == 0 {
+      nlsf_q15[0] = delta_min_q15[0];
+    } else if index == l {
+      nlsf_q15[l - 1] = ((1 << 15) - delta_min_q15[l] as i32) as i16;
+    } else {
+      // Moves the pair apart around its center, within the range the
+      // distances to the ends leave for it.
+      let half = delta_min_q15[index] as i32 >> 1;
+      let mut min_center = half;
+      for k in 0..index {
+        min_center += delta_min_q15[k] as i32;
+      }
+      let mut max_center = (1 << 15) - half;
+      for k in index + 1 .. l + 1 {
+        max_center -= delta_min_q15[k] as i32;
+      }
+      let center = limit(rshift_round(nlsf_q15[index - 1] as i32 + nlsf_q15[index] as i32, 1),
+                         min_center, max_center);
+      nlsf_q15[index - 1] = (center - half) as i16;
+      nlsf_q15[index] = nlsf_q15[index - 1].wrapping_add(delta_min_q15[index]);
+    }
+  }
+
+  // Falls back to sorting the NLSFs and enforcing the distances in order.
+  nlsf_q15.sort();
+  nlsf_q15[0] = nlsf_q15[0].max(delta_min_q15[0]);
+  for i in 1..l {
+    nlsf_q15[i] = nlsf_q15[i].max(nlsf_q15[i - 1].saturating_add(delta_min_q15[i]));
+  }
+  nlsf_q15[l - 1] = nlsf_q15[l - 1].min(((1 << 15) - delta_min_q15[l] as i32) as i16);
+  for i in (0 .. l - 1).rev() {
+    nlsf_q15[i] = nlsf_q15[i].min(nlsf_q15[i + 1] - delta_min_q15[i + 1]);
+  }
+}
+
+/// Computes the polynomial of the even or odd NLSFs, from twice their
+/// cosines in QA.
+fn nlsf2a_find_poly(out: &mut [i32], c_lsf: &[i32], dd: usize) {
+  out[0] = 1 << QA;
+  out[1] = -c_lsf[0];
+  for k in 1..dd {
+    let f = c_lsf[2 * k] as i64;
+    out[k + 1] = (out[k - 1] << 1) - rshift_round64(f * out[k] as i64, QA) as i32;
+    for n in (2 .. k + 1).rev() {
+      out[n] += out[n - 2] - rshift_round64(f * out[n - 1] as i64, QA) as i32;
+    }
+    out[1] -= f as i32;
+  }
+}
+
+/// Converts NLSFs in Q15 to the coefficients of a stable prediction filter
+/// in Q12.
+pub fn nlsf2a(nlsf_q15: &[i16], d: usize) -> [i16; MAX_LPC_ORDER] {
+  // Orderings of the cosines which improve the numerical accuracy.
+  const ORDERING_16: [usize; 16] = [0, 15, 8, 7, 4, 11, 12, 3, 2, 13, 10, 5, 6, 9, 14, 1];
+  const ORDERING_10: [usize; 10] = [0, 9, 6, 3, 4, 5, 8, 1, 2, 7];
+  let ordering: &[usize] = if d == 16 { &ORDERING_16 } else { &ORDERING_10 };
+
+  // Interpolates twice the cosine of each NLSF from the table.
+  let mut cos_lsf_qa = [0i32; MAX_LPC_ORDER];
+  for k in 0..d {
+    let f_int  = (nlsf_q15[k] >> 8) as usize;
+    let f_frac = (nlsf_q15[k] & 0xFF) as i32;
+    let cos   = LSF_COS_Q12[f_int] as i32;
+    let delta = LSF_COS_Q12[f_int + 1] as i32 - cos;
+    cos_lsf_qa[ordering[k]] = rshift_round((cos << 8) + delta * f_frac, 20 - QA);
+  }
+
+  let dd = d / 2;
+  let mut p = [0i32; MAX_LPC_ORDER / 2 + 1];
+  let mut q = [0i32; MAX_LPC_ORDER / 2 + 1];
+  nlsf2a_find_poly(&mut p, &cos_lsf_qa, dd);
+  nlsf2a_find_poly(&mut q, &cos_lsf_qa[1..], dd);
+
+  // Combines the polynomials into the coefficients in Q(QA + 1).
+  let mut a32_qa1 = [0i32; MAX_LPC_ORDER];
+  for k in 0..dd {
+    let p_tmp = p[k + 1] + p[k];
+    let q_tmp = q[k + 1] - q[k];
+    a32_qa1[k]         = -q_tmp - p_tmp;
+    a32_qa1[d - k - 1] =  q_tmp - p_tmp;
+  }
+
+  let mut a_q12 = [0i16; MAX_LPC_ORDER];
+  lpc_fit(&mut a_q12[..d], &mut a32_qa1[..d], 12, QA + 1);
+  let mut i = 0;
+  while inverse_pred_gain(&a_q12[..d]) == 0 && i < MAX_LPC_STABILIZE_ITERATIONS {
+    // Expands the bandwidth of a filter which is too close to unstable.
+    bwexpander_32(&mut a32_qa1[..d], 65536 - (2 << i));
+    for k in 0..d {
+      a_q12[k] = rshift_round(a32_qa1[k], QA + 1 - 12) as i16;
+    }
+    i += 1;
+  }
+  a_q12
+}
+
+/// Converts coefficients in Q`q_in` to 16 bits in Q`q_out`, expanding the
+/// bandwidth of the filter until they fit.
+fn lpc_fit(a_out: &mut [i16], a_in: &mut [i32], q_out: u32, q_in: u32) {
+  let d = a_in.len();
+  let mut fits = false;
+  for _ in 0..10 {
+    let mut max_abs = 0;
+    let mut index = 0;
+    for k in 0..d {
+      let abs = a_in[k].wrapping_abs();
+      if abs > max_abs {
+        max_abs = abs;
+        index = k;
+      }
+    }
+    max_abs = rshift_round(max_abs, q_in - q_out);
+    if max_abs > i16::max_value() as i32 {
+      // Reduces the magnitude of the coefficients.
+      max_abs = max_abs.min(163838);
+      let chirp_q16 = 65470 - ((max_abs - i16::max_value() as i32) << 14) /
+                              ((max_abs * (index as i32 + 1)) >> 2);
+      bwexpander_32(a_in, chirp_q16);
+    } else {
+      fits = true;
+      break;
+    }
+  }
+
+  if fits {
+    for k in 0..d {
+      a_out[k] = rshift_round(a_in[k], q_in - q_out) as i16;
+    }
+  } else {
+    // Clips the coefficients after the last round.
+    for k in 0..d {
+      a_out[k] = sat16(rshift_round(a_in[k], q_in - q_out));
+      a_in[k] = (a_out[k] as i32) << (q_in - q_out);
+    }
+  }
+}
+
+/// Returns the inverse of the prediction gain of a filter in Q12, in Q30,
+/// or 0 if the filter is unstable.
+pub fn inverse_pred_gain(a_q12: &[i16]) -> i32 {
+  let order = a_q12.len();
+  let mut a_qa = [0i32; MAX_LPC_ORDER];
+  let mut dc_resp = 0;
+  for k in 0..order {
+    dc_resp += a_q12[k] as i32;
+    a_qa[k] = (a_q12[k] as i32) << (QA_GAIN - 12);
+  }
+  if dc_resp >= 4096 {
+    return 0;
+  }
+
+  let mut inv_gain_q30 = 1 << 30;
+  for k in (1..order).rev() {
+    if a_qa[k] > A_LIMIT || a_qa[k] < -A_LIMIT {
+      return 0;
+    }
+    // The reflection coefficient is the negated coefficient.
+    let rc_q31 = -(a_qa[k] << (31 - QA_GAIN));
+    let rc_mult1_q30 = (1 << 30) - smmul(rc_q31, rc_q31);
+    inv_gain_q30 = smmul(inv_gain_q30, rc_mult1_q30) << 2;
+    if inv_gain_q30 < MIN_INV_GAIN_Q30 {
+      return 0;
+    }
+    let mult2_q = 32 - rc_mult1_q30.wrapping_abs().leading_zeros();
+    let rc_mult2 = inverse32_var_q(rc_mult1_q30, mult2_q as i32 + 30);
+
+    // Steps down to the filter of the next lower order.
+    for n in 0 .. (k + 1) >> 1 {
+      let tmp1 = a_qa[n];
+      let tmp2 = a_qa[k - n - 1];
+      let frac2 = rshift_round64(tmp2 as i64 * rc_q31 as i64, 31) as i32;
+      let value = rshift_round64(tmp1.saturating_sub(frac2) as i64 * rc_mult2 as i64, mult2_q);
+      if value > i32::max_value() as i64 || value < i32::min_value() as i64 {
+        return 0;
+      }
+      a_qa[n] = value as i32;
+      let frac1 = rshift_round64(tmp1 as i64 * rc_q31 as i64, 31) as i32;
+      let value = rshift_round64(tmp2.saturating_sub(frac1) as i64 * rc_mult2 as i64, mult2_q);
+      if value > i32::max_value() as i64 || value < i32::min_value() as i64 {
+        return 0;
+      }
+      a_qa[k - n - 1] = value as i32;
+    }
+  }
+
+  if a_qa[0] > A_LIMIT || a_qa[0] < -A_LIMIT {
+    return 0;
+  }
+  let rc_q31 = -(a_qa[0] << (31 - QA_GAIN));
+  let rc_mult1_q30 = (1 << 30) - smmul(rc_q31, rc_q31);
+  inv_gain_q30 = smmul(inv_gain_q30, rc_mult1_q30) << 2;
+  if inv_gain_q30 < MIN_INV_GAIN_Q30 {
+    return 0;
+  }
+  inv_gain_q30
+}
+
+/// Expands the bandwidth of a filter by scaling its coefficients by powers
+/// of the chirp factor in Q16.
+fn bwexpander_32(ar: &mut [i32], mut chirp_q16: i32) {
+  let d = ar.len();
+  let chirp_minus_one_q16 = chirp_q16 - 65536;
+  for i in 0 .. d - 1 {
+    ar[i] = smulww(chirp_q16, ar[i]);
+    chirp_q16 += rshift_round(chirp_q16.wrapping_mul(chirp_minus_one_q16), 16);
+  }
+  ar[d - 1] = smulww(chirp_q16, ar[d - 1]);
+}
+
+/// Filters the input with the prediction filter in Q12, giving the
+/// prediction residual, with the first `d` samples of the output zeroed.
+pub fn analysis_filter(output: &mut [i16], input: &[i16], b_q12: &[i16], len: usize) {
+  let d = b_q12.len();
+  for ix in d..len {
+    let mut out_q12 = 0i32;
+    for j in 0..d {
+      out_q12 = smlabb(out_q12, input[ix - 1 - j] as i32, b_q12[j] as i32);
+    }
+    // Subtracts the prediction, allowing wrap around.
+    out_q12 = ((input[ix] as i32) << 12).wrapping_sub(out_q12);
+    output[ix] = sat16(rshift_round(out_q12, 12));
+  }
+  for x in output[..d].iter_mut() {
+    *x = 0;
+  }
+}
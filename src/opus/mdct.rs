@@ -0,0 +1,249 @@
+//! CELT Modified Discrete Cosine Transform
+//!
+//! CELT uses a low-overlap MDCT, where consecutive blocks only overlap by
+//! the length of the window. The transform of `n` samples is computed with a
+//! complex FFT of `n/4` points, whose sizes are not powers of two, so the FFT
+//! is computed by splitting it into factors of 2, 3, 4, and 5.
+use std::f64::consts::PI;
+use opus::tables::{FRAME_SIZE, MAX_LM};
+
+/// Complex FFT of a size made up of factors of 2, 3, 4, and 5.
+struct Fft {
+  n:        usize,
+  factors:  Vec<usize>,
+  twiddles: Vec<(f32, f32)>
+}
+
+impl Fft {
+  fn new(n: usize) -> Fft {
+    let mut factors: Vec<usize> = Vec::new();
+    let mut m = n;
+    while m > 1 {
+      let p =
+        if m % 4 == 0 { 4 }
+        else if m % 2 == 0 { 2 }
+        else if m % 3 == 0 { 3 }
+        else if m % 5 == 0 { 5 }
+        else { m };
+      factors.push(p);
+      m /= p;
+    }
+    let twiddles = (0..n).map(|k| {
+      let angle = -2f64 * PI * k as f64 / n as f64;
+      (angle.cos() as f32, angle.sin() as f32)
+    }).collect();
+    Fft {
+      n:        n,
+      factors:  factors,
+      twiddles: twiddles
+    }
+  }
+
+  /// Computes the forward transform of the input into the output.
+  fn forward(&self, input: &[(f32, f32)], output: &mut [(f32, f32)]) {
+    self.transform(input, 1, output, 0);
+  }
+
+  /// Transforms the `m` values of the input `stride` apart, where the
+  /// remaining factors of `m` start at `factor`.
+  fn transform(&self, input: &[(f32, f32)], stride: usize, output: &mut [(f32, f32)],
+               factor: usize) {
+    let m = output.len();
+    if m == 1 {
+      output[0] = input[0];
+      return;
+    }
+    let p = self.factors[factor];
+    let q = m / p;
+    for r in 0..p {
+      self.transform(&input[r * stride ..], stride * p, &mut output[r * q .. (r + 1) * q],
+                     factor + 1);
+    }
+    // Combines the transforms of each residue with a DFT of p points.
+    let step = self.n / m;
+    let mut values = [(0f32, 0f32); 5];
+    let mut sums   = [(0f32, 0f32); 5];
+    for k in 0..q {
+      for r in 0..p {
+        let (re, im) = output[r * q + k];
+        let (c, s) = self.twiddles[(r * k * step) % self.n];
+        values[r] = (re * c - im * s, re * s + im * c);
+      }
+      for j in 0..p {
+        let mut sum = (0f32, 0f32);
+        for r in 0..p {
+          let (c, s) = self.twiddles[(r * j * q * step) % self.n];
+          let (re, im) = values[r];
+          sum.0 += re * c - im * s;
+          sum.1 += re * s + im * c;
+        }
+        sums[j] = sum;
+      }
+      for j in 0..p {
+        output[j * q + k] = sums[j];
+      }
+    }
+  }
+}
+
+/// The transforms for each block size, from frames of 20 ms down to a short
+/// block of 2.5 ms.
+pub struct Mdct {
+  ffts: Vec<Fft>,
+  trig: Vec<Vec<f32>>
+}
+
+impl Mdct {
+  pub fn new() -> Mdct {
+    let mut ffts: Vec<Fft>      = Vec::new();
+    let mut trig: Vec<Vec<f32>> = Vec::new();
+    for shift in 0 .. MAX_LM + 1 {
+      let n = 2 * FRAME_SIZE >> shift;
+      ffts.push(Fft::new(n >> 2));
+      trig.push((0 .. n >> 1).map(|i|
+        (2f64 * PI * (i as f64 + 0.125) / n as f64).cos() as f32
+      ).collect());
+    }
+    Mdct {
+      ffts: ffts,
+      trig: trig
+    }
+  }
+
+  /// Computes the MDCT of the `n/2 + overlap` samples of the input, for a
+  /// block size given by `shift`, writing the coefficients to every
+  /// `stride`th value of the output.
+  pub fn forward(&self, input: &[f32], output: &mut [f32], window: &[f32], overlap: usize,
+                 shift: usize, stride: usize) {
+    let trig = &self.trig[shift];
+    let fft  = &self.ffts[shift];
+    let n  = 2 * FRAME_SIZE >> shift;
+    let n2 = n >> 1;
+    let n4 = n >> 2;
+    let scale = 1f32 / n4 as f32;
+
+    // Windows and folds the input into n/2 values.
+    let mut f: Vec<f32> = vec![0f32; n2];
+    {
+      let mut xp1 = overlap >> 1;
+      let mut xp2 = n2 - 1 + (overlap >> 1);
+      let mut wp1 = overlap >> 1;
+      let mut wp2 = (overlap >> 1) as isize - 1;
+      let mut yp = 0;
+      let mut i = 0;
+      while i < (overlap + 3) >> 2 {
+        f[yp]     = window[wp2 as usize] * input[xp1 + n2] + window[wp1] * input[xp2];
+        f[yp + 1] = window[wp1] * input[xp1] - window[wp2 as usize] * input[xp2 - n2];
+        yp  += 2;
+        xp1 += 2;
+        xp2 -= 2;
+        wp1 += 2;
+        wp2 -= 2;
+        i += 1;
+      }
+      let mut wp1 = 0;
+      let mut wp2 = overlap as isize - 1;
+      while i < n4 - ((overlap + 3) >> 2) {
+        f[yp]     = input[xp2];
+        f[yp + 1] = input[xp1];
+        yp  += 2;
+        xp1 += 2;
+        xp2 -= 2;
+        i += 1;
+      }
+      while i < n4 {
+        f[yp]     = -window[wp1] * input[xp1 - n2] + window[wp2 as usize] * input[xp2];
+        f[yp + 1] = window[wp2 as usize] * input[xp1] + window[wp1] * input[xp2 + n2];
+        yp  += 2;
+        xp1 += 2;
+        xp2 -= 2;
+        wp1 += 2;
+        wp2 -= 2;
+        i += 1;
+      }
+    }
+
+    // Pre-rotates into complex values, scaling the FFT.
+    let mut data: Vec<(f32, f32)> = vec![(0f32, 0f32); n4];
+    for i in 0..n4 {
+      let (t0, t1) = (trig[i], trig[n4 + i]);
+      let (re, im) = (f[2 * i], f[2 * i + 1]);
+      data[i] = (scale * (re * t0 - im * t1), scale * (im * t0 + re * t1));
+    }
+    let mut spectrum: Vec<(f32, f32)> = vec![(0f32, 0f32); n4];
+    fft.forward(&data, &mut spectrum);
+
+    // Post-rotates the values into the coefficients.
+    for i in 0..n4 {
+      let (re, im) = spectrum[i];
+      output[2 * i * stride]            = im * trig[n4 + i] - re * trig[i];
+      output[stride * (n2 - 1 - 2 * i)] = re * trig[n4 + i] + im * trig[i];
+    }
+  }
+
+  /// Computes the inverse MDCT of `n/2` coefficients, taken from every
+  /// `stride`th value of the input, for a block size given by `shift`.
+  ///
+  /// The first `overlap` values of the output must hold the end of the
+  /// previous block, which is overlapped and added with the start of the
+  /// block. The output is written up to `n/2 + overlap/2` values, where the
+  /// values past `n/2` are completed by the next block.
+  pub fn backward(&self, input: &[f32], output: &mut [f32], window: &[f32], overlap: usize,
+                  shift: usize, stride: usize) {
+    let trig = &self.trig[shift];
+    let fft  = &self.ffts[shift];
+    let n  = 2 * FRAME_SIZE >> shift;
+    let n2 = n >> 1;
+    let n4 = n >> 2;
+
+    // Pre-rotates the coefficients, swapping the real and imaginary parts to
+    // compute the inverse FFT with the forward FFT.
+    let mut data: Vec<(f32, f32)> = vec![(0f32, 0f32); n4];
+    for i in 0..n4 {
+      let x1 = input[2 * i * stride];
+      let x2 = input[stride * (n2 - 1 - 2 * i)];
+      let yr = x2 * trig[i] + x1 * trig[n4 + i];
+      let yi = x1 * trig[i] - x2 * trig[n4 + i];
+      data[i] = (yi, yr);
+    }
+    let mut spectrum: Vec<(f32, f32)> = vec![(0f32, 0f32); n4];
+    fft.forward(&data, &mut spectrum);
+    let out = &mut output[overlap >> 1 ..];
+    for i in 0..n4 {
+      out[2 * i]     = spectrum[i].0;
+      out[2 * i + 1] = spectrum[i].1;
+    }
+
+    // Post-rotates from both ends of the buffer at once.
+    let mut yp0 = 0;
+    let mut yp1 = n2 - 2;
+    for i in 0 .. (n4 + 1) >> 1 {
+      let re = out[yp0 + 1];
+      let im = out[yp0];
+      let (t0, t1) = (trig[i], trig[n4 + i]);
+      let yr = re * t0 + im * t1;
+      let yi = re * t1 - im * t0;
+      let re = out[yp1 + 1];
+      let im = out[yp1];
+      out[yp0]     = yr;
+      out[yp1 + 1] = yi;
+      let (t0, t1) = (trig[n4 - i - 1], trig[n2 - i - 1]);
+      let yr = re * t0 + im * t1;
+      let yi = re * t1 - im * t0;
+      out[yp1]     = yr;
+      out[yp0 + 1] = yi;
+      yp0 += 2;
+      yp1 = yp1.wrapping_sub(2);
+    }
+
+    // Mirrors the start of the block for the time-domain aliasing
+    // cancellation with the previous block.
+    for i in 0 .. overlap / 2 {
+      let x1 = output[overlap - 1 - i];
+      let x2 = output[i];
+      let (w1, w2) = (window[i], window[overlap - 1 - i]);
+      output[i]               = w2 * x2 - w1 * x1;
+      output[overlap - 1 - i] = w1 * x2 + w2 * x1;
+    }
+  }
+}
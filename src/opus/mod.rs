@@ -0,0 +1,198 @@
+//! The Ogg Opus Format
+//!
+//! Opus streams are carried in Ogg pages, starting with an identification
+//! header, `OpusHead`, and a comment header, `OpusTags`, followed by a
+//! packet for each frame of audio. Opus always codes audio at 48 kHz, and
+//! decoded audio is given at 48 kHz. Audio at other sample rates is
+//! converted to 48 kHz when encoding, and its original sample rate is kept
+//! in the identification header.
+//!
+//! One or two channels are coded in a single stream with channel mapping
+//! family 0. Up to eight channels are coded with channel mapping family 1,
+//! in which each pair of coupled channels is coded in a stereo stream, and
+//! the channels follow the Vorbis channel order. The granule positions and
+//! the pre-skip of the identification header are used to trim the samples
+//! at the start and end of the stream, and comments are stored as textual
+//! information in the metadata.
+//!
+//! Frames coded with SILK, for speech at low bitrates, with CELT, or in the
+//! hybrid mode combining both are decoded. Lost frames are decoded as
+//! silence, with the audio coded with CELT fading out. The encoder codes
+//! fullband CELT frames of 20 ms at a constant bitrate.
+//!
+//! References
+//! - [RFC 6716](https://tools.ietf.org/html/rfc6716)
+//! - [RFC 7845](https://tools.ietf.org/html/rfc7845)
+
+mod bands;
+mod celt;
+mod container;
+mod cwrs;
+mod energy;
+mod fixed;
+mod lpc;
+mod mdct;
+mod packet;
+mod range;
+mod rate;
+mod resampler;
+mod silk;
+mod silk_tables;
+mod stream;
+mod tables;
+mod vq;
+pub mod decoder;
+pub mod encoder;
+
+pub use opus::decoder::Decoder as Decoder;
+pub use opus::encoder::Encoder as Encoder;
+
+/// Signature of the identification header.
+const OPUS_HEAD: &'static [u8; 8] = b"OpusHead";
+
+/// Signature of the comment header.
+const OPUS_TAGS: &'static [u8; 8] = b"OpusTags";
+
+/// Sample rate of audio coded with Opus.
+const SAMPLE_RATE: u32 = 48000;
+
+/// Serial number of the logical bitstream written by the encoder.
+const SERIAL: u32 = 0x4F707573;
+
+/// Vendor string of the comment header written by the encoder.
+const VENDOR: &'static str = "audio";
+
+#[cfg(test)]
+mod io {
+  use std::fs::File;
+  use std::io::Cursor;
+  use std::path::Path;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::codecs::Codec::*;
+  use ::error::AudioError;
+  use ::ogg::page::read_packets;
+  use super::packet::Packet;
+  use ::testing::{SINE_LEFT, SINE_RIGHT, Sine, sine_error, sines};
+
+  #[test]
+  fn read_mono() {
+    let audio = audio::open(Path::new("tests/opus/sine-mono.opus")).unwrap();
+    assert_eq!(1,     audio.channels);
+    assert_eq!(48000, audio.sample_rate);
+    assert_eq!(12000, audio.samples.len());
+    assert!(sine_error(&audio, 0, SINE_LEFT, 0, 0) < 0.05);
+  }
+
+  #[test]
+  fn read_stereo() {
+    let audio = audio::open(Path::new("tests/opus/sine-stereo.opus")).unwrap();
+    assert_eq!(2,     audio.channels);
+    assert_eq!(48000, audio.sample_rate);
+    assert_eq!(9288,  audio.samples.len() / 2);
+    assert!(sine_error(&audio, 0, SINE_LEFT,  0, 0) < 0.05);
+    assert!(sine_error(&audio, 1, SINE_RIGHT, 0, 0) < 0.05);
+  }
+
+  #[test]
+  fn comments() {
+    let audio = audio::open(Path::new("tests/opus/sine-mono.opus")).unwrap();
+    assert_eq!(vec![
+      ("TITLE".to_string(),   "Sine".to_string()),
+      ("ARTIST".to_string(),  "Test".to_string()),
+      ("comment".to_string(), "440 Hz".to_string())
+    ], audio.metadata.info);
+  }
+
+  #[test]
+  fn read_speech() {
+    let audio = audio::open(Path::new("tests/opus/speech.opus")).unwrap();
+    assert_eq!(1,     audio.channels);
+    assert_eq!(48000, audio.sample_rate);
+    assert_eq!(4488,  audio.samples.len());
+    // SILK is decoded with integer arithmetic, exactly as the reference
+    // decoder does.
+    let peak = audio.samples.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+    assert_eq!(17288f32 / 32768f32, peak);
+  }
+
+  /// Checks a stereo file from the libopus encoder, whose packets all have
+  /// a configuration from `first` to `last`, against the samples decoded by
+  /// libopus.
+  fn check_reference(path: &str, reference: &str, first: u8, last: u8) {
+    let packets = read_packets(&mut File::open(path).unwrap()).unwrap();
+    for packet in packets[2..].iter() {
+      let (packet, _) = Packet::parse(&packet.data, false).unwrap();
+      assert!(packet.config() >= first && packet.config() <= last);
+    }
+    let audio = audio::open(Path::new(path)).unwrap();
+    let expected = audio::open(Path::new(reference)).unwrap();
+    assert_eq!(2,     audio.channels);
+    assert_eq!(48000, audio.sample_rate);
+    assert_eq!(24000, audio.samples.len() / 2);
+    assert_eq!(expected.samples.len(), audio.samples.len());
+    for (sample, expected) in audio.samples.iter().zip(expected.samples.iter()) {
+      let sample = (sample * 32768f32).round().max(-32768f32).min(32767f32);
+      assert!((sample - expected * 32768f32).abs() <= 1f32);
+    }
+  }
+
+  #[test]
+  fn read_silk() {
+    // Encoded at 12 kbit/s, which is low enough for every packet to be
+    // coded with SILK only.
+    check_reference("tests/opus/sine-silk.opus", "tests/opus/sine-silk.wav", 0, 11);
+  }
+
+  #[test]
+  fn read_hybrid() {
+    // Encoded at 16 kbit/s, where the fullband packets are coded with both
+    // SILK and CELT.
+    check_reference("tests/opus/sine-hybrid.opus", "tests/opus/sine-hybrid.wav", 12, 15);
+  }
+
+  #[test]
+  fn write_resampled() {
+    let mut audio = sines(8000, 1, 4000);
+    audio.metadata.info.push(("TITLE".to_string(), "Call".to_string()));
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write(&mut bytes, &audio, AudioFormat::Opus).unwrap();
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::Opus).unwrap();
+    assert_eq!(1,     verify.channels);
+    assert_eq!(48000, verify.sample_rate);
+    assert_eq!(24000, verify.samples.len());
+    assert_eq!(audio.metadata.info, verify.metadata.info);
+    assert!(sine_error(&verify, 0, Sine::for_channel(0), 0, 480) < 0.05);
+  }
+
+  #[test]
+  fn write_surround() {
+    let audio = sines(48000, 6, 9600);
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write(&mut bytes, &audio, AudioFormat::Opus).unwrap();
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::Opus).unwrap();
+    assert_eq!(6,    verify.channels);
+    assert_eq!(9600, verify.samples.len() / 6);
+    // The channels are back in their original order.
+    for c in 0..6 {
+      assert!(sine_error(&verify, c, Sine::for_channel(c), 0, 480) < 0.05);
+    }
+    assert!(sine_error(&verify, 1, Sine::for_channel(2), 0, 480) > 0.1);
+  }
+
+  #[test]
+  fn not_opus() {
+    let mut bytes = Cursor::new(b"RIFF\x24\x00\x00\x00WAVEfmt ".to_vec());
+    assert!(audio::load(&mut bytes, AudioFormat::Opus).is_err());
+  }
+
+  #[test]
+  fn write_as_unsupported() {
+    let audio = sines(48000, 1, 960);
+    let mut bytes: Vec<u8> = Vec::new();
+    match audio::write_as(&mut bytes, &audio, AudioFormat::Opus, LPCM_I16_LE) {
+      Err(AudioError::Unsupported(_)) => {},
+      _ => panic!("Writing Ogg Opus with another codec should be unsupported")
+    }
+  }
+}
@@ -0,0 +1,283 @@
+//! Opus Packets
+//!
+//! Each packet starts with a table of contents byte giving the mode,
+//! bandwidth, and duration of its frames, whether they are stereo, and how
+//! the packet is split into frames. Packets of a multistream stream are
+//! concatenated, with each packet but the last one self-delimited by an
+//! extra frame length.
+use error::*;
+
+/// Largest number of bytes of a frame.
+const MAX_FRAME_BYTES: usize = 1275;
+
+/// Largest duration of a packet, in samples at 48 kHz.
+const MAX_PACKET_SAMPLES: usize = 5760;
+
+/// The frames of a packet, which share the table of contents byte.
+pub struct Packet<'a> {
+  pub toc:    u8,
+  pub frames: Vec<&'a [u8]>
+}
+
+impl<'a> Packet<'a> {
+  /// Returns the configuration of the frames, giving their mode, bandwidth,
+  /// and duration.
+  #[inline]
+  pub fn config(&self) -> u8 {
+    self.toc >> 3
+  }
+
+  /// Returns the number of channels coded in the frames.
+  #[inline]
+  pub fn channels(&self) -> usize {
+    if self.toc & 0x04 != 0 { 2 } else { 1 }
+  }
+
+  /// Returns the number of samples of each frame at 48 kHz.
+  #[inline]
+  pub fn frame_size(&self) -> usize {
+    frame_size(self.toc)
+  }
+
+  /// Parses a packet from the start of the data, returning the packet and
+  /// the number of bytes it takes up, including padding.
+  pub fn parse(data: &'a [u8], self_delimited: bool) -> AudioResult<(Packet<'a>, usize)> {
+    if data.is_empty() {
+      return Err(invalid("Empty packet"));
+    }
+    let toc = data[0];
+    let mut offset = 1;
+    let mut len = data.len() - 1;
+    let mut padding = 0;
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut cbr = false;
+    let count;
+    let mut last_size = len;
+    match toc & 0x03 {
+      // One frame
+      0 => count = 1,
+      // Two frames of the same size
+      1 => {
+        count = 2;
+        cbr = true;
+        if !self_delimited {
+          if len & 1 != 0 {
+            return Err(invalid("Odd length of two frames of equal size"));
+          }
+          last_size = len / 2;
+          sizes.push(last_size);
+        }
+      },
+      // Two frames of different sizes
+      2 => {
+        count = 2;
+        let (bytes, size) = try!(parse_size(&data[offset..]));
+        len -= bytes;
+        if size > len {
+          return Err(invalid("Frame extends past the end of the packet"));
+        }
+        offset += bytes;
+        last_size = len - size;
+        sizes.push(size);
+      },
+      // Any number of frames, with optional padding
+      _ => {
+        if len < 1 {
+          return Err(invalid("Missing frame count"));
+        }
+        let header = data[offset];
+        offset += 1;
+        len -= 1;
+        count = (header & 0x3F) as usize;
+        if count == 0 || count * frame_size(toc) > MAX_PACKET_SAMPLES {
+          return Err(invalid("Invalid number of frames"));
+        }
+        if header & 0x40 != 0 {
+          loop {
+            if len == 0 {
+              return Err(invalid("Padding extends past the end of the packet"));
+            }
+            let p = data[offset] as usize;
+            offset += 1;
+            len -= 1;
+            let size = if p == 255 { 254 } else { p };
+            if size > len {
+              return Err(invalid("Padding extends past the end of the packet"));
+            }
+            len -= size;
+            padding += size;
+            if p != 255 {
+              break;
+            }
+          }
+        }
+        cbr = header & 0x80 == 0;
+        if !cbr {
+          last_size = len;
+          for _ in 0 .. count - 1 {
+            let (bytes, size) = try!(parse_size(&data[offset..offset + len]));
+            len -= bytes;
+            if size > len || bytes + size > last_size {
+              return Err(invalid("Frame extends past the end of the packet"));
+            }
+            offset += bytes;
+            last_size -= bytes + size;
+            sizes.push(size);
+          }
+        }
+        else if !self_delimited {
+          last_size = len / count;
+          if last_size * count != len {
+            return Err(invalid("Length of frames of equal size does not divide evenly"));
+          }
+          for _ in 0 .. count - 1 {
+            sizes.push(last_size);
+          }
+        }
+      }
+    }
+    if self_delimited {
+      let (bytes, size) = try!(parse_size(&data[offset..offset + len]));
+      len -= bytes;
+      offset += bytes;
+      if cbr {
+        if size * count > len {
+          return Err(invalid("Frame extends past the end of the packet"));
+        }
+        sizes = vec![size; count - 1];
+      }
+      else if bytes + size > last_size {
+        return Err(invalid("Frame extends past the end of the packet"));
+      }
+      last_size = size;
+    }
+    if last_size > MAX_FRAME_BYTES {
+      return Err(invalid("Frame is too long"));
+    }
+    sizes.push(last_size);
+
+    let mut frames: Vec<&[u8]> = Vec::with_capacity(count);
+    for size in sizes.iter() {
+      if *size > MAX_FRAME_BYTES {
+        return Err(invalid("Frame is too long"));
+      }
+      frames.push(&data[offset .. offset + size]);
+      offset += *size;
+    }
+    Ok((Packet { toc: toc, frames: frames }, offset + padding))
+  }
+}
+
+/// Writes a packet of a single frame, self-delimited when it is followed by
+/// the packet of another stream.
+pub fn write(toc: u8, frame: &[u8], self_delimited: bool, data: &mut Vec<u8>) {
+  data.push(toc & !0x03);
+  if self_delimited {
+    if frame.len() < 252 {
+      data.push(frame.len() as u8);
+    }
+    else {
+      let first = 252 + (frame.len() & 0x03);
+      data.push(first as u8);
+      data.push(((frame.len() - first) >> 2) as u8);
+    }
+  }
+  data.extend_from_slice(frame);
+}
+
+// Private functions
+
+/// Returns the number of samples of each frame of a packet at 48 kHz.
+fn frame_size(toc: u8) -> usize {
+  let size = ((toc >> 3) & 0x03) as usize;
+  match toc >> 3 {
+    0 ..= 11  => if size == 3 { 2880 } else { 480 << size },
+    12 ..= 15 => if size & 1 == 0 { 480 } else { 960 },
+    _         => 120 << size
+  }
+}
+
+/// Parses a frame length coded in one or two bytes, returning the number of
+/// bytes and the length.
+fn parse_size(data: &[u8]) -> AudioResult<(usize, usize)> {
+  if data.is_empty() {
+    Err(invalid("Missing frame length"))
+  }
+  else if data[0] < 252 {
+    Ok((1, data[0] as usize))
+  }
+  else if data.len() < 2 {
+    Err(invalid("Missing frame length"))
+  }
+  else {
+    Ok((2, 4 * data[1] as usize + data[0] as usize))
+  }
+}
+
+#[inline]
+fn invalid(reason: &str) -> AudioError {
+  AudioError::Format(format!("File is not valid Ogg Opus ({})", reason))
+}
+
+#[cfg(test)]
+mod framing {
+  use super::*;
+
+  #[test]
+  fn frame_sizes() {
+    let sizes: Vec<usize> = [0x00u8, 0x58, 0x60, 0x68, 0x80, 0x98, 0xF8].iter().map(|toc|
+      frame_size(*toc)
+    ).collect();
+    assert_eq!(vec![480, 2880, 480, 960, 120, 960, 960], sizes);
+  }
+
+  #[test]
+  fn one_frame() {
+    let data = [0xFCu8, 1, 2, 3];
+    let (packet, len) = Packet::parse(&data, false).unwrap();
+    assert_eq!(2, packet.channels());
+    assert_eq!(31, packet.config());
+    assert_eq!(vec![&data[1..]], packet.frames);
+    assert_eq!(4, len);
+  }
+
+  #[test]
+  fn two_frames() {
+    let data = [0xF9u8, 1, 2, 3, 4];
+    let (packet, _) = Packet::parse(&data, false).unwrap();
+    assert_eq!(vec![&data[1..3], &data[3..5]], packet.frames);
+    assert!(Packet::parse(&data[..4], false).is_err());
+
+    let data = [0xFAu8, 1, 9, 3, 4];
+    let (packet, _) = Packet::parse(&data, false).unwrap();
+    assert_eq!(vec![&data[2..3], &data[3..5]], packet.frames);
+    assert!(Packet::parse(&[0xFAu8, 5, 1], false).is_err());
+  }
+
+  #[test]
+  fn padded_frames() {
+    // Three frames of two bytes, with a byte of padding.
+    let data = [0xFBu8, 0x43, 1, 1, 2, 3, 4, 5, 6, 0];
+    let (packet, len) = Packet::parse(&data, false).unwrap();
+    assert_eq!(vec![&data[3..5], &data[5..7], &data[7..9]], packet.frames);
+    assert_eq!(10, len);
+    // Variable frame sizes.
+    let data = [0xFBu8, 0x82, 1, 9, 3, 4];
+    let (packet, _) = Packet::parse(&data, false).unwrap();
+    assert_eq!(vec![&data[3..4], &data[4..6]], packet.frames);
+  }
+
+  #[test]
+  fn self_delimited() {
+    let mut data: Vec<u8> = Vec::new();
+    let first:  Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let second: Vec<u8> = vec![7u8; 20];
+    write(0xF8, &first, true, &mut data);
+    write(0xFC, &second, false, &mut data);
+    let (packet, len) = Packet::parse(&data, true).unwrap();
+    assert_eq!(vec![&first[..]], packet.frames);
+    let (packet, _) = Packet::parse(&data[len..], false).unwrap();
+    assert_eq!(2, packet.channels());
+    assert_eq!(vec![&second[..]], packet.frames);
+  }
+}
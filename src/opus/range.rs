@@ -0,0 +1,590 @@
+//! Range Coder
+//!
+//! Opus packets are coded with a range coder, which codes symbols from the
+//! start of the packet, and raw bits from the end of the packet. The same
+//! struct is used for encoding and decoding, which lets code shared by the
+//! encoder and decoder measure the bits used in the same way.
+
+/// Number of bits of a symbol written to the packet.
+const SYM_BITS: u32 = 8;
+
+/// Number of bits of the range.
+const CODE_BITS: u32 = 32;
+
+const SYM_MAX:    u32 = (1 << SYM_BITS) - 1;
+const CODE_SHIFT: u32 = CODE_BITS - SYM_BITS - 1;
+const CODE_TOP:   u32 = 1 << (CODE_BITS - 1);
+const CODE_BOT:   u32 = CODE_TOP >> SYM_BITS;
+const CODE_EXTRA: u32 = (CODE_BITS - 2) % SYM_BITS + 1;
+
+/// Number of bits of a value coded by the range coder, above which the
+/// remaining bits of `decode_uint` values are coded as raw bits.
+const UINT_BITS: u32 = 8;
+
+/// Number of fractional bits returned by `tell_frac`.
+pub const BITRES: i32 = 3;
+
+/// State of a range encoder or decoder.
+pub struct RangeCoder {
+  buffer:      Vec<u8>,
+  storage:     u32,
+  /// Number of raw bytes read from or written to the end of the buffer
+  end_offset:  u32,
+  end_window:  u32,
+  end_bits:    i32,
+  /// Number of whole bits read or written
+  total_bits:  i32,
+  offset:      u32,
+  range:       u32,
+  /// In the decoder, the difference between the top of the range and the
+  /// input value, minus one. In the encoder, the low end of the range.
+  value:       u32,
+  /// In the decoder, the saved normalization factor of `decode`. In the
+  /// encoder, the number of buffered bytes awaiting carry propagation.
+  ext:         u32,
+  remainder:   i32,
+  pub error:   bool
+}
+
+impl RangeCoder {
+  /// Creates a decoder reading from the given packet data.
+  pub fn decoder(data: &[u8]) -> RangeCoder {
+    let mut coder = RangeCoder {
+      buffer:     data.to_vec(),
+      storage:    data.len() as u32,
+      end_offset: 0,
+      end_window: 0,
+      end_bits:   0,
+      total_bits: (CODE_BITS + 1 - ((CODE_BITS - CODE_EXTRA) / SYM_BITS) * SYM_BITS) as i32,
+      offset:     0,
+      range:      1 << CODE_EXTRA,
+      value:      0,
+      ext:        0,
+      remainder:  0,
+      error:      false
+    };
+    coder.remainder = coder.read_byte() as i32;
+    coder.value = coder.range - 1 - (coder.remainder as u32 >> (SYM_BITS - CODE_EXTRA));
+    coder.normalize_decoder();
+    coder
+  }
+
+  /// Creates an encoder writing a packet of the given size.
+  pub fn encoder(size: usize) -> RangeCoder {
+    RangeCoder {
+      buffer:     vec![0u8; size],
+      storage:    size as u32,
+      end_offset: 0,
+      end_window: 0,
+      end_bits:   0,
+      total_bits: (CODE_BITS + 1) as i32,
+      offset:     0,
+      range:      CODE_TOP,
+      value:      0,
+      ext:        0,
+      remainder:  -1,
+      error:      false
+    }
+  }
+
+  /// Returns the number of bytes available for the packet.
+  #[inline]
+  pub fn storage(&self) -> u32 {
+    self.storage
+  }
+
+  /// Returns the number of bits used so far, rounded up.
+  #[inline]
+  pub fn tell(&self) -> i32 {
+    self.total_bits - ilog(self.range)
+  }
+
+  /// Returns the number of bits used so far in eighths of a bit, rounded
+  /// up.
+  pub fn tell_frac(&self) -> i32 {
+    const CORRECTION: [u32; 8] = [35733, 38967, 42495, 46340, 50535, 55109, 60097, 65535];
+    let bits = self.total_bits << BITRES;
+    let mut l = ilog(self.range);
+    let r = self.range >> (l - 16);
+    let mut b = (r >> 12) - 8;
+    if r > CORRECTION[b as usize] {
+      b += 1;
+    }
+    l = (l << 3) + b as i32;
+    bits - l
+  }
+
+  /// Counts the remaining bits of the packet as used, so that nothing else
+  /// is coded in a silent frame.
+  pub fn skip_to_end(&mut self) {
+    let tell = self.tell();
+    self.total_bits += self.storage as i32 * 8 - tell;
+  }
+
+  /// Leaves out bytes at the end of the packet, which hold other data.
+  pub fn shrink(&mut self, bytes: u32) {
+    self.storage -= bytes;
+  }
+
+  /// Returns the final range, which is used to check that the encoder and
+  /// decoder agree.
+  #[inline]
+  pub fn range(&self) -> u32 {
+    self.range
+  }
+
+  // Decoding
+
+  fn read_byte(&mut self) -> u32 {
+    if self.offset < self.storage {
+      self.offset += 1;
+      self.buffer[self.offset as usize - 1] as u32
+    } else {
+      0
+    }
+  }
+
+  fn read_byte_from_end(&mut self) -> u32 {
+    if self.end_offset < self.storage {
+      self.end_offset += 1;
+      self.buffer[(self.storage - self.end_offset) as usize] as u32
+    } else {
+      0
+    }
+  }
+
+  fn normalize_decoder(&mut self) {
+    while self.range <= CODE_BOT {
+      self.total_bits += SYM_BITS as i32;
+      self.range <<= SYM_BITS;
+      let mut symbol = self.remainder as u32;
+      self.remainder = self.read_byte() as i32;
+      symbol = (symbol << SYM_BITS | self.remainder as u32) >> (SYM_BITS - CODE_EXTRA);
+      self.value = (self.value.wrapping_shl(SYM_BITS) + (SYM_MAX & !symbol)) & (CODE_TOP - 1);
+    }
+  }
+
+  /// Returns the cumulative frequency of the next symbol out of `total`,
+  /// which must be followed by a call to `update`.
+  pub fn decode(&mut self, total: u32) -> u32 {
+    self.ext = self.range / total;
+    let s = self.value / self.ext;
+    total - (s + 1).min(total)
+  }
+
+  /// Same as `decode` with a total frequency of `1 << bits`.
+  pub fn decode_bin(&mut self, bits: u32) -> u32 {
+    self.ext = self.range >> bits;
+    let s = self.value / self.ext;
+    (1 << bits) - (s + 1).min(1 << bits)
+  }
+
+  /// Advances past the decoded symbol with the given frequency range.
+  pub fn update(&mut self, low: u32, high: u32, total: u32) {
+    let s = self.ext.wrapping_mul(total - high);
+    self.value = self.value.wrapping_sub(s);
+    self.range =
+      if low > 0 {
+        self.ext.wrapping_mul(high - low)
+      } else {
+        self.range - s
+      };
+    self.normalize_decoder();
+  }
+
+  /// Decodes a bit whose probability of being set is `1 / (1 << logp)`.
+  pub fn decode_bit_logp(&mut self, logp: u32) -> bool {
+    let r = self.range;
+    let d = self.value;
+    let s = r >> logp;
+    let bit = d < s;
+    if !bit {
+      self.value = d - s;
+    }
+    self.range = if bit { s } else { r - s };
+    self.normalize_decoder();
+    bit
+  }
+
+  /// Decodes a symbol using an inverse cumulative distribution function
+  /// table, whose values are out of `1 << bits`.
+  pub fn decode_icdf(&mut self, icdf: &[u8], bits: u32) -> usize {
+    let mut s = self.range;
+    let d = self.value;
+    let r = s >> bits;
+    let mut symbol = 0;
+    let mut t;
+    loop {
+      t = s;
+      s = r.wrapping_mul(icdf[symbol] as u32);
+      if d >= s {
+        break;
+      }
+      symbol += 1;
+    }
+    self.value = d - s;
+    self.range = t - s;
+    self.normalize_decoder();
+    symbol
+  }
+
+  /// Decodes a uniformly distributed value less than `total`.
+  pub fn decode_uint(&mut self, total: u32) -> u32 {
+    debug_assert!(total > 1);
+    let ft = total - 1;
+    let mut bits = ilog(ft) as u32;
+    if bits > UINT_BITS {
+      bits -= UINT_BITS;
+      let ft_high = (ft >> bits) + 1;
+      let s = self.decode(ft_high);
+      self.update(s, s + 1, ft_high);
+      let t = s << bits | self.decode_bits(bits);
+      if t <= ft {
+        return t;
+      }
+      self.error = true;
+      ft
+    }
+    else {
+      let s = self.decode(total);
+      self.update(s, s + 1, total);
+      s
+    }
+  }
+
+  /// Decodes raw bits from the end of the packet.
+  pub fn decode_bits(&mut self, bits: u32) -> u32 {
+    let mut window    = self.end_window;
+    let mut available = self.end_bits;
+    if (available as u32) < bits {
+      loop {
+        window |= self.read_byte_from_end() << available;
+        available += SYM_BITS as i32;
+        if available > 32 - SYM_BITS as i32 {
+          break;
+        }
+      }
+    }
+    let value = if bits == 32 { window } else { window & ((1 << bits) - 1) };
+    window = if bits == 32 { 0 } else { window >> bits };
+    available -= bits as i32;
+    self.end_window  = window;
+    self.end_bits    = available;
+    self.total_bits += bits as i32;
+    value
+  }
+
+  // Encoding
+
+  fn write_byte(&mut self, value: u32) {
+    if self.offset + self.end_offset >= self.storage {
+      self.error = true;
+      return;
+    }
+    self.buffer[self.offset as usize] = value as u8;
+    self.offset += 1;
+  }
+
+  fn write_byte_at_end(&mut self, value: u32) {
+    if self.offset + self.end_offset >= self.storage {
+      self.error = true;
+      return;
+    }
+    self.end_offset += 1;
+    self.buffer[(self.storage - self.end_offset) as usize] = value as u8;
+  }
+
+  /// Outputs a symbol, buffering symbols that may still be changed by a
+  /// carry.
+  fn carry_out(&mut self, c: u32) {
+    if c != SYM_MAX {
+      let carry = c >> SYM_BITS;
+      if self.remainder >= 0 {
+        let byte = self.remainder as u32 + carry;
+        self.write_byte(byte);
+      }
+      if self.ext > 0 {
+        let symbol = (SYM_MAX + carry) & SYM_MAX;
+        while self.ext > 0 {
+          self.write_byte(symbol);
+          self.ext -= 1;
+        }
+      }
+      self.remainder = (c & SYM_MAX) as i32;
+    }
+    else {
+      self.ext += 1;
+    }
+  }
+
+  fn normalize_encoder(&mut self) {
+    while self.range <= CODE_BOT {
+      let c = self.value >> CODE_SHIFT;
+      self.carry_out(c);
+      self.value = (self.value << SYM_BITS) & (CODE_TOP - 1);
+      self.range <<= SYM_BITS;
+      self.total_bits += SYM_BITS as i32;
+    }
+  }
+
+  /// Encodes a symbol with the given cumulative frequency range out of
+  /// `total`.
+  pub fn encode(&mut self, low: u32, high: u32, total: u32) {
+    let r = self.range / total;
+    if low > 0 {
+      self.value += self.range - r * (total - low);
+      self.range  = r * (high - low);
+    }
+    else {
+      self.range -= r * (total - high);
+    }
+    self.normalize_encoder();
+  }
+
+  /// Same as `encode` with a total frequency of `1 << bits`.
+  pub fn encode_bin(&mut self, low: u32, high: u32, bits: u32) {
+    let r = self.range >> bits;
+    if low > 0 {
+      self.value += self.range - r * ((1 << bits) - low);
+      self.range  = r * (high - low);
+    }
+    else {
+      self.range -= r * ((1 << bits) - high);
+    }
+    self.normalize_encoder();
+  }
+
+  /// Encodes a bit whose probability of being set is `1 / (1 << logp)`.
+  pub fn encode_bit_logp(&mut self, bit: bool, logp: u32) {
+    let s = self.range >> logp;
+    let r = self.range - s;
+    if bit {
+      self.value += r;
+    }
+    self.range = if bit { s } else { r };
+    self.normalize_encoder();
+  }
+
+  /// Encodes a symbol using an inverse cumulative distribution function
+  /// table, whose values are out of `1 << bits`.
+  pub fn encode_icdf(&mut self, symbol: usize, icdf: &[u8], bits: u32) {
+    let r = self.range >> bits;
+    if symbol > 0 {
+      self.value += self.range - r * icdf[symbol - 1] as u32;
+      self.range  = r * (icdf[symbol - 1] as u32 - icdf[symbol] as u32);
+    }
+    else {
+      self.range -= r * icdf[symbol] as u32;
+    }
+    self.normalize_encoder();
+  }
+
+  /// Encodes a uniformly distributed value less than `total`.
+  pub fn encode_uint(&mut self, value: u32, total: u32) {
+    debug_assert!(total > 1);
+    let ft = total - 1;
+    let mut bits = ilog(ft) as u32;
+    if bits > UINT_BITS {
+      bits -= UINT_BITS;
+      let ft_high = (ft >> bits) + 1;
+      let low = value >> bits;
+      self.encode(low, low + 1, ft_high);
+      self.encode_bits(value & ((1 << bits) - 1), bits);
+    }
+    else {
+      self.encode(value, value + 1, total);
+    }
+  }
+
+  /// Encodes raw bits at the end of the packet.
+  pub fn encode_bits(&mut self, value: u32, bits: u32) {
+    let mut window = self.end_window;
+    let mut used   = self.end_bits;
+    if used as u32 + bits > 32 {
+      loop {
+        self.write_byte_at_end(window & SYM_MAX);
+        window >>= SYM_BITS;
+        used -= SYM_BITS as i32;
+        if used < SYM_BITS as i32 {
+          break;
+        }
+      }
+    }
+    window |= value << used;
+    used += bits as i32;
+    self.end_window  = window;
+    self.end_bits    = used;
+    self.total_bits += bits as i32;
+  }
+
+  /// Flushes the encoder, returning the packet data.
+  pub fn finish(mut self) -> Vec<u8> {
+    // Output the fewest bits that decode to the current range regardless of
+    // the bits that follow.
+    let mut l = CODE_BITS as i32 - ilog(self.range);
+    let mut mask = (CODE_TOP - 1) >> l;
+    let mut end  = (self.value.wrapping_add(mask)) & !mask;
+    if (end | mask) >= self.value.wrapping_add(self.range) {
+      l += 1;
+      mask >>= 1;
+      end = (self.value.wrapping_add(mask)) & !mask;
+    }
+    while l > 0 {
+      self.carry_out(end >> CODE_SHIFT);
+      end = (end << SYM_BITS) & (CODE_TOP - 1);
+      l -= SYM_BITS as i32;
+    }
+    if self.remainder >= 0 || self.ext > 0 {
+      self.carry_out(0);
+    }
+    let mut window = self.end_window;
+    let mut used   = self.end_bits;
+    while used >= SYM_BITS as i32 {
+      self.write_byte_at_end(window & SYM_MAX);
+      window >>= SYM_BITS;
+      used -= SYM_BITS as i32;
+    }
+    if !self.error {
+      for i in self.offset .. self.storage - self.end_offset {
+        self.buffer[i as usize] = 0;
+      }
+      if used > 0 {
+        if self.end_offset >= self.storage {
+          self.error = true;
+        }
+        else {
+          let l = -l;
+          if self.offset + self.end_offset >= self.storage && l < used {
+            window &= (1 << l) - 1;
+            self.error = true;
+          }
+          let index = (self.storage - self.end_offset - 1) as usize;
+          self.buffer[index] |= window as u8;
+        }
+      }
+    }
+    self.buffer
+  }
+
+  // Laplace distribution used to code band energies
+
+  /// Decodes a value from a Laplace-like distribution, given the
+  /// probability of zero and the decay of the distribution, both out of
+  /// 32768.
+  pub fn decode_laplace(&mut self, fs: u32, decay: i32) -> i32 {
+    let mut value = 0;
+    let fm = self.decode_bin(15);
+    let mut fl = 0;
+    let mut fs = fs;
+    if fm >= fs {
+      value += 1;
+      fl = fs;
+      fs = laplace_freq1(fs, decay) + LAPLACE_MINP;
+      while fs > LAPLACE_MINP && fm >= fl + 2 * fs {
+        fs *= 2;
+        fl += fs;
+        fs = (((fs - 2 * LAPLACE_MINP) as i32 * decay) >> 15) as u32;
+        fs += LAPLACE_MINP;
+        value += 1;
+      }
+      if fs <= LAPLACE_MINP {
+        let di = (fm - fl) >> 1;
+        value += di as i32;
+        fl += 2 * di * LAPLACE_MINP;
+      }
+      if fm < fl + fs {
+        value = -value;
+      } else {
+        fl += fs;
+      }
+    }
+    self.update(fl, (fl + fs).min(32768), 32768);
+    value
+  }
+
+  /// Encodes a value from a Laplace-like distribution, returning the value
+  /// that was coded, which is clamped to the values the distribution can
+  /// represent.
+  pub fn encode_laplace(&mut self, value: i32, fs: u32, decay: i32) -> i32 {
+    let mut coded = value;
+    let mut fl = 0u32;
+    let mut fs = fs;
+    if value != 0 {
+      let s: i32 = if value < 0 { -1 } else { 0 };
+      let val = (value + s) ^ s;
+      fl = fs;
+      fs = laplace_freq1(fs, decay);
+      let mut i = 1;
+      while fs > 0 && i < val {
+        fs *= 2;
+        fl += fs + 2 * LAPLACE_MINP;
+        fs = ((fs as i32 * decay) >> 15) as u32;
+        i += 1;
+      }
+      if fs == 0 {
+        let mut ndi_max = (32768 - fl + LAPLACE_MINP - 1) as i32;
+        ndi_max = (ndi_max - s) >> 1;
+        let di = (val - i).min(ndi_max - 1);
+        fl += ((2 * di + 1 + s) as u32) * LAPLACE_MINP;
+        fs = LAPLACE_MINP.min(32768 - fl);
+        coded = (i + di + s) ^ s;
+      }
+      else {
+        fs += LAPLACE_MINP;
+        if s == 0 {
+          fl += fs;
+        }
+      }
+    }
+    self.encode_bin(fl, fl + fs, 15);
+    coded
+  }
+}
+
+/// Minimum probability of an energy delta, out of 32768.
+const LAPLACE_MINP: u32 = 1;
+
+/// Minimum number of energy deltas that can be coded in each direction.
+const LAPLACE_NMIN: u32 = 16;
+
+#[inline]
+fn laplace_freq1(fs0: u32, decay: i32) -> u32 {
+  let ft = 32768 - LAPLACE_MINP * (2 * LAPLACE_NMIN) - fs0;
+  ((ft as i32 * (16384 - decay)) >> 15) as u32
+}
+
+/// Returns the number of bits needed to represent the value.
+#[inline]
+pub fn ilog(value: u32) -> i32 {
+  32 - value.leading_zeros() as i32
+}
+
+#[cfg(test)]
+mod coding {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let icdf = [200u8, 120, 40, 0];
+    let mut encoder = RangeCoder::encoder(64);
+    for i in 0..20u32 {
+      encoder.encode_bit_logp(i % 3 == 0, 2);
+      encoder.encode_icdf((i % 4) as usize, &icdf, 8);
+      encoder.encode_uint(i * 37 % 1000, 1000);
+      encoder.encode_bits(i % 8, 3);
+      encoder.encode_laplace(i as i32 % 7 - 3, 6000, 5000);
+    }
+    let tell = encoder.tell();
+    let data = encoder.finish();
+    let mut decoder = RangeCoder::decoder(&data);
+    for i in 0..20u32 {
+      assert_eq!(i % 3 == 0,         decoder.decode_bit_logp(2));
+      assert_eq!((i % 4) as usize,   decoder.decode_icdf(&icdf, 8));
+      assert_eq!(i * 37 % 1000,      decoder.decode_uint(1000));
+      assert_eq!(i % 8,              decoder.decode_bits(3));
+      assert_eq!(i as i32 % 7 - 3,   decoder.decode_laplace(6000, 5000));
+    }
+    assert_eq!(tell, decoder.tell());
+    assert!(!decoder.error);
+  }
+}
@@ -0,0 +1,424 @@
+//! CELT Bit Allocation
+//!
+//! The bits of a frame are split between bands using the allocation
+//! vectors, interpolated to use the bits available. The encoder and decoder
+//! compute the same allocation, apart from the few decisions the encoder
+//! signals in the stream.
+use opus::range::{RangeCoder, BITRES};
+use opus::tables::*;
+
+/// Number of bisection steps used to interpolate between allocation
+/// vectors.
+const ALLOC_STEPS: i32 = 6;
+
+/// Number of bisection steps used to search the pulse cache.
+const LOG_MAX_PSEUDO: i32 = 6;
+
+/// Allocation of the bits of a frame.
+pub struct Allocation {
+  /// Bits of each band for the shape, in eighths of a bit
+  pub pulses:         [i32; BANDS],
+  /// Number of fine energy bits of each band
+  pub fine_quant:     [i32; BANDS],
+  /// Whether each band is given priority for the remaining fine bits
+  pub fine_priority:  [i32; BANDS],
+  /// Number of bands coded with pulses
+  pub coded_bands:    usize,
+  /// Bits left over after capping the bands
+  pub balance:        i32,
+  /// First band coded with intensity stereo
+  pub intensity:      usize,
+  pub dual_stereo:    bool
+}
+
+/// Returns the largest number of bits each band can use.
+pub fn init_caps(lm: usize, channels: usize) -> [i32; BANDS] {
+  let mut caps = [0i32; BANDS];
+  for i in 0..BANDS {
+    let n = ((EBANDS[i + 1] - EBANDS[i]) << lm) as i32;
+    caps[i] = (CACHE_CAPS[BANDS * (2 * lm + channels - 1) + i] as i32 + 64)
+              * channels as i32 * n >> 2;
+  }
+  caps
+}
+
+/// Returns the number of pulses of a pseudo-pulse index.
+#[inline]
+pub fn get_pulses(i: i32) -> i32 {
+  if i < 8 { i } else { (8 + (i & 7)) << ((i >> 3) - 1) }
+}
+
+/// Returns the pseudo-pulse index whose number of bits is closest to the
+/// given number of bits.
+pub fn bits2pulses(band: usize, lm: i32, bits: i32) -> i32 {
+  let cache = pulse_cache(band, lm + 1);
+  let mut lo = 0;
+  let mut hi = cache[0] as i32;
+  let bits = bits - 1;
+  for _ in 0..LOG_MAX_PSEUDO {
+    let mid = (lo + hi + 1) >> 1;
+    if cache[mid as usize] as i32 >= bits {
+      hi = mid;
+    } else {
+      lo = mid;
+    }
+  }
+  let low_bits = if lo == 0 { -1 } else { cache[lo as usize] as i32 };
+  if bits - low_bits <= cache[hi as usize] as i32 - bits { lo } else { hi }
+}
+
+/// Returns the number of bits needed by a pseudo-pulse index.
+#[inline]
+pub fn pulses2bits(band: usize, lm: i32, pulses: i32) -> i32 {
+  if pulses == 0 {
+    0
+  } else {
+    pulse_cache(band, lm + 1)[pulses as usize] as i32 + 1
+  }
+}
+
+#[inline]
+fn pulse_cache(band: usize, lm: i32) -> &'static [u8] {
+  let index = CACHE_INDEX[lm as usize * BANDS + band];
+  &CACHE_BITS[index as usize ..]
+}
+
+/// Computes the allocation of the bits of a frame.
+///
+/// The encoder chooses the bands to skip and the stereo parameters, given by
+/// `intensity` and `dual_stereo`, while the decoder reads them.
+pub fn compute_allocation(start: usize, end: usize, offsets: &[i32], caps: &[i32],
+                          alloc_trim: i32, intensity: usize, dual_stereo: bool,
+                          total: i32, channels: usize, lm: usize, coder: &mut RangeCoder,
+                          encode: bool, prev: usize, signal_bandwidth: usize) -> Allocation {
+  let c  = channels as i32;
+  let lm_shift = lm as i32;
+  let mut total = total.max(0);
+  let mut skip_start = start;
+  // Reserve a bit to signal the end of manually skipped bands.
+  let skip_rsv = if total >= 1 << BITRES { 1 << BITRES } else { 0 };
+  total -= skip_rsv;
+  // Reserve bits for the intensity and dual stereo parameters.
+  let mut intensity_rsv   = 0;
+  let mut dual_stereo_rsv = 0;
+  if channels == 2 {
+    intensity_rsv = LOG2_FRAC_TABLE[end - start];
+    if intensity_rsv > total {
+      intensity_rsv = 0;
+    }
+    else {
+      total -= intensity_rsv;
+      dual_stereo_rsv = if total >= 1 << BITRES { 1 << BITRES } else { 0 };
+      total -= dual_stereo_rsv;
+    }
+  }
+
+  let mut bits1       = [0i32; BANDS];
+  let mut bits2       = [0i32; BANDS];
+  let mut thresh      = [0i32; BANDS];
+  let mut trim_offset = [0i32; BANDS];
+  for j in start..end {
+    let n = (EBANDS[j + 1] - EBANDS[j]) as i32;
+    // Below this threshold, no PVQ bits are allocated.
+    thresh[j] = (c << BITRES).max((3 * n << lm_shift << BITRES) >> 4);
+    // Tilt of the allocation curve
+    trim_offset[j] = c * n * (alloc_trim - 5 - lm_shift) * (end - j - 1) as i32
+                     * (1 << (lm_shift + BITRES)) >> 6;
+    // Single coefficient bands benefit more from coarse energy.
+    if n << lm_shift == 1 {
+      trim_offset[j] -= c << BITRES;
+    }
+  }
+  let mut lo = 1i32;
+  let mut hi = ALLOC_VECTORS as i32 - 1;
+  loop {
+    let mut done = false;
+    let mut psum = 0;
+    let mid = (lo + hi) >> 1;
+    for j in (start..end).rev() {
+      let n = (EBANDS[j + 1] - EBANDS[j]) as i32;
+      let mut bitsj = c * n * (BAND_ALLOCATION[mid as usize][j] as i32) << lm_shift >> 2;
+      if bitsj > 0 {
+        bitsj = (bitsj + trim_offset[j]).max(0);
+      }
+      bitsj += offsets[j];
+      if bitsj >= thresh[j] || done {
+        done = true;
+        psum += bitsj.min(caps[j]);
+      }
+      else if bitsj >= c << BITRES {
+        psum += c << BITRES;
+      }
+    }
+    if psum > total {
+      hi = mid - 1;
+    } else {
+      lo = mid + 1;
+    }
+    if lo > hi {
+      break;
+    }
+  }
+  hi = lo;
+  lo -= 1;
+  for j in start..end {
+    let n = (EBANDS[j + 1] - EBANDS[j]) as i32;
+    let mut bits1j = c * n * (BAND_ALLOCATION[lo as usize][j] as i32) << lm_shift >> 2;
+    let mut bits2j =
+      if hi >= ALLOC_VECTORS as i32 {
+        caps[j]
+      } else {
+        c * n * (BAND_ALLOCATION[hi as usize][j] as i32) << lm_shift >> 2
+      };
+    if bits1j > 0 {
+      bits1j = (bits1j + trim_offset[j]).max(0);
+    }
+    if bits2j > 0 {
+      bits2j = (bits2j + trim_offset[j]).max(0);
+    }
+    if lo > 0 {
+      bits1j += offsets[j];
+    }
+    bits2j += offsets[j];
+    if offsets[j] > 0 {
+      skip_start = j;
+    }
+    bits1[j] = bits1j;
+    bits2[j] = (bits2j - bits1j).max(0);
+  }
+
+  interp_bits2pulses(start, end, skip_start, &bits1, &bits2, &thresh, caps, total,
+                     skip_rsv, intensity, intensity_rsv, dual_stereo, dual_stereo_rsv,
+                     channels, lm, coder, encode, prev, signal_bandwidth)
+}
+
+fn interp_bits2pulses(start: usize, end: usize, skip_start: usize,
+                      bits1: &[i32], bits2: &[i32], thresh: &[i32], caps: &[i32],
+                      total: i32, skip_rsv: i32, intensity: usize, intensity_rsv: i32,
+                      dual_stereo: bool, dual_stereo_rsv: i32, channels: usize, lm: usize,
+                      coder: &mut RangeCoder, encode: bool, prev: usize,
+                      signal_bandwidth: usize) -> Allocation {
+  let c = channels as i32;
+  let stereo = (channels > 1) as i32;
+  let alloc_floor = c << BITRES;
+  let log_m = (lm as i32) << BITRES;
+  let mut total           = total;
+  let mut intensity_rsv   = intensity_rsv;
+  let mut dual_stereo_rsv = dual_stereo_rsv;
+
+  // Find the interpolation between the allocation vectors that uses the
+  // bits available.
+  let mut lo = 0;
+  let mut hi = 1 << ALLOC_STEPS;
+  for _ in 0..ALLOC_STEPS {
+    let mid = (lo + hi) >> 1;
+    let mut psum = 0;
+    let mut done = false;
+    for j in (start..end).rev() {
+      let tmp = bits1[j] + (mid * bits2[j] >> ALLOC_STEPS);
+      if tmp >= thresh[j] || done {
+        done = true;
+        psum += tmp.min(caps[j]);
+      }
+      else if tmp >= alloc_floor {
+        psum += alloc_floor;
+      }
+    }
+    if psum > total {
+      hi = mid;
+    } else {
+      lo = mid;
+    }
+  }
+  let mut bits = [0i32; BANDS];
+  let mut psum = 0;
+  let mut done = false;
+  for j in (start..end).rev() {
+    let mut tmp = bits1[j] + (lo * bits2[j] >> ALLOC_STEPS);
+    if tmp < thresh[j] && !done {
+      tmp = if tmp >= alloc_floor { alloc_floor } else { 0 };
+    } else {
+      done = true;
+    }
+    tmp = tmp.min(caps[j]);
+    bits[j] = tmp;
+    psum += tmp;
+  }
+
+  // Decide which bands to skip, working backwards from the end.
+  let mut coded_bands = end;
+  loop {
+    let j = coded_bands - 1;
+    // Never skip the first band, nor a band boosted by dynamic allocation.
+    if j <= skip_start {
+      total += skip_rsv;
+      break;
+    }
+    // Find the left over bits this band would be given, including the bits
+    // taken back from skipped bands.
+    let mut left = total - psum;
+    let percoeff = left / (EBANDS[coded_bands] - EBANDS[start]) as i32;
+    left -= (EBANDS[coded_bands] - EBANDS[start]) as i32 * percoeff;
+    let rem = (left - (EBANDS[j] - EBANDS[start]) as i32).max(0);
+    let band_width = (EBANDS[coded_bands] - EBANDS[j]) as i32;
+    let mut band_bits = bits[j] + percoeff * band_width + rem;
+    // The skip decision is only coded above the threshold of the band, and
+    // bands below it are always skipped.
+    if band_bits >= thresh[j].max(alloc_floor + (1 << BITRES)) {
+      if encode {
+        let depth_threshold =
+          if coded_bands > 17 {
+            if j < prev { 7 } else { 9 }
+          } else {
+            0
+          };
+        if coded_bands <= start + 2 ||
+           (band_bits > (depth_threshold * band_width << lm << BITRES) >> 4 &&
+            j <= signal_bandwidth) {
+          coder.encode_bit_logp(true, 1);
+          break;
+        }
+        coder.encode_bit_logp(false, 1);
+      }
+      else if coder.decode_bit_logp(1) {
+        break;
+      }
+      // A bit was used to skip the band.
+      psum      += 1 << BITRES;
+      band_bits -= 1 << BITRES;
+    }
+    // Take back the bits allocated to the band.
+    psum -= bits[j] + intensity_rsv;
+    if intensity_rsv > 0 {
+      intensity_rsv = LOG2_FRAC_TABLE[j - start];
+    }
+    psum += intensity_rsv;
+    if band_bits >= alloc_floor {
+      // Keep a fine energy bit for each channel.
+      psum += alloc_floor;
+      bits[j] = alloc_floor;
+    }
+    else {
+      bits[j] = 0;
+    }
+    coded_bands -= 1;
+  }
+
+  // Code the intensity and dual stereo parameters.
+  let mut intensity = intensity;
+  let mut dual_stereo = dual_stereo;
+  if intensity_rsv > 0 {
+    if encode {
+      intensity = intensity.min(coded_bands);
+      coder.encode_uint((intensity - start) as u32, (coded_bands + 1 - start) as u32);
+    }
+    else {
+      intensity = start + coder.decode_uint((coded_bands + 1 - start) as u32) as usize;
+    }
+  }
+  else {
+    intensity = 0;
+  }
+  if intensity <= start {
+    total += dual_stereo_rsv;
+    dual_stereo_rsv = 0;
+  }
+  if dual_stereo_rsv > 0 {
+    if encode {
+      coder.encode_bit_logp(dual_stereo, 1);
+    } else {
+      dual_stereo = coder.decode_bit_logp(1);
+    }
+  }
+  else {
+    dual_stereo = false;
+  }
+
+  // Allocate the remaining bits.
+  let mut left = total - psum;
+  let percoeff = left / (EBANDS[coded_bands] - EBANDS[start]) as i32;
+  left -= (EBANDS[coded_bands] - EBANDS[start]) as i32 * percoeff;
+  for j in start..coded_bands {
+    bits[j] += percoeff * (EBANDS[j + 1] - EBANDS[j]) as i32;
+  }
+  for j in start..coded_bands {
+    let tmp = left.min((EBANDS[j + 1] - EBANDS[j]) as i32);
+    bits[j] += tmp;
+    left -= tmp;
+  }
+
+  let mut fine_quant    = [0i32; BANDS];
+  let mut fine_priority = [0i32; BANDS];
+  let mut balance = 0;
+  for j in start..coded_bands {
+    let n0 = (EBANDS[j + 1] - EBANDS[j]) as i32;
+    let n  = n0 << lm;
+    let bit = bits[j] + balance;
+    let mut excess;
+    if n > 1 {
+      excess  = (bit - caps[j]).max(0);
+      bits[j] = bit - excess;
+      // Compensate for the extra degree of freedom in stereo.
+      let den = c * n +
+        if channels == 2 && n > 2 && !dual_stereo && j < intensity { 1 } else { 0 };
+      let nc_log_n = den * (LOG_N[j] + log_m);
+      // Offset the number of fine bits by log2(N) / 2 + FINE_OFFSET compared
+      // to the fair share of the band.
+      let mut offset = (nc_log_n >> 1) - den * FINE_OFFSET;
+      if n == 2 {
+        offset += den << BITRES >> 2;
+      }
+      // Change the offset for the second and third fine energy bits.
+      if bits[j] + offset < den * 2 << BITRES {
+        offset += nc_log_n >> 2;
+      }
+      else if bits[j] + offset < den * 3 << BITRES {
+        offset += nc_log_n >> 3;
+      }
+      // Divide with rounding.
+      fine_quant[j] = (bits[j] + offset + (den << (BITRES - 1))).max(0);
+      fine_quant[j] = (fine_quant[j] / den) >> BITRES;
+      if c * fine_quant[j] > bits[j] >> BITRES {
+        fine_quant[j] = bits[j] >> stereo >> BITRES;
+      }
+      fine_quant[j] = fine_quant[j].min(MAX_FINE_BITS);
+      // Bands that were rounded down or capped are candidates for the final
+      // fine energy pass.
+      fine_priority[j] = (fine_quant[j] * (den << BITRES) >= bits[j] + offset) as i32;
+      // The remaining bits are used for the shape.
+      bits[j] -= c * fine_quant[j] << BITRES;
+    }
+    else {
+      // Single coefficient bands use all bits for fine energy, apart from a
+      // sign bit.
+      excess = (bit - (c << BITRES)).max(0);
+      bits[j] = bit - excess;
+      fine_quant[j] = 0;
+      fine_priority[j] = 1;
+    }
+    // Bits over the cap are used for fine energy.
+    if excess > 0 {
+      let extra_fine = (excess >> (stereo + BITRES)).min(MAX_FINE_BITS - fine_quant[j]);
+      fine_quant[j] += extra_fine;
+      let extra_bits = extra_fine * c << BITRES;
+      fine_priority[j] = (extra_bits >= excess - balance) as i32;
+      excess -= extra_bits;
+    }
+    balance = excess;
+  }
+  // Skipped bands use all of their bits for fine energy.
+  for j in coded_bands..end {
+    fine_quant[j] = bits[j] >> stereo >> BITRES;
+    bits[j] = 0;
+    fine_priority[j] = (fine_quant[j] < 1) as i32;
+  }
+  Allocation {
+    pulses:         bits,
+    fine_quant:     fine_quant,
+    fine_priority:  fine_priority,
+    coded_bands:    coded_bands,
+    balance:        balance,
+    intensity:      intensity,
+    dual_stereo:    dual_stereo
+  }
+}
@@ -0,0 +1,130 @@
+//! Resampling of SILK
+//!
+//! SILK codes audio at 8, 12 or 16 kHz, which the decoder converts to
+//! 48 kHz by upsampling by two with allpass filters, followed by
+//! interpolation with a fractional FIR filter. The resampler matches the
+//! reference decoder exactly, as its delay is part of the alignment of
+//! SILK and CELT in hybrid frames.
+use opus::fixed::*;
+use opus::silk_tables::*;
+
+/// Sample rate of the output, in kHz.
+const OUTPUT_RATE_KHZ: usize = 48;
+
+/// Order of the fractional interpolation filter.
+const ORDER_FIR_12: usize = 8;
+
+/// Duration of the batches of input filtered at once, in milliseconds.
+const MAX_BATCH_SIZE_MS: usize = 10;
+
+/// Upsampler of the output of SILK at one sample rate to 48 kHz.
+#[derive(Clone)]
+pub struct Resampler {
+  rate_khz:     usize,
+  /// Number of input samples delayed to align SILK and CELT
+  input_delay:  usize,
+  batch_size:   usize,
+  inv_ratio_q16: i32,
+  s_iir:        [i32; 6],
+  s_fir:        [i16; ORDER_FIR_12],
+  delay_buf:    [i16; 16]
+}
+
+impl Resampler {
+  /// Creates a resampler from 8, 12 or 16 kHz.
+  pub fn new(rate_khz: usize) -> Resampler {
+    let rate_in  = (rate_khz * 1000) as i32;
+    let rate_out = (OUTPUT_RATE_KHZ * 1000) as i32;
+    // Ratio of input to output samples, rounded up, for the rate upsampled
+    // by two.
+    let mut inv_ratio_q16 = ((rate_in << 15) / rate_out) << 2;
+    while smulww(inv_ratio_q16, rate_out) < rate_in << 1 {
+      inv_ratio_q16 += 1;
+    }
+    Resampler {
+      rate_khz:      rate_khz,
+      input_delay:   match rate_khz { 8 => 0, 12 => 4, _ => 7 },
+      batch_size:    rate_khz * MAX_BATCH_SIZE_MS,
+      inv_ratio_q16: inv_ratio_q16,
+      s_iir:         [0i32; 6],
+      s_fir:         [0i16; ORDER_FIR_12],
+      delay_buf:     [0i16; 16]
+    }
+  }
+
+  /// Resamples at least a millisecond of input, appending the samples to
+  /// the output.
+  pub fn resample(&mut self, input: &[i16], output: &mut Vec<i16>) {
+    let n = self.rate_khz - self.input_delay;
+    // Completes the delayed samples with the start of the input.
+    let mut delayed = self.delay_buf;
+    delayed[self.input_delay .. self.rate_khz].copy_from_slice(&input[..n]);
+    self.resample_iir_fir(&delayed[.. self.rate_khz], output);
+    self.resample_iir_fir(&input[n .. input.len() - self.input_delay], output);
+    let delay = self.input_delay;
+    self.delay_buf[..delay].copy_from_slice(&input[input.len() - delay ..]);
+  }
+
+  /// Upsamples by two, and interpolates the upsampled signal.
+  fn resample_iir_fir(&mut self, mut input: &[i16], output: &mut Vec<i16>) {
+    let mut buf = vec![0i16; 2 * self.batch_size + ORDER_FIR_12];
+    buf[..ORDER_FIR_12].copy_from_slice(&self.s_fir);
+    let increment_q16 = self.inv_ratio_q16;
+    loop {
+      let n = input.len().min(self.batch_size);
+      up2_hq(&mut self.s_iir, &mut buf[ORDER_FIR_12 .. ORDER_FIR_12 + 2 * n], &input[..n]);
+      let max_index_q16 = (n as i32) << 17;
+      let mut index_q16 = 0;
+      while index_q16 < max_index_q16 {
+        let table = smulwb(index_q16 & 0xFFFF, 12) as usize;
+        let x = &buf[(index_q16 >> 16) as usize ..];
+        let a = &RESAMPLER_FRAC_FIR_12[table];
+        let b = &RESAMPLER_FRAC_FIR_12[11 - table];
+        let mut res_q15 = smulbb(x[0] as i32, a[0] as i32);
+        res_q15 = smlabb(res_q15, x[1] as i32, a[1] as i32);
+        res_q15 = smlabb(res_q15, x[2] as i32, a[2] as i32);
+        res_q15 = smlabb(res_q15, x[3] as i32, a[3] as i32);
+        res_q15 = smlabb(res_q15, x[4] as i32, b[3] as i32);
+        res_q15 = smlabb(res_q15, x[5] as i32, b[2] as i32);
+        res_q15 = smlabb(res_q15, x[6] as i32, b[1] as i32);
+        res_q15 = smlabb(res_q15, x[7] as i32, b[0] as i32);
+        output.push(sat16(rshift_round(res_q15, 15)));
+        index_q16 += increment_q16;
+      }
+      input = &input[n..];
+      // Keeps the end of the upsampled signal for the filter.
+      buf.copy_within(2 * n .. 2 * n + ORDER_FIR_12, 0);
+      if input.is_empty() {
+        break;
+      }
+    }
+    self.s_fir.copy_from_slice(&buf[..ORDER_FIR_12]);
+  }
+}
+
+/// Upsamples by two with a pair of allpass filters for each output phase.
+fn up2_hq(s: &mut [i32; 6], output: &mut [i16], input: &[i16]) {
+  for (k, x) in input.iter().enumerate() {
+    let x = (*x as i32) << 10;
+    output[2 * k]     = sat16(rshift_round(allpass(&mut s[..3], x, &RESAMPLER_UP2_HQ_0), 10));
+    output[2 * k + 1] = sat16(rshift_round(allpass(&mut s[3..], x, &RESAMPLER_UP2_HQ_1), 10));
+  }
+}
+
+/// Applies three allpass sections in Q10 to a sample.
+fn allpass(s: &mut [i32], x: i32, coefs: &[i16; 3]) -> i32 {
+  let y = x - s[0];
+  let t = smulwb(y, coefs[0] as i32);
+  let out1 = s[0] + t;
+  s[0] = x + t;
+  let y = out1 - s[1];
+  let t = smulwb(y, coefs[1] as i32);
+  let out2 = s[1] + t;
+  s[1] = out1 + t;
+  // The last coefficient is negative, and applied on top of the input.
+  let y = out2 - s[2];
+  let t = smlawb(y, y, coefs[2] as i32);
+  let out = s[2] + t;
+  s[2] = out2 + t;
+  out
+}
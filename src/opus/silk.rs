@@ -0,0 +1,860 @@
+//! SILK Frames
+//!
+//! SILK codes speech at 8, 12 or 16 kHz with linear prediction. Each frame
+//! of 10 or 20 ms codes the gain, the short-term prediction filter, and for
+//! voiced frames the pitch lag and long-term prediction filter of each
+//! subframe of 5 ms, followed by the excitation as pulses. Stereo is coded
+//! as a mid and a side channel, with the side channel predicted from the
+//! mid channel. The decoder follows the reference decoder of RFC 6716
+//! exactly, in fixed-point arithmetic, leaving out the concealment of lost
+//! frames and the comfort noise, so lost frames are decoded as silence.
+use opus::fixed::*;
+use opus::lpc::{self, MAX_LPC_ORDER, NLSF_QUANT_MAX_AMPLITUDE};
+use opus::range::RangeCoder;
+use opus::resampler::Resampler;
+use opus::silk_tables::*;
+
+/// Largest number of subframes of a frame.
+const MAX_SUBFRAMES: usize = 4;
+
+/// Duration of a subframe, in milliseconds.
+const SUBFRAME_MS: usize = 5;
+
+/// Duration of the signal kept for long-term prediction, in milliseconds.
+const LTP_MEM_MS: usize = 20;
+
+/// Number of samples of a frame at 16 kHz, the highest sample rate.
+const MAX_FRAME_LENGTH: usize = 320;
+
+/// Number of samples kept for long-term prediction at 16 kHz.
+const MAX_LTP_MEM_LENGTH: usize = 320;
+
+/// Number of taps of the long-term prediction filter.
+const LTP_ORDER: usize = 5;
+
+/// Number of pulses of a block of the shell coder.
+const SHELL_BLOCK_LENGTH: usize = 16;
+
+/// Largest number of pulses of a block, above which the least significant
+/// bits of the pulses are coded separately.
+const MAX_PULSES: usize = 16;
+
+/// Number of rate levels of the pulse distributions.
+const RATE_LEVELS: usize = 10;
+
+/// Adjustment of the excitation towards zero, in Q10.
+const QUANT_LEVEL_ADJUST_Q10: i32 = 80;
+
+/// Smallest and largest change of the gain index.
+const MIN_DELTA_GAIN: i32 = -4;
+const MAX_DELTA_GAIN: i32 = 36;
+
+/// Number of levels of the gain.
+const GAIN_LEVELS: i32 = 64;
+
+/// Offset and scale of the gain index on the log scale of `log2lin`.
+const GAIN_OFFSET: i32 = 2090;
+const GAIN_INV_SCALE_Q16: i32 = 1907825;
+
+/// Smallest and largest pitch lag, in milliseconds.
+const MIN_LAG_MS: i32 = 2;
+const MAX_LAG_MS: i32 = 18;
+
+/// Duration over which the stereo prediction changes, in milliseconds.
+const STEREO_INTERP_MS: usize = 8;
+
+/// Type of signal of voiced frames.
+const TYPE_VOICED: usize = 2;
+
+/// How the parameters of a frame are coded relative to the previous frame
+/// of the packet.
+#[derive(Clone, Copy, PartialEq)]
+enum Coding {
+  Independent,
+  /// Independent, except for the scaling of the long-term prediction
+  IndependentNoLtpScaling,
+  Conditional
+}
+
+/// Quantization indices of the parameters of a frame.
+#[derive(Clone, Copy, Default)]
+struct Indices {
+  gains:              [i32; MAX_SUBFRAMES],
+  ltp:                [usize; MAX_SUBFRAMES],
+  nlsf:               [i32; MAX_LPC_ORDER + 1],
+  lag:                i32,
+  contour:            usize,
+  signal_type:        usize,
+  quant_offset_type:  usize,
+  nlsf_interp_coef_q2: i32,
+  per:                usize,
+  ltp_scale:          usize,
+  seed:               i32
+}
+
+/// Parameters of a frame.
+#[derive(Default)]
+struct Control {
+  pitch_lags:    [i32; MAX_SUBFRAMES],
+  gains_q16:     [i32; MAX_SUBFRAMES],
+  /// Prediction filters of the first and second half of the frame
+  pred_coef_q12: [[i16; MAX_LPC_ORDER]; 2],
+  ltp_coef_q14:  [i16; LTP_ORDER * MAX_SUBFRAMES],
+  ltp_scale_q14: i32
+}
+
+/// State of the decoder of a channel.
+struct ChannelDecoder {
+  prev_gain_q16:           i32,
+  exc_q14:                 [i32; MAX_FRAME_LENGTH],
+  s_lpc_q14:               [i32; MAX_LPC_ORDER],
+  /// Decoded signal, for long-term prediction
+  out_buf:                 [i16; MAX_LTP_MEM_LENGTH + MAX_FRAME_LENGTH],
+  lag_prev:                i32,
+  last_gain_index:         i32,
+  rate_khz:                usize,
+  subframes:               usize,
+  frame_length:            usize,
+  subframe_length:         usize,
+  ltp_mem_length:          usize,
+  lpc_order:               usize,
+  prev_nlsf_q15:           [i16; MAX_LPC_ORDER],
+  first_frame_after_reset: bool,
+  pitch_lag_low_bits_icdf: &'static [u8],
+  pitch_contour_icdf:      &'static [u8],
+  nlsf_cb:                 &'static NlsfCodebook,
+  frames_decoded:          usize,
+  frames_per_packet:       usize,
+  ec_prev_signal_type:     usize,
+  ec_prev_lag_index:       i32,
+  vad_flags:               [bool; 3],
+  lbrr_flags:              [bool; 3],
+  indices:                 Indices,
+  resampler:               Resampler
+}
+
+impl ChannelDecoder {
+  fn new() -> ChannelDecoder {
+    ChannelDecoder {
+      prev_gain_q16:           65536,
+      exc_q14:                 [0i32; MAX_FRAME_LENGTH],
+      s_lpc_q14:               [0i32; MAX_LPC_ORDER],
+      out_buf:                 [0i16; MAX_LTP_MEM_LENGTH + MAX_FRAME_LENGTH],
+      lag_prev:                0,
+      last_gain_index:         0,
+      rate_khz:                0,
+      subframes:               0,
+      frame_length:            0,
+      subframe_length:         0,
+      ltp_mem_length:          0,
+      lpc_order:               0,
+      prev_nlsf_q15:           [0i16; MAX_LPC_ORDER],
+      first_frame_after_reset: true,
+      pitch_lag_low_bits_icdf: &UNIFORM4_ICDF,
+      pitch_contour_icdf:      &PITCH_CONTOUR_ICDF,
+      nlsf_cb:                 &NLSF_CB_NB_MB,
+      frames_decoded:          0,
+      frames_per_packet:       0,
+      ec_prev_signal_type:     0,
+      ec_prev_lag_index:       0,
+      vad_flags:               [false; 3],
+      lbrr_flags:              [false; 3],
+      indices:                 Indices::default(),
+      resampler:               Resampler::new(8)
+    }
+  }
+
+  /// Sets the sample rate and number of subframes of the frames, resetting
+  /// the state when the sample rate changes.
+  fn set_rate(&mut self, rate_khz: usize, subframes: usize) {
+    self.subframes = subframes;
+    self.subframe_length = SUBFRAME_MS * rate_khz;
+    let frame_length = subframes * self.subframe_length;
+    if self.rate_khz != rate_khz {
+      self.resampler = Resampler::new(rate_khz);
+    }
+    if self.rate_khz != rate_khz || self.frame_length != frame_length {
+      self.pitch_contour_icdf =
+        match (rate_khz == 8, subframes == MAX_SUBFRAMES) {
+          (true,  true)  => &PITCH_CONTOUR_NB_ICDF,
+          (true,  false) => &PITCH_CONTOUR_10_MS_NB_ICDF,
+          (false, true)  => &PITCH_CONTOUR_ICDF,
+          (false, false) => &PITCH_CONTOUR_10_MS_ICDF
+        };
+      if self.rate_khz != rate_khz {
+        self.ltp_mem_length = LTP_MEM_MS * rate_khz;
+        if rate_khz == 16 {
+          self.lpc_order = 16;
+          self.nlsf_cb = &NLSF_CB_WB;
+          self.pitch_lag_low_bits_icdf = &UNIFORM8_ICDF;
+        } else {
+          self.lpc_order = 10;
+          self.nlsf_cb = &NLSF_CB_NB_MB;
+          self.pitch_lag_low_bits_icdf =
+            if rate_khz == 12 { &UNIFORM6_ICDF } else { &UNIFORM4_ICDF };
+        }
+        self.first_frame_after_reset = true;
+        self.lag_prev = 100;
+        self.last_gain_index = 10;
+        self.out_buf = [0i16; MAX_LTP_MEM_LENGTH + MAX_FRAME_LENGTH];
+        self.s_lpc_q14 = [0i32; MAX_LPC_ORDER];
+      }
+      self.rate_khz = rate_khz;
+      self.frame_length = frame_length;
+    }
+  }
+
+  /// Decodes the quantization indices of the parameters of a frame.
+  fn decode_indices(&mut self, coder: &mut RangeCoder, frame: usize, lbrr: bool,
+                    coding: Coding) {
+    let indices = &mut self.indices;
+    let ix =
+      if lbrr || self.vad_flags[frame] {
+        coder.decode_icdf(&TYPE_OFFSET_VAD_ICDF, 8) + 2
+      } else {
+        coder.decode_icdf(&TYPE_OFFSET_NO_VAD_ICDF, 8)
+      };
+    indices.signal_type = ix >> 1;
+    indices.quant_offset_type = ix & 1;
+
+    // The first gain is coded relative to the previous frame, or in two
+    // stages with its 3 least significant bits last.
+    if coding == Coding::Conditional {
+      indices.gains[0] = coder.decode_icdf(&DELTA_GAIN_ICDF, 8) as i32;
+    } else {
+      indices.gains[0] = (coder.decode_icdf(&GAIN_ICDF[indices.signal_type], 8) << 3) as i32;
+      indices.gains[0] += coder.decode_icdf(&UNIFORM8_ICDF, 8) as i32;
+    }
+    for i in 1..self.subframes {
+      indices.gains[i] = coder.decode_icdf(&DELTA_GAIN_ICDF, 8) as i32;
+    }
+
+    let cb = self.nlsf_cb;
+    let index = coder.decode_icdf(&cb.cb1_icdf[(indices.signal_type >> 1) * cb.vectors ..], 8);
+    indices.nlsf[0] = index as i32;
+    let (ec_ix, _) = lpc::nlsf_unpack(cb, index);
+    for i in 0..cb.order {
+      let mut ix = coder.decode_icdf(&cb.cb2_icdf[ec_ix[i] ..], 8) as i32;
+      if ix == 0 {
+        ix -= coder.decode_icdf(&NLSF_EXT_ICDF, 8) as i32;
+      } else if ix == 2 * NLSF_QUANT_MAX_AMPLITUDE {
+        ix += coder.decode_icdf(&NLSF_EXT_ICDF, 8) as i32;
+      }
+      indices.nlsf[i + 1] = ix - NLSF_QUANT_MAX_AMPLITUDE;
+    }
+    indices.nlsf_interp_coef_q2 =
+      if self.subframes == MAX_SUBFRAMES {
+        coder.decode_icdf(&NLSF_INTERPOLATION_FACTOR_ICDF, 8) as i32
+      } else {
+        4
+      };
+
+    if indices.signal_type == TYPE_VOICED {
+      // The pitch lag is coded relative to the previous frame, or as its
+      // most and least significant parts.
+      let mut absolute = true;
+      if coding == Coding::Conditional && self.ec_prev_signal_type == TYPE_VOICED {
+        let delta = coder.decode_icdf(&PITCH_DELTA_ICDF, 8) as i32;
+        if delta > 0 {
+          indices.lag = self.ec_prev_lag_index + delta - 9;
+          absolute = false;
+        }
+      }
+      if absolute {
+        indices.lag = (coder.decode_icdf(&PITCH_LAG_ICDF, 8) * (self.rate_khz >> 1)) as i32;
+        indices.lag += coder.decode_icdf(self.pitch_lag_low_bits_icdf, 8) as i32;
+      }
+      self.ec_prev_lag_index = indices.lag;
+      indices.contour = coder.decode_icdf(self.pitch_contour_icdf, 8);
+
+      indices.per = coder.decode_icdf(&LTP_PER_INDEX_ICDF, 8);
+      let ltp_icdf: &[u8] =
+        match indices.per {
+          0 => &LTP_GAIN_ICDF_0,
+          1 => &LTP_GAIN_ICDF_1,
+          _ => &LTP_GAIN_ICDF_2
+        };
+      for k in 0..self.subframes {
+        indices.ltp[k] = coder.decode_icdf(ltp_icdf, 8);
+      }
+      indices.ltp_scale =
+        if coding == Coding::Independent { coder.decode_icdf(&LTP_SCALE_ICDF, 8) } else { 0 };
+    }
+    self.ec_prev_signal_type = indices.signal_type;
+    indices.seed = coder.decode_icdf(&UNIFORM4_ICDF, 8) as i32;
+  }
+
+  /// Decodes the parameters of a frame from its quantization indices.
+  fn decode_parameters(&mut self, control: &mut Control, coding: Coding) {
+    // Dequantizes the gains, with the first gain falling no more than 16
+    // steps when coded independently.
+    let mut prev = self.last_gain_index;
+    for k in 0..self.subframes {
+      let index = self.indices.gains[k];
+      if k == 0 && coding != Coding::Conditional {
+        prev = index.max(prev - 16);
+      } else {
+        let delta = index + MIN_DELTA_GAIN;
+        let threshold = 2 * MAX_DELTA_GAIN - GAIN_LEVELS + prev;
+        prev += if delta > threshold { (delta << 1) - threshold } else { delta };
+      }
+      prev = prev.max(0).min(GAIN_LEVELS - 1);
+      control.gains_q16[k] = log2lin((smulwb(GAIN_INV_SCALE_Q16, prev) + GAIN_OFFSET).min(3967));
+    }
+    self.last_gain_index = prev;
+
+    // Decodes the prediction filters, interpolating the NLSFs of the first
+    // half of the frame from the previous frame.
+    let order = self.lpc_order;
+    let nlsf_q15 = lpc::nlsf_decode(&self.indices.nlsf, self.nlsf_cb);
+    control.pred_coef_q12[1] = lpc::nlsf2a(&nlsf_q15, order);
+    if self.first_frame_after_reset {
+      self.indices.nlsf_interp_coef_q2 = 4;
+    }
+    if self.indices.nlsf_interp_coef_q2 < 4 {
+      let mut nlsf0_q15 = [0i16; MAX_LPC_ORDER];
+      for i in 0..order {
+        let prev = self.prev_nlsf_q15[i] as i32;
+        nlsf0_q15[i] = (prev + ((self.indices.nlsf_interp_coef_q2 *
+                                 (nlsf_q15[i] as i32 - prev)) >> 2)) as i16;
+      }
+      control.pred_coef_q12[0] = lpc::nlsf2a(&nlsf0_q15, order);
+    } else {
+      control.pred_coef_q12[0] = control.pred_coef_q12[1];
+    }
+    self.prev_nlsf_q15 = nlsf_q15;
+
+    if self.indices.signal_type == TYPE_VOICED {
+      self.decode_pitch(&mut control.pitch_lags);
+      let codebook: &[[i8; LTP_ORDER]] =
+        match self.indices.per {
+          0 => &LTP_GAIN_VQ_0_Q7,
+          1 => &LTP_GAIN_VQ_1_Q7,
+          _ => &LTP_GAIN_VQ_2_Q7
+        };
+      for k in 0..self.subframes {
+        let filter = &codebook[self.indices.ltp[k]];
+        for i in 0..LTP_ORDER {
+          control.ltp_coef_q14[k * LTP_ORDER + i] = (filter[i] as i16) << 7;
+        }
+      }
+      control.ltp_scale_q14 = LTP_SCALES_Q14[self.indices.ltp_scale] as i32;
+    } else {
+      control.pitch_lags = [0; MAX_SUBFRAMES];
+      control.ltp_coef_q14 = [0; LTP_ORDER * MAX_SUBFRAMES];
+      self.indices.per = 0;
+      control.ltp_scale_q14 = 0;
+    }
+  }
+
+  /// Decodes the pitch lag of each subframe from the lag and contour.
+  fn decode_pitch(&self, pitch_lags: &mut [i32; MAX_SUBFRAMES]) {
+    let rate = self.rate_khz as i32;
+    let contour = self.indices.contour;
+    let min_lag = MIN_LAG_MS * rate;
+    let max_lag = MAX_LAG_MS * rate;
+    let lag = min_lag + self.indices.lag;
+    for k in 0..self.subframes {
+      let delta =
+        match (rate == 8, self.subframes == MAX_SUBFRAMES) {
+          (true,  true)  => CB_LAGS_STAGE2[k][contour],
+          (true,  false) => CB_LAGS_STAGE2_10_MS[k][contour],
+          (false, true)  => CB_LAGS_STAGE3[k][contour],
+          (false, false) => CB_LAGS_STAGE3_10_MS[k][contour]
+        };
+      pitch_lags[k] = limit(lag + delta as i32, min_lag, max_lag);
+    }
+  }
+
+  /// Synthesizes the frame from the excitation, with long-term prediction
+  /// for voiced frames, followed by short-term prediction.
+  fn decode_core(&mut self, control: &mut Control, xq: &mut [i16], pulses: &[i16]) {
+    let order = self.lpc_order;
+    let subframe_length = self.subframe_length;
+    let ltp_mem_length = self.ltp_mem_length;
+    let mut s_ltp = [0i16; MAX_LTP_MEM_LENGTH];
+    let mut s_ltp_q15 = [0i32; MAX_LTP_MEM_LENGTH + MAX_FRAME_LENGTH];
+    let mut res_q14 = [0i32; MAX_FRAME_LENGTH / MAX_SUBFRAMES];
+    let mut s_lpc_q14 = [0i32; MAX_FRAME_LENGTH / MAX_SUBFRAMES + MAX_LPC_ORDER];
+
+    let offset_q10 = QUANTIZATION_OFFSETS_Q10[self.indices.signal_type >> 1]
+                                             [self.indices.quant_offset_type] as i32;
+    let nlsf_interpolation = self.indices.nlsf_interp_coef_q2 < 4;
+
+    // Decodes the excitation, with signs given by the pseudo-random
+    // generator.
+    let mut seed = self.indices.seed;
+    for i in 0..self.frame_length {
+      seed = rand(seed);
+      let mut exc = (pulses[i] as i32) << 14;
+      if exc > 0 {
+        exc -= QUANT_LEVEL_ADJUST_Q10 << 4;
+      } else if exc < 0 {
+        exc += QUANT_LEVEL_ADJUST_Q10 << 4;
+      }
+      exc += offset_q10 << 4;
+      self.exc_q14[i] = if seed < 0 { -exc } else { exc };
+      seed = seed.wrapping_add(pulses[i] as i32);
+    }
+
+    s_lpc_q14[..MAX_LPC_ORDER].copy_from_slice(&self.s_lpc_q14);
+    let mut s_ltp_buf_idx = ltp_mem_length;
+    for k in 0..self.subframes {
+      let exc = &self.exc_q14[k * subframe_length .. (k + 1) * subframe_length];
+      let a_q12 = control.pred_coef_q12[k >> 1];
+      let b_q14 = &control.ltp_coef_q14[k * LTP_ORDER .. (k + 1) * LTP_ORDER];
+      let gain_q16 = control.gains_q16[k];
+      let gain_q10 = gain_q16 >> 6;
+      let mut inv_gain_q31 = inverse32_var_q(gain_q16, 47);
+
+      // Scales the short-term state to the gain of the subframe.
+      let gain_adj_q16 =
+        if gain_q16 != self.prev_gain_q16 {
+          let gain_adj_q16 = div32_var_q(self.prev_gain_q16, gain_q16, 16);
+          for s in s_lpc_q14[..MAX_LPC_ORDER].iter_mut() {
+            *s = smulww(gain_adj_q16, *s);
+          }
+          gain_adj_q16
+        } else {
+          1 << 16
+        };
+      self.prev_gain_q16 = gain_q16;
+
+      let voiced = self.indices.signal_type == TYPE_VOICED;
+      if voiced {
+        let lag = control.pitch_lags[k] as usize;
+        if k == 0 || (k == 2 && nlsf_interpolation) {
+          // Whitens the past output with the new prediction filter.
+          let start = ltp_mem_length - lag - order - LTP_ORDER / 2;
+          if k == 2 {
+            self.out_buf[ltp_mem_length .. ltp_mem_length + 2 * subframe_length]
+              .copy_from_slice(&xq[.. 2 * subframe_length]);
+          }
+          let input_start = start + k * subframe_length;
+          lpc::analysis_filter(&mut s_ltp[start..],
+                               &self.out_buf[input_start .. input_start + ltp_mem_length - start],
+                               &a_q12[..order], ltp_mem_length - start);
+          // Scales down the long-term prediction of the first subframe, to
+          // reduce the dependency between packets.
+          if k == 0 {
+            inv_gain_q31 = smulwb(inv_gain_q31, control.ltp_scale_q14) << 2;
+          }
+          for i in 0 .. lag + LTP_ORDER / 2 {
+            s_ltp_q15[s_ltp_buf_idx - i - 1] =
+              smulwb(inv_gain_q31, s_ltp[ltp_mem_length - i - 1] as i32);
+          }
+        } else if gain_adj_q16 != 1 << 16 {
+          for i in 0 .. lag + LTP_ORDER / 2 {
+            let j = s_ltp_buf_idx - i - 1;
+            s_ltp_q15[j] = smulww(gain_adj_q16, s_ltp_q15[j]);
+          }
+        }
+
+        // Long-term prediction, rounding to avoid a bias.
+        let mut pred_lag = s_ltp_buf_idx + LTP_ORDER / 2 - lag;
+        for i in 0..subframe_length {
+          let mut ltp_pred_q13 = 2;
+          for j in 0..LTP_ORDER {
+            ltp_pred_q13 = smlawb(ltp_pred_q13, s_ltp_q15[pred_lag - j], b_q14[j] as i32);
+          }
+          pred_lag += 1;
+          res_q14[i] = exc[i] + (ltp_pred_q13 << 1);
+          s_ltp_q15[s_ltp_buf_idx] = res_q14[i] << 1;
+          s_ltp_buf_idx += 1;
+        }
+      } else {
+        res_q14[..subframe_length].copy_from_slice(exc);
+      }
+
+      // Short-term prediction, rounding to avoid a bias.
+      let out = &mut xq[k * subframe_length .. (k + 1) * subframe_length];
+      for i in 0..subframe_length {
+        let mut lpc_pred_q10 = (order >> 1) as i32;
+        for j in 0..order {
+          lpc_pred_q10 = smlawb(lpc_pred_q10, s_lpc_q14[MAX_LPC_ORDER + i - j - 1],
+                                a_q12[j] as i32);
+        }
+        let value = res_q14[i].saturating_add(lshift_sat32(lpc_pred_q10, 4));
+        s_lpc_q14[MAX_LPC_ORDER + i] = value;
+        out[i] = sat16(rshift_round(smulww(value, gain_q10), 8));
+      }
+      s_lpc_q14.copy_within(subframe_length .. subframe_length + MAX_LPC_ORDER, 0);
+    }
+    self.s_lpc_q14.copy_from_slice(&s_lpc_q14[..MAX_LPC_ORDER]);
+  }
+
+  /// Decodes a frame into the output, returning its number of samples.
+  fn decode_frame(&mut self, coder: &mut RangeCoder, output: &mut [i16], coding: Coding)
+                  -> usize {
+    let length = self.frame_length;
+    let mut pulses = [0i16; MAX_FRAME_LENGTH];
+    let mut control = Control::default();
+    let frame = self.frames_decoded;
+    self.decode_indices(coder, frame, false, coding);
+    decode_pulses(coder, &mut pulses, self.indices.signal_type,
+                  self.indices.quant_offset_type, length);
+    self.decode_parameters(&mut control, coding);
+    self.decode_core(&mut control, &mut output[..length], &pulses);
+    self.first_frame_after_reset = false;
+
+    // Keeps the output for long-term prediction.
+    let kept = self.ltp_mem_length - length;
+    self.out_buf.copy_within(length .. length + kept, 0);
+    self.out_buf[kept .. kept + length].copy_from_slice(&output[..length]);
+    self.lag_prev = control.pitch_lags[self.subframes - 1];
+    length
+  }
+}
+
+/// Decodes the pulses of the excitation of a frame.
+fn decode_pulses(coder: &mut RangeCoder, pulses: &mut [i16], signal_type: usize,
+                 quant_offset_type: usize, frame_length: usize) {
+  let rate_level = coder.decode_icdf(&RATE_LEVELS_ICDF[signal_type >> 1], 8);
+  // Frames of 10 ms at 12 kHz end with half a block.
+  let blocks = (frame_length + SHELL_BLOCK_LENGTH - 1) / SHELL_BLOCK_LENGTH;
+
+  // Decodes the number of pulses of each block, with a pulse count above
+  // the largest signalling an extra least significant bit.
+  let mut sum_pulses = [0usize; MAX_FRAME_LENGTH / SHELL_BLOCK_LENGTH];
+  let mut lshifts    = [0usize; MAX_FRAME_LENGTH / SHELL_BLOCK_LENGTH];
+  for i in 0..blocks {
+    sum_pulses[i] = coder.decode_icdf(&PULSES_PER_BLOCK_ICDF[rate_level], 8);
+    while sum_pulses[i] == MAX_PULSES + 1 {
+      lshifts[i] += 1;
+      // No more extra bits are allowed after 10.
+      let skip = (lshifts[i] == 10) as usize;
+      sum_pulses[i] = coder.decode_icdf(&PULSES_PER_BLOCK_ICDF[RATE_LEVELS - 1][skip..], 8);
+    }
+  }
+
+  for i in 0..blocks {
+    let block = &mut pulses[i * SHELL_BLOCK_LENGTH .. (i + 1) * SHELL_BLOCK_LENGTH];
+    if sum_pulses[i] > 0 {
+      shell_decode(coder, block, sum_pulses[i]);
+    } else {
+      for p in block.iter_mut() {
+        *p = 0;
+      }
+    }
+  }
+
+  for i in 0..blocks {
+    if lshifts[i] > 0 {
+      for p in pulses[i * SHELL_BLOCK_LENGTH .. (i + 1) * SHELL_BLOCK_LENGTH].iter_mut() {
+        let mut abs = *p as i32;
+        for _ in 0..lshifts[i] {
+          abs = (abs << 1) + coder.decode_icdf(&LSB_ICDF, 8) as i32;
+        }
+        *p = abs as i16;
+      }
+      // Marks the block as having pulses for the signs.
+      sum_pulses[i] |= lshifts[i] << 5;
+    }
+  }
+
+  // Decodes the sign of each pulse.
+  let sign_icdf = &SIGN_ICDF[7 * (quant_offset_type + (signal_type << 1)) ..];
+  for i in 0..blocks {
+    let p = sum_pulses[i];
+    if p > 0 {
+      let icdf = [sign_icdf[(p & 0x1F).min(6)], 0];
+      for q in pulses[i * SHELL_BLOCK_LENGTH .. (i + 1) * SHELL_BLOCK_LENGTH].iter_mut() {
+        if *q > 0 && coder.decode_icdf(&icdf, 8) == 0 {
+          *q = -*q;
+        }
+      }
+    }
+  }
+}
+
+/// Decodes the split of the pulses of a block between its halves, down to
+/// single samples.
+fn shell_decode(coder: &mut RangeCoder, block: &mut [i16], pulses: usize) {
+  let n = block.len();
+  if n == 1 {
+    block[0] = pulses as i16;
+    return;
+  }
+  let table: &[u8] =
+    match n {
+      16 => &SHELL_CODE_TABLE3,
+      8  => &SHELL_CODE_TABLE2,
+      4  => &SHELL_CODE_TABLE1,
+      _  => &SHELL_CODE_TABLE0
+    };
+  let left =
+    if pulses > 0 {
+      coder.decode_icdf(&table[SHELL_CODE_TABLE_OFFSETS[pulses] as usize ..], 8)
+    } else {
+      0
+    };
+  let (first, second) = block.split_at_mut(n / 2);
+  shell_decode(coder, first, left);
+  shell_decode(coder, second, pulses - left);
+}
+
+/// Decodes the stereo prediction weights in Q13.
+fn decode_stereo_pred(coder: &mut RangeCoder) -> [i32; 2] {
+  let n = coder.decode_icdf(&STEREO_PRED_JOINT_ICDF, 8);
+  let mut ix = [[0usize; 3]; 2];
+  ix[0][2] = n / 5;
+  ix[1][2] = n - 5 * ix[0][2];
+  for i in 0..2 {
+    ix[i][0] = coder.decode_icdf(&UNIFORM3_ICDF, 8);
+    ix[i][1] = coder.decode_icdf(&UNIFORM5_ICDF, 8);
+  }
+  let mut pred_q13 = [0i32; 2];
+  for i in 0..2 {
+    ix[i][0] += 3 * ix[i][2];
+    let low_q13 = STEREO_PRED_QUANT_Q13[ix[i][0]] as i32;
+    // Steps of a tenth of the interval, 0.1 in Q16.
+    let step_q13 = smulwb(STEREO_PRED_QUANT_Q13[ix[i][0] + 1] as i32 - low_q13, 6554);
+    pred_q13[i] = smlabb(low_q13, step_q13, 2 * ix[i][1] as i32 + 1);
+  }
+  // The first weight is coded relative to the second.
+  pred_q13[0] -= pred_q13[1];
+  pred_q13
+}
+
+/// State of the stereo decoding.
+#[derive(Default)]
+struct StereoState {
+  pred_prev_q13: [i32; 2],
+  s_mid:         [i16; 2],
+  s_side:        [i16; 2]
+}
+
+impl StereoState {
+  /// Converts the mid and side channels of a frame to left and right, where
+  /// each channel starts with two samples kept from the previous frame.
+  fn ms_to_lr(&mut self, x1: &mut [i16], x2: &mut [i16], pred_q13: [i32; 2],
+              rate_khz: usize, length: usize) {
+    x1[..2].copy_from_slice(&self.s_mid);
+    x2[..2].copy_from_slice(&self.s_side);
+    self.s_mid.copy_from_slice(&x1[length .. length + 2]);
+    self.s_side.copy_from_slice(&x2[length .. length + 2]);
+
+    // Adds the prediction from the mid channel to the side channel, moving
+    // from the previous prediction weights at the start of the frame.
+    let interp = STEREO_INTERP_MS * rate_khz;
+    let mut pred0_q13 = self.pred_prev_q13[0];
+    let mut pred1_q13 = self.pred_prev_q13[1];
+    let denom_q16 = (1 << 16) / interp as i32;
+    let delta0_q13 = rshift_round(smulbb(pred_q13[0] - pred0_q13, denom_q16), 16);
+    let delta1_q13 = rshift_round(smulbb(pred_q13[1] - pred1_q13, denom_q16), 16);
+    for n in 0..length {
+      if n < interp {
+        pred0_q13 += delta0_q13;
+        pred1_q13 += delta1_q13;
+      } else {
+        pred0_q13 = pred_q13[0];
+        pred1_q13 = pred_q13[1];
+      }
+      let sum = ((x1[n] as i32 + x1[n + 2] as i32) + ((x1[n + 1] as i32) << 1)) << 9;
+      let sum = smlawb((x2[n + 1] as i32) << 8, sum, pred0_q13);
+      let sum = smlawb(sum, (x1[n + 1] as i32) << 11, pred1_q13);
+      x2[n + 1] = sat16(rshift_round(sum, 8));
+    }
+    self.pred_prev_q13 = pred_q13;
+
+    for n in 1 .. length + 1 {
+      let sum  = x1[n] as i32 + x2[n] as i32;
+      let diff = x1[n] as i32 - x2[n] as i32;
+      x1[n] = sat16(sum);
+      x2[n] = sat16(diff);
+    }
+  }
+}
+
+/// Decoder of the SILK frames of a stream, giving 48 kHz output.
+pub struct SilkDecoder {
+  /// Number of output channels
+  channels:                usize,
+  decoders:                [ChannelDecoder; 2],
+  stereo:                  StereoState,
+  /// Number of channels coded in the previous packet
+  stream_channels:         usize,
+  prev_decode_only_middle: bool
+}
+
+impl SilkDecoder {
+  pub fn new(channels: usize) -> SilkDecoder {
+    SilkDecoder {
+      channels:                channels,
+      decoders:                [ChannelDecoder::new(), ChannelDecoder::new()],
+      stereo:                  StereoState::default(),
+      stream_channels:         0,
+      prev_decode_only_middle: false
+    }
+  }
+
+  /// Resets the state, as when the previous frame was coded with CELT.
+  pub fn reset(&mut self) {
+    self.decoders = [ChannelDecoder::new(), ChannelDecoder::new()];
+    self.stereo = StereoState::default();
+    self.prev_decode_only_middle = false;
+  }
+
+  /// Decodes a SILK frame of 10 or 20 ms of a packet of `payload_ms`,
+  /// coded with `stream_channels` at `rate_khz`, appending the interleaved
+  /// samples at 48 kHz to the output. The first frame of a packet starts
+  /// with the flags of all of its frames.
+  pub fn decode(&mut self, coder: &mut RangeCoder, stream_channels: usize, rate_khz: usize,
+                payload_ms: usize, first_frame: bool, output: &mut Vec<i16>) {
+    if first_frame {
+      for n in 0..stream_channels {
+        self.decoders[n].frames_decoded = 0;
+      }
+    }
+    // Starts the side channel afresh in a change from mono to stereo.
+    if stream_channels > self.stream_channels {
+      self.decoders[1] = ChannelDecoder::new();
+    }
+    let stereo_to_mono = stream_channels == 1 && self.stream_channels == 2 &&
+                         rate_khz == self.decoders[0].rate_khz;
+
+    if self.decoders[0].frames_decoded == 0 {
+      let (frames, subframes) =
+        match payload_ms {
+          10 => (1, 2),
+          20 => (1, 4),
+          40 => (2, 4),
+          _  => (3, 4)
+        };
+      for n in 0..stream_channels {
+        self.decoders[n].frames_per_packet = frames;
+        self.decoders[n].set_rate(rate_khz, subframes);
+      }
+    }
+    if self.channels == 2 && stream_channels == 2 && self.stream_channels == 1 {
+      self.stereo.pred_prev_q13 = [0; 2];
+      self.stereo.s_side = [0; 2];
+      let (first, second) = self.decoders.split_at_mut(1);
+      second[0].resampler = first[0].resampler.clone();
+    }
+    self.stream_channels = stream_channels;
+
+    let mut ms_pred_q13 = [0i32; 2];
+    let mut decode_only_middle = false;
+    if self.decoders[0].frames_decoded == 0 {
+      // Decodes the voice activity and LBRR flags of each frame.
+      for n in 0..stream_channels {
+        let decoder = &mut self.decoders[n];
+        for i in 0..decoder.frames_per_packet {
+          decoder.vad_flags[i] = coder.decode_bit_logp(1);
+        }
+        decoder.lbrr_flags[0] = coder.decode_bit_logp(1);
+      }
+      for n in 0..stream_channels {
+        let decoder = &mut self.decoders[n];
+        let lbrr = decoder.lbrr_flags[0];
+        decoder.lbrr_flags = [false; 3];
+        if lbrr {
+          if decoder.frames_per_packet == 1 {
+            decoder.lbrr_flags[0] = true;
+          } else {
+            let icdf: &[u8] =
+              if decoder.frames_per_packet == 2 { &LBRR_FLAGS_2_ICDF } else { &LBRR_FLAGS_3_ICDF };
+            let symbol = coder.decode_icdf(icdf, 8) + 1;
+            for i in 0..decoder.frames_per_packet {
+              decoder.lbrr_flags[i] = (symbol >> i) & 1 != 0;
+            }
+          }
+        }
+      }
+      // Skips the low bitrate redundancy of the previous packet, which is
+      // only needed when it is lost.
+      for i in 0..self.decoders[0].frames_per_packet {
+        for n in 0..stream_channels {
+          if self.decoders[n].lbrr_flags[i] {
+            if stream_channels == 2 && n == 0 {
+              decode_stereo_pred(coder);
+              if !self.decoders[1].lbrr_flags[i] {
+                coder.decode_icdf(&STEREO_ONLY_CODE_MID_ICDF, 8);
+              }
+            }
+            let coding =
+              if i > 0 && self.decoders[n].lbrr_flags[i - 1] { Coding::Conditional }
+              else { Coding::Independent };
+            let decoder = &mut self.decoders[n];
+            let mut pulses = [0i16; MAX_FRAME_LENGTH];
+            decoder.decode_indices(coder, i, true, coding);
+            decode_pulses(coder, &mut pulses, decoder.indices.signal_type,
+                          decoder.indices.quant_offset_type, decoder.frame_length);
+          }
+        }
+      }
+    }
+
+    let frame = self.decoders[0].frames_decoded;
+    if stream_channels == 2 {
+      ms_pred_q13 = decode_stereo_pred(coder);
+      if !self.decoders[1].vad_flags[frame] {
+        decode_only_middle = coder.decode_icdf(&STEREO_ONLY_CODE_MID_ICDF, 8) == 1;
+      }
+    }
+    // Resets the side channel when it is coded again.
+    if stream_channels == 2 && !decode_only_middle && self.prev_decode_only_middle {
+      let side = &mut self.decoders[1];
+      side.out_buf = [0i16; MAX_LTP_MEM_LENGTH + MAX_FRAME_LENGTH];
+      side.s_lpc_q14 = [0i32; MAX_LPC_ORDER];
+      side.lag_prev = 100;
+      side.last_gain_index = 10;
+      side.first_frame_after_reset = true;
+    }
+
+    // Each channel starts with two samples kept from the previous frame.
+    let length = self.decoders[0].frame_length;
+    let mut samples = [vec![0i16; length + 2], vec![0i16; length + 2]];
+    for n in 0..stream_channels {
+      if n == 0 || !decode_only_middle {
+        // The frame of the mid channel is already counted for the side
+        // channel.
+        let index = self.decoders[0].frames_decoded as i32 - n as i32;
+        let coding =
+          if index <= 0 { Coding::Independent }
+          else if n > 0 && self.prev_decode_only_middle { Coding::IndependentNoLtpScaling }
+          else { Coding::Conditional };
+        self.decoders[n].decode_frame(coder, &mut samples[n][2..], coding);
+      }
+      self.decoders[n].frames_decoded += 1;
+    }
+
+    {
+      let (mid, side) = samples.split_at_mut(1);
+      if self.channels == 2 && stream_channels == 2 {
+        self.stereo.ms_to_lr(&mut mid[0], &mut side[0], ms_pred_q13, rate_khz, length);
+      } else {
+        mid[0][..2].copy_from_slice(&self.stereo.s_mid);
+        self.stereo.s_mid.copy_from_slice(&mid[0][length .. length + 2]);
+      }
+    }
+
+    // Resamples each channel, and interleaves the channels.
+    let first = output.len();
+    let mut resampled: Vec<i16> = Vec::with_capacity(length * 48 / rate_khz);
+    for n in 0 .. self.channels.min(stream_channels) {
+      resampled.clear();
+      self.decoders[n].resampler.resample(&samples[n][1 .. length + 1], &mut resampled);
+      if n == 0 {
+        output.resize(first + resampled.len() * self.channels, 0);
+      }
+      for (i, s) in resampled.iter().enumerate() {
+        output[first + i * self.channels + n] = *s;
+      }
+    }
+    if self.channels == 2 && stream_channels == 1 {
+      if stereo_to_mono {
+        // Keeps the resampler of the right channel going after a change
+        // from stereo to mono.
+        resampled.clear();
+        self.decoders[1].resampler.resample(&samples[0][1 .. length + 1], &mut resampled);
+      }
+      for i in 0 .. (output.len() - first) / 2 {
+        output[first + 2 * i + 1] =
+          if stereo_to_mono { resampled[i] } else { output[first + 2 * i] };
+      }
+    }
+    self.prev_decode_only_middle = decode_only_middle;
+  }
+}
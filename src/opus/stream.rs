@@ -0,0 +1,283 @@
+//! Opus Frames
+//!
+//! Each frame of an Opus stream is coded with SILK, with CELT, or in the
+//! hybrid mode, where SILK codes the audio up to 8 kHz and CELT the bands
+//! above, both with the same range coder. A frame coded with SILK may end
+//! with a redundant CELT frame of 5 ms, which smooths a change to or from
+//! CELT, and other changes of mode are smoothed by fading from the audio
+//! concealing a lost frame in the previous mode.
+use opus::celt::{self, CeltDecoder};
+use opus::packet::Packet;
+use opus::range::RangeCoder;
+use opus::silk::SilkDecoder;
+
+/// Number of samples of a frame of 20 ms at 48 kHz.
+const F20: usize = 960;
+
+/// Number of samples of a frame of 10 ms at 48 kHz.
+const F10: usize = 480;
+
+/// Number of samples of a frame of 5 ms at 48 kHz.
+const F5: usize = 240;
+
+/// Number of samples of a frame of 2.5 ms at 48 kHz.
+const F2_5: usize = 120;
+
+/// First band coded by CELT in hybrid frames.
+const HYBRID_START_BAND: usize = 17;
+
+/// Modes of the frames of a packet.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+  Silk,
+  Hybrid,
+  Celt
+}
+
+/// Decoder of a stream, with a SILK and a CELT decoder.
+pub struct StreamDecoder {
+  /// Number of output channels
+  channels:        usize,
+  silk:            SilkDecoder,
+  celt:            CeltDecoder,
+  window:          Vec<f32>,
+  /// Number of channels coded in the last packet
+  stream_channels: usize,
+  /// Band after the last band coded by CELT, for the last bandwidth
+  end_band:        usize,
+  prev_mode:       Option<Mode>,
+  /// Whether the previous frame ended with a redundant CELT frame
+  prev_redundancy: bool
+}
+
+impl StreamDecoder {
+  pub fn new(channels: usize) -> StreamDecoder {
+    StreamDecoder {
+      channels:        channels,
+      silk:            SilkDecoder::new(channels),
+      celt:            CeltDecoder::new(channels),
+      window:          celt::window(),
+      stream_channels: channels,
+      end_band:        celt::end_band(3),
+      prev_mode:       None,
+      prev_redundancy: false
+    }
+  }
+
+  /// Decodes the frames of a packet, appending the interleaved samples to
+  /// the output.
+  pub fn decode(&mut self, packet: &Packet, output: &mut Vec<f32>) {
+    let config = packet.config();
+    // The configuration gives the mode, and the bandwidth from narrowband
+    // (0) to fullband (4).
+    let (mode, bandwidth) =
+      match config {
+        0 ..= 11  => (Mode::Silk, (config >> 2) as usize),
+        12 ..= 15 => (Mode::Hybrid, 3 + ((config >> 1) & 1) as usize),
+        _         => (Mode::Celt, match (config >> 2) & 3 { 0 => 0, b => b as usize + 1 })
+      };
+    self.stream_channels = packet.channels();
+    let frame_size = packet.frame_size();
+    for frame in packet.frames.iter() {
+      if frame.len() <= 1 {
+        self.decode_lost(frame_size, output);
+      } else {
+        self.decode_frame(frame, mode, bandwidth, frame_size, output);
+      }
+    }
+  }
+
+  /// Decodes a lost frame, continuing the previous mode, in frames of no
+  /// more than 20 ms.
+  fn decode_lost(&mut self, frame_size: usize, output: &mut Vec<f32>) {
+    let mode =
+      match self.prev_mode {
+        Some(mode) => mode,
+        None => {
+          let first = output.len();
+          output.resize(first + frame_size * self.channels, 0f32);
+          return;
+        }
+      };
+    let mut remaining = frame_size;
+    while remaining > 0 {
+      let size =
+        if remaining >= F20 { F20 }
+        else if remaining > F10 { F10 }
+        else if mode != Mode::Silk && remaining > F5 && remaining < F10 { F5 }
+        else { remaining };
+      let first = output.len();
+      output.resize(first + size * self.channels, 0f32);
+      // The audio coded with SILK is left out, as concealing it is not
+      // supported, and the audio coded with CELT fades out.
+      if mode != Mode::Silk {
+        let start = if mode == Mode::Hybrid { HYBRID_START_BAND } else { 0 };
+        let mut pcm = Vec::with_capacity(size * self.channels);
+        let mut coder = RangeCoder::decoder(&[]);
+        self.celt.decode(&mut coder, 0, self.stream_channels, lm(size), start, self.end_band,
+                         &mut pcm);
+        output[first..].copy_from_slice(&pcm);
+      }
+      self.prev_redundancy = false;
+      remaining -= size;
+    }
+  }
+
+  /// Decodes a frame of `frame_size` samples coded in a mode.
+  fn decode_frame(&mut self, data: &[u8], mode: Mode, bandwidth: usize, frame_size: usize,
+                  output: &mut Vec<f32>) {
+    let channels = self.channels;
+    let stream_channels = self.stream_channels;
+    let mut len = data.len();
+    let mut coder = RangeCoder::decoder(data);
+    self.end_band = match bandwidth { 0 => 13, 1 | 2 => 17, 3 => 19, _ => 21 };
+
+    // Changes between CELT and the other modes without a redundant frame
+    // fade from the previous mode.
+    let mut transition =
+      match self.prev_mode {
+        Some(prev_mode) =>
+          (mode == Mode::Celt && prev_mode != Mode::Celt && !self.prev_redundancy) ||
+          (mode != Mode::Celt && prev_mode == Mode::Celt),
+        None => false
+      };
+    let mut pcm_transition = Vec::new();
+    if transition && mode == Mode::Celt {
+      self.decode_lost(F5.min(frame_size), &mut pcm_transition);
+    }
+
+    // SILK decodes the audio at its internal sample rate in frames of 10
+    // or 20 ms, which the first frame gives for the whole packet.
+    let mut pcm_silk: Vec<i16> = Vec::new();
+    if mode != Mode::Celt {
+      if self.prev_mode == Some(Mode::Celt) {
+        self.silk.reset();
+      }
+      let payload_ms = (frame_size / 48).max(10);
+      let rate_khz = if mode == Mode::Silk { [8, 12, 16][bandwidth] } else { 16 };
+      while pcm_silk.len() < frame_size * channels {
+        let first_frame = pcm_silk.is_empty();
+        self.silk.decode(&mut coder, stream_channels, rate_khz, payload_ms, first_frame,
+                         &mut pcm_silk);
+      }
+    }
+
+    // A frame coded with SILK may end with a redundant CELT frame, which
+    // comes first when changing from CELT.
+    let mut redundancy = false;
+    let mut celt_to_silk = false;
+    let mut redundancy_bytes = 0;
+    let hybrid_bits = if mode == Mode::Hybrid { 20 } else { 0 };
+    if mode != Mode::Celt && coder.tell() + 17 + hybrid_bits <= 8 * len as i32 {
+      redundancy = mode == Mode::Silk || coder.decode_bit_logp(12);
+      if redundancy {
+        celt_to_silk = coder.decode_bit_logp(1);
+        redundancy_bytes =
+          if mode == Mode::Hybrid { coder.decode_uint(256) as usize + 2 }
+          else { len - ((coder.tell() as usize + 7) >> 3) };
+        // Ignores redundant frames longer than the frame.
+        if redundancy_bytes > len || 8 * (len - redundancy_bytes) < coder.tell() as usize {
+          redundancy_bytes = 0;
+          redundancy = false;
+          len = 0;
+        } else {
+          len -= redundancy_bytes;
+        }
+        coder.shrink(redundancy_bytes as u32);
+      }
+    }
+    let start_band = if mode != Mode::Celt { HYBRID_START_BAND } else { 0 };
+    if redundancy {
+      transition = false;
+    }
+    if transition && mode != Mode::Celt {
+      self.decode_lost(F5.min(frame_size), &mut pcm_transition);
+    }
+
+    let redundant_data = &data[len .. len + redundancy_bytes];
+    let mut redundant_audio = Vec::new();
+    if redundancy && celt_to_silk {
+      let mut redundant_coder = RangeCoder::decoder(redundant_data);
+      self.celt.decode(&mut redundant_coder, redundancy_bytes, stream_channels, lm(F5), 0,
+                       self.end_band, &mut redundant_audio);
+    }
+
+    let mut pcm = Vec::with_capacity(frame_size * channels);
+    if mode != Mode::Silk {
+      if Some(mode) != self.prev_mode && self.prev_mode.is_some() && !self.prev_redundancy {
+        self.celt.reset();
+      }
+      self.celt.decode(&mut coder, len, stream_channels, lm(frame_size.min(F20)), start_band,
+                       self.end_band, &mut pcm);
+    } else {
+      // Changes from the hybrid mode let the CELT MDCT fade out, by decoding
+      // a silent frame.
+      if self.prev_mode == Some(Mode::Hybrid) &&
+         !(redundancy && celt_to_silk && self.prev_redundancy) {
+        let silence = [0xFF, 0xFF];
+        let mut silence_coder = RangeCoder::decoder(&silence);
+        self.celt.decode(&mut silence_coder, silence.len(), stream_channels, lm(F2_5), 0,
+                         self.end_band, &mut pcm);
+      }
+      pcm.resize(frame_size * channels, 0f32);
+    }
+    if mode != Mode::Celt {
+      for (p, s) in pcm.iter_mut().zip(pcm_silk.iter()) {
+        *p += (1f32 / 32768f32) * *s as f32;
+      }
+    }
+
+    if redundancy && !celt_to_silk {
+      self.celt.reset();
+      let mut redundant_coder = RangeCoder::decoder(redundant_data);
+      self.celt.decode(&mut redundant_coder, redundancy_bytes, stream_channels, lm(F5), 0,
+                       self.end_band, &mut redundant_audio);
+      let end = (frame_size - F2_5) * channels;
+      let faded = smooth_fade(&pcm[end..], &redundant_audio[F2_5 * channels ..],
+                              channels, &self.window);
+      pcm[end..].copy_from_slice(&faded);
+    }
+    if redundancy && celt_to_silk {
+      pcm[.. F2_5 * channels].copy_from_slice(&redundant_audio[.. F2_5 * channels]);
+      let faded = smooth_fade(&redundant_audio[F2_5 * channels ..],
+                              &pcm[F2_5 * channels .. F5 * channels], channels, &self.window);
+      pcm[F2_5 * channels .. F5 * channels].copy_from_slice(&faded);
+    }
+    if transition {
+      if frame_size >= F5 {
+        pcm[.. F2_5 * channels].copy_from_slice(&pcm_transition[.. F2_5 * channels]);
+        let faded = smooth_fade(&pcm_transition[F2_5 * channels ..],
+                                &pcm[F2_5 * channels .. F5 * channels], channels, &self.window);
+        pcm[F2_5 * channels .. F5 * channels].copy_from_slice(&faded);
+      } else {
+        // Without enough audio for a clean change, fades over what there
+        // is.
+        let faded = smooth_fade(&pcm_transition, &pcm, channels, &self.window);
+        pcm[.. F2_5 * channels].copy_from_slice(&faded);
+      }
+    }
+
+    self.prev_mode = Some(mode);
+    self.prev_redundancy = redundancy && !celt_to_silk;
+    output.extend_from_slice(&pcm);
+  }
+}
+
+/// Returns the number of short blocks of a CELT frame as a power of two.
+fn lm(frame_size: usize) -> usize {
+  (frame_size / F2_5).trailing_zeros() as usize
+}
+
+/// Fades from the first to the second signal over 2.5 ms with the square of
+/// the MDCT window, returning the faded samples.
+fn smooth_fade(from: &[f32], to: &[f32], channels: usize, window: &[f32]) -> Vec<f32> {
+  let mut out = vec![0f32; F2_5 * channels];
+  for c in 0..channels {
+    for i in 0..F2_5 {
+      let w = window[i] * window[i];
+      let j = i * channels + c;
+      out[j] = w * to[j] + (1f32 - w) * from[j];
+    }
+  }
+  out
+}
@@ -0,0 +1,240 @@
+//! Pyramid Vector Quantization
+//!
+//! The normalized shape of a band is quantized to the closest vector of
+//! integers whose absolute values sum to a number of pulses, after a
+//! rotation which spreads the energy of the pulses over nearby values.
+use std::f32::consts::PI;
+use opus::cwrs;
+use opus::range::RangeCoder;
+
+/// Small value added to energies to avoid dividing by zero.
+pub const EPSILON: f32 = 1e-15;
+
+/// Spreading decisions, from no rotation to the largest rotation, with
+/// light spreading in between.
+pub const SPREAD_NONE:       usize = 0;
+pub const SPREAD_NORMAL:     usize = 2;
+pub const SPREAD_AGGRESSIVE: usize = 3;
+
+/// Rotates pairs of values `stride` apart forwards then backwards.
+fn exp_rotation1(x: &mut [f32], len: usize, stride: usize, c: f32, s: f32) {
+  for i in 0 .. len - stride {
+    let x1 = x[i];
+    let x2 = x[i + stride];
+    x[i + stride] = c * x2 + s * x1;
+    x[i]          = c * x1 - s * x2;
+  }
+  if len >= 2 * stride + 1 {
+    let mut i = len - 2 * stride - 1;
+    loop {
+      let x1 = x[i];
+      let x2 = x[i + stride];
+      x[i + stride] = c * x2 + s * x1;
+      x[i]          = c * x1 - s * x2;
+      if i == 0 {
+        break;
+      }
+      i -= 1;
+    }
+  }
+}
+
+/// Applies the spreading rotation to a vector of `blocks` interleaved
+/// blocks, or its inverse when `forward` is false.
+pub fn exp_rotation(x: &mut [f32], len: usize, forward: bool, blocks: usize,
+                    k: usize, spread: usize) {
+  const SPREAD_FACTOR: [usize; 3] = [15, 10, 5];
+  if 2 * k >= len || spread == SPREAD_NONE {
+    return;
+  }
+  let factor = SPREAD_FACTOR[spread - 1];
+  let gain  = len as f32 / (len + factor * k) as f32;
+  let theta = 0.5 * gain * gain;
+  let c = (0.5 * PI * theta).cos();
+  let s = (0.5 * PI * (1f32 - theta)).cos();
+
+  let mut stride2 = 0;
+  if len >= 8 * blocks {
+    stride2 = 1;
+    // Rounded square root of len / blocks.
+    while (stride2 * stride2 + stride2) * blocks + (blocks >> 2) < len {
+      stride2 += 1;
+    }
+  }
+  let len = len / blocks;
+  for i in 0..blocks {
+    let block = &mut x[i * len .. (i + 1) * len];
+    if !forward {
+      if stride2 != 0 {
+        exp_rotation1(block, len, stride2, s, c);
+      }
+      exp_rotation1(block, len, 1, c, s);
+    }
+    else {
+      exp_rotation1(block, len, 1, c, -s);
+      if stride2 != 0 {
+        exp_rotation1(block, len, stride2, s, -c);
+      }
+    }
+  }
+}
+
+/// Scales the pulses of a vector to the given norm.
+fn normalise_residual(iy: &[i32], x: &mut [f32], n: usize, ryy: f32, gain: f32) {
+  let g = gain / ryy.sqrt();
+  for i in 0..n {
+    x[i] = g * iy[i] as f32;
+  }
+}
+
+/// Returns a bit for each block of the vector which has any pulses.
+fn extract_collapse_mask(iy: &[i32], n: usize, blocks: usize) -> u32 {
+  if blocks <= 1 {
+    return 1;
+  }
+  let n0 = n / blocks;
+  let mut mask = 0;
+  for i in 0..blocks {
+    if iy[i * n0 .. (i + 1) * n0].iter().any(|&y| y != 0) {
+      mask |= 1 << i;
+    }
+  }
+  mask
+}
+
+/// Finds the vector of `k` pulses closest in direction to `x`, returning its
+/// squared norm.
+fn pvq_search(x: &mut [f32], iy: &mut [i32], k: usize, n: usize) -> f32 {
+  let mut y     = vec![0f32; n];
+  let mut signx = vec![false; n];
+  for j in 0..n {
+    signx[j] = x[j] < 0f32;
+    x[j] = x[j].abs();
+    iy[j] = 0;
+  }
+  let mut xy = 0f32;
+  let mut yy = 0f32;
+  let mut pulses_left = k as i32;
+
+  // Start by projecting on the pyramid.
+  if k > n >> 1 {
+    let mut sum: f32 = x[..n].iter().sum();
+    // Replaces a vector too small, infinite, or not a number with a pulse.
+    if !(sum > EPSILON && sum < 64f32) {
+      x[0] = 1f32;
+      for j in 1..n {
+        x[j] = 0f32;
+      }
+      sum = 1f32;
+    }
+    // Using k + 0.8 ensures no more than k pulses are placed.
+    let rcp = (k as f32 + 0.8) / sum;
+    for j in 0..n {
+      iy[j] = (rcp * x[j]).floor() as i32;
+      y[j] = iy[j] as f32;
+      yy += y[j] * y[j];
+      xy += x[j] * y[j];
+      y[j] *= 2f32;
+      pulses_left -= iy[j];
+    }
+  }
+
+  if pulses_left > n as i32 + 3 {
+    let tmp = pulses_left as f32;
+    yy += tmp * tmp;
+    yy += tmp * y[0];
+    iy[0] += pulses_left;
+    pulses_left = 0;
+  }
+
+  for _ in 0..pulses_left {
+    yy += 1f32;
+    // Maximizes rxy / sqrt(ryy), comparing without divisions.
+    let mut best_id  = 0;
+    let mut rxy      = xy + x[0];
+    let mut best_den = yy + y[0];
+    let mut best_num = rxy * rxy;
+    for j in 1..n {
+      rxy = xy + x[j];
+      let ryy = yy + y[j];
+      rxy = rxy * rxy;
+      if best_den * rxy > ryy * best_num {
+        best_den = ryy;
+        best_num = rxy;
+        best_id  = j;
+      }
+    }
+    xy += x[best_id];
+    yy += y[best_id];
+    y[best_id] += 2f32;
+    iy[best_id] += 1;
+  }
+
+  for j in 0..n {
+    if signx[j] {
+      iy[j] = -iy[j];
+    }
+  }
+  yy
+}
+
+/// Quantizes and encodes the shape of a band with `k` pulses, returning the
+/// collapse mask of its blocks. When `resynth` is set, `x` is replaced with
+/// the quantized shape scaled by `gain`.
+pub fn quantize(x: &mut [f32], n: usize, k: usize, spread: usize, blocks: usize,
+                coder: &mut RangeCoder, gain: f32, resynth: bool) -> u32 {
+  let mut iy = vec![0i32; n];
+  exp_rotation(x, n, true, blocks, k, spread);
+  let yy = pvq_search(x, &mut iy, k, n);
+  coder.encode_uint(cwrs::index(&iy, n, k), cwrs::count(n, k));
+  if resynth {
+    normalise_residual(&iy, x, n, yy, gain);
+    exp_rotation(x, n, false, blocks, k, spread);
+  }
+  extract_collapse_mask(&iy, n, blocks)
+}
+
+/// Decodes the shape of a band with `k` pulses scaled by `gain`, returning
+/// the collapse mask of its blocks.
+pub fn unquantize(x: &mut [f32], n: usize, k: usize, spread: usize, blocks: usize,
+                  coder: &mut RangeCoder, gain: f32) -> u32 {
+  let mut iy = vec![0i32; n];
+  let index = coder.decode_uint(cwrs::count(n, k));
+  let ryy = cwrs::vector(index, &mut iy, n, k);
+  normalise_residual(&iy, x, n, ryy, gain);
+  exp_rotation(x, n, false, blocks, k, spread);
+  extract_collapse_mask(&iy, n, blocks)
+}
+
+/// Scales a vector to the given norm.
+pub fn renormalise_vector(x: &mut [f32], gain: f32) {
+  let energy = EPSILON + x.iter().map(|v| v * v).sum::<f32>();
+  let g = gain / energy.sqrt();
+  for v in x.iter_mut() {
+    *v *= g;
+  }
+}
+
+/// Returns the angle between the mid and side of a stereo band, or between
+/// the halves of a split band, where 16384 is a right angle.
+pub fn stereo_itheta(x: &[f32], y: &[f32], stereo: bool, n: usize) -> i32 {
+  let mut emid  = EPSILON;
+  let mut eside = EPSILON;
+  if stereo {
+    for i in 0..n {
+      let m = x[i] + y[i];
+      let s = x[i] - y[i];
+      emid  += m * m;
+      eside += s * s;
+    }
+  }
+  else {
+    for i in 0..n {
+      emid  += x[i] * x[i];
+      eside += y[i] * y[i];
+    }
+  }
+  let mid  = emid.sqrt();
+  let side = eside.sqrt();
+  (0.5 + 16384f32 * 0.63662 * side.atan2(mid)).floor() as i32
+}
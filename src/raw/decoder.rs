@@ -0,0 +1,50 @@
+use std::io::{Read, Seek, SeekFrom};
+use buffer::AudioBuffer;
+use error::*;
+use raw::RawSpec;
+use traits::AudioDecoder;
+
+/// Decodes raw audio described by a `RawSpec` from the
+/// provided reader.
+pub struct Decoder<'r, R: 'r> where R: Read + Seek {
+  reader: &'r mut R,
+  spec:   RawSpec
+}
+
+impl<'r, R> Decoder<'r, R> where R: Read + Seek {
+  /// Create a new raw `Decoder` using the provided reader and the
+  /// `RawSpec` describing the audio.
+  #[inline]
+  pub fn new(reader: &'r mut R, spec: RawSpec) -> Decoder<'r, R> {
+    Decoder {
+      reader: reader,
+      spec:   spec
+    }
+  }
+}
+
+impl<'r, R> AudioDecoder for Decoder<'r, R> where R: Read + Seek {
+  /// Creates an `AudioBuffer` from the included reader, skipping the header
+  /// and reading up to the frame limit of the `RawSpec`.
+  fn decode(self) -> AudioResult<AudioBuffer> {
+    let spec = self.spec;
+    if spec.channels == 0 {
+      return Err(AudioError::Format(
+        "Raw audio must have at least one channel".to_string()
+      ));
+    }
+    let frame_size = (spec.codec.sample_size() * spec.channels as usize) as u64;
+    try!(self.reader.seek(SeekFrom::Current(spec.header_bytes as i64)));
+    let mut bytes: Vec<u8> = Vec::new();
+    match spec.frame_limit {
+      Some(frames) =>
+        try!(self.reader.take(frames.saturating_mul(frame_size)).read_to_end(&mut bytes)),
+      None =>
+        try!(self.reader.read_to_end(&mut bytes))
+    };
+    // Incomplete frames at the end of the data are ignored.
+    let complete_frames_len = bytes.len() - bytes.len() % frame_size as usize;
+    bytes.truncate(complete_frames_len);
+    AudioBuffer::from_bytes(spec.sample_rate, spec.channels, &bytes, spec.codec)
+  }
+}
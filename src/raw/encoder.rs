@@ -0,0 +1,49 @@
+use std::io::Write;
+use buffer::AudioBuffer;
+use codecs::Codec;
+use error::*;
+use raw::RawSpec;
+use traits::AudioEncoder;
+
+/// Encodes audio as raw samples to the provided writer. The header and frame
+/// limit of the `RawSpec` only apply when reading. Since the sample rate and
+/// number of channels are not stored, the audio must match the `RawSpec`.
+pub struct Encoder<'w, W: 'w> {
+  writer: &'w mut W,
+  spec:   RawSpec
+}
+
+impl<'w, W> Encoder<'w, W> where W: Write {
+  /// Create a new raw `Encoder` using the provided writer and the
+  /// `RawSpec` describing the audio.
+  #[inline]
+  pub fn new(writer: &'w mut W, spec: RawSpec) -> Encoder<'w, W> {
+    Encoder {
+      writer: writer,
+      spec:   spec
+    }
+  }
+}
+
+impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
+  /// Writes the audio encoded using the codec of the `RawSpec`.
+  #[inline]
+  fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
+    let codec = self.spec.codec;
+    self.encode_as(audio, codec)
+  }
+  /// Writes the audio encoded using the provided `Codec`.
+  fn encode_as(&mut self, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    if audio.sample_rate != self.spec.sample_rate || audio.channels != self.spec.channels {
+      return Err(AudioError::Unsupported(
+        format!("Raw audio is described as {} Hz with {} channels, \
+                but the audio is {} Hz with {} channels",
+                self.spec.sample_rate, self.spec.channels,
+                audio.sample_rate, audio.channels)
+      ));
+    }
+    let data: Vec<u8> = try!(::codecs::encode(audio, codec));
+    try!(self.writer.write_all(&data));
+    Ok(())
+  }
+}
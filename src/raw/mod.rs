@@ -0,0 +1,134 @@
+//! Headerless Raw Audio
+//!
+//! Raw audio files contain only encoded samples, so the codec, sample rate,
+//! and number of channels must be described by a `RawSpec`. Files produced by
+//! devices may also begin with a header of a known size that is skipped.
+//!
+//! Files with the `.ul` and `.al` extensions are conventionally µ-law and
+//! A-law telephony audio, sampled at 8 kHz with one channel.
+
+pub mod decoder;
+pub mod encoder;
+
+pub use raw::decoder::Decoder as Decoder;
+pub use raw::encoder::Encoder as Encoder;
+
+use codecs::Codec;
+
+/// Describes the layout of raw audio.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RawSpec {
+  /// Codec used to encode the samples
+  pub codec:        Codec,
+  /// Number of samples per second
+  pub sample_rate:  u32,
+  /// Number of channels
+  pub channels:     u32,
+  /// Number of bytes skipped before the first sample when reading
+  pub header_bytes: u64,
+  /// Maximum number of frames read, or all frames if `None`
+  pub frame_limit:  Option<u64>
+}
+
+impl RawSpec {
+  /// Creates a `RawSpec` for audio without a header.
+  pub fn new(codec: Codec, sample_rate: u32, channels: u32) -> RawSpec {
+    RawSpec {
+      codec:        codec,
+      sample_rate:  sample_rate,
+      channels:     channels,
+      header_bytes: 0,
+      frame_limit:  None
+    }
+  }
+}
+
+impl Default for RawSpec {
+  /// Signed 16-bit, little-endian LPCM sampled at 44.1 kHz with one channel,
+  /// which is the most common layout of raw audio.
+  fn default() -> Self {
+    RawSpec::new(Codec::LPCM_I16_LE, 44100, 1)
+  }
+}
+
+#[cfg(test)]
+mod io {
+  use std::fs::File;
+  use std::io::Cursor;
+  use std::path::Path;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+  use ::error::AudioError;
+  use super::RawSpec;
+
+  #[test]
+  fn header_and_frame_limit() {
+    let spec = RawSpec::new(LPCM_I16_BE, 16000, 2);
+    let audio = AudioBuffer::from_samples(16000, 2, vec![0f32, 0.5f32, -0.5f32, 0.25f32, 0.125f32, 0f32]);
+    let mut bytes: Vec<u8> = vec![0xAB; 5];
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::Raw(spec)).is_ok());
+    // Incomplete frames at the end of the data are ignored.
+    bytes.push(0);
+    assert_eq!(5 + 12 + 1, bytes.len());
+
+    let spec = RawSpec { header_bytes: 5, .. spec };
+    let verify = audio::load(&mut Cursor::new(bytes.clone()), AudioFormat::Raw(spec)).unwrap();
+    assert_eq!(16000, verify.sample_rate);
+    assert_eq!(2, verify.channels);
+    assert_eq!(audio.samples, verify.samples);
+
+    let spec = RawSpec { frame_limit: Some(2), .. spec };
+    let verify = audio::load(&mut Cursor::new(bytes.clone()), AudioFormat::Raw(spec)).unwrap();
+    assert_eq!(&audio.samples[0..4], &verify.samples[..]);
+
+    // A limit too large for the number of bytes reads every frame.
+    let spec = RawSpec { frame_limit: Some(u64::max_value()), .. spec };
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::Raw(spec)).unwrap();
+    assert_eq!(audio.samples, verify.samples);
+  }
+
+  #[test]
+  fn g711_extensions() {
+    let audio = AudioBuffer::from_samples(8000, 1, vec![0f32, 0.5f32, -0.5f32]);
+    for &(file, codec) in [("tests/results/tmp_raw.ul", G711_ULAW),
+                           ("tests/results/tmp_raw.al", G711_ALAW)].iter() {
+      let write_path = Path::new(file);
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(8000, verify.sample_rate);
+      assert_eq!(1, verify.channels);
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write_as(&mut bytes, &audio, AudioFormat::Raw(RawSpec::new(codec, 8000, 1)), codec).unwrap();
+      let expected = AudioBuffer::from_bytes(8000, 1, &bytes, codec).unwrap();
+      assert_eq!(expected.samples, verify.samples);
+    }
+  }
+
+  #[test]
+  fn open_as() {
+    let audio = AudioBuffer::from_samples(22050, 1, vec![0f32, 0.5f32, -0.5f32]);
+    let write_path = Path::new("tests/results/tmp_raw.pcm");
+    let spec = RawSpec::new(LPCM_F32_LE, 22050, 1);
+    let mut file = File::create(&write_path).unwrap();
+    assert!(audio::write(&mut file, &audio, AudioFormat::Raw(spec)).is_ok());
+    let verify = audio::open_as(&write_path, AudioFormat::Raw(spec)).unwrap();
+    assert_eq!(audio.samples, verify.samples);
+  }
+
+  #[test]
+  fn mismatched_spec() {
+    // The file would be read back as 8 kHz mono.
+    let audio = AudioBuffer::from_samples(44100, 2, vec![0f32, 0.5f32, -0.5f32, 0.25f32]);
+    let write_path = Path::new("tests/results/tmp_mismatched.ul");
+    match audio::save(&write_path, &audio) {
+      Err(AudioError::Unsupported(_)) => {},
+      result => panic!("Expected unsupported error, got {:?}", result)
+    }
+    let spec = RawSpec::new(LPCM_I16_LE, 44100, 1);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write(&mut bytes, &audio, AudioFormat::Raw(spec)).is_err());
+    assert!(audio::save_as(Path::new("tests/results/tmp_raw.pcm"), &audio, LPCM_F32_LE).is_err());
+  }
+}
@@ -0,0 +1,80 @@
+use std::io::{Cursor, Read, Seek, Write};
+use aiff::container::AiffContainer;
+use buffer::*;
+use codecs::Codec;
+use error::*;
+use metadata::{Metadata, RiffWrapper};
+use sample::*;
+use shorten::DEFAULT_SAMPLE_RATE;
+use shorten::stream::{ShortenStream, to_bytes};
+use traits::Container;
+use wave::container::WaveContainer;
+
+/// Struct containing all necessary information for decoding a Shorten
+/// stream to an `AudioBuffer`.
+pub struct ShortenContainer {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for ShortenContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<ShortenContainer> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(reader.read_to_end(&mut bytes));
+    let stream = try!(ShortenStream::read(&bytes));
+
+    // Samples are converted as if they were read from LPCM, so the audio is
+    // encoded identically by the LPCM codecs of the same bit depth.
+    let codec = stream.codec();
+    let data = to_bytes(&stream.values, codec);
+    let samples = try!(::codecs::decode(&data, codec));
+
+    // The sample rate and metadata are read from the header and trailer of
+    // the file the audio was compressed from.
+    let mut sample_rate = DEFAULT_SAMPLE_RATE;
+    let mut metadata = Metadata::default();
+    let header = &stream.header;
+    if header.len() >= 12 && &header[8..12] == b"WAVE" {
+      let wrapper = RiffWrapper {
+        header:   stream.header.clone(),
+        trailer:  stream.trailer.clone()
+      };
+      if let Ok(container) = WaveContainer::read_wrapper(&wrapper, &data) {
+        sample_rate = container.sample_rate;
+        metadata = container.metadata;
+      }
+      metadata.riff = Some(wrapper);
+    } else if header.len() >= 12 && &header[0..4] == b"FORM" {
+      let mut file = header.clone();
+      file.extend_from_slice(&data);
+      file.extend_from_slice(&stream.trailer);
+      if let Ok(container) = AiffContainer::open(&mut Cursor::new(file)) {
+        sample_rate = container.sample_rate;
+        metadata = container.metadata;
+      }
+    }
+
+    Ok(ShortenContainer {
+      bit_depth:      codec.bit_depth() as u32,
+      sample_rate:    sample_rate,
+      channels:       stream.channels as u32,
+      order:
+        if stream.channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:        samples,
+      metadata:       metadata
+    })
+  }
+  fn create<W: Write>(_: &mut W, _: &AudioBuffer, _: Codec) -> AudioResult<()> {
+    Err(AudioError::Unsupported(
+      "Encoding Shorten is not supported".to_string()
+    ))
+  }
+}
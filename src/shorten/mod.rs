@@ -0,0 +1,94 @@
+//! The Shorten Format
+//!
+//! Shorten files hold a single Shorten stream, which compresses the samples
+//! of a WAVE or AIFF file losslessly along with the bytes of the file
+//! surrounding them. The sample rate and metadata are read from those bytes,
+//! and the header and trailer of a WAVE file are kept with the audio, so
+//! saving it to WAVE restores the original file. Streams compressed from
+//! headerless audio are assumed to have a sample rate of 44.1 kHz.
+//!
+//! Signed and unsigned samples of 8 and 16 bits are decoded. Shorten
+//! streams are also embedded in NIST SPHERE files.
+//!
+//! References
+//! - [Shorten](https://www.etree.org/shnutils/shorten/)
+//! - [Shorten: Simple lossless and near-lossless waveform compression](http://svr-www.eng.cam.ac.uk/reports/abstracts/robinson_tr156.html)
+
+mod container;
+pub mod decoder;
+pub mod stream;
+
+pub use shorten::decoder::Decoder as Decoder;
+
+/// Sample rate of streams whose header does not give one.
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+#[cfg(test)]
+mod io {
+  use std::io::Cursor;
+  use std::path::Path;
+  use byteorder::{BigEndian, ByteOrder, LittleEndian};
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+  use ::wave::container::WaveContainer;
+  use super::stream::*;
+
+  #[test]
+  fn wave_round_trip() {
+    let original = audio::open(Path::new("tests/wav/M1F1-int16-AFsp.wav")).unwrap();
+    let lpcm = ::codecs::encode(&original, LPCM_I16_LE).unwrap();
+    let values: Vec<i32> = lpcm.chunks(2).map(|b| LittleEndian::read_i16(b) as i32).collect();
+    let wrapper = WaveContainer::wrapper(&original, LPCM_I16_LE).unwrap();
+    let bytes = write_stream(&values, 2, TYPE_S16LH, &wrapper.header, &wrapper.trailer);
+
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::Shorten).unwrap();
+    assert_eq!(2,    audio.channels);
+    assert_eq!(8000, audio.sample_rate);
+    assert_eq!(lpcm, ::codecs::encode(&audio, LPCM_I16_LE).unwrap());
+    assert_eq!(Some(&wrapper), audio.metadata.riff.as_ref());
+
+    // The original file is restored when saved to WAVE.
+    let mut file = wrapper.header.clone();
+    file.extend_from_slice(&lpcm);
+    file.extend_from_slice(&wrapper.trailer);
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write_as(&mut bytes, &audio, AudioFormat::WAVE, LPCM_I16_LE).unwrap();
+    assert_eq!(file, bytes);
+  }
+
+  #[test]
+  fn aiff_header() {
+    let original = audio::open(Path::new("tests/aiff/M1F1-int16-AFsp.aif")).unwrap();
+    let mut file: Vec<u8> = Vec::new();
+    audio::write_as(&mut file, &original, AudioFormat::AIFF, LPCM_I16_BE).unwrap();
+    let ssnd = (0 .. file.len()).find(|&i| &file[i .. i + 4] == b"SSND").unwrap();
+    let end = ssnd + 8 + BigEndian::read_u32(&file[ssnd + 4 .. ssnd + 8]) as usize;
+    let values: Vec<i32> = file[ssnd + 16 .. end].chunks(2)
+                           .map(|b| BigEndian::read_i16(b) as i32).collect();
+    let bytes = write_stream(&values, 2, TYPE_S16HL, &file[.. ssnd + 16], &file[end..]);
+
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::Shorten).unwrap();
+    assert_eq!(original.sample_rate, audio.sample_rate);
+    assert_eq!(original.samples,     audio.samples);
+    assert!(audio.metadata.riff.is_none());
+  }
+
+  #[test]
+  fn headerless() {
+    let values: Vec<i32> = (0..3000).map(|i| (i * 37) % 256).collect();
+    let bytes = write_stream(&values, 1, TYPE_U8, &[], &[]);
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::Shorten).unwrap();
+    assert_eq!(44100, audio.sample_rate);
+    let lpcm: Vec<u8> = values.iter().map(|value| *value as u8).collect();
+    assert_eq!(lpcm, ::codecs::encode(&audio, LPCM_U8).unwrap());
+  }
+
+  #[test]
+  fn errors() {
+    assert!(audio::load(&mut Cursor::new(b"ajkh\x02".to_vec()), AudioFormat::Shorten).is_err());
+    let audio = AudioBuffer::from_samples(44100, 1, vec![0f32]);
+    assert!(audio::write(&mut Vec::new(), &audio, AudioFormat::Shorten).is_err());
+  }
+}
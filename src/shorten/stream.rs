@@ -0,0 +1,658 @@
+//! Shorten Streams
+//!
+//! A Shorten stream is a bitstream of variable length codes, beginning with
+//! the type of the samples, the number of channels, and the parameters of
+//! the stream. It is followed by a sequence of commands, each either coding
+//! a block of one channel or changing the state of the decoder. Each block
+//! is predicted from the samples before it, using a fixed polynomial or
+//! quantized LPC coefficients, and the residuals are coded with Rice codes.
+//! Verbatim commands hold bytes of the original file, such as its header.
+use codecs::Codec;
+use codecs::Codec::*;
+use error::*;
+
+/// Signature at the start of the stream.
+const AJKG: &'static [u8; 4] = b"ajkg";
+
+/// Newest version of the stream that can be decoded.
+const MAX_VERSION: u8 = 3;
+
+/// Number of low bits of the variable length code of each field.
+const TYPE_SIZE:            u32 = 4;
+const CHANNEL_SIZE:         u32 = 0;
+const LPC_ORDER_SIZE:       u32 = 2;
+const MEAN_SIZE:            u32 = 0;
+const SKIP_SIZE:            u32 = 1;
+const ULONG_SIZE:           u32 = 2;
+const COMMAND_SIZE:         u32 = 2;
+const ENERGY_SIZE:          u32 = 3;
+const BITSHIFT_SIZE:        u32 = 2;
+const VERBATIM_SIZE:        u32 = 5;
+const VERBATIM_BYTE_SIZE:   u32 = 8;
+
+/// Number of fractional bits of quantized LPC coefficients.
+const LPC_QUANT: u32 = 5;
+
+/// Parameters used unless given by the stream.
+const DEFAULT_BLOCK_SIZE: usize = 256;
+const DEFAULT_V0_MEANS:   usize = 0;
+const DEFAULT_V2_MEANS:   usize = 4;
+
+/// Least number of samples of the previous block kept for prediction.
+const MIN_WRAP: usize = 3;
+
+/// Limits of the stream parameters.
+const MAX_CHANNELS:   usize = 8;
+const MAX_BLOCK_SIZE: usize = 65535;
+const MAX_LPC_ORDER:  usize = 1024;
+const MAX_MEANS:      usize = 32768;
+
+/// Commands of the stream.
+const FN_DIFF0:     u32 = 0;
+const FN_DIFF1:     u32 = 1;
+const FN_DIFF2:     u32 = 2;
+const FN_DIFF3:     u32 = 3;
+const FN_QUIT:      u32 = 4;
+const FN_BLOCKSIZE: u32 = 5;
+const FN_BITSHIFT:  u32 = 6;
+const FN_QLPC:      u32 = 7;
+const FN_ZERO:      u32 = 8;
+const FN_VERBATIM:  u32 = 9;
+
+/// Types of the samples, for the types that can be decoded.
+pub const TYPE_S8:    u32 = 1;
+pub const TYPE_U8:    u32 = 2;
+pub const TYPE_S16HL: u32 = 3;
+pub const TYPE_U16HL: u32 = 4;
+pub const TYPE_S16LH: u32 = 5;
+pub const TYPE_U16LH: u32 = 6;
+
+/// A decoded Shorten stream.
+pub struct ShortenStream {
+  /// Type of the samples, giving their size, signedness, and byte order
+  pub file_type:  u32,
+  pub channels:   usize,
+  /// Verbatim bytes preceding the audio, usually the header of the file
+  /// the audio was compressed from
+  pub header:     Vec<u8>,
+  /// Verbatim bytes following the audio
+  pub trailer:    Vec<u8>,
+  /// Samples interleaved by channel, as signed integers
+  pub values:     Vec<i32>
+}
+
+impl ShortenStream {
+  /// Decodes a stream beginning with its signature. Bytes following the
+  /// end of the stream are ignored.
+  pub fn read(bytes: &[u8]) -> AudioResult<ShortenStream> {
+    if bytes.len() < 5 || &bytes[0..4] != AJKG {
+      return Err(AudioError::Format(
+        "Not valid Shorten".to_string()
+      ));
+    }
+    let version = bytes[4];
+    if version > MAX_VERSION {
+      return Err(AudioError::Unsupported(
+        format!("Shorten version {} is not supported", version)
+      ));
+    }
+    let mut bits = BitReader::new(&bytes[5..]);
+    let file_type = try!(bits.read_uint(version, TYPE_SIZE));
+    let mean =
+      match file_type {
+        TYPE_S8 | TYPE_S16HL | TYPE_S16LH => 0,
+        TYPE_U8                           => 0x80,
+        TYPE_U16HL | TYPE_U16LH           => 0x8000,
+        t @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("Shorten sample type {} is not supported", t)
+          ))
+      };
+    let channels = try!(bits.read_uint(version, CHANNEL_SIZE)) as usize;
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut max_lpc_order = 0;
+    let mut means = if version < 2 { DEFAULT_V0_MEANS } else { DEFAULT_V2_MEANS };
+    if version > 0 {
+      block_size    = try!(bits.read_uint(version, log2(DEFAULT_BLOCK_SIZE))) as usize;
+      max_lpc_order = try!(bits.read_uint(version, LPC_ORDER_SIZE)) as usize;
+      means         = try!(bits.read_uint(version, MEAN_SIZE)) as usize;
+      let skip      = try!(bits.read_uint(version, SKIP_SIZE));
+      for _ in 0..skip {
+        bits.read(8);
+      }
+    }
+    if channels == 0 || channels > MAX_CHANNELS
+       || block_size == 0 || block_size > MAX_BLOCK_SIZE
+       || max_lpc_order > MAX_LPC_ORDER || means > MAX_MEANS {
+      return Err(AudioError::Format(
+        "File is not valid Shorten (Invalid stream parameters)".to_string()
+      ));
+    }
+
+    let mut state = State::new(version, channels, block_size, max_lpc_order, means, mean);
+    let mut header: Vec<u8> = Vec::new();
+    let mut trailer: Vec<u8> = Vec::new();
+    let mut values: Vec<i32> = Vec::new();
+    let mut blocks: Vec<Vec<i32>> = Vec::with_capacity(channels);
+    loop {
+      let command = bits.read_uvar(COMMAND_SIZE);
+      if bits.is_past_end() {
+        return Err(AudioError::Format(
+          "File is not valid Shorten (Stream is truncated)".to_string()
+        ));
+      }
+      match command {
+        FN_QUIT => break,
+        FN_VERBATIM => {
+          let len = bits.read_uvar(VERBATIM_SIZE);
+          let verbatim = if values.is_empty() && blocks.is_empty() { &mut header } else { &mut trailer };
+          for _ in 0..len {
+            verbatim.push(bits.read_uvar(VERBATIM_BYTE_SIZE) as u8);
+          }
+        },
+        FN_BITSHIFT => {
+          state.bitshift = bits.read_uvar(BITSHIFT_SIZE);
+          if state.bitshift > 32 {
+            return Err(AudioError::Format(
+              "File is not valid Shorten (Invalid bit shift)".to_string()
+            ));
+          }
+        },
+        FN_BLOCKSIZE => {
+          let size = try!(bits.read_uint(version, log2(state.block_size))) as usize;
+          // The block size may only decrease, usually for the last block.
+          if size == 0 || size > state.block_size {
+            return Err(AudioError::Format(
+              "File is not valid Shorten (Invalid block size)".to_string()
+            ));
+          }
+          state.block_size = size;
+        },
+        FN_DIFF0 | FN_DIFF1 | FN_DIFF2 | FN_DIFF3 | FN_QLPC | FN_ZERO => {
+          let channel = blocks.len();
+          let coffset = state.offset(channel);
+          if command == FN_ZERO {
+            try!(state.predict_block(channel, command, &[], coffset, |_| Ok(0)));
+          } else {
+            // Version 0 streams code residuals with one bit less.
+            let k = (bits.read_uvar(ENERGY_SIZE) + 1).saturating_sub((version == 0) as u32);
+            let mut coefs: Vec<i32> = Vec::new();
+            if command == FN_QLPC {
+              let order = bits.read_uvar(LPC_ORDER_SIZE) as usize;
+              if order > state.wrap {
+                return Err(AudioError::Format(
+                  "File is not valid Shorten (Invalid prediction order)".to_string()
+                ));
+              }
+              for _ in 0..order {
+                coefs.push(bits.read_svar(LPC_QUANT + 1));
+              }
+            }
+            try!(state.predict_block(channel, command, &coefs, coffset,
+                                     |prediction| Ok(prediction.wrapping_add(bits.read_svar(k)))));
+          }
+          blocks.push(state.end_block(channel));
+          if blocks.len() == channels {
+            for i in 0..state.block_size {
+              for block in blocks.iter() {
+                values.push(block[i] - mean);
+              }
+            }
+            blocks.clear();
+          }
+        },
+        c @ _ =>
+          return Err(AudioError::Format(
+            format!("File is not valid Shorten (Unknown command {})", c)
+          ))
+      }
+    }
+
+    Ok(ShortenStream {
+      file_type:  file_type,
+      channels:   channels,
+      header:     header,
+      trailer:    trailer,
+      values:     values
+    })
+  }
+
+  /// Returns the LPCM codec storing the samples as in the file the audio was
+  /// compressed from.
+  pub fn codec(&self) -> Codec {
+    match self.file_type {
+      TYPE_S8                   => LPCM_I8,
+      TYPE_U8                   => LPCM_U8,
+      TYPE_S16HL | TYPE_U16HL   => LPCM_I16_BE,
+      _                         => LPCM_I16_LE
+    }
+  }
+}
+
+/// Returns the bytes of integer samples, as stored using the LPCM codec.
+pub fn to_bytes(values: &[i32], codec: Codec) -> Vec<u8> {
+  let size = codec.sample_size();
+  let mut bytes = Vec::with_capacity(values.len() * size);
+  for &value in values.iter() {
+    match codec {
+      LPCM_U8 => bytes.push((value + 128) as u8),
+      LPCM_I16_BE | LPCM_I24_BE | LPCM_I32_BE => {
+        for i in (0..size).rev() {
+          bytes.push((value >> (8 * i)) as u8);
+        }
+      },
+      _ => {
+        for i in 0..size {
+          bytes.push((value >> (8 * i)) as u8);
+        }
+      }
+    }
+  }
+  bytes
+}
+
+/// Returns the number of bits below the highest set bit.
+#[inline]
+fn log2(value: usize) -> u32 {
+  63 - (value as u64 | 1).leading_zeros()
+}
+
+/// State of the decoder shared by every block.
+#[derive(Clone)]
+struct State {
+  version:      u8,
+  block_size:   usize,
+  bitshift:     u32,
+  lpc_offset:   i32,
+  wrap:         usize,
+  means:        usize,
+  channels:     Vec<ChannelState>
+}
+
+/// State of the decoder for one channel.
+#[derive(Clone)]
+struct ChannelState {
+  /// The current block, preceded by the last samples of the previous block
+  samples:  Vec<i32>,
+  /// Means of the last blocks
+  offsets:  Vec<i32>
+}
+
+impl State {
+  fn new(version: u8, channels: usize, block_size: usize, max_lpc_order: usize,
+         means: usize, mean: i32) -> State {
+    let wrap = max_lpc_order.max(MIN_WRAP);
+    State {
+      version:      version,
+      block_size:   block_size,
+      bitshift:     0,
+      lpc_offset:   if version > 1 { 1 << LPC_QUANT } else { 0 },
+      wrap:         wrap,
+      means:        means,
+      channels:     (0..channels).map(|_|
+        ChannelState {
+          samples:  vec![0; wrap + block_size],
+          offsets:  vec![mean; means.max(1)]
+        }
+      ).collect()
+    }
+  }
+
+  /// Returns the offset of the next block of a channel, from the means of
+  /// its last blocks.
+  fn offset(&self, channel: usize) -> i32 {
+    let offsets = &self.channels[channel].offsets;
+    if self.means == 0 {
+      return offsets[0];
+    }
+    let initial = if self.version < 2 { 0 } else { self.means as i32 / 2 };
+    let sum = offsets.iter().fold(initial, |sum, offset| sum.wrapping_add(*offset));
+    let offset = sum / self.means as i32;
+    if self.version >= 2 && self.bitshift > 0 {
+      (offset >> (self.bitshift - 1)) >> 1
+    } else {
+      offset
+    }
+  }
+
+  /// Predicts each sample of the next block of a channel, given by the
+  /// function from its prediction.
+  fn predict_block<F>(&mut self, channel: usize, command: u32, lpc_coefs: &[i32],
+                      offset: i32, mut sample: F) -> AudioResult<()>
+                      where F: FnMut(i32) -> AudioResult<i32> {
+    let (coefs, shift): (&[i32], u32) =
+      match command {
+        FN_QLPC  => (lpc_coefs, LPC_QUANT),
+        FN_DIFF1 => (&[1], 0),
+        FN_DIFF2 => (&[2, -1], 0),
+        FN_DIFF3 => (&[3, -3, 1], 0),
+        _        => (&[], 0)
+      };
+    let initial =
+      if coefs.is_empty() {
+        offset
+      } else if command == FN_QLPC {
+        self.lpc_offset
+      } else {
+        0
+      };
+    let wrap = self.wrap;
+    let end = wrap + self.block_size;
+    let samples = &mut self.channels[channel].samples;
+    let lpc_offset = if command == FN_QLPC { offset } else { 0 };
+    for value in samples[wrap - coefs.len() .. wrap].iter_mut() {
+      *value = value.wrapping_sub(lpc_offset);
+    }
+    for i in wrap..end {
+      if command == FN_ZERO {
+        samples[i] = 0;
+        continue;
+      }
+      let sum = coefs.iter().enumerate().fold(initial, |sum, (j, coef)|
+        sum.wrapping_add(coef.wrapping_mul(samples[i - j - 1]))
+      );
+      samples[i] = try!(sample(sum >> shift));
+    }
+    for value in samples[wrap..end].iter_mut() {
+      *value = value.wrapping_add(lpc_offset);
+    }
+    Ok(())
+  }
+
+  /// Updates the means and history of a channel with its decoded block,
+  /// returning the samples of the block.
+  fn end_block(&mut self, channel: usize) -> Vec<i32> {
+    let (wrap, block_size, bitshift) = (self.wrap, self.block_size, self.bitshift);
+    let state = &mut self.channels[channel];
+    if self.means > 0 {
+      let initial = if self.version < 2 { 0 } else { block_size as i64 / 2 };
+      let sum = state.samples[wrap .. wrap + block_size].iter()
+                .fold(initial, |sum, value| sum + *value as i64);
+      let mean =
+        if self.version < 2 {
+          sum / block_size as i64
+        } else if bitshift == 32 {
+          0
+        } else {
+          (sum / block_size as i64) << bitshift
+        };
+      state.offsets.remove(0);
+      state.offsets.push(mean as i32);
+    }
+    for i in 0..wrap {
+      state.samples[i] = state.samples[i + block_size];
+    }
+    // Low bits removed by the encoder are restored.
+    state.samples[wrap .. wrap + block_size].iter().map(|value|
+      if bitshift == 32 { 0 } else { ((*value as i64) << bitshift) as i32 }
+    ).collect()
+  }
+}
+
+/// Reads bits packed into a byte slice, starting at the most significant
+/// bit of each byte. Reading past the end of the slice gives zero bits.
+struct BitReader<'a> {
+  data:     &'a [u8],
+  position: usize
+}
+
+impl<'a> BitReader<'a> {
+  fn new(data: &'a [u8]) -> BitReader<'a> {
+    BitReader {
+      data:     data,
+      position: 0
+    }
+  }
+
+  fn is_past_end(&self) -> bool {
+    self.position > self.data.len() * 8
+  }
+
+  fn read_bit(&mut self) -> u32 {
+    let byte = self.data.get(self.position / 8).cloned().unwrap_or(0);
+    let bit = (byte >> (7 - self.position % 8)) & 1;
+    self.position += 1;
+    bit as u32
+  }
+
+  fn read(&mut self, bits: u32) -> u32 {
+    (0..bits).fold(0u64, |value, _| value << 1 | self.read_bit() as u64) as u32
+  }
+
+  /// Reads an unsigned Rice code, a unary count of zero bits ending with a
+  /// one bit followed by the given number of low bits.
+  fn read_uvar(&mut self, bits: u32) -> u32 {
+    let mut high: u64 = 0;
+    while self.read_bit() == 0 && !self.is_past_end() {
+      high += 1;
+    }
+    ((high << bits) | self.read(bits) as u64) as u32
+  }
+
+  /// Reads a signed Rice code, whose lowest bit is the sign.
+  fn read_svar(&mut self, bits: u32) -> i32 {
+    let value = self.read_uvar(bits);
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+  }
+
+  /// Reads an unsigned integer, which is preceded by its number of bits in
+  /// streams after version 0.
+  fn read_uint(&mut self, version: u8, bits: u32) -> AudioResult<u32> {
+    if version == 0 {
+      return Ok(self.read_uvar(bits));
+    }
+    let bits = self.read_uvar(ULONG_SIZE);
+    if bits > 32 {
+      return Err(AudioError::Format(
+        "File is not valid Shorten (Invalid integer size)".to_string()
+      ));
+    }
+    Ok(self.read_uvar(bits))
+  }
+}
+
+/// Encodes a stream of version 2, holding samples interleaved by channel in
+/// the representation of the sample type, and the verbatim bytes. This is
+/// the inverse of `ShortenStream::read`, used to create files for testing.
+#[cfg(test)]
+pub fn write_stream(values: &[i32], channels: usize, file_type: u32,
+                    header: &[u8], trailer: &[u8]) -> Vec<u8> {
+  let version = 2;
+  let block_size = DEFAULT_BLOCK_SIZE;
+  let means = DEFAULT_V2_MEANS;
+  let mean =
+    match file_type {
+      TYPE_U8                 => 0x80,
+      TYPE_U16HL | TYPE_U16LH => 0x8000,
+      _                       => 0
+    };
+  let mut bits = BitWriter::new();
+  bits.write_uint(file_type);
+  bits.write_uint(channels as u32);
+  bits.write_uint(block_size as u32);
+  bits.write_uint(MIN_WRAP as u32);
+  bits.write_uint(means as u32);
+  bits.write_uint(0);
+  let write_verbatim = |bits: &mut BitWriter, bytes: &[u8]| {
+    if !bytes.is_empty() {
+      bits.write_uvar(FN_VERBATIM, COMMAND_SIZE);
+      bits.write_uvar(bytes.len() as u32, VERBATIM_SIZE);
+      for byte in bytes.iter() {
+        bits.write_uvar(*byte as u32, VERBATIM_BYTE_SIZE);
+      }
+    }
+  };
+  write_verbatim(&mut bits, header);
+
+  let mut state = State::new(version, channels, block_size, MIN_WRAP, means, mean);
+  for frames in values.chunks(block_size * channels) {
+    let size = frames.len() / channels;
+    if size != state.block_size {
+      bits.write_uvar(FN_BLOCKSIZE, COMMAND_SIZE);
+      bits.write_uint(size as u32);
+      state.block_size = size;
+    }
+    for channel in 0..channels {
+      let block: Vec<i32> = frames.iter().skip(channel).step_by(channels).cloned().collect();
+      let offset = state.offset(channel);
+      if block.iter().all(|value| *value == 0) {
+        bits.write_uvar(FN_ZERO, COMMAND_SIZE);
+        state.predict_block(channel, FN_ZERO, &[], offset, |_| Ok(0)).unwrap();
+        state.end_block(channel);
+        continue;
+      }
+      // Each command is tried, keeping the one with the smallest residuals.
+      let lpc_coefs = [48, -16];
+      // LPC predicts the samples after the offset is removed.
+      let lpc_offset = |command: u32| if command == FN_QLPC { offset } else { 0 };
+      let residuals = |command: u32| {
+        let mut trial = state.clone();
+        let mut residuals: Vec<i32> = Vec::with_capacity(size);
+        let mut samples = block.iter();
+        trial.predict_block(channel, command, &lpc_coefs, offset, |prediction| {
+          let sample = *samples.next().unwrap() - lpc_offset(command);
+          residuals.push(sample - prediction);
+          Ok(sample)
+        }).unwrap();
+        residuals
+      };
+      let (command, residuals) =
+        [FN_DIFF0, FN_DIFF1, FN_DIFF2, FN_DIFF3, FN_QLPC].iter()
+        .map(|command| (*command, residuals(*command)))
+        .min_by_key(|&(_, ref residuals)| residuals.iter().map(|r| r.abs() as i64).sum::<i64>())
+        .unwrap();
+      let mean = residuals.iter().map(|r| r.abs() as u64).sum::<u64>() / size as u64;
+      let energy = log2(mean as usize + 1);
+      bits.write_uvar(command, COMMAND_SIZE);
+      bits.write_uvar(energy, ENERGY_SIZE);
+      if command == FN_QLPC {
+        bits.write_uvar(lpc_coefs.len() as u32, LPC_ORDER_SIZE);
+        for coef in lpc_coefs.iter() {
+          bits.write_svar(*coef, LPC_QUANT + 1);
+        }
+      }
+      for residual in residuals.iter() {
+        bits.write_svar(*residual, energy + 1);
+      }
+      let mut samples = block.iter();
+      state.predict_block(channel, command, &lpc_coefs, offset,
+                          |_| Ok(*samples.next().unwrap() - lpc_offset(command))).unwrap();
+      state.end_block(channel);
+    }
+  }
+  write_verbatim(&mut bits, trailer);
+  bits.write_uvar(FN_QUIT, COMMAND_SIZE);
+
+  let mut bytes = AJKG.to_vec();
+  bytes.push(version);
+  bytes.extend(bits.finish());
+  bytes
+}
+
+#[cfg(test)]
+struct BitWriter {
+  data:     Vec<u8>,
+  position: usize
+}
+
+#[cfg(test)]
+impl BitWriter {
+  fn new() -> BitWriter {
+    BitWriter {
+      data:     Vec::new(),
+      position: 0
+    }
+  }
+
+  fn write(&mut self, value: u32, bits: u32) {
+    for i in (0..bits).rev() {
+      if self.position % 8 == 0 {
+        self.data.push(0);
+      }
+      *self.data.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (7 - self.position % 8);
+      self.position += 1;
+    }
+  }
+
+  fn write_uvar(&mut self, value: u32, bits: u32) {
+    for _ in 0 .. (value as u64 >> bits) {
+      self.write(0, 1);
+    }
+    self.write(1, 1);
+    self.write(value & ((1u64 << bits) - 1) as u32, bits);
+  }
+
+  fn write_svar(&mut self, value: i32, bits: u32) {
+    self.write_uvar(if value < 0 { !(value << 1) } else { value << 1 } as u32, bits);
+  }
+
+  fn write_uint(&mut self, value: u32) {
+    let size = 32 - value.leading_zeros();
+    self.write_uvar(size, ULONG_SIZE);
+    self.write_uvar(value, size);
+  }
+
+  fn finish(self) -> Vec<u8> {
+    self.data
+  }
+}
+
+#[cfg(test)]
+mod coding {
+  use super::*;
+
+  #[test]
+  fn rice_round_trip() {
+    let mut writer = BitWriter::new();
+    for i in 0..40 {
+      writer.write_uvar(i * 37, i % 6);
+      writer.write_svar(i as i32 * 11 - 200, 3);
+      writer.write_uint(i * 1000);
+    }
+    let data = writer.finish();
+    let mut reader = BitReader::new(&data);
+    for i in 0..40 {
+      assert_eq!(i * 37, reader.read_uvar(i % 6));
+      assert_eq!(i as i32 * 11 - 200, reader.read_svar(3));
+      assert_eq!(i * 1000, reader.read_uint(2, 0).unwrap());
+    }
+    assert!(!reader.is_past_end());
+  }
+
+  #[test]
+  fn stream_round_trip() {
+    for &(file_type, channels, max) in [(TYPE_S16LH, 2, 32768), (TYPE_S16HL, 1, 32768),
+                                        (TYPE_U8, 3, 128), (TYPE_S8, 1, 128),
+                                        (TYPE_U16LH, 2, 32768)].iter() {
+      let mean = if file_type == TYPE_U8 { 128 } else if file_type == TYPE_U16LH { 32768 } else { 0 };
+      let frames = 1000;
+      let values: Vec<i32> = (0 .. frames * channels).map(|i| {
+        let t = (i / channels) as f64 / (10 + i % channels) as f64;
+        // Silence in the middle is coded by zero blocks.
+        let silent = i / channels >= 300 && i / channels < 600;
+        if silent { 0 } else { (t.sin() * (max - 1) as f64) as i32 }
+      }).collect();
+      let stored: Vec<i32> = values.iter().map(|value| value + mean).collect();
+      let bytes = write_stream(&stored, channels, file_type, b"header", b"trailer");
+      assert!(bytes.len() < values.len() * (if max == 128 { 1 } else { 2 }));
+
+      let stream = ShortenStream::read(&bytes).unwrap();
+      assert_eq!(file_type, stream.file_type);
+      assert_eq!(channels,  stream.channels);
+      assert_eq!(b"header".to_vec(),  stream.header);
+      assert_eq!(b"trailer".to_vec(), stream.trailer);
+      assert_eq!(values, stream.values);
+      assert!(ShortenStream::read(&bytes[.. bytes.len() / 2]).is_err());
+    }
+  }
+
+  #[test]
+  fn version_and_type() {
+    let mut bytes = write_stream(&[1, 2, 3], 1, TYPE_S16LH, &[], &[]);
+    bytes[4] = 4;
+    assert!(ShortenStream::read(&bytes).is_err());
+    // Samples of the mu-law type
+    let bytes = write_stream(&[1, 2, 3], 1, 7, &[], &[]);
+    assert!(ShortenStream::read(&bytes).is_err());
+  }
+}
@@ -0,0 +1,348 @@
+use std::io::{Read, Seek, Write};
+use buffer::*;
+use codecs::Codec;
+use codecs::Codec::*;
+use error::*;
+use metadata::Metadata;
+use sample::*;
+use shorten::stream::{ShortenStream, to_bytes};
+use sphere::{END_HEAD, HEADER_BLOCK_SIZE, NIST_1A, LAYOUT_FIELDS};
+use traits::Container;
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct SphereContainer {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+/// The value of a SPHERE header field.
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+  Integer(i64),
+  Real(f64),
+  Text(String)
+}
+
+impl Field {
+  fn to_string(&self) -> String {
+    match *self {
+      Field::Integer(i)    => i.to_string(),
+      Field::Real(r)       => format!("{:?}", r),
+      Field::Text(ref s)   => s.clone()
+    }
+  }
+}
+
+impl Container for SphereContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<SphereContainer> {
+    // Read and validate the header, whose size is given on the second line.
+    let mut preamble: [u8; 16] = [0u8; 16];
+    try!(reader.read_exact(&mut preamble));
+    if &preamble[0..8] != NIST_1A {
+      return Err(AudioError::Format(
+        "Not valid NIST SPHERE".to_string()
+      ));
+    }
+    let header_size: usize =
+      match String::from_utf8_lossy(&preamble[8..16]).trim().parse() {
+        Ok(size) if size >= 16 => size,
+        _ =>
+          return Err(AudioError::Format(
+            "File is not valid NIST SPHERE (Invalid header size)".to_string()
+          ))
+      };
+    let mut header: Vec<u8> = vec![0u8; header_size - 16];
+    try!(reader.read_exact(&mut header));
+    let fields = try!(read_fields(&header));
+    let field = |key: &str| fields.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v);
+    let integer = |key: &str|
+      match field(key) {
+        Some(&Field::Integer(i)) => Some(i),
+        _                        => None
+      };
+    let text = |key: &str| field(key).map(|value| value.to_string());
+
+    let channels    = integer("channel_count").unwrap_or(1);
+    let sample_rate =
+      match integer("sample_rate") {
+        Some(rate) => rate,
+        None =>
+          match field("sample_rate") {
+            Some(&Field::Real(rate)) => rate as i64,
+            _ =>
+              return Err(AudioError::Format(
+                "File is not valid NIST SPHERE (Missing sample rate)".to_string()
+              ))
+          }
+      };
+    if channels <= 0 || sample_rate <= 0 {
+      return Err(AudioError::Format(
+        "File is not valid NIST SPHERE \
+        (Invalid number of channels or sample rate)".to_string()
+      ));
+    }
+    let codec = try!(determine_codec(text("sample_coding"),
+                                     integer("sample_n_bytes"),
+                                     text("sample_byte_format")));
+
+    // Read the audio data, which may be shorter than the sample count if the
+    // file was truncated.
+    let frame_size = codec.sample_size() * channels as usize;
+    let mut data: Vec<u8> = Vec::new();
+    if text("sample_coding").map_or(false, |coding| is_shorten(&coding)) {
+      let mut bytes: Vec<u8> = Vec::new();
+      try!(reader.read_to_end(&mut bytes));
+      let stream = try!(ShortenStream::read(&bytes));
+      if stream.channels != channels as usize {
+        return Err(AudioError::Format(
+          "File is not valid NIST SPHERE \
+          (Channel count does not match the Shorten stream)".to_string()
+        ));
+      }
+      data = to_bytes(&stream.values, codec);
+      // Samples past the sample count are ignored.
+      if let Some(frames) = integer("sample_count") {
+        if frames >= 0 && data.len() as u64 > frames as u64 * frame_size as u64 {
+          data.truncate(frames as usize * frame_size);
+        }
+      }
+    } else {
+      match integer("sample_count") {
+        Some(frames) if frames >= 0 =>
+          try!(reader.take(frames as u64 * frame_size as u64).read_to_end(&mut data)),
+        _ =>
+          try!(reader.read_to_end(&mut data))
+      };
+    }
+    // Incomplete frames at the end of the data are ignored.
+    let complete_frames_len = data.len() - data.len() % frame_size;
+    data.truncate(complete_frames_len);
+
+    // Fields describing the audio data are not kept as metadata, since they
+    // are written from the audio.
+    let mut metadata = Metadata::default();
+    metadata.info =
+      fields.iter()
+      .filter(|&&(ref key, _)| !LAYOUT_FIELDS.contains(&key.as_str()))
+      .map(|&(ref key, ref value)| (key.clone(), value.to_string()))
+      .collect();
+    Ok(SphereContainer {
+      bit_depth:    codec.bit_depth() as u32,
+      sample_rate:  sample_rate as u32,
+      channels:     channels as u32,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:      try!(::codecs::decode(&data, codec)),
+      metadata:     metadata
+    })
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    let (coding, byte_format) = try!(determine_coding(codec));
+    let data: Vec<u8> = try!(::codecs::encode(audio, codec));
+
+    // Write the fields describing the audio data, followed by the textual
+    // information of the audio.
+    let mut fields: Vec<(String, Field)> = vec![
+      ("channel_count".to_string(),       Field::Integer(audio.channels as i64)),
+      ("sample_count".to_string(),        Field::Integer(audio.samples.len() as i64
+                                                         / audio.channels as i64)),
+      ("sample_rate".to_string(),         Field::Integer(audio.sample_rate as i64)),
+      ("sample_n_bytes".to_string(),      Field::Integer(codec.sample_size() as i64)),
+      ("sample_byte_format".to_string(),  Field::Text(byte_format.to_string())),
+      ("sample_coding".to_string(),       Field::Text(coding.to_string())),
+      ("sample_sig_bits".to_string(),     Field::Integer(codec.sample_size() as i64 * 8))
+    ];
+    for &(ref key, ref value) in audio.metadata.info.iter() {
+      // Keys may not contain whitespace.
+      let key: String = key.split_whitespace().collect::<Vec<&str>>().join("_");
+      if key.is_empty() || LAYOUT_FIELDS.contains(&key.as_str()) {
+        continue;
+      }
+      fields.push((key, parse_value(value)));
+    }
+    let header = write_fields(&fields);
+
+    try!(writer.write_all(&header));
+    try!(writer.write_all(&data));
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Reads the fields of the header following the first two lines. Each field
+/// is a line containing the key, type, and value, separated by a space. The
+/// length of string values is part of the type, so strings may contain any
+/// character.
+fn read_fields(header: &[u8]) -> AudioResult<Vec<(String, Field)>> {
+  let mut fields: Vec<(String, Field)> = Vec::new();
+  let mut rest: &[u8] = header;
+  loop {
+    // Skip blank lines and read the key.
+    while rest.first().map_or(false, |b| (*b as char).is_whitespace()) {
+      rest = &rest[1..];
+    }
+    let key_end = match rest.iter().position(|b| (*b as char).is_whitespace()) {
+      Some(i) => i,
+      None    => break
+    };
+    let key = String::from_utf8_lossy(&rest[..key_end]).into_owned();
+    if key.as_bytes() == END_HEAD {
+      return Ok(fields);
+    }
+    rest = &rest[key_end + 1 ..];
+    let type_end = match rest.iter().position(|b| *b == b' ') {
+      Some(i) => i,
+      None    => break
+    };
+    let field_type = String::from_utf8_lossy(&rest[..type_end]).into_owned();
+    rest = &rest[type_end + 1 ..];
+    let value_end =
+      if field_type.starts_with("-s") {
+        match field_type[2..].parse::<usize>() {
+          Ok(len) if len <= rest.len() => len,
+          _ => break
+        }
+      }
+      else {
+        rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len())
+      };
+    let value = String::from_utf8_lossy(&rest[..value_end]).into_owned();
+    rest = &rest[value_end ..];
+    let field =
+      match field_type.as_str() {
+        "-i" => value.trim().parse().ok().map(Field::Integer),
+        "-r" => value.trim().parse().ok().map(Field::Real),
+        _ if field_type.starts_with("-s") => Some(Field::Text(value)),
+        _    => None
+      };
+    match field {
+      Some(field) => fields.push((key, field)),
+      None => break
+    }
+  }
+  Err(AudioError::Format(
+    "File is not valid NIST SPHERE (Malformed header)".to_string()
+  ))
+}
+
+/// Writes the header, which is padded with spaces to a multiple of 1024
+/// bytes.
+fn write_fields(fields: &[(String, Field)]) -> Vec<u8> {
+  let mut lines = String::new();
+  for &(ref key, ref value) in fields.iter() {
+    match *value {
+      Field::Integer(i)  => lines.push_str(&format!("{} -i {}\n", key, i)),
+      Field::Real(r)     => lines.push_str(&format!("{} -r {:?}\n", key, r)),
+      Field::Text(ref s) => lines.push_str(&format!("{} -s{} {}\n", key, s.len(), s))
+    }
+  }
+  lines.push_str("end_head\n");
+  // The preamble is 16 bytes long.
+  let size = (16 + lines.len() + HEADER_BLOCK_SIZE - 1)
+             / HEADER_BLOCK_SIZE * HEADER_BLOCK_SIZE;
+  let mut header: Vec<u8> = Vec::with_capacity(size);
+  header.extend_from_slice(NIST_1A);
+  header.extend_from_slice(format!("{:>7}\n", size).as_bytes());
+  header.extend_from_slice(lines.as_bytes());
+  header.resize(size, b' ');
+  header
+}
+
+/// Returns the field for a textual value, using the narrowest type that
+/// represents it.
+fn parse_value(value: &str) -> Field {
+  if let Ok(i) = value.parse() {
+    Field::Integer(i)
+  }
+  else if let Ok(r) = value.parse::<f64>() {
+    if r.is_finite() && value.contains('.') {
+      Field::Real(r)
+    } else {
+      Field::Text(value.to_string())
+    }
+  }
+  else {
+    Field::Text(value.to_string())
+  }
+}
+
+/// Returns whether the sample coding describes samples compressed with
+/// Shorten.
+fn is_shorten(coding: &str) -> bool {
+  coding.split(',').skip(1).any(|c| c.contains("shorten"))
+}
+
+/// Returns the `Codec` described by the sample coding, number of bytes per
+/// sample, and byte format fields. Without a coding field, samples are PCM.
+fn determine_codec(coding:      Option<String>,
+                   n_bytes:     Option<i64>,
+                   byte_format: Option<String>) -> AudioResult<Codec> {
+  // Compressed samples have the compression appended to the coding.
+  let coding = coding.unwrap_or("pcm".to_string());
+  let coding =
+    match coding.find(',') {
+      Some(i) if is_shorten(&coding) => coding[..i].to_string(),
+      Some(_) =>
+        return Err(AudioError::Unsupported(
+          format!("NIST SPHERE {} audio is not supported", coding)
+        )),
+      None    => coding
+    };
+  // The byte format gives the order of the bytes of each sample, from the
+  // least significant byte.
+  let little_endian =
+    match byte_format.as_ref().map(|f| f.as_str()) {
+      Some("01") | Some("012") | Some("0123") | Some("1") | None => true,
+      Some("10") | Some("210") | Some("3210")                    => false,
+      Some(f) =>
+        return Err(AudioError::Unsupported(
+          format!("NIST SPHERE byte format {} is not supported", f)
+        ))
+    };
+  match (coding.as_str(), n_bytes.unwrap_or(2), little_endian) {
+    ("pcm", 1, _)              => Ok(LPCM_I8),
+    ("pcm", 2, true)           => Ok(LPCM_I16_LE),
+    ("pcm", 2, false)          => Ok(LPCM_I16_BE),
+    ("pcm", 3, true)           => Ok(LPCM_I24_LE),
+    ("pcm", 3, false)          => Ok(LPCM_I24_BE),
+    ("pcm", 4, true)           => Ok(LPCM_I32_LE),
+    ("pcm", 4, false)          => Ok(LPCM_I32_BE),
+    ("ulaw", _, _)             |
+    ("mu-law", _, _)           => Ok(G711_ULAW),
+    ("alaw", _, _)             => Ok(G711_ALAW),
+    (c, n, _) =>
+      Err(AudioError::Unsupported(
+        format!("NIST SPHERE {}-byte {} audio is not supported", n, c)
+      ))
+  }
+}
+
+/// Returns the sample coding and byte format fields for a `Codec`. If the
+/// container does not support a codec, an error is returned.
+fn determine_coding(codec: Codec) -> AudioResult<(&'static str, &'static str)> {
+  match codec {
+    LPCM_I8     => Ok(("pcm", "1")),
+    LPCM_I16_LE => Ok(("pcm", "01")),
+    LPCM_I24_LE => Ok(("pcm", "012")),
+    LPCM_I32_LE => Ok(("pcm", "0123")),
+    LPCM_I16_BE => Ok(("pcm", "10")),
+    LPCM_I24_BE => Ok(("pcm", "210")),
+    LPCM_I32_BE => Ok(("pcm", "3210")),
+    G711_ULAW   => Ok(("ulaw", "1")),
+    G711_ALAW   => Ok(("alaw", "1")),
+    c @ _ =>
+      Err(AudioError::Unsupported(
+        format!("NIST SPHERE does not support the {:?} codec", c)
+      ))
+  }
+}
@@ -0,0 +1,157 @@
+//! The NIST SPHERE Format
+//!
+//! SPHERE files begin with an ASCII header of key and value fields, padded to
+//! a multiple of 1024 bytes, followed by the audio data. The header describes
+//! the audio data along with information about the recording, such as the
+//! speaker or the corpus the recording belongs to. Samples are stored in the
+//! byte order given by the `sample_byte_format` field.
+//!
+//! Audio compressed with Shorten, whose sample coding ends with
+//! `embedded-shorten`, is decoded but cannot be encoded.
+//!
+//! References
+//! - [NIST SPHERE](https://www.nist.gov/itl/iad/mig/tools)
+//! - [McGill University](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/SPHERE/SPHERE.html)
+
+mod container;
+pub mod decoder;
+pub mod encoder;
+
+pub use sphere::decoder::Decoder as Decoder;
+pub use sphere::encoder::Encoder as Encoder;
+
+/// First line of the header.
+const NIST_1A: &'static [u8; 8] = b"NIST_1A\n";
+
+/// Key ending the header fields.
+const END_HEAD: &'static [u8; 8] = b"end_head";
+
+/// Headers are padded to a multiple of this size.
+const HEADER_BLOCK_SIZE: usize = 1024;
+
+/// Header fields describing the audio data, which are written from the audio
+/// rather than kept as metadata.
+const LAYOUT_FIELDS: [&'static str; 8] = [
+  "channel_count",
+  "sample_count",
+  "sample_rate",
+  "sample_n_bytes",
+  "sample_byte_format",
+  "sample_coding",
+  "sample_sig_bits",
+  "sample_checksum"
+];
+
+#[cfg(test)]
+mod io {
+  use std::io::Cursor;
+  use std::path::Path;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+  use ::shorten::stream::{TYPE_S16HL, write_stream};
+
+  const HEADER: &'static str = "NIST_1A\n   1024\n\
+database_id -s5 TIMIT\n\
+utterance_id -s7 dr1_sa1\n\
+speaker_id -s4 fcjf\n\
+channel_count -i 1\n\
+sample_count -i 3\n\
+sample_rate -i 16000\n\
+sample_min -i -2\n\
+sample_n_bytes -i 2\n\
+sample_byte_format -s2 10\n\
+sample_sig_bits -i 16\n\
+sample_coding -s3 pcm\n\
+end_head\n";
+
+  fn sphere_file(header: &str, data: &[u8]) -> Vec<u8> {
+    let mut bytes = header.as_bytes().to_vec();
+    bytes.resize(1024, b' ');
+    bytes.extend_from_slice(data);
+    bytes
+  }
+
+  #[test]
+  fn read_header() {
+    let bytes = sphere_file(HEADER, &[0x00, 0x01, 0xFF, 0xFE, 0x40, 0x00, 0x12]);
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::Sphere).unwrap();
+    assert_eq!(16000, audio.sample_rate);
+    assert_eq!(1, audio.channels);
+    assert_eq!(3, audio.samples.len());
+    assert_eq!(0.5f32, audio.samples[2]);
+    assert_eq!(vec![
+      ("database_id".to_string(),  "TIMIT".to_string()),
+      ("utterance_id".to_string(), "dr1_sa1".to_string()),
+      ("speaker_id".to_string(),   "fcjf".to_string()),
+      ("sample_min".to_string(),   "-2".to_string())
+    ], audio.metadata.info);
+  }
+
+  #[test]
+  fn byte_formats() {
+    // Four byte samples give the order of all four bytes.
+    let data = [0x00, 0x00, 0x00, 0x40, 0x40, 0x00, 0x00, 0x00];
+    for &(format, codec) in [("-s4 0123", LPCM_I32_LE), ("-s4 3210", LPCM_I32_BE)].iter() {
+      let header = HEADER.replace("sample_byte_format -s2 10", &format!("sample_byte_format {}", format))
+                         .replace("sample_n_bytes -i 2", "sample_n_bytes -i 4")
+                         .replace("sample_count -i 3", "sample_count -i 2");
+      let audio = audio::load(&mut Cursor::new(sphere_file(&header, &data)), AudioFormat::Sphere).unwrap();
+      assert_eq!(AudioBuffer::from_bytes(16000, 1, &data, codec).unwrap().samples, audio.samples);
+    }
+    let audio = AudioBuffer::from_samples(16000, 1, vec![0.5f32]);
+    for &(codec, format) in [(LPCM_I24_LE, "-s3 012"), (LPCM_I24_BE, "-s3 210"),
+                             (LPCM_I32_LE, "-s4 0123"), (LPCM_I32_BE, "-s4 3210")].iter() {
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write_as(&mut bytes, &audio, AudioFormat::Sphere, codec).unwrap();
+      let header = String::from_utf8_lossy(&bytes[..1024]).into_owned();
+      assert!(header.contains(&format!("sample_byte_format {}\n", format)));
+    }
+  }
+
+  #[test]
+  fn shorten() {
+    let values: Vec<i32> = (0..5000).map(|i| ((i as f64 / 7f64).sin() * 20000f64) as i32).collect();
+    let stream = write_stream(&values, 1, TYPE_S16HL, &[], &[]);
+    let header = HEADER.replace("sample_coding -s3 pcm", "sample_coding -s26 pcm,embedded-shorten-v2.00")
+                       .replace("sample_count -i 3", "sample_count -i 4000");
+    let bytes = sphere_file(&header, &stream);
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::Sphere).unwrap();
+    assert_eq!(16000, audio.sample_rate);
+    let lpcm: Vec<u8> = values[..4000].iter().flat_map(|v| vec![*v as u8, (*v >> 8) as u8]).collect();
+    assert_eq!(lpcm, ::codecs::encode(&audio, LPCM_I16_LE).unwrap());
+
+    // Other compressions are not supported.
+    let header = HEADER.replace("sample_coding -s3 pcm", "sample_coding -s19 pcm,embedded-other");
+    let bytes = sphere_file(&header, &stream);
+    assert!(audio::load(&mut Cursor::new(bytes), AudioFormat::Sphere).is_err());
+  }
+
+  #[test]
+  fn codecs_eq() {
+    let mut audio = audio::open(Path::new("tests/wav/M1F1-int16-AFsp.wav")).unwrap();
+    audio.metadata.info = vec![
+      ("speaker id".to_string(), "fcjf".to_string()),
+      ("prompt".to_string(),     "She had your dark suit".to_string())
+    ];
+    for codec in [LPCM_I8, LPCM_I16_LE, LPCM_I16_BE, LPCM_I24_LE, LPCM_I24_BE,
+                  LPCM_I32_LE, LPCM_I32_BE, G711_ULAW, G711_ALAW].iter() {
+      let write_path = Path::new("tests/results/tmp_codec.sph");
+      assert!(audio::save_as(&write_path, &audio, *codec).is_ok());
+      let expected = {
+        let mut bytes: Vec<u8> = Vec::new();
+        audio::write_as(&mut bytes, &audio, AudioFormat::Sphere, *codec).unwrap();
+        bytes.split_off(1024)
+      };
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(audio.channels,    verify.channels);
+      assert_eq!(audio.sample_rate, verify.sample_rate);
+      assert_eq!(AudioBuffer::from_bytes(audio.sample_rate, audio.channels,
+                                         &expected, *codec).unwrap().samples,
+                 verify.samples);
+      assert_eq!("speaker_id", verify.metadata.info[0].0);
+      assert_eq!(audio.metadata.info[1], verify.metadata.info[1]);
+    }
+  }
+}
@@ -0,0 +1,304 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use aiff::container::{read_chunk_header, read_form, write_chunk};
+use audio::AudioFormat;
+use buffer::*;
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use codecs::Codec;
+use codecs::Codec::*;
+use codecs::fibonacci;
+use error::*;
+use metadata::{Instrument, Loop, LoopMode, Metadata, UnknownChunk};
+use sample::*;
+use svx::*;
+use traits::Container;
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct SvxContainer {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for SvxContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<SvxContainer> {
+    let (form_type, mut buffer) = try!(read_form(reader));
+    if &form_type != SVX8 {
+      return Err(AudioError::Format(
+        "Not valid 8SVX".to_string()
+      ));
+    }
+    let file_size = buffer.get_ref().len();
+
+    let mut metadata    : Metadata            = Metadata::default();
+    let mut voice_header: Option<VoiceHeader> = None;
+    let mut body        : Option<Vec<u8>>     = None;
+    let mut channels    : u32                 = 1;
+    while buffer.position() < file_size as u64 {
+      let (chunk_id, data_size, chunk_size) = try!(read_chunk_header(&mut buffer));
+      let pos: usize  = buffer.position() as usize;
+      let chunk_end   = (pos + data_size).min(file_size);
+      let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+      match &chunk_id {
+        VHDR => {
+          voice_header = Some(try!(VoiceHeader::read(chunk_bytes)));
+        },
+        CHAN => {
+          if chunk_bytes.len() >= 4 && BigEndian::read_u32(chunk_bytes) == CHAN_STEREO {
+            channels = 2;
+          }
+        },
+        BODY => {
+          body = Some(chunk_bytes.to_vec());
+        },
+        NAME | AUTH | COPY => {
+          metadata.info.push((text_key(&chunk_id), read_text(chunk_bytes)));
+        },
+        ANNO => {
+          metadata.annotation = Some(read_text(chunk_bytes));
+        },
+        _ => {
+          metadata.chunks.push(UnknownChunk {
+            format:     AudioFormat::SVX,
+            id:         chunk_id,
+            data:       chunk_bytes.to_vec(),
+            after_data: body.is_some()
+          });
+        }
+      }
+      try!(buffer.seek(SeekFrom::Current(chunk_size as i64)));
+    }
+
+    let voice_header =
+      match voice_header {
+        Some(vhdr) => vhdr,
+        None => return Err(AudioError::Format(
+          "File is not valid 8SVX \
+          (Missing required VoiceHeader chunk)".to_string()
+        ))
+      };
+    let body =
+      match body {
+        Some(body) => body,
+        None => return Err(AudioError::Format(
+          "File is not valid 8SVX \
+          (Missing required Body chunk)".to_string()
+        ))
+      };
+    let codec =
+      match voice_header.compression {
+        COMPRESSION_NONE      => LPCM_I8,
+        COMPRESSION_FIBONACCI => FIBONACCI_DELTA,
+        c @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("8SVX compression type {} is not supported", c)
+          ))
+      };
+
+    // The body is divided equally between the channels. Only the first
+    // octave is read when there are several, whose length is given by the
+    // voice header.
+    let channel_size = body.len() / channels as usize;
+    let mut channel_samples: Vec<Vec<i8>> = Vec::with_capacity(channels as usize);
+    for bytes in body.chunks(channel_size.max(1)).take(channels as usize) {
+      channel_samples.push(
+        match codec {
+          FIBONACCI_DELTA => fibonacci::read(bytes),
+          _               => bytes.iter().map(|&b| b as i8).collect()
+        }
+      );
+    }
+    let length  = voice_header.one_shot_samples as usize
+                + voice_header.repeat_samples as usize;
+    let mut num_frames = channel_samples.iter().map(|c| c.len()).min().unwrap_or(0);
+    if length > 0 {
+      num_frames = num_frames.min(length);
+    }
+    let mut data: Vec<u8> = Vec::with_capacity(num_frames * channels as usize);
+    for i in 0..num_frames {
+      for samples in channel_samples.iter() {
+        data.push(samples[i] as u8);
+      }
+    }
+
+    // The repeated part of the audio follows the part played once.
+    if voice_header.repeat_samples > 0 {
+      metadata.instrument = Some(Instrument {
+        sustain_loop: Some(Loop {
+          mode:       LoopMode::Forward,
+          start:      voice_header.one_shot_samples,
+          end:        voice_header.one_shot_samples
+                      + voice_header.repeat_samples - 1,
+          play_count: 0
+        }),
+        ..Instrument::default()
+      });
+    }
+
+    Ok(SvxContainer {
+      bit_depth:      8,
+      sample_rate:    voice_header.sample_rate as u32,
+      channels:       channels,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:        try!(::codecs::decode(&data, LPCM_I8)),
+      metadata:       metadata
+    })
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    let compression =
+      match codec {
+        LPCM_I8         => COMPRESSION_NONE,
+        FIBONACCI_DELTA => COMPRESSION_FIBONACCI,
+        c @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("8SVX does not support the {:?} codec", c)
+          ))
+      };
+    if audio.channels == 0 || audio.channels > 2 {
+      return Err(AudioError::Unsupported(
+        format!("8SVX does not support audio with {} channels", audio.channels)
+      ));
+    }
+    if audio.sample_rate > u16::max_value() as u32 {
+      return Err(AudioError::Unsupported(
+        format!("8SVX does not support a sample rate of {} Hz", audio.sample_rate)
+      ));
+    }
+    let channels   = audio.channels as usize;
+    let num_frames = audio.samples.len() / channels;
+
+    // Each channel is stored, and compressed, separately.
+    let data = try!(::codecs::encode(audio, LPCM_I8));
+    let mut body: Vec<u8> = Vec::with_capacity(data.len() + 2 * channels);
+    for c in 0..channels {
+      let samples: Vec<i8> = data.chunks(channels).map(|frame| frame[c] as i8).collect();
+      match codec {
+        FIBONACCI_DELTA => body.extend(fibonacci::create(&samples)),
+        _               => body.extend(samples.iter().map(|&s| s as u8))
+      }
+    }
+
+    // Only a sustain loop ending with the audio can be repeated.
+    let loop_start =
+      audio.metadata.instrument.as_ref()
+      .and_then(|inst| inst.sustain_loop)
+      .and_then(|l|
+        if l.end as usize + 1 == num_frames && l.start <= l.end {
+          Some(l.start)
+        } else {
+          None
+        }
+      );
+    let voice_header = VoiceHeader {
+      one_shot_samples:   loop_start.unwrap_or(num_frames as u32),
+      repeat_samples:     loop_start.map(|start| num_frames as u32 - start).unwrap_or(0),
+      samples_per_cycle:  0,
+      sample_rate:        audio.sample_rate as u16,
+      octaves:            1,
+      compression:        compression,
+      volume:             FULL_VOLUME
+    };
+
+    // Chunks are written after the form type once their total size is known.
+    let mut chunks: Vec<u8> = Vec::new();
+    try!(write_chunk(&mut chunks, VHDR, &voice_header.to_bytes()));
+    for &(ref key, ref value) in audio.metadata.info.iter() {
+      for id in [NAME, AUTH, COPY].iter() {
+        if *key == text_key(id) {
+          try!(write_chunk(&mut chunks, id, value.as_bytes()));
+        }
+      }
+    }
+    if let Some(ref annotation) = audio.metadata.annotation {
+      try!(write_chunk(&mut chunks, ANNO, annotation.as_bytes()));
+    }
+    if channels == 2 {
+      let mut chan = [0u8; 4];
+      BigEndian::write_u32(&mut chan, CHAN_STEREO);
+      try!(write_chunk(&mut chunks, CHAN, &chan));
+    }
+    let unknown_chunks =
+      audio.metadata.chunks.iter().filter(|chunk| chunk.format == AudioFormat::SVX);
+    for chunk in unknown_chunks.clone().filter(|chunk| !chunk.after_data) {
+      try!(write_chunk(&mut chunks, &chunk.id, &chunk.data));
+    }
+    try!(write_chunk(&mut chunks, BODY, &body));
+    for chunk in unknown_chunks.filter(|chunk| chunk.after_data) {
+      try!(write_chunk(&mut chunks, &chunk.id, &chunk.data));
+    }
+
+    try!(writer.write(b"FORM"));
+    try!(writer.write_u32::<BigEndian>(4 + chunks.len() as u32));
+    try!(writer.write(SVX8));
+    try!(writer.write_all(&chunks));
+    Ok(())
+  }
+}
+
+/// The voice header, which describes how the samples are stored and played.
+struct VoiceHeader {
+  /// Number of samples in the part played once, in the highest octave
+  one_shot_samples:   u32,
+  /// Number of samples in the repeated part, in the highest octave
+  repeat_samples:     u32,
+  /// Number of samples in a cycle of the repeated part, or 0 if unknown
+  samples_per_cycle:  u32,
+  sample_rate:        u16,
+  /// Number of octaves of the sample stored in the body
+  octaves:            u8,
+  compression:        u8,
+  /// Playback volume as a 16.16 fixed-point number
+  volume:             u32
+}
+
+impl VoiceHeader {
+  fn read(bytes: &[u8]) -> AudioResult<VoiceHeader> {
+    if bytes.len() < VHDR_SIZE {
+      return Err(AudioError::Format(
+        "File is not valid 8SVX (VoiceHeader chunk is too small)".to_string()
+      ));
+    }
+    Ok(VoiceHeader {
+      one_shot_samples:   BigEndian::read_u32(&bytes[0..4]),
+      repeat_samples:     BigEndian::read_u32(&bytes[4..8]),
+      samples_per_cycle:  BigEndian::read_u32(&bytes[8..12]),
+      sample_rate:        BigEndian::read_u16(&bytes[12..14]),
+      octaves:            bytes[14],
+      compression:        bytes[15],
+      volume:             BigEndian::read_u32(&bytes[16..20])
+    })
+  }
+  fn to_bytes(&self) -> [u8; VHDR_SIZE] {
+    let mut bytes = [0u8; VHDR_SIZE];
+    BigEndian::write_u32(&mut bytes[0..4],   self.one_shot_samples);
+    BigEndian::write_u32(&mut bytes[4..8],   self.repeat_samples);
+    BigEndian::write_u32(&mut bytes[8..12],  self.samples_per_cycle);
+    BigEndian::write_u16(&mut bytes[12..14], self.sample_rate);
+    bytes[14] = self.octaves;
+    bytes[15] = self.compression;
+    BigEndian::write_u32(&mut bytes[16..20], self.volume);
+    bytes
+  }
+}
+
+// Private functions
+
+/// Returns the key of the metadata information read from a text chunk.
+#[inline]
+fn text_key(id: &[u8; 4]) -> String {
+  String::from_utf8_lossy(id).trim_right().to_string()
+}
+
+/// Reads the text of a chunk, removing any trailing null bytes.
+#[inline]
+fn read_text(bytes: &[u8]) -> String {
+  String::from_utf8_lossy(bytes).trim_right_matches('\u{0}').to_string()
+}
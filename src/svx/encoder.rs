@@ -0,0 +1,38 @@
+use std::io::Write;
+use buffer::AudioBuffer;
+use codecs::Codec;
+use codecs::Codec::LPCM_I8;
+use error::AudioResult;
+use traits::{AudioEncoder, Container};
+use svx::container::SvxContainer;
+
+/// Encodes audio to 8SVX format to the provided writer.
+pub struct Encoder<'w, W: 'w> {
+  writer: &'w mut W,
+}
+
+impl<'w, W> Encoder<'w, W> where W: Write {
+  /// Create a new 8SVX format `Encoder` using the provided writer.
+  #[inline]
+  pub fn new(writer: &'w mut W) -> Encoder<'w, W> {
+    Encoder {
+      writer: writer
+    }
+  }
+}
+ 
+impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
+  /// Creates and writes a `SvxContainer` to the included writer. The audio
+  /// is encoded to uncompressed 8-bit LPCM audio.
+  #[inline]
+  fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
+    SvxContainer::create(&mut self.writer, audio, LPCM_I8)
+  }
+  /// Creates and writes a `SvxContainer` using the provided `SampleFormat`
+  /// to the included writer. This is how audio can be encoded to different
+  /// bit rates supported by the format.
+  #[inline]
+  fn encode_as(&mut self, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    SvxContainer::create(&mut self.writer, audio, codec)
+  }
+}
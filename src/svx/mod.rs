@@ -0,0 +1,155 @@
+//! The Amiga 8-Bit Sampled Voice Format
+//!
+//! 8SVX files use the Interchange File Format (IFF), like AIFF, and store
+//! signed 8-bit samples either uncompressed or using Fibonacci-delta
+//! compression. Audio is described by the voice header, which divides the
+//! samples into a part played once followed by a part that is repeated. The
+//! repeated part is read as the sustain loop of an instrument. Stereo audio
+//! stores all samples of the left channel followed by those of the right.
+//! All integers are stored in big-endian format.
+//!
+//! Files holding several octaves of a sample are read from the first, highest
+//! octave only.
+//!
+//! References
+//! - [8SVX IFF 8-Bit Sampled Voice](http://amigadev.elowar.com/read/ADCD_2.1/Devices_Manual_guide/node02D8.html)
+//! - [AmigaOS Documentation Wiki](https://wiki.amigaos.net/wiki/8SVX_IFF_8-Bit_Sampled_Voice)
+
+pub mod container;
+pub mod decoder;
+pub mod encoder;
+
+pub use svx::decoder::Decoder as Decoder;
+pub use svx::encoder::Encoder as Encoder;
+
+/// 8SVX chunk identifiers.
+const SVX8: &'static [u8; 4] = b"8SVX";
+const VHDR: &'static [u8; 4] = b"VHDR";
+const CHAN: &'static [u8; 4] = b"CHAN";
+const BODY: &'static [u8; 4] = b"BODY";
+const NAME: &'static [u8; 4] = b"NAME";
+const AUTH: &'static [u8; 4] = b"AUTH";
+const COPY: &'static [u8; 4] = b"(c) ";
+const ANNO: &'static [u8; 4] = b"ANNO";
+
+/// Size of the voice header chunk.
+const VHDR_SIZE: usize = 20;
+
+/// Compression types of the voice header.
+const COMPRESSION_NONE:      u8 = 0;
+const COMPRESSION_FIBONACCI: u8 = 1;
+
+/// Channel assignment of the CHAN chunk for stereo audio.
+const CHAN_STEREO: u32 = 6;
+
+/// Full volume, as a 16.16 fixed-point number.
+const FULL_VOLUME: u32 = 0x10000;
+
+#[cfg(test)]
+mod io {
+  use std::f64::consts::PI;
+  use std::io::Cursor;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+  use ::metadata::{Instrument, Loop, LoopMode};
+  use ::testing::{Sine, signal};
+
+  /// Returns a tone on each channel, quantized to 8 bits.
+  fn tone(channels: u32, frames: usize) -> AudioBuffer {
+    let waves = [Sine::new(13f64, 0.9), Sine { phase: PI, ..Sine::new(13f64, 0.45) }];
+    let mut audio = signal(8363, frames, &waves[.. channels as usize]);
+    for sample in audio.samples.iter_mut() {
+      *sample = (*sample * 128f32).round() / 128f32;
+    }
+    audio
+  }
+
+  #[test]
+  fn i8_eq() {
+    for channels in 1..3 {
+      let audio = tone(channels, 1001);
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write(&mut bytes, &audio, AudioFormat::SVX).unwrap();
+      assert_eq!(b"FORM", &bytes[0..4]);
+      assert_eq!(b"8SVX", &bytes[8..12]);
+      let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::SVX).unwrap();
+      assert_eq!(channels, verify.channels);
+      assert_eq!(8363,     verify.sample_rate);
+      assert_eq!(audio.samples, verify.samples);
+    }
+  }
+
+  #[test]
+  fn fibonacci_delta() {
+    for channels in 1..3 {
+      let audio = tone(channels, 1001);
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write_as(&mut bytes, &audio, AudioFormat::SVX, FIBONACCI_DELTA).unwrap();
+      let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::SVX).unwrap();
+      assert_eq!(channels, verify.channels);
+      assert_eq!(audio.samples.len(), verify.samples.len());
+      for (sample, verify) in audio.samples.iter().zip(verify.samples.iter()) {
+        assert!((sample - verify).abs() <= 2f32 / 128f32);
+      }
+    }
+  }
+
+  #[test]
+  fn layout() {
+    let mut audio = AudioBuffer::from_samples(16000, 2, vec![0.5f32, -0.5f32, 0.25f32, 0f32]);
+    audio.metadata.info = vec![("NAME".to_string(), "Bass".to_string())];
+    audio.metadata.annotation = Some("Sampled from tape".to_string());
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write(&mut bytes, &audio, AudioFormat::SVX).unwrap();
+    let body = (0 .. bytes.len()).find(|&i| &bytes[i .. i + 4] == b"BODY").unwrap();
+    // The samples of the left channel are followed by those of the right.
+    assert_eq!(&[4, 64, 32, 192, 0], &bytes[body + 7 .. body + 12]);
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::SVX).unwrap();
+    assert_eq!(audio.samples,             verify.samples);
+    assert_eq!(audio.metadata.info,       verify.metadata.info);
+    assert_eq!(audio.metadata.annotation, verify.metadata.annotation);
+  }
+
+  #[test]
+  fn repeat_loop() {
+    let mut audio = tone(1, 300);
+    audio.metadata.instrument = Some(Instrument {
+      sustain_loop: Some(Loop {
+        mode:       LoopMode::Forward,
+        start:      100,
+        end:        299,
+        play_count: 0
+      }),
+      ..Instrument::default()
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write(&mut bytes, &audio, AudioFormat::SVX).unwrap();
+    // The voice header gives the number of samples played once and repeated.
+    assert_eq!(&[0, 0, 0, 100, 0, 0, 0, 200], &bytes[20..28]);
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::SVX).unwrap();
+    assert_eq!(audio.metadata.instrument, verify.metadata.instrument);
+
+    // Loops that do not end with the audio cannot be stored.
+    audio.metadata.instrument.as_mut().unwrap().sustain_loop.as_mut().unwrap().end = 200;
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write(&mut bytes, &audio, AudioFormat::SVX).unwrap();
+    assert_eq!(&[0, 0, 1, 44, 0, 0, 0, 0], &bytes[20..28]);
+  }
+
+  #[test]
+  fn errors() {
+    assert!(audio::load(&mut Cursor::new(b"FORM\x00\x00\x00\x04AIFF".to_vec()), AudioFormat::SVX).is_err());
+    let missing_body = b"FORM\x00\x00\x00\x20\x38SVXVHDR\x00\x00\x00\x14\
+                         \x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\
+                         \x1F\x40\x01\x00\x00\x01\x00\x00".to_vec();
+    assert!(audio::load(&mut Cursor::new(missing_body), AudioFormat::SVX).is_err());
+    let audio = AudioBuffer::from_samples(96000, 1, vec![0f32]);
+    assert!(audio::write(&mut Vec::new(), &audio, AudioFormat::SVX).is_err());
+    let audio = AudioBuffer::from_samples(44100, 3, vec![0f32; 3]);
+    assert!(audio::write(&mut Vec::new(), &audio, AudioFormat::SVX).is_err());
+    let audio = AudioBuffer::from_samples(44100, 1, vec![0f32]);
+    assert!(audio::write_as(&mut Vec::new(), &audio, AudioFormat::SVX, LPCM_I16_BE).is_err());
+  }
+}
@@ -0,0 +1,78 @@
+//! Test Signals
+//!
+//! Sine waves shared by the tests of the formats, codecs and processors,
+//! both to generate audio and to measure how closely decoded audio follows
+//! the waves it was encoded from.
+
+use std::f64::consts::PI;
+use buffer::AudioBuffer;
+
+/// Wave on the first channel of the sine test files.
+pub const SINE_LEFT: Sine = Sine { frequency: 440f64, amplitude: 0.5, phase: 0f64 };
+
+/// Wave on the second channel of the stereo sine test files.
+pub const SINE_RIGHT: Sine = Sine { frequency: 660f64, amplitude: 0.25, phase: 1f64 };
+
+/// A sine wave.
+#[derive(Clone, Copy, Debug)]
+pub struct Sine {
+  pub frequency: f64,
+  pub amplitude: f64,
+  /// Phase in radians at the first frame
+  pub phase:     f64
+}
+
+impl Sine {
+  /// Creates a `Sine` starting at a phase of zero.
+  pub fn new(frequency: f64, amplitude: f64) -> Sine {
+    Sine {
+      frequency: frequency,
+      amplitude: amplitude,
+      phase:     0f64
+    }
+  }
+
+  /// Returns a different wave for each channel, so channels that are mixed
+  /// up or swapped are noticed.
+  pub fn for_channel(channel: usize) -> Sine {
+    Sine::new(300f64 + 100f64 * channel as f64, 0.5)
+  }
+
+  /// Returns the value of the wave at a frame.
+  pub fn at(&self, frame: usize, sample_rate: u32) -> f64 {
+    let t = frame as f64 / sample_rate as f64;
+    self.amplitude * (2f64 * PI * self.frequency * t + self.phase).sin()
+  }
+}
+
+/// Returns audio with one wave on each channel.
+pub fn signal(sample_rate: u32, frames: usize, waves: &[Sine]) -> AudioBuffer {
+  let mut samples = Vec::with_capacity(frames * waves.len());
+  for i in 0..frames {
+    for wave in waves.iter() {
+      samples.push(wave.at(i, sample_rate) as f32);
+    }
+  }
+  AudioBuffer::from_samples(sample_rate, waves.len() as u32, samples)
+}
+
+/// Returns audio with the wave of `Sine::for_channel` on each channel.
+pub fn sines(sample_rate: u32, channels: u32, frames: usize) -> AudioBuffer {
+  let waves: Vec<Sine> = (0 .. channels as usize).map(Sine::for_channel).collect();
+  signal(sample_rate, frames, &waves)
+}
+
+/// Returns the root mean square difference between a channel of the audio
+/// and a wave, where the audio starts at frame `start` of the wave. The
+/// `edge` frames at either end of the audio are skipped.
+pub fn sine_error(audio: &AudioBuffer, channel: usize, wave: Sine,
+                  start: usize, edge: usize) -> f64 {
+  let channels = audio.channels as usize;
+  let frames   = audio.samples.len() / channels;
+  let sum: f64 = (edge .. frames - edge).map(|i| {
+    let error = audio.samples[i * channels + channel] as f64
+              - wave.at(start + i, audio.sample_rate);
+    error * error
+  }).sum();
+  (sum / (frames - 2 * edge) as f64).sqrt()
+}
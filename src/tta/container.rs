@@ -0,0 +1,162 @@
+use std::io::{Read, Seek, Write};
+use buffer::*;
+use byteorder::{ByteOrder, LittleEndian};
+use codecs::Codec;
+use codecs::Codec::*;
+use error::*;
+use id3;
+use id3::Id3Tag;
+use metadata::Metadata;
+use sample::*;
+use traits::Container;
+use tta::*;
+use tta::frame::read_frame;
+
+/// Struct containing all necessary information for decoding a TTA stream
+/// to an `AudioBuffer`.
+pub struct TtaContainer {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for TtaContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<TtaContainer> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(reader.read_to_end(&mut bytes));
+
+    // Skips the ID3v2 tags at the start of the file, keeping the first that
+    // can be read.
+    let mut metadata = Metadata::default();
+    let mut start = 0;
+    while let Some(len) = id3::tag_len(&bytes[start..]) {
+      if metadata.id3.is_none() {
+        if let Ok(tag) = Id3Tag::read(&bytes[start .. start + len]) {
+          metadata.id3 = Some(tag);
+        }
+      }
+      start += len;
+    }
+    let data = &bytes[start..];
+
+    if data.len() < HEADER_SIZE || &data[0..4] != TTA1 {
+      return Err(AudioError::Format(
+        "Not valid TTA".to_string()
+      ));
+    }
+    if crc32(&data[0..18]) != LittleEndian::read_u32(&data[18..22]) {
+      return Err(AudioError::Format(
+        "File is not valid TTA (Header checksum does not match)".to_string()
+      ));
+    }
+    match LittleEndian::read_u16(&data[4..6]) {
+      FORMAT_SIMPLE => {},
+      FORMAT_ENCRYPTED =>
+        return Err(AudioError::Unsupported(
+          "Encrypted TTA is not supported".to_string()
+        )),
+      f @ _ =>
+        return Err(AudioError::Unsupported(
+          format!("TTA audio format {} is not supported", f)
+        ))
+    }
+    let channels    = LittleEndian::read_u16(&data[6..8]) as usize;
+    let bit_depth   = LittleEndian::read_u16(&data[8..10]) as u32;
+    let sample_rate = LittleEndian::read_u32(&data[10..14]);
+    let length      = LittleEndian::read_u32(&data[14..18]) as usize;
+    let codec =
+      match bit_depth {
+        8  => LPCM_U8,
+        16 => LPCM_I16_LE,
+        24 => LPCM_I24_LE,
+        b @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("TTA audio with {} bits per sample is not supported", b)
+          ))
+      };
+    if channels == 0 || frame_length(sample_rate) == 0 {
+      return Err(AudioError::Format(
+        "File is not valid TTA (Invalid channel count or sample rate)".to_string()
+      ));
+    }
+
+    // The seek table holds the size of each frame.
+    let frame_length = frame_length(sample_rate);
+    let frames = (length + frame_length - 1) / frame_length;
+    let table_end = HEADER_SIZE + 4 * frames + 4;
+    if data.len() < table_end {
+      return Err(AudioError::Format(
+        "File is not valid TTA (Seek table is truncated)".to_string()
+      ));
+    }
+    let seek_table = &data[HEADER_SIZE .. table_end - 4];
+    if crc32(seek_table) != LittleEndian::read_u32(&data[table_end - 4 .. table_end]) {
+      return Err(AudioError::Format(
+        "File is not valid TTA (Seek table checksum does not match)".to_string()
+      ));
+    }
+
+    let mut values: Vec<i32> = Vec::with_capacity(length * channels);
+    let mut position = table_end;
+    for (i, size) in seek_table.chunks(4).map(LittleEndian::read_u32).enumerate() {
+      let end = position + size as usize;
+      if size < 4 || end > data.len() {
+        return Err(AudioError::Format(
+          "File is not valid TTA (Frame extends past the end of the file)".to_string()
+        ));
+      }
+      let frame = &data[position .. end - 4];
+      if crc32(frame) != LittleEndian::read_u32(&data[end - 4 .. end]) {
+        return Err(AudioError::Format(
+          "File is not valid TTA (Frame checksum does not match)".to_string()
+        ));
+      }
+      let count = frame_length.min(length - i * frame_length);
+      values.extend(try!(read_frame(frame, channels, codec.sample_size(), count)));
+      position = end;
+    }
+
+    // Samples are converted as if they were read from LPCM, so the audio is
+    // encoded identically by the LPCM codecs of the same bit depth.
+    let samples = try!(::codecs::decode(&to_bytes(&values, codec), codec));
+
+    Ok(TtaContainer {
+      bit_depth:      bit_depth,
+      sample_rate:    sample_rate,
+      channels:       channels as u32,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:        samples,
+      metadata:       metadata
+    })
+  }
+  fn create<W: Write>(_: &mut W, _: &AudioBuffer, _: Codec) -> AudioResult<()> {
+    Err(AudioError::Unsupported(
+      "Encoding TTA is not supported".to_string()
+    ))
+  }
+}
+
+/// Returns the bytes of the decoded samples, as stored using the codec.
+fn to_bytes(values: &[i32], codec: Codec) -> Vec<u8> {
+  let size = codec.sample_size();
+  let mut bytes = Vec::with_capacity(values.len() * size);
+  for &value in values.iter() {
+    match codec {
+      LPCM_U8 => bytes.push((value + 128) as u8),
+      _ => {
+        for i in 0..size {
+          bytes.push((value >> (8 * i)) as u8);
+        }
+      }
+    }
+  }
+  bytes
+}
@@ -0,0 +1,364 @@
+//! TTA Frames
+//!
+//! Each sample is predicted in three stages, which are undone in reverse
+//! order when decoding. The channels of each frame are decorrelated by
+//! taking the difference of neighbouring channels, each channel is
+//! predicted from its previous sample by a fixed first order predictor, and
+//! the error of that prediction is predicted again by an adaptive filter.
+//! The remaining residuals are coded with adaptive Rice codes. The state of
+//! every stage is reset at the start of each frame.
+use error::*;
+
+/// Shift of the adaptive filter for each number of bytes per sample.
+const FILTER_SHIFTS: [u32; 4] = [10, 9, 10, 12];
+
+/// Initial Rice parameter of both stages of the residual coder.
+const INITIAL_K: u32 = 10;
+
+/// Decodes the samples of a frame, interleaved by channel, whose bitstream
+/// excludes the checksum of the frame.
+pub fn read_frame(data: &[u8], channels: usize, bytes_per_sample: usize,
+                  frames: usize) -> AudioResult<Vec<i32>> {
+  let mut states: Vec<ChannelState> =
+    (0..channels).map(|_| ChannelState::new(bytes_per_sample)).collect();
+  let mut bits = BitReader::new(data);
+  let mut values = vec![0i32; frames * channels];
+  for frame in values.chunks_mut(channels) {
+    for (value, state) in frame.iter_mut().zip(states.iter_mut()) {
+      let residual = state.rice.read(&mut bits);
+      let filtered = state.filter.decode(residual);
+      *value = filtered.wrapping_add(state.prediction());
+      state.previous = *value;
+    }
+    // Each channel but the last was coded as the difference from the next
+    // channel, and the last relative to half of that difference.
+    if channels > 1 {
+      frame[channels - 1] = frame[channels - 1].wrapping_add(frame[channels - 2] / 2);
+      for i in (0 .. channels - 1).rev() {
+        frame[i] = frame[i + 1].wrapping_sub(frame[i]);
+      }
+    }
+  }
+  if bits.is_past_end() {
+    return Err(AudioError::Format(
+      "File is not valid TTA (Frame is truncated)".to_string()
+    ));
+  }
+  Ok(values)
+}
+
+/// Encodes the samples of a frame, interleaved by channel. This is the
+/// inverse of `read_frame`, used to create files for testing.
+#[cfg(test)]
+pub fn write_frame(values: &[i32], channels: usize, bytes_per_sample: usize) -> Vec<u8> {
+  let mut states: Vec<ChannelState> =
+    (0..channels).map(|_| ChannelState::new(bytes_per_sample)).collect();
+  let mut bits = BitWriter::new();
+  for frame in values.chunks(channels) {
+    let mut decorrelated = frame.to_vec();
+    if channels > 1 {
+      for i in 0 .. channels - 1 {
+        decorrelated[i] = frame[i + 1] - frame[i];
+      }
+      decorrelated[channels - 1] = frame[channels - 1] - decorrelated[channels - 2] / 2;
+    }
+    for (value, state) in decorrelated.iter().zip(states.iter_mut()) {
+      let filtered = value - state.prediction();
+      state.previous = *value;
+      let residual = state.filter.encode(filtered);
+      state.rice.write(&mut bits, residual);
+    }
+  }
+  bits.finish()
+}
+
+/// State of the prediction stages of a channel.
+struct ChannelState {
+  bytes_per_sample: usize,
+  previous:         i32,
+  filter:           Filter,
+  rice:             Rice
+}
+
+impl ChannelState {
+  fn new(bytes_per_sample: usize) -> ChannelState {
+    ChannelState {
+      bytes_per_sample: bytes_per_sample,
+      previous:         0,
+      filter:           Filter::new(FILTER_SHIFTS[bytes_per_sample - 1]),
+      rice:             Rice::new()
+    }
+  }
+
+  /// Returns the fixed prediction of the next sample, a fraction of the
+  /// previous sample close to one.
+  fn prediction(&self) -> i32 {
+    let previous = self.previous as i64;
+    match self.bytes_per_sample {
+      1 => ((previous * 15) >> 4) as i32,
+      4 => self.previous,
+      _ => ((previous * 31) >> 5) as i32
+    }
+  }
+}
+
+/// Adaptive filter of eight taps, whose coefficients follow the sign of the
+/// last residual.
+struct Filter {
+  shift:  u32,
+  error:  i32,
+  qm:     [i32; 8],
+  dx:     [i32; 8],
+  dl:     [i32; 8]
+}
+
+impl Filter {
+  fn new(shift: u32) -> Filter {
+    Filter {
+      shift:  shift,
+      error:  0,
+      qm:     [0; 8],
+      dx:     [0; 8],
+      dl:     [0; 8]
+    }
+  }
+
+  /// Adapts the coefficients and returns the prediction of the next value.
+  fn predict(&mut self) -> i32 {
+    if self.error != 0 {
+      for (qm, dx) in self.qm.iter_mut().zip(self.dx.iter()) {
+        *qm =
+          if self.error < 0 {
+            qm.wrapping_sub(*dx)
+          } else {
+            qm.wrapping_add(*dx)
+          };
+      }
+    }
+    let sum = self.dl.iter().zip(self.qm.iter())
+              .fold(1i32 << (self.shift - 1), |sum, (dl, qm)| sum.wrapping_add(dl.wrapping_mul(*qm)));
+
+    for i in 0..4 {
+      self.dx[i] = self.dx[i + 1];
+      self.dl[i] = self.dl[i + 1];
+    }
+    self.dx[4] = (self.dl[4] >> 30) | 1;
+    self.dx[5] = ((self.dl[5] >> 30) | 2) & !1;
+    self.dx[6] = ((self.dl[6] >> 30) | 2) & !1;
+    self.dx[7] = ((self.dl[7] >> 30) | 4) & !3;
+    sum >> self.shift
+  }
+
+  /// Updates the history with the residual and the value it was predicted
+  /// from.
+  fn update(&mut self, residual: i32, value: i32) {
+    self.error = residual;
+    self.dl[4] = self.dl[5].wrapping_neg();
+    self.dl[5] = self.dl[6].wrapping_neg();
+    self.dl[6] = value.wrapping_sub(self.dl[7]);
+    self.dl[7] = value;
+    self.dl[5] = self.dl[5].wrapping_add(self.dl[6]);
+    self.dl[4] = self.dl[4].wrapping_add(self.dl[5]);
+  }
+
+  fn decode(&mut self, residual: i32) -> i32 {
+    let value = residual.wrapping_add(self.predict());
+    self.update(residual, value);
+    value
+  }
+
+  #[cfg(test)]
+  fn encode(&mut self, value: i32) -> i32 {
+    let residual = value.wrapping_sub(self.predict());
+    self.update(residual, value);
+    residual
+  }
+}
+
+/// Adaptive Rice coder of two stages. Values at least the first stage's
+/// limit are coded by the second stage, following a unary prefix.
+struct Rice {
+  k0:   u32,
+  k1:   u32,
+  sum0: u32,
+  sum1: u32
+}
+
+impl Rice {
+  fn new() -> Rice {
+    Rice {
+      k0:   INITIAL_K,
+      k1:   INITIAL_K,
+      sum0: shift_16(INITIAL_K),
+      sum1: shift_16(INITIAL_K)
+    }
+  }
+
+  fn read(&mut self, bits: &mut BitReader) -> i32 {
+    let unary = bits.read_ones();
+    let mut value =
+      if unary == 0 {
+        bits.read(self.k0)
+      } else {
+        let k = self.k1;
+        let value = ((unary - 1) << k).wrapping_add(bits.read(k));
+        self.sum1 = adapt(&mut self.k1, self.sum1, value);
+        value.wrapping_add(1 << self.k0)
+      };
+    self.sum0 = adapt(&mut self.k0, self.sum0, value);
+    // Values alternate between positive and negative residuals.
+    value = (value >> 1) ^ (value & 1).wrapping_sub(1);
+    (value as i32).wrapping_add(1)
+  }
+
+  #[cfg(test)]
+  fn write(&mut self, bits: &mut BitWriter, residual: i32) {
+    let mut value =
+      if residual > 0 {
+        (residual as u32) * 2 - 1
+      } else {
+        residual.wrapping_neg() as u32 * 2
+      };
+    let mut k = self.k0;
+    self.sum0 = adapt(&mut self.k0, self.sum0, value);
+    let unary =
+      if value >= 1 << k {
+        value -= 1 << k;
+        k = self.k1;
+        self.sum1 = adapt(&mut self.k1, self.sum1, value);
+        1 + (value >> k)
+      } else {
+        0
+      };
+    for _ in 0..unary {
+      bits.write(1, 1);
+    }
+    bits.write(0, 1);
+    bits.write(value & ((1u64 << k) - 1) as u32, k);
+  }
+}
+
+/// Adds a value to the running sum of a stage, adjusting its Rice parameter
+/// when the sum leaves the range of the parameter.
+fn adapt(k: &mut u32, sum: u32, value: u32) -> u32 {
+  let sum = sum.wrapping_add(value).wrapping_sub(sum >> 4);
+  if *k > 0 && sum < shift_16(*k) {
+    *k -= 1;
+  } else if sum > shift_16(*k + 1) {
+    *k += 1;
+  }
+  sum
+}
+
+#[inline]
+fn shift_16(k: u32) -> u32 {
+  if k + 4 < 32 { 1 << (k + 4) } else { 1 << 31 }
+}
+
+/// Reads bits packed into a byte slice, starting at the least significant
+/// bit of each byte. Reading past the end of the slice gives zero bits.
+struct BitReader<'a> {
+  data:     &'a [u8],
+  position: usize
+}
+
+impl<'a> BitReader<'a> {
+  fn new(data: &'a [u8]) -> BitReader<'a> {
+    BitReader {
+      data:     data,
+      position: 0
+    }
+  }
+
+  fn is_past_end(&self) -> bool {
+    self.position > self.data.len() * 8
+  }
+
+  fn read_bit(&mut self) -> u32 {
+    let byte = self.data.get(self.position / 8).cloned().unwrap_or(0);
+    let bit = (byte >> (self.position % 8)) & 1;
+    self.position += 1;
+    bit as u32
+  }
+
+  fn read(&mut self, bits: u32) -> u32 {
+    (0..bits).fold(0, |value, i| value | self.read_bit() << i)
+  }
+
+  /// Reads a unary code of ones ending with a zero bit.
+  fn read_ones(&mut self) -> u32 {
+    let mut count = 0;
+    while !self.is_past_end() && self.read_bit() == 1 {
+      count += 1;
+    }
+    count
+  }
+}
+
+#[cfg(test)]
+struct BitWriter {
+  data:     Vec<u8>,
+  position: usize
+}
+
+#[cfg(test)]
+impl BitWriter {
+  fn new() -> BitWriter {
+    BitWriter {
+      data:     Vec::new(),
+      position: 0
+    }
+  }
+
+  fn write(&mut self, value: u32, bits: u32) {
+    for i in 0..bits {
+      if self.position % 8 == 0 {
+        self.data.push(0);
+      }
+      *self.data.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (self.position % 8);
+      self.position += 1;
+    }
+  }
+
+  fn finish(self) -> Vec<u8> {
+    self.data
+  }
+}
+
+#[cfg(test)]
+mod coding {
+  use super::*;
+
+  #[test]
+  fn rice_round_trip() {
+    let residuals = [0, 1, -1, 2, 5000, -70000, 3, 0, 0, 1 << 20, -(1 << 22), 7];
+    let mut writer = BitWriter::new();
+    let mut rice = Rice::new();
+    for residual in residuals.iter() {
+      rice.write(&mut writer, *residual);
+    }
+    let data = writer.finish();
+    let mut reader = BitReader::new(&data);
+    let mut rice = Rice::new();
+    for residual in residuals.iter() {
+      assert_eq!(*residual, rice.read(&mut reader));
+    }
+    assert!(!reader.is_past_end());
+  }
+
+  #[test]
+  fn frame_round_trip() {
+    for &(channels, bytes_per_sample) in [(1, 1), (2, 2), (3, 3), (6, 2)].iter() {
+      let max = 1i32 << (8 * bytes_per_sample - 1);
+      let values: Vec<i32> = (0 .. 1000 * channels).map(|i| {
+        let t = (i / channels) as f64 / 20f64;
+        let tone = (t + channels as f64 * (i % channels) as f64).sin() * (max - 1) as f64;
+        (tone as i32).max(-max).min(max - 1)
+      }).collect();
+      let data = write_frame(&values, channels, bytes_per_sample);
+      assert!(data.len() < values.len() * bytes_per_sample);
+      assert_eq!(values, read_frame(&data, channels, bytes_per_sample, 1000).unwrap());
+      assert!(read_frame(&data[.. data.len() / 2], channels, bytes_per_sample, 1000).is_err());
+    }
+  }
+}
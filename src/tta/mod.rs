@@ -0,0 +1,192 @@
+//! The True Audio Format
+//!
+//! TTA files begin with a header giving the format of the samples and the
+//! number of samples of each channel, followed by a seek table holding the
+//! size of each frame. Each frame codes about a second of audio, 256/245
+//! seconds exactly, and may be decoded on its own. The header, seek table,
+//! and each frame end with a CRC-32 checksum.
+//!
+//! Integer samples of 8, 16, and 24 bits are decoded losslessly. An ID3v2
+//! tag at the start of the file is kept in the metadata. Encrypted files are
+//! not supported.
+//!
+//! References
+//! - [TTA Format](https://tausoft.org/wiki/True_Audio_Codec_Format)
+//! - [TTA Lossless Audio Codec](http://tausoft.org/en/true_audio_codec_download/)
+
+mod container;
+mod frame;
+pub mod decoder;
+
+pub use tta::decoder::Decoder as Decoder;
+
+/// Signature at the start of the header.
+const TTA1: &'static [u8; 4] = b"TTA1";
+
+/// Size of the header, including its checksum.
+const HEADER_SIZE: usize = 22;
+
+/// Audio formats given by the header.
+const FORMAT_SIMPLE:    u16 = 1;
+const FORMAT_ENCRYPTED: u16 = 2;
+
+/// Returns the number of samples of each channel coded in every frame but
+/// the last.
+#[inline]
+fn frame_length(sample_rate: u32) -> usize {
+  (256 * sample_rate as u64 / 245) as usize
+}
+
+/// Returns the CRC-32 checksum of the bytes, as used by zlib.
+fn crc32(bytes: &[u8]) -> u32 {
+  let mut table: [u32; 256] = [0u32; 256];
+  for i in 0..256 {
+    let mut crc = i as u32;
+    for _ in 0..8 {
+      crc =
+        if crc & 1 != 0 {
+          (crc >> 1) ^ 0xEDB88320
+        } else {
+          crc >> 1
+        };
+    }
+    table[i] = crc;
+  }
+  !bytes.iter().fold(0xFFFFFFFF, |crc, byte|
+    (crc >> 8) ^ table[((crc as u8) ^ byte) as usize]
+  )
+}
+
+#[cfg(test)]
+mod io {
+  use std::io::Cursor;
+  use std::path::Path;
+  use byteorder::{ByteOrder, LittleEndian};
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec;
+  use ::codecs::Codec::*;
+  use ::id3::Id3Tag;
+  use super::*;
+  use super::frame::write_frame;
+
+  /// Returns a TTA file holding the interleaved samples.
+  fn tta_file(values: &[i32], channels: usize, bits: u16, sample_rate: u32) -> Vec<u8> {
+    let bytes_per_sample = (bits as usize + 7) / 8;
+    let frames = values.len() / channels;
+    let mut header: Vec<u8> = vec![0u8; HEADER_SIZE];
+    header[0..4].copy_from_slice(TTA1);
+    LittleEndian::write_u16(&mut header[4..6], FORMAT_SIMPLE);
+    LittleEndian::write_u16(&mut header[6..8], channels as u16);
+    LittleEndian::write_u16(&mut header[8..10], bits);
+    LittleEndian::write_u32(&mut header[10..14], sample_rate);
+    LittleEndian::write_u32(&mut header[14..18], frames as u32);
+    let crc = crc32(&header[0..18]);
+    LittleEndian::write_u32(&mut header[18..22], crc);
+
+    let mut seek_table: Vec<u8> = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    for chunk in values.chunks(frame_length(sample_rate) * channels) {
+      let mut frame = write_frame(chunk, channels, bytes_per_sample);
+      let crc = crc32(&frame);
+      frame.extend_from_slice(&[0u8; 4]);
+      let size = frame.len();
+      LittleEndian::write_u32(&mut frame[size - 4 ..], crc);
+      seek_table.extend_from_slice(&[0u8; 4]);
+      let entry = seek_table.len() - 4;
+      LittleEndian::write_u32(&mut seek_table[entry..], size as u32);
+      data.extend(frame);
+    }
+    let crc = crc32(&seek_table);
+    seek_table.extend_from_slice(&[0u8; 4]);
+    let size = seek_table.len();
+    LittleEndian::write_u32(&mut seek_table[size - 4 ..], crc);
+
+    header.extend(seek_table);
+    header.extend(data);
+    header
+  }
+
+  /// Returns the integer samples stored in LPCM bytes.
+  fn lpcm_values(bytes: &[u8], codec: Codec) -> Vec<i32> {
+    match codec {
+      LPCM_U8     => bytes.iter().map(|b| *b as i32 - 128).collect(),
+      LPCM_I16_LE => bytes.chunks(2).map(|b| LittleEndian::read_i16(b) as i32).collect(),
+      _           => bytes.chunks(3).map(|b| LittleEndian::read_int(b, 3) as i32).collect()
+    }
+  }
+
+  #[test]
+  fn checksum() {
+    assert_eq!(0xCBF43926, crc32(b"123456789"));
+  }
+
+  #[test]
+  fn codecs_eq() {
+    let original = audio::open(Path::new("tests/wav/M1F1-int16-AFsp.wav")).unwrap();
+    for &(codec, bits) in [(LPCM_U8, 8), (LPCM_I16_LE, 16), (LPCM_I24_LE, 24)].iter() {
+      let lpcm = ::codecs::encode(&original, codec).unwrap();
+      let values = lpcm_values(&lpcm, codec);
+      let bytes = tta_file(&values, 2, bits, original.sample_rate);
+      assert!(bytes.len() < lpcm.len());
+      let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::TTA).unwrap();
+      assert_eq!(2,                    audio.channels);
+      assert_eq!(original.sample_rate, audio.sample_rate);
+      assert_eq!(lpcm, ::codecs::encode(&audio, codec).unwrap());
+    }
+  }
+
+  #[test]
+  fn multichannel() {
+    let values: Vec<i32> = (0 .. 6 * 50000i64).map(|i| ((i * 7919) % 65536 - 32768) as i32).collect();
+    let bytes = tta_file(&values, 6, 16, 48000);
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::TTA).unwrap();
+    assert_eq!(6,     audio.channels);
+    assert_eq!(48000, audio.sample_rate);
+    assert_eq!(values, lpcm_values(&::codecs::encode(&audio, LPCM_I16_LE).unwrap(), LPCM_I16_LE));
+  }
+
+  #[test]
+  fn id3_tag() {
+    let mut tag = Id3Tag::default();
+    tag.set_text("TIT2", "Tape Transfer");
+    let mut bytes: Vec<u8> = Vec::new();
+    tag.write(&mut bytes).unwrap();
+    bytes.extend(tta_file(&[1, -1, 2, -2], 1, 16, 44100));
+    let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::TTA).unwrap();
+    assert_eq!(Some(&tag), audio.metadata.id3.as_ref());
+    assert_eq!(4, audio.samples.len());
+  }
+
+  #[test]
+  fn errors() {
+    let bytes = tta_file(&[0, 100, -100, 5], 2, 16, 44100);
+    assert!(audio::load(&mut Cursor::new(bytes.clone()), AudioFormat::TTA).is_ok());
+
+    // Checksums of the header and each frame are verified.
+    let mut corrupt = bytes.clone();
+    corrupt[12] ^= 1;
+    assert!(audio::load(&mut Cursor::new(corrupt), AudioFormat::TTA).is_err());
+    let mut corrupt = bytes.clone();
+    let last = corrupt.len() - 5;
+    corrupt[last] ^= 1;
+    assert!(audio::load(&mut Cursor::new(corrupt), AudioFormat::TTA).is_err());
+    let mut truncated = bytes.clone();
+    truncated.pop();
+    assert!(audio::load(&mut Cursor::new(truncated), AudioFormat::TTA).is_err());
+
+    let mut encrypted = bytes.clone();
+    LittleEndian::write_u16(&mut encrypted[4..6], FORMAT_ENCRYPTED);
+    let crc = crc32(&encrypted[0..18]);
+    LittleEndian::write_u32(&mut encrypted[18..22], crc);
+    match audio::load(&mut Cursor::new(encrypted), AudioFormat::TTA) {
+      Err(::error::AudioError::Unsupported(_)) => {},
+      _ => panic!("encrypted files should be unsupported")
+    }
+
+    assert!(audio::load(&mut Cursor::new(b"TTA2".to_vec()), AudioFormat::TTA).is_err());
+    let audio = AudioBuffer::from_samples(44100, 1, vec![0f32]);
+    assert!(audio::write(&mut Vec::new(), &audio, AudioFormat::TTA).is_err());
+  }
+}
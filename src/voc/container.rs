@@ -0,0 +1,396 @@
+use std::io::{Read, Seek, Write};
+use buffer::*;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use codecs::Codec;
+use codecs::Codec::*;
+use codecs::creative;
+use codecs::creative::AdpcmState;
+use error::*;
+use metadata::{Instrument, Loop, LoopMode, Metadata};
+use sample::*;
+use traits::Container;
+use voc::*;
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct VocContainer {
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for VocContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<VocContainer> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(reader.read_to_end(&mut bytes));
+    if bytes.len() < HEADER_SIZE || &bytes[0..20] != SIGNATURE {
+      return Err(AudioError::Format(
+        "Not valid VOC".to_string()
+      ));
+    }
+
+    let mut sound    = SoundReader::default();
+    let mut metadata = Metadata::default();
+    // Sample rate and channels given by an extended block, which replace
+    // those of the following sound data block.
+    let mut extended: Option<(u32, u32, u8)> = None;
+    // Frame and repeat count of each unfinished repeated part.
+    let mut repeats:  Vec<(usize, u16)>      = Vec::new();
+    let mut pos = LittleEndian::read_u16(&bytes[20..22]) as usize;
+    while pos < bytes.len() && bytes[pos] != TERMINATOR {
+      if pos + 4 > bytes.len() {
+        return Err(AudioError::Format(
+          "File is not valid VOC (Block header is truncated)".to_string()
+        ));
+      }
+      let block_type = bytes[pos];
+      let block_size = LittleEndian::read_u32(&[bytes[pos + 1], bytes[pos + 2], bytes[pos + 3], 0]) as usize;
+      let block_end  = (pos + 4 + block_size).min(bytes.len());
+      let data       = &bytes[pos + 4 .. block_end];
+      pos += 4 + block_size;
+      match block_type {
+        SOUND_DATA => {
+          if data.len() < 2 {
+            return Err(AudioError::Format(
+              "File is not valid VOC (Sound data block is too small)".to_string()
+            ));
+          }
+          let (sample_rate, channels, pack) =
+            extended.take().unwrap_or((1000000 / (256 - data[0] as u32), 1, data[1]));
+          let codec =
+            match pack as u16 {
+              CODEC_U8      => LPCM_U8,
+              CODEC_ADPCM_4 => CREATIVE_ADPCM,
+              p @ _ =>
+                return Err(AudioError::Unsupported(
+                  format!("VOC sound data with packing {} is not supported", p)
+                ))
+            };
+          try!(sound.start(sample_rate, channels, codec, &data[2..]));
+        },
+        SOUND_CONTINUE => {
+          try!(sound.continue_with(data));
+        },
+        SILENCE => {
+          if data.len() < 3 {
+            return Err(AudioError::Format(
+              "File is not valid VOC (Silence block is too small)".to_string()
+            ));
+          }
+          let frames = LittleEndian::read_u16(&data[0..2]) as usize + 1;
+          sound.silence(frames, 1000000 / (256 - data[2] as u32));
+        },
+        TEXT => {
+          let text = String::from_utf8_lossy(data).trim_right_matches('\u{0}').to_string();
+          metadata.annotation =
+            match metadata.annotation.take() {
+              Some(annotation) => Some(annotation + "\n" + &text),
+              None             => Some(text)
+            };
+        },
+        REPEAT_START => {
+          if data.len() >= 2 {
+            repeats.push((sound.num_frames(), LittleEndian::read_u16(&data[0..2])));
+          }
+        },
+        REPEAT_END => {
+          if let Some((start, count)) = repeats.pop() {
+            let end = sound.num_frames();
+            if count == REPEAT_FOREVER {
+              // The part is kept once, as the loop of an instrument.
+              if metadata.instrument.is_none() && end > start {
+                metadata.instrument = Some(Instrument {
+                  sustain_loop: Some(Loop {
+                    mode:       LoopMode::Forward,
+                    start:      start as u32,
+                    end:        end as u32 - 1,
+                    play_count: 0
+                  }),
+                  ..Instrument::default()
+                });
+              }
+            } else {
+              sound.repeat(start, count as usize);
+            }
+          }
+        },
+        EXTENDED => {
+          if data.len() >= 4 {
+            let time_constant = LittleEndian::read_u16(&data[0..2]) as u32;
+            let channels      = data[3] as u32 + 1;
+            let sample_rate   = 256000000 / (channels * (65536 - time_constant));
+            extended = Some((sample_rate, channels, data[2]));
+          }
+        },
+        SOUND_DATA_NEW => {
+          if data.len() < 12 {
+            return Err(AudioError::Format(
+              "File is not valid VOC (Sound data block is too small)".to_string()
+            ));
+          }
+          let sample_rate = LittleEndian::read_u32(&data[0..4]);
+          let bit_depth   = data[4];
+          let channels    = data[5] as u32;
+          let codec =
+            match (LittleEndian::read_u16(&data[6..8]), bit_depth) {
+              (CODEC_U8,             8 ) => LPCM_U8,
+              (CODEC_ADPCM_4,        4 ) |
+              (CODEC_CREATIVE_ADPCM, 4 ) => CREATIVE_ADPCM,
+              (CODEC_I16,            16) => LPCM_I16_LE,
+              (CODEC_ALAW,           8 ) => G711_ALAW,
+              (CODEC_ULAW,           8 ) => G711_ULAW,
+              (c, b) =>
+                return Err(AudioError::Unsupported(
+                  format!("VOC sound data of codec {} with {} bits is not supported", c, b)
+                ))
+            };
+          try!(sound.start(sample_rate, channels, codec, &data[12..]));
+        },
+        MARKER | _ => {}
+      }
+    }
+
+    let channels =
+      match (sound.sample_rate, sound.channels) {
+        (Some(_), Some(channels)) => channels,
+        (Some(_), None)           => 1,
+        _ => return Err(AudioError::Format(
+          "File is not valid VOC (Missing sound data)".to_string()
+        ))
+      };
+    Ok(VocContainer {
+      bit_depth:      sound.codec.map(|c| c.bit_depth() as u32).unwrap_or(8),
+      sample_rate:    sound.sample_rate.unwrap_or(0),
+      channels:       channels,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:        sound.samples,
+      metadata:       metadata
+    })
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    let (codec_id, bit_depth) =
+      match codec {
+        LPCM_U8        => (CODEC_U8,             8),
+        LPCM_I16_LE    => (CODEC_I16,            16),
+        G711_ALAW      => (CODEC_ALAW,           8),
+        G711_ULAW      => (CODEC_ULAW,           8),
+        CREATIVE_ADPCM => (CODEC_CREATIVE_ADPCM, 4),
+        c @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("VOC does not support the {:?} codec", c)
+          ))
+      };
+    if audio.channels == 0 || audio.channels > 255 ||
+       (codec == CREATIVE_ADPCM && audio.channels != 1) {
+      return Err(AudioError::Unsupported(
+        format!("VOC does not support {} audio with {} channels", codec, audio.channels)
+      ));
+    }
+    let channels   = audio.channels as usize;
+    let num_frames = audio.samples.len() / channels;
+
+    try!(writer.write(SIGNATURE));
+    try!(writer.write_u16::<LittleEndian>(HEADER_SIZE as u16));
+    try!(writer.write_u16::<LittleEndian>(VERSION));
+    try!(writer.write_u16::<LittleEndian>((!VERSION).wrapping_add(CHECKSUM_KEY)));
+    if let Some(ref annotation) = audio.metadata.annotation {
+      let mut text = annotation.as_bytes().to_vec();
+      text.push(0);
+      try!(write_block(writer, TEXT, &text));
+    }
+
+    // A sustain loop played until the note is released is written as a part
+    // repeated until playback is stopped.
+    let repeated =
+      audio.metadata.instrument.as_ref()
+      .and_then(|inst| inst.sustain_loop)
+      .and_then(|l|
+        if l.play_count == 0 && l.start <= l.end && (l.end as usize) < num_frames {
+          Some((l.start as usize, l.end as usize + 1))
+        } else {
+          None
+        }
+      );
+    let parts =
+      match repeated {
+        Some((start, end)) => vec![(0, start), (start, end), (end, num_frames)],
+        None               => vec![(0, num_frames)]
+      };
+    for (i, &(start, end)) in parts.iter().enumerate() {
+      if repeated.is_some() && i == 1 {
+        let mut count = [0u8; 2];
+        LittleEndian::write_u16(&mut count, REPEAT_FOREVER);
+        try!(write_block(writer, REPEAT_START, &count));
+      }
+      if end > start {
+        let part = AudioBuffer::from_samples(
+          audio.sample_rate,
+          audio.channels,
+          audio.samples[start * channels .. end * channels].to_vec()
+        );
+        let data =
+          match codec {
+            CREATIVE_ADPCM => {
+              let values: Vec<i32> =
+                try!(::codecs::encode(&part, LPCM_U8)).iter()
+                .map(|&b| b as i32 - 128).collect();
+              creative::create(&values)
+            },
+            _ => try!(::codecs::encode(&part, codec))
+          };
+        try!(write_sound(writer, audio, codec_id, bit_depth, &data, codec.sample_size() * channels));
+      }
+      if repeated.is_some() && i == 1 {
+        try!(write_block(writer, REPEAT_END, &[]));
+      }
+    }
+    try!(writer.write_u8(TERMINATOR));
+    Ok(())
+  }
+}
+
+/// Decodes the sound data and silence blocks of a file, keeping the state
+/// continued by later blocks.
+#[derive(Default)]
+struct SoundReader {
+  sample_rate:  Option<u32>,
+  channels:     Option<u32>,
+  codec:        Option<Codec>,
+  adpcm:        Option<AdpcmState>,
+  samples:      Vec<Sample>
+}
+
+impl SoundReader {
+  /// Returns the number of frames decoded so far.
+  fn num_frames(&self) -> usize {
+    self.samples.len() / self.channels.unwrap_or(1) as usize
+  }
+
+  /// Decodes a block of sound data. The sample rate of the audio is given by
+  /// the first block.
+  fn start(&mut self, sample_rate: u32, channels: u32, codec: Codec, bytes: &[u8]) -> AudioResult<()> {
+    if channels == 0 || self.channels.map_or(false, |c| c != channels) {
+      return Err(AudioError::Unsupported(
+        "VOC files whose sound data blocks change the channel count are not supported".to_string()
+      ));
+    }
+    if codec == CREATIVE_ADPCM && channels != 1 {
+      return Err(AudioError::Unsupported(
+        "VOC files of Creative ADPCM with several channels are not supported".to_string()
+      ));
+    }
+    if self.sample_rate.is_none() {
+      self.sample_rate = Some(sample_rate);
+    }
+    // Silence before the first sound data block is given a single channel.
+    if self.channels.is_none() && channels != 1 {
+      let silence: Vec<Sample> =
+        self.samples.iter().flat_map(|&s| vec![s; channels as usize]).collect();
+      self.samples = silence;
+    }
+    self.channels = Some(channels);
+    self.codec    = Some(codec);
+    self.adpcm    = None;
+    if codec == CREATIVE_ADPCM {
+      let (values, state) = creative::read(bytes);
+      self.adpcm = state;
+      return self.push_adpcm(&values);
+    }
+    self.push(bytes)
+  }
+
+  /// Decodes a block continuing the previous block of sound data.
+  fn continue_with(&mut self, bytes: &[u8]) -> AudioResult<()> {
+    match self.codec {
+      Some(CREATIVE_ADPCM) => {
+        let mut values = Vec::new();
+        if let Some(ref mut state) = self.adpcm {
+          state.decode(bytes, &mut values);
+        }
+        self.push_adpcm(&values)
+      },
+      Some(_) => self.push(bytes),
+      None => Err(AudioError::Format(
+        "File is not valid VOC (Sound data continues without a sound data block)".to_string()
+      ))
+    }
+  }
+
+  /// Adds frames of silence.
+  fn silence(&mut self, frames: usize, sample_rate: u32) {
+    if self.sample_rate.is_none() {
+      self.sample_rate = Some(sample_rate);
+    }
+    let len = self.samples.len() + frames * self.channels.unwrap_or(1) as usize;
+    self.samples.resize(len, 0f32);
+  }
+
+  /// Plays the frames from the start of a repeated part again the given
+  /// number of times.
+  fn repeat(&mut self, start: usize, count: usize) {
+    let part = self.samples[start * self.channels.unwrap_or(1) as usize ..].to_vec();
+    for _ in 0..count {
+      self.samples.extend_from_slice(&part);
+    }
+  }
+
+  /// Decodes whole frames of sound data using the current codec.
+  fn push(&mut self, bytes: &[u8]) -> AudioResult<()> {
+    if let (Some(codec), Some(channels)) = (self.codec, self.channels) {
+      let frame_size = codec.sample_size() * channels as usize;
+      let len = bytes.len() / frame_size * frame_size;
+      self.samples.extend(try!(::codecs::decode(&bytes[..len], codec)));
+    }
+    Ok(())
+  }
+
+  /// Adds signed 8-bit samples decoded from Creative ADPCM. They are
+  /// converted as if read from unsigned 8-bit PCM, which encodes them
+  /// identically.
+  fn push_adpcm(&mut self, values: &[i32]) -> AudioResult<()> {
+    let bytes: Vec<u8> = values.iter().map(|&v| (v + 128) as u8).collect();
+    self.samples.extend(try!(::codecs::decode(&bytes, LPCM_U8)));
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Writes a block with the given type and data.
+fn write_block<W: Write>(writer: &mut W, block_type: u8, data: &[u8]) -> AudioResult<()> {
+  try!(writer.write_u8(block_type));
+  try!(writer.write_u8(data.len() as u8));
+  try!(writer.write_u16::<LittleEndian>((data.len() >> 8) as u16));
+  try!(writer.write_all(data));
+  Ok(())
+}
+
+/// Writes sound data, continued in further blocks when it is too large for
+/// one. Blocks are split between frames.
+fn write_sound<W: Write>(writer: &mut W, audio: &AudioBuffer, codec_id: u16,
+                         bit_depth: u8, data: &[u8], frame_size: usize) -> AudioResult<()> {
+  let frame_size = if codec_id == CODEC_CREATIVE_ADPCM { 1 } else { frame_size };
+  let block_size = (MAX_BLOCK_SIZE - 12) / frame_size * frame_size;
+  for (i, chunk) in data.chunks(block_size).enumerate() {
+    if i == 0 {
+      let mut block = vec![0u8; 12];
+      LittleEndian::write_u32(&mut block[0..4], audio.sample_rate);
+      block[4] = bit_depth;
+      block[5] = audio.channels as u8;
+      LittleEndian::write_u16(&mut block[6..8], codec_id);
+      block.extend_from_slice(chunk);
+      try!(write_block(writer, SOUND_DATA_NEW, &block));
+    } else {
+      try!(write_block(writer, SOUND_CONTINUE, chunk));
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,38 @@
+use std::io::Write;
+use buffer::AudioBuffer;
+use codecs::Codec;
+use codecs::Codec::LPCM_U8;
+use error::AudioResult;
+use traits::{AudioEncoder, Container};
+use voc::container::VocContainer;
+
+/// Encodes audio to VOC format to the provided writer.
+pub struct Encoder<'w, W: 'w> {
+  writer: &'w mut W,
+}
+
+impl<'w, W> Encoder<'w, W> where W: Write {
+  /// Create a new VOC format `Encoder` using the provided writer.
+  #[inline]
+  pub fn new(writer: &'w mut W) -> Encoder<'w, W> {
+    Encoder {
+      writer: writer
+    }
+  }
+}
+ 
+impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
+  /// Creates and writes a `VocContainer` to the included writer. The audio
+  /// is encoded to unsigned 8-bit LPCM audio.
+  #[inline]
+  fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
+    VocContainer::create(&mut self.writer, audio, LPCM_U8)
+  }
+  /// Creates and writes a `VocContainer` using the provided `SampleFormat`
+  /// to the included writer. This is how audio can be encoded to different
+  /// bit rates supported by the format.
+  #[inline]
+  fn encode_as(&mut self, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    VocContainer::create(&mut self.writer, audio, codec)
+  }
+}
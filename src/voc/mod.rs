@@ -0,0 +1,207 @@
+//! The Creative Voice Format
+//!
+//! VOC files begin with a short header followed by a sequence of blocks,
+//! ending with a terminator block. Blocks of sound data are decoded in order,
+//! along with blocks of silence and blocks marking a part of the audio to be
+//! repeated. Finite repeats are expanded into the samples they play, while a
+//! part repeated until playback is stopped is read once as the sustain loop of
+//! an instrument. Text blocks are read as the annotation of the audio. All
+//! integers are stored in little-endian format.
+//!
+//! Audio is written using the sound data blocks of version 1.20, with each
+//! block of Creative ADPCM holding an odd number of frames. The last frame of
+//! such a block is repeated when it would otherwise hold an even number.
+//!
+//! References
+//! - [Creative Voice File Format](https://wiki.multimedia.cx/index.php/Creative_Voice)
+//! - [VOC File Format](http://www.shikadi.net/moddingwiki/VOC_Format)
+
+mod container;
+pub mod decoder;
+pub mod encoder;
+
+pub use voc::decoder::Decoder as Decoder;
+pub use voc::encoder::Encoder as Encoder;
+
+/// Identifier at the start of every VOC file.
+const SIGNATURE: &'static [u8; 20] = b"Creative Voice File\x1A";
+
+/// Size of the header written to VOC files.
+const HEADER_SIZE: usize = 26;
+
+/// Version 1.20, which introduced sound data blocks of any codec.
+const VERSION: u16 = 0x0114;
+
+/// Added to the complement of the version to give the header checksum.
+const CHECKSUM_KEY: u16 = 0x1234;
+
+/// Block types.
+const TERMINATOR:     u8 = 0;
+const SOUND_DATA:     u8 = 1;
+const SOUND_CONTINUE: u8 = 2;
+const SILENCE:        u8 = 3;
+const MARKER:         u8 = 4;
+const TEXT:           u8 = 5;
+const REPEAT_START:   u8 = 6;
+const REPEAT_END:     u8 = 7;
+const EXTENDED:       u8 = 8;
+const SOUND_DATA_NEW: u8 = 9;
+
+/// Largest size of a block, excluding its header.
+const MAX_BLOCK_SIZE: usize = 0xFFFFFF;
+
+/// Codec identifiers of sound data blocks.
+const CODEC_U8:             u16 = 0x000;
+const CODEC_ADPCM_4:        u16 = 0x001;
+const CODEC_I16:            u16 = 0x004;
+const CODEC_ALAW:           u16 = 0x006;
+const CODEC_ULAW:           u16 = 0x007;
+const CODEC_CREATIVE_ADPCM: u16 = 0x200;
+
+/// Repeat count of a part played until playback is stopped.
+const REPEAT_FOREVER: u16 = 0xFFFF;
+
+#[cfg(test)]
+mod io {
+  use std::f64::consts::PI;
+  use std::io::Cursor;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+  use ::metadata::{Instrument, Loop, LoopMode};
+  use ::testing::{Sine, signal};
+
+  /// Returns a file holding the given blocks, followed by a terminator.
+  fn voc_file(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut bytes = b"Creative Voice File\x1A\x1A\x00\x0A\x01\x29\x11".to_vec();
+    for &(block_type, ref data) in blocks.iter() {
+      let size = data.len();
+      bytes.extend_from_slice(&[block_type, size as u8, (size >> 8) as u8, (size >> 16) as u8]);
+      bytes.extend_from_slice(data);
+    }
+    bytes.push(0);
+    bytes
+  }
+
+  fn tone(channels: u32, frames: usize) -> AudioBuffer {
+    let waves = [Sine::new(35f64, 0.75), Sine { phase: PI, ..Sine::new(35f64, 0.75) }];
+    signal(22050, frames, &waves[.. channels as usize])
+  }
+
+  #[test]
+  fn codecs_eq() {
+    for &(codec, channels) in [(LPCM_U8, 1), (LPCM_U8, 2), (LPCM_I16_LE, 2),
+                               (G711_ALAW, 1), (G711_ULAW, 2)].iter() {
+      let audio = tone(channels, 1000);
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write_as(&mut bytes, &audio, AudioFormat::VOC, codec).unwrap();
+      assert_eq!(b"Creative Voice File\x1A\x1A\x00\x14\x01\x1F\x11", &bytes[0..26]);
+      let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::VOC).unwrap();
+      assert_eq!(channels, verify.channels);
+      assert_eq!(22050,    verify.sample_rate);
+      assert_eq!(::codecs::encode(&audio,  codec).unwrap(),
+                 ::codecs::encode(&verify, codec).unwrap());
+    }
+  }
+
+  #[test]
+  fn creative_adpcm() {
+    let audio = tone(1, 1001);
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write_as(&mut bytes, &audio, AudioFormat::VOC, CREATIVE_ADPCM).unwrap();
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::VOC).unwrap();
+    assert_eq!(audio.samples.len(), verify.samples.len());
+    for (sample, verify) in audio.samples.iter().zip(verify.samples.iter()) {
+      assert!((sample - verify).abs() < 0.07);
+    }
+    let audio = tone(2, 10);
+    assert!(audio::write_as(&mut Vec::new(), &audio, AudioFormat::VOC, CREATIVE_ADPCM).is_err());
+  }
+
+  #[test]
+  fn legacy_blocks() {
+    let file = voc_file(&[
+      // 8-bit sound data at 1000000 / (256 - 156) = 10000 Hz
+      (1, vec![156, 0, 128, 255, 0]),
+      (2, vec![192]),
+      // Silence of 3 samples
+      (3, vec![2, 0, 156]),
+      (4, vec![1, 0]),
+      // A part played three times
+      (6, vec![2, 0]),
+      (1, vec![156, 0, 64]),
+      (7, vec![]),
+      (5, b"Game over\x00".to_vec())
+    ]);
+    let audio = audio::load(&mut Cursor::new(file), AudioFormat::VOC).unwrap();
+    assert_eq!(10000, audio.sample_rate);
+    assert_eq!(1,     audio.channels);
+    assert_eq!(vec![0f32, 127f32 / 128f32, -1f32, 0.5f32, 0f32, 0f32, 0f32,
+                    -0.5f32, -0.5f32, -0.5f32], audio.samples);
+    assert_eq!(Some("Game over".to_string()), audio.metadata.annotation);
+    assert_eq!(None, audio.metadata.instrument);
+  }
+
+  #[test]
+  fn extended_blocks() {
+    // The extended block gives the sample rate and channels of the sound data
+    // block that follows, here stereo 8-bit audio at
+    // 256000000 / (2 * (65536 - 59733)) = 22057 Hz.
+    let file = voc_file(&[
+      (8, vec![0x55, 0xE9, 0, 1]),
+      (1, vec![0, 0, 128, 129, 130, 131])
+    ]);
+    let audio = audio::load(&mut Cursor::new(file), AudioFormat::VOC).unwrap();
+    assert_eq!(22057, audio.sample_rate);
+    assert_eq!(2,     audio.channels);
+    assert_eq!(4,     audio.samples.len());
+
+    // Mono 4-bit ADPCM, starting from a reference byte.
+    let file = voc_file(&[
+      (8, vec![0x55, 0xE9, 1, 0]),
+      (1, vec![0, 1, 128, 0x12, 0x31])
+    ]);
+    let audio = audio::load(&mut Cursor::new(file), AudioFormat::VOC).unwrap();
+    let expected: Vec<f32> = vec![0, 1, 3, 6, 7].iter().map(|&v| v as f32 / 128f32).collect();
+    assert_eq!(expected, audio.samples);
+  }
+
+  #[test]
+  fn repeat_forever() {
+    let mut audio = tone(2, 300);
+    audio.metadata.annotation = Some("Engine hum".to_string());
+    audio.metadata.instrument = Some(Instrument {
+      sustain_loop: Some(Loop {
+        mode:       LoopMode::Forward,
+        start:      100,
+        end:        199,
+        play_count: 0
+      }),
+      ..Instrument::default()
+    });
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write_as(&mut bytes, &audio, AudioFormat::VOC, LPCM_I16_LE).unwrap();
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::VOC).unwrap();
+    assert_eq!(::codecs::encode(&audio,  LPCM_I16_LE).unwrap(),
+               ::codecs::encode(&verify, LPCM_I16_LE).unwrap());
+    assert_eq!(audio.metadata.annotation, verify.metadata.annotation);
+    assert_eq!(audio.metadata.instrument, verify.metadata.instrument);
+  }
+
+  #[test]
+  fn errors() {
+    assert!(audio::load(&mut Cursor::new(b"Creative Voice Fil".to_vec()), AudioFormat::VOC).is_err());
+    // Continuing sound data that was never started
+    let file = voc_file(&[(2, vec![0, 0])]);
+    assert!(audio::load(&mut Cursor::new(file), AudioFormat::VOC).is_err());
+    // Changing the channel count
+    let file = voc_file(&[
+      (9, vec![0x44, 0xAC, 0, 0, 8, 1, 0, 0, 0, 0, 0, 0, 128]),
+      (9, vec![0x44, 0xAC, 0, 0, 8, 2, 0, 0, 0, 0, 0, 0, 128, 128])
+    ]);
+    assert!(audio::load(&mut Cursor::new(file), AudioFormat::VOC).is_err());
+    let audio = AudioBuffer::from_samples(44100, 1, vec![0f32]);
+    assert!(audio::write_as(&mut Vec::new(), &audio, AudioFormat::VOC, LPCM_I24_LE).is_err());
+  }
+}
@@ -0,0 +1,197 @@
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use buffer::*;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use codecs::Codec;
+use error::*;
+use metadata::Metadata;
+use sample::*;
+use traits::{Chunk, Container};
+use w64::{RIFF, WAVE, FMT, FACT, DATA, CHUNK_HEADER_SIZE};
+use wave::chunks::{FormatChunk, FormatTag, determine_codec, is_supported};
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct W64Container {
+  codec:            Codec,
+  pub bit_depth:    u32,
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub block_size:   u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for W64Container {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<W64Container> {
+    // Read and validate riff header
+    let mut riff_header: [u8; 40] = [0u8; 40];
+    try!(reader.read_exact(&mut riff_header));
+    if &riff_header[0..16]  != RIFF
+    || &riff_header[24..40] != WAVE {
+      return Err(AudioError::Format(
+        "Not valid Wave64".to_string()
+      ));
+    }
+    // The riff size includes the header itself.
+    let file_size: u64 = LittleEndian::read_u64(&riff_header[16..24]);
+    if file_size < 40 {
+      return Err(AudioError::Format(
+        "File is not valid Wave64 (Riff size is too small)".to_string()
+      ));
+    }
+    let mut buffer: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+    try!(reader.take(file_size - 40).read_to_end(buffer.get_mut()));
+    let buffer_size = buffer.get_ref().len() as u64;
+
+    // Read all supported chunks
+    let mut container =
+      W64Container {
+        codec:          Codec::LPCM_I16_LE,
+        bit_depth:      0u32,
+        sample_rate:    0u32,
+        channels:       1u32,
+        block_size:     0u32,
+        order:          SampleOrder::Interleaved,
+        samples:        Vec::with_capacity(1024),
+        metadata:       Metadata::default()
+      };
+    let mut chunk_header      : [u8; 24] = [0u8; 24];
+    let mut read_fmt_chunk    : bool     = false;
+    let mut read_fact_chunk   : bool     = false;
+    let mut read_data_chunk   : bool     = false;
+    while buffer.position() + CHUNK_HEADER_SIZE <= buffer_size {
+      try!(buffer.read_exact(&mut chunk_header));
+      // Chunk sizes include the chunk header.
+      let chunk_size: u64 = LittleEndian::read_u64(&chunk_header[16..24]);
+      if chunk_size < CHUNK_HEADER_SIZE {
+        return Err(AudioError::Format(
+          "File is not valid Wave64 (Chunk size is too small)".to_string()
+        ))
+      }
+      let data_size = chunk_size - CHUNK_HEADER_SIZE;
+      let pos: usize = buffer.position() as usize;
+      let chunk_end: usize = (pos as u64 + data_size).min(buffer_size) as usize;
+      let guid = &chunk_header[0..16];
+      if guid == FMT {
+        let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+        if chunk_bytes.len() < 16 {
+          return Err(AudioError::Format(
+            "File is not valid Wave64 (Format chunk is too small)".to_string()
+          ))
+        }
+        let fmt_chunk = try!(FormatChunk::read(&chunk_bytes));
+        container.bit_depth       = fmt_chunk.bit_depth    as u32;
+        container.sample_rate     = fmt_chunk.sample_rate;
+        container.channels        = fmt_chunk.num_channels as u32;
+        container.block_size      = fmt_chunk.block_size   as u32;
+        container.metadata.channel_mask = fmt_chunk.channel_mask;
+        container.order           =
+          if container.channels == 1 {
+            SampleOrder::Mono
+          } else {
+            SampleOrder::Interleaved
+          };
+        container.codec           =
+          try!(determine_codec(fmt_chunk.format_tag,
+                               fmt_chunk.bit_depth));
+        read_fmt_chunk            = true;
+        if fmt_chunk.format_tag == FormatTag::Pcm {
+          // Don't need to check for fact chunk if PCM
+          read_fact_chunk = true;
+        }
+      }
+      else if guid == FACT {
+        read_fact_chunk = true;
+      }
+      else if guid == DATA {
+        if !read_fmt_chunk {
+          return Err(AudioError::Format(
+            "File is not valid Wave64 \
+            (Format chunk does not occur before Data chunk)".to_string()
+          ))
+        }
+        let chunk_bytes   = &(buffer.get_ref()[pos .. chunk_end]);
+        container.samples = try!(::codecs::decode(chunk_bytes, container.codec));
+        read_data_chunk   = true;
+      }
+      // Wave64 chunks are aligned to 8 bytes, so chunks are followed by
+      // padding that is not included in the chunk size.
+      let padding = (8 - data_size % 8) % 8;
+      try!(buffer.seek(SeekFrom::Current((data_size + padding) as i64)));
+    }
+
+    // Check if required chunks were read
+    if !read_fmt_chunk {
+      return Err(AudioError::Format(
+        "File is not valid Wave64 (Missing required Format chunk)".to_string()
+      ))
+    }
+    if !read_fact_chunk {
+      return Err(AudioError::Format(
+        "File is not valid Wave64 \
+        (Missing Fact chunk for non-PCM data)".to_string()
+      ))
+    }
+    if !read_data_chunk {
+      return Err(AudioError::Format(
+        "File is not valid Wave64 (Missing required Data chunk)".to_string()
+      ))
+    }
+    Ok(container)
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    // Determine if codec is supported by container and if data is non-PCM.
+    let data_non_pcm: bool = try!(is_supported(codec));
+    // Encode audio samples using codec.
+    let data: Vec<u8> = try!(::codecs::encode(audio, codec));
+    let fmt_chunk_size = CHUNK_HEADER_SIZE
+                       + FormatChunk::calculate_size(audio, codec) as u64;
+    let fact_chunk_size = CHUNK_HEADER_SIZE + 8;
+    let data_chunk_size = CHUNK_HEADER_SIZE + data.len() as u64;
+    let mut total_bytes: u64 = 40 + aligned(fmt_chunk_size) + data_chunk_size;
+    // Files encoded with non-PCM data must include a fact chunk.
+    if data_non_pcm {
+      total_bytes += fact_chunk_size;
+    }
+
+    // Write the riff header to the writer.
+    try!(writer.write(RIFF));
+    try!(writer.write_u64::<LittleEndian>(total_bytes));
+    try!(writer.write(WAVE));
+    // Write fmt chunk to the writer.
+    try!(writer.write(FMT));
+    try!(writer.write_u64::<LittleEndian>(fmt_chunk_size));
+    try!(FormatChunk::write_data::<LittleEndian, W>(writer, audio, codec));
+    try!(write_padding(writer, fmt_chunk_size));
+    // Write fact chunk to writer if data is non-PCM
+    if data_non_pcm {
+      try!(writer.write(FACT));
+      try!(writer.write_u64::<LittleEndian>(fact_chunk_size));
+      try!(writer.write_u64::<LittleEndian>(audio.samples.len() as u64 / audio.channels as u64));
+    }
+    // Write data chunk to the writer.
+    try!(writer.write(DATA));
+    try!(writer.write_u64::<LittleEndian>(data_chunk_size));
+    try!(writer.write_all(&data));
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Returns the size of a chunk including the padding that aligns the next
+/// chunk to 8 bytes.
+#[inline]
+fn aligned(chunk_size: u64) -> u64 {
+  (chunk_size + 7) / 8 * 8
+}
+
+/// Writes the padding that aligns the next chunk to 8 bytes.
+#[inline]
+fn write_padding<W: Write>(writer: &mut W, chunk_size: u64) -> AudioResult<()> {
+  for _ in chunk_size .. aligned(chunk_size) {
+    try!(writer.write_u8(0));
+  }
+  Ok(())
+}
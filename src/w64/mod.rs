@@ -0,0 +1,111 @@
+//! The Sony Wave64 Format
+//!
+//! Wave64 files follow the structure of WAVE files, but identify chunks using
+//! 128-bit GUIDs and store chunk sizes as 64-bit integers, allowing files
+//! larger than 4 GB. Chunk sizes include the 24 byte chunk header, and chunks
+//! are aligned to 8 bytes. All integers are stored in little-endian format.
+//!
+//! References
+//! - [McGill University](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/WAVE.html)
+//! - [libsndfile](https://github.com/libsndfile/libsndfile/blob/master/src/w64.c)
+
+mod container;
+pub mod decoder;
+pub mod encoder;
+
+pub use w64::decoder::Decoder as Decoder;
+pub use w64::encoder::Encoder as Encoder;
+
+/// Wave64 chunk identifiers.
+const RIFF: &'static [u8; 16] = &[
+  0x72, 0x69, 0x66, 0x66, 0x2E, 0x91, 0xCF, 0x11,
+  0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00
+];
+const WAVE: &'static [u8; 16] = &[
+  0x77, 0x61, 0x76, 0x65, 0xF3, 0xAC, 0xD3, 0x11,
+  0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A
+];
+const FMT:  &'static [u8; 16] = &[
+  0x66, 0x6D, 0x74, 0x20, 0xF3, 0xAC, 0xD3, 0x11,
+  0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A
+];
+const FACT: &'static [u8; 16] = &[
+  0x66, 0x61, 0x63, 0x74, 0xF3, 0xAC, 0xD3, 0x11,
+  0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A
+];
+const DATA: &'static [u8; 16] = &[
+  0x64, 0x61, 0x74, 0x61, 0xF3, 0xAC, 0xD3, 0x11,
+  0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A
+];
+
+/// Size of a chunk header, made up of the GUID and the chunk size.
+const CHUNK_HEADER_SIZE: u64 = 24;
+
+#[cfg(test)]
+mod io {
+  use std::io::Cursor;
+  use std::path::{Path, PathBuf};
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+
+  #[test]
+  fn codecs_eq() {
+    let mut path = PathBuf::from("tests");
+    path.push("wav");
+    path.push("empty.wav");
+    let files = vec![
+      ("M1F1-uint8-AFsp.wav",   LPCM_U8),
+      ("M1F1-int16-AFsp.wav",   LPCM_I16_LE),
+      ("M1F1-int24-AFsp.wav",   LPCM_I24_LE),
+      ("M1F1-int32-AFsp.wav",   LPCM_I32_LE),
+      ("M1F1-float32-AFsp.wav", LPCM_F32_LE),
+      ("M1F1-float64-AFsp.wav", LPCM_F64_LE),
+      ("M1F1-mulaw-AFsp.wav",   G711_ULAW),
+      ("M1F1-Alaw-AFsp.wav",    G711_ALAW)
+    ];
+
+    for &(file, codec) in files.iter() {
+      path.set_file_name(file);
+      println!("{:?}", path.as_path());
+      let audio = audio::open(path.as_path()).unwrap();
+
+      let write_path = Path::new("tests/results/tmp_codec.w64");
+      assert!(audio::save_as(&write_path, &audio, codec).is_ok());
+
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(audio.channels,      verify.channels);
+      assert_eq!(audio.sample_rate,   verify.sample_rate);
+      assert_eq!(audio.samples.len(), verify.samples.len());
+      for (inital_sample, written_sample) in
+          audio.samples.iter().zip(&verify.samples) {
+        assert_eq!(inital_sample, written_sample);
+      }
+    }
+  }
+
+  #[test]
+  fn chunk_layout() {
+    let audio = AudioBuffer::from_samples(44100, 1, vec![0f32, 0.5f32, -0.5f32]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::W64, LPCM_F32_LE).is_ok());
+    // The 18 byte non-PCM format chunk is padded to 8 byte alignment.
+    assert_eq!(b"riff", &bytes[0..4]);
+    assert_eq!(b"wave", &bytes[24..28]);
+    assert_eq!(b"fmt ", &bytes[40..44]);
+    assert_eq!(b"fact", &bytes[88..92]);
+    assert_eq!(b"data", &bytes[120..124]);
+    assert_eq!(156, bytes[16]);
+    assert_eq!(156, bytes.len());
+    let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::W64).unwrap();
+    assert_eq!(audio.samples, verify.samples);
+  }
+
+  #[test]
+  fn unsupported_codec() {
+    let audio = AudioBuffer::from_samples(8000, 1, vec![0f32; 4]);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::W64, LPCM_I16_BE).is_err());
+  }
+}
@@ -1,15 +1,17 @@
 //! WAVE Chunks
 use std::fmt;
 use std::io::Write;
+use audio::AudioFormat;
 use buffer::AudioBuffer;
-use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use codecs::Codec;
 use codecs::Codec::*;
 use error::*;
+use metadata::{Instrument, Loop, LoopInfo, LoopMode};
 use self::FormatChunkVariant::*;
 use self::FormatTag::*;
 use traits::Chunk;
-use wave::{FACT, FMT, DATA};
+use wave::{ACID, FACT, FMT, DATA, INST, RIFF, RIFX, SMPL};
 
 /// Format tag for the wave extensible format. Unlike chunk identifiers,
 /// this is read as little endian data since it is within the chunk.
@@ -22,6 +24,77 @@ const GUID_SUFFIX: [u8; 14] = [
   0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71
 ];
 
+/// Byte order of the integers and samples within a RIFF file.
+///
+/// WAVE files are normally stored as little-endian `RIFF` files, but some
+/// hardware writes big-endian `RIFX` files with the same structure. The format
+/// chunk describes samples the same way in both, so codecs are determined from
+/// the format chunk as little-endian and converted to the byte order of the
+/// file.
+pub trait RiffByteOrder : ByteOrder {
+  /// Returns the identifier of the riff header.
+  fn riff_id() -> &'static [u8; 4];
+  /// Returns the format of the unrecognized chunks of files in this byte
+  /// order.
+  fn chunk_format() -> AudioFormat;
+  /// Returns the codec of samples stored in this byte order, given the codec
+  /// described by the format chunk.
+  fn codec(format_codec: Codec) -> Codec;
+  /// Returns the codec described by the format chunk, given the codec of
+  /// samples stored in this byte order.
+  fn format_codec(codec: Codec) -> Codec;
+}
+
+impl RiffByteOrder for LittleEndian {
+  #[inline]
+  fn riff_id() -> &'static [u8; 4] {
+    RIFF
+  }
+  #[inline]
+  fn chunk_format() -> AudioFormat {
+    AudioFormat::WAVE
+  }
+  #[inline]
+  fn codec(format_codec: Codec) -> Codec {
+    format_codec
+  }
+  #[inline]
+  fn format_codec(codec: Codec) -> Codec {
+    codec
+  }
+}
+
+impl RiffByteOrder for BigEndian {
+  #[inline]
+  fn riff_id() -> &'static [u8; 4] {
+    RIFX
+  }
+  #[inline]
+  fn chunk_format() -> AudioFormat {
+    AudioFormat::RIFX
+  }
+  fn codec(format_codec: Codec) -> Codec {
+    match format_codec {
+      LPCM_I16_LE => LPCM_I16_BE,
+      LPCM_I24_LE => LPCM_I24_BE,
+      LPCM_I32_LE => LPCM_I32_BE,
+      LPCM_F32_LE => LPCM_F32_BE,
+      LPCM_F64_LE => LPCM_F64_BE,
+      c @ _       => c
+    }
+  }
+  fn format_codec(codec: Codec) -> Codec {
+    match codec {
+      LPCM_I16_BE => LPCM_I16_LE,
+      LPCM_I24_BE => LPCM_I24_LE,
+      LPCM_I32_BE => LPCM_I32_LE,
+      LPCM_F32_BE => LPCM_F32_LE,
+      LPCM_F64_BE => LPCM_F64_LE,
+      c @ _       => c
+    }
+  }
+}
+
 /// Supported WAVE chunks
 ///
 /// Some chunks may only contain one item with a size specified by the chunk
@@ -30,7 +103,13 @@ const GUID_SUFFIX: [u8; 14] = [
 pub enum WaveChunk {
   Format,
   Fact,
-  Data
+  Data,
+  Sampler,
+  Instrument,
+  Id3,
+  Ixml,
+  Axml,
+  Acid
 }
 
 /// Supported compression codes in the WAVE format chunk. These also correspond
@@ -73,7 +152,8 @@ pub struct FormatChunk {
   pub block_size:       u16,
   pub bit_depth:        u16,
   // pub valid_bits_per_sample:  Some(u16),
-  // pub speaker_position_mask:  Some(u32),  // Requires `extern crate bitflags`
+  /// Speaker position mask of the extensible format, if one is given
+  pub channel_mask:     Option<u32>
 }
 
 /// The variants of the format chunk with their respective chunk sizes.
@@ -118,6 +198,44 @@ fn get_bit_depth(codec: Codec) -> AudioResult<u16> {
   }
 }
 
+/// Determines if codec is supported by container. Since WAVE encoding also
+/// depends on whether the codec is integer-based PCM, the return value
+/// represeents if the codec as such.
+pub fn is_supported(codec: Codec) -> AudioResult<bool> {
+  match codec {
+    LPCM_U8      |
+    LPCM_I16_LE  |
+    LPCM_I24_LE  |
+    LPCM_I32_LE  => Ok(false),
+    LPCM_F32_LE  |
+    LPCM_F64_LE  |
+    G711_ALAW    |
+    G711_ULAW    => Ok(true),
+    c @ _ =>
+      return Err(AudioError::Unsupported(
+        format!("Wave does not support the {:?} codec", c)
+      ))
+  }
+}
+
+/// Returns the `Codec` used by the read audio attributes.
+pub fn determine_codec(format_tag: FormatTag, bit_depth: u16) -> AudioResult<Codec> {
+  match (format_tag, bit_depth) {
+    (FormatTag::Pcm,    8) => Ok(LPCM_U8),
+    (FormatTag::Pcm,   16) => Ok(LPCM_I16_LE),
+    (FormatTag::Pcm,   24) => Ok(LPCM_I24_LE),
+    (FormatTag::Pcm,   32) => Ok(LPCM_I32_LE),
+    (FormatTag::ALaw,   8) => Ok(G711_ALAW),
+    (FormatTag::MuLaw,  8) => Ok(G711_ULAW),
+    (FormatTag::Float, 32) => Ok(LPCM_F32_LE),
+    (FormatTag::Float, 64) => Ok(LPCM_F64_LE),
+    (_, _) =>
+      return Err(AudioError::Unsupported(
+        "Audio encoded with unsupported codec".to_string()
+      ))
+  }
+}
+
 impl FormatChunk {
   // Cases:
   // is WAVE_FORMAT_EXTENSIBLE if:
@@ -149,41 +267,52 @@ impl FormatChunk {
     FormatChunk::determine_variant(audio, codec) as u32
   }
 
-  pub fn write<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+  pub fn write<E: ByteOrder, W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
     try!(writer.write(FMT));
+    try!(writer.write_u32::<E>(FormatChunk::calculate_size(audio, codec)));
+    FormatChunk::write_data::<E, W>(writer, audio, codec)
+  }
+
+  /// Writes the contents of the chunk without the chunk header. This allows
+  /// the chunk to be shared by formats with different chunk headers.
+  pub fn write_data<E: ByteOrder, W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
     let format_tag = try!(determine_format_tag(codec));
     let bit_depth  = try!(get_bit_depth(codec));
     let data_rate  = audio.sample_rate * audio.channels * (bit_depth / 8) as u32;
     let block_size = audio.channels as u16 * bit_depth / 8;
     let variant = FormatChunk::determine_variant(audio, codec);
-    try!(writer.write_u32::<LittleEndian>(variant as u32));
     match variant {
       WaveFormatExtensible =>
-        try!(writer.write_u16::<LittleEndian>(WAVE_FORMAT_EXTENSIBLE_TAG)),
-      _ => try!(writer.write_u16::<LittleEndian>(format_tag as u16))
+        try!(writer.write_u16::<E>(WAVE_FORMAT_EXTENSIBLE_TAG)),
+      _ => try!(writer.write_u16::<E>(format_tag as u16))
     }
-    try!(writer.write_u16::<LittleEndian>(audio.channels as u16));
-    try!(writer.write_u32::<LittleEndian>(audio.sample_rate as u32));
-    try!(writer.write_u32::<LittleEndian>(data_rate));
-    try!(writer.write_u16::<LittleEndian>(block_size));
-    try!(writer.write_u16::<LittleEndian>(bit_depth));
+    try!(writer.write_u16::<E>(audio.channels as u16));
+    try!(writer.write_u32::<E>(audio.sample_rate as u32));
+    try!(writer.write_u32::<E>(data_rate));
+    try!(writer.write_u16::<E>(block_size));
+    try!(writer.write_u16::<E>(bit_depth));
     match variant {
       WaveFormatPcm => {},
-      WaveFormatNonPcm => try!(writer.write_u16::<LittleEndian>(0)),
+      WaveFormatNonPcm => try!(writer.write_u16::<E>(0)),
       WaveFormatExtensible => {
-        try!(writer.write_u16::<LittleEndian>(22));
+        try!(writer.write_u16::<E>(22));
         // Note this is suppose to be the actual bit depth of the data,
         // the number of bits that may be non-zero, not the container
         // type of the encoded data. Ranges is [1, bit_depth].
-        try!(writer.write_u16::<LittleEndian>(bit_depth));
-        // Speaker position mask, for now only support mono and stereo
-        match audio.channels {
-          1 => try!(writer.write_u32::<LittleEndian>(0x4)),
-          2 => try!(writer.write_u32::<LittleEndian>(0x2 | 0x1)),
-          _ => try!(writer.write_u32::<LittleEndian>(0x0)),
+        try!(writer.write_u16::<E>(bit_depth));
+        // Speaker position mask, from the metadata when it has a speaker
+        // for each channel
+        match audio.metadata.channel_mask {
+          Some(mask) if mask.count_ones() == audio.channels =>
+            try!(writer.write_u32::<E>(mask)),
+          _ => match audio.channels {
+            1 => try!(writer.write_u32::<E>(0x4)),
+            2 => try!(writer.write_u32::<E>(0x2 | 0x1)),
+            _ => try!(writer.write_u32::<E>(0x0)),
+          }
         }
         // GUID
-        try!(writer.write_u16::<LittleEndian>(format_tag as u16));
+        try!(writer.write_u16::<E>(format_tag as u16));
         try!(writer.write(&GUID_SUFFIX));
       }
     }
@@ -191,11 +320,16 @@ impl FormatChunk {
   }
 }
 
-impl Chunk for FormatChunk {
-  fn read(buffer: &[u8]) -> AudioResult<FormatChunk> {
-    let mut format_value: u16 = LittleEndian::read_u16(&buffer[0..2]);
+impl FormatChunk {
+  /// Reads the chunk using the byte order of the file.
+  pub fn read_with<E: ByteOrder>(buffer: &[u8]) -> AudioResult<FormatChunk> {
+    let mut format_value: u16 = E::read_u16(&buffer[0..2]);
+    let mut channel_mask = None;
     if format_value == WAVE_FORMAT_EXTENSIBLE_TAG {
-      format_value = LittleEndian::read_u16(&buffer[24..26])
+      format_value = E::read_u16(&buffer[24..26]);
+      // A mask of zero leaves the speakers unspecified.
+      channel_mask = Some(E::read_u32(&buffer[20..24])).and_then(|mask|
+        if mask == 0 { None } else { Some(mask) });
     }
     let format_tag : FormatTag = 
       match format_value {
@@ -208,36 +342,452 @@ impl Chunk for FormatChunk {
     Ok(
       FormatChunk {
         format_tag:       format_tag,
-        num_channels:     LittleEndian::read_u16(&buffer[2..4]),
-        sample_rate:      LittleEndian::read_u32(&buffer[4..8]),
-        data_rate:        LittleEndian::read_u32(&buffer[8..12]),
-        block_size:       LittleEndian::read_u16(&buffer[12..14]),
-        bit_depth:        LittleEndian::read_u16(&buffer[14..16]),
+        num_channels:     E::read_u16(&buffer[2..4]),
+        sample_rate:      E::read_u32(&buffer[4..8]),
+        data_rate:        E::read_u32(&buffer[8..12]),
+        block_size:       E::read_u16(&buffer[12..14]),
+        bit_depth:        E::read_u16(&buffer[14..16]),
+        channel_mask:     channel_mask
       }
     )
   }
 }
 
+impl Chunk for FormatChunk {
+  #[inline]
+  fn read(buffer: &[u8]) -> AudioResult<FormatChunk> {
+    FormatChunk::read_with::<LittleEndian>(buffer)
+  }
+}
+
 pub struct DataChunk;
 impl DataChunk {
-  pub fn write<W: Write>(writer: &mut W, encoded_data: &[u8]) -> AudioResult<()> {
+  /// Writes the data chunk. A trailing byte is added to odd sized data only
+  /// if other chunks will follow, since it is often omitted from the end of
+  /// files.
+  pub fn write<E: ByteOrder, W: Write>(writer: &mut W, encoded_data: &[u8], padded: bool) -> AudioResult<()> {
     try!(writer.write(DATA));
-    try!(writer.write_u32::<LittleEndian>(encoded_data.len() as u32));
+    try!(writer.write_u32::<E>(encoded_data.len() as u32));
     try!(writer.write_all(encoded_data));
+    if padded && encoded_data.len() % 2 != 0 {
+      try!(writer.write_u8(0));
+    }
+    Ok(())
+  }
+}
+
+/// ACID chunk flags.
+const ACID_ONE_SHOT:  u32 = 0x01;
+const ACID_ROOT_NOTE: u32 = 0x02;
+const ACID_STRETCH:   u32 = 0x04;
+
+/// The WAVE ACID Chunk.
+///
+/// This chunk is written by loop-based applications to describe the tempo,
+/// meter, and key of the audio. The two fields following the root note have
+/// no known purpose. They are not kept in `LoopInfo`, so a chunk created from
+/// `LoopInfo` is given the values commonly written by other applications.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AcidChunk {
+  pub flags:              u32,
+  pub root_note:          u16,
+  pub unknown_1:          u16,
+  pub unknown_2:          f32,
+  pub num_beats:          u32,
+  pub meter_denominator:  u16,
+  pub meter_numerator:    u16,
+  pub tempo:              f32
+}
+
+impl AcidChunk {
+  pub fn from_loop_info(info: &LoopInfo) -> AcidChunk {
+    let mut flags = 0;
+    if info.one_shot {
+      flags |= ACID_ONE_SHOT;
+    }
+    if info.root_note.is_some() {
+      flags |= ACID_ROOT_NOTE;
+    }
+    if info.stretch {
+      flags |= ACID_STRETCH;
+    }
+    AcidChunk {
+      flags:              flags,
+      root_note:          info.root_note.unwrap_or(60) as u16,
+      unknown_1:          0x8000,
+      unknown_2:          0f32,
+      num_beats:          info.beats,
+      meter_denominator:  info.meter_denominator,
+      meter_numerator:    info.meter_numerator,
+      tempo:              info.tempo
+    }
+  }
+
+  pub fn to_loop_info(&self) -> LoopInfo {
+    LoopInfo {
+      one_shot:           self.flags & ACID_ONE_SHOT != 0,
+      stretch:            self.flags & ACID_STRETCH  != 0,
+      root_note:
+        if self.flags & ACID_ROOT_NOTE != 0 {
+          Some(self.root_note.min(127) as u8)
+        } else {
+          None
+        },
+      beats:              self.num_beats,
+      meter_numerator:    self.meter_numerator,
+      meter_denominator:  self.meter_denominator,
+      tempo:              self.tempo
+    }
+  }
+
+  #[inline]
+  pub fn calculate_size() -> u32 {
+    24
+  }
+
+  pub fn write<E: ByteOrder, W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(ACID));
+    try!(writer.write_u32::<E>(Self::calculate_size()));
+    try!(writer.write_u32::<E>(self.flags));
+    try!(writer.write_u16::<E>(self.root_note));
+    try!(writer.write_u16::<E>(self.unknown_1));
+    try!(writer.write_f32::<E>(self.unknown_2));
+    try!(writer.write_u32::<E>(self.num_beats));
+    try!(writer.write_u16::<E>(self.meter_denominator));
+    try!(writer.write_u16::<E>(self.meter_numerator));
+    try!(writer.write_f32::<E>(self.tempo));
     Ok(())
   }
 }
 
+impl AcidChunk {
+  /// Reads the chunk using the byte order of the file.
+  pub fn read_with<E: ByteOrder>(buffer: &[u8]) -> AudioResult<AcidChunk> {
+    if buffer.len() < 24 {
+      return Err(AudioError::Format(
+        "ACID chunk is too small".to_string()
+      ))
+    }
+    Ok(
+      AcidChunk {
+        flags:              E::read_u32(&buffer[0..4]),
+        root_note:          E::read_u16(&buffer[4..6]),
+        unknown_1:          E::read_u16(&buffer[6..8]),
+        unknown_2:          E::read_f32(&buffer[8..12]),
+        num_beats:          E::read_u32(&buffer[12..16]),
+        meter_denominator:  E::read_u16(&buffer[16..18]),
+        meter_numerator:    E::read_u16(&buffer[18..20]),
+        tempo:              E::read_f32(&buffer[20..24])
+      }
+    )
+  }
+}
+
+impl Chunk for AcidChunk {
+  #[inline]
+  fn read(buffer: &[u8]) -> AudioResult<AcidChunk> {
+    AcidChunk::read_with::<LittleEndian>(buffer)
+  }
+}
+
+/// Writes a chunk containing the given data, adding a trailing byte if the
+/// data is of odd size. This is used for chunks whose contents are encoded
+/// separately, such as unrecognized chunks and ID3 tags.
+pub fn write_chunk<E: ByteOrder, W: Write>(writer: &mut W, id: &[u8; 4], data: &[u8]) -> AudioResult<()> {
+  try!(writer.write(id));
+  try!(writer.write_u32::<E>(data.len() as u32));
+  try!(writer.write_all(data));
+  if data.len() % 2 != 0 {
+    try!(writer.write_u8(0));
+  }
+  Ok(())
+}
+
 pub struct FactChunk;
 impl FactChunk {
-  pub fn write<W: Write>(writer: &mut W, audio: &AudioBuffer) -> AudioResult<()> {
+  pub fn write<E: ByteOrder, W: Write>(writer: &mut W, audio: &AudioBuffer) -> AudioResult<()> {
     try!(writer.write(FACT));
-    try!(writer.write_u32::<LittleEndian>(4));
-    try!(writer.write_u32::<LittleEndian>(audio.samples.len() as u32 / audio.channels));
+    try!(writer.write_u32::<E>(4));
+    try!(writer.write_u32::<E>(audio.samples.len() as u32 / audio.channels));
+    Ok(())
+  }
+}
+
+/// A loop within the WAVE Sampler Chunk.
+///
+/// Loop points are sample frame offsets, and the `end` frame is included in
+/// the loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleLoop {
+  pub cue_point_id: u32,
+  pub loop_type:    u32,
+  pub start:        u32,
+  pub end:          u32,
+  pub fraction:     u32,
+  pub play_count:   u32
+}
+
+/// The WAVE Sampler Chunk.
+///
+/// This chunk describes how a sampler should play the audio data, including
+/// its MIDI unity note and any number of loops. Sampler specific data that
+/// may follow the loops is not retained.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplerChunk {
+  pub manufacturer:         u32,
+  pub product:              u32,
+  pub sample_period:        u32,
+  pub midi_unity_note:      u32,
+  pub midi_pitch_fraction:  u32,
+  pub smpte_format:         u32,
+  pub smpte_offset:         u32,
+  pub loops:                Vec<SampleLoop>
+}
+
+impl SamplerChunk {
+  /// Creates a sampler chunk from an `Instrument`. The sustain loop is
+  /// written first, followed by the release loop.
+  pub fn from_instrument(instrument: &Instrument, sample_rate: u32) -> SamplerChunk {
+    // The pitch fraction can only raise the unity note, so negative tuning
+    // is represented relative to the note below. Tuning is limited to less
+    // than a semitone either way.
+    let fine_tune = instrument.fine_tune.max(-99).min(99) as i32;
+    let (unity_note, cents) =
+      if fine_tune < 0 && instrument.root_note > 0 {
+        (instrument.root_note - 1, 100 + fine_tune)
+      }
+      else {
+        (instrument.root_note, fine_tune.max(0))
+      };
+    let loops =
+      instrument.sustain_loop.iter()
+      .chain(instrument.release_loop.iter())
+      .enumerate()
+      .map(|(id, lp)| SampleLoop {
+        cue_point_id: id as u32,
+        loop_type:
+          match lp.mode {
+            LoopMode::Forward     => 0,
+            LoopMode::Alternating => 1,
+            LoopMode::Backward    => 2
+          },
+        start:        lp.start,
+        end:          lp.end,
+        fraction:     0,
+        play_count:   lp.play_count
+      })
+      .collect();
+    SamplerChunk {
+      manufacturer:         0,
+      product:              0,
+      sample_period:
+        if sample_rate == 0 { 0 } else { 1_000_000_000 / sample_rate },
+      midi_unity_note:      unity_note as u32,
+      midi_pitch_fraction:  ((cents as u64 * 0x1_0000_0000) / 100) as u32,
+      smpte_format:         0,
+      smpte_offset:         0,
+      loops:                loops
+    }
+  }
+
+  /// Returns the fine tuning of the unity note in cents.
+  pub fn fine_tune(&self) -> i8 {
+    ((self.midi_pitch_fraction as u64 * 100 + 0x8000_0000) >> 32) as i8
+  }
+
+  /// Returns the sampler loops as `Loop`s. Manufacturer specific loop types
+  /// are treated as forward loops.
+  pub fn to_loops(&self) -> Vec<Loop> {
+    self.loops.iter().map(|lp| Loop {
+      mode:
+        match lp.loop_type {
+          1 => LoopMode::Alternating,
+          2 => LoopMode::Backward,
+          _ => LoopMode::Forward
+        },
+      start:      lp.start,
+      end:        lp.end,
+      play_count: lp.play_count
+    })
+    .collect()
+  }
+
+  #[inline]
+  pub fn calculate_size(&self) -> u32 {
+    36 + 24 * self.loops.len() as u32
+  }
+
+  pub fn write<E: ByteOrder, W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(SMPL));
+    try!(writer.write_u32::<E>(self.calculate_size()));
+    try!(writer.write_u32::<E>(self.manufacturer));
+    try!(writer.write_u32::<E>(self.product));
+    try!(writer.write_u32::<E>(self.sample_period));
+    try!(writer.write_u32::<E>(self.midi_unity_note));
+    try!(writer.write_u32::<E>(self.midi_pitch_fraction));
+    try!(writer.write_u32::<E>(self.smpte_format));
+    try!(writer.write_u32::<E>(self.smpte_offset));
+    try!(writer.write_u32::<E>(self.loops.len() as u32));
+    // No sampler specific data
+    try!(writer.write_u32::<E>(0));
+    for lp in self.loops.iter() {
+      try!(writer.write_u32::<E>(lp.cue_point_id));
+      try!(writer.write_u32::<E>(lp.loop_type));
+      try!(writer.write_u32::<E>(lp.start));
+      try!(writer.write_u32::<E>(lp.end));
+      try!(writer.write_u32::<E>(lp.fraction));
+      try!(writer.write_u32::<E>(lp.play_count));
+    }
+    Ok(())
+  }
+}
+
+impl SamplerChunk {
+  /// Reads the chunk using the byte order of the file.
+  pub fn read_with<E: ByteOrder>(buffer: &[u8]) -> AudioResult<SamplerChunk> {
+    if buffer.len() < 36 {
+      return Err(AudioError::Format(
+        "Sampler chunk is too small".to_string()
+      ))
+    }
+    let num_loops = E::read_u32(&buffer[28..32]) as usize;
+    if buffer.len() < 36 + 24 * num_loops {
+      return Err(AudioError::Format(
+        "Sampler chunk is too small for its number of loops".to_string()
+      ))
+    }
+    let mut loops = Vec::with_capacity(num_loops);
+    for i in 0..num_loops {
+      let lp = &buffer[36 + 24 * i .. 60 + 24 * i];
+      loops.push(SampleLoop {
+        cue_point_id: E::read_u32(&lp[0..4]),
+        loop_type:    E::read_u32(&lp[4..8]),
+        start:        E::read_u32(&lp[8..12]),
+        end:          E::read_u32(&lp[12..16]),
+        fraction:     E::read_u32(&lp[16..20]),
+        play_count:   E::read_u32(&lp[20..24])
+      });
+    }
+    Ok(
+      SamplerChunk {
+        manufacturer:         E::read_u32(&buffer[0..4]),
+        product:              E::read_u32(&buffer[4..8]),
+        sample_period:        E::read_u32(&buffer[8..12]),
+        midi_unity_note:      E::read_u32(&buffer[12..16]),
+        midi_pitch_fraction:  E::read_u32(&buffer[16..20]),
+        smpte_format:         E::read_u32(&buffer[20..24]),
+        smpte_offset:         E::read_u32(&buffer[24..28]),
+        loops:                loops
+      }
+    )
+  }
+}
+
+impl Chunk for SamplerChunk {
+  #[inline]
+  fn read(buffer: &[u8]) -> AudioResult<SamplerChunk> {
+    SamplerChunk::read_with::<LittleEndian>(buffer)
+  }
+}
+
+/// The WAVE Instrument Chunk.
+///
+/// This chunk is 7 bytes long, so a padding byte always follows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstrumentChunk {
+  pub unshifted_note: u8,
+  pub fine_tune:      i8,
+  pub gain:           i8,
+  pub low_note:       u8,
+  pub high_note:      u8,
+  pub low_velocity:   u8,
+  pub high_velocity:  u8
+}
+
+impl InstrumentChunk {
+  pub fn from_instrument(instrument: &Instrument) -> InstrumentChunk {
+    InstrumentChunk {
+      unshifted_note: instrument.root_note,
+      fine_tune:      instrument.fine_tune,
+      gain:           instrument.gain,
+      low_note:       instrument.low_note,
+      high_note:      instrument.high_note,
+      low_velocity:   instrument.low_velocity,
+      high_velocity:  instrument.high_velocity
+    }
+  }
+
+  #[inline]
+  pub fn calculate_size() -> u32 {
+    7
+  }
+
+  pub fn write<E: ByteOrder, W: Write>(&self, writer: &mut W) -> AudioResult<()> {
+    try!(writer.write(INST));
+    try!(writer.write_u32::<E>(Self::calculate_size()));
+    try!(writer.write_u8(self.unshifted_note));
+    try!(writer.write_i8(self.fine_tune));
+    try!(writer.write_i8(self.gain));
+    try!(writer.write_u8(self.low_note));
+    try!(writer.write_u8(self.high_note));
+    try!(writer.write_u8(self.low_velocity));
+    try!(writer.write_u8(self.high_velocity));
+    // Chunks must be of even size
+    try!(writer.write_u8(0));
     Ok(())
   }
 }
 
+impl Chunk for InstrumentChunk {
+  fn read(buffer: &[u8]) -> AudioResult<InstrumentChunk> {
+    if buffer.len() < 7 {
+      return Err(AudioError::Format(
+        "Instrument chunk is too small".to_string()
+      ))
+    }
+    Ok(
+      InstrumentChunk {
+        unshifted_note: buffer[0],
+        fine_tune:      buffer[1] as i8,
+        gain:           buffer[2] as i8,
+        low_note:       buffer[3],
+        high_note:      buffer[4],
+        low_velocity:   buffer[5],
+        high_velocity:  buffer[6]
+      }
+    )
+  }
+}
+
+/// Combines the sampler and instrument chunks read from a file into an
+/// `Instrument`. Values in the instrument chunk take precedence since it
+/// supports negative tuning, and the first two sampler loops are used as the
+/// sustain and release loops respectively.
+pub fn to_instrument(smpl: Option<&SamplerChunk>,
+                     inst: Option<&InstrumentChunk>) -> Option<Instrument> {
+  if smpl.is_none() && inst.is_none() {
+    return None;
+  }
+  let mut instrument = Instrument::default();
+  if let Some(smpl) = smpl {
+    instrument.root_note = smpl.midi_unity_note.min(127) as u8;
+    instrument.fine_tune = smpl.fine_tune();
+    let mut loops = smpl.to_loops().into_iter();
+    instrument.sustain_loop = loops.next();
+    instrument.release_loop = loops.next();
+  }
+  if let Some(inst) = inst {
+    instrument.root_note     = inst.unshifted_note;
+    instrument.fine_tune     = inst.fine_tune;
+    instrument.gain          = inst.gain;
+    instrument.low_note      = inst.low_note;
+    instrument.high_note     = inst.high_note;
+    instrument.low_velocity  = inst.low_velocity;
+    instrument.high_velocity = inst.high_velocity;
+  }
+  Some(instrument)
+}
+
 // Crate generates too many warnings for dead code, no need to include it if
 // a full speaker_position implementation hasn't been created yet.
 //
@@ -300,4 +850,51 @@ impl FactChunk {
 //                             | SPEAKER_FRONT_LEFT_OF_CENTER.bits
 //                             | SPEAKER_FRONT_RIGHT_OF_CENTER.bits
 //   }
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod sampler {
+  use super::*;
+  use byteorder::LittleEndian;
+  use metadata::Instrument;
+  use traits::Chunk;
+
+  #[test]
+  fn pitch_fraction() {
+    let mut instrument = Instrument::default();
+    instrument.fine_tune = 50;
+    let smpl = SamplerChunk::from_instrument(&instrument, 44100);
+    assert_eq!(60, smpl.midi_unity_note);
+    assert_eq!(0x8000_0000, smpl.midi_pitch_fraction);
+    assert_eq!(22675, smpl.sample_period);
+
+    // Negative tuning is relative to the note below.
+    instrument.fine_tune = -25;
+    let smpl = SamplerChunk::from_instrument(&instrument, 44100);
+    assert_eq!(59, smpl.midi_unity_note);
+    assert_eq!(75, smpl.fine_tune());
+
+    // Tuning beyond a semitone is clamped.
+    instrument.fine_tune = -128;
+    let smpl = SamplerChunk::from_instrument(&instrument, 44100);
+    assert_eq!(59, smpl.midi_unity_note);
+    assert_eq!(1, smpl.fine_tune());
+    instrument.fine_tune = 127;
+    let smpl = SamplerChunk::from_instrument(&instrument, 44100);
+    assert_eq!(60, smpl.midi_unity_note);
+    assert_eq!(99, smpl.fine_tune());
+  }
+
+  #[test]
+  fn read_write() {
+    let mut instrument = Instrument::default();
+    instrument.sustain_loop = Some(Loop {
+      mode: LoopMode::Backward, start: 4, end: 8, play_count: 3
+    });
+    let smpl = SamplerChunk::from_instrument(&instrument, 48000);
+    let mut bytes = Vec::new();
+    smpl.write::<LittleEndian, _>(&mut bytes).unwrap();
+    assert_eq!(8 + 60, bytes.len());
+    assert_eq!(smpl, SamplerChunk::read(&bytes[8..]).unwrap());
+    assert_eq!(vec![instrument.sustain_loop.unwrap()], smpl.to_loops());
+  }
+}
@@ -1,13 +1,15 @@
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use buffer::*;
-use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 use codecs::Codec;
 use codecs::Codec::*;
 use error::*;
+use id3::Id3Tag;
+use metadata::{Metadata, RiffWrapper, UnknownChunk};
 use sample::*;
 use sample::SampleOrder::*;
 use traits::{Chunk, Container};
-use wave::{RIFF, WAVE, FMT, FACT, DATA};
+use wave::{RIFF, RIFX, WAVE, FMT, FACT, DATA, ACID, AXML, ID3, ID3_ALT, INST, IXML, SMPL};
 use wave::chunks::*;
 use wave::chunks::WaveChunk::*;
 
@@ -20,7 +22,8 @@ pub struct WaveContainer {
   pub channels:     u32,
   pub block_size:   u32,
   pub order:        SampleOrder,
-  pub samples:      Vec<Sample>
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
 }
 
 impl Container for WaveContainer {
@@ -28,13 +31,125 @@ impl Container for WaveContainer {
     // Read and validate riff header
     let mut riff_header: [u8; 12] = [0u8; 12];
     try!(reader.read(&mut riff_header));
-    if &riff_header[0..4]  != RIFF
-    || &riff_header[8..12] != WAVE {
+    if &riff_header[8..12] != WAVE {
       return Err(AudioError::Format(
         "Not valid WAVE".to_string()
       ));
     }
-    let file_size : u32 = LittleEndian::read_u32(&riff_header[4..8]) - 4;
+    match &[riff_header[0], riff_header[1], riff_header[2], riff_header[3]] {
+      RIFF => WaveContainer::open_with::<LittleEndian, R>(reader, &riff_header),
+      RIFX => WaveContainer::open_with::<BigEndian, R>(reader, &riff_header),
+      _    =>
+        Err(AudioError::Format(
+          "Not valid WAVE".to_string()
+        ))
+    }
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    // Audio restored from a compressed WAVE file is written with the header
+    // and trailer of the original file, as long as they still describe it.
+    if let Some(bytes) = WaveContainer::unwrap_riff(audio, codec) {
+      try!(writer.write_all(&bytes));
+      return Ok(());
+    }
+    // Audio encoded with big-endian codecs is written as RIFX.
+    match codec {
+      LPCM_I16_BE |
+      LPCM_I24_BE |
+      LPCM_I32_BE |
+      LPCM_F32_BE |
+      LPCM_F64_BE => WaveContainer::create_with::<BigEndian, W>(writer, audio, codec),
+      _           => WaveContainer::create_with::<LittleEndian, W>(writer, audio, codec)
+    }
+  }
+}
+
+impl WaveContainer {
+  /// Reads the WAVE file made of a RIFF header and trailer surrounding the
+  /// given audio data.
+  pub fn read_wrapper(wrapper: &RiffWrapper, data: &[u8]) -> AudioResult<WaveContainer> {
+    let mut bytes = Vec::with_capacity(wrapper.header.len() + data.len() + wrapper.trailer.len());
+    bytes.extend_from_slice(&wrapper.header);
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(&wrapper.trailer);
+    WaveContainer::open(&mut Cursor::new(bytes))
+  }
+
+  /// Returns the codec of the audio data described by the format chunk of a
+  /// RIFF header, if it can be read.
+  pub fn wrapper_codec(wrapper: &RiffWrapper) -> Option<Codec> {
+    let header = &wrapper.header;
+    if header.len() < 12 || &header[8..12] != WAVE {
+      return None;
+    }
+    match &[header[0], header[1], header[2], header[3]] {
+      RIFF => read_format_codec::<LittleEndian>(header),
+      RIFX => read_format_codec::<BigEndian>(header),
+      _    => None
+    }
+  }
+
+  /// Returns the RIFF header and trailer of the WAVE file the audio is
+  /// saved as using the given codec.
+  pub fn wrapper(audio: &AudioBuffer, codec: Codec) -> AudioResult<RiffWrapper> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(WaveContainer::create(&mut bytes, audio, codec));
+    let data =
+      match &[bytes[0], bytes[1], bytes[2], bytes[3]] {
+        RIFF => find_chunk::<LittleEndian>(&bytes, DATA),
+        _    => find_chunk::<BigEndian>(&bytes, DATA)
+      };
+    match data {
+      Some((start, size)) =>
+        Ok(RiffWrapper {
+          header:   bytes[..start].to_vec(),
+          trailer:  bytes[start + size ..].to_vec()
+        }),
+      None =>
+        Err(AudioError::Format(
+          "File is not valid WAVE (Missing required Data chunk)".to_string()
+        ))
+    }
+  }
+
+  /// Returns the WAVE file given by the RIFF header and trailer stored with
+  /// the audio, with the audio data encoded using the codec. `None` is
+  /// returned unless the file has the same format, sample count, and
+  /// metadata as the audio.
+  fn unwrap_riff(audio: &AudioBuffer, codec: Codec) -> Option<Vec<u8>> {
+    let wrapper =
+      match audio.metadata.riff {
+        Some(ref wrapper) => wrapper,
+        None              => return None
+      };
+    let data = match ::codecs::encode(audio, codec) {
+      Ok(data) => data,
+      Err(_)   => return None
+    };
+    let container = match WaveContainer::read_wrapper(wrapper, &data) {
+      Ok(container) => container,
+      Err(_)        => return None
+    };
+    let mut metadata = audio.metadata.clone();
+    metadata.riff = None;
+    if container.codec         == codec
+       && container.channels    == audio.channels
+       && container.sample_rate == audio.sample_rate
+       && container.samples.len() == audio.samples.len()
+       && container.metadata    == metadata {
+      let mut bytes = wrapper.header.clone();
+      bytes.extend_from_slice(&data);
+      bytes.extend_from_slice(&wrapper.trailer);
+      Some(bytes)
+    } else {
+      None
+    }
+  }
+
+  /// Reads the chunks following the riff header using the byte order of the
+  /// file.
+  fn open_with<E: RiffByteOrder, R: Read + Seek>(reader: &mut R, riff_header: &[u8]) -> AudioResult<WaveContainer> {
+    let file_size : u32 = E::read_u32(&riff_header[4..8]) - 4;
     let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0u8; file_size as usize]);
     try!(reader.read(buffer.get_mut()));
 
@@ -47,34 +162,38 @@ impl Container for WaveContainer {
         channels:       1u32,
         block_size:     0u32,
         order:          SampleOrder::Interleaved,
-        samples:        Vec::with_capacity(1024)
+        samples:        Vec::with_capacity(1024),
+        metadata:       Metadata::default()
       };
     let mut chunk_header      : [u8; 8] = [0u8; 8];
     let mut read_fmt_chunk    : bool    = false;
     let mut read_fact_chunk   : bool    = false;
     let mut read_data_chunk   : bool    = false;
+    let mut smpl_chunk        : Option<SamplerChunk>    = None;
+    let mut inst_chunk        : Option<InstrumentChunk> = None;
     while buffer.position() < file_size as u64 {
       try!(buffer.read(&mut chunk_header));
       let chunk_size: usize = 
-        LittleEndian::read_u32(&chunk_header[4..8]) as usize;
+        E::read_u32(&chunk_header[4..8]) as usize;
       let pos: usize = buffer.position() as usize;
       match identify(&chunk_header[0..4]).ok() {
         Some(Format) => {
           let chunk_bytes = &(buffer.get_ref()[pos .. pos + chunk_size]);
-          let fmt_chunk = try!(FormatChunk::read(&chunk_bytes));
+          let fmt_chunk = try!(FormatChunk::read_with::<E>(&chunk_bytes));
           container.bit_depth       = fmt_chunk.bit_depth    as u32;
           container.sample_rate     = fmt_chunk.sample_rate;
           container.channels        = fmt_chunk.num_channels as u32;
           container.block_size      = fmt_chunk.block_size   as u32;
+          container.metadata.channel_mask = fmt_chunk.channel_mask;
           container.order           =
             if container.channels == 1 {
               SampleOrder::Mono
             } else {
               SampleOrder::Interleaved
             };
-          container.codec           = 
+          container.codec           = E::codec(
             try!(determine_codec(fmt_chunk.format_tag,
-                                 fmt_chunk.bit_depth));
+                                 fmt_chunk.bit_depth)));
           read_fmt_chunk            = true;
           if fmt_chunk.format_tag == FormatTag::Pcm {
             // Don't need to check for fact chunk if PCM
@@ -95,12 +214,75 @@ impl Container for WaveContainer {
             ))
           }
           let chunk_bytes   = &(buffer.get_ref()[pos .. pos + chunk_size]);
-          container.samples = try!(read_codec(chunk_bytes, container.codec));
+          container.samples = try!(read_codec::<E>(chunk_bytes, container.codec));
           read_data_chunk   = true;
         },
-        None => {}
+        Some(Sampler) => {
+          // Sampler chunks that cannot be read are kept as unrecognized
+          // chunks.
+          let chunk_end   = (pos + chunk_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match SamplerChunk::read_with::<E>(&chunk_bytes) {
+            Ok(chunk) => smpl_chunk = Some(chunk),
+            Err(_)    => container.metadata.chunks.push(
+              unknown_chunk::<E>(&chunk_header, chunk_bytes, read_data_chunk)
+            )
+          }
+        },
+        Some(Instrument) => {
+          let chunk_end   = (pos + chunk_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match InstrumentChunk::read(&chunk_bytes) {
+            Ok(chunk) => inst_chunk = Some(chunk),
+            Err(_)    => container.metadata.chunks.push(
+              unknown_chunk::<E>(&chunk_header, chunk_bytes, read_data_chunk)
+            )
+          }
+        },
+        Some(Id3) => {
+          // Tags that cannot be decoded are kept as unrecognized chunks.
+          let chunk_end   = (pos + chunk_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match Id3Tag::read(&chunk_bytes) {
+            Ok(tag) => container.metadata.id3 = Some(tag),
+            Err(_)  => container.metadata.chunks.push(
+              unknown_chunk::<E>(&chunk_header, chunk_bytes, read_data_chunk)
+            )
+          }
+        },
+        Some(Acid) => {
+          // Loop chunks that cannot be read are kept as unrecognized chunks.
+          let chunk_end   = (pos + chunk_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          match AcidChunk::read_with::<E>(&chunk_bytes) {
+            Ok(chunk) => container.metadata.loop_info = Some(chunk.to_loop_info()),
+            Err(_)    => container.metadata.chunks.push(
+              unknown_chunk::<E>(&chunk_header, chunk_bytes, read_data_chunk)
+            )
+          }
+        },
+        Some(Ixml) => {
+          let chunk_end   = (pos + chunk_size).min(buffer.get_ref().len());
+          container.metadata.ixml =
+            Some(read_xml(&buffer.get_ref()[pos .. chunk_end]));
+        },
+        Some(Axml) => {
+          let chunk_end   = (pos + chunk_size).min(buffer.get_ref().len());
+          container.metadata.axml =
+            Some(read_xml(&buffer.get_ref()[pos .. chunk_end]));
+        },
+        None => {
+          let chunk_end   = (pos + chunk_size).min(buffer.get_ref().len());
+          let chunk_bytes = &(buffer.get_ref()[pos .. chunk_end]);
+          container.metadata.chunks.push(
+            unknown_chunk::<E>(&chunk_header, chunk_bytes, read_data_chunk)
+          );
+        }
       }
-      try!(buffer.seek(SeekFrom::Current(chunk_size as i64)));
+      // RIFF chunks are word aligned, so odd sized chunks are followed by a
+      // padding byte that is not included in the chunk size.
+      let padding = chunk_size % 2;
+      try!(buffer.seek(SeekFrom::Current((chunk_size + padding) as i64)));
     }
 
     // Check if required chunks were read
@@ -120,39 +302,147 @@ impl Container for WaveContainer {
         "File is not valid WAVE (Missing required Data chunk)".to_string()
       ))
     }
+    container.metadata.instrument =
+      to_instrument(smpl_chunk.as_ref(), inst_chunk.as_ref());
     Ok(container)
   }
-  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+
+  /// Writes the audio using the given byte order, which must match the byte
+  /// order of the codec.
+  fn create_with<E: RiffByteOrder, W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
     // Determine if codec is supported by container and if data is non-PCM.
-    let data_non_pcm: bool = try!(is_supported(codec));
+    let format_codec = E::format_codec(codec);
+    let data_non_pcm: bool = try!(is_supported(format_codec));
     // Encode audio samples using codec.
-    let data: Vec<u8> = try!(write_codec(audio, codec));
-    let fmt_chunk_size = FormatChunk::calculate_size(audio, codec);
+    let data: Vec<u8> = try!(write_codec::<E>(audio, codec));
+    let fmt_chunk_size = FormatChunk::calculate_size(audio, format_codec);
     let mut total_bytes: u32 = 12 + (8 + fmt_chunk_size)
                                   + (8 + data.len() as u32);
     // Files encoded with non-PCM data must include a fact chunk.
     if data_non_pcm {
       total_bytes += 12;
     }
+    // Sampler settings are written using both the sampler and instrument
+    // chunks, where the instrument chunk includes a padding byte.
+    let sampler_chunks =
+      audio.metadata.instrument.as_ref().map(|instrument|
+        (SamplerChunk::from_instrument(instrument, audio.sample_rate),
+         InstrumentChunk::from_instrument(instrument))
+      );
+    if let Some((ref smpl_chunk, _)) = sampler_chunks {
+      total_bytes += (8 + smpl_chunk.calculate_size())
+                   + (8 + InstrumentChunk::calculate_size() + 1);
+    }
+    let acid_chunk = audio.metadata.loop_info.as_ref().map(AcidChunk::from_loop_info);
+    if acid_chunk.is_some() {
+      total_bytes += 8 + AcidChunk::calculate_size();
+    }
+    // Metadata chunks are encoded before writing so their sizes are known.
+    // Unrecognized chunks read from a file in the same byte order are
+    // written back in order, either before or after the data chunk, and ID3
+    // tags and XML documents are written after the data chunk.
+    let mut chunks_before_data: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    let mut chunks_after_data:  Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    for chunk in audio.metadata.chunks.iter()
+                 .filter(|chunk| chunk.format == E::chunk_format()) {
+      if chunk.after_data {
+        chunks_after_data.push((chunk.id, chunk.data.clone()));
+      }
+      else {
+        chunks_before_data.push((chunk.id, chunk.data.clone()));
+      }
+    }
+    if let Some(ref tag) = audio.metadata.id3 {
+      let mut tag_bytes = Vec::new();
+      try!(tag.write(&mut tag_bytes));
+      chunks_after_data.push((*ID3, tag_bytes));
+    }
+    if let Some(ref xml) = audio.metadata.ixml {
+      chunks_after_data.push((*IXML, xml.as_bytes().to_vec()));
+    }
+    if let Some(ref xml) = audio.metadata.axml {
+      chunks_after_data.push((*AXML, xml.as_bytes().to_vec()));
+    }
+    for &(_, ref chunk_data) in
+        chunks_before_data.iter().chain(chunks_after_data.iter()) {
+      let size = chunk_data.len() as u32;
+      total_bytes += 8 + size + size % 2;
+    }
+    let data_padded = !chunks_after_data.is_empty();
+    if data_padded {
+      total_bytes += data.len() as u32 % 2;
+    }
 
     // Write the riff header to the writer.
-    try!(writer.write(RIFF));
-    try!(writer.write_u32::<LittleEndian>(total_bytes - 8));
+    try!(writer.write(E::riff_id()));
+    try!(writer.write_u32::<E>(total_bytes - 8));
     try!(writer.write(WAVE));
     // Write fmt chunk to the writer.
-    try!(FormatChunk::write(writer, audio, codec));
+    try!(FormatChunk::write::<E, W>(writer, audio, format_codec));
     // Write fact chunk to writer if data is non-PCM
     if data_non_pcm {
-      try!(FactChunk::write(writer, audio));
+      try!(FactChunk::write::<E, W>(writer, audio));
+    }
+    // Write sampler and instrument chunks if the audio has sampler settings
+    if let Some((ref smpl_chunk, ref inst_chunk)) = sampler_chunks {
+      try!(smpl_chunk.write::<E, W>(writer));
+      try!(inst_chunk.write::<E, W>(writer));
+    }
+    // Write acid chunk if the audio has loop information
+    if let Some(ref acid_chunk) = acid_chunk {
+      try!(acid_chunk.write::<E, W>(writer));
+    }
+    for &(ref id, ref chunk_data) in chunks_before_data.iter() {
+      try!(write_chunk::<E, W>(writer, id, chunk_data));
     }
     // Write data chunk to the writer.
-    try!(DataChunk::write(writer, &data));
+    try!(DataChunk::write::<E, W>(writer, &data, data_padded));
+    for &(ref id, ref chunk_data) in chunks_after_data.iter() {
+      try!(write_chunk::<E, W>(writer, id, chunk_data));
+    }
     Ok(())
   }
 }
 
 // Private functions
 
+/// Creates an `UnknownChunk` from a chunk header and the chunk data, read
+/// in the given byte order.
+#[inline]
+fn unknown_chunk<E: RiffByteOrder>(header: &[u8], data: &[u8], after_data: bool) -> UnknownChunk {
+  UnknownChunk {
+    format:     E::chunk_format(),
+    id:         [header[0], header[1], header[2], header[3]],
+    data:       data.to_vec(),
+    after_data: after_data
+  }
+}
+
+/// Returns the position and size of the data of the first chunk with the
+/// given identifier, where the size may extend past the given bytes.
+fn find_chunk<E: RiffByteOrder>(bytes: &[u8], id: &[u8; 4]) -> Option<(usize, usize)> {
+  let mut position = 12;
+  while position + 8 <= bytes.len() {
+    let size = E::read_u32(&bytes[position + 4 .. position + 8]) as usize;
+    if &bytes[position .. position + 4] == id {
+      return Some((position + 8, size));
+    }
+    position += 8 + size + size % 2;
+  }
+  None
+}
+
+/// Returns the codec described by the format chunk of a RIFF header.
+fn read_format_codec<E: RiffByteOrder>(header: &[u8]) -> Option<Codec> {
+  match find_chunk::<E>(header, FMT) {
+    Some((start, size)) if size >= 16 && start + size <= header.len() =>
+      FormatChunk::read_with::<E>(&header[start .. start + size]).ok()
+        .and_then(|fmt| determine_codec(fmt.format_tag, fmt.bit_depth).ok())
+        .map(E::codec),
+    _ => None
+  }
+}
+
 /// This function reads the four byte identifier for each WAVE chunk.
 #[inline]
 fn identify(bytes: &[u8]) -> AudioResult<WaveChunk> {
@@ -160,6 +450,13 @@ fn identify(bytes: &[u8]) -> AudioResult<WaveChunk> {
     FMT  => Ok(Format),
     FACT => Ok(Fact),
     DATA => Ok(Data),
+    SMPL => Ok(Sampler),
+    INST => Ok(Instrument),
+    ID3  |
+    ID3_ALT => Ok(Id3),
+    IXML => Ok(Ixml),
+    AXML => Ok(Axml),
+    ACID => Ok(Acid),
     err @ _ => 
       Err(AudioError::Format(
         format!("Do not recognize WAVE chunk with identifier {:?}", err)
@@ -167,49 +464,18 @@ fn identify(bytes: &[u8]) -> AudioResult<WaveChunk> {
   }
 }
 
-/// Determines if codec is supported by container. Since WAVE encoding also
-/// depends on whether the codec is integer-based PCM, the return value
-/// represeents if the codec as such.
-fn is_supported(codec: Codec) -> AudioResult<bool> {
-  match codec {
-    LPCM_U8      |
-    LPCM_I16_LE  |
-    LPCM_I24_LE  |
-    LPCM_I32_LE  => Ok(false),
-    LPCM_F32_LE  |
-    LPCM_F64_LE  |
-    G711_ALAW    |
-    G711_ULAW    => Ok(true),
-    c @ _ =>
-      return Err(AudioError::Unsupported(
-        format!("Wave does not support the {:?} codec", c)
-      ))
-  }
-}
-
-/// Returns the `Codec` used by the read audio attributes.
-fn determine_codec(format_tag: FormatTag, bit_depth: u16) -> AudioResult<Codec> {
-  match (format_tag, bit_depth) {
-    (FormatTag::Pcm,    8) => Ok(LPCM_U8),
-    (FormatTag::Pcm,   16) => Ok(LPCM_I16_LE),
-    (FormatTag::Pcm,   24) => Ok(LPCM_I24_LE),
-    (FormatTag::Pcm,   32) => Ok(LPCM_I32_LE),
-    (FormatTag::ALaw,   8) => Ok(G711_ALAW),
-    (FormatTag::MuLaw,  8) => Ok(G711_ULAW),
-    (FormatTag::Float, 32) => Ok(LPCM_F32_LE),
-    (FormatTag::Float, 64) => Ok(LPCM_F64_LE),
-    (_, _) =>
-      return Err(AudioError::Unsupported(
-        "Audio encoded with unsupported codec".to_string()
-      ))
-  }
+/// Reads an XML document from a chunk. Some applications pad the document
+/// with null bytes, which are removed.
+#[inline]
+fn read_xml(bytes: &[u8]) -> String {
+  String::from_utf8_lossy(bytes).trim_right_matches('\u{0}').to_string()
 }
 
 /// Returns samples read using the given codec. If the container does not
 /// support a codec, an error is returned.
 #[inline]
-fn read_codec(bytes: &[u8], codec: Codec) -> AudioResult<Vec<Sample>> {
-  match is_supported(codec) {
+fn read_codec<E: RiffByteOrder>(bytes: &[u8], codec: Codec) -> AudioResult<Vec<Sample>> {
+  match is_supported(E::format_codec(codec)) {
     Ok(_)  => ::codecs::decode(bytes, codec),
     Err(e) => Err(e)
   }
@@ -218,8 +484,8 @@ fn read_codec(bytes: &[u8], codec: Codec) -> AudioResult<Vec<Sample>> {
 /// Returns samples as bytes created using the given codec. If the container
 /// does not support a codec, an error is returned.
 #[inline]
-fn write_codec(audio: &AudioBuffer, codec: Codec) -> AudioResult<Vec<u8>> {
-  match is_supported(codec) {
+fn write_codec<E: RiffByteOrder>(audio: &AudioBuffer, codec: Codec) -> AudioResult<Vec<u8>> {
+  match is_supported(E::format_codec(codec)) {
     Ok(_)  => ::codecs::encode(audio, codec),
     Err(e) => Err(e)
   }
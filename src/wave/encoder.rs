@@ -23,10 +23,14 @@ impl<'w, W> Encoder<'w, W> where W: Write {
 
 impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
   /// Creates and writes a `WaveContainer` to the included writer. The audio
-  /// is encoded to standard 16-bit, uncompressed LPCM audio.
+  /// is encoded to standard 16-bit, uncompressed LPCM audio, unless it was
+  /// restored from a compressed WAVE file, whose codec is used instead.
   #[inline]
   fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
-    WaveContainer::create(&mut self.writer, audio, LPCM_I16_LE)
+    let codec = audio.metadata.riff.as_ref()
+                .and_then(WaveContainer::wrapper_codec)
+                .unwrap_or(LPCM_I16_LE);
+    WaveContainer::create(&mut self.writer, audio, codec)
   }
   /// Creates and writes a `WaveContainer` using the provided `SampleFormat`
   /// to the included writer. This is how audio can be encoded to different
@@ -4,13 +4,16 @@
 //! file container format that uses chunks to store data. All integers are stored
 //! in little-endian format, but identifier bytes are in ASCII, big-endian.
 //!
+//! The RIFX variant uses the same structure with big-endian integers and
+//! samples. Audio is written as RIFX when encoded with a big-endian codec.
+//!
 //! References
 //! - [McGill University](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/WAVE.html)
 //! - [WAVE Spec](http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/Docs/riffmci.pdf)
 //! - [ksmedia.h](http://www-mmsp.ece.mcgill.ca/documents/audioformats/wave/Docs/ksmedia.h)
 
-mod container;
-mod chunks;
+pub mod container;
+pub mod chunks;
 pub mod decoder;
 pub mod encoder;
 
@@ -19,10 +22,19 @@ pub use wave::encoder::Encoder as Encoder;
 
 /// WAVE chunk identifiers.
 const RIFF: &'static [u8; 4] = b"RIFF";
+const RIFX: &'static [u8; 4] = b"RIFX";
 const WAVE: &'static [u8; 4] = b"WAVE";
 const FMT:  &'static [u8; 4] = b"fmt ";
 const DATA: &'static [u8; 4] = b"data";
 const FACT: &'static [u8; 4] = b"fact";
+const SMPL: &'static [u8; 4] = b"smpl";
+const INST: &'static [u8; 4] = b"inst";
+const ID3:  &'static [u8; 4] = b"id3 ";
+const IXML: &'static [u8; 4] = b"iXML";
+const AXML: &'static [u8; 4] = b"axml";
+const ACID: &'static [u8; 4] = b"acid";
+/// Alternate identifier used by some applications for ID3 tags.
+const ID3_ALT: &'static [u8; 4] = b"ID3 ";
 
 #[cfg(test)]
 mod io {
@@ -308,8 +320,11 @@ mod io {
     }
   }
   mod wavex {
+    use std::io::Cursor;
     use std::path::Path;
     use ::audio;
+    use ::audio::AudioFormat;
+    use ::codecs::Codec::LPCM_I16_LE;
 
     #[test]
     fn read_wave_extensible_format() {
@@ -326,5 +341,297 @@ mod io {
         assert_eq!(wave_sample, wavex_sample);
       }
     }
+
+    #[test]
+    fn channel_mask() {
+      // The speakers of 5.1 audio are kept when it is written again.
+      let mut audio = audio::open(Path::new("tests/wavpack/sine-5.1.wav")).unwrap();
+      assert_eq!(Some(0x3F), audio.metadata.channel_mask);
+      audio.metadata.riff = None;
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write_as(&mut bytes, &audio, AudioFormat::WAVE, LPCM_I16_LE).unwrap();
+      assert_eq!(&[0x3F, 0, 0, 0], &bytes[40..44]);
+      let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+      assert_eq!(Some(0x3F), verify.metadata.channel_mask);
+      // A mask without a speaker for each channel is not written.
+      audio.metadata.channel_mask = Some(0x7);
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write_as(&mut bytes, &audio, AudioFormat::WAVE, LPCM_I16_LE).unwrap();
+      assert_eq!(&[0, 0, 0, 0], &bytes[40..44]);
+    }
+  }
+  mod rifx {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use std::path::{Path, PathBuf};
+    use ::audio;
+    use ::audio::AudioFormat;
+    use ::codecs::Codec::*;
+    use ::metadata::{Instrument, LoopInfo};
+
+    #[test]
+    fn codecs_eq() {
+      let mut path = PathBuf::from("tests");
+      path.push("wav");
+      path.push("empty.wav");
+      let files = vec![
+        ("M1F1-int16-AFsp.wav",   LPCM_I16_BE),
+        ("M1F1-int24-AFsp.wav",   LPCM_I24_BE),
+        ("M1F1-int32-AFsp.wav",   LPCM_I32_BE),
+        ("M1F1-float32-AFsp.wav", LPCM_F32_BE),
+        ("M1F1-float64-AFsp.wav", LPCM_F64_BE)
+      ];
+
+      for &(file, codec) in files.iter() {
+        path.set_file_name(file);
+        println!("{:?}", path.as_path());
+        let audio = audio::open(path.as_path()).unwrap();
+
+        let write_path = Path::new("tests/results/tmp_rifx.wav");
+        assert!(audio::save_as(&write_path, &audio, codec).is_ok());
+
+        let mut header = [0u8; 4];
+        File::open(&write_path).unwrap().read_exact(&mut header).unwrap();
+        assert_eq!(b"RIFX", &header);
+
+        let verify = audio::open(&write_path).unwrap();
+        assert_eq!(audio.channels,      verify.channels);
+        assert_eq!(audio.sample_rate,   verify.sample_rate);
+        assert_eq!(audio.samples.len(), verify.samples.len());
+        for (inital_sample, written_sample) in
+            audio.samples.iter().zip(&verify.samples) {
+          assert_eq!(inital_sample, written_sample);
+        }
+      }
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+      let mut audio = audio::open(Path::new("tests/wav/mono440-i16-44100.wav")).unwrap();
+      audio.metadata.instrument = Some(Instrument::default());
+      audio.metadata.loop_info  = Some(LoopInfo { beats: 4, tempo: 120f32, .. LoopInfo::default() });
+      let mut bytes: Vec<u8> = Vec::new();
+      assert!(audio::write_as(&mut bytes, &audio, AudioFormat::WAVE, LPCM_I16_BE).is_ok());
+      // Integers within chunks are big-endian.
+      assert_eq!(b"fmt ", &bytes[12..16]);
+      assert_eq!(&[0, 0, 0, 16], &bytes[16..20]);
+      let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+      assert_eq!(audio.metadata, verify.metadata);
+      assert_eq!(audio.samples,  verify.samples);
+    }
+
+    #[test]
+    fn unknown_chunks() {
+      use ::metadata::UnknownChunk;
+
+      // Chunks read from RIFX are only written back to RIFX, as their
+      // integers are big-endian.
+      let mut audio = audio::open(Path::new("tests/wav/mono440-i16-44100.wav")).unwrap();
+      audio.metadata.chunks.push(UnknownChunk {
+        format: AudioFormat::RIFX, id: *b"test", data: vec![0, 0, 0, 1], after_data: false
+      });
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write(&mut bytes, &audio, AudioFormat::RIFX).unwrap();
+      assert_eq!(b"RIFX", &bytes[0..4]);
+      let rifx = audio::load(&mut Cursor::new(bytes), AudioFormat::RIFX).unwrap();
+      assert_eq!(audio.metadata.chunks, rifx.metadata.chunks);
+      assert_eq!(audio.samples, rifx.samples);
+
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write(&mut bytes, &rifx, AudioFormat::WAVE).unwrap();
+      assert_eq!(b"RIFF", &bytes[0..4]);
+      let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+      assert!(verify.metadata.chunks.is_empty());
+      let mut bytes: Vec<u8> = Vec::new();
+      audio::write_as(&mut bytes, &rifx, AudioFormat::RIFX, LPCM_I24_LE).unwrap();
+      let verify = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+      assert_eq!(audio.metadata.chunks, verify.metadata.chunks);
+    }
+  }
+  mod metadata {
+    use std::path::Path;
+    use ::audio;
+    use ::metadata::{Instrument, Loop, LoopMode};
+
+    #[test]
+    fn sampler_round_trip() {
+      let mut audio = audio::open(Path::new("tests/wav/mono440-i16-44100.wav")).unwrap();
+      let instrument = Instrument {
+        root_note:      57,
+        fine_tune:      -12,
+        gain:           -3,
+        low_note:       48,
+        high_note:      64,
+        low_velocity:   10,
+        high_velocity:  120,
+        sustain_loop:   Some(Loop {
+          mode: LoopMode::Forward, start: 100, end: 199, play_count: 0
+        }),
+        release_loop:   Some(Loop {
+          mode: LoopMode::Alternating, start: 300, end: 399, play_count: 2
+        })
+      };
+      audio.metadata.instrument = Some(instrument);
+
+      let write_path = Path::new("tests/results/tmp_sampler.wav");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(Some(instrument), verify.metadata.instrument);
+      assert_eq!(audio.samples, verify.samples);
+
+      // Loops are converted to AIFF markers and back.
+      let write_path = Path::new("tests/results/tmp_sampler.aiff");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      let mut expected = instrument;
+      expected.release_loop.as_mut().unwrap().play_count = 0;
+      assert_eq!(Some(expected), verify.metadata.instrument);
+    }
+
+    #[test]
+    fn malformed_sampler_chunks() {
+      use std::fs::File;
+      use std::io::{Cursor, Read};
+      use byteorder::{ByteOrder, LittleEndian};
+      use ::audio::AudioFormat;
+
+      // An instrument chunk that is too short, and a sampler chunk that is
+      // cut off by the end of the file, are kept as unrecognized chunks.
+      let mut bytes = Vec::new();
+      File::open("tests/wav/mono440-i16-44100.wav").unwrap().read_to_end(&mut bytes).unwrap();
+      bytes.extend_from_slice(b"inst\x04\x00\x00\x00\x3C\x00\x00\x7F");
+      bytes.extend_from_slice(b"smpl\x3C\x00\x00\x00");
+      bytes.extend_from_slice(&[0u8; 10]);
+      let riff_size = bytes.len() as u32 - 8;
+      LittleEndian::write_u32(&mut bytes[4..8], riff_size);
+      let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+      assert_eq!(None, audio.metadata.instrument);
+      let chunks: Vec<(&[u8], usize)> =
+        audio.metadata.chunks.iter().map(|c| (&c.id[..], c.data.len())).collect();
+      assert_eq!(vec![(&b"inst"[..], 4), (&b"smpl"[..], 10)], chunks);
+    }
+
+    #[test]
+    fn unknown_chunks_round_trip() {
+      use std::fs::File;
+      use std::io::Read;
+
+      let path = Path::new("tests/wav/M1F1-int16-AFsp.wav");
+      let audio = audio::open(&path).unwrap();
+      let ids: Vec<&[u8]> =
+        audio.metadata.chunks.iter().map(|c| &c.id[..]).collect();
+      assert_eq!(vec![&b"afsp"[..], &b"LIST"[..]], ids);
+      assert!(audio.metadata.chunks.iter().all(|c| c.after_data));
+
+      let write_path = Path::new("tests/results/tmp_unknown.wav");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let mut read_bytes = Vec::new();
+      let mut written_bytes = Vec::new();
+      File::open(&path).unwrap().read_to_end(&mut read_bytes).unwrap();
+      File::open(&write_path).unwrap().read_to_end(&mut written_bytes).unwrap();
+      assert_eq!(read_bytes, written_bytes);
+
+      // Chunks are not written to other formats.
+      let write_path = Path::new("tests/results/tmp_unknown.aiff");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      assert!(verify.metadata.chunks.is_empty());
+    }
+
+    #[test]
+    fn id3_round_trip() {
+      use ::id3::{Id3Frame, Id3Tag, Picture};
+
+      let mut audio = audio::open(Path::new("tests/wav/mono440-i16-44100.wav")).unwrap();
+      let mut tag = Id3Tag::default();
+      tag.set_text("TIT2", "Sine");
+      tag.set_text("TPE1", "Oscillator");
+      tag.frames.push(Id3Frame::Picture(Picture {
+        mime_type:    "image/png".to_string(),
+        picture_type: 3,
+        description:  "Cover".to_string(),
+        data:         vec![0x89, 0x50, 0x4E, 0x47]
+      }));
+      audio.metadata.id3 = Some(tag.clone());
+
+      for file in ["tests/results/tmp_id3.wav", "tests/results/tmp_id3.aiff"].iter() {
+        let write_path = Path::new(file);
+        assert!(audio::save(&write_path, &audio).is_ok());
+        let verify = audio::open(&write_path).unwrap();
+        assert_eq!(Some(&tag), verify.metadata.id3.as_ref());
+        assert_eq!(audio.samples, verify.samples);
+      }
+        }
+
+    #[test]
+    fn xml_round_trip() {
+      let mut audio = audio::open(Path::new("tests/wav/mono440-i16-44100.wav")).unwrap();
+      let ixml = "<BWFXML><PROJECT>Sine</PROJECT><SCENE>1</SCENE>\
+                  <TAKE>2</TAKE></BWFXML>";
+      let axml = "<ebuCoreMain><coreMetadata/></ebuCoreMain>";
+      audio.metadata.ixml = Some(ixml.to_string());
+      audio.metadata.axml = Some(axml.to_string());
+
+      let write_path = Path::new("tests/results/tmp_xml.wav");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(Some(ixml), verify.metadata.ixml.as_ref().map(|s| &s[..]));
+      assert_eq!(Some(axml), verify.metadata.axml.as_ref().map(|s| &s[..]));
+      let fields = verify.metadata.ixml_fields().unwrap();
+      assert_eq!(Some("Sine".to_string()), fields.project);
+      assert_eq!(Some("1".to_string()),    fields.scene);
+      assert_eq!(Some("2".to_string()),    fields.take);
+        }
+
+    #[test]
+    fn loop_info_round_trip() {
+      use ::metadata::LoopInfo;
+
+      // One second of audio
+      let mut audio = audio::open(Path::new("tests/wav/mono440-i16-44100.wav")).unwrap();
+      let info = LoopInfo {
+        one_shot:           false,
+        stretch:            true,
+        root_note:          Some(69),
+        beats:              2,
+        meter_numerator:    3,
+        meter_denominator:  4,
+        tempo:              120f32
+      };
+      audio.metadata.loop_info = Some(info);
+
+      let write_path = Path::new("tests/results/tmp_acid.wav");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(Some(info), verify.metadata.loop_info);
+
+      // AIFF derives the tempo from the number of beats and duration.
+      audio.metadata.loop_info = Some(LoopInfo { beats: 0, .. info });
+      let write_path = Path::new("tests/results/tmp_basc.aiff");
+      assert!(audio::save(&write_path, &audio).is_ok());
+      let verify = audio::open(&write_path).unwrap();
+      assert_eq!(Some(info), verify.metadata.loop_info);
+    }
+
+    #[test]
+    fn malformed_loop_chunks() {
+      use std::fs::File;
+      use std::io::{Cursor, Read};
+      use byteorder::{ByteOrder, LittleEndian};
+      use ::audio::AudioFormat;
+
+      // An ACID chunk cut off by the end of the file is kept as an
+      // unrecognized chunk.
+      let mut bytes = Vec::new();
+      File::open("tests/wav/mono440-i16-44100.wav").unwrap().read_to_end(&mut bytes).unwrap();
+      bytes.extend_from_slice(b"acid\x18\x00\x00\x00\x01\x00\x00\x00\x3C\x00");
+      let riff_size = bytes.len() as u32 - 8;
+      LittleEndian::write_u32(&mut bytes[4..8], riff_size);
+      let audio = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+      assert_eq!(None, audio.metadata.loop_info);
+      assert_eq!(1, audio.metadata.chunks.len());
+      assert_eq!(b"acid", &audio.metadata.chunks[0].id);
+      assert_eq!(6, audio.metadata.chunks[0].data.len());
+    }
   }
 }
@@ -0,0 +1,126 @@
+//! WavPack Bitstream
+
+/// Reads bits packed into a byte slice, starting at the least significant
+/// bit of each byte.
+///
+/// Reading past the end of the slice gives zero bits.
+pub struct BitReader<'a> {
+  data:     &'a [u8],
+  position: usize
+}
+
+impl<'a> BitReader<'a> {
+  #[inline]
+  pub fn new(data: &'a [u8]) -> BitReader<'a> {
+    BitReader {
+      data:     data,
+      position: 0
+    }
+  }
+
+  #[inline]
+  pub fn read_bit(&mut self) -> bool {
+    let byte = self.data.get(self.position / 8).cloned().unwrap_or(0);
+    self.position += 1;
+    (byte >> ((self.position - 1) % 8)) & 1 == 1
+  }
+
+  /// Reads an unsigned integer of up to 32 bits, whose first bit read is
+  /// the least significant.
+  pub fn read(&mut self, bits: u32) -> u32 {
+    debug_assert!(bits <= 32);
+    let mut value: u64 = 0;
+    for i in 0..bits {
+      if self.read_bit() {
+        value |= 1 << i;
+      }
+    }
+    value as u32
+  }
+}
+
+/// Writes bits to a byte vector, starting at the least significant bit of
+/// each byte.
+pub struct BitWriter {
+  data:     Vec<u8>,
+  position: usize
+}
+
+impl BitWriter {
+  #[inline]
+  pub fn new() -> BitWriter {
+    BitWriter {
+      data:     Vec::new(),
+      position: 0
+    }
+  }
+
+  #[inline]
+  pub fn write_bit(&mut self, bit: bool) {
+    if self.position % 8 == 0 {
+      self.data.push(0);
+    }
+    if bit {
+      *self.data.last_mut().unwrap() |= 1 << (self.position % 8);
+    }
+    self.position += 1;
+  }
+
+  /// Writes the lowest bits of a value, least significant bit first.
+  pub fn write(&mut self, value: u64, bits: u32) {
+    for i in 0..bits {
+      self.write_bit((value >> i) & 1 == 1);
+    }
+  }
+
+  /// Returns the written bytes. The last byte is filled with ones, and the
+  /// bytes are padded to a whole number of 16-bit words.
+  pub fn finish(mut self) -> Vec<u8> {
+    while self.position % 16 != 0 {
+      self.write_bit(true);
+    }
+    self.data
+  }
+}
+
+#[cfg(test)]
+mod bitstream {
+  use super::*;
+
+  #[test]
+  fn read_lsb_first() {
+    let data = [0b1010_0110u8, 0b0000_0001];
+    let mut reader = BitReader::new(&data);
+    assert_eq!(false, reader.read_bit());
+    assert_eq!(true,  reader.read_bit());
+    assert_eq!(0b01,  reader.read(2));
+    assert_eq!(0b1_1010, reader.read(5));
+    assert_eq!(0, reader.read(7));
+    // Past the end of the data
+    assert_eq!(0, reader.read(8));
+  }
+
+  #[test]
+  fn write_lsb_first() {
+    let mut writer = BitWriter::new();
+    writer.write_bit(false);
+    writer.write_bit(true);
+    writer.write(0b1_1001, 5);
+    assert_eq!(vec![0b1110_0110u8, 0xFF], writer.finish());
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut writer = BitWriter::new();
+    for i in 0..32 {
+      writer.write(i * 7919, i as u32);
+    }
+    let data = writer.finish();
+    assert_eq!(0, data.len() % 2);
+    let mut reader = BitReader::new(&data);
+    for i in 0..32 {
+      let mask = (1u64 << i) - 1;
+      assert_eq!((i * 7919) & mask, reader.read(i as u32) as u64);
+    }
+  }
+}
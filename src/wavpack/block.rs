@@ -0,0 +1,194 @@
+//! WavPack Blocks
+//!
+//! Each block starts with a 32 byte header, followed by metadata
+//! sub-blocks holding the state of the decoder and the coded residuals. The
+//! header of a sub-block is a byte identifying its function and flags,
+//! followed by its size in 16-bit words, in one or three bytes.
+use error::*;
+use wavpack::WVPK;
+
+/// Sub-block flag of data that decoders may ignore.
+const ID_OPTIONAL_DATA: u8 = 0x20;
+
+/// Sub-block flag of data whose last word has a padding byte.
+const ID_ODD_SIZE: u8 = 0x40;
+
+/// Sub-block flag of data whose size is given in three bytes.
+const ID_LARGE: u8 = 0x80;
+
+/// The header of a block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockHeader {
+  /// Size of the block, excluding the identifier and this field
+  pub size:           u32,
+  pub version:        u16,
+  /// Number of frames of the whole stream, if known
+  pub total_samples:  u32,
+  /// Index of the first frame of the block within the stream
+  pub block_index:    u32,
+  /// Number of frames in the block
+  pub block_samples:  u32,
+  pub flags:          u32,
+  /// Checksum of the decoded samples
+  pub crc:            u32
+}
+
+impl BlockHeader {
+  pub fn read(bytes: &[u8]) -> AudioResult<BlockHeader> {
+    if bytes.len() < 32 || &bytes[0..4] != WVPK {
+      return Err(AudioError::Format(
+        "File is not valid WavPack (Invalid block header)".to_string()
+      ));
+    }
+    let size = read_u32(&bytes[4..8]);
+    if size < 24 || size % 2 != 0 {
+      return Err(AudioError::Format(
+        "File is not valid WavPack (Invalid block size)".to_string()
+      ));
+    }
+    Ok(BlockHeader {
+      size:           size,
+      version:        bytes[8] as u16 | (bytes[9] as u16) << 8,
+      total_samples:  read_u32(&bytes[12..16]),
+      block_index:    read_u32(&bytes[16..20]),
+      block_samples:  read_u32(&bytes[20..24]),
+      flags:          read_u32(&bytes[24..28]),
+      crc:            read_u32(&bytes[28..32])
+    })
+  }
+
+  /// Writes the header of a block with the given sub-blocks.
+  pub fn write(&self, sub_blocks: &[u8], bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(WVPK);
+    push_u32(bytes, 24 + sub_blocks.len() as u32);
+    bytes.push(self.version as u8);
+    bytes.push((self.version >> 8) as u8);
+    // The upper bytes of the indexes, used by newer versions, are zero.
+    bytes.push(0);
+    bytes.push(0);
+    push_u32(bytes, self.total_samples);
+    push_u32(bytes, self.block_index);
+    push_u32(bytes, self.block_samples);
+    push_u32(bytes, self.flags);
+    push_u32(bytes, self.crc);
+    bytes.extend_from_slice(sub_blocks);
+  }
+}
+
+/// A metadata sub-block of a block.
+pub struct SubBlock<'a> {
+  /// Function of the sub-block, including the optional data flag
+  pub id:   u8,
+  pub data: &'a [u8]
+}
+
+/// Reads the sub-blocks following the header of a block.
+pub fn read_sub_blocks(bytes: &[u8]) -> AudioResult<Vec<SubBlock>> {
+  let mut sub_blocks = Vec::new();
+  let mut position = 0;
+  while position + 2 <= bytes.len() {
+    let id = bytes[position];
+    let (words, header_len) =
+      if id & ID_LARGE != 0 {
+        if position + 4 > bytes.len() {
+          break;
+        }
+        (bytes[position + 1] as usize
+         | (bytes[position + 2] as usize) << 8
+         | (bytes[position + 3] as usize) << 16, 4)
+      } else {
+        (bytes[position + 1] as usize, 2)
+      };
+    let start = position + header_len;
+    let end   = start + words * 2;
+    if end > bytes.len() {
+      return Err(AudioError::Format(
+        "File is not valid WavPack (Sub-block exceeds block)".to_string()
+      ));
+    }
+    let len = if id & ID_ODD_SIZE != 0 && words > 0 { words * 2 - 1 } else { words * 2 };
+    sub_blocks.push(SubBlock {
+      id:   id & (ID_OPTIONAL_DATA | 0x1F),
+      data: &bytes[start .. start + len]
+    });
+    position = end;
+  }
+  Ok(sub_blocks)
+}
+
+/// Writes a sub-block, padding its data to a whole number of words.
+pub fn write_sub_block(id: u8, data: &[u8], bytes: &mut Vec<u8>) {
+  let words = (data.len() + 1) / 2;
+  let mut flags = 0;
+  if data.len() % 2 == 1 {
+    flags |= ID_ODD_SIZE;
+  }
+  if words > 0xFF {
+    bytes.push(id | flags | ID_LARGE);
+    bytes.push(words as u8);
+    bytes.push((words >> 8) as u8);
+    bytes.push((words >> 16) as u8);
+  } else {
+    bytes.push(id | flags);
+    bytes.push(words as u8);
+  }
+  bytes.extend_from_slice(data);
+  if data.len() % 2 == 1 {
+    bytes.push(0);
+  }
+}
+
+#[inline]
+pub fn read_u32(bytes: &[u8]) -> u32 {
+  bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+#[inline]
+pub fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+  bytes.push(value as u8);
+  bytes.push((value >> 8) as u8);
+  bytes.push((value >> 16) as u8);
+  bytes.push((value >> 24) as u8);
+}
+
+#[cfg(test)]
+mod blocks {
+  use super::*;
+
+  #[test]
+  fn header_round_trip() {
+    let header = BlockHeader {
+      size:           24 + 6,
+      version:        0x407,
+      total_samples:  44100,
+      block_index:    22050,
+      block_samples:  22050,
+      flags:          0x1801,
+      crc:            0xDEADBEEF
+    };
+    let mut bytes = Vec::new();
+    header.write(&[1, 2, 3, 4, 5, 6], &mut bytes);
+    assert_eq!(32 + 6, bytes.len());
+    assert_eq!(header, BlockHeader::read(&bytes).unwrap());
+    assert!(BlockHeader::read(&bytes[1..]).is_err());
+  }
+
+  #[test]
+  fn sub_blocks() {
+    let large = vec![7u8; 1001];
+    let mut bytes = Vec::new();
+    write_sub_block(0x02, &[0x12, 0x34], &mut bytes);
+    write_sub_block(0x21, &[1, 2, 3], &mut bytes);
+    write_sub_block(0x0A, &large, &mut bytes);
+    assert_eq!(2 + 2 + 2 + 4 + 4 + 1002, bytes.len());
+    let sub_blocks = read_sub_blocks(&bytes).unwrap();
+    assert_eq!(3, sub_blocks.len());
+    assert_eq!(0x02, sub_blocks[0].id);
+    assert_eq!(&[0x12, 0x34], sub_blocks[0].data);
+    assert_eq!(0x21, sub_blocks[1].id);
+    assert_eq!(&[1, 2, 3], sub_blocks[1].data);
+    assert_eq!(0x0A, sub_blocks[2].id);
+    assert_eq!(&large[..], sub_blocks[2].data);
+    assert!(read_sub_blocks(&bytes[..bytes.len() - 2]).is_err());
+  }
+}
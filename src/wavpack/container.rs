@@ -0,0 +1,306 @@
+use std::io::{Read, Seek, Write};
+use buffer::*;
+use codecs::Codec;
+use codecs::Codec::*;
+use error::*;
+use metadata::{Metadata, RiffWrapper};
+use sample::*;
+use traits::Container;
+use wave::container::WaveContainer;
+use wavpack::*;
+use wavpack::block::{BlockHeader, SubBlock, read_sub_blocks, write_sub_block};
+use wavpack::pack::Packer;
+use wavpack::unpack::unpack;
+
+/// Struct containing all necessary information for encoding and decoding
+/// bytes to an `AudioBuffer`.
+pub struct WavPackContainer {
+  pub sample_rate:  u32,
+  pub channels:     u32,
+  pub order:        SampleOrder,
+  pub samples:      Vec<Sample>,
+  pub metadata:     Metadata
+}
+
+impl Container for WavPackContainer {
+  fn open<R: Read + Seek>(reader: &mut R) -> AudioResult<WavPackContainer> {
+    let mut bytes: Vec<u8> = Vec::new();
+    try!(reader.read_to_end(&mut bytes));
+
+    let mut first: Option<BlockHeader> = None;
+    let mut sample_rate: Option<u32>    = None;
+    let mut channels: usize             = 0;
+    let mut riff_header: Option<Vec<u8>> = None;
+    let mut riff_trailer: Vec<u8>       = Vec::new();
+    // Integer samples, or the bits of floating-point samples, of all
+    // channels. The blocks of a frame range each hold one or two channels,
+    // from the initial block to the final block.
+    let mut values: Vec<i32>            = Vec::new();
+    let mut range: Option<(usize, usize)> = None;
+    let mut channel: usize              = 0;
+
+    let mut position = 0;
+    while position + 32 <= bytes.len() {
+      // Data between blocks, such as an APEv2 tag, is skipped.
+      if &bytes[position .. position + 4] != WVPK {
+        position += 1;
+        continue;
+      }
+      let header = try!(BlockHeader::read(&bytes[position..]));
+      let end    = position + 8 + header.size as usize;
+      if end > bytes.len() {
+        return Err(AudioError::Format(
+          "File is not valid WavPack (Block exceeds file)".to_string()
+        ));
+      }
+      if header.version < MIN_VERSION || header.version > MAX_VERSION {
+        return Err(AudioError::Unsupported(
+          format!("WavPack version {:#x} is not supported", header.version)
+        ));
+      }
+      let sub_blocks = try!(read_sub_blocks(&bytes[position + 32 .. end]));
+      for sub_block in sub_blocks.iter() {
+        match sub_block.id {
+          ID_RIFF_HEADER if riff_header.is_none() =>
+            riff_header = Some(sub_block.data.to_vec()),
+          ID_RIFF_TRAILER =>
+            riff_trailer.extend_from_slice(sub_block.data),
+          _ => {}
+        }
+      }
+      if first.is_none() {
+        first       = Some(header);
+        sample_rate = read_sample_rate(&header, &sub_blocks);
+        channels    =
+          match sub_blocks.iter().find(|sub_block| sub_block.id == ID_CHANNEL_INFO) {
+            Some(info) if info.data.len() > 0 && info.data[0] > 0 => info.data[0] as usize,
+            _ => if header.flags & MONO_FLAG != 0 { 1 } else { 2 }
+          };
+      }
+
+      if header.block_samples > 0 {
+        let count = header.block_samples as usize;
+        if header.flags & INITIAL_BLOCK != 0 {
+          range   = Some((values.len() / channels, count));
+          channel = 0;
+          let len = values.len() + count * channels;
+          values.resize(len, 0);
+        }
+        let block_channels = if header.flags & MONO_FLAG != 0 { 1 } else { 2 };
+        let start =
+          match range {
+            Some((start, frames)) if frames == count && channel + block_channels <= channels => start,
+            _ =>
+              return Err(AudioError::Format(
+                "File is not valid WavPack (Blocks do not match channels)".to_string()
+              ))
+          };
+        let block = try!(unpack(&header, &sub_blocks));
+        for (i, frame) in block.chunks(block_channels).enumerate() {
+          let offset = (start + i) * channels + channel;
+          values[offset .. offset + block_channels].copy_from_slice(frame);
+        }
+        channel += block_channels;
+      }
+      position = end;
+    }
+
+    let first =
+      match first {
+        Some(header) => header,
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid WavPack (No blocks found)".to_string()
+          ))
+      };
+    let sample_rate =
+      match sample_rate {
+        Some(rate) => rate,
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid WavPack (Missing sample rate)".to_string()
+          ))
+      };
+    let codec =
+      match ((first.flags & BYTES_STORED) + 1, first.flags & FLOAT_DATA != 0) {
+        (4, true) => LPCM_F32_LE,
+        (4, _)    => LPCM_I32_LE,
+        (3, _)    => LPCM_I24_LE,
+        (2, _)    => LPCM_I16_LE,
+        _         => LPCM_U8
+      };
+    let data = to_bytes(&values, codec);
+    let samples = try!(::codecs::decode(&data, codec));
+
+    // Metadata is read from the chunks of the original WAVE file.
+    let mut metadata = Metadata::default();
+    if let Some(header) = riff_header {
+      let wrapper = RiffWrapper {
+        header:   header,
+        trailer:  riff_trailer
+      };
+      if let Ok(container) = WaveContainer::read_wrapper(&wrapper, &data) {
+        metadata = container.metadata;
+      }
+      metadata.riff = Some(wrapper);
+    }
+
+    Ok(WavPackContainer {
+      sample_rate:    sample_rate,
+      channels:       channels as u32,
+      order:
+        if channels == 1 {
+          SampleOrder::Mono
+        } else {
+          SampleOrder::Interleaved
+        },
+      samples:        samples,
+      metadata:       metadata
+    })
+  }
+  fn create<W: Write>(writer: &mut W, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    let bytes_stored: u32 =
+      match codec {
+        LPCM_U8     => 1,
+        LPCM_I16_LE => 2,
+        LPCM_I24_LE => 3,
+        LPCM_I32_LE |
+        LPCM_F32_LE => 4,
+        c @ _ =>
+          return Err(AudioError::Unsupported(
+            format!("WavPack does not support the {:?} codec", c)
+          ))
+      };
+    let channels = audio.channels as usize;
+    if channels == 0 || channels > 255 {
+      return Err(AudioError::Unsupported(
+        format!("WavPack does not support {} channels", channels)
+      ));
+    }
+    let values  = from_bytes(&try!(::codecs::encode(audio, codec)), codec);
+    let frames  = values.len() / channels;
+    let wrapper = try!(WaveContainer::wrapper(audio, codec));
+
+    let rate_index =
+      SAMPLE_RATES.iter().position(|&rate| rate == audio.sample_rate)
+      .unwrap_or(SAMPLE_RATES.len()) as u32;
+    let mut flags = (bytes_stored - 1) | rate_index << SRATE_LSB;
+    if codec == LPCM_F32_LE {
+      flags |= FLOAT_DATA;
+    }
+
+    // Channels are coded in pairs, followed by a mono block for an odd
+    // number of channels.
+    let streams: Vec<(usize, bool)> =
+      (0 .. (channels + 1) / 2)
+      .map(|i| (2 * i, 2 * i + 1 < channels))
+      .collect();
+    let mut packers: Vec<Packer> =
+      streams.iter().map(|&(_, stereo)| Packer::new(stereo)).collect();
+    let block_frames = (audio.sample_rate as usize / 2).max(1);
+    let block_count  = ((frames + block_frames - 1) / block_frames).max(1);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    for block in 0..block_count {
+      let start = block * block_frames;
+      let count = block_frames.min(frames - start);
+      for (s, (&(first_channel, stereo), packer)) in
+          streams.iter().zip(packers.iter_mut()).enumerate() {
+        let mut header = BlockHeader {
+          size:           0,
+          version:        VERSION,
+          total_samples:  frames as u32,
+          block_index:    start as u32,
+          block_samples:  count as u32,
+          flags:          flags,
+          crc:            0
+        };
+        if !stereo {
+          header.flags |= MONO_FLAG;
+        }
+        if s == 0 {
+          header.flags |= INITIAL_BLOCK;
+        }
+        if s == streams.len() - 1 {
+          header.flags |= FINAL_BLOCK;
+        }
+        let mut metadata: Vec<u8> = Vec::new();
+        if block == 0 && s == 0 {
+          write_sub_block(ID_RIFF_HEADER, &wrapper.header, &mut metadata);
+          if channels > 2 {
+            write_sub_block(ID_CHANNEL_INFO, &[channels as u8], &mut metadata);
+          }
+          if rate_index as usize == SAMPLE_RATES.len() {
+            let rate = audio.sample_rate;
+            write_sub_block(ID_SAMPLE_RATE, &[rate as u8, (rate >> 8) as u8, (rate >> 16) as u8], &mut metadata);
+          }
+        }
+        if block == block_count - 1 && s == streams.len() - 1 && !wrapper.trailer.is_empty() {
+          write_sub_block(ID_RIFF_TRAILER, &wrapper.trailer, &mut metadata);
+        }
+        let block_channels = if stereo { 2 } else { 1 };
+        let samples: Vec<i32> =
+          (start .. start + count)
+          .flat_map(|frame| {
+            let offset = frame * channels + first_channel;
+            values[offset .. offset + block_channels].to_vec().into_iter()
+          })
+          .collect();
+        packer.pack(header, &samples, &metadata, &mut bytes);
+      }
+    }
+    try!(writer.write_all(&bytes));
+    Ok(())
+  }
+}
+
+// Private functions
+
+/// Returns the sample rate given by the flags of a block, or stored in a
+/// sub-block when it is not one of the standard rates.
+fn read_sample_rate(header: &BlockHeader, sub_blocks: &[SubBlock]) -> Option<u32> {
+  let index = ((header.flags & SRATE_MASK) >> SRATE_LSB) as usize;
+  if index < SAMPLE_RATES.len() {
+    return Some(SAMPLE_RATES[index]);
+  }
+  sub_blocks.iter()
+    .find(|sub_block| sub_block.id == ID_SAMPLE_RATE && sub_block.data.len() >= 3)
+    .map(|sub_block|
+      sub_block.data.iter().take(4).enumerate()
+        .fold(0, |rate, (i, &byte)| rate | (byte as u32) << (8 * i))
+    )
+}
+
+/// Returns the bytes of samples decoded from WavPack blocks, as stored in a
+/// WAVE file using the codec.
+fn to_bytes(values: &[i32], codec: Codec) -> Vec<u8> {
+  let size = codec.sample_size();
+  let mut bytes = Vec::with_capacity(values.len() * size);
+  for &value in values.iter() {
+    match codec {
+      LPCM_U8 => bytes.push((value + 128) as u8),
+      _ => {
+        for i in 0..size {
+          bytes.push((value >> (8 * i)) as u8);
+        }
+      }
+    }
+  }
+  bytes
+}
+
+/// Returns the integer samples, or the bits of floating-point samples,
+/// given by the bytes of a WAVE file using the codec.
+fn from_bytes(bytes: &[u8], codec: Codec) -> Vec<i32> {
+  let size = codec.sample_size();
+  bytes.chunks(size).map(|sample|
+    match codec {
+      LPCM_U8 => sample[0] as i32 - 128,
+      _ => {
+        // Samples are sign extended from their most significant byte.
+        let value = sample.iter().rev().fold(0u32, |value, &byte| value << 8 | byte as u32);
+        (value << (32 - 8 * size)) as i32 >> (32 - 8 * size)
+      }
+    }
+  ).collect()
+}
@@ -0,0 +1,423 @@
+//! WavPack Decorrelation
+//!
+//! Samples are decorrelated by a series of passes, each of which subtracts
+//! an adaptive prediction from the samples. A pass predicts from the sample
+//! `term` samples before for terms from 1 to 8, or extrapolates from the
+//! previous two samples for terms 17 and 18. The negative terms of stereo
+//! blocks predict each channel from the other. The weight of each pass is
+//! adapted by `delta` after every sample, depending on whether the
+//! prediction had the right sign.
+//!
+//! The encoder applies the passes in order, and the decoder undoes them in
+//! the reverse order. The weights and the previous samples of each pass are
+//! stored at the start of each block, so blocks can be decoded on their own.
+
+/// Largest term that predicts from a previous sample.
+pub const MAX_TERM: usize = 8;
+
+/// A decorrelation pass with the state of one or two channels.
+#[derive(Clone, Copy, Debug)]
+pub struct DecorrPass {
+  pub term:       i32,
+  pub delta:      i32,
+  pub weight_a:   i32,
+  pub weight_b:   i32,
+  pub samples_a:  [i32; MAX_TERM],
+  pub samples_b:  [i32; MAX_TERM]
+}
+
+impl DecorrPass {
+  pub fn new(term: i32, delta: i32) -> DecorrPass {
+    DecorrPass {
+      term:       term,
+      delta:      delta,
+      weight_a:   0,
+      weight_b:   0,
+      samples_a:  [0i32; MAX_TERM],
+      samples_b:  [0i32; MAX_TERM]
+    }
+  }
+
+  /// Returns whether a term can be used by blocks with the given number of
+  /// channels.
+  #[inline]
+  pub fn is_valid_term(term: i32, stereo: bool) -> bool {
+    match term {
+      -3 ..= -1 => stereo,
+      1 ..= 8   |
+      17 | 18   => true,
+      _         => false
+    }
+  }
+
+  /// Returns the number of previous samples of each channel kept by the
+  /// pass.
+  #[inline]
+  pub fn history_len(&self) -> usize {
+    match self.term {
+      17 | 18   => 2,
+      t if t < 0 => 1,
+      t          => t as usize
+    }
+  }
+
+  /// Undoes the pass on the samples of a mono block.
+  pub fn decode_mono(&mut self, buffer: &mut [i32]) {
+    let delta = self.delta;
+    match self.term {
+      17 | 18 => {
+        for sample in buffer.iter_mut() {
+          let prediction = self.extrapolate_a();
+          self.samples_a[1] = self.samples_a[0];
+          self.samples_a[0] = apply_weight(self.weight_a, prediction).wrapping_add(*sample);
+          update_weight(&mut self.weight_a, delta, prediction, *sample);
+          *sample = self.samples_a[0];
+        }
+      },
+      term @ _ => {
+        let mut m = 0;
+        let mut k = term as usize & (MAX_TERM - 1);
+        for sample in buffer.iter_mut() {
+          let prediction = self.samples_a[m];
+          self.samples_a[k] = apply_weight(self.weight_a, prediction).wrapping_add(*sample);
+          update_weight(&mut self.weight_a, delta, prediction, *sample);
+          *sample = self.samples_a[k];
+          m = (m + 1) & (MAX_TERM - 1);
+          k = (k + 1) & (MAX_TERM - 1);
+        }
+        self.rotate(m);
+      }
+    }
+  }
+
+  /// Applies the pass to the samples of a mono block.
+  pub fn encode_mono(&mut self, buffer: &mut [i32]) {
+    let delta = self.delta;
+    match self.term {
+      17 | 18 => {
+        for sample in buffer.iter_mut() {
+          let prediction = self.extrapolate_a();
+          self.samples_a[1] = self.samples_a[0];
+          self.samples_a[0] = *sample;
+          *sample = sample.wrapping_sub(apply_weight(self.weight_a, prediction));
+          update_weight(&mut self.weight_a, delta, prediction, *sample);
+        }
+      },
+      term @ _ => {
+        let mut m = 0;
+        let mut k = term as usize & (MAX_TERM - 1);
+        for sample in buffer.iter_mut() {
+          let prediction = self.samples_a[m];
+          self.samples_a[k] = *sample;
+          *sample = sample.wrapping_sub(apply_weight(self.weight_a, prediction));
+          update_weight(&mut self.weight_a, delta, prediction, *sample);
+          m = (m + 1) & (MAX_TERM - 1);
+          k = (k + 1) & (MAX_TERM - 1);
+        }
+        self.rotate(m);
+      }
+    }
+  }
+
+  /// Undoes the pass on the interleaved samples of a stereo block.
+  pub fn decode_stereo(&mut self, buffer: &mut [i32]) {
+    let delta = self.delta;
+    match self.term {
+      17 | 18 => {
+        for frame in buffer.chunks_mut(2) {
+          let prediction = self.extrapolate_a();
+          self.samples_a[1] = self.samples_a[0];
+          self.samples_a[0] = apply_weight(self.weight_a, prediction).wrapping_add(frame[0]);
+          update_weight(&mut self.weight_a, delta, prediction, frame[0]);
+          frame[0] = self.samples_a[0];
+
+          let prediction = self.extrapolate_b();
+          self.samples_b[1] = self.samples_b[0];
+          self.samples_b[0] = apply_weight(self.weight_b, prediction).wrapping_add(frame[1]);
+          update_weight(&mut self.weight_b, delta, prediction, frame[1]);
+          frame[1] = self.samples_b[0];
+        }
+      },
+      -1 => {
+        for frame in buffer.chunks_mut(2) {
+          let left = frame[0].wrapping_add(apply_weight(self.weight_a, self.samples_a[0]));
+          update_weight_clip(&mut self.weight_a, delta, self.samples_a[0], frame[0]);
+          frame[0] = left;
+          self.samples_a[0] = frame[1].wrapping_add(apply_weight(self.weight_b, left));
+          update_weight_clip(&mut self.weight_b, delta, left, frame[1]);
+          frame[1] = self.samples_a[0];
+        }
+      },
+      -2 => {
+        for frame in buffer.chunks_mut(2) {
+          let right = frame[1].wrapping_add(apply_weight(self.weight_b, self.samples_b[0]));
+          update_weight_clip(&mut self.weight_b, delta, self.samples_b[0], frame[1]);
+          frame[1] = right;
+          self.samples_b[0] = frame[0].wrapping_add(apply_weight(self.weight_a, right));
+          update_weight_clip(&mut self.weight_a, delta, right, frame[0]);
+          frame[0] = self.samples_b[0];
+        }
+      },
+      -3 => {
+        for frame in buffer.chunks_mut(2) {
+          let left = frame[0].wrapping_add(apply_weight(self.weight_a, self.samples_a[0]));
+          update_weight_clip(&mut self.weight_a, delta, self.samples_a[0], frame[0]);
+          let right = frame[1].wrapping_add(apply_weight(self.weight_b, self.samples_b[0]));
+          update_weight_clip(&mut self.weight_b, delta, self.samples_b[0], frame[1]);
+          frame[0] = left;
+          frame[1] = right;
+          self.samples_b[0] = left;
+          self.samples_a[0] = right;
+        }
+      },
+      term @ _ => {
+        let mut m = 0;
+        let mut k = term as usize & (MAX_TERM - 1);
+        for frame in buffer.chunks_mut(2) {
+          let prediction = self.samples_a[m];
+          self.samples_a[k] = apply_weight(self.weight_a, prediction).wrapping_add(frame[0]);
+          update_weight(&mut self.weight_a, delta, prediction, frame[0]);
+          frame[0] = self.samples_a[k];
+
+          let prediction = self.samples_b[m];
+          self.samples_b[k] = apply_weight(self.weight_b, prediction).wrapping_add(frame[1]);
+          update_weight(&mut self.weight_b, delta, prediction, frame[1]);
+          frame[1] = self.samples_b[k];
+
+          m = (m + 1) & (MAX_TERM - 1);
+          k = (k + 1) & (MAX_TERM - 1);
+        }
+        self.rotate(m);
+      }
+    }
+  }
+
+  /// Applies the pass to the interleaved samples of a stereo block.
+  pub fn encode_stereo(&mut self, buffer: &mut [i32]) {
+    let delta = self.delta;
+    match self.term {
+      17 | 18 => {
+        for frame in buffer.chunks_mut(2) {
+          let prediction = self.extrapolate_a();
+          self.samples_a[1] = self.samples_a[0];
+          self.samples_a[0] = frame[0];
+          frame[0] = frame[0].wrapping_sub(apply_weight(self.weight_a, prediction));
+          update_weight(&mut self.weight_a, delta, prediction, frame[0]);
+
+          let prediction = self.extrapolate_b();
+          self.samples_b[1] = self.samples_b[0];
+          self.samples_b[0] = frame[1];
+          frame[1] = frame[1].wrapping_sub(apply_weight(self.weight_b, prediction));
+          update_weight(&mut self.weight_b, delta, prediction, frame[1]);
+        }
+      },
+      -1 => {
+        for frame in buffer.chunks_mut(2) {
+          let (left, right) = (frame[0], frame[1]);
+          frame[0] = left.wrapping_sub(apply_weight(self.weight_a, self.samples_a[0]));
+          update_weight_clip(&mut self.weight_a, delta, self.samples_a[0], frame[0]);
+          frame[1] = right.wrapping_sub(apply_weight(self.weight_b, left));
+          update_weight_clip(&mut self.weight_b, delta, left, frame[1]);
+          self.samples_a[0] = right;
+        }
+      },
+      -2 => {
+        for frame in buffer.chunks_mut(2) {
+          let (left, right) = (frame[0], frame[1]);
+          frame[1] = right.wrapping_sub(apply_weight(self.weight_b, self.samples_b[0]));
+          update_weight_clip(&mut self.weight_b, delta, self.samples_b[0], frame[1]);
+          frame[0] = left.wrapping_sub(apply_weight(self.weight_a, right));
+          update_weight_clip(&mut self.weight_a, delta, right, frame[0]);
+          self.samples_b[0] = left;
+        }
+      },
+      -3 => {
+        for frame in buffer.chunks_mut(2) {
+          let (left, right) = (frame[0], frame[1]);
+          frame[0] = left.wrapping_sub(apply_weight(self.weight_a, self.samples_a[0]));
+          update_weight_clip(&mut self.weight_a, delta, self.samples_a[0], frame[0]);
+          frame[1] = right.wrapping_sub(apply_weight(self.weight_b, self.samples_b[0]));
+          update_weight_clip(&mut self.weight_b, delta, self.samples_b[0], frame[1]);
+          self.samples_b[0] = left;
+          self.samples_a[0] = right;
+        }
+      },
+      term @ _ => {
+        let mut m = 0;
+        let mut k = term as usize & (MAX_TERM - 1);
+        for frame in buffer.chunks_mut(2) {
+          let prediction = self.samples_a[m];
+          self.samples_a[k] = frame[0];
+          frame[0] = frame[0].wrapping_sub(apply_weight(self.weight_a, prediction));
+          update_weight(&mut self.weight_a, delta, prediction, frame[0]);
+
+          let prediction = self.samples_b[m];
+          self.samples_b[k] = frame[1];
+          frame[1] = frame[1].wrapping_sub(apply_weight(self.weight_b, prediction));
+          update_weight(&mut self.weight_b, delta, prediction, frame[1]);
+
+          m = (m + 1) & (MAX_TERM - 1);
+          k = (k + 1) & (MAX_TERM - 1);
+        }
+        self.rotate(m);
+      }
+    }
+  }
+
+  /// Extrapolates the next sample of the first channel for terms 17 and 18.
+  #[inline]
+  fn extrapolate_a(&self) -> i32 {
+    extrapolate(self.term, self.samples_a[0], self.samples_a[1])
+  }
+
+  #[inline]
+  fn extrapolate_b(&self) -> i32 {
+    extrapolate(self.term, self.samples_b[0], self.samples_b[1])
+  }
+
+  /// Moves the previous samples so the oldest is first, as the position of
+  /// the samples within the history is not stored.
+  fn rotate(&mut self, m: usize) {
+    if m != 0 {
+      let (a, b) = (self.samples_a, self.samples_b);
+      for k in 0..MAX_TERM {
+        self.samples_a[k] = a[(m + k) & (MAX_TERM - 1)];
+        self.samples_b[k] = b[(m + k) & (MAX_TERM - 1)];
+      }
+    }
+  }
+}
+
+#[inline]
+fn extrapolate(term: i32, s0: i32, s1: i32) -> i32 {
+  if term == 17 {
+    s0.wrapping_mul(2).wrapping_sub(s1)
+  } else {
+    s0.wrapping_mul(3).wrapping_sub(s1) >> 1
+  }
+}
+
+/// Returns the prediction of a weight, in units of 1/1024, for a sample.
+#[inline]
+pub fn apply_weight(weight: i32, sample: i32) -> i32 {
+  if sample == sample as i16 as i32 {
+    weight.wrapping_mul(sample).wrapping_add(512) >> 10
+  } else {
+    // Larger samples are split so the products do not overflow.
+    let low  = ((sample & 0xFFFF).wrapping_mul(weight)) >> 9;
+    let high = ((sample & !0xFFFF) >> 9).wrapping_mul(weight);
+    low.wrapping_add(high).wrapping_add(1) >> 1
+  }
+}
+
+/// Adapts a weight by moving it towards the sign of the product of the
+/// sample it was applied to and the residual.
+#[inline]
+fn update_weight(weight: &mut i32, delta: i32, source: i32, result: i32) {
+  if source != 0 && result != 0 {
+    if (source ^ result) < 0 {
+      *weight -= delta;
+    } else {
+      *weight += delta;
+    }
+  }
+}
+
+/// Adapts a weight of the cross channel terms, which is kept within -1024
+/// and 1024.
+#[inline]
+fn update_weight_clip(weight: &mut i32, delta: i32, source: i32, result: i32) {
+  if source != 0 && result != 0 {
+    if (source ^ result) < 0 {
+      *weight = (*weight - delta).max(-1024);
+    } else {
+      *weight = (*weight + delta).min(1024);
+    }
+  }
+}
+
+/// Returns the weight stored in a byte of a block.
+#[inline]
+pub fn restore_weight(weight: i8) -> i32 {
+  let weight = (weight as i32) << 3;
+  if weight > 0 {
+    weight + ((weight + 64) >> 7)
+  } else {
+    weight
+  }
+}
+
+/// Returns the byte a weight is stored as.
+#[inline]
+pub fn store_weight(weight: i32) -> i8 {
+  let mut weight = weight.max(-1024).min(1024);
+  if weight > 0 {
+    weight -= (weight + 64) >> 7;
+  }
+  ((weight + 4) >> 3) as i8
+}
+
+#[cfg(test)]
+mod decorrelation {
+  use super::*;
+
+  fn signal(len: usize) -> Vec<i32> {
+    let mut seed = 7u32;
+    (0..len).map(|i| {
+      seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+      let noise = (seed >> 16) as i32 % 64 - 32;
+      ((i as f32 * 0.05).sin() * 20000f32) as i32 + noise + if i > len / 2 { 1 << 20 } else { 0 }
+    }).collect()
+  }
+
+  #[test]
+  fn weights() {
+    for weight in -128..128 {
+      let weight = weight as i8;
+      assert_eq!(weight, store_weight(restore_weight(weight)));
+    }
+    assert_eq!(1024, restore_weight(store_weight(1024)));
+    assert_eq!(-1024, restore_weight(store_weight(-2000)));
+  }
+
+  #[test]
+  fn mono_passes() {
+    let samples = signal(1000);
+    for &term in [1, 2, 3, 5, 8, 17, 18].iter() {
+      let mut encoder = DecorrPass::new(term, 2);
+      let mut decoder = encoder;
+      let mut buffer  = samples.clone();
+      // Both halves continue from the state left by the first.
+      for half in buffer.chunks_mut(333) {
+        encoder.encode_mono(half);
+      }
+      if term == 17 {
+        assert!(buffer[100].abs() < samples[100].abs());
+      }
+      for half in buffer.chunks_mut(333) {
+        decoder.decode_mono(half);
+      }
+      assert_eq!(samples, buffer);
+    }
+  }
+
+  #[test]
+  fn stereo_passes() {
+    let left    = signal(1000);
+    let samples = left.iter().enumerate()
+                  .flat_map(|(i, &s)| vec![s, s / 2 + i as i32].into_iter())
+                  .collect::<Vec<i32>>();
+    for &term in [-3, -2, -1, 1, 2, 3, 5, 8, 17, 18].iter() {
+      let mut encoder = DecorrPass::new(term, 3);
+      let mut decoder = encoder;
+      let mut buffer  = samples.clone();
+      for block in buffer.chunks_mut(2 * 333) {
+        encoder.encode_stereo(block);
+      }
+      for block in buffer.chunks_mut(2 * 333) {
+        decoder.decode_stereo(block);
+      }
+      assert_eq!(samples, buffer);
+    }
+  }
+}
@@ -0,0 +1,43 @@
+use std::io::Write;
+use buffer::AudioBuffer;
+use codecs::Codec;
+use codecs::Codec::LPCM_I16_LE;
+use error::AudioResult;
+use traits::{AudioEncoder, Container};
+use wave::container::WaveContainer;
+use wavpack::container::WavPackContainer;
+
+/// Encodes audio to WavPack format to the provided writer.
+pub struct Encoder<'w, W: 'w> {
+  writer: &'w mut W,
+}
+
+impl<'w, W> Encoder<'w, W> where W: Write {
+  /// Create a new WavPack format `Encoder` using the provided writer.
+  #[inline]
+  pub fn new(writer: &'w mut W) -> Encoder<'w, W> {
+    Encoder {
+      writer: writer
+    }
+  }
+}
+
+impl<'w, W> AudioEncoder for Encoder<'w, W> where W: Write {
+  /// Creates and writes a `WavPackContainer` to the included writer. The audio
+  /// is losslessly compressed from 16-bit LPCM audio, unless it was restored
+  /// from a WAVE file, whose codec is used instead.
+  #[inline]
+  fn encode(&mut self, audio: &AudioBuffer) -> AudioResult<()> {
+    let codec = audio.metadata.riff.as_ref()
+                .and_then(WaveContainer::wrapper_codec)
+                .unwrap_or(LPCM_I16_LE);
+    WavPackContainer::create(&mut self.writer, audio, codec)
+  }
+  /// Creates and writes a `WavPackContainer` using the provided `SampleFormat`
+  /// to the included writer. This is how audio can be encoded to different
+  /// bit rates supported by the format.
+  #[inline]
+  fn encode_as(&mut self, audio: &AudioBuffer, codec: Codec) -> AudioResult<()> {
+    WavPackContainer::create(&mut self.writer, audio, codec)
+  }
+}
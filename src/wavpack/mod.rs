@@ -0,0 +1,235 @@
+//! The WavPack Format
+//!
+//! WavPack files are a sequence of blocks, each holding up to a few seconds
+//! of one or two channels. Audio with more channels is coded as several
+//! blocks for each range of frames, from an initial to a final block. The
+//! header of each block gives the format of the samples, and is followed by
+//! metadata sub-blocks holding the state of the decoder and the coded
+//! residuals, so each block can be decoded on its own.
+//!
+//! Integer samples of 1 to 4 bytes and 32-bit floating-point samples are
+//! coded losslessly. The header and trailer of the WAVE file the audio was
+//! compressed from are stored in the first and last blocks, and the
+//! metadata of the audio is read from the chunks they contain. They are
+//! kept with the audio, so saving it to WAVE restores the original file.
+//!
+//! Hybrid files, which hold a lossy stream with an optional correction
+//! file, and DSD audio are not supported.
+//!
+//! References
+//! - [WavPack 5 File Format](http://www.wavpack.com/WavPack5FileFormat.pdf)
+//! - [WavPack Source](https://github.com/dbry/WavPack)
+
+mod bits;
+mod block;
+mod container;
+mod decorr;
+mod pack;
+mod unpack;
+mod words;
+pub mod decoder;
+pub mod encoder;
+
+pub use wavpack::decoder::Decoder as Decoder;
+pub use wavpack::encoder::Encoder as Encoder;
+
+/// Identifier of each block.
+const WVPK: &'static [u8; 4] = b"wvpk";
+
+/// Oldest and newest versions of the block format that can be decoded.
+const MIN_VERSION: u16 = 0x402;
+const MAX_VERSION: u16 = 0x410;
+
+/// Version of the blocks written by the encoder.
+const VERSION: u16 = 0x407;
+
+/// Block header flags.
+const BYTES_STORED:   u32 = 0x3;
+const MONO_FLAG:      u32 = 0x4;
+const HYBRID_FLAG:    u32 = 0x8;
+const JOINT_STEREO:   u32 = 0x10;
+const CROSS_DECORR:   u32 = 0x20;
+const FLOAT_DATA:     u32 = 0x80;
+const INT32_DATA:     u32 = 0x100;
+const INITIAL_BLOCK:  u32 = 0x800;
+const FINAL_BLOCK:    u32 = 0x1000;
+const SHIFT_LSB:      u32 = 13;
+const SHIFT_MASK:     u32 = 0x1F << SHIFT_LSB;
+const MAG_LSB:        u32 = 18;
+const MAG_MASK:       u32 = 0x1F << MAG_LSB;
+const SRATE_LSB:      u32 = 23;
+const SRATE_MASK:     u32 = 0xF << SRATE_LSB;
+const FALSE_STEREO:   u32 = 0x40000000;
+const DSD_FLAG:       u32 = 0x80000000;
+
+/// Sample rates given by the sample rate index of the block header. Other
+/// sample rates are stored in a sub-block.
+const SAMPLE_RATES: [u32; 15] = [
+  6000, 8000, 9600, 11025, 12000, 16000, 22050, 24000,
+  32000, 44100, 48000, 64000, 88200, 96000, 192000
+];
+
+/// Sub-block functions, where functions from 0x20 are optional.
+const ID_DECORR_TERMS:    u8 = 0x02;
+const ID_DECORR_WEIGHTS:  u8 = 0x03;
+const ID_DECORR_SAMPLES:  u8 = 0x04;
+const ID_ENTROPY_VARS:    u8 = 0x05;
+const ID_FLOAT_INFO:      u8 = 0x08;
+const ID_INT32_INFO:      u8 = 0x09;
+const ID_WV_BITSTREAM:    u8 = 0x0A;
+const ID_WVX_BITSTREAM:   u8 = 0x0C;
+const ID_CHANNEL_INFO:    u8 = 0x0D;
+const ID_RIFF_HEADER:     u8 = 0x21;
+const ID_RIFF_TRAILER:    u8 = 0x22;
+const ID_SAMPLE_RATE:     u8 = 0x27;
+
+/// Flags of the float information sub-block, describing how the bits lost
+/// when scaling floating-point samples to integers are restored.
+const FLOAT_SHIFT_ONES:   u8 = 0x01;
+const FLOAT_SHIFT_SAME:   u8 = 0x02;
+const FLOAT_SHIFT_SENT:   u8 = 0x04;
+const FLOAT_ZEROS_SENT:   u8 = 0x08;
+const FLOAT_NEG_ZEROS:    u8 = 0x10;
+const FLOAT_EXCEPTIONS:   u8 = 0x20;
+
+#[cfg(test)]
+mod io {
+  use std::fs::File;
+  use std::io::{Cursor, Read};
+  use std::path::Path;
+  use ::audio;
+  use ::audio::AudioFormat;
+  use ::buffer::AudioBuffer;
+  use ::codecs::Codec::*;
+  use ::codecs::Codec;
+  use ::testing::sines;
+
+  fn round_trip(audio: &AudioBuffer, codec: Codec) -> AudioBuffer {
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write_as(&mut bytes, audio, AudioFormat::WavPack, codec).unwrap();
+    audio::load(&mut Cursor::new(bytes), AudioFormat::WavPack).unwrap()
+  }
+
+  #[test]
+  fn codecs_eq() {
+    let audio = sines(44100, 2, 30000);
+    for codec in [LPCM_U8, LPCM_I16_LE, LPCM_I24_LE, LPCM_I32_LE, LPCM_F32_LE].iter() {
+      let expected = AudioBuffer::from_bytes(audio.sample_rate, audio.channels,
+        &::codecs::encode(&audio, *codec).unwrap(), *codec).unwrap();
+      let verify = round_trip(&audio, *codec);
+      assert_eq!(audio.sample_rate, verify.sample_rate);
+      assert_eq!(audio.channels,    verify.channels);
+      assert_eq!(expected.samples,  verify.samples);
+    }
+  }
+
+  #[test]
+  fn channels_eq() {
+    for channels in 1..6 {
+      let audio = sines(22050, channels, 12000);
+      let verify = round_trip(&audio, LPCM_I16_LE);
+      assert_eq!(channels, verify.channels);
+      let expected = AudioBuffer::from_bytes(audio.sample_rate, channels,
+        &::codecs::encode(&audio, LPCM_I16_LE).unwrap(), LPCM_I16_LE).unwrap();
+      assert_eq!(expected.samples, verify.samples);
+    }
+  }
+
+  #[test]
+  fn custom_sample_rate() {
+    let audio = sines(37800, 1, 1000);
+    let verify = round_trip(&audio, LPCM_I24_LE);
+    assert_eq!(37800, verify.sample_rate);
+    assert_eq!(audio.samples.len(), verify.samples.len());
+  }
+
+  #[test]
+  fn float_special_values() {
+    let samples = vec![
+      0f32, -0f32, 1e-40, -1e-42, 1.0, -1.0, 1e-8, 0.33333334,
+      -0.75, 3e-39, 0.99999994, -0.5
+    ];
+    let audio = AudioBuffer::from_samples(48000, 2, samples.clone());
+    let verify = round_trip(&audio, LPCM_F32_LE);
+    let bits: Vec<u32> = samples.iter().map(|s| s.to_bits()).collect();
+    let verify_bits: Vec<u32> = verify.samples.iter().map(|s| s.to_bits()).collect();
+    assert_eq!(bits, verify_bits);
+  }
+
+  #[test]
+  fn wave_round_trip() {
+    for &(path, codec) in [("tests/wav/M1F1-int16-AFsp.wav",   LPCM_I16_LE),
+                           ("tests/wav/M1F1-int24-AFsp.wav",   LPCM_I24_LE),
+                           ("tests/wav/M1F1-float32-AFsp.wav", LPCM_F32_LE),
+                           ("tests/wav/M1F1-uint8-AFsp.wav",   LPCM_U8)].iter() {
+      let path = Path::new(path);
+      let audio = audio::open(&path).unwrap();
+      let mut packed: Vec<u8> = Vec::new();
+      audio::write_as(&mut packed, &audio, AudioFormat::WavPack, codec).unwrap();
+      let verify = audio::load(&mut Cursor::new(packed), AudioFormat::WavPack).unwrap();
+      assert_eq!(audio.samples, verify.samples);
+      assert_eq!(audio.metadata.chunks, verify.metadata.chunks);
+
+      let mut read_bytes = Vec::new();
+      File::open(&path).unwrap().read_to_end(&mut read_bytes).unwrap();
+      let mut written_bytes = Vec::new();
+      audio::write(&mut written_bytes, &verify, AudioFormat::WAVE).unwrap();
+      assert_eq!(read_bytes, written_bytes);
+    }
+  }
+
+  #[test]
+  fn read_wavpack_files() {
+    for &(path, source, channels) in [
+      ("tests/wavpack/M1F1-int16-AFsp.wv",   "tests/wav/M1F1-int16-AFsp.wav",   2),
+      ("tests/wavpack/M1F1-int24-AFsp.wv",   "tests/wav/M1F1-int24-AFsp.wav",   2),
+      ("tests/wavpack/M1F1-float32-AFsp.wv", "tests/wav/M1F1-float32-AFsp.wav", 2),
+      ("tests/wavpack/sine-5.1.wv",          "tests/wavpack/sine-5.1.wav",      6)
+    ].iter() {
+      let audio = audio::open(Path::new(path)).unwrap();
+      let expected = audio::open(Path::new(source)).unwrap();
+      assert_eq!(channels,             audio.channels);
+      assert_eq!(expected.sample_rate, audio.sample_rate);
+      assert_eq!(expected.samples,     audio.samples);
+
+      let mut read_bytes = Vec::new();
+      File::open(&Path::new(source)).unwrap().read_to_end(&mut read_bytes).unwrap();
+      let mut written_bytes = Vec::new();
+      audio::write(&mut written_bytes, &audio, AudioFormat::WAVE).unwrap();
+      assert_eq!(read_bytes, written_bytes);
+    }
+  }
+
+  #[test]
+  fn changed_audio_is_rewritten() {
+    let mut audio = audio::open(Path::new("tests/wav/M1F1-int16-AFsp.wav")).unwrap();
+    let mut packed: Vec<u8> = Vec::new();
+    audio::write(&mut packed, &audio, AudioFormat::WavPack).unwrap();
+    let mut verify = audio::load(&mut Cursor::new(packed), AudioFormat::WavPack).unwrap();
+    verify.samples.truncate(1000);
+    audio.samples.truncate(1000);
+    verify.metadata.ixml = Some("<BWFXML></BWFXML>".to_string());
+    let mut bytes: Vec<u8> = Vec::new();
+    audio::write(&mut bytes, &verify, AudioFormat::WAVE).unwrap();
+    let written = audio::load(&mut Cursor::new(bytes), AudioFormat::WAVE).unwrap();
+    assert_eq!(audio.samples, written.samples);
+    assert_eq!(verify.metadata.ixml, written.metadata.ixml);
+  }
+
+  #[test]
+  fn errors() {
+    let audio = sines(44100, 1, 100);
+    let mut bytes: Vec<u8> = Vec::new();
+    assert!(audio::write_as(&mut bytes, &audio, AudioFormat::WavPack, LPCM_F64_LE).is_err());
+    assert!(audio::load(&mut Cursor::new(vec![0u8; 64]), AudioFormat::WavPack).is_err());
+
+    // Hybrid blocks are not supported.
+    bytes.clear();
+    audio::write(&mut bytes, &audio, AudioFormat::WavPack).unwrap();
+    bytes[24] |= 0x08;
+    assert!(audio::load(&mut Cursor::new(bytes.clone()), AudioFormat::WavPack).is_err());
+    bytes[24] &= !0x08;
+    bytes[28] ^= 0x01;
+    assert!(audio::load(&mut Cursor::new(bytes), AudioFormat::WavPack).is_err());
+  }
+}
@@ -0,0 +1,329 @@
+//! WavPack Block Encoding
+//!
+//! Blocks are encoded losslessly with the default decorrelation passes of
+//! WavPack and joint stereo. Trailing zero bits shared by all samples of a
+//! block are shifted out. The lowest bits of 32-bit integer samples, which
+//! the entropy coder would not compress, and the bits of floating-point
+//! samples lost when scaling them to integers, are stored in the `wvx`
+//! bitstream.
+use wavpack::*;
+use wavpack::bits::BitWriter;
+use wavpack::block::{BlockHeader, push_u32, write_sub_block};
+use wavpack::decorr::{DecorrPass, MAX_TERM, store_weight, restore_weight};
+use wavpack::unpack::checksum;
+use wavpack::words::{exp2s, log2s, write_medians, WordsEncoder};
+
+/// Decorrelation terms of stereo blocks, in the order they are applied.
+const STEREO_TERMS: [i32; 5] = [18, 18, 2, 3, -2];
+
+/// Decorrelation terms of mono blocks, in the order they are applied.
+const MONO_TERMS: [i32; 4] = [18, 18, 2, 3];
+
+/// Rate at which the weights of the passes adapt.
+const DELTA: i32 = 2;
+
+/// Encodes the blocks of one or two channels, keeping the state of the
+/// encoder from block to block.
+pub struct Packer {
+  stereo: bool,
+  passes: Vec<DecorrPass>,
+  words:  WordsEncoder
+}
+
+impl Packer {
+  pub fn new(stereo: bool) -> Packer {
+    let terms: &[i32] = if stereo { &STEREO_TERMS } else { &MONO_TERMS };
+    Packer {
+      stereo: stereo,
+      passes: terms.iter().map(|&term| DecorrPass::new(term, DELTA)).collect(),
+      words:  WordsEncoder::new()
+    }
+  }
+
+  /// Encodes the samples of a block, interleaved if the block holds two
+  /// channels, and appends the block to the bytes. The header gives the
+  /// position of the block and its format flags, and the metadata holds
+  /// any other sub-blocks of the block.
+  pub fn pack(&mut self, mut header: BlockHeader, samples: &[i32], metadata: &[u8], bytes: &mut Vec<u8>) {
+    let channels = if self.stereo { 2 } else { 1 };
+    let mut flags = header.flags & !(SHIFT_MASK | MAG_MASK | JOINT_STEREO | CROSS_DECORR);
+    let mut sub_blocks = metadata.to_vec();
+    let mut values = samples.to_vec();
+
+    let mut extra: Option<(u32, BitWriter)> = None;
+    let magnitude;
+    if flags & FLOAT_DATA != 0 {
+      let info = scan_float_values(&mut values);
+      if info.flags & (FLOAT_EXCEPTIONS | FLOAT_ZEROS_SENT | FLOAT_SHIFT_SENT | FLOAT_SHIFT_SAME) != 0 {
+        let mut bits = BitWriter::new();
+        send_float_values(samples, &info, &mut bits);
+        extra = Some((info.crc, bits));
+      }
+      write_sub_block(ID_FLOAT_INFO, &[info.flags, info.shift, info.max_exp, 127], &mut sub_blocks);
+      magnitude = info.magnitude;
+    } else {
+      // Zeros in the lowest bits of every sample are shifted out.
+      let ordata = values.iter().fold(0, |ordata, &value| ordata | value);
+      let shift  = if ordata == 0 { 0 } else { ordata.trailing_zeros() };
+      for value in values.iter_mut() {
+        *value >>= shift;
+      }
+      flags |= shift << SHIFT_LSB;
+      let magdata = values.iter().fold(0, |magdata, &value|
+        magdata | if value < 0 { !value } else { value }
+      );
+      let mut bits = 32 - magdata.leading_zeros();
+      if bits > 23 {
+        let sent = bits - 23;
+        let mut extra_bits = BitWriter::new();
+        let mut crc = 0xFFFFFFFFu32;
+        for value in values.iter_mut() {
+          crc = crc.wrapping_mul(9)
+                   .wrapping_add((*value as u32 & 0xFFFF).wrapping_mul(3))
+                   .wrapping_add(*value as u32 >> 16 & 0xFFFF);
+          extra_bits.write(*value as u64, sent);
+          *value >>= sent;
+        }
+        extra = Some((crc, extra_bits));
+        write_sub_block(ID_INT32_INFO, &[sent as u8, 0, 0, 0], &mut sub_blocks);
+        flags |= INT32_DATA;
+        bits = 23;
+      }
+      magnitude = bits;
+    }
+    flags |= magnitude.min(31) << MAG_LSB;
+    header.crc = checksum(&values);
+
+    if self.stereo {
+      flags |= JOINT_STEREO;
+      for frame in values.chunks_mut(2) {
+        frame[0] = frame[0].wrapping_sub(frame[1]);
+        frame[1] = frame[1].wrapping_add(frame[0] >> 1);
+      }
+    }
+    if self.passes.iter().any(|pass| pass.term < 0) {
+      flags |= CROSS_DECORR;
+    }
+
+    // The state of the encoder is stored at the start of the block, and
+    // replaced with the values the decoder reads from it.
+    let terms: Vec<u8> = self.passes.iter()
+                         .map(|pass| ((pass.term + 5) & 0x1F) as u8 | (pass.delta << 5) as u8)
+                         .collect();
+    let mut weights: Vec<u8> = Vec::new();
+    let mut history: Vec<u8> = Vec::new();
+    for pass in self.passes.iter_mut() {
+      weights.push(store_weight(pass.weight_a) as u8);
+      pass.weight_a = restore_weight(weights[weights.len() - 1] as i8);
+      if self.stereo {
+        weights.push(store_weight(pass.weight_b) as u8);
+        pass.weight_b = restore_weight(weights[weights.len() - 1] as i8);
+      }
+      let len = pass.history_len();
+      if pass.term < 0 {
+        store_sample(&mut pass.samples_a[0], &mut history);
+        store_sample(&mut pass.samples_b[0], &mut history);
+      } else if pass.term > MAX_TERM as i32 {
+        for i in 0..len {
+          store_sample(&mut pass.samples_a[i], &mut history);
+        }
+        if self.stereo {
+          for i in 0..len {
+            store_sample(&mut pass.samples_b[i], &mut history);
+          }
+        }
+      } else {
+        for i in 0..len {
+          store_sample(&mut pass.samples_a[i], &mut history);
+          if self.stereo {
+            store_sample(&mut pass.samples_b[i], &mut history);
+          }
+        }
+      }
+    }
+    let medians = write_medians(&mut self.words.medians, channels);
+    write_sub_block(ID_DECORR_TERMS, &terms, &mut sub_blocks);
+    write_sub_block(ID_DECORR_WEIGHTS, &weights, &mut sub_blocks);
+    write_sub_block(ID_DECORR_SAMPLES, &history, &mut sub_blocks);
+    write_sub_block(ID_ENTROPY_VARS, &medians, &mut sub_blocks);
+
+    for pass in self.passes.iter_mut() {
+      if self.stereo {
+        pass.encode_stereo(&mut values);
+      } else {
+        pass.encode_mono(&mut values);
+      }
+    }
+    let mut bits = BitWriter::new();
+    self.words.reset();
+    for (i, &value) in values.iter().enumerate() {
+      self.words.write(&mut bits, value, i & (channels - 1));
+    }
+    self.words.flush(&mut bits);
+    write_sub_block(ID_WV_BITSTREAM, &bits.finish(), &mut sub_blocks);
+    if let Some((crc, extra_bits)) = extra {
+      let mut data = Vec::new();
+      push_u32(&mut data, crc);
+      data.extend_from_slice(&extra_bits.finish());
+      write_sub_block(ID_WVX_BITSTREAM, &data, &mut sub_blocks);
+    }
+
+    header.flags = flags;
+    header.write(&sub_blocks, bytes);
+  }
+}
+
+/// Stores a previous sample of a pass as a logarithm, replacing it with the
+/// value the decoder reads.
+#[inline]
+fn store_sample(sample: &mut i32, bytes: &mut Vec<u8>) {
+  let log = log2s(*sample);
+  bytes.push(log as u8);
+  bytes.push((log >> 8) as u8);
+  *sample = exp2s(log);
+}
+
+/// How the floating-point samples of a block are scaled to integers.
+struct FloatInfo {
+  flags:      u8,
+  shift:      u8,
+  max_exp:    u8,
+  /// Number of bits of the largest scaled sample
+  magnitude:  u32,
+  /// Checksum of the floating-point samples
+  crc:        u32
+}
+
+#[inline]
+fn split_float(bits: i32) -> (u32, i32, bool) {
+  (bits as u32 & 0x7FFFFF, (bits >> 23) & 0xFF, bits < 0)
+}
+
+/// Returns the mantissa of a floating-point sample scaled to the largest
+/// exponent, and the number of bits shifted out.
+#[inline]
+fn scale_float(mantissa: u32, exponent: i32, max_exp: i32) -> (i32, i32) {
+  let (value, shift_count) =
+    if exponent == 255 {
+      (0x1000000, 0)
+    } else if exponent > 0 {
+      (0x800000 + mantissa as i32, max_exp - exponent)
+    } else {
+      (mantissa as i32, if max_exp > 0 { max_exp - 1 } else { 0 })
+    };
+  (if shift_count < 25 { value >> shift_count } else { 0 }, shift_count)
+}
+
+/// Replaces the bits of floating-point samples with their mantissas scaled
+/// to the largest exponent of the block, and returns how the bits lost in
+/// scaling are stored.
+fn scan_float_values(values: &mut [i32]) -> FloatInfo {
+  let mut crc     = 0xFFFFFFFFu32;
+  let mut max_exp = 0;
+  for &value in values.iter() {
+    let (mantissa, exponent, sign) = split_float(value);
+    crc = crc.wrapping_mul(27)
+             .wrapping_add(mantissa.wrapping_mul(9))
+             .wrapping_add(exponent as u32 * 3)
+             .wrapping_add(sign as u32);
+    if exponent > max_exp && exponent < 255 {
+      max_exp = exponent;
+    }
+  }
+
+  let (mut shifted_ones, mut shifted_zeros, mut shifted_both) = (0, 0, 0);
+  let (mut false_zeros, mut neg_zeros) = (0, 0);
+  let mut ordata = 0;
+  let mut flags  = 0;
+  for sample in values.iter_mut() {
+    let (mantissa, exponent, sign) = split_float(*sample);
+    if exponent == 255 {
+      flags |= FLOAT_EXCEPTIONS;
+    }
+    let (value, shift_count) = scale_float(mantissa, exponent, max_exp);
+    if value == 0 {
+      if exponent != 0 || mantissa != 0 {
+        false_zeros += 1;
+      } else if sign {
+        neg_zeros += 1;
+      }
+    } else if shift_count > 0 {
+      let mask = (1 << shift_count) - 1;
+      if mantissa & mask == 0 {
+        shifted_zeros += 1;
+      } else if mantissa & mask == mask {
+        shifted_ones += 1;
+      } else {
+        shifted_both += 1;
+      }
+    }
+    ordata |= value;
+    *sample = if sign { -value } else { value };
+  }
+
+  let mut shift = 0;
+  if shifted_both > 0 {
+    flags |= FLOAT_SHIFT_SENT;
+  } else if shifted_ones > 0 && shifted_zeros == 0 {
+    flags |= FLOAT_SHIFT_ONES;
+  } else if shifted_ones > 0 && shifted_zeros > 0 {
+    flags |= FLOAT_SHIFT_SAME;
+  } else if ordata != 0 && ordata & 1 == 0 {
+    shift = ordata.trailing_zeros();
+    ordata >>= shift;
+    for sample in values.iter_mut() {
+      *sample >>= shift;
+    }
+  }
+  if false_zeros > 0 || neg_zeros > 0 {
+    flags |= FLOAT_ZEROS_SENT;
+  }
+  if neg_zeros > 0 {
+    flags |= FLOAT_NEG_ZEROS;
+  }
+  FloatInfo {
+    flags:      flags,
+    shift:      shift as u8,
+    max_exp:    max_exp as u8,
+    magnitude:  32 - ordata.leading_zeros(),
+    crc:        crc
+  }
+}
+
+/// Writes the bits of floating-point samples lost in scaling them.
+fn send_float_values(samples: &[i32], info: &FloatInfo, bits: &mut BitWriter) {
+  let max_exp = info.max_exp as i32;
+  for &sample in samples.iter() {
+    let (mantissa, exponent, sign) = split_float(sample);
+    if exponent == 255 {
+      bits.write_bit(mantissa != 0);
+      if mantissa != 0 {
+        bits.write(mantissa as u64, 23);
+      }
+    }
+    let (value, shift_count) = scale_float(mantissa, exponent, max_exp);
+    if value == 0 {
+      if info.flags & FLOAT_ZEROS_SENT != 0 {
+        if exponent != 0 || mantissa != 0 {
+          bits.write_bit(true);
+          bits.write(mantissa as u64, 23);
+          if max_exp >= 25 {
+            bits.write(exponent as u64, 8);
+          }
+          bits.write_bit(sign);
+        } else {
+          bits.write_bit(false);
+          if info.flags & FLOAT_NEG_ZEROS != 0 {
+            bits.write_bit(sign);
+          }
+        }
+      }
+    } else if shift_count > 0 {
+      if info.flags & FLOAT_SHIFT_SENT != 0 {
+        bits.write(mantissa as u64 & ((1 << shift_count) - 1), shift_count as u32);
+      } else if info.flags & FLOAT_SHIFT_SAME != 0 {
+        bits.write_bit(mantissa & 1 == 1);
+      }
+    }
+  }
+}
@@ -0,0 +1,327 @@
+//! WavPack Block Decoding
+//!
+//! The residuals of a block are decoded, the decorrelation passes undone,
+//! and the stereo channels restored from their difference and average.
+//! Integer samples are then shifted back into place, and floating-point
+//! samples rebuilt from their integer mantissas using the extra bits stored
+//! in the `wvx` bitstream.
+use error::*;
+use wavpack::*;
+use wavpack::bits::BitReader;
+use wavpack::block::{BlockHeader, SubBlock};
+use wavpack::decorr::{DecorrPass, MAX_TERM, restore_weight};
+use wavpack::words::{exp2s, read_medians, WordsDecoder};
+
+/// Decodes the samples of a block, interleaved if the block holds two
+/// channels. Integer samples are given in the range of the number of bytes
+/// stored, and floating-point samples as their bits.
+pub fn unpack(header: &BlockHeader, sub_blocks: &[SubBlock]) -> AudioResult<Vec<i32>> {
+  let flags = header.flags;
+  if flags & HYBRID_FLAG != 0 {
+    return Err(AudioError::Unsupported(
+      "Decoding hybrid WavPack is not supported".to_string()
+    ));
+  }
+  if flags & DSD_FLAG != 0 {
+    return Err(AudioError::Unsupported(
+      "Decoding DSD WavPack is not supported".to_string()
+    ));
+  }
+  let stereo   = flags & (MONO_FLAG | FALSE_STEREO) == 0;
+  let channels = if stereo { 2 } else { 1 };
+
+  // The passes are kept in the order they are undone, which is the reverse
+  // of the order they are stored.
+  let mut passes: Vec<DecorrPass> = Vec::new();
+  if let Some(terms) = find(sub_blocks, ID_DECORR_TERMS) {
+    for &byte in terms.iter().rev() {
+      let term  = (byte & 0x1F) as i32 - 5;
+      let delta = (byte >> 5) as i32 & 0x7;
+      if !DecorrPass::is_valid_term(term, stereo) {
+        return Err(AudioError::Format(
+          format!("File is not valid WavPack (Invalid decorrelation term {})", term)
+        ));
+      }
+      passes.push(DecorrPass::new(term, delta));
+    }
+  }
+  if let Some(weights) = find(sub_blocks, ID_DECORR_WEIGHTS) {
+    if weights.len() % channels != 0 || weights.len() / channels > passes.len() {
+      return Err(AudioError::Format(
+        "File is not valid WavPack (Invalid decorrelation weights)".to_string()
+      ));
+    }
+    for (pass, weight) in passes.iter_mut().rev().zip(weights.chunks(channels)) {
+      pass.weight_a = restore_weight(weight[0] as i8);
+      if stereo {
+        pass.weight_b = restore_weight(weight[1] as i8);
+      }
+    }
+  }
+  if let Some(samples) = find(sub_blocks, ID_DECORR_SAMPLES) {
+    try!(read_decorr_samples(samples, &mut passes, stereo));
+  }
+  let medians =
+    match find(sub_blocks, ID_ENTROPY_VARS) {
+      Some(vars) => try!(read_medians(vars, channels)),
+      None       => [[0u32; 3]; 2]
+    };
+
+  let count = header.block_samples as usize;
+  let mut buffer = vec![0i32; count * channels];
+  if count > 0 {
+    let bitstream =
+      match find(sub_blocks, ID_WV_BITSTREAM) {
+        Some(bitstream) => bitstream,
+        None =>
+          return Err(AudioError::Format(
+            "File is not valid WavPack (Missing bitstream)".to_string()
+          ))
+      };
+    try!(WordsDecoder::new(medians).read(&mut BitReader::new(bitstream), &mut buffer, stereo));
+  }
+  for pass in passes.iter_mut() {
+    if stereo {
+      pass.decode_stereo(&mut buffer);
+    } else {
+      pass.decode_mono(&mut buffer);
+    }
+  }
+  if stereo && flags & JOINT_STEREO != 0 {
+    for frame in buffer.chunks_mut(2) {
+      frame[1] = frame[1].wrapping_sub(frame[0] >> 1);
+      frame[0] = frame[0].wrapping_add(frame[1]);
+    }
+  }
+  if checksum(&buffer) != header.crc {
+    return Err(AudioError::Format(
+      "File is not valid WavPack (Block checksum does not match)".to_string()
+    ));
+  }
+
+  // The extra bits of the samples are stored after a checksum.
+  let mut extra = find(sub_blocks, ID_WVX_BITSTREAM)
+                  .filter(|wvx| wvx.len() >= 4)
+                  .map(|wvx| BitReader::new(&wvx[4..]));
+  if flags & FLOAT_DATA != 0 {
+    let info =
+      match find(sub_blocks, ID_FLOAT_INFO) {
+        Some(info) if info.len() >= 4 => info,
+        _ =>
+          return Err(AudioError::Format(
+            "File is not valid WavPack (Missing float information)".to_string()
+          ))
+      };
+    float_values(&mut buffer, info, extra.as_mut());
+  } else {
+    let mut shift = (flags & SHIFT_MASK) >> SHIFT_LSB;
+    if let Some(info) = find(sub_blocks, ID_INT32_INFO).filter(|info| info.len() >= 4) {
+      match extra {
+        Some(ref mut extra) => int32_values(&mut buffer, info, extra),
+        None => {
+          shift += info.iter().take(4).map(|&bits| bits as u32).sum::<u32>();
+        }
+      }
+    }
+    shift_values(&mut buffer, shift.min(31), (flags & BYTES_STORED) + 1);
+  }
+
+  // Identical channels of a stereo block are stored once.
+  if flags & FALSE_STEREO != 0 && flags & MONO_FLAG == 0 {
+    buffer = buffer.iter().flat_map(|&sample| vec![sample, sample].into_iter()).collect();
+  }
+  Ok(buffer)
+}
+
+/// Returns the checksum of the decoded integer samples of a block.
+pub fn checksum(samples: &[i32]) -> u32 {
+  samples.iter().fold(0xFFFFFFFF, |crc: u32, &sample|
+    crc.wrapping_mul(3).wrapping_add(sample as u32)
+  )
+}
+
+/// Returns the data of the first sub-block with the given function.
+#[inline]
+fn find<'a>(sub_blocks: &[SubBlock<'a>], id: u8) -> Option<&'a [u8]> {
+  sub_blocks.iter().find(|sub_block| sub_block.id == id).map(|sub_block| sub_block.data)
+}
+
+/// Reads the previous samples of the passes, stored as logarithms in the
+/// order the passes are applied by the encoder.
+fn read_decorr_samples(bytes: &[u8], passes: &mut [DecorrPass], stereo: bool) -> AudioResult<()> {
+  let mut logs = bytes.chunks(2).map(|log| exp2s((log[0] as u16 | (log[1] as u16) << 8) as i16 as i32));
+  let invalid = || AudioError::Format(
+    "File is not valid WavPack (Invalid decorrelation samples)".to_string()
+  );
+  if bytes.len() % 2 != 0 {
+    return Err(invalid());
+  }
+  for pass in passes.iter_mut().rev() {
+    if logs.len() == 0 {
+      break;
+    }
+    let len = pass.history_len();
+    if pass.term < 0 {
+      pass.samples_a[0] = try!(logs.next().ok_or_else(&invalid));
+      pass.samples_b[0] = try!(logs.next().ok_or_else(&invalid));
+    } else if pass.term > MAX_TERM as i32 {
+      for i in 0..len {
+        pass.samples_a[i] = try!(logs.next().ok_or_else(&invalid));
+      }
+      if stereo {
+        for i in 0..len {
+          pass.samples_b[i] = try!(logs.next().ok_or_else(&invalid));
+        }
+      }
+    } else {
+      for i in 0..len {
+        pass.samples_a[i] = try!(logs.next().ok_or_else(&invalid));
+        if stereo {
+          pass.samples_b[i] = try!(logs.next().ok_or_else(&invalid));
+        }
+      }
+    }
+  }
+  if logs.len() != 0 {
+    return Err(invalid());
+  }
+  Ok(())
+}
+
+/// Restores the lowest bits of 32-bit integer samples, which are stored in
+/// the `wvx` bitstream, along with any identical lowest bits.
+fn int32_values(buffer: &mut [i32], info: &[u8], extra: &mut BitReader) {
+  let (sent, zeros, ones, dups) = (info[0] as u32, info[1] as u32, info[2] as u32, info[3] as u32);
+  for sample in buffer.iter_mut() {
+    let mut value = *sample;
+    if sent > 0 {
+      value = value.wrapping_shl(sent) | extra.read(sent) as i32 & ((1i64 << sent) - 1) as i32;
+    }
+    if zeros > 0 {
+      value = value.wrapping_shl(zeros);
+    } else if ones > 0 {
+      value = value.wrapping_add(1).wrapping_shl(ones).wrapping_sub(1);
+    } else if dups > 0 {
+      let low = value & 1;
+      value = value.wrapping_add(low).wrapping_shl(dups).wrapping_sub(low);
+    }
+    *sample = value;
+  }
+}
+
+/// Shifts integer samples back into place, clipping them to the range of
+/// the number of bytes stored.
+fn shift_values(buffer: &mut [i32], shift: u32, bytes: u32) {
+  if shift == 0 {
+    return;
+  }
+  let max: i32 = ((1i64 << (bytes * 8 - 1)) - 1) as i32;
+  let min: i32 = -max - 1;
+  let (min_value, max_value) = (min >> shift, max >> shift);
+  for sample in buffer.iter_mut() {
+    *sample =
+      if *sample < min_value {
+        min_value << shift
+      } else if *sample > max_value {
+        max_value << shift
+      } else {
+        *sample << shift
+      };
+  }
+}
+
+/// Rebuilds floating-point samples from their integer mantissas, which are
+/// scaled to the largest exponent of the block.
+fn float_values(buffer: &mut [i32], info: &[u8], mut extra: Option<&mut BitReader>) {
+  let (float_flags, float_shift, max_exp) = (info[0], info[1] as u32, info[2] as i32);
+  for sample in buffer.iter_mut() {
+    let mut value       = *sample;
+    let mut exponent    = max_exp;
+    let mut mantissa    = 0u32;
+    let mut sign        = 0u32;
+    let mut shift_count = 0;
+    match extra {
+      Some(ref mut extra) => {
+        if value == 0 {
+          exponent = 0;
+          if float_flags & FLOAT_ZEROS_SENT != 0 {
+            if extra.read_bit() {
+              mantissa = extra.read(23);
+              if max_exp >= 25 {
+                exponent = extra.read(8) as i32;
+              }
+              sign = extra.read_bit() as u32;
+            } else if float_flags & FLOAT_NEG_ZEROS != 0 {
+              sign = extra.read_bit() as u32;
+            }
+          }
+        } else {
+          value = value.wrapping_shl(float_shift);
+          if value < 0 {
+            value = value.wrapping_neg();
+            sign  = 1;
+          }
+          if value == 0x1000000 {
+            // Infinities and NaNs
+            if extra.read_bit() {
+              mantissa = extra.read(23);
+            }
+            exponent = 255;
+          } else {
+            if exponent != 0 {
+              while value & 0x800000 == 0 {
+                exponent -= 1;
+                if exponent == 0 {
+                  break;
+                }
+                shift_count += 1;
+                value <<= 1;
+              }
+            }
+            if shift_count > 0 {
+              let mask = (1 << shift_count) - 1;
+              if float_flags & FLOAT_SHIFT_ONES != 0
+                 || (float_flags & FLOAT_SHIFT_SAME != 0 && extra.read_bit()) {
+                value |= mask;
+              } else if float_flags & FLOAT_SHIFT_SENT != 0 {
+                value |= extra.read(shift_count) as i32 & mask;
+              }
+            }
+            mantissa = value as u32;
+          }
+        }
+      },
+      None => {
+        if value == 0 {
+          exponent = 0;
+        } else {
+          value = value.wrapping_shl(float_shift);
+          if value < 0 {
+            value = value.wrapping_neg();
+            sign  = 1;
+          }
+          if value >= 0x1000000 {
+            while value & 0xF000000 != 0 {
+              value >>= 1;
+              exponent += 1;
+            }
+          } else if exponent != 0 {
+            while value & 0x800000 == 0 {
+              exponent -= 1;
+              if exponent == 0 {
+                break;
+              }
+              shift_count += 1;
+              value <<= 1;
+            }
+            if shift_count > 0 && float_flags & FLOAT_SHIFT_ONES != 0 {
+              value |= (1 << shift_count) - 1;
+            }
+          }
+          mantissa = value as u32;
+        }
+      }
+    }
+    *sample = (sign << 31 | (exponent as u32 & 0xFF) << 23 | mantissa & 0x7FFFFF) as i32;
+  }
+}
@@ -0,0 +1,513 @@
+//! WavPack Entropy Coding
+//!
+//! Residuals are coded with an adaptive code driven by three running
+//! medians per channel. A unary count of ones selects the range of the
+//! magnitude between the medians, the offset within the range is coded in
+//! the fewest bits able to represent it, and a sign bit follows. The unary
+//! counts are held back by one word so that their terminating zeros can be
+//! shared, and runs of zeros in silent passages are coded by their length.
+//!
+//! The medians, along with the decorrelation samples, are stored at the
+//! start of each block as base 2 logarithms with 8 fractional bits.
+use error::*;
+use wavpack::bits::{BitReader, BitWriter};
+
+/// Unary counts of this many ones are followed by an escape code.
+const LIMIT_ONES: u32 = 16;
+
+/// Fractional bits of the base 2 logarithm of `1 + i / 256`.
+const LOG2_TABLE: [u8; 256] = [
+  0x00, 0x01, 0x03, 0x04, 0x06, 0x07, 0x09, 0x0a, 0x0b, 0x0d, 0x0e, 0x10, 0x11, 0x12, 0x14, 0x15,
+  0x16, 0x18, 0x19, 0x1a, 0x1c, 0x1d, 0x1e, 0x20, 0x21, 0x22, 0x24, 0x25, 0x26, 0x28, 0x29, 0x2a,
+  0x2c, 0x2d, 0x2e, 0x2f, 0x31, 0x32, 0x33, 0x34, 0x36, 0x37, 0x38, 0x39, 0x3b, 0x3c, 0x3d, 0x3e,
+  0x3f, 0x41, 0x42, 0x43, 0x44, 0x45, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4d, 0x4e, 0x4f, 0x50, 0x51,
+  0x52, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x5c, 0x5d, 0x5e, 0x5f, 0x60, 0x61, 0x62, 0x63,
+  0x64, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x70, 0x71, 0x72, 0x74, 0x75,
+  0x76, 0x77, 0x78, 0x79, 0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f, 0x80, 0x81, 0x82, 0x83, 0x84, 0x85,
+  0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d, 0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95,
+  0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b, 0x9b, 0x9c, 0x9d, 0x9e, 0x9f, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4,
+  0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xa9, 0xaa, 0xab, 0xac, 0xad, 0xae, 0xaf, 0xb0, 0xb1, 0xb2, 0xb2,
+  0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xb9, 0xba, 0xbb, 0xbc, 0xbd, 0xbe, 0xbf, 0xc0, 0xc0,
+  0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcb, 0xcc, 0xcd, 0xce,
+  0xcf, 0xd0, 0xd0, 0xd1, 0xd2, 0xd3, 0xd4, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd8, 0xd9, 0xda, 0xdb,
+  0xdc, 0xdc, 0xdd, 0xde, 0xdf, 0xe0, 0xe0, 0xe1, 0xe2, 0xe3, 0xe4, 0xe4, 0xe5, 0xe6, 0xe7, 0xe7,
+  0xe8, 0xe9, 0xea, 0xea, 0xeb, 0xec, 0xed, 0xee, 0xee, 0xef, 0xf0, 0xf1, 0xf1, 0xf2, 0xf3, 0xf4,
+  0xf4, 0xf5, 0xf6, 0xf7, 0xf7, 0xf8, 0xf9, 0xf9, 0xfa, 0xfb, 0xfc, 0xfc, 0xfd, 0xfe, 0xff, 0xff
+];
+/// Fractional bits of `2 ^ (i / 256) - 1`.
+const EXP2_TABLE: [u8; 256] = [
+  0x00, 0x01, 0x01, 0x02, 0x03, 0x03, 0x04, 0x05, 0x06, 0x06, 0x07, 0x08, 0x08, 0x09, 0x0a, 0x0b,
+  0x0b, 0x0c, 0x0d, 0x0e, 0x0e, 0x0f, 0x10, 0x10, 0x11, 0x12, 0x13, 0x13, 0x14, 0x15, 0x16, 0x16,
+  0x17, 0x18, 0x19, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1d, 0x1e, 0x1f, 0x20, 0x20, 0x21, 0x22, 0x23,
+  0x24, 0x24, 0x25, 0x26, 0x27, 0x28, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2c, 0x2d, 0x2e, 0x2f, 0x30,
+  0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3a, 0x3b, 0x3c, 0x3d,
+  0x3e, 0x3f, 0x40, 0x41, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x48, 0x49, 0x4a, 0x4b,
+  0x4c, 0x4d, 0x4e, 0x4f, 0x50, 0x51, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a,
+  0x5b, 0x5c, 0x5d, 0x5e, 0x5e, 0x5f, 0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+  0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f, 0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79,
+  0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f, 0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x87, 0x88, 0x89, 0x8a,
+  0x8b, 0x8c, 0x8d, 0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+  0x9c, 0x9d, 0x9f, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa8, 0xa9, 0xaa, 0xab, 0xac, 0xad,
+  0xaf, 0xb0, 0xb1, 0xb2, 0xb3, 0xb4, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xbc, 0xbd, 0xbe, 0xbf, 0xc0,
+  0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc8, 0xc9, 0xca, 0xcb, 0xcd, 0xce, 0xcf, 0xd0, 0xd2, 0xd3, 0xd4,
+  0xd6, 0xd7, 0xd8, 0xd9, 0xdb, 0xdc, 0xdd, 0xde, 0xe0, 0xe1, 0xe2, 0xe4, 0xe5, 0xe6, 0xe8, 0xe9,
+  0xea, 0xec, 0xed, 0xee, 0xf0, 0xf1, 0xf2, 0xf4, 0xf5, 0xf6, 0xf8, 0xf9, 0xfa, 0xfc, 0xfd, 0xff
+];
+
+/// Returns the base 2 logarithm of a value, with 8 fractional bits.
+pub fn log2(value: u32) -> i32 {
+  let value = value.wrapping_add(value >> 9);
+  let bits  = 32 - value.leading_zeros();
+  let index =
+    if bits < 9 {
+      value << (9 - bits)
+    } else {
+      value >> (bits - 9)
+    };
+  (bits << 8) as i32 + LOG2_TABLE[(index & 0xFF) as usize] as i32
+}
+
+/// Returns the signed base 2 logarithm of a value, which keeps the sign of
+/// the value.
+#[inline]
+pub fn log2s(value: i32) -> i32 {
+  if value < 0 {
+    -log2(value.wrapping_neg() as u32)
+  } else {
+    log2(value as u32)
+  }
+}
+
+/// Returns the value of a signed base 2 logarithm.
+pub fn exp2s(log: i32) -> i32 {
+  if log < 0 {
+    return exp2s(-log).wrapping_neg();
+  }
+  let value = EXP2_TABLE[(log & 0xFF) as usize] as u32 | 0x100;
+  let shift = log >> 8;
+  if shift <= 9 {
+    (value >> (9 - shift)) as i32
+  } else if shift - 9 < 32 {
+    (value << (shift - 9)) as i32
+  } else {
+    0
+  }
+}
+
+/// Running medians of the magnitudes of the residuals of a channel.
+pub type Medians = [u32; 3];
+
+/// Divisors used to adapt each of the medians.
+const DIVISORS: [u32; 3] = [128, 64, 32];
+
+#[inline]
+fn get_median(medians: &Medians, n: usize) -> u32 {
+  (medians[n] >> 4) + 1
+}
+
+#[inline]
+fn increase_median(medians: &mut Medians, n: usize) {
+  let div = DIVISORS[n];
+  medians[n] = medians[n].wrapping_add((medians[n] + div) / div * 5);
+}
+
+#[inline]
+fn decrease_median(medians: &mut Medians, n: usize) {
+  let div = DIVISORS[n];
+  medians[n] -= (medians[n] + div - 2) / div * 2;
+}
+
+/// Returns the number of bits needed to represent a value.
+#[inline]
+fn count_bits(value: u32) -> u32 {
+  32 - value.leading_zeros()
+}
+
+/// Reads the medians of one or two channels stored at the start of a block.
+pub fn read_medians(bytes: &[u8], channels: usize) -> AudioResult<[Medians; 2]> {
+  if bytes.len() != 6 * channels {
+    return Err(AudioError::Format(
+      "File is not valid WavPack (Invalid entropy variables)".to_string()
+    ));
+  }
+  let mut medians = [[0u32; 3]; 2];
+  for (i, log) in bytes.chunks(2).enumerate() {
+    let log = log[0] as i32 | (log[1] as i32) << 8;
+    medians[i / 3][i % 3] = exp2s(log) as u32;
+  }
+  Ok(medians)
+}
+
+/// Writes the medians of one or two channels, replacing them with the
+/// values the decoder reads.
+pub fn write_medians(medians: &mut [Medians; 2], channels: usize) -> Vec<u8> {
+  let mut bytes = Vec::with_capacity(6 * channels);
+  for median in medians[..channels].iter_mut().flat_map(|m| m.iter_mut()) {
+    let log = log2(*median);
+    bytes.push(log as u8);
+    bytes.push((log >> 8) as u8);
+    *median = exp2s(log) as u32;
+  }
+  bytes
+}
+
+/// Reads an Elias gamma code, whose number of bits is given in unary and
+/// whose most significant bit is implied.
+fn read_gamma(bits: &mut BitReader) -> AudioResult<u32> {
+  let mut count = 0;
+  while count < 33 && bits.read_bit() {
+    count += 1;
+  }
+  if count == 33 {
+    return Err(AudioError::Format(
+      "File is not valid WavPack (Invalid bitstream)".to_string()
+    ));
+  }
+  if count < 2 {
+    return Ok(count);
+  }
+  Ok(bits.read(count - 1) | 1 << (count - 1))
+}
+
+/// Writes a value as an Elias gamma code.
+fn write_gamma(bits: &mut BitWriter, value: u32) {
+  let count = count_bits(value);
+  bits.write((1u64 << count) - 1, count + 1);
+  if count > 1 {
+    bits.write(value as u64, count - 1);
+  }
+}
+
+/// Reads a value from 0 to `max` coded in the fewest bits, where the
+/// smallest values use one bit less than the others.
+fn read_code(bits: &mut BitReader, max: u32) -> u32 {
+  if max < 2 {
+    return if max == 1 { bits.read_bit() as u32 } else { 0 };
+  }
+  let count  = count_bits(max);
+  let extras = (1 << count) - max - 1;
+  let code   = bits.read(count - 1);
+  if code >= extras {
+    (code << 1) - extras + bits.read_bit() as u32
+  } else {
+    code
+  }
+}
+
+/// Decodes the residuals of a block.
+pub struct WordsDecoder {
+  medians:      [Medians; 2],
+  holding_zero: bool,
+  holding_one:  bool,
+  zeros:        u32
+}
+
+impl WordsDecoder {
+  pub fn new(medians: [Medians; 2]) -> WordsDecoder {
+    WordsDecoder {
+      medians:      medians,
+      holding_zero: false,
+      holding_one:  false,
+      zeros:        0
+    }
+  }
+
+  /// Fills the buffer with residuals, which alternate between the channels
+  /// of stereo blocks.
+  pub fn read(&mut self, bits: &mut BitReader, buffer: &mut [i32], stereo: bool) -> AudioResult<()> {
+    for (i, residual) in buffer.iter_mut().enumerate() {
+      let channel = if stereo { i & 1 } else { 0 };
+      // Runs of zeros are coded while the first medians of both channels
+      // are small, and reset the medians.
+      if self.medians[0][0] < 2 && self.medians[1][0] < 2
+         && !self.holding_zero && !self.holding_one {
+        if self.zeros > 0 {
+          self.zeros -= 1;
+          if self.zeros > 0 {
+            *residual = 0;
+            continue;
+          }
+        } else {
+          self.zeros = try!(read_gamma(bits));
+          if self.zeros > 0 {
+            self.medians = [[0u32; 3]; 2];
+            *residual = 0;
+            continue;
+          }
+        }
+      }
+
+      let ones =
+        if self.holding_zero {
+          self.holding_zero = false;
+          0
+        } else {
+          let mut count = 0;
+          while count < LIMIT_ONES + 1 && bits.read_bit() {
+            count += 1;
+          }
+          if count == LIMIT_ONES + 1 {
+            return Err(AudioError::Format(
+              "File is not valid WavPack (Invalid bitstream)".to_string()
+            ));
+          }
+          if count == LIMIT_ONES {
+            count += try!(read_gamma(bits));
+          }
+          // The lowest bit of the count tells whether the next count is
+          // increased by one, or else whether it is zero and not coded.
+          let ones = if self.holding_one { (count >> 1) + 1 } else { count >> 1 };
+          self.holding_one  = count & 1 == 1;
+          self.holding_zero = !self.holding_one;
+          ones
+        };
+
+      let medians = &mut self.medians[channel];
+      let (low, high) =
+        if ones == 0 {
+          let high = get_median(medians, 0) - 1;
+          decrease_median(medians, 0);
+          (0, high)
+        } else {
+          let mut low = get_median(medians, 0);
+          increase_median(medians, 0);
+          if ones == 1 {
+            let high = low + get_median(medians, 1) - 1;
+            decrease_median(medians, 1);
+            (low, high)
+          } else {
+            low = low.wrapping_add(get_median(medians, 1));
+            increase_median(medians, 1);
+            if ones == 2 {
+              let high = low.wrapping_add(get_median(medians, 2) - 1);
+              decrease_median(medians, 2);
+              (low, high)
+            } else {
+              low = low.wrapping_add((ones - 2).wrapping_mul(get_median(medians, 2)));
+              let high = low.wrapping_add(get_median(medians, 2) - 1);
+              increase_median(medians, 2);
+              (low, high)
+            }
+          }
+        };
+      let low   = low & 0x7FFFFFFF;
+      let high  = high & 0x7FFFFFFF;
+      let value = low.wrapping_add(read_code(bits, high.wrapping_sub(low))) as i32;
+      *residual = if bits.read_bit() { !value } else { value };
+    }
+    Ok(())
+  }
+}
+
+/// Encodes the residuals of a block.
+///
+/// The code of each residual is held until the unary count of the next one
+/// is known, and must be flushed at the end of the block.
+pub struct WordsEncoder {
+  pub medians:  [Medians; 2],
+  holding_zero: bool,
+  holding_one:  u32,
+  zeros:        u32,
+  pending:      u64,
+  pending_bits: u32
+}
+
+impl WordsEncoder {
+  pub fn new() -> WordsEncoder {
+    WordsEncoder {
+      medians:      [[0u32; 3]; 2],
+      holding_zero: false,
+      holding_one:  0,
+      zeros:        0,
+      pending:      0,
+      pending_bits: 0
+    }
+  }
+
+  /// Writes the residual of a channel.
+  pub fn write(&mut self, bits: &mut BitWriter, residual: i32, channel: usize) {
+    if self.medians[0][0] < 2 && self.medians[1][0] < 2 && !self.holding_zero {
+      if self.zeros > 0 {
+        if residual != 0 {
+          self.flush(bits);
+        } else {
+          self.zeros += 1;
+          return;
+        }
+      } else if residual != 0 {
+        bits.write_bit(false);
+      } else {
+        self.medians = [[0u32; 3]; 2];
+        self.zeros   = 1;
+        return;
+      }
+    }
+
+    let sign  = residual < 0;
+    let value = (if sign { !residual } else { residual }) as u32;
+    let (mut ones, low, high) = {
+      let medians = &mut self.medians[channel];
+      if value < get_median(medians, 0) {
+        let high = get_median(medians, 0) - 1;
+        decrease_median(medians, 0);
+        (0, 0, high)
+      } else {
+        let mut low = get_median(medians, 0);
+        increase_median(medians, 0);
+        if value - low < get_median(medians, 1) {
+          let high = low + get_median(medians, 1) - 1;
+          decrease_median(medians, 1);
+          (1, low, high)
+        } else {
+          low += get_median(medians, 1);
+          increase_median(medians, 1);
+          if value - low < get_median(medians, 2) {
+            let high = low + get_median(medians, 2) - 1;
+            decrease_median(medians, 2);
+            (2, low, high)
+          } else {
+            let ones = 2 + (value - low) / get_median(medians, 2);
+            low += (ones - 2) * get_median(medians, 2);
+            let high = low + get_median(medians, 2) - 1;
+            increase_median(medians, 2);
+            (ones, low, high)
+          }
+        }
+      }
+    };
+
+    if self.holding_zero {
+      if ones > 0 {
+        self.holding_one += 1;
+      }
+      self.flush(bits);
+      if ones > 0 {
+        self.holding_zero = true;
+        ones -= 1;
+      } else {
+        self.holding_zero = false;
+      }
+    } else {
+      self.holding_zero = true;
+    }
+    self.holding_one = ones * 2;
+
+    if high != low {
+      let max    = high - low;
+      let code   = value - low;
+      let count  = count_bits(max);
+      let extras = (1 << count) - max - 1;
+      if code < extras {
+        self.pend(code as u64, count - 1);
+      } else {
+        self.pend(((code + extras) >> 1) as u64, count - 1);
+        self.pend(((code + extras) & 1) as u64, 1);
+      }
+    }
+    self.pend(sign as u64, 1);
+    if !self.holding_zero {
+      self.flush(bits);
+    }
+  }
+
+  #[inline]
+  fn pend(&mut self, value: u64, bits: u32) {
+    self.pending      |= value << self.pending_bits;
+    self.pending_bits += bits;
+  }
+
+  /// Writes the held run of zeros, unary count, and code.
+  pub fn flush(&mut self, bits: &mut BitWriter) {
+    if self.zeros > 0 {
+      write_gamma(bits, self.zeros);
+      self.zeros = 0;
+    }
+    if self.holding_one > 0 {
+      if self.holding_one >= LIMIT_ONES {
+        bits.write((1 << LIMIT_ONES) - 1, LIMIT_ONES + 1);
+        write_gamma(bits, self.holding_one - LIMIT_ONES);
+        self.holding_zero = false;
+      } else {
+        bits.write((1 << self.holding_one) - 1, self.holding_one);
+      }
+      self.holding_one = 0;
+    }
+    if self.holding_zero {
+      bits.write_bit(false);
+      self.holding_zero = false;
+    }
+    if self.pending_bits > 0 {
+      bits.write(self.pending, self.pending_bits);
+      self.pending      = 0;
+      self.pending_bits = 0;
+    }
+  }
+
+  /// Clears the state held within a block, keeping the medians.
+  pub fn reset(&mut self) {
+    self.holding_zero = false;
+    self.holding_one  = 0;
+    self.zeros        = 0;
+    self.pending      = 0;
+    self.pending_bits = 0;
+  }
+}
+
+#[cfg(test)]
+mod entropy_coding {
+  use super::*;
+  use wavpack::bits::{BitReader, BitWriter};
+
+  #[test]
+  fn logarithms() {
+    assert_eq!(0, log2(0));
+    assert_eq!(256, log2(1));
+    assert_eq!(2 * 256, log2(2));
+    assert_eq!(1, exp2s(256));
+    assert_eq!(-1, exp2s(-256));
+    for &value in [1i32, 2, 3, 100, -100, 1000, 32767, -32768, 1 << 20].iter() {
+      let restored = exp2s(log2s(value));
+      assert!((restored - value).abs() <= value.abs() / 128, "{} {}", value, restored);
+    }
+  }
+
+  #[test]
+  fn medians() {
+    let mut medians = [[1000u32, 200, 30], [0, 1, 5000000]];
+    let bytes = write_medians(&mut medians, 2);
+    assert_eq!(12, bytes.len());
+    assert_eq!(medians, read_medians(&bytes, 2).unwrap());
+    assert!(read_medians(&bytes, 1).is_err());
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut residuals: Vec<i32> = Vec::new();
+    let mut seed = 1u32;
+    for i in 0..4000 {
+      seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+      let residual =
+        match i / 500 {
+          // Silence gives runs of zeros
+          1 | 5 => 0,
+          2     => (seed >> 16) as i32 % 3 - 1,
+          3     => (seed >> 4) as i32 >> (seed % 20),
+          _     => (seed >> 16) as i32 % 2000 - 1000
+        };
+      residuals.push(residual);
+    }
+    for &stereo in [false, true].iter() {
+      let mut encoder = WordsEncoder::new();
+      let mut writer  = BitWriter::new();
+      for (i, &residual) in residuals.iter().enumerate() {
+        encoder.write(&mut writer, residual, if stereo { i & 1 } else { 0 });
+      }
+      encoder.flush(&mut writer);
+      let data = writer.finish();
+      let mut decoder = WordsDecoder::new([[0u32; 3]; 2]);
+      let mut decoded = vec![0i32; residuals.len()];
+      decoder.read(&mut BitReader::new(&data), &mut decoded, stereo).unwrap();
+      assert_eq!(residuals, decoded);
+    }
+  }
+}